@@ -1,7 +1,7 @@
 use criterion::{criterion_group, criterion_main, Criterion};
 use std::hint::black_box;
 use std::path::PathBuf;
-use vcf_mcp_server::vcf::load_vcf;
+use vcf_mcp_server::vcf::{load_vcf, load_vcf_with_options, LoadOptions};
 
 fn setup_vcf_index() -> vcf_mcp_server::vcf::VcfIndex {
     let vcf_path = PathBuf::from("sample_data/sample.compressed.vcf.gz");
@@ -64,10 +64,72 @@ fn benchmark_query_by_id(c: &mut Criterion) {
     });
 }
 
+fn benchmark_concurrent_region_queries(c: &mut Criterion) {
+    let vcf_path = PathBuf::from("sample_data/sample.compressed.vcf.gz");
+
+    if !vcf_path.exists() {
+        eprintln!("Warning: Sample VCF file not found, skipping benchmark");
+        return;
+    }
+
+    let index = setup_vcf_index();
+    let threads = std::thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(4);
+
+    // Each iteration runs one region query per thread against the shared index
+    c.bench_function("query_by_region_concurrent", |b| {
+        b.iter(|| {
+            std::thread::scope(|scope| {
+                for _ in 0..threads {
+                    scope.spawn(|| {
+                        let (results, _) = index.query_by_region(
+                            black_box("20"),
+                            black_box(14000),
+                            black_box(18000),
+                        );
+                        black_box(results);
+                    });
+                }
+            });
+        })
+    });
+}
+
+fn benchmark_block_cache(c: &mut Criterion) {
+    let vcf_path = PathBuf::from("sample_data/sample.compressed.vcf.gz");
+
+    if !vcf_path.exists() {
+        eprintln!("Warning: Sample VCF file not found, skipping benchmark");
+        return;
+    }
+
+    // The same region queried repeatedly, with and without cached blocks
+    for (name, block_cache_bytes) in [
+        ("query_by_region_uncached", 0),
+        ("query_by_region_cached", 64 << 20),
+    ] {
+        let options = LoadOptions {
+            block_cache_bytes,
+            ..Default::default()
+        };
+        let index = load_vcf_with_options(&vcf_path, &options).expect("Failed to load VCF file");
+        c.bench_function(name, |b| {
+            b.iter(|| {
+                let (results, _) =
+                    index.query_by_region(black_box("20"), black_box(14000), black_box(18000));
+                black_box(results);
+            })
+        });
+    }
+}
+
 criterion_group!(
     benches,
     benchmark_query_by_position,
     benchmark_query_by_region,
-    benchmark_query_by_id
+    benchmark_query_by_id,
+    benchmark_concurrent_region_queries,
+    benchmark_block_cache
 );
 criterion_main!(benches);
@@ -0,0 +1,516 @@
+// GA4GH Beacon v2 genomic variant queries for the /beacon/g_variants route (--beacon), answered
+// from the same index as the MCP tools. Only the individuals-free part of the API is served:
+// whether variants exist, how many, and their descriptions, never genotypes or sample names.
+//
+// A request gives referenceName, start and optionally end (0-based, end exclusive, as in the
+// Beacon spec), referenceBases, alternateBases, variantType and assemblyId, as GET query
+// parameters or in a POST body's query.requestParameters. A start without an end is a
+// sequence query: variants at that position. With an end it is a range query: variants
+// starting in [start, end). Bases match base by base, with 'N' in the request matching any
+// base. Each matching ALT allele is one result.
+//
+// The answer's granularity is the one requested (requestedGranularity: boolean, count or
+// record; boolean when omitted), lowered to the server's --beacon-granularity.
+
+use crate::build_check::Build;
+use crate::vcf::{AlleleType, AlternateAllele, Variant};
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+
+pub const API_VERSION: &str = "v2.0.0";
+pub const BEACON_ID: &str = "vcf_mcp_server";
+
+// Results per page when the request has no pagination.limit
+const DEFAULT_LIMIT: usize = 10;
+
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default, clap::ValueEnum, serde::Serialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum Granularity {
+    #[default]
+    Boolean,
+    Count,
+    Record,
+}
+
+impl Granularity {
+    fn parse(name: &str) -> Result<Granularity, String> {
+        match name {
+            "boolean" => Ok(Granularity::Boolean),
+            "count" => Ok(Granularity::Count),
+            "record" => Ok(Granularity::Record),
+            _ => Err(format!(
+                "Unknown requestedGranularity '{}'; expected boolean, count or record",
+                name
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct VariantRequest {
+    pub reference_name: String,
+    // 0-based
+    pub start: u64,
+    // 0-based, exclusive; None for a sequence query
+    pub end: Option<u64>,
+    pub reference_bases: Option<String>,
+    pub alternate_bases: Option<String>,
+    pub variant_type: Option<String>,
+    pub assembly_id: Option<String>,
+    pub granularity: Granularity,
+    pub skip: usize,
+    pub limit: usize,
+    // As received, echoed in the response's receivedRequestSummary
+    parameters: Map<String, Value>,
+}
+
+impl VariantRequest {
+    // A GET request's query parameters
+    pub fn from_query(query: &HashMap<String, String>) -> Result<Self, String> {
+        let mut parameters = Map::new();
+        let mut granularity = None;
+        let mut pagination = Map::new();
+        for (key, value) in query {
+            let value = Value::String(value.clone());
+            match key.as_str() {
+                "requestedGranularity" => granularity = Some(value),
+                "skip" | "limit" => {
+                    pagination.insert(key.clone(), value);
+                }
+                _ => {
+                    parameters.insert(key.clone(), value);
+                }
+            }
+        }
+        Self::parse(parameters, granularity.as_ref(), &pagination)
+    }
+
+    // A POST request's JSON body: {"meta": ..., "query": {"requestParameters": {...},
+    // "requestedGranularity": ..., "pagination": {"skip": ..., "limit": ...}}}
+    pub fn from_body(body: &Value) -> Result<Self, String> {
+        let query = &body["query"];
+        let parameters = match &query["requestParameters"] {
+            Value::Object(parameters) => parameters.clone(),
+            Value::Null => Map::new(),
+            _ => return Err("query.requestParameters must be an object".to_string()),
+        };
+        let pagination = query["pagination"].as_object().cloned().unwrap_or_default();
+        Self::parse(parameters, query.get("requestedGranularity"), &pagination)
+    }
+
+    fn parse(
+        parameters: Map<String, Value>,
+        granularity: Option<&Value>,
+        pagination: &Map<String, Value>,
+    ) -> Result<Self, String> {
+        let text = |key: &str| match parameters.get(key) {
+            None | Some(Value::Null) => Ok(None),
+            Some(Value::String(text)) if text.is_empty() => Ok(None),
+            Some(Value::String(text)) => Ok(Some(text.clone())),
+            Some(Value::Number(number)) => Ok(Some(number.to_string())),
+            Some(_) => Err(format!("{} must be a string", key)),
+        };
+        let reference_name =
+            text("referenceName")?.ok_or_else(|| "referenceName is required".to_string())?;
+        let start = positions(parameters.get("start"), "start")?;
+        let end = positions(parameters.get("end"), "end")?;
+        if start.len() > 1 || end.len() > 1 {
+            return Err(
+                "Bracket queries (start or end with two values) are not supported".to_string(),
+            );
+        }
+        let start = *start
+            .first()
+            .ok_or_else(|| "start is required".to_string())?;
+        let end = end.first().copied();
+        if end.is_some_and(|end| end <= start) {
+            return Err("end must be greater than start (0-based, end exclusive)".to_string());
+        }
+        let bases = |key: &str| -> Result<Option<String>, String> {
+            let Some(bases) = text(key)? else {
+                return Ok(None);
+            };
+            if !bases.bytes().all(|base| b"ACGTNacgtn".contains(&base)) {
+                return Err(format!(
+                    "{} '{}' must consist of A, C, G, T or N",
+                    key, bases
+                ));
+            }
+            Ok(Some(bases.to_ascii_uppercase()))
+        };
+        let count = |key: &str, default: usize| -> Result<usize, String> {
+            match pagination.get(key) {
+                None | Some(Value::Null) => Ok(default),
+                Some(Value::Number(number)) => number
+                    .as_u64()
+                    .map(|number| number as usize)
+                    .ok_or_else(|| format!("{} must be a non-negative integer", key)),
+                Some(Value::String(text)) => text
+                    .parse()
+                    .map_err(|_| format!("{} must be a non-negative integer", key)),
+                Some(_) => Err(format!("{} must be a non-negative integer", key)),
+            }
+        };
+        Ok(VariantRequest {
+            reference_name,
+            start,
+            end,
+            reference_bases: bases("referenceBases")?,
+            alternate_bases: bases("alternateBases")?,
+            variant_type: text("variantType")?,
+            assembly_id: text("assemblyId")?,
+            granularity: match granularity {
+                None | Some(Value::Null) => Granularity::default(),
+                Some(Value::String(name)) => Granularity::parse(name)?,
+                Some(_) => return Err("requestedGranularity must be a string".to_string()),
+            },
+            skip: count("skip", 0)?,
+            limit: count("limit", DEFAULT_LIMIT)?,
+            parameters,
+        })
+    }
+
+    // The 1-based inclusive VCF region to read
+    pub fn vcf_region(&self) -> (u64, u64) {
+        (self.start + 1, self.end.unwrap_or(self.start + 1))
+    }
+
+    // The ALT alleles of a record read from vcf_region that the request matches
+    pub fn matching_alleles<'a>(&self, variant: &'a Variant) -> Vec<&'a AlternateAllele> {
+        // The region query also returns records starting before it that overlap it
+        let (first, last) = self.vcf_region();
+        if variant.position < first || variant.position > last {
+            return Vec::new();
+        }
+        if let Some(reference) = &self.reference_bases {
+            if !bases_match(reference, &variant.reference) {
+                return Vec::new();
+            }
+        }
+        variant
+            .alternate_alleles
+            .iter()
+            .filter(|allele| {
+                allele_type(&variant.reference, allele).is_some_and(|ty| {
+                    self.variant_type
+                        .as_ref()
+                        .is_none_or(|wanted| wanted.eq_ignore_ascii_case(&ty))
+                })
+            })
+            .filter(|allele| {
+                self.alternate_bases.as_ref().is_none_or(|alternate| {
+                    allele.allele_type == AlleleType::Sequence
+                        && bases_match(alternate, &allele.allele)
+                })
+            })
+            .collect()
+    }
+}
+
+// "start" or "end": a number, a numeric string, or an array of either (a GET parameter
+// may list values separated by commas)
+fn positions(value: Option<&Value>, key: &str) -> Result<Vec<u64>, String> {
+    let invalid = || format!("{} must be a non-negative integer or a list of them", key);
+    let position = |value: &Value| match value {
+        Value::Number(number) => number.as_u64().ok_or_else(invalid),
+        Value::String(text) => text.trim().parse().map_err(|_| invalid()),
+        _ => Err(invalid()),
+    };
+    match value {
+        None | Some(Value::Null) => Ok(Vec::new()),
+        Some(Value::Array(values)) => values.iter().map(position).collect(),
+        Some(Value::String(text)) => text
+            .split(',')
+            .map(|part| position(&Value::String(part.to_string())))
+            .collect(),
+        Some(value) => Ok(vec![position(value)?]),
+    }
+}
+
+// Whether requested bases match a record's, with 'N' in the request matching any base
+fn bases_match(requested: &str, bases: &str) -> bool {
+    requested.len() == bases.len()
+        && requested
+            .bytes()
+            .zip(bases.bytes())
+            .all(|(wanted, base)| wanted == b'N' || wanted.eq_ignore_ascii_case(&base))
+}
+
+// Beacon variantType of an ALT allele: SNP, MNP, INS or DEL for bases, the symbol's type for
+// symbolic alleles (e.g. DUP for <DUP:TANDEM>) and BND for breakends. None for '*', <NON_REF>
+// and missing alleles, which describe no variant.
+fn allele_type(reference: &str, allele: &AlternateAllele) -> Option<String> {
+    match allele.allele_type {
+        AlleleType::Sequence => Some(
+            match allele.allele.len().cmp(&reference.len()) {
+                std::cmp::Ordering::Equal if reference.len() == 1 => "SNP",
+                std::cmp::Ordering::Equal => "MNP",
+                std::cmp::Ordering::Greater => "INS",
+                std::cmp::Ordering::Less => "DEL",
+            }
+            .to_string(),
+        ),
+        AlleleType::Symbolic => allele
+            .symbol
+            .as_deref()
+            .and_then(|symbol| symbol.split(':').next())
+            .map(str::to_string),
+        AlleleType::Breakend => Some("BND".to_string()),
+        AlleleType::SpanningDeletion | AlleleType::NonRef | AlleleType::Missing => None,
+    }
+}
+
+// Whether a request's assemblyId names the file's build. Names are compared as builds
+// (hg19 is GRCh37); a file whose build is unknown answers for any assembly.
+pub fn assembly_matches(assembly_id: &str, build: &str) -> bool {
+    match (Build::from_name(assembly_id), Build::from_name(build)) {
+        (_, None) => true,
+        (Some(requested), Some(build)) => requested == build,
+        (None, Some(_)) => assembly_id.eq_ignore_ascii_case(build),
+    }
+}
+
+// A genomicVariant result for one ALT allele of a record (beacon-g_variant-v2.0.0), with a
+// VRS SequenceLocation in 0-based interbase coordinates
+pub fn g_variant(variant: &Variant, allele: &AlternateAllele) -> Value {
+    let start = variant.position - 1;
+    let mut result = json!({
+        "variantInternalId": format!(
+            "{}:{}:{}:{}",
+            variant.chromosome, variant.position, variant.reference, allele.allele
+        ),
+        "variation": {
+            "variantType": allele_type(&variant.reference, allele),
+            "referenceBases": variant.reference,
+            "alternateBases": allele.allele,
+            "location": {
+                "type": "SequenceLocation",
+                "sequence_id": variant.chromosome,
+                "interval": {
+                    "type": "SequenceInterval",
+                    "start": {"type": "Number", "value": start},
+                    "end": {"type": "Number", "value": start + variant.reference.len() as u64},
+                },
+            },
+        },
+    });
+    if !variant.ids.is_empty() {
+        result["identifiers"] = json!({
+            "variantAlternativeIds": variant
+                .ids
+                .iter()
+                .map(|id| json!({"id": id}))
+                .collect::<Vec<_>>(),
+        });
+    }
+    result
+}
+
+// The resultSets response for a request: `results` are the page of results asked for and
+// `total` the number of matching alleles. `max_granularity` is --beacon-granularity.
+pub fn result_sets_response(
+    dataset: &str,
+    request: &VariantRequest,
+    max_granularity: Granularity,
+    results: Vec<Value>,
+    total: usize,
+) -> Value {
+    let granularity = request.granularity.min(max_granularity);
+    let mut response = json!({
+        "meta": {
+            "beaconId": BEACON_ID,
+            "apiVersion": API_VERSION,
+            "returnedGranularity": granularity,
+            "receivedRequestSummary": {
+                "apiVersion": API_VERSION,
+                "requestedSchemas": [],
+                "requestParameters": request.parameters,
+                "requestedGranularity": request.granularity,
+                "pagination": {"skip": request.skip, "limit": request.limit},
+            },
+            "returnedSchemas": [
+                {"entityType": "genomicVariant", "schema": "beacon-g_variant-v2.0.0"}
+            ],
+        },
+        "responseSummary": {"exists": total > 0},
+    });
+    if granularity == Granularity::Boolean {
+        return response;
+    }
+    response["responseSummary"]["numTotalResults"] = json!(total);
+    let mut result_set = json!({
+        "id": dataset,
+        "setType": "dataset",
+        "exists": total > 0,
+        "resultsCount": total,
+    });
+    if granularity == Granularity::Record {
+        result_set["results"] = Value::Array(results);
+    }
+    response["response"] = json!({"resultSets": [result_set]});
+    response
+}
+
+// A Beacon error response
+pub fn error_response(code: u16, message: &str) -> Value {
+    json!({
+        "meta": {
+            "beaconId": BEACON_ID,
+            "apiVersion": API_VERSION,
+            "returnedSchemas": [],
+        },
+        "error": {"errorCode": code, "errorMessage": message},
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn allele(allele: &str, allele_type: AlleleType, symbol: Option<&str>) -> AlternateAllele {
+        AlternateAllele {
+            allele: allele.to_string(),
+            allele_type,
+            symbol: symbol.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn test_parse_requests() {
+        let query: HashMap<String, String> = [
+            ("referenceName", "20"),
+            ("start", "14369"),
+            ("alternateBases", "a"),
+            ("requestedGranularity", "record"),
+            ("limit", "5"),
+        ]
+        .into_iter()
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect();
+        let request = VariantRequest::from_query(&query).unwrap();
+        assert_eq!(request.start, 14369);
+        assert_eq!(request.end, None);
+        assert_eq!(request.vcf_region(), (14370, 14370));
+        assert_eq!(request.alternate_bases.as_deref(), Some("A"));
+        assert_eq!(request.granularity, Granularity::Record);
+        assert_eq!((request.skip, request.limit), (0, 5));
+
+        let request = VariantRequest::from_body(&json!({
+            "meta": {"apiVersion": "v2.0"},
+            "query": {
+                "requestParameters": {
+                    "assemblyId": "GRCh37",
+                    "referenceName": "20",
+                    "start": [1000000],
+                    "end": [1300000],
+                },
+                "requestedGranularity": "count",
+            }
+        }))
+        .unwrap();
+        assert_eq!(request.vcf_region(), (1000001, 1300000));
+        assert_eq!(request.granularity, Granularity::Count);
+        assert_eq!(request.limit, DEFAULT_LIMIT);
+
+        let body = |parameters: Value| json!({"query": {"requestParameters": parameters}});
+        assert!(VariantRequest::from_body(&body(json!({"start": [1]}))).is_err());
+        assert!(VariantRequest::from_body(&body(json!({"referenceName": "20"}))).is_err());
+        assert!(VariantRequest::from_body(&body(
+            json!({"referenceName": "20", "start": [100, 200], "end": [300, 400]})
+        ))
+        .is_err());
+        assert!(VariantRequest::from_body(&body(
+            json!({"referenceName": "20", "start": [100], "end": [100]})
+        ))
+        .is_err());
+        assert!(VariantRequest::from_body(&body(
+            json!({"referenceName": "20", "start": [100], "alternateBases": "<DEL>"})
+        ))
+        .is_err());
+    }
+
+    #[test]
+    fn test_allele_matching() {
+        assert!(bases_match("N", "A"));
+        assert!(bases_match("AN", "AT"));
+        assert!(!bases_match("A", "AT"));
+        assert!(!bases_match("C", "A"));
+
+        assert_eq!(
+            allele_type("G", &allele("A", AlleleType::Sequence, None)).as_deref(),
+            Some("SNP")
+        );
+        assert_eq!(
+            allele_type("GTC", &allele("G", AlleleType::Sequence, None)).as_deref(),
+            Some("DEL")
+        );
+        assert_eq!(
+            allele_type(
+                "G",
+                &allele("<DUP:TANDEM>", AlleleType::Symbolic, Some("DUP:TANDEM"))
+            )
+            .as_deref(),
+            Some("DUP")
+        );
+        assert_eq!(
+            allele_type("G", &allele("*", AlleleType::SpanningDeletion, None)),
+            None
+        );
+    }
+
+    #[test]
+    fn test_assembly_matches() {
+        assert!(assembly_matches("GRCh37", "GRCh37"));
+        assert!(assembly_matches("hg19", "GRCh37"));
+        assert!(!assembly_matches("GRCh38", "GRCh37"));
+        assert!(!assembly_matches("CHM13", "GRCh38"));
+        assert!(assembly_matches("GRCh38", "Unknown"));
+    }
+
+    #[test]
+    fn test_granularity_is_capped() {
+        let query: HashMap<String, String> = [
+            ("referenceName", "20"),
+            ("start", "14369"),
+            ("requestedGranularity", "record"),
+        ]
+        .into_iter()
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect();
+        let request = VariantRequest::from_query(&query).unwrap();
+        let results = vec![json!({"variantInternalId": "20:14370:G:A"})];
+
+        let response = result_sets_response(
+            "sample.vcf.gz",
+            &request,
+            Granularity::Count,
+            results.clone(),
+            1,
+        );
+        assert_eq!(response["meta"]["returnedGranularity"], "count");
+        assert_eq!(response["responseSummary"]["numTotalResults"], 1);
+        assert!(response["response"]["resultSets"][0]
+            .get("results")
+            .is_none());
+
+        let response =
+            result_sets_response("sample.vcf.gz", &request, Granularity::Record, results, 1);
+        assert_eq!(response["response"]["resultSets"][0]["id"], "sample.vcf.gz");
+        assert_eq!(
+            response["response"]["resultSets"][0]["results"][0]["variantInternalId"],
+            "20:14370:G:A"
+        );
+
+        let response = result_sets_response(
+            "sample.vcf.gz",
+            &request,
+            Granularity::Boolean,
+            Vec::new(),
+            0,
+        );
+        assert_eq!(response["responseSummary"], json!({"exists": false}));
+        assert!(response.get("response").is_none());
+    }
+}
@@ -0,0 +1,113 @@
+// BED intervals for query_by_bed, given inline or read from a file under --bed-dir.
+//
+// A BED line has at least three whitespace-separated columns: chromosome, start and end,
+// 0-based and end-exclusive, so "chr1 99 200" covers VCF positions 100-200. The optional
+// fourth column names the interval; query_by_bed groups intervals sharing a name (e.g. the
+// exons of a gene). Further columns (score, strand, ...) are ignored. Empty lines, '#'
+// comments and UCSC "track" and "browser" lines are skipped.
+
+use std::path::Path;
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct BedRegion {
+    pub chromosome: String,
+    // 0-based, inclusive
+    pub start: u64,
+    // 0-based, exclusive
+    pub end: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+}
+
+impl BedRegion {
+    // The 1-based inclusive VCF region the interval covers
+    pub fn vcf_region(&self) -> (u64, u64) {
+        (self.start + 1, self.end)
+    }
+}
+
+fn invalid_data(message: String) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, message)
+}
+
+pub fn load_bed(path: &Path) -> std::io::Result<Vec<BedRegion>> {
+    parse_bed(&std::fs::read_to_string(path)?)
+}
+
+// Intervals of BED text, in order
+pub fn parse_bed(text: &str) -> std::io::Result<Vec<BedRegion>> {
+    let mut regions = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty()
+            || line.starts_with('#')
+            || line.starts_with("track")
+            || line.starts_with("browser")
+        {
+            continue;
+        }
+        let columns: Vec<&str> = line.split_whitespace().collect();
+        if columns.len() < 3 {
+            return Err(invalid_data(format!(
+                "BED line {} has {} columns, expected at least 3 (chromosome, start, end)",
+                number + 1,
+                columns.len()
+            )));
+        }
+        let coordinate = |column: usize, label: &str| {
+            columns[column].parse::<u64>().map_err(|_| {
+                invalid_data(format!(
+                    "BED line {}: {} '{}' is not a non-negative integer",
+                    number + 1,
+                    label,
+                    columns[column]
+                ))
+            })
+        };
+        let (start, end) = (coordinate(1, "start")?, coordinate(2, "end")?);
+        if end <= start {
+            return Err(invalid_data(format!(
+                "BED line {}: end ({}) must be greater than start ({}); BED coordinates are 0-based and end-exclusive",
+                number + 1,
+                end,
+                start
+            )));
+        }
+        regions.push(BedRegion {
+            chromosome: columns[0].to_string(),
+            start,
+            end,
+            name: columns
+                .get(3)
+                .filter(|name| **name != ".")
+                .map(|name| name.to_string()),
+        });
+    }
+    Ok(regions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_bed() {
+        let regions = parse_bed(
+            "track name=panel\n\
+             # exons\n\
+             chr20\t14369\t14370\tGENE1\t0\t+\n\
+             20 17329 17400\n\
+             20 1000 2000 .\n",
+        )
+        .unwrap();
+        assert_eq!(regions.len(), 3);
+        assert_eq!(regions[0].name.as_deref(), Some("GENE1"));
+        assert_eq!(regions[0].vcf_region(), (14370, 14370));
+        assert_eq!(regions[1].name, None);
+        assert_eq!(regions[2].name, None);
+
+        assert!(parse_bed("chr20 100\n").is_err());
+        assert!(parse_bed("chr20 -1 100\n").is_err());
+        assert!(parse_bed("chr20 100 100\n").is_err());
+    }
+}
@@ -0,0 +1,468 @@
+// BGZF reader backed by a shared LRU cache of decompressed blocks.
+//
+// Region queries seek to virtual positions from the tabix/CSI index and decompress every
+// block they touch. Interactive sessions tend to query the same few regions over and over,
+// so decompressed blocks are kept in a cache shared by all readers of a file, keyed by the
+// block's compressed offset (the upper 48 bits of a virtual position). A repeated query in
+// a hot region then reads only memory. Readers doing bulk scans (whole chromosomes, large
+// regions) can read through the cache without adding to it, so one scan doesn't evict the
+// hot regions' blocks.
+//
+// Blocks are decoded with noodles' own BGZF reader (one block at a time), so CRC checks and
+// format handling match the uncached path.
+
+use noodles::bgzf::{self, VirtualPosition};
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, BufRead, Read, Seek, SeekFrom};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+// Fixed part of a BGZF block header, up to and including XLEN
+const GZIP_HEADER_SIZE: usize = 12;
+const GZIP_MAGIC: [u8; 4] = [0x1f, 0x8b, 0x08, 0x04];
+
+// A decompressed block and the compressed offset of the block after it
+struct Block {
+    data: Vec<u8>,
+    next_offset: u64,
+}
+
+struct CacheEntry {
+    block: Arc<Block>,
+    last_used: u64,
+}
+
+#[derive(Default)]
+struct CacheState {
+    entries: HashMap<u64, CacheEntry>,
+    // last_used -> offset of every entry, oldest first; clock values are never reused
+    recency: BTreeMap<u64, u64>,
+    size: usize,
+    clock: u64,
+}
+
+impl CacheState {
+    // Mark the entry at `offset` as used now
+    fn touch(&mut self, offset: u64) -> Option<Arc<Block>> {
+        self.clock += 1;
+        let clock = self.clock;
+        let entry = self.entries.get_mut(&offset)?;
+        self.recency.remove(&entry.last_used);
+        entry.last_used = clock;
+        self.recency.insert(clock, offset);
+        Some(Arc::clone(&entry.block))
+    }
+
+    fn remove(&mut self, offset: u64) {
+        if let Some(entry) = self.entries.remove(&offset) {
+            self.recency.remove(&entry.last_used);
+            self.size -= entry.block.data.len();
+        }
+    }
+}
+
+// Hit and miss counts since the cache was created
+#[derive(Debug, Clone, Copy, serde::Serialize)]
+pub struct BlockCacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub cached_blocks: usize,
+    pub cached_bytes: usize,
+}
+
+// Decompressed blocks of one BGZF file, evicted least recently used first once their
+// total size exceeds `capacity` bytes. A capacity of 0 disables caching.
+pub struct BlockCache {
+    capacity: usize,
+    state: Mutex<CacheState>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl BlockCache {
+    pub fn new(capacity: usize) -> Self {
+        BlockCache {
+            capacity,
+            state: Mutex::new(CacheState::default()),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn stats(&self) -> BlockCacheStats {
+        let state = self.state.lock().unwrap();
+        BlockCacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            cached_blocks: state.entries.len(),
+            cached_bytes: state.size,
+        }
+    }
+
+    fn get(&self, offset: u64) -> Option<Arc<Block>> {
+        if self.capacity == 0 {
+            return None;
+        }
+        let block = self.state.lock().unwrap().touch(offset);
+
+        let counter = if block.is_some() {
+            &self.hits
+        } else {
+            &self.misses
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        block
+    }
+
+    fn insert(&self, offset: u64, block: Arc<Block>) {
+        let size = block.data.len();
+        if size > self.capacity {
+            return;
+        }
+        let mut state = self.state.lock().unwrap();
+        state.remove(offset);
+        state.clock += 1;
+        let clock = state.clock;
+        state.entries.insert(
+            offset,
+            CacheEntry {
+                block,
+                last_used: clock,
+            },
+        );
+        state.recency.insert(clock, offset);
+        state.size += size;
+
+        while state.size > self.capacity {
+            let Some((_, oldest)) = state.recency.first_key_value() else {
+                break;
+            };
+            let oldest = *oldest;
+            state.remove(oldest);
+        }
+    }
+}
+
+// BGZF reader that serves blocks from a BlockCache, decompressing only on a miss
+pub struct CachedBgzfReader<R> {
+    inner: R,
+    cache: Arc<BlockCache>,
+    block: Arc<Block>,
+    // Compressed offset of `block` and the read position within its data
+    block_offset: u64,
+    position: usize,
+    // Set when the last block load ran into the end of the file
+    at_eof: bool,
+    // Whether blocks read from the file are added to the cache
+    fill_cache: bool,
+}
+
+impl<R: Read + Seek> CachedBgzfReader<R> {
+    pub fn new(inner: R, cache: Arc<BlockCache>) -> Self {
+        CachedBgzfReader {
+            inner,
+            cache,
+            block: Arc::new(Block {
+                data: Vec::new(),
+                next_offset: 0,
+            }),
+            block_offset: 0,
+            position: 0,
+            at_eof: false,
+            fill_cache: true,
+        }
+    }
+
+    // Stop (or resume) adding the blocks this reader decompresses to the cache; cached
+    // blocks are still read from it. Bulk scans turn filling off so they don't evict the
+    // blocks of regions queried repeatedly.
+    pub fn set_fill_cache(&mut self, fill_cache: bool) {
+        self.fill_cache = fill_cache;
+    }
+
+    // Make the block at compressed `offset` current, from the cache or the file
+    fn load_block(&mut self, offset: u64) -> io::Result<()> {
+        let block = match self.cache.get(offset) {
+            Some(block) => Some(block),
+            None => self.read_block(offset)?.map(|block| {
+                let block = Arc::new(block);
+                if self.fill_cache {
+                    self.cache.insert(offset, Arc::clone(&block));
+                }
+                block
+            }),
+        };
+        self.at_eof = block.is_none();
+        self.block = block.unwrap_or_else(|| {
+            Arc::new(Block {
+                data: Vec::new(),
+                next_offset: offset,
+            })
+        });
+        self.block_offset = offset;
+        self.position = 0;
+        Ok(())
+    }
+
+    // The block at compressed `offset`, or None at end of file
+    fn read_block(&mut self, offset: u64) -> io::Result<Option<Block>> {
+        self.inner.seek(SeekFrom::Start(offset))?;
+
+        let mut compressed = vec![0; GZIP_HEADER_SIZE];
+        let header_len = read_up_to(&mut self.inner, &mut compressed)?;
+        if header_len == 0 {
+            return Ok(None);
+        }
+        if header_len < GZIP_HEADER_SIZE || compressed[..4] != GZIP_MAGIC {
+            return Err(invalid_block(offset, "missing BGZF header"));
+        }
+
+        let extra_len = u16::from_le_bytes([compressed[10], compressed[11]]) as usize;
+        compressed.resize(GZIP_HEADER_SIZE + extra_len, 0);
+        self.inner.read_exact(&mut compressed[GZIP_HEADER_SIZE..])?;
+        let block_size = block_size(&compressed[GZIP_HEADER_SIZE..])
+            .ok_or_else(|| invalid_block(offset, "missing BC subfield"))?;
+        if block_size < compressed.len() {
+            return Err(invalid_block(offset, "block size smaller than its header"));
+        }
+
+        let header_end = compressed.len();
+        compressed.resize(block_size, 0);
+        self.inner.read_exact(&mut compressed[header_end..])?;
+
+        let mut data = Vec::new();
+        bgzf::io::Reader::new(&compressed[..]).read_to_end(&mut data)?;
+        Ok(Some(Block {
+            data,
+            next_offset: offset + block_size as u64,
+        }))
+    }
+}
+
+// Total block size from the BC extra subfield (BSIZE + 1)
+fn block_size(mut extra: &[u8]) -> Option<usize> {
+    while extra.len() >= 4 {
+        let subfield_len = u16::from_le_bytes([extra[2], extra[3]]) as usize;
+        let payload = extra.get(4..4 + subfield_len)?;
+        if extra[..2] == *b"BC" && subfield_len == 2 {
+            return Some(u16::from_le_bytes([payload[0], payload[1]]) as usize + 1);
+        }
+        extra = &extra[4 + subfield_len..];
+    }
+    None
+}
+
+// Like read_exact, but stops cleanly at end of file and returns the bytes read
+fn read_up_to<R: Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(filled)
+}
+
+fn invalid_block(offset: u64, reason: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("Invalid BGZF block at offset {}: {}", offset, reason),
+    )
+}
+
+impl<R: Read + Seek> Read for CachedBgzfReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let n = available.len().min(buf.len());
+        buf[..n].copy_from_slice(&available[..n]);
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl<R: Read + Seek> BufRead for CachedBgzfReader<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        // Move past exhausted and empty blocks (such as the EOF marker) until data or end of file
+        while self.position >= self.block.data.len() && !self.at_eof {
+            self.load_block(self.block.next_offset)?;
+        }
+        Ok(&self.block.data[self.position.min(self.block.data.len())..])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.position = (self.position + amt).min(self.block.data.len());
+    }
+}
+
+impl<R: Read + Seek> bgzf::io::BufRead for CachedBgzfReader<R> {
+    fn virtual_position(&self) -> VirtualPosition {
+        // Past the end of a block's data is the start of the next block, as in bgzf::io::Reader
+        let (offset, position) = if self.position < self.block.data.len() {
+            (self.block_offset, self.position)
+        } else {
+            (self.block.next_offset, 0)
+        };
+        VirtualPosition::from((offset << 16) | position as u64)
+    }
+}
+
+impl<R: Read + Seek> bgzf::io::Seek for CachedBgzfReader<R> {
+    fn seek_to_virtual_position(&mut self, pos: VirtualPosition) -> io::Result<VirtualPosition> {
+        let offset = pos.compressed();
+        if offset != self.block_offset || self.block.data.is_empty() {
+            self.load_block(offset)?;
+        }
+        self.position = usize::from(pos.uncompressed());
+        Ok(pos)
+    }
+
+    fn seek_with_index(&mut self, _index: &bgzf::gzi::Index, _pos: SeekFrom) -> io::Result<u64> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "seeking by uncompressed offset is not supported by the cached BGZF reader",
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Cursor, Write};
+
+    // BGZF file of `lines` written with noodles, split into blocks of `block_lines` lines
+    fn bgzf_file(lines: usize, block_lines: usize) -> Vec<u8> {
+        let mut writer = bgzf::io::Writer::new(Vec::new());
+        for i in 0..lines {
+            writeln!(writer, "line {}", i).unwrap();
+            if (i + 1) % block_lines == 0 {
+                writer.flush().unwrap();
+            }
+        }
+        writer.finish().unwrap()
+    }
+
+    #[test]
+    fn test_cached_reader_matches_bgzf_reader() {
+        let file = bgzf_file(1000, 100);
+        let mut expected = String::new();
+        bgzf::io::Reader::new(&file[..])
+            .read_to_string(&mut expected)
+            .unwrap();
+
+        let cache = Arc::new(BlockCache::new(1 << 20));
+        let mut actual = String::new();
+        CachedBgzfReader::new(Cursor::new(&file), Arc::clone(&cache))
+            .read_to_string(&mut actual)
+            .unwrap();
+        assert_eq!(actual, expected);
+
+        // A second pass is served entirely from the cache
+        let misses = cache.stats().misses;
+        let mut again = String::new();
+        CachedBgzfReader::new(Cursor::new(&file), Arc::clone(&cache))
+            .read_to_string(&mut again)
+            .unwrap();
+        assert_eq!(again, expected);
+        assert_eq!(cache.stats().misses, misses);
+    }
+
+    #[test]
+    fn test_cached_reader_seeks_to_virtual_positions() {
+        use bgzf::io::{BufRead as _, Seek as _};
+
+        let file = bgzf_file(1000, 100);
+        let mut reader = bgzf::io::Reader::new(Cursor::new(&file));
+        let mut line = String::new();
+        for _ in 0..250 {
+            line.clear();
+            reader.read_line(&mut line).unwrap();
+        }
+        let position = reader.virtual_position();
+
+        let cache = Arc::new(BlockCache::new(1 << 20));
+        let mut cached = CachedBgzfReader::new(Cursor::new(&file), cache);
+        cached.seek_to_virtual_position(position).unwrap();
+        assert_eq!(cached.virtual_position(), position);
+        line.clear();
+        cached.read_line(&mut line).unwrap();
+        assert_eq!(line, "line 250\n");
+    }
+
+    #[test]
+    fn test_cache_evicts_least_recently_used() {
+        let block = |len| {
+            Arc::new(Block {
+                data: vec![0; len],
+                next_offset: 0,
+            })
+        };
+        let cache = BlockCache::new(300);
+        cache.insert(0, block(100));
+        cache.insert(1, block(100));
+        cache.insert(2, block(100));
+        assert!(cache.get(0).is_some());
+        cache.insert(3, block(100));
+
+        // Block 1 was the least recently used
+        assert!(cache.get(1).is_none());
+        assert!(cache.get(0).is_some());
+        assert_eq!(cache.stats().cached_bytes, 300);
+    }
+
+    #[test]
+    fn test_cache_reinsert_and_eviction_order() {
+        let block = |len| {
+            Arc::new(Block {
+                data: vec![0; len],
+                next_offset: 0,
+            })
+        };
+        let cache = BlockCache::new(300);
+        cache.insert(0, block(100));
+        cache.insert(1, block(100));
+        // Replacing a block counts its size once and makes it the most recent
+        cache.insert(0, block(100));
+        assert_eq!(cache.stats().cached_bytes, 200);
+        cache.insert(2, block(100));
+        cache.insert(3, block(150));
+
+        // Blocks 1 and 2 were the oldest; 0 was refreshed by its reinsertion
+        assert!(cache.get(1).is_none());
+        assert!(cache.get(2).is_none());
+        assert!(cache.get(0).is_some());
+        assert!(cache.get(3).is_some());
+        let stats = cache.stats();
+        assert_eq!((stats.cached_blocks, stats.cached_bytes), (2, 250));
+    }
+
+    #[test]
+    fn test_reader_without_filling_keeps_cached_blocks() {
+        let file = bgzf_file(1000, 100);
+        let cache = Arc::new(BlockCache::new(1 << 20));
+
+        // A bulk read leaves the cache empty
+        let mut scan = CachedBgzfReader::new(Cursor::new(&file), Arc::clone(&cache));
+        scan.set_fill_cache(false);
+        let mut text = String::new();
+        scan.read_to_string(&mut text).unwrap();
+        assert_eq!(cache.stats().cached_blocks, 0);
+
+        // but still uses blocks others cached
+        CachedBgzfReader::new(Cursor::new(&file), Arc::clone(&cache))
+            .read_to_string(&mut String::new())
+            .unwrap();
+        let (cached, hits) = (cache.stats().cached_blocks, cache.stats().hits);
+        scan = CachedBgzfReader::new(Cursor::new(&file), Arc::clone(&cache));
+        scan.set_fill_cache(false);
+        scan.read_to_string(&mut String::new()).unwrap();
+        assert_eq!(cache.stats().cached_blocks, cached);
+        assert!(cache.stats().hits > hits);
+    }
+}
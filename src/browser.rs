@@ -0,0 +1,76 @@
+// Genome browser links for the query tools' browser_links option: an IGV.js (igv.org web app)
+// and a UCSC Genome Browser URL showing the variant on the file's build.
+//
+// Both browsers name the builds hg19 (GRCh37) and hg38 (GRCh38) and expect UCSC chromosome
+// names, so "20" becomes "chr20" and "MT" becomes "chrM". The view spans the REF bases with
+// BROWSER_PADDING bp either side; UCSC also highlights the REF bases.
+
+use crate::build_check::Build;
+use crate::vcf::BrowserLinks;
+
+const IGV_APP: &str = "https://igv.org/app/";
+const UCSC_TRACKS: &str = "https://genome.ucsc.edu/cgi-bin/hgTracks";
+
+// Bases shown either side of the variant
+const BROWSER_PADDING: u64 = 50;
+
+// The UCSC (and IGV) genome name of a build
+pub fn genome(build: Build) -> &'static str {
+    match build {
+        Build::Grch37 => "hg19",
+        Build::Grch38 => "hg38",
+    }
+}
+
+// The UCSC name of a VCF chromosome: "chr" prefixed, with the mitochondrion as chrM
+fn ucsc_chromosome(chromosome: &str) -> String {
+    let name = chromosome.strip_prefix("chr").unwrap_or(chromosome);
+    match name {
+        "MT" | "M" => "chrM".to_string(),
+        _ => format!("chr{}", name),
+    }
+}
+
+// Links showing the 1-based inclusive span start-end of `chromosome` on `build`
+pub fn links(build: Build, chromosome: &str, start: u64, end: u64) -> BrowserLinks {
+    let genome = genome(build);
+    let chromosome = ucsc_chromosome(chromosome);
+    let view = format!(
+        "{}:{}-{}",
+        chromosome,
+        start.saturating_sub(BROWSER_PADDING).max(1),
+        end.saturating_add(BROWSER_PADDING)
+    );
+    BrowserLinks {
+        igv: format!("{}?genome={}&locus={}", IGV_APP, genome, view),
+        ucsc: format!(
+            "{}?db={}&position={}&highlight={}.{}:{}-{}",
+            UCSC_TRACKS, genome, view, genome, chromosome, start, end
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_links() {
+        let snv = links(Build::Grch38, "20", 14370, 14370);
+        assert_eq!(
+            snv.igv,
+            "https://igv.org/app/?genome=hg38&locus=chr20:14320-14420"
+        );
+        assert_eq!(
+            snv.ucsc,
+            "https://genome.ucsc.edu/cgi-bin/hgTracks?db=hg38&position=chr20:14320-14420&highlight=hg38.chr20:14370-14370"
+        );
+
+        let mitochondrial = links(Build::Grch37, "MT", 10, 12);
+        assert_eq!(
+            mitochondrial.igv,
+            "https://igv.org/app/?genome=hg19&locus=chrM:1-62"
+        );
+        assert_eq!(ucsc_chromosome("chrX"), "chrX");
+    }
+}
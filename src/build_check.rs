@@ -0,0 +1,240 @@
+// Genome build check for validate_build: well-known rsIDs whose GRCh37 and GRCh38 positions
+// differ, looked for in the file to tell the builds apart when the header has no ##reference
+// line, its chr1 length is missing, or either is wrong.
+//
+// A marker is found by its rsID when the ID index has it, and otherwise by position: a record
+// at the marker's position on one build with the marker's REF and ALT. Both builds' positions
+// are on the forward strand, and the alleles are the forward-strand alleles of both builds.
+// Each found marker votes for the build whose position it sits at; a record with the rsID
+// anywhere else votes for neither. The markers are common variants (APOE, MTHFR, LCT, ...), so
+// most whole-genome files carry several, but a small panel or exome may carry none.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+pub enum Build {
+    #[serde(rename = "GRCh37")]
+    Grch37,
+    #[serde(rename = "GRCh38")]
+    Grch38,
+}
+
+impl Build {
+    // The build a name such as "GRCh38", "hg19" or a FASTA path from ##reference refers to
+    pub fn from_name(name: &str) -> Option<Build> {
+        let name = name.to_ascii_lowercase();
+        if name.contains("37") || name.contains("hg19") {
+            Some(Build::Grch37)
+        } else if name.contains("38") {
+            Some(Build::Grch38)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+pub struct Marker {
+    pub rsid: &'static str,
+    pub gene: &'static str,
+    // Without a "chr" prefix
+    pub chromosome: &'static str,
+    pub grch37: u64,
+    pub grch38: u64,
+    pub reference: &'static str,
+    pub alternate: &'static str,
+}
+
+pub const MARKERS: &[Marker] = &[
+    Marker {
+        rsid: "rs1801133",
+        gene: "MTHFR",
+        chromosome: "1",
+        grch37: 11_856_378,
+        grch38: 11_796_321,
+        reference: "G",
+        alternate: "A",
+    },
+    Marker {
+        rsid: "rs6025",
+        gene: "F5",
+        chromosome: "1",
+        grch37: 169_519_049,
+        grch38: 169_549_811,
+        reference: "C",
+        alternate: "T",
+    },
+    Marker {
+        rsid: "rs4988235",
+        gene: "MCM6",
+        chromosome: "2",
+        grch37: 136_608_646,
+        grch38: 135_851_076,
+        reference: "G",
+        alternate: "A",
+    },
+    Marker {
+        rsid: "rs334",
+        gene: "HBB",
+        chromosome: "11",
+        grch37: 5_248_232,
+        grch38: 5_227_002,
+        reference: "T",
+        alternate: "A",
+    },
+    Marker {
+        rsid: "rs1799963",
+        gene: "F2",
+        chromosome: "11",
+        grch37: 46_761_055,
+        grch38: 46_739_505,
+        reference: "G",
+        alternate: "A",
+    },
+    Marker {
+        rsid: "rs1815739",
+        gene: "ACTN3",
+        chromosome: "11",
+        grch37: 66_328_095,
+        grch38: 66_560_624,
+        reference: "C",
+        alternate: "T",
+    },
+    Marker {
+        rsid: "rs12913832",
+        gene: "HERC2",
+        chromosome: "15",
+        grch37: 28_365_618,
+        grch38: 28_120_472,
+        reference: "A",
+        alternate: "G",
+    },
+    Marker {
+        rsid: "rs9939609",
+        gene: "FTO",
+        chromosome: "16",
+        grch37: 53_820_527,
+        grch38: 53_786_615,
+        reference: "T",
+        alternate: "A",
+    },
+    Marker {
+        rsid: "rs1042522",
+        gene: "TP53",
+        chromosome: "17",
+        grch37: 7_579_472,
+        grch38: 7_676_154,
+        reference: "G",
+        alternate: "C",
+    },
+    Marker {
+        rsid: "rs429358",
+        gene: "APOE",
+        chromosome: "19",
+        grch37: 45_411_941,
+        grch38: 44_908_684,
+        reference: "T",
+        alternate: "C",
+    },
+    Marker {
+        rsid: "rs7412",
+        gene: "APOE",
+        chromosome: "19",
+        grch37: 45_412_079,
+        grch38: 44_908_822,
+        reference: "C",
+        alternate: "T",
+    },
+];
+
+// What the file's records say about the build, overall
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Verdict {
+    #[serde(rename = "GRCh37")]
+    Grch37,
+    #[serde(rename = "GRCh38")]
+    Grch38,
+    // Markers were found at both builds' positions
+    Conflicting,
+    // No marker was found at either build's position
+    Undetermined,
+}
+
+impl Marker {
+    pub fn position(&self, build: Build) -> u64 {
+        match build {
+            Build::Grch37 => self.grch37,
+            Build::Grch38 => self.grch38,
+        }
+    }
+
+    // The build whose position for this marker is `chromosome`:`position`
+    pub fn build_at(&self, chromosome: &str, position: u64) -> Option<Build> {
+        if chromosome.strip_prefix("chr").unwrap_or(chromosome) != self.chromosome {
+            return None;
+        }
+        [Build::Grch37, Build::Grch38]
+            .into_iter()
+            .find(|&build| self.position(build) == position)
+    }
+
+    // Whether a record's alleles are this marker's, so a record at its position without the
+    // rsID can stand in for it
+    pub fn alleles_match(&self, reference: &str, alternates: &[String]) -> bool {
+        reference == self.reference && alternates.iter().any(|alt| alt == self.alternate)
+    }
+}
+
+// The verdict from the number of markers found at each build's position
+pub fn verdict(grch37_matches: usize, grch38_matches: usize) -> Verdict {
+    match (grch37_matches, grch38_matches) {
+        (0, 0) => Verdict::Undetermined,
+        (_, 0) => Verdict::Grch37,
+        (0, _) => Verdict::Grch38,
+        _ => Verdict::Conflicting,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_from_name() {
+        assert_eq!(Build::from_name("GRCh37"), Some(Build::Grch37));
+        assert_eq!(
+            Build::from_name("file:///refs/human_g1k_v37.fasta"),
+            Some(Build::Grch37)
+        );
+        assert_eq!(Build::from_name("hg38"), Some(Build::Grch38));
+        assert_eq!(Build::from_name("Unknown"), None);
+    }
+
+    #[test]
+    fn test_marker_build_at() {
+        let apoe = MARKERS
+            .iter()
+            .find(|marker| marker.rsid == "rs429358")
+            .unwrap();
+        assert_eq!(apoe.build_at("chr19", 44_908_684), Some(Build::Grch38));
+        assert_eq!(apoe.build_at("19", 45_411_941), Some(Build::Grch37));
+        assert_eq!(apoe.build_at("19", 45_411_942), None);
+        assert_eq!(apoe.build_at("20", 45_411_941), None);
+        assert!(apoe.alleles_match("T", &["G".to_string(), "C".to_string()]));
+        assert!(!apoe.alleles_match("C", &["T".to_string()]));
+    }
+
+    #[test]
+    fn test_verdict() {
+        assert_eq!(verdict(0, 0), Verdict::Undetermined);
+        assert_eq!(verdict(3, 0), Verdict::Grch37);
+        assert_eq!(verdict(0, 2), Verdict::Grch38);
+        assert_eq!(verdict(1, 4), Verdict::Conflicting);
+    }
+
+    #[test]
+    fn test_markers_differ_between_builds() {
+        for marker in MARKERS {
+            assert_ne!(marker.grch37, marker.grch38, "{}", marker.rsid);
+        }
+    }
+}
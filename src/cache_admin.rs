@@ -0,0 +1,329 @@
+// Lifecycle management for the files the server keeps next to each VCF.
+//
+// Loading `sample.vcf.gz` may leave `sample.vcf.gz.tbi` (or `.csi`), `.idx` (ID index) and
+// `.stats` (cached statistics) beside it, plus `.tmp` files from writes that were
+// interrupted. The `cache` subcommand lists these sidecars in a directory (`ls`), checks
+// each against its VCF (`verify`) and deletes the ones the server would rebuild or never
+// read again (`prune`). Tabix and CSI indexes are often shipped with the VCF rather than
+// built by the server, so prune only removes them once their VCF is gone. `index --force`
+// deletes the sidecars the server builds itself before building them again.
+
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use crate::id_index::{IdIndex, SourceFingerprint};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SidecarKind {
+    Tabix,
+    Csi,
+    IdIndex,
+    Statistics,
+}
+
+impl SidecarKind {
+    const ALL: [SidecarKind; 4] = [
+        SidecarKind::Tabix,
+        SidecarKind::Csi,
+        SidecarKind::IdIndex,
+        SidecarKind::Statistics,
+    ];
+
+    fn extension(self) -> &'static str {
+        match self {
+            SidecarKind::Tabix => "tbi",
+            SidecarKind::Csi => "csi",
+            SidecarKind::IdIndex => "idx",
+            SidecarKind::Statistics => "stats",
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            SidecarKind::Tabix => "tabix",
+            SidecarKind::Csi => "csi",
+            SidecarKind::IdIndex => "id-index",
+            SidecarKind::Statistics => "stats",
+        }
+    }
+}
+
+// One file kept next to a VCF
+#[derive(Debug)]
+pub struct Sidecar {
+    pub path: PathBuf,
+    pub kind: SidecarKind,
+    // Left behind by an interrupted write (`<vcf>.idx.tmp` and the like)
+    pub temporary: bool,
+    // The VCF it belongs to, which may no longer exist
+    pub vcf: PathBuf,
+    pub size: u64,
+    pub modified: SystemTime,
+}
+
+impl Sidecar {
+    pub fn age(&self) -> Duration {
+        self.modified.elapsed().unwrap_or_default()
+    }
+
+    pub fn orphaned(&self) -> bool {
+        !self.vcf.is_file()
+    }
+}
+
+// Outcome of checking a sidecar against its VCF
+#[derive(Debug, PartialEq, Eq)]
+pub enum SidecarStatus {
+    Ok,
+    Temporary,
+    Orphaned,
+    // The VCF changed after the sidecar was written
+    Stale(String),
+    Unreadable(String),
+    // ID index written by a newer release, which the server leaves in place
+    NewerFormat(String),
+}
+
+impl SidecarStatus {
+    pub fn label(&self) -> &'static str {
+        match self {
+            SidecarStatus::Ok => "ok",
+            SidecarStatus::Temporary => "temporary",
+            SidecarStatus::Orphaned => "orphaned",
+            SidecarStatus::Stale(_) => "stale",
+            SidecarStatus::Unreadable(_) => "unreadable",
+            SidecarStatus::NewerFormat(_) => "newer-format",
+        }
+    }
+
+    pub fn detail(&self) -> Option<&str> {
+        match self {
+            SidecarStatus::Stale(detail)
+            | SidecarStatus::Unreadable(detail)
+            | SidecarStatus::NewerFormat(detail) => Some(detail),
+            _ => None,
+        }
+    }
+
+    // Whether the sidecar needs attention (verify fails on these)
+    pub fn is_problem(&self) -> bool {
+        !matches!(self, SidecarStatus::Ok | SidecarStatus::NewerFormat(_))
+    }
+}
+
+// Where a sidecar of `vcf` is kept
+pub fn sidecar_path(vcf: &Path, kind: SidecarKind) -> PathBuf {
+    PathBuf::from(format!("{}.{}", vcf.display(), kind.extension()))
+}
+
+// Delete the sidecars the server builds itself, so the next load builds them again. A CSI
+// index can only come from bcftools and is left alone.
+pub fn remove_built(vcf: &Path) -> std::io::Result<()> {
+    for kind in [
+        SidecarKind::Tabix,
+        SidecarKind::IdIndex,
+        SidecarKind::Statistics,
+    ] {
+        match std::fs::remove_file(sidecar_path(vcf, kind)) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e),
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+// Sidecars in `dir` (not its subdirectories), sorted by path
+pub fn scan(dir: &Path) -> std::io::Result<Vec<Sidecar>> {
+    let mut sidecars = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        if !metadata.is_file() {
+            continue;
+        }
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let (stem, temporary) = match name.strip_suffix(".tmp") {
+            Some(stem) => (stem, true),
+            None => (name.as_str(), false),
+        };
+        let Some((vcf_name, kind)) = SidecarKind::ALL.iter().find_map(|&kind| {
+            stem.strip_suffix(kind.extension())
+                .and_then(|rest| rest.strip_suffix('.'))
+                .filter(|vcf_name| !vcf_name.is_empty())
+                .map(|vcf_name| (vcf_name, kind))
+        }) else {
+            continue;
+        };
+        sidecars.push(Sidecar {
+            path: entry.path(),
+            kind,
+            temporary,
+            vcf: dir.join(vcf_name),
+            size: metadata.len(),
+            modified: metadata.modified()?,
+        });
+    }
+    sidecars.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(sidecars)
+}
+
+// Check that a sidecar can be read and was written for the current contents of its VCF
+pub fn verify(sidecar: &Sidecar) -> SidecarStatus {
+    if sidecar.temporary {
+        return SidecarStatus::Temporary;
+    }
+    if sidecar.orphaned() {
+        return SidecarStatus::Orphaned;
+    }
+    check_against_vcf(sidecar).unwrap_or_else(|e| SidecarStatus::Unreadable(e.to_string()))
+}
+
+fn check_against_vcf(sidecar: &Sidecar) -> std::io::Result<SidecarStatus> {
+    match sidecar.kind {
+        SidecarKind::IdIndex => match IdIndex::open(&sidecar.path) {
+            Ok(index) if index.source() != SourceFingerprint::of_file(&sidecar.vcf)? => Ok(
+                SidecarStatus::Stale("size, mtime or checksum differ from the VCF".to_string()),
+            ),
+            Ok(_) => Ok(SidecarStatus::Ok),
+            Err(e) if e.kind() == std::io::ErrorKind::Unsupported => {
+                Ok(SidecarStatus::NewerFormat(e.to_string()))
+            }
+            Err(e) => Err(e),
+        },
+        // These carry no fingerprint, so modification times are all there is to compare
+        SidecarKind::Tabix => {
+            noodles::tabix::fs::read(&sidecar.path)?;
+            written_before_vcf(sidecar)
+        }
+        SidecarKind::Csi => {
+            noodles::csi::fs::read(&sidecar.path)?;
+            written_before_vcf(sidecar)
+        }
+        SidecarKind::Statistics => {
+            crate::vcf::load_statistics_from_disk(&sidecar.path, false)?;
+            written_before_vcf(sidecar)
+        }
+    }
+}
+
+fn written_before_vcf(sidecar: &Sidecar) -> std::io::Result<SidecarStatus> {
+    let vcf_modified = std::fs::metadata(&sidecar.vcf)?.modified()?;
+    Ok(if sidecar.modified < vcf_modified {
+        SidecarStatus::Stale("written before the VCF was last modified".to_string())
+    } else {
+        SidecarStatus::Ok
+    })
+}
+
+// Whether prune deletes a sidecar; temp files younger than `min_temp_age` may belong to a
+// build still in progress
+pub fn should_prune(sidecar: &Sidecar, status: &SidecarStatus, min_temp_age: Duration) -> bool {
+    match status {
+        SidecarStatus::Temporary => sidecar.age() >= min_temp_age,
+        SidecarStatus::Orphaned => true,
+        SidecarStatus::Stale(_) | SidecarStatus::Unreadable(_) => {
+            matches!(sidecar.kind, SidecarKind::IdIndex | SidecarKind::Statistics)
+        }
+        SidecarStatus::Ok | SidecarStatus::NewerFormat(_) => false,
+    }
+}
+
+// "512 B", "1.5 KiB", "12.0 MiB", ...
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit + 1 < UNITS.len() {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+// Largest whole unit of an age: "45s", "12m", "5h", "3d"
+pub fn format_age(age: Duration) -> String {
+    let secs = age.as_secs();
+    match secs {
+        0..=59 => format!("{}s", secs),
+        60..=3599 => format!("{}m", secs / 60),
+        3600..=86399 => format!("{}h", secs / 3600),
+        _ => format!("{}d", secs / 86400),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::id_index::IdIndexBuilder;
+
+    fn status_of<'a>(sidecars: &'a [Sidecar], name: &str) -> Option<(&'a Sidecar, SidecarStatus)> {
+        let sidecar = sidecars
+            .iter()
+            .find(|sidecar| sidecar.path.file_name().unwrap() == name)?;
+        Some((sidecar, verify(sidecar)))
+    }
+
+    #[test]
+    fn test_scan_and_verify_sidecars() {
+        let dir = tempfile::TempDir::new().expect("temp dir");
+        let write = |name: &str, contents: &[u8]| std::fs::write(dir.path().join(name), contents);
+        write("a.vcf.gz", b"not really bgzf").unwrap();
+        // Built for some other file, so the fingerprint cannot match
+        let mut builder = IdIndexBuilder::new();
+        builder.add("rs1", "20", 100);
+        write("a.vcf.gz.idx", &builder.finish().unwrap()).unwrap();
+        write("a.vcf.gz.stats", b"garbage").unwrap();
+        write("a.vcf.gz.idx.tmp", b"partial").unwrap();
+        write("gone.vcf.gz.tbi", b"").unwrap();
+        write("notes.txt", b"").unwrap();
+        write("scratch.tmp", b"").unwrap();
+
+        let sidecars = scan(dir.path()).unwrap();
+        assert_eq!(sidecars.len(), 4);
+
+        let (idx, status) = status_of(&sidecars, "a.vcf.gz.idx").unwrap();
+        assert_eq!(idx.kind, SidecarKind::IdIndex);
+        assert_eq!(idx.vcf, dir.path().join("a.vcf.gz"));
+        assert!(matches!(status, SidecarStatus::Stale(_)));
+        assert!(should_prune(idx, &status, Duration::ZERO));
+
+        let (stats, status) = status_of(&sidecars, "a.vcf.gz.stats").unwrap();
+        assert!(matches!(status, SidecarStatus::Unreadable(_)));
+        assert!(should_prune(stats, &status, Duration::ZERO));
+
+        let (tmp, status) = status_of(&sidecars, "a.vcf.gz.idx.tmp").unwrap();
+        assert!(tmp.temporary);
+        assert_eq!(status, SidecarStatus::Temporary);
+        // A recent temp file may still be written to
+        assert!(!should_prune(tmp, &status, Duration::from_secs(3600)));
+
+        let (tbi, status) = status_of(&sidecars, "gone.vcf.gz.tbi").unwrap();
+        assert_eq!(status, SidecarStatus::Orphaned);
+        assert!(should_prune(tbi, &status, Duration::ZERO));
+    }
+
+    #[test]
+    fn test_unreadable_tabix_index_is_reported_but_kept() {
+        let dir = tempfile::TempDir::new().expect("temp dir");
+        std::fs::write(dir.path().join("a.vcf.gz"), b"").unwrap();
+        std::fs::write(dir.path().join("a.vcf.gz.tbi"), b"garbage").unwrap();
+
+        let sidecars = scan(dir.path()).unwrap();
+        let status = verify(&sidecars[0]);
+        assert!(matches!(status, SidecarStatus::Unreadable(_)));
+        assert!(status.is_problem());
+        assert!(!should_prune(&sidecars[0], &status, Duration::ZERO));
+    }
+
+    #[test]
+    fn test_format_size_and_age() {
+        assert_eq!(format_size(512), "512 B");
+        assert_eq!(format_size(1536), "1.5 KiB");
+        assert_eq!(format_size(12 * 1024 * 1024), "12.0 MiB");
+        assert_eq!(format_age(Duration::from_secs(45)), "45s");
+        assert_eq!(format_age(Duration::from_secs(3 * 86400 + 5)), "3d");
+    }
+}
@@ -0,0 +1,106 @@
+// MCP logging: structured notifications/message entries (tool calls received, how long they
+// took, response sizes, records scanned) sent to clients at or above the level they chose with
+// logging/setLevel.
+//
+// Levels are kept per client session, like resource subscriptions. Sessions that never set a
+// level get warnings and worse, or everything with --debug.
+
+use rmcp::{
+    model::{LoggingLevel, LoggingMessageNotificationParam},
+    service::Peer,
+    RoleServer,
+};
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+// The logger named in every entry
+const LOGGER: &str = "vcf_mcp_server";
+
+// Syslog order: debug is the least severe, emergency the most
+fn severity(level: &LoggingLevel) -> u8 {
+    match level {
+        LoggingLevel::Debug => 0,
+        LoggingLevel::Info => 1,
+        LoggingLevel::Notice => 2,
+        LoggingLevel::Warning => 3,
+        LoggingLevel::Error => 4,
+        LoggingLevel::Critical => 5,
+        LoggingLevel::Alert => 6,
+        LoggingLevel::Emergency => 7,
+    }
+}
+
+pub struct ClientLog {
+    // Least severe level each session asked for
+    levels: Mutex<HashMap<String, u8>>,
+    // For sessions that never set a level
+    default: u8,
+}
+
+impl ClientLog {
+    pub fn new(debug: bool) -> Self {
+        ClientLog {
+            levels: Mutex::new(HashMap::new()),
+            default: if debug {
+                severity(&LoggingLevel::Debug)
+            } else {
+                severity(&LoggingLevel::Warning)
+            },
+        }
+    }
+
+    pub fn set_level(&self, session: String, level: &LoggingLevel) {
+        let mut levels = self.levels.lock().unwrap_or_else(|e| e.into_inner());
+        levels.insert(session, severity(level));
+    }
+
+    // Whether an entry at `level` is sent to the session
+    pub fn enabled(&self, session: &str, level: &LoggingLevel) -> bool {
+        let levels = self.levels.lock().unwrap_or_else(|e| e.into_inner());
+        severity(level) >= levels.get(session).copied().unwrap_or(self.default)
+    }
+
+    // Send an entry to the session's client if it wants this level; a client that stopped
+    // listening just misses it
+    pub async fn log(
+        &self,
+        session: &str,
+        peer: &Peer<RoleServer>,
+        level: LoggingLevel,
+        data: serde_json::Value,
+    ) {
+        if !self.enabled(session, &level) {
+            return;
+        }
+        let _ = peer
+            .notify_logging_message(LoggingMessageNotificationParam {
+                level,
+                logger: Some(LOGGER.to_string()),
+                data,
+            })
+            .await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_levels_per_session() {
+        let log = ClientLog::new(false);
+        assert!(!log.enabled("a", &LoggingLevel::Info));
+        assert!(log.enabled("a", &LoggingLevel::Error));
+
+        log.set_level("a".to_string(), &LoggingLevel::Debug);
+        assert!(log.enabled("a", &LoggingLevel::Debug));
+        assert!(!log.enabled("b", &LoggingLevel::Debug));
+
+        log.set_level("b".to_string(), &LoggingLevel::Critical);
+        assert!(!log.enabled("b", &LoggingLevel::Error));
+        assert!(log.enabled("b", &LoggingLevel::Emergency));
+
+        let debug = ClientLog::new(true);
+        assert!(debug.enabled("a", &LoggingLevel::Debug));
+    }
+}
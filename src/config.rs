@@ -0,0 +1,427 @@
+// --config: a TOML file holding the same options as the command line, so a deployment's
+// datasets, limits, caching, annotation sources and transport settings live in one place.
+// Keys are option names (`max_results` or `max-results`), at the top level or grouped under
+// tables of any name ([http], [limits], ...). `[[datasets]]` entries are served like a
+// --manifest. Every option can also be set with a VCF_MCP_<NAME> environment variable, e.g.
+// VCF_MCP_MAX_RESULTS=1000 or VCF_MCP_CONFIG=/etc/vcf_mcp/server.toml.
+//
+// Command line flags win over environment variables, which win over the file. Relative paths in
+// the file are resolved against its directory. Settings are turned into ordinary arguments
+// placed ahead of the user's, so clap validates them like any other; flags can only be turned
+// on this way.
+
+use clap::{Arg, ArgAction, Command};
+use std::collections::{HashMap, HashSet};
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+const ENV_PREFIX: &str = "VCF_MCP_";
+
+// Options taking a file or directory, resolved against the config file's directory
+const PATH_OPTIONS: [&str; 10] = [
+    "vcf_file",
+    "manifest",
+    "log_file",
+    "reference",
+    "annotation",
+    "chain",
+    "bed_dir",
+    "allow_write_dir",
+    "ped",
+    "record_queries",
+];
+
+// What a config key or environment variable sets an option to
+#[derive(Debug, Clone, PartialEq)]
+enum Setting {
+    Flag(bool),
+    Values(Vec<String>),
+}
+
+struct Config {
+    path: PathBuf,
+    options: HashMap<String, Setting>,
+    // The file has [[datasets]], to be loaded as a manifest
+    datasets: bool,
+}
+
+// The environment variable setting an option, e.g. VCF_MCP_MAX_RESULTS
+fn env_name(id: &str) -> String {
+    format!("{}{}", ENV_PREFIX, id.to_ascii_uppercase())
+}
+
+fn is_flag(arg: &Arg) -> bool {
+    matches!(arg.get_action(), ArgAction::SetTrue)
+}
+
+// The option named by a config key, given with dashes or underscores
+fn find_option<'a>(command: &'a Command, key: &str) -> Option<&'a Arg> {
+    let id = key.replace('-', "_");
+    command.get_arguments().find(|arg| {
+        arg.get_id().as_str() == id && (arg.get_long().is_some() || arg.is_positional())
+    })
+}
+
+fn scalar(value: &toml::Value) -> Option<String> {
+    match value {
+        toml::Value::String(s) => Some(s.clone()),
+        toml::Value::Integer(n) => Some(n.to_string()),
+        toml::Value::Float(x) => Some(x.to_string()),
+        toml::Value::Boolean(b) => Some(b.to_string()),
+        toml::Value::Datetime(d) => Some(d.to_string()),
+        toml::Value::Array(_) | toml::Value::Table(_) => None,
+    }
+}
+
+fn config_setting(arg: &Arg, value: &toml::Value, base_dir: &Path) -> Result<Setting, String> {
+    let id = arg.get_id().as_str();
+    if is_flag(arg) {
+        return match value {
+            toml::Value::Boolean(on) => Ok(Setting::Flag(*on)),
+            _ => Err(format!("'{}' must be true or false", id)),
+        };
+    }
+    let values = match value {
+        toml::Value::Array(items) if arg.get_value_delimiter().is_some() => items
+            .iter()
+            .map(|item| scalar(item).ok_or_else(|| format!("'{}' must be a list of values", id)))
+            .collect::<Result<Vec<_>, _>>()?,
+        _ => vec![scalar(value).ok_or_else(|| format!("'{}' takes a single value", id))?],
+    };
+    if PATH_OPTIONS.contains(&id) {
+        return Ok(Setting::Values(
+            values
+                .into_iter()
+                .map(|value| base_dir.join(value).to_string_lossy().into_owned())
+                .collect(),
+        ));
+    }
+    Ok(Setting::Values(values))
+}
+
+fn env_setting(arg: &Arg, value: String) -> Result<Setting, String> {
+    if !is_flag(arg) {
+        return Ok(Setting::Values(vec![value]));
+    }
+    match value.trim().to_ascii_lowercase().as_str() {
+        "1" | "true" | "yes" | "on" => Ok(Setting::Flag(true)),
+        "" | "0" | "false" | "no" | "off" => Ok(Setting::Flag(false)),
+        _ => Err(format!(
+            "{} must be true or false, not '{}'",
+            env_name(arg.get_id().as_str()),
+            value
+        )),
+    }
+}
+
+fn load(command: &Command, path: &Path) -> Result<Config, String> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| format!("Cannot read config {}: {}", path.display(), e))?;
+    let table: toml::Table = text
+        .parse()
+        .map_err(|e| format!("Invalid config {}: {}", path.display(), e))?;
+    let base_dir = path.parent().unwrap_or_else(|| Path::new("."));
+
+    let mut config = Config {
+        path: path.to_path_buf(),
+        options: HashMap::new(),
+        datasets: false,
+    };
+    let mut add = |key: &str, value: &toml::Value| -> Result<(), String> {
+        let arg = find_option(command, key)
+            .filter(|arg| arg.get_id().as_str() != "config")
+            .ok_or_else(|| format!("Unknown option '{}' in {}", key, path.display()))?;
+        let setting = config_setting(arg, value, base_dir)
+            .map_err(|e| format!("{} in {}", e, path.display()))?;
+        config
+            .options
+            .insert(arg.get_id().as_str().to_string(), setting);
+        Ok(())
+    };
+    for (key, value) in &table {
+        match value {
+            // Datasets are read by Manifest::load
+            toml::Value::Array(_) if key == "datasets" => {}
+            toml::Value::Table(section) => {
+                for (key, value) in section {
+                    add(key, value)?;
+                }
+            }
+            _ => add(key, value)?,
+        }
+    }
+    config.datasets = table.contains_key("datasets");
+    Ok(config)
+}
+
+// Options the user gave on the command line, found without running clap
+#[derive(Default)]
+struct Given {
+    ids: HashSet<String>,
+    config: Option<PathBuf>,
+    subcommand: bool,
+}
+
+impl Given {
+    fn scan(command: &Command, argv: &[OsString]) -> Given {
+        let mut given = Given::default();
+        let positional = command
+            .get_positionals()
+            .next()
+            .map(|arg| arg.get_id().as_str().to_string());
+        let mut tokens = argv.iter();
+        while let Some(token) = tokens.next() {
+            let text = token.to_string_lossy();
+            if text == "--" {
+                if tokens.next().is_some() {
+                    given.ids.extend(positional.clone());
+                }
+                break;
+            }
+            if let Some(long) = text.strip_prefix("--") {
+                let (name, inline) = match long.split_once('=') {
+                    Some((name, value)) => (name, Some(OsString::from(value))),
+                    None => (long, None),
+                };
+                let Some(arg) = command.get_arguments().find(|a| a.get_long() == Some(name)) else {
+                    continue;
+                };
+                let value = match inline {
+                    None if arg.get_action().takes_values() => tokens.next().cloned(),
+                    value => value,
+                };
+                if arg.get_id().as_str() == "config" {
+                    given.config = value.map(PathBuf::from);
+                }
+                given.ids.insert(arg.get_id().as_str().to_string());
+                continue;
+            }
+            if text.starts_with('-') && text.len() > 1 {
+                continue;
+            }
+            if given.ids.is_empty() && command.find_subcommand(token).is_some() {
+                given.subcommand = true;
+                break;
+            }
+            given.ids.extend(positional.clone());
+        }
+        given
+    }
+}
+
+fn push_setting(arg: &Arg, setting: Setting, argv: &mut Vec<OsString>) {
+    let values = match setting {
+        Setting::Flag(false) => return,
+        Setting::Flag(true) => Vec::new(),
+        Setting::Values(values) => values,
+    };
+    let Some(long) = arg.get_long() else {
+        argv.extend(values.into_iter().map(OsString::from));
+        return;
+    };
+    if values.is_empty() {
+        argv.push(format!("--{}", long).into());
+        return;
+    }
+    let delimiter = arg.get_value_delimiter().unwrap_or(',');
+    let joined = values.join(&delimiter.to_string());
+    // Attached with '=' so values starting with '-' aren't taken for options
+    argv.push(format!("--{}={}", long, joined).into());
+}
+
+// `argv` with the settings from --config and VCF_MCP_* variables added ahead of the user's
+// arguments; left as it is for subcommands
+pub fn expand_args(command: &Command, argv: Vec<OsString>) -> Result<Vec<OsString>, String> {
+    expand_args_with(command, argv, |name| std::env::var(name).ok())
+}
+
+fn expand_args_with(
+    command: &Command,
+    argv: Vec<OsString>,
+    env: impl Fn(&str) -> Option<String>,
+) -> Result<Vec<OsString>, String> {
+    let Some((program, user)) = argv.split_first() else {
+        return Ok(argv);
+    };
+    let given = Given::scan(command, user);
+    if given.subcommand {
+        return Ok(argv);
+    }
+
+    let config_path = given
+        .config
+        .clone()
+        .or_else(|| env(&env_name("config")).map(PathBuf::from));
+    let config = config_path
+        .as_deref()
+        .map(|path| load(command, path))
+        .transpose()?;
+
+    let mut expanded = vec![program.clone()];
+    let mut set: HashSet<String> = given.ids.clone();
+    if let (None, Some(path)) = (&given.config, &config_path) {
+        expanded.push(format!("--config={}", path.display()).into());
+    }
+    for arg in command.get_arguments() {
+        let id = arg.get_id().as_str();
+        if id == "config" || given.ids.contains(id) {
+            continue;
+        }
+        let setting = match env(&env_name(id)) {
+            Some(value) => env_setting(arg, value)?,
+            None => match config.as_ref().and_then(|c| c.options.get(id)) {
+                Some(setting) => setting.clone(),
+                None => continue,
+            },
+        };
+        if setting != Setting::Flag(false) {
+            set.insert(id.to_string());
+        }
+        push_setting(arg, setting, &mut expanded);
+    }
+    // [[datasets]] make the config file the manifest, unless a file or manifest was given
+    if let Some(config) = config.filter(|c| c.datasets) {
+        if !set.contains("vcf_file") && !set.contains("manifest") {
+            expanded.push(format!("--manifest={}", config.path.display()).into());
+        }
+    }
+    expanded.extend(user.iter().cloned());
+    Ok(expanded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Args;
+    use clap::{CommandFactory, Parser};
+
+    fn expand(argv: &[&str], env: &[(&str, &str)]) -> Result<Args, String> {
+        let env: HashMap<String, String> = env
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        let argv = argv.iter().map(OsString::from).collect();
+        let expanded = expand_args_with(&Args::command(), argv, |name| env.get(name).cloned())?;
+        Args::try_parse_from(expanded).map_err(|e| e.to_string())
+    }
+
+    #[test]
+    fn test_path_options_exist() {
+        let command = Args::command();
+        for id in PATH_OPTIONS {
+            assert!(find_option(&command, id).is_some(), "{}", id);
+        }
+    }
+
+    #[test]
+    fn test_config_file_with_env_and_command_line_overrides() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("server.toml");
+        std::fs::write(
+            &path,
+            r#"
+vcf_file = "calls.vcf.gz"
+debug = true
+mmap = false
+
+[http]
+sse = "127.0.0.1:8090"
+cors-origins = ["http://localhost:6274", "https://inspector.example.org"]
+
+[limits]
+max_results = 1000
+query_timeout = 60
+"#,
+        )
+        .unwrap();
+        let config = path.to_str().unwrap();
+
+        let args = expand(
+            &[
+                "vcf_mcp_server",
+                "--config",
+                config,
+                "--sse",
+                "0.0.0.0:9000",
+            ],
+            &[("VCF_MCP_MAX_RESULTS", "50"), ("VCF_MCP_LOW_MEMORY", "1")],
+        )
+        .unwrap();
+        assert_eq!(args.vcf_file, Some(dir.path().join("calls.vcf.gz")));
+        assert_eq!(args.config.as_deref(), Some(path.as_path()));
+        assert!(args.debug);
+        assert!(!args.mmap);
+        assert!(args.low_memory);
+        assert_eq!(args.sse.as_deref(), Some("0.0.0.0:9000"));
+        assert_eq!(args.cors_origins.len(), 2);
+        assert_eq!(args.max_results, 50);
+        assert_eq!(args.query_timeout, 60);
+
+        // Found through VCF_MCP_CONFIG; a file on the command line replaces the configured one
+        let args = expand(
+            &["vcf_mcp_server", "other.vcf.gz"],
+            &[("VCF_MCP_CONFIG", config)],
+        )
+        .unwrap();
+        assert_eq!(args.vcf_file, Some(PathBuf::from("other.vcf.gz")));
+        assert_eq!(args.config.as_deref(), Some(path.as_path()));
+        assert_eq!(args.max_results, 1000);
+    }
+
+    #[test]
+    fn test_config_datasets_become_the_manifest() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("trio.vcf.gz"), b"").unwrap();
+        let path = dir.path().join("server.toml");
+        std::fs::write(
+            &path,
+            r#"
+max_loaded_datasets = 4
+
+[[datasets]]
+name = "trio"
+path = "trio.vcf.gz"
+description = "Family trio"
+"#,
+        )
+        .unwrap();
+
+        let args = expand(&["vcf_mcp_server", "--config", path.to_str().unwrap()], &[]).unwrap();
+        assert_eq!(args.manifest.as_deref(), Some(path.as_path()));
+        assert_eq!(args.max_loaded_datasets, 4);
+        let manifest = crate::supervisor::Manifest::load(&path).unwrap();
+        assert_eq!(manifest.datasets.len(), 1);
+        assert_eq!(manifest.datasets[0].path, dir.path().join("trio.vcf.gz"));
+    }
+
+    #[test]
+    fn test_config_errors() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("server.toml");
+        let config = path.to_str().unwrap();
+
+        std::fs::write(&path, "max_resutls = 10\n").unwrap();
+        let error = expand(&["vcf_mcp_server", "--config", config, "a.vcf.gz"], &[]).unwrap_err();
+        assert!(error.contains("Unknown option 'max_resutls'"), "{}", error);
+
+        std::fs::write(&path, "debug = \"yes\"\n").unwrap();
+        assert!(expand(&["vcf_mcp_server", "--config", config, "a.vcf.gz"], &[]).is_err());
+
+        std::fs::write(&path, "sse = [\"a\", \"b\"]\n").unwrap();
+        assert!(expand(&["vcf_mcp_server", "--config", config, "a.vcf.gz"], &[]).is_err());
+
+        assert!(expand(
+            &["vcf_mcp_server", "a.vcf.gz"],
+            &[("VCF_MCP_DEBUG", "maybe")]
+        )
+        .is_err());
+
+        // Subcommands are passed through untouched
+        let args = expand(
+            &["vcf_mcp_server", "cache", "ls"],
+            &[("VCF_MCP_CONFIG", "/nonexistent.toml")],
+        )
+        .unwrap();
+        assert!(args.command.is_some());
+    }
+}
@@ -0,0 +1,361 @@
+// dbSNP rsID lookups in the NCBI Variation API for query_by_id's resolve_via_ncbi option,
+// available only when the server runs with --allow-network.
+//
+// GET /refsnp/<number> returns the RefSNP record, whose placements give the variant's SPDI
+// (sequence, 0-based position, deleted and inserted sequence) on each assembly's RefSeq
+// chromosome (NC_000020.11 is chromosome 20 of GRCh38). The placement on the VCF's build is
+// turned into 1-based coordinates; a merged rsID is followed once to the record it was merged
+// into. SPDI alleles are not anchored like VCF indels and may span a whole repeat, so alleles
+// are compared after trimming the bases shared by REF and ALT at both ends.
+
+use crate::vcf::Variant;
+use std::time::Duration;
+
+pub const NCBI_VARIATION_SERVER: &str = "https://api.ncbi.nlm.nih.gov/variation/v0";
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+pub struct DbSnpClient {
+    http: reqwest::Client,
+    server: String,
+}
+
+// Where dbSNP places an rsID on one assembly, in VCF terms
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct Placement {
+    // The rsID the coordinates belong to, after following a merge
+    pub rsid: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub merged_into: Option<String>,
+    // Assembly name as NCBI reports it, e.g. "GRCh38.p14"
+    pub assembly: String,
+    pub chromosome: String,
+    // 1-based position of the first deleted base (or of the base after an insertion)
+    pub position: u64,
+    pub reference: String,
+    pub alternate: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DbSnpError {
+    pub message: String,
+    // Worth repeating later (rate limiting, server or network trouble)
+    pub transient: bool,
+}
+
+// The number of an rsID ("rs6054257" -> "6054257"), compared ignoring case
+pub fn rsid_number(id: &str) -> Option<&str> {
+    let number = id
+        .strip_prefix("rs")
+        .or_else(|| id.strip_prefix("RS"))
+        .or_else(|| id.strip_prefix("Rs"))?;
+    (!number.is_empty() && number.bytes().all(|b| b.is_ascii_digit())).then_some(number)
+}
+
+// Chromosome name of a RefSeq chromosome accession: NC_000001-NC_000022, NC_000023 (X),
+// NC_000024 (Y) and NC_012920 (MT)
+fn refseq_chromosome(accession: &str) -> Option<String> {
+    let (name, _version) = accession.split_once('.').unwrap_or((accession, ""));
+    if name == "NC_012920" {
+        return Some("MT".to_string());
+    }
+    let number: u32 = name.strip_prefix("NC_0000")?.parse().ok()?;
+    match number {
+        1..=22 => Some(number.to_string()),
+        23 => Some("X".to_string()),
+        24 => Some("Y".to_string()),
+        _ => None,
+    }
+}
+
+// The placement of a RefSNP record (GET /refsnp/<number>) on the assembly whose name starts
+// with `build` ("GRCh38", "GRCh37"); Err names the assemblies it has instead
+pub fn placement_on_build(
+    refsnp: &serde_json::Value,
+    build: &str,
+) -> Result<Option<Placement>, String> {
+    let rsid = format!(
+        "rs{}",
+        refsnp
+            .get("refsnp_id")
+            .and_then(serde_json::Value::as_str)
+            .unwrap_or_default()
+    );
+    let Some(placements) = refsnp
+        .pointer("/primary_snapshot_data/placements_with_allele")
+        .and_then(serde_json::Value::as_array)
+    else {
+        return Ok(None);
+    };
+
+    let mut assemblies = Vec::new();
+    for placement in placements {
+        let Some(traits) = placement
+            .pointer("/placement_annot/seq_id_traits_by_assembly")
+            .and_then(serde_json::Value::as_array)
+        else {
+            continue;
+        };
+        let Some(assembly) = traits.iter().find_map(|traits| {
+            let is_chromosome = traits.get("is_chromosome")?.as_bool()?;
+            let name = traits.get("assembly_name")?.as_str()?;
+            is_chromosome.then_some(name)
+        }) else {
+            continue;
+        };
+        assemblies.push(assembly.to_string());
+        if !assembly
+            .to_ascii_lowercase()
+            .starts_with(&build.to_ascii_lowercase())
+        {
+            continue;
+        }
+
+        let Some(chromosome) = placement
+            .get("seq_id")
+            .and_then(serde_json::Value::as_str)
+            .and_then(refseq_chromosome)
+        else {
+            continue;
+        };
+        let spdis: Vec<&serde_json::Value> = placement
+            .get("alleles")
+            .and_then(serde_json::Value::as_array)
+            .map(|alleles| {
+                alleles
+                    .iter()
+                    .filter_map(|allele| allele.pointer("/allele/spdi"))
+                    .collect()
+            })
+            .unwrap_or_default();
+        let text = |spdi: &serde_json::Value, key: &str| {
+            spdi.get(key)
+                .and_then(serde_json::Value::as_str)
+                .unwrap_or_default()
+                .to_string()
+        };
+        let Some(first) = spdis.first() else {
+            continue;
+        };
+        let Some(position) = first.get("position").and_then(serde_json::Value::as_u64) else {
+            continue;
+        };
+        let reference = text(first, "deleted_sequence");
+        let alternate = spdis
+            .iter()
+            .map(|spdi| text(spdi, "inserted_sequence"))
+            .filter(|inserted| *inserted != reference)
+            .collect();
+        return Ok(Some(Placement {
+            rsid,
+            merged_into: None,
+            assembly: assembly.to_string(),
+            chromosome,
+            position: position + 1,
+            reference,
+            alternate,
+        }));
+    }
+    if assemblies.is_empty() {
+        Ok(None)
+    } else {
+        Err(format!(
+            "dbSNP places {} on {} but not on {}",
+            rsid,
+            assemblies.join(", "),
+            build
+        ))
+    }
+}
+
+// REF and ALT without the bases they share at the end and then at the start
+fn trimmed<'a>(reference: &'a str, alternate: &'a str) -> (&'a str, &'a str) {
+    let (mut reference, mut alternate) = (reference.as_bytes(), alternate.as_bytes());
+    while let (Some(r), Some(a)) = (reference.last(), alternate.last()) {
+        if !r.eq_ignore_ascii_case(a) {
+            break;
+        }
+        reference = &reference[..reference.len() - 1];
+        alternate = &alternate[..alternate.len() - 1];
+    }
+    while let (Some(r), Some(a)) = (reference.first(), alternate.first()) {
+        if !r.eq_ignore_ascii_case(a) {
+            break;
+        }
+        reference = &reference[1..];
+        alternate = &alternate[1..];
+    }
+    // Only whole ASCII bases were removed, so both are still valid UTF-8
+    (
+        std::str::from_utf8(reference).unwrap_or_default(),
+        std::str::from_utf8(alternate).unwrap_or_default(),
+    )
+}
+
+// Whether a VCF record shares an ALT allele with a dbSNP placement, compared after trimming
+pub fn matches_placement(variant: &Variant, placement: &Placement) -> bool {
+    variant.alternate.iter().any(|alternate| {
+        let (reference, alternate) = trimmed(&variant.reference, alternate);
+        placement.alternate.iter().any(|inserted| {
+            let (deleted, inserted) = trimmed(&placement.reference, inserted);
+            deleted.eq_ignore_ascii_case(reference) && inserted.eq_ignore_ascii_case(alternate)
+        })
+    })
+}
+
+impl DbSnpClient {
+    pub fn new(server: &str) -> Result<Self, reqwest::Error> {
+        let http = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .user_agent(concat!("vcf_mcp_server/", env!("CARGO_PKG_VERSION")))
+            .build()?;
+        Ok(DbSnpClient {
+            http,
+            server: server.trim_end_matches('/').to_string(),
+        })
+    }
+
+    pub fn refsnp_url(&self, number: &str) -> String {
+        format!("{}/refsnp/{}", self.server, number)
+    }
+
+    // Where dbSNP places rsID number `number` on `build`; None for unknown or withdrawn rsIDs
+    pub async fn resolve(
+        &self,
+        number: &str,
+        build: &str,
+    ) -> Result<Option<Placement>, DbSnpError> {
+        let refsnp = match self.refsnp(number).await? {
+            Some(refsnp) => refsnp,
+            None => return Ok(None),
+        };
+        let merged_into = refsnp
+            .pointer("/merged_snapshot_data/merged_into/0")
+            .and_then(serde_json::Value::as_str)
+            .map(str::to_string);
+        let refsnp = match &merged_into {
+            Some(current)
+                if refsnp
+                    .get("primary_snapshot_data")
+                    .is_none_or(|v| v.is_null()) =>
+            {
+                match self.refsnp(current).await? {
+                    Some(refsnp) => refsnp,
+                    None => return Ok(None),
+                }
+            }
+            _ => refsnp,
+        };
+        let placement = placement_on_build(&refsnp, build).map_err(|message| DbSnpError {
+            message,
+            transient: false,
+        })?;
+        Ok(placement.map(|placement| Placement {
+            merged_into: merged_into.map(|number| format!("rs{}", number)),
+            ..placement
+        }))
+    }
+
+    async fn refsnp(&self, number: &str) -> Result<Option<serde_json::Value>, DbSnpError> {
+        let response = self
+            .http
+            .get(self.refsnp_url(number))
+            .header(reqwest::header::ACCEPT, "application/json")
+            .send()
+            .await
+            .map_err(|e| DbSnpError {
+                message: format!(
+                    "NCBI Variation API request to {} failed: {}",
+                    self.server, e
+                ),
+                transient: true,
+            })?;
+        let status = response.status();
+        if status == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        if !status.is_success() {
+            return Err(DbSnpError {
+                message: format!("NCBI Variation API returned {}", status),
+                transient: status.as_u16() == 429 || status.is_server_error(),
+            });
+        }
+        response.json().await.map(Some).map_err(|e| DbSnpError {
+            message: format!("Unexpected response from the NCBI Variation API: {}", e),
+            transient: false,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn refsnp() -> serde_json::Value {
+        let placement = |accession: &str, assembly: &str, position: u64| {
+            serde_json::json!({
+                "seq_id": accession,
+                "placement_annot": {
+                    "seq_id_traits_by_assembly": [
+                        {"assembly_name": assembly, "is_chromosome": true}
+                    ]
+                },
+                "alleles": [
+                    {"allele": {"spdi": {"seq_id": accession, "position": position,
+                        "deleted_sequence": "CAA", "inserted_sequence": "CAA"}}},
+                    {"allele": {"spdi": {"seq_id": accession, "position": position,
+                        "deleted_sequence": "CAA", "inserted_sequence": "CA"}}}
+                ]
+            })
+        };
+        serde_json::json!({
+            "refsnp_id": "123",
+            "primary_snapshot_data": {
+                "placements_with_allele": [
+                    placement("NC_000020.11", "GRCh38.p14", 1_000),
+                    placement("NC_000020.10", "GRCh37.p13", 900),
+                    {"seq_id": "NG_000001.1", "placement_annot": {"seq_id_traits_by_assembly": []}}
+                ]
+            }
+        })
+    }
+
+    #[test]
+    fn test_rsid_number() {
+        assert_eq!(rsid_number("rs6054257"), Some("6054257"));
+        assert_eq!(rsid_number("RS12"), Some("12"));
+        assert_eq!(rsid_number("rs"), None);
+        assert_eq!(rsid_number("microsat1"), None);
+        assert_eq!(rsid_number("rs12a"), None);
+    }
+
+    #[test]
+    fn test_placement_on_build() {
+        let placement = placement_on_build(&refsnp(), "GRCh37").unwrap().unwrap();
+        assert_eq!(placement.rsid, "rs123");
+        assert_eq!(placement.assembly, "GRCh37.p13");
+        assert_eq!(placement.chromosome, "20");
+        assert_eq!(placement.position, 901);
+        assert_eq!(placement.reference, "CAA");
+        assert_eq!(placement.alternate, vec!["CA"]);
+
+        let error = placement_on_build(&refsnp(), "NCBI36").unwrap_err();
+        assert!(error.contains("GRCh38.p14, GRCh37.p13"));
+        assert_eq!(
+            placement_on_build(&serde_json::json!({"refsnp_id": "1"}), "GRCh38"),
+            Ok(None)
+        );
+        assert_eq!(refseq_chromosome("NC_000023.11").as_deref(), Some("X"));
+        assert_eq!(refseq_chromosome("NC_012920.1").as_deref(), Some("MT"));
+        assert_eq!(refseq_chromosome("NT_187361.1"), None);
+    }
+
+    #[test]
+    fn test_trimmed_alleles() {
+        // A VCF deletion anchored on the preceding base against dbSNP's repeat-wide SPDI
+        assert_eq!(trimmed("TA", "T"), ("A", ""));
+        assert_eq!(trimmed("CAA", "CA"), ("A", ""));
+        assert_eq!(trimmed("G", "A"), ("G", "A"));
+        assert_eq!(trimmed("GTC", "GTCT"), ("", "T"));
+    }
+}
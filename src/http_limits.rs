@@ -0,0 +1,217 @@
+// Session and connection limits for the HTTP transport.
+//
+// SessionTable counts the stateful MCP sessions (--stateful-sessions) a client opened and has
+// not ended, so --max-sessions can turn away new ones with 503 once full. rmcp assigns the
+// Mcp-Session-Id when it answers `initialize`; the table learns it from that response, forgets
+// it on DELETE or when rmcp no longer knows it (404), and expires sessions idle for longer than
+// --session-keep-alive, the same idle time after which rmcp closes them.
+//
+// IdleTracked wraps a connection's socket and notes when bytes last moved in either
+// direction, so --idle-timeout can close connections that carry nothing, including open event
+// streams whose keep-alive pings are disabled.
+
+use std::collections::HashMap;
+use std::io;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+// Open stateful sessions and when each was last used
+#[derive(Debug)]
+pub struct SessionTable {
+    max: usize,
+    keep_alive: Option<Duration>,
+    sessions: Mutex<HashMap<String, Instant>>,
+}
+
+impl SessionTable {
+    pub fn new(max: usize, keep_alive: Option<Duration>) -> Self {
+        SessionTable {
+            max,
+            keep_alive,
+            sessions: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // Whether another session may start, after forgetting the ones rmcp has expired
+    pub fn has_room(&self) -> bool {
+        let mut sessions = self.sessions.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(keep_alive) = self.keep_alive {
+            sessions.retain(|_, last_used| last_used.elapsed() < keep_alive);
+        }
+        sessions.len() < self.max
+    }
+
+    pub fn opened(&self, id: &str) {
+        self.used(id);
+    }
+
+    pub fn used(&self, id: &str) {
+        let mut sessions = self.sessions.lock().unwrap_or_else(|e| e.into_inner());
+        sessions.insert(id.to_string(), Instant::now());
+    }
+
+    pub fn closed(&self, id: &str) {
+        let mut sessions = self.sessions.lock().unwrap_or_else(|e| e.into_inner());
+        sessions.remove(id);
+    }
+
+    #[cfg(test)]
+    fn len(&self) -> usize {
+        self.sessions.lock().unwrap().len()
+    }
+}
+
+// When bytes last moved on a connection
+#[derive(Debug, Clone)]
+pub struct Activity {
+    started: Instant,
+    // Milliseconds after `started`
+    last_ms: Arc<AtomicU64>,
+}
+
+impl Activity {
+    pub fn new() -> Self {
+        Activity {
+            started: Instant::now(),
+            last_ms: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    fn touch(&self) {
+        let now = self.started.elapsed().as_millis() as u64;
+        self.last_ms.fetch_max(now, Ordering::Relaxed);
+    }
+
+    pub fn idle_for(&self) -> Duration {
+        let last = Duration::from_millis(self.last_ms.load(Ordering::Relaxed));
+        self.started.elapsed().saturating_sub(last)
+    }
+
+    // Resolve once nothing has been read or written for `timeout`
+    pub async fn until_idle(&self, timeout: Duration) {
+        loop {
+            let idle = self.idle_for();
+            if idle >= timeout {
+                return;
+            }
+            tokio::time::sleep(timeout - idle).await;
+        }
+    }
+}
+
+// Socket that records its reads and writes in an Activity
+pub struct IdleTracked<T> {
+    inner: T,
+    activity: Activity,
+}
+
+impl<T> IdleTracked<T> {
+    pub fn new(inner: T, activity: Activity) -> Self {
+        activity.touch();
+        IdleTracked { inner, activity }
+    }
+}
+
+impl<T: AsyncRead + Unpin> AsyncRead for IdleTracked<T> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let filled = buf.filled().len();
+        let poll = Pin::new(&mut self.inner).poll_read(cx, buf);
+        if buf.filled().len() > filled {
+            self.activity.touch();
+        }
+        poll
+    }
+}
+
+impl<T: AsyncWrite + Unpin> AsyncWrite for IdleTracked<T> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let poll = Pin::new(&mut self.inner).poll_write(cx, buf);
+        if matches!(poll, Poll::Ready(Ok(n)) if n > 0) {
+            self.activity.touch();
+        }
+        poll
+    }
+
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        let poll = Pin::new(&mut self.inner).poll_write_vectored(cx, bufs);
+        if matches!(poll, Poll::Ready(Ok(n)) if n > 0) {
+            self.activity.touch();
+        }
+        poll
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.inner.is_write_vectored()
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[test]
+    fn test_session_table_counts_open_sessions() {
+        let table = SessionTable::new(2, None);
+        assert!(table.has_room());
+        table.opened("a");
+        table.opened("b");
+        assert!(!table.has_room());
+        table.used("a");
+        assert_eq!(table.len(), 2);
+        table.closed("a");
+        assert!(table.has_room());
+
+        // Sessions idle past the keep-alive have been closed by rmcp
+        let table = SessionTable::new(1, Some(Duration::ZERO));
+        table.opened("a");
+        assert!(table.has_room());
+        assert_eq!(table.len(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_idle_tracked_notes_reads_and_writes() {
+        let (client, server) = tokio::io::duplex(64);
+        let activity = Activity::new();
+        let mut server = IdleTracked::new(server, activity.clone());
+        let mut client = client;
+
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(activity.idle_for() >= Duration::from_millis(20));
+        client.write_all(b"ping").await.unwrap();
+        let mut buf = [0u8; 4];
+        server.read_exact(&mut buf).await.unwrap();
+        assert!(activity.idle_for() < Duration::from_millis(20));
+
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        server.write_all(b"pong").await.unwrap();
+        assert!(activity.idle_for() < Duration::from_millis(20));
+
+        // Resolves at once when already idle long enough
+        activity.until_idle(Duration::ZERO).await;
+    }
+}
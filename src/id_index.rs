@@ -0,0 +1,1723 @@
+// On-disk ID index: an FST mapping variant ID -> offset into a postings table of
+// (chromosome id, position) pairs. The file is memory-mapped, so lookups only touch
+// the pages they need instead of holding every ID in a HashMap. A bloom filter in front
+// of the FST answers most lookups for IDs that are not in the file without walking it.
+//
+// File layout (all integers little-endian):
+//   magic               8 bytes  "VCFIDIDX"
+//   version             u32      format version, bumped on incompatible changes
+//   flags               u32      low 16 bits: features a reader must support to use the file;
+//                                high 16 bits: optional sections a reader may ignore
+//   chromosome table    u64 length + newline-separated chromosome names
+//   fst                 u64 length + fst::Map bytes (ID -> postings offset)
+//   postings            u64 length + per ID: u32 count, then count x (u32 chromosome id, u64 position)
+//   source fingerprint  u64 length + u64 size, u64 mtime (ns since epoch), u64 checksum of the VCF
+//   optional sections   u64 length + bytes each, in flag bit order:
+//     bloom filter      (FLAG_BLOOM) u8 hash count, then the bit array
+//     position ranges   (FLAG_POSITION_RANGES) per chromosome in table order: u64 first start,
+//                       u64 last end of its variants
+//     sample presence   (FLAG_SAMPLE_PRESENCE) u32 sample count, then per variant in file order:
+//                       u32 chromosome id, u64 position, ceil(samples / 8) bytes with bit i set
+//                       when sample i has an ALT allele in its GT
+//     variant positions (FLAG_VARIANT_POSITIONS) the start positions of each chromosome's
+//                       variants as a roaring-style bitmap, see PositionSet
+//
+// Files with an older version or an unknown magic (including the bincode HashMap used before
+// the FST index and the unversioned "VCFIDFST" layout) fail with InvalidData and are rebuilt.
+// Files from a newer version, or requiring features this build doesn't know, fail with
+// Unsupported so they can be left in place for the version that wrote them.
+
+use fst::automaton::{Automaton, Str};
+use fst::{IntoStreamer, Streamer};
+use memmap2::Mmap;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BinaryHeap, HashMap};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+const MAGIC: &[u8; 8] = b"VCFIDIDX";
+// Pre-versioning layout with the same sections, but no version or flags
+const LEGACY_MAGIC: &[u8; 8] = b"VCFIDFST";
+pub const FORMAT_VERSION: u32 = 1;
+const HEADER_SIZE: usize = 16;
+
+const REQUIRED_FLAGS_MASK: u32 = 0x0000_ffff;
+// No required features exist yet in version 1
+const KNOWN_REQUIRED_FLAGS: u32 = 0;
+const FLAG_BLOOM: u32 = 1 << 16;
+const FLAG_POSITION_RANGES: u32 = 1 << 17;
+// Only written when requested (--sample-presence-index): it grows with variants x samples
+const FLAG_SAMPLE_PRESENCE: u32 = 1 << 18;
+const FLAG_VARIANT_POSITIONS: u32 = 1 << 19;
+const DEFAULT_FLAGS: u32 = FLAG_BLOOM | FLAG_POSITION_RANGES | FLAG_VARIANT_POSITIONS;
+
+const POSTING_SIZE: usize = 12; // u32 chromosome id + u64 position
+const REQUIRED_SECTIONS: usize = 4;
+const FINGERPRINT_SIZE: usize = 24;
+// Bytes hashed at each end of the VCF for the fingerprint checksum
+const CHECKSUM_SPAN: u64 = 64 * 1024;
+
+// Position set containers: arrays of up to 4096 u16 values, bitmaps of 65536 bits above that
+const ARRAY_CONTAINER_MAX: usize = 4096;
+const BITMAP_CONTAINER_BYTES: usize = 8192;
+// Per chromosome: u64 max span, u64 directory offset, u32 container count
+const POSITION_TABLE_ENTRY_SIZE: usize = 20;
+// Per container: u32 key, u32 cardinality, u64 data offset
+const CONTAINER_ENTRY_SIZE: usize = 16;
+
+// ~1% false positives: 10 bits per ID with 7 hash functions
+const BLOOM_BITS_PER_ID: usize = 10;
+const BLOOM_HASHES: u8 = 7;
+const BLOOM_MIN_BITS: usize = 64;
+
+fn invalid_data<E>(e: E) -> std::io::Error
+where
+    E: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    std::io::Error::new(std::io::ErrorKind::InvalidData, e)
+}
+
+fn unsupported(msg: String) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::Unsupported, msg)
+}
+
+// Backing storage for the index: either a built image in memory or a mapped file
+#[derive(Clone)]
+enum IndexBytes {
+    Owned(Arc<Vec<u8>>),
+    Mapped(Arc<Mmap>),
+}
+
+impl AsRef<[u8]> for IndexBytes {
+    fn as_ref(&self) -> &[u8] {
+        match self {
+            Self::Owned(bytes) => bytes.as_slice(),
+            Self::Mapped(mmap) => mmap.as_ref(),
+        }
+    }
+}
+
+// A section of the index file, usable as fst::Map storage without copying
+#[derive(Clone)]
+struct Section {
+    bytes: IndexBytes,
+    start: usize,
+    end: usize,
+}
+
+impl AsRef<[u8]> for Section {
+    fn as_ref(&self) -> &[u8] {
+        &self.bytes.as_ref()[self.start..self.end]
+    }
+}
+
+// Stable 64-bit FNV-1a hash (the filter is persisted, so std's hasher can't be used)
+pub(crate) fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &b| {
+        (hash ^ b as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+// Identifies the VCF an index was built from, so a regenerated file triggers a rebuild.
+// Hashing the whole file would cost as much as rebuilding, so the checksum covers its
+// first and last 64 KiB (the header and the BGZF tail); size and mtime catch the rest.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SourceFingerprint {
+    pub size: u64,
+    pub modified_nanos: u64,
+    pub checksum: u64,
+}
+
+impl SourceFingerprint {
+    pub fn of_file(path: &Path) -> std::io::Result<Self> {
+        let mut file = File::open(path)?;
+        let metadata = file.metadata()?;
+        let size = metadata.len();
+        let modified_nanos = metadata
+            .modified()
+            .ok()
+            .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or(0);
+
+        let mut sample = Vec::new();
+        (&mut file).take(CHECKSUM_SPAN).read_to_end(&mut sample)?;
+        if size > CHECKSUM_SPAN {
+            file.seek(SeekFrom::Start(
+                size.saturating_sub(CHECKSUM_SPAN).max(CHECKSUM_SPAN),
+            ))?;
+            file.take(CHECKSUM_SPAN).read_to_end(&mut sample)?;
+        }
+
+        Ok(SourceFingerprint {
+            size,
+            modified_nanos,
+            checksum: fnv1a(&sample),
+        })
+    }
+
+    fn to_bytes(self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(FINGERPRINT_SIZE);
+        for value in [self.size, self.modified_nanos, self.checksum] {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() != FINGERPRINT_SIZE {
+            return None;
+        }
+        let value = |i: usize| -> Option<u64> {
+            Some(u64::from_le_bytes(
+                bytes.get(i * 8..i * 8 + 8)?.try_into().ok()?,
+            ))
+        };
+        Some(SourceFingerprint {
+            size: value(0)?,
+            modified_nanos: value(1)?,
+            checksum: value(2)?,
+        })
+    }
+}
+
+// Bit positions of an ID, using double hashing (h1 + i * h2)
+fn bloom_positions(id: &[u8], hashes: u8, bit_count: u64) -> impl Iterator<Item = u64> {
+    let h1 = fnv1a(id);
+    // splitmix64 finalizer derives an independent second hash; forced odd so it never degenerates
+    let mut h2 = h1.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    h2 = (h2 ^ (h2 >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    h2 = (h2 ^ (h2 >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    h2 = (h2 ^ (h2 >> 31)) | 1;
+    (0..hashes as u64).map(move |i| h1.wrapping_add(i.wrapping_mul(h2)) % bit_count)
+}
+
+// Bloom filter section under construction, sized for a known number of IDs
+struct BloomBuilder {
+    section: Vec<u8>,
+    bit_count: u64,
+}
+
+impl BloomBuilder {
+    fn new(id_count: usize) -> Self {
+        let bit_count = (id_count * BLOOM_BITS_PER_ID)
+            .max(BLOOM_MIN_BITS)
+            .next_multiple_of(8);
+        let mut section = vec![0u8; 1 + bit_count / 8];
+        section[0] = BLOOM_HASHES;
+        BloomBuilder {
+            section,
+            bit_count: bit_count as u64,
+        }
+    }
+
+    fn insert(&mut self, id: &[u8]) {
+        for bit in bloom_positions(id, BLOOM_HASHES, self.bit_count) {
+            self.section[1 + (bit / 8) as usize] |= 1 << (bit % 8);
+        }
+    }
+}
+
+// Serialize a bloom filter section for the given IDs
+fn build_bloom<'a>(ids: impl ExactSizeIterator<Item = &'a String>) -> Vec<u8> {
+    let mut bloom = BloomBuilder::new(ids.len());
+    for id in ids {
+        bloom.insert(id.as_bytes());
+    }
+    bloom.section
+}
+
+// Lookup counters, reported in debug logging to show how well the bloom filter works
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BloomStats {
+    pub lookups: u64,
+    // Absent IDs answered by the bloom filter alone
+    pub rejected: u64,
+    // Absent IDs that passed the bloom filter and needed an FST lookup
+    pub false_positives: u64,
+    // Theoretical false-positive rate from the filter's fill ratio
+    pub expected_fp_rate: f64,
+}
+
+impl BloomStats {
+    // Fraction of absent IDs that got past the filter
+    pub fn observed_fp_rate(&self) -> f64 {
+        let absent = self.rejected + self.false_positives;
+        if absent == 0 {
+            0.0
+        } else {
+            self.false_positives as f64 / absent as f64
+        }
+    }
+}
+
+// Start positions of one chromosome's variants, kept like a roaring bitmap: positions are
+// grouped by their high bits (the container key), and each group holds the low 16 bits as a
+// sorted array, or as a 65536-bit bitmap once it has more than 4096 of them. Sparse and dense
+// regions both take at most about 2 bytes per variant, and lookups read one or two containers.
+#[derive(Default)]
+struct PositionSet {
+    containers: BTreeMap<u32, Container>,
+    // Longest reference span (end - start) of a variant, so lookups can tell which starts may
+    // reach a position
+    max_span: u64,
+}
+
+enum Container {
+    Array(Vec<u16>),
+    Bitmap(Box<[u8; BITMAP_CONTAINER_BYTES]>),
+}
+
+impl PositionSet {
+    fn insert(&mut self, position: u64, span: u64) {
+        self.max_span = self.max_span.max(span);
+        let key = (position >> 16) as u32;
+        let low = position as u16;
+        let container = self
+            .containers
+            .entry(key)
+            .or_insert_with(|| Container::Array(Vec::new()));
+        match container {
+            Container::Array(values) => {
+                // Records arrive sorted, so this is nearly always an append
+                if let Err(i) = values.binary_search(&low) {
+                    values.insert(i, low);
+                }
+                if values.len() > ARRAY_CONTAINER_MAX {
+                    let mut bits = Box::new([0u8; BITMAP_CONTAINER_BYTES]);
+                    for &value in values.iter() {
+                        bits[value as usize / 8] |= 1 << (value % 8);
+                    }
+                    *container = Container::Bitmap(bits);
+                }
+            }
+            Container::Bitmap(bits) => bits[low as usize / 8] |= 1 << (low % 8),
+        }
+    }
+}
+
+impl Container {
+    fn cardinality(&self) -> usize {
+        match self {
+            Container::Array(values) => values.len(),
+            Container::Bitmap(bits) => bits.iter().map(|b| b.count_ones() as usize).sum(),
+        }
+    }
+
+    fn data_len(&self) -> usize {
+        match self {
+            Container::Array(values) => values.len() * 2,
+            Container::Bitmap(_) => BITMAP_CONTAINER_BYTES,
+        }
+    }
+}
+
+// Serialize the position sets of every chromosome, in table order:
+//   u32 chromosome count
+//   per chromosome: u64 max span, u64 offset of its container directory, u32 container count
+//   per chromosome: its directory, per container u32 key, u32 cardinality, u64 data offset
+//   container data: cardinality x u16 when at most 4096, else the 8192-byte bitmap
+// Offsets are from the start of the section.
+fn position_sets_section(sets: &[PositionSet]) -> Vec<u8> {
+    let container_count: usize = sets.iter().map(|set| set.containers.len()).sum();
+    let mut data_offset =
+        4 + sets.len() * POSITION_TABLE_ENTRY_SIZE + container_count * CONTAINER_ENTRY_SIZE;
+    let mut section = Vec::with_capacity(data_offset);
+    section.extend_from_slice(&(sets.len() as u32).to_le_bytes());
+
+    let mut directory_offset = 4 + sets.len() * POSITION_TABLE_ENTRY_SIZE;
+    for set in sets {
+        section.extend_from_slice(&set.max_span.to_le_bytes());
+        section.extend_from_slice(&(directory_offset as u64).to_le_bytes());
+        section.extend_from_slice(&(set.containers.len() as u32).to_le_bytes());
+        directory_offset += set.containers.len() * CONTAINER_ENTRY_SIZE;
+    }
+    for set in sets {
+        for (key, container) in &set.containers {
+            section.extend_from_slice(&key.to_le_bytes());
+            section.extend_from_slice(&(container.cardinality() as u32).to_le_bytes());
+            section.extend_from_slice(&(data_offset as u64).to_le_bytes());
+            data_offset += container.data_len();
+        }
+    }
+    for set in sets {
+        for container in set.containers.values() {
+            match container {
+                Container::Array(values) => {
+                    for value in values {
+                        section.extend_from_slice(&value.to_le_bytes());
+                    }
+                }
+                Container::Bitmap(bits) => section.extend_from_slice(&bits[..]),
+            }
+        }
+    }
+    section
+}
+
+// Read (version, flags) from the file header
+fn parse_header(data: &[u8]) -> std::io::Result<(u32, u32)> {
+    match data.get(..MAGIC.len()) {
+        Some(magic) if magic == MAGIC => {}
+        Some(magic) if magic == LEGACY_MAGIC => {
+            return Err(invalid_data("unversioned ID index format"))
+        }
+        _ => return Err(invalid_data("not an FST ID index file")),
+    }
+    let field = |offset: usize| -> Option<u32> {
+        Some(u32::from_le_bytes(
+            data.get(offset..offset + 4)?.try_into().ok()?,
+        ))
+    };
+    match (field(8), field(12)) {
+        (Some(version), Some(flags)) => Ok((version, flags)),
+        _ => Err(invalid_data("truncated ID index file")),
+    }
+}
+
+// Collects (ID, chromosome, position) entries and serializes them to the index format
+#[derive(Default)]
+pub struct IdIndexBuilder {
+    chromosomes: Vec<String>,
+    chromosome_ids: HashMap<String, u32>,
+    entries: HashMap<String, Vec<(u32, u64)>>,
+    // (first start, last end) per chromosome id
+    ranges: Vec<(u64, u64)>,
+    source: SourceFingerprint,
+    // Sample presence section (count prefix included) once enabled
+    sample_presence: Option<Vec<u8>>,
+    // Variant start positions per chromosome id
+    positions: Vec<PositionSet>,
+}
+
+impl IdIndexBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Record which VCF the index is built from (checked on load to detect stale indexes)
+    pub fn set_source(&mut self, source: SourceFingerprint) {
+        self.source = source;
+    }
+
+    fn chromosome_id(&mut self, chromosome: &str) -> u32 {
+        match self.chromosome_ids.get(chromosome) {
+            Some(&chromosome_id) => chromosome_id,
+            None => {
+                let chromosome_id = self.chromosomes.len() as u32;
+                self.chromosomes.push(chromosome.to_string());
+                self.chromosome_ids
+                    .insert(chromosome.to_string(), chromosome_id);
+                self.ranges.push((u64::MAX, 0));
+                self.positions.push(PositionSet::default());
+                chromosome_id
+            }
+        }
+    }
+
+    // Record a variant spanning start..=end, with or without an ID, for the position ranges
+    pub fn add_span(&mut self, chromosome: &str, start: u64, end: u64) {
+        let chromosome_id = self.chromosome_id(chromosome) as usize;
+        let range = &mut self.ranges[chromosome_id];
+        range.0 = range.0.min(start);
+        range.1 = range.1.max(end.max(start));
+        self.positions[chromosome_id].insert(start, end.saturating_sub(start));
+    }
+
+    pub fn add(&mut self, id: &str, chromosome: &str, position: u64) {
+        self.add_span(chromosome, position, position);
+        let chromosome_id = self.chromosome_id(chromosome);
+        self.entries
+            .entry(id.to_string())
+            .or_default()
+            .push((chromosome_id, position));
+    }
+
+    // Also write the sample presence section, with bitmaps over `sample_count` samples
+    pub fn enable_sample_presence(&mut self, sample_count: usize) {
+        self.sample_presence = Some((sample_count as u32).to_le_bytes().to_vec());
+    }
+
+    // Record which samples carry a variant: `carriers` is its bitmap of ceil(samples / 8) bytes.
+    // Ignored unless enable_sample_presence was called.
+    pub fn add_sample_presence(&mut self, chromosome: &str, position: u64, carriers: &[u8]) {
+        let chromosome_id = self.chromosome_id(chromosome);
+        if let Some(section) = &mut self.sample_presence {
+            section.extend_from_slice(&chromosome_id.to_le_bytes());
+            section.extend_from_slice(&position.to_le_bytes());
+            section.extend_from_slice(carriers);
+        }
+    }
+
+    // Number of distinct IDs added so far
+    pub fn id_count(&self) -> usize {
+        self.entries.len()
+    }
+
+    // Serialize to the on-disk image (FST keys must be inserted in sorted order)
+    pub fn finish(self) -> std::io::Result<Vec<u8>> {
+        self.finish_with_flags(DEFAULT_FLAGS)
+    }
+
+    fn finish_with_flags(mut self, flags: u32) -> std::io::Result<Vec<u8>> {
+        let sample_presence = self.sample_presence.take();
+        let flags = match sample_presence {
+            Some(_) => flags | FLAG_SAMPLE_PRESENCE,
+            None => flags,
+        };
+        let mut entries: Vec<(String, Vec<(u32, u64)>)> = self.entries.into_iter().collect();
+        entries.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+
+        let mut postings = Vec::new();
+        let mut map_builder = fst::MapBuilder::memory();
+        for (id, locations) in &entries {
+            map_builder
+                .insert(id, postings.len() as u64)
+                .map_err(invalid_data)?;
+            postings.extend_from_slice(&(locations.len() as u32).to_le_bytes());
+            for (chromosome_id, position) in locations {
+                postings.extend_from_slice(&chromosome_id.to_le_bytes());
+                postings.extend_from_slice(&position.to_le_bytes());
+            }
+        }
+        let fst_bytes = map_builder.into_inner().map_err(invalid_data)?;
+        let bloom = if flags & FLAG_BLOOM != 0 {
+            build_bloom(entries.iter().map(|(id, _)| id))
+        } else {
+            Vec::new()
+        };
+
+        let mut sections = vec![self.chromosome_table(), fst_bytes, postings];
+        sections.extend(self.trailing_sections(flags, bloom));
+        sections.extend(sample_presence);
+        sections.extend(self.variant_positions_section(flags));
+
+        let section_bytes: usize = sections.iter().map(|section| 8 + section.len()).sum();
+        let mut image = Vec::with_capacity(HEADER_SIZE + section_bytes);
+        image.extend_from_slice(&file_header(flags));
+        for section in &sections {
+            image.extend_from_slice(&(section.len() as u64).to_le_bytes());
+            image.extend_from_slice(section);
+        }
+        Ok(image)
+    }
+
+    fn chromosome_table(&self) -> Vec<u8> {
+        self.chromosomes.join("\n").into_bytes()
+    }
+
+    // Sections following the postings: the fingerprint, then the optional ones enabled in `flags`
+    // up to the position ranges (the builders append the sample presence section themselves)
+    fn trailing_sections(&self, flags: u32, bloom: Vec<u8>) -> Vec<Vec<u8>> {
+        let mut sections = vec![self.source.to_bytes()];
+        if flags & FLAG_BLOOM != 0 {
+            sections.push(bloom);
+        }
+        if flags & FLAG_POSITION_RANGES != 0 {
+            let mut ranges = Vec::with_capacity(self.ranges.len() * 16);
+            for (first, last) in &self.ranges {
+                ranges.extend_from_slice(&first.to_le_bytes());
+                ranges.extend_from_slice(&last.to_le_bytes());
+            }
+            sections.push(ranges);
+        }
+        sections
+    }
+
+    // The variant positions section, which follows the sample presence section
+    fn variant_positions_section(&self, flags: u32) -> Option<Vec<u8>> {
+        (flags & FLAG_VARIANT_POSITIONS != 0).then(|| position_sets_section(&self.positions))
+    }
+}
+
+fn file_header(flags: u32) -> [u8; HEADER_SIZE] {
+    let mut header = [0u8; HEADER_SIZE];
+    header[..8].copy_from_slice(MAGIC);
+    header[8..12].copy_from_slice(&FORMAT_VERSION.to_le_bytes());
+    header[12..].copy_from_slice(&flags.to_le_bytes());
+    header
+}
+
+// Entries an ExternalIdIndexBuilder buffers before spilling a sorted run (a few hundred MB
+// with dbSNP-style IDs)
+pub const DEFAULT_RUN_ENTRIES: usize = 4_000_000;
+
+// Builds the same file as IdIndexBuilder with bounded memory, for files with too many IDs to
+// sort in RAM: entries are spilled to temporary files in sorted runs, which are then merged
+// while the FST and postings are streamed to disk.
+pub struct ExternalIdIndexBuilder {
+    // Chromosome table, position ranges and source; its entries stay empty
+    layout: IdIndexBuilder,
+    buffer: Vec<(String, u32, u64)>,
+    max_buffered: usize,
+    spill_dir: PathBuf,
+    // Unique per builder so concurrent builds can share a temp directory
+    file_prefix: String,
+    runs: Vec<PathBuf>,
+    // Sample presence section streamed to a temp file, once enabled
+    sample_presence: Option<BufWriter<File>>,
+}
+
+impl ExternalIdIndexBuilder {
+    pub fn new(spill_dir: &Path, max_buffered: usize) -> Self {
+        let nanos = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_nanos())
+            .unwrap_or(0);
+        ExternalIdIndexBuilder {
+            layout: IdIndexBuilder::new(),
+            buffer: Vec::new(),
+            max_buffered: max_buffered.max(1),
+            spill_dir: spill_dir.to_path_buf(),
+            file_prefix: format!("vcf-idx-{}-{}", std::process::id(), nanos),
+            runs: Vec::new(),
+            sample_presence: None,
+        }
+    }
+
+    pub fn set_source(&mut self, source: SourceFingerprint) {
+        self.layout.set_source(source);
+    }
+
+    pub fn add_span(&mut self, chromosome: &str, start: u64, end: u64) {
+        self.layout.add_span(chromosome, start, end);
+    }
+
+    pub fn enable_sample_presence(&mut self, sample_count: usize) -> std::io::Result<()> {
+        let mut writer = BufWriter::new(File::create(self.temp_path("presence"))?);
+        writer.write_all(&(sample_count as u32).to_le_bytes())?;
+        self.sample_presence = Some(writer);
+        Ok(())
+    }
+
+    pub fn add_sample_presence(
+        &mut self,
+        chromosome: &str,
+        position: u64,
+        carriers: &[u8],
+    ) -> std::io::Result<()> {
+        let chromosome_id = self.layout.chromosome_id(chromosome);
+        if let Some(writer) = &mut self.sample_presence {
+            writer.write_all(&chromosome_id.to_le_bytes())?;
+            writer.write_all(&position.to_le_bytes())?;
+            writer.write_all(carriers)?;
+        }
+        Ok(())
+    }
+
+    pub fn add(&mut self, id: &str, chromosome: &str, position: u64) -> std::io::Result<()> {
+        self.layout.add_span(chromosome, position, position);
+        let chromosome_id = self.layout.chromosome_id(chromosome);
+        self.buffer.push((id.to_string(), chromosome_id, position));
+        if self.buffer.len() >= self.max_buffered {
+            self.spill()?;
+        }
+        Ok(())
+    }
+
+    fn temp_path(&self, name: &str) -> PathBuf {
+        self.spill_dir
+            .join(format!("{}-{}.tmp", self.file_prefix, name))
+    }
+
+    // Write the buffered entries to a new run file, sorted by ID. The sort is stable so
+    // locations of an ID keep the order they were added in, as with IdIndexBuilder.
+    fn spill(&mut self) -> std::io::Result<()> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        self.buffer.sort_by(|a, b| a.0.cmp(&b.0));
+
+        let path = self.temp_path(&format!("run{}", self.runs.len()));
+        // Registered before writing so a failed write is still cleaned up
+        self.runs.push(path.clone());
+        let mut writer = BufWriter::new(File::create(&path)?);
+        for (id, chromosome_id, position) in self.buffer.drain(..) {
+            writer.write_all(&(id.len() as u32).to_le_bytes())?;
+            writer.write_all(id.as_bytes())?;
+            writer.write_all(&chromosome_id.to_le_bytes())?;
+            writer.write_all(&position.to_le_bytes())?;
+        }
+        writer.flush()
+    }
+
+    // Merge the runs and write the index file to `path`; returns the number of distinct IDs
+    pub fn finish_to_file(self, path: &Path) -> std::io::Result<usize> {
+        self.finish_to_file_with_flags(path, DEFAULT_FLAGS)
+    }
+
+    fn finish_to_file_with_flags(mut self, path: &Path, flags: u32) -> std::io::Result<usize> {
+        self.spill()?;
+        let fst_path = self.temp_path("fst");
+        let postings_path = self.temp_path("postings");
+        let presence_path = self.temp_path("presence");
+        let flags = match self.sample_presence.take() {
+            Some(writer) => {
+                writer.into_inner().map_err(|e| e.into_error())?;
+                flags | FLAG_SAMPLE_PRESENCE
+            }
+            None => flags,
+        };
+
+        let id_count = self.merge_runs(&fst_path, &postings_path)?;
+
+        let bloom = if flags & FLAG_BLOOM != 0 {
+            // Re-read the IDs from the finished FST, now that their number is known
+            let file = File::open(&fst_path)?;
+            // Safety: the file is private to this builder and no longer written to
+            let mmap = unsafe { Mmap::map(&file)? };
+            let map = fst::Map::new(mmap).map_err(invalid_data)?;
+            let mut bloom = BloomBuilder::new(id_count);
+            let mut stream = map.stream();
+            while let Some((id, _)) = stream.next() {
+                bloom.insert(id);
+            }
+            bloom.section
+        } else {
+            Vec::new()
+        };
+
+        let mut out = BufWriter::new(File::create(path)?);
+        out.write_all(&file_header(flags))?;
+        write_section(&mut out, &self.layout.chromosome_table())?;
+        copy_section(&mut out, &fst_path)?;
+        copy_section(&mut out, &postings_path)?;
+        for section in self.layout.trailing_sections(flags, bloom) {
+            write_section(&mut out, &section)?;
+        }
+        if flags & FLAG_SAMPLE_PRESENCE != 0 {
+            copy_section(&mut out, &presence_path)?;
+        }
+        if let Some(section) = self.layout.variant_positions_section(flags) {
+            write_section(&mut out, &section)?;
+        }
+        out.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+        Ok(id_count)
+    }
+
+    // k-way merge of the sorted runs into an FST file and a postings file
+    fn merge_runs(&self, fst_path: &Path, postings_path: &Path) -> std::io::Result<usize> {
+        let mut readers = self
+            .runs
+            .iter()
+            .map(|run| Ok(BufReader::new(File::open(run)?)))
+            .collect::<std::io::Result<Vec<_>>>()?;
+
+        // Ordered by (ID, run number): equal IDs come out in the order they were added
+        let mut heap = BinaryHeap::new();
+        for (run, reader) in readers.iter_mut().enumerate() {
+            if let Some((id, chromosome_id, position)) = read_run_entry(reader)? {
+                heap.push(Reverse((id, run, chromosome_id, position)));
+            }
+        }
+
+        let mut map_builder =
+            fst::MapBuilder::new(BufWriter::new(File::create(fst_path)?)).map_err(invalid_data)?;
+        let mut postings = BufWriter::new(File::create(postings_path)?);
+        let mut postings_len = 0u64;
+        let mut id_count = 0;
+        let mut current: Option<(String, Vec<(u32, u64)>)> = None;
+
+        let mut flush = |id: &str, locations: &[(u32, u64)]| -> std::io::Result<()> {
+            map_builder.insert(id, postings_len).map_err(invalid_data)?;
+            postings.write_all(&(locations.len() as u32).to_le_bytes())?;
+            for (chromosome_id, position) in locations {
+                postings.write_all(&chromosome_id.to_le_bytes())?;
+                postings.write_all(&position.to_le_bytes())?;
+            }
+            postings_len += 4 + (locations.len() * POSTING_SIZE) as u64;
+            Ok(())
+        };
+
+        while let Some(Reverse((id, run, chromosome_id, position))) = heap.pop() {
+            match &mut current {
+                Some((current_id, locations)) if *current_id == id => {
+                    locations.push((chromosome_id, position));
+                }
+                _ => {
+                    if let Some((previous_id, locations)) = current.take() {
+                        flush(&previous_id, &locations)?;
+                    }
+                    id_count += 1;
+                    current = Some((id, vec![(chromosome_id, position)]));
+                }
+            }
+            if let Some((id, chromosome_id, position)) = read_run_entry(&mut readers[run])? {
+                heap.push(Reverse((id, run, chromosome_id, position)));
+            }
+        }
+        if let Some((id, locations)) = current.take() {
+            flush(&id, &locations)?;
+        }
+
+        map_builder
+            .into_inner()
+            .map_err(invalid_data)?
+            .into_inner()
+            .map_err(|e| e.into_error())?
+            .sync_all()?;
+        postings.flush()?;
+        Ok(id_count)
+    }
+}
+
+impl Drop for ExternalIdIndexBuilder {
+    fn drop(&mut self) {
+        let merged = [
+            self.temp_path("fst"),
+            self.temp_path("postings"),
+            self.temp_path("presence"),
+        ];
+        for path in self.runs.iter().chain(&merged) {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+// Next (ID, chromosome id, position) from a run file, or None at its end
+fn read_run_entry(reader: &mut impl Read) -> std::io::Result<Option<(String, u32, u64)>> {
+    let mut len = [0u8; 4];
+    match reader.read_exact(&mut len) {
+        Ok(()) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e),
+    }
+    let mut id = vec![0u8; u32::from_le_bytes(len) as usize];
+    reader.read_exact(&mut id)?;
+    let mut chromosome_id = [0u8; 4];
+    reader.read_exact(&mut chromosome_id)?;
+    let mut position = [0u8; 8];
+    reader.read_exact(&mut position)?;
+    Ok(Some((
+        String::from_utf8(id).map_err(invalid_data)?,
+        u32::from_le_bytes(chromosome_id),
+        u64::from_le_bytes(position),
+    )))
+}
+
+fn write_section(out: &mut impl Write, bytes: &[u8]) -> std::io::Result<()> {
+    out.write_all(&(bytes.len() as u64).to_le_bytes())?;
+    out.write_all(bytes)
+}
+
+// Append a file as a length-prefixed section
+fn copy_section(out: &mut impl Write, path: &Path) -> std::io::Result<()> {
+    let mut file = File::open(path)?;
+    out.write_all(&file.metadata()?.len().to_le_bytes())?;
+    std::io::copy(&mut file, out)?;
+    Ok(())
+}
+
+pub struct IdIndex {
+    chromosomes: Vec<String>,
+    map: fst::Map<Section>,
+    postings: Section,
+    // None when the file was written without the optional bloom filter section
+    bloom: Option<Section>,
+    // Chromosome -> (first start, last end) of its variants; None if the section is absent
+    position_ranges: Option<HashMap<String, (u64, u64)>>,
+    // Per-variant sample bitmaps; None when the file was written without them
+    sample_presence: Option<Section>,
+    // Variant start positions per chromosome; None when the section is absent
+    variant_positions: Option<Section>,
+    source: SourceFingerprint,
+    lookups: AtomicU64,
+    bloom_rejected: AtomicU64,
+    bloom_false_positives: AtomicU64,
+}
+
+impl IdIndex {
+    // Load an index image built in memory (used when indexes are not saved to disk)
+    pub fn from_image(image: Vec<u8>) -> std::io::Result<Self> {
+        Self::parse(IndexBytes::Owned(Arc::new(image)))
+    }
+
+    // Memory-map an index file from disk
+    pub fn open(path: &Path) -> std::io::Result<Self> {
+        let file = File::open(path)?;
+        // Safety: index files are only ever replaced via atomic rename, never modified in place
+        let mmap = unsafe { Mmap::map(&file)? };
+        Self::parse(IndexBytes::Mapped(Arc::new(mmap)))
+    }
+
+    fn parse(bytes: IndexBytes) -> std::io::Result<Self> {
+        let data = bytes.as_ref();
+        let (version, flags) = parse_header(data)?;
+        if version > FORMAT_VERSION {
+            return Err(unsupported(format!(
+                "ID index format version {} is newer than supported version {}",
+                version, FORMAT_VERSION
+            )));
+        }
+        if version < FORMAT_VERSION {
+            return Err(invalid_data(format!(
+                "outdated ID index format version {}",
+                version
+            )));
+        }
+        let unknown_required = flags & REQUIRED_FLAGS_MASK & !KNOWN_REQUIRED_FLAGS;
+        if unknown_required != 0 {
+            return Err(unsupported(format!(
+                "ID index requires unsupported features (flags {:#x})",
+                unknown_required
+            )));
+        }
+
+        // Split the file into its length-prefixed sections; optional sections this build
+        // doesn't know about follow the known ones and are ignored
+        let section_count = REQUIRED_SECTIONS
+            + usize::from(flags & FLAG_BLOOM != 0)
+            + usize::from(flags & FLAG_POSITION_RANGES != 0)
+            + usize::from(flags & FLAG_SAMPLE_PRESENCE != 0)
+            + usize::from(flags & FLAG_VARIANT_POSITIONS != 0);
+        let mut offset = HEADER_SIZE;
+        let mut sections = Vec::with_capacity(section_count);
+        for _ in 0..section_count {
+            let len_bytes: [u8; 8] = data
+                .get(offset..offset + 8)
+                .and_then(|b| b.try_into().ok())
+                .ok_or_else(|| invalid_data("truncated ID index file"))?;
+            let len = u64::from_le_bytes(len_bytes) as usize;
+            let start = offset + 8;
+            let end = start
+                .checked_add(len)
+                .filter(|&end| end <= data.len())
+                .ok_or_else(|| invalid_data("truncated ID index file"))?;
+            sections.push(Section {
+                bytes: bytes.clone(),
+                start,
+                end,
+            });
+            offset = end;
+        }
+        let mut sections = sections.into_iter();
+        let chromosome_section = sections.next().expect("required section");
+        let fst_section = sections.next().expect("required section");
+        let postings = sections.next().expect("required section");
+        let fingerprint_section = sections.next().expect("required section");
+        let mut optional_section = |flag: u32| {
+            if flags & flag != 0 {
+                sections.next()
+            } else {
+                None
+            }
+        };
+        let bloom = optional_section(FLAG_BLOOM);
+        let ranges_section = optional_section(FLAG_POSITION_RANGES);
+        let sample_presence = optional_section(FLAG_SAMPLE_PRESENCE);
+        let variant_positions = optional_section(FLAG_VARIANT_POSITIONS);
+
+        let source = SourceFingerprint::from_bytes(fingerprint_section.as_ref())
+            .ok_or_else(|| invalid_data("invalid source fingerprint in ID index file"))?;
+
+        if let Some(section) = &sample_presence {
+            let valid = presence_row_size(section.as_ref())
+                .is_some_and(|row_size| (section.as_ref().len() - 4) % row_size == 0);
+            if !valid {
+                return Err(invalid_data(
+                    "invalid sample presence section in ID index file",
+                ));
+            }
+        }
+
+        if let Some(bloom) = &bloom {
+            let bloom_bytes = bloom.as_ref();
+            if bloom_bytes.len() < 2 || bloom_bytes[0] == 0 {
+                return Err(invalid_data("invalid bloom filter in ID index file"));
+            }
+        }
+
+        let chromosome_table =
+            std::str::from_utf8(chromosome_section.as_ref()).map_err(invalid_data)?;
+        let chromosomes = if chromosome_table.is_empty() {
+            Vec::new()
+        } else {
+            chromosome_table.split('\n').map(str::to_string).collect()
+        };
+        let map = fst::Map::new(fst_section).map_err(invalid_data)?;
+
+        if let Some(section) = &variant_positions {
+            let section = section.as_ref();
+            let valid = le_u32(section, 0).is_some_and(|count| {
+                count as usize == chromosomes.len()
+                    && section.len() >= 4 + chromosomes.len() * POSITION_TABLE_ENTRY_SIZE
+            });
+            if !valid {
+                return Err(invalid_data("invalid variant positions in ID index file"));
+            }
+        }
+
+        let position_ranges = match ranges_section {
+            Some(section) => {
+                let ranges = section.as_ref();
+                if ranges.len() != chromosomes.len() * 16 {
+                    return Err(invalid_data("invalid position ranges in ID index file"));
+                }
+                let value = |i: usize| {
+                    u64::from_le_bytes(ranges[i * 8..i * 8 + 8].try_into().unwrap_or_default())
+                };
+                Some(
+                    chromosomes
+                        .iter()
+                        .enumerate()
+                        .map(|(i, chromosome)| {
+                            (chromosome.clone(), (value(2 * i), value(2 * i + 1)))
+                        })
+                        .collect(),
+                )
+            }
+            None => None,
+        };
+
+        Ok(IdIndex {
+            chromosomes,
+            map,
+            postings,
+            bloom,
+            position_ranges,
+            sample_presence,
+            variant_positions,
+            source,
+            lookups: AtomicU64::new(0),
+            bloom_rejected: AtomicU64::new(0),
+            bloom_false_positives: AtomicU64::new(0),
+        })
+    }
+
+    // Number of distinct IDs
+    pub fn id_count(&self) -> usize {
+        self.map.len()
+    }
+
+    // Whether the file carries per-chromosome position ranges (older files may not)
+    pub fn has_position_ranges(&self) -> bool {
+        self.position_ranges.is_some()
+    }
+
+    // First start and last end position of the variants on a chromosome
+    pub fn position_range(&self, chromosome: &str) -> Option<(u64, u64)> {
+        self.position_ranges.as_ref()?.get(chromosome).copied()
+    }
+
+    // Position ranges of every chromosome with variants, in file order
+    pub fn position_ranges(&self) -> Option<Vec<(String, u64, u64)>> {
+        let ranges = self.position_ranges.as_ref()?;
+        Some(
+            self.chromosomes
+                .iter()
+                .filter_map(|chromosome| {
+                    let (first, last) = ranges.get(chromosome)?;
+                    Some((chromosome.clone(), *first, *last))
+                })
+                .collect(),
+        )
+    }
+
+    // Whether the file carries the start positions of every chromosome's variants
+    pub fn has_variant_positions(&self) -> bool {
+        self.variant_positions.is_some()
+    }
+
+    // Position set of a chromosome (empty for one without variants); None when the file has
+    // no variant positions section
+    fn position_set(&self, chromosome: &str) -> Option<PositionSetView<'_>> {
+        let section = self.variant_positions.as_ref()?.as_ref();
+        let Some(chromosome_id) = self.chromosomes.iter().position(|c| c == chromosome) else {
+            return Some(PositionSetView {
+                section,
+                max_span: 0,
+                directory: 0,
+                containers: 0,
+            });
+        };
+        let entry = 4 + chromosome_id * POSITION_TABLE_ENTRY_SIZE;
+        Some(PositionSetView {
+            section,
+            max_span: le_u64(section, entry)?,
+            directory: le_u64(section, entry + 8)? as usize,
+            containers: le_u32(section, entry + 16)? as usize,
+        })
+    }
+
+    // Up to `limit` start positions of the chromosome's variants within start..=end, in
+    // order; None when the file has no variant positions section
+    pub fn variant_starts(
+        &self,
+        chromosome: &str,
+        start: u64,
+        end: u64,
+        limit: usize,
+    ) -> Option<Vec<u64>> {
+        Some(self.position_set(chromosome)?.starts(start, end, limit))
+    }
+
+    // Whether a variant on the chromosome may overlap start..=end: one starts in the range, or
+    // close enough before it to reach it. None when the file has no variant positions section.
+    pub fn may_overlap(&self, chromosome: &str, start: u64, end: u64) -> Option<bool> {
+        let set = self.position_set(chromosome)?;
+        Some(
+            !set.starts(start.saturating_sub(set.max_span), end, 1)
+                .is_empty(),
+        )
+    }
+
+    // Whether the file carries per-variant sample presence bitmaps
+    pub fn has_sample_presence(&self) -> bool {
+        self.sample_presence.is_some()
+    }
+
+    // Presence lookups for sample column `sample` on a chromosome; None when the file has no
+    // sample presence section or was built with fewer samples
+    pub fn sample_carriers(&self, chromosome: &str, sample: usize) -> Option<SampleCarriers<'_>> {
+        let section = self.sample_presence.as_ref()?.as_ref();
+        let sample_count = u32::from_le_bytes(section.get(..4)?.try_into().ok()?) as usize;
+        if sample >= sample_count {
+            return None;
+        }
+        let row_size = presence_row_size(section)?;
+        let carriers = SampleCarriers {
+            rows: &section[4..],
+            row_size,
+            sample,
+            start: 0,
+            end: 0,
+        };
+        let Some(chromosome_id) = self.chromosomes.iter().position(|c| c == chromosome) else {
+            // No variant on the chromosome, so no carriers either
+            return Some(carriers);
+        };
+        // Rows are in file order, and chromosome ids in order of first appearance, so a
+        // chromosome's rows are contiguous and sorted by position
+        let chromosome_id = chromosome_id as u32;
+        let row_count = carriers.rows.len() / row_size;
+        let start = partition(0, row_count, |row| {
+            carriers.chromosome_id(row) < chromosome_id
+        });
+        let end = partition(start, row_count, |row| {
+            carriers.chromosome_id(row) <= chromosome_id
+        });
+        Some(SampleCarriers {
+            start,
+            end,
+            ..carriers
+        })
+    }
+
+    // Fingerprint of the VCF this index was built from
+    pub fn source(&self) -> SourceFingerprint {
+        self.source
+    }
+
+    // Locations (chromosome, position) of an ID, empty if the ID is absent
+    pub fn get(&self, id: &str) -> Vec<(String, u64)> {
+        self.lookups.fetch_add(1, Ordering::Relaxed);
+        if !self.may_contain(id) {
+            self.bloom_rejected.fetch_add(1, Ordering::Relaxed);
+            return Vec::new();
+        }
+        match self.map.get(id) {
+            Some(offset) => self.read_postings(offset),
+            None => {
+                self.bloom_false_positives.fetch_add(1, Ordering::Relaxed);
+                Vec::new()
+            }
+        }
+    }
+
+    // False means the ID is definitely absent; true means it is probably present
+    fn may_contain(&self, id: &str) -> bool {
+        let Some(bloom) = &self.bloom else {
+            return true;
+        };
+        let bloom = bloom.as_ref();
+        let (hashes, bits) = (bloom[0], &bloom[1..]);
+        bloom_positions(id.as_bytes(), hashes, bits.len() as u64 * 8)
+            .all(|bit| bits[(bit / 8) as usize] & (1 << (bit % 8)) != 0)
+    }
+
+    pub fn bloom_stats(&self) -> BloomStats {
+        // Without a filter every absent ID gets through
+        let expected_fp_rate = self.bloom.as_ref().map_or(1.0, |bloom| {
+            let bloom = bloom.as_ref();
+            let (hashes, bits) = (bloom[0], &bloom[1..]);
+            let set_bits: u64 = bits.iter().map(|b| b.count_ones() as u64).sum();
+            let fill = set_bits as f64 / (bits.len() as f64 * 8.0);
+            fill.powi(hashes as i32)
+        });
+
+        BloomStats {
+            lookups: self.lookups.load(Ordering::Relaxed),
+            rejected: self.bloom_rejected.load(Ordering::Relaxed),
+            false_positives: self.bloom_false_positives.load(Ordering::Relaxed),
+            expected_fp_rate,
+        }
+    }
+
+    // Visit IDs starting with `prefix` in sorted order until `visit` returns false
+    pub fn for_each_with_prefix<F>(&self, prefix: &str, mut visit: F)
+    where
+        F: FnMut(&str, Vec<(String, u64)>) -> bool,
+    {
+        let mut stream = self
+            .map
+            .search(Str::new(prefix).starts_with())
+            .into_stream();
+        while let Some((key, offset)) = stream.next() {
+            let id = String::from_utf8_lossy(key);
+            if !visit(&id, self.read_postings(offset)) {
+                break;
+            }
+        }
+    }
+
+    // Up to `limit` IDs with their locations in sorted order, after skipping the first `skip`
+    pub fn entries(&self, skip: usize, limit: usize) -> Vec<(String, Vec<(String, u64)>)> {
+        let mut stream = self.map.stream();
+        let mut entries = Vec::new();
+        let mut skipped = 0;
+        while let Some((key, offset)) = stream.next() {
+            // Skipped IDs are only walked in the FST; their postings are never read
+            if skipped < skip {
+                skipped += 1;
+                continue;
+            }
+            if entries.len() == limit {
+                break;
+            }
+            let id = String::from_utf8_lossy(key).into_owned();
+            entries.push((id, self.read_postings(offset)));
+        }
+        entries
+    }
+
+    fn read_postings(&self, offset: u64) -> Vec<(String, u64)> {
+        let postings = self.postings.as_ref();
+        let offset = offset as usize;
+        let Some(count_bytes) = postings.get(offset..offset + 4) else {
+            return Vec::new();
+        };
+        let count = u32::from_le_bytes(count_bytes.try_into().unwrap_or_default()) as usize;
+
+        let entries_start = offset + 4;
+        let Some(entries) = postings.get(entries_start..entries_start + count * POSTING_SIZE)
+        else {
+            return Vec::new();
+        };
+        entries
+            .chunks_exact(POSTING_SIZE)
+            .filter_map(|entry| {
+                let chromosome_id = u32::from_le_bytes(entry[..4].try_into().ok()?) as usize;
+                let position = u64::from_le_bytes(entry[4..].try_into().ok()?);
+                let chromosome = self.chromosomes.get(chromosome_id)?.clone();
+                Some((chromosome, position))
+            })
+            .collect()
+    }
+}
+
+// First index in lo..hi for which `before` is false (it must hold for a prefix of the range)
+fn partition(mut lo: usize, mut hi: usize, before: impl Fn(usize) -> bool) -> usize {
+    while lo < hi {
+        let mid = lo + (hi - lo) / 2;
+        if before(mid) {
+            lo = mid + 1;
+        } else {
+            hi = mid;
+        }
+    }
+    lo
+}
+
+fn le_u32(bytes: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(
+        bytes.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+fn le_u64(bytes: &[u8], offset: usize) -> Option<u64> {
+    Some(u64::from_le_bytes(
+        bytes.get(offset..offset + 8)?.try_into().ok()?,
+    ))
+}
+
+// One chromosome's position set, read in place from the variant positions section
+struct PositionSetView<'a> {
+    section: &'a [u8],
+    max_span: u64,
+    directory: usize,
+    containers: usize,
+}
+
+impl PositionSetView<'_> {
+    // (key, cardinality, data offset) of a container
+    fn entry(&self, container: usize) -> Option<(u32, usize, usize)> {
+        let offset = self.directory + container * CONTAINER_ENTRY_SIZE;
+        Some((
+            le_u32(self.section, offset)?,
+            le_u32(self.section, offset + 4)? as usize,
+            le_u64(self.section, offset + 8)? as usize,
+        ))
+    }
+
+    // Up to `limit` positions in start..=end, in order
+    fn starts(&self, start: u64, end: u64, limit: usize) -> Vec<u64> {
+        let mut found = Vec::new();
+        if start > end || limit == 0 {
+            return found;
+        }
+        let (first_key, last_key) = ((start >> 16) as u32, (end >> 16) as u32);
+        let first = partition(0, self.containers, |container| {
+            self.entry(container)
+                .is_some_and(|(key, _, _)| key < first_key)
+        });
+        for container in first..self.containers {
+            let Some((key, cardinality, offset)) = self.entry(container) else {
+                break;
+            };
+            if key > last_key {
+                break;
+            }
+            let base = (key as u64) << 16;
+            let low_start = if key == first_key { start as u16 } else { 0 };
+            let low_end = if key == last_key {
+                end as u16
+            } else {
+                u16::MAX
+            };
+            if cardinality > ARRAY_CONTAINER_MAX {
+                let Some(bits) = self.section.get(offset..offset + BITMAP_CONTAINER_BYTES) else {
+                    break;
+                };
+                for low in low_start..=low_end {
+                    if bits[low as usize / 8] & (1 << (low % 8)) != 0 {
+                        found.push(base | low as u64);
+                        if found.len() == limit {
+                            return found;
+                        }
+                    }
+                }
+            } else {
+                let Some(values) = self.section.get(offset..offset + cardinality * 2) else {
+                    break;
+                };
+                let value = |i: usize| u16::from_le_bytes([values[2 * i], values[2 * i + 1]]);
+                let mut i = partition(0, cardinality, |i| value(i) < low_start);
+                while i < cardinality && value(i) <= low_end {
+                    found.push(base | value(i) as u64);
+                    if found.len() == limit {
+                        return found;
+                    }
+                    i += 1;
+                }
+            }
+        }
+        found
+    }
+}
+
+// Bytes per row of a sample presence section, from its sample count prefix
+fn presence_row_size(section: &[u8]) -> Option<usize> {
+    let sample_count = u32::from_le_bytes(section.get(..4)?.try_into().ok()?) as usize;
+    Some(POSTING_SIZE + sample_count.div_ceil(8))
+}
+
+// The sample presence rows of one chromosome, answering whether a sample carries an ALT
+// allele at a position without decoding the VCF record
+#[derive(Clone, Copy)]
+pub struct SampleCarriers<'a> {
+    rows: &'a [u8],
+    row_size: usize,
+    sample: usize,
+    // Row range of the chromosome
+    start: usize,
+    end: usize,
+}
+
+impl SampleCarriers<'_> {
+    fn row(&self, row: usize) -> &[u8] {
+        &self.rows[row * self.row_size..(row + 1) * self.row_size]
+    }
+
+    fn chromosome_id(&self, row: usize) -> u32 {
+        u32::from_le_bytes(self.row(row)[..4].try_into().unwrap_or_default())
+    }
+
+    fn position(&self, row: usize) -> u64 {
+        u64::from_le_bytes(self.row(row)[4..12].try_into().unwrap_or_default())
+    }
+
+    // Whether the sample has an ALT allele in any variant at `position`
+    pub fn carries(&self, position: u64) -> bool {
+        let first = partition(self.start, self.end, |row| self.position(row) < position);
+        let (byte, bit) = (POSTING_SIZE + self.sample / 8, self.sample % 8);
+        (first..self.end)
+            .take_while(|&row| self.position(row) == position)
+            .any(|row| self.row(row)[byte] & (1 << bit) != 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn build_test_image() -> Vec<u8> {
+        let mut builder = IdIndexBuilder::new();
+        builder.add("rs6054257", "20", 14370);
+        builder.add("rs6040355", "20", 1110696);
+        builder.add("rsDup", "20", 100);
+        builder.add("rsDup", "X", 200);
+        builder.add("microsat1", "20", 1234567);
+        builder.finish().expect("build index")
+    }
+
+    fn build_test_index() -> IdIndex {
+        IdIndex::from_image(build_test_image()).expect("parse index")
+    }
+
+    #[test]
+    fn test_lookup_round_trip() {
+        let index = build_test_index();
+        assert_eq!(index.id_count(), 4);
+        assert_eq!(index.get("rs6054257"), vec![("20".to_string(), 14370)]);
+        assert_eq!(
+            index.get("rsDup"),
+            vec![("20".to_string(), 100), ("X".to_string(), 200)]
+        );
+        assert!(index.get("rs0").is_empty());
+        assert!(index.get("").is_empty());
+    }
+
+    #[test]
+    fn test_prefix_iteration_is_sorted() {
+        let index = build_test_index();
+        let mut ids = Vec::new();
+        index.for_each_with_prefix("rs60", |id, _| {
+            ids.push(id.to_string());
+            true
+        });
+        assert_eq!(ids, vec!["rs6040355", "rs6054257"]);
+
+        let mut first = Vec::new();
+        index.for_each_with_prefix("", |id, _| {
+            first.push(id.to_string());
+            false
+        });
+        assert_eq!(first, vec!["microsat1"]);
+    }
+
+    #[test]
+    fn test_entries_are_paged_in_id_order() {
+        let index = build_test_index();
+        let ids = |skip, limit| -> Vec<String> {
+            index
+                .entries(skip, limit)
+                .into_iter()
+                .map(|(id, _)| id)
+                .collect()
+        };
+        assert_eq!(ids(0, 2), vec!["microsat1", "rs6040355"]);
+        assert_eq!(ids(2, 2), vec!["rs6054257", "rsDup"]);
+        assert!(ids(4, 2).is_empty());
+
+        let duplicate = index.entries(3, 1);
+        assert_eq!(
+            duplicate[0].1,
+            vec![("20".to_string(), 100), ("X".to_string(), 200)]
+        );
+    }
+
+    #[test]
+    fn test_rejects_foreign_file() {
+        assert!(IdIndex::from_image(b"not an index".to_vec()).is_err());
+        let mut truncated = IdIndexBuilder::new();
+        truncated.add("rs1", "1", 1);
+        let mut image = truncated.finish().expect("build index");
+        image.truncate(image.len() - 3);
+        assert!(IdIndex::from_image(image).is_err());
+    }
+
+    #[test]
+    fn test_bloom_filter_rejects_absent_ids() {
+        let index = build_test_index();
+        for id in ["rs6054257", "rs6040355", "rsDup", "microsat1"] {
+            assert!(index.may_contain(id), "bloom filter must not drop {}", id);
+        }
+
+        for i in 0..1000 {
+            assert!(index.get(&format!("rsAbsent{}", i)).is_empty());
+        }
+        assert!(!index.get("rsDup").is_empty());
+
+        let stats = index.bloom_stats();
+        assert_eq!(stats.lookups, 1001);
+        assert_eq!(stats.rejected + stats.false_positives, 1000);
+        assert!(stats.observed_fp_rate() < 0.05);
+        assert!(stats.expected_fp_rate < 0.05);
+    }
+
+    #[test]
+    fn test_source_fingerprint_round_trip() {
+        let source = SourceFingerprint::of_file(Path::new("Cargo.toml")).expect("fingerprint");
+        assert!(source.size > 0);
+        assert_eq!(
+            source,
+            SourceFingerprint::of_file(Path::new("Cargo.toml")).expect("fingerprint")
+        );
+
+        let mut builder = IdIndexBuilder::new();
+        builder.add("rs1", "1", 1);
+        builder.set_source(source);
+        let index = IdIndex::from_image(builder.finish().expect("build index")).expect("parse");
+        assert_eq!(index.source(), source);
+        assert_ne!(build_test_index().source(), source);
+    }
+
+    fn set_header(image: &mut [u8], version: u32, flags: u32) {
+        image[8..12].copy_from_slice(&version.to_le_bytes());
+        image[12..16].copy_from_slice(&flags.to_le_bytes());
+    }
+
+    fn error_kind(image: Vec<u8>) -> std::io::ErrorKind {
+        match IdIndex::from_image(image) {
+            Ok(_) => panic!("expected the index to be rejected"),
+            Err(e) => e.kind(),
+        }
+    }
+
+    #[test]
+    fn test_position_ranges_cover_variants_without_ids() {
+        let mut builder = IdIndexBuilder::new();
+        builder.add_span("20", 17330, 17330);
+        builder.add("rs6054257", "20", 14370);
+        builder.add_span("20", 1234567, 1234572);
+        builder.add_span("X", 10, 10);
+        let index = IdIndex::from_image(builder.finish().expect("build index")).expect("parse");
+
+        assert_eq!(index.position_range("20"), Some((14370, 1234572)));
+        assert_eq!(index.position_range("X"), Some((10, 10)));
+        assert_eq!(index.position_range("Y"), None);
+        assert_eq!(
+            index.position_ranges(),
+            Some(vec![
+                ("20".to_string(), 14370, 1234572),
+                ("X".to_string(), 10, 10)
+            ])
+        );
+    }
+
+    #[test]
+    fn test_version_1_round_trip_with_optional_sections() {
+        for flags in [DEFAULT_FLAGS, FLAG_BLOOM, FLAG_POSITION_RANGES, 0] {
+            let mut builder = IdIndexBuilder::new();
+            builder.add("rs6054257", "20", 14370);
+            builder.add("rsTest", "X", 10);
+            let image = builder.finish_with_flags(flags).expect("build index");
+            assert_eq!(&image[..8], MAGIC);
+            assert_eq!(
+                parse_header(&image).expect("header"),
+                (FORMAT_VERSION, flags)
+            );
+
+            let index = IdIndex::from_image(image).expect("parse index");
+            assert_eq!(index.bloom.is_some(), flags & FLAG_BLOOM != 0);
+            assert_eq!(
+                index.has_position_ranges(),
+                flags & FLAG_POSITION_RANGES != 0
+            );
+            assert_eq!(index.get("rsTest"), vec![("X".to_string(), 10)]);
+            assert!(index.get("rs0").is_empty());
+        }
+    }
+
+    #[test]
+    fn test_rejects_outdated_and_legacy_formats() {
+        let image = build_test_image();
+
+        let mut outdated = image.clone();
+        set_header(&mut outdated, 0, FLAG_BLOOM);
+        assert_eq!(error_kind(outdated), std::io::ErrorKind::InvalidData);
+
+        let mut legacy = image.clone();
+        legacy[..8].copy_from_slice(LEGACY_MAGIC);
+        assert_eq!(error_kind(legacy), std::io::ErrorKind::InvalidData);
+
+        // A bincode HashMap starts with its u64 entry count
+        let mut bincode = 3u64.to_le_bytes().to_vec();
+        bincode.extend_from_slice(b"\x09\0\0\0\0\0\0\0rs6054257");
+        assert_eq!(error_kind(bincode), std::io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_newer_versions_and_required_features_are_unsupported() {
+        let mut newer = build_test_image();
+        set_header(&mut newer, FORMAT_VERSION + 1, FLAG_BLOOM);
+        assert_eq!(error_kind(newer), std::io::ErrorKind::Unsupported);
+
+        let mut required = build_test_image();
+        set_header(&mut required, FORMAT_VERSION, DEFAULT_FLAGS | 1);
+        assert_eq!(error_kind(required), std::io::ErrorKind::Unsupported);
+    }
+
+    #[test]
+    fn test_unknown_optional_sections_are_ignored() {
+        let mut image = build_test_image();
+        set_header(&mut image, FORMAT_VERSION, DEFAULT_FLAGS | (1 << 20));
+        image.extend_from_slice(&4u64.to_le_bytes());
+        image.extend_from_slice(b"next");
+
+        let index = IdIndex::from_image(image).expect("parse index");
+        assert_eq!(index.get("microsat1"), vec![("20".to_string(), 1234567)]);
+    }
+
+    #[test]
+    fn test_empty_index() {
+        let index = IdIndex::from_image(IdIndexBuilder::new().finish().expect("build index"))
+            .expect("parse index");
+        assert_eq!(index.id_count(), 0);
+        assert!(index.get("rs1").is_empty());
+    }
+    #[test]
+    fn test_external_builder_matches_in_memory_image() {
+        let entries = [
+            ("rs6054257", "20", 14370),
+            ("rsDup", "20", 100),
+            ("rs6040355", "20", 1110696),
+            ("microsat1", "20", 1234567),
+            ("rsDup", "X", 200),
+            ("rsB", "X", 300),
+            ("rsDup", "20", 150),
+        ];
+        let source = SourceFingerprint {
+            size: 948,
+            modified_nanos: 1,
+            checksum: 2,
+        };
+        let dir = tempfile::TempDir::new().expect("temp dir");
+
+        for flags in [0, DEFAULT_FLAGS] {
+            let mut builder = IdIndexBuilder::new();
+            // Two entries per run, so rsDup is spread over three runs
+            let mut external = ExternalIdIndexBuilder::new(dir.path(), 2);
+            builder.set_source(source);
+            external.set_source(source);
+            builder.add_span("20", 1, 5);
+            external.add_span("20", 1, 5);
+            for (id, chromosome, position) in entries {
+                builder.add(id, chromosome, position);
+                external.add(id, chromosome, position).expect("add entry");
+            }
+
+            let path = dir.path().join("external.idx");
+            let id_count = external
+                .finish_to_file_with_flags(&path, flags)
+                .expect("build external index");
+            assert_eq!(id_count, builder.id_count());
+            assert_eq!(
+                std::fs::read(&path).expect("read index"),
+                builder.finish_with_flags(flags).expect("build index")
+            );
+
+            // Only the index itself is left behind
+            let leftover: Vec<_> = std::fs::read_dir(dir.path())
+                .expect("list temp dir")
+                .map(|entry| entry.expect("dir entry").file_name())
+                .collect();
+            assert_eq!(leftover, vec![std::ffi::OsString::from("external.idx")]);
+        }
+    }
+
+    #[test]
+    fn test_sample_presence_lookups() {
+        // Nine samples, so bitmaps take two bytes; sample 8 only carries the X variant
+        let rows: [(&str, u64, [u8; 2]); 4] = [
+            ("20", 14370, [0b0000_0110, 0]),
+            ("20", 17330, [0b0000_0010, 0]),
+            ("20", 17330, [0b0000_1000, 0]),
+            ("X", 10, [0, 0b0000_0001]),
+        ];
+        let dir = tempfile::TempDir::new().expect("temp dir");
+        let mut builder = IdIndexBuilder::new();
+        let mut external = ExternalIdIndexBuilder::new(dir.path(), 2);
+        builder.enable_sample_presence(9);
+        external.enable_sample_presence(9).expect("enable presence");
+        for (chromosome, position, carriers) in rows {
+            builder.add_span(chromosome, position, position);
+            external.add_span(chromosome, position, position);
+            builder.add_sample_presence(chromosome, position, &carriers);
+            external
+                .add_sample_presence(chromosome, position, &carriers)
+                .expect("add presence");
+        }
+        let path = dir.path().join("external.idx");
+        external
+            .finish_to_file(&path)
+            .expect("build external index");
+        let image = builder.finish().expect("build index");
+        assert_eq!(std::fs::read(&path).expect("read index"), image);
+
+        let index = IdIndex::from_image(image).expect("parse index");
+        assert!(index.has_sample_presence());
+        let on_20 = |sample| index.sample_carriers("20", sample).expect("carriers");
+        assert!(on_20(1).carries(14370));
+        assert!(on_20(1).carries(17330));
+        assert!(!on_20(0).carries(14370));
+        // Either record at a shared position counts
+        assert!(on_20(3).carries(17330));
+        assert!(!on_20(2).carries(17330));
+        assert!(!on_20(1).carries(20000));
+        assert!(!on_20(8).carries(14370));
+        assert!(index.sample_carriers("X", 8).expect("carriers").carries(10));
+        assert!(!index.sample_carriers("Y", 1).expect("carriers").carries(10));
+        assert!(index.sample_carriers("20", 9).is_none());
+
+        assert!(!build_test_index().has_sample_presence());
+        assert!(build_test_index().sample_carriers("20", 0).is_none());
+    }
+
+    #[test]
+    fn test_variant_positions() {
+        let mut builder = IdIndexBuilder::new();
+        builder.add("rs6054257", "20", 14370);
+        builder.add_span("20", 17330, 17330);
+        // A deletion reaching 1230237 + 10
+        builder.add_span("20", 1230237, 1230247);
+        // Dense enough for a bitmap container: every other position of 0x20000..0x22800
+        for position in (0x2_0000..0x2_2800).step_by(2) {
+            builder.add_span("X", position, position);
+        }
+        builder.add_span("X", 0x2_ffff, 0x2_ffff);
+        let index = IdIndex::from_image(builder.finish().expect("build index")).expect("parse");
+        assert!(index.has_variant_positions());
+
+        let starts = |chromosome, start, end| index.variant_starts(chromosome, start, end, 10);
+        assert_eq!(starts("20", 1, 20000), Some(vec![14370, 17330]));
+        assert_eq!(starts("20", 14371, 17329), Some(vec![]));
+        assert_eq!(starts("20", 1230237, 1230237), Some(vec![1230237]));
+        assert_eq!(
+            starts("X", 0x2_0001, 0x2_0008),
+            Some(vec![0x2_0002, 0x2_0004, 0x2_0006, 0x2_0008])
+        );
+        assert_eq!(starts("X", 0x2_2800, 0x3_0000), Some(vec![0x2_ffff]));
+        assert_eq!(
+            index.variant_starts("X", 0, u64::MAX, 3).map(|s| s.len()),
+            Some(3)
+        );
+        assert_eq!(starts("Y", 1, 100), Some(vec![]));
+
+        // Positions inside the deletion are overlapped though no variant starts there
+        assert_eq!(index.may_overlap("20", 1230240, 1230240), Some(true));
+        assert_eq!(index.may_overlap("20", 1230260, 1230300), Some(false));
+        assert_eq!(index.may_overlap("20", 14370, 14370), Some(true));
+        assert_eq!(index.may_overlap("Y", 1, 100), Some(false));
+
+        let mut without = IdIndexBuilder::new();
+        without.add("rs1", "1", 1);
+        let index = IdIndex::from_image(without.finish_with_flags(FLAG_BLOOM).expect("build"))
+            .expect("parse");
+        assert!(index.variant_starts("1", 1, 1, 1).is_none());
+        assert!(index.may_overlap("1", 1, 1).is_none());
+    }
+}
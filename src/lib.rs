@@ -1 +1,5 @@
+pub mod block_cache;
+pub mod id_index;
+pub mod reference;
 pub mod vcf;
+pub mod vrs;
@@ -0,0 +1,323 @@
+// Liftover between genome builds with a UCSC chain file (--chain), for liftover_position and
+// the query tools' liftover option.
+//
+// A chain file lists alignments ("chains") from a source build (the chain's target, t) to a
+// destination build (its query, q). Each starts with a header line
+//   chain score tName tSize tStrand tStart tEnd qName qSize qStrand qStart qEnd id
+// followed by "size dt dq" lines: an ungapped block of `size` bases, then `dt` source and `dq`
+// destination bases skipped before the next block. The last line holds only a size.
+// Coordinates are 0-based; on a '-' destination strand they count from the end of the
+// destination chromosome. A position maps through the block containing it, so positions in
+// gaps have no counterpart. Several chains can cover one position (duplications); every
+// mapping is returned, highest-scoring chain first.
+//
+// Chromosomes are matched without a "chr" prefix (and chrM as MT), so a UCSC chain file
+// works with Ensembl-style VCF names.
+
+use flate2::read::MultiGzDecoder;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read};
+use std::path::Path;
+
+// An ungapped block of one chain
+#[derive(Debug, Clone, Copy)]
+struct Block {
+    source_start: u64,
+    destination_start: u64,
+    length: u64,
+    chain: usize,
+}
+
+#[derive(Debug, Clone)]
+struct Chain {
+    id: String,
+    score: u64,
+    destination: String,
+    destination_size: u64,
+    reverse: bool,
+}
+
+pub struct ChainFile {
+    // File name, echoed in responses
+    name: String,
+    chains: Vec<Chain>,
+    // Blocks by source chromosome key (contig_key), sorted by start
+    blocks: HashMap<String, Vec<Block>>,
+    // Longest block per source chromosome key, bounding the search for overlapping blocks
+    longest: HashMap<String, u64>,
+}
+
+// A position on the destination build
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct LiftedPosition {
+    pub chromosome: String,
+    // 1-based
+    pub position: u64,
+    // "-" when the chain maps the source to the reverse strand
+    pub strand: &'static str,
+    pub chain_id: String,
+    pub chain_score: u64,
+}
+
+// A 1-based inclusive region on the destination build
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct LiftedRegion {
+    pub chromosome: String,
+    pub start: u64,
+    pub end: u64,
+    pub strand: &'static str,
+    pub chain_id: String,
+}
+
+fn invalid_data(message: String) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, message)
+}
+
+// Chromosome name as matched against the chain file
+fn contig_key(name: &str) -> String {
+    let name = name
+        .strip_prefix("chr")
+        .or_else(|| name.strip_prefix("Chr"))
+        .unwrap_or(name);
+    if name.eq_ignore_ascii_case("M") {
+        "MT".to_string()
+    } else {
+        name.to_string()
+    }
+}
+
+impl ChainFile {
+    // Read a chain file, gzip-compressed or not
+    pub fn open(path: &Path) -> std::io::Result<Self> {
+        let mut file = File::open(path)?;
+        let mut magic = [0u8; 2];
+        let gzipped = file.read(&mut magic)? == 2 && magic == [0x1f, 0x8b];
+        let file = File::open(path)?;
+        let reader: Box<dyn BufRead> = if gzipped {
+            Box::new(BufReader::new(MultiGzDecoder::new(file)))
+        } else {
+            Box::new(BufReader::new(file))
+        };
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        Self::parse(name, reader)
+    }
+
+    pub fn parse(name: String, reader: impl BufRead) -> std::io::Result<Self> {
+        let mut chain_file = ChainFile {
+            name,
+            chains: Vec::new(),
+            blocks: HashMap::new(),
+            longest: HashMap::new(),
+        };
+        // Source key and the current source and destination offsets of the open chain
+        let mut open: Option<(String, u64, u64)> = None;
+        for (number, line) in reader.lines().enumerate() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let malformed = || {
+                invalid_data(format!(
+                    "Chain file line {}: malformed: {}",
+                    number + 1,
+                    line
+                ))
+            };
+            let number_at = |index: usize| -> std::io::Result<u64> {
+                fields
+                    .get(index)
+                    .and_then(|field| field.parse().ok())
+                    .ok_or_else(malformed)
+            };
+
+            if fields[0] == "chain" {
+                if fields.len() < 12 {
+                    return Err(malformed());
+                }
+                chain_file.chains.push(Chain {
+                    id: fields.get(12).unwrap_or(&"").to_string(),
+                    score: fields[1].parse::<f64>().map_err(|_| malformed())? as u64,
+                    destination: fields[7].to_string(),
+                    destination_size: number_at(8)?,
+                    reverse: fields[9] == "-",
+                });
+                open = Some((contig_key(fields[2]), number_at(5)?, number_at(10)?));
+                continue;
+            }
+
+            let Some((source, source_offset, destination_offset)) = open.as_mut() else {
+                return Err(malformed());
+            };
+            let length = number_at(0)?;
+            let block = Block {
+                source_start: *source_offset,
+                destination_start: *destination_offset,
+                length,
+                chain: chain_file.chains.len() - 1,
+            };
+            let longest = chain_file.longest.entry(source.clone()).or_insert(0);
+            *longest = (*longest).max(length);
+            chain_file
+                .blocks
+                .entry(source.clone())
+                .or_default()
+                .push(block);
+            match fields.len() {
+                1 => open = None,
+                3 => {
+                    *source_offset += length + number_at(1)?;
+                    *destination_offset += length + number_at(2)?;
+                }
+                _ => return Err(malformed()),
+            }
+        }
+        if chain_file.chains.is_empty() {
+            return Err(invalid_data(format!(
+                "{} has no chains; expected a UCSC chain file",
+                chain_file.name
+            )));
+        }
+        for blocks in chain_file.blocks.values_mut() {
+            blocks.sort_by_key(|block| block.source_start);
+        }
+        Ok(chain_file)
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    // Every mapping of 1-based `position` on `chromosome`, highest-scoring chain first
+    pub fn lift(&self, chromosome: &str, position: u64) -> Vec<LiftedPosition> {
+        let key = contig_key(chromosome);
+        let (Some(blocks), Some(&longest)) = (self.blocks.get(&key), self.longest.get(&key)) else {
+            return Vec::new();
+        };
+        let Some(offset) = position.checked_sub(1) else {
+            return Vec::new();
+        };
+        // Blocks starting at or before the position, back to the longest block's length
+        let end = blocks.partition_point(|block| block.source_start <= offset);
+        let mut lifted: Vec<LiftedPosition> = blocks[..end]
+            .iter()
+            .rev()
+            .take_while(|block| block.source_start + longest > offset)
+            .filter(|block| offset < block.source_start + block.length)
+            .map(|block| {
+                let chain = &self.chains[block.chain];
+                let mut destination = block.destination_start + (offset - block.source_start);
+                if chain.reverse {
+                    destination = chain.destination_size - 1 - destination;
+                }
+                LiftedPosition {
+                    chromosome: chain.destination.clone(),
+                    position: destination + 1,
+                    strand: if chain.reverse { "-" } else { "+" },
+                    chain_id: chain.id.clone(),
+                    chain_score: chain.score,
+                }
+            })
+            .collect();
+        lifted.sort_by(|a, b| b.chain_score.cmp(&a.chain_score));
+        lifted
+    }
+
+    // The 1-based inclusive region `start`-`end` on the destination build: both ends must map
+    // through the same chain, the best-scoring one they share. Gaps inside the region are
+    // covered by the lifted region.
+    pub fn lift_region(
+        &self,
+        chromosome: &str,
+        start: u64,
+        end: u64,
+    ) -> Result<LiftedRegion, String> {
+        let from_start = self.lift(chromosome, start);
+        let from_end = self.lift(chromosome, end);
+        let unmapped = |position: u64| {
+            format!(
+                "{}:{} has no counterpart in {} (a gap or a sequence missing from the other build)",
+                chromosome, position, self.name
+            )
+        };
+        if from_start.is_empty() {
+            return Err(unmapped(start));
+        }
+        if from_end.is_empty() {
+            return Err(unmapped(end));
+        }
+        let (first, last) = from_start
+            .iter()
+            .find_map(|first| {
+                from_end
+                    .iter()
+                    .find(|last| last.chain_id == first.chain_id && last.chromosome == first.chromosome)
+                    .map(|last| (first, last))
+            })
+            .ok_or_else(|| {
+                format!(
+                    "{}:{}-{} spans a break between chains in {}; lift its ends separately with liftover_position",
+                    chromosome, start, end, self.name
+                )
+            })?;
+        Ok(LiftedRegion {
+            chromosome: first.chromosome.clone(),
+            start: first.position.min(last.position),
+            end: first.position.max(last.position),
+            strand: first.strand,
+            chain_id: first.chain_id.clone(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // chr1:100-199 maps to chr1:1000-1099 with a 10 bp source gap after the first 50 bases,
+    // and chr2:0-99 maps to the reverse strand of the 1000 bp chr2
+    const CHAIN: &str = "chain 5000 chr1 1000 + 100 210 chr1 5000 + 1000 1100 1\n\
+                         50\t10\t0\n\
+                         50\n\
+                         \n\
+                         chain 300 chr2 500 + 0 100 chr2 1000 - 0 100 2\n\
+                         100\n";
+
+    #[test]
+    fn test_lift() {
+        let chains = ChainFile::parse("test.chain".to_string(), CHAIN.as_bytes()).unwrap();
+        // 1-based 101 is the first base of the first block
+        let lifted = chains.lift("1", 101);
+        assert_eq!(lifted.len(), 1);
+        assert_eq!(lifted[0].chromosome, "chr1");
+        assert_eq!(lifted[0].position, 1001);
+        assert_eq!(lifted[0].strand, "+");
+        assert_eq!(lifted[0].chain_id, "1");
+        // After the gap, source 161 (0-based 160) is the second block's first base
+        assert_eq!(chains.lift("chr1", 161)[0].position, 1051);
+        // In the gap
+        assert!(chains.lift("chr1", 155).is_empty());
+        assert!(chains.lift("chr3", 101).is_empty());
+        // Reverse strand: 0-based 0 maps to 1000 - 1 - 0
+        let reverse = chains.lift("chr2", 1);
+        assert_eq!(reverse[0].position, 1000);
+        assert_eq!(reverse[0].strand, "-");
+    }
+
+    #[test]
+    fn test_lift_region() {
+        let chains = ChainFile::parse("test.chain".to_string(), CHAIN.as_bytes()).unwrap();
+        let region = chains.lift_region("chr1", 101, 200).unwrap();
+        assert_eq!((region.start, region.end), (1001, 1090));
+        let reverse = chains.lift_region("2", 1, 10).unwrap();
+        assert_eq!((reverse.start, reverse.end), (991, 1000));
+        assert!(chains.lift_region("chr1", 101, 155).is_err());
+        assert!(ChainFile::parse("empty".to_string(), "".as_bytes()).is_err());
+        assert!(ChainFile::parse("bad".to_string(), "10 0 0\n".as_bytes()).is_err());
+    }
+}
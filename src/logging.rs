@@ -0,0 +1,114 @@
+// Server logs through tracing: startup and index building messages, warnings, and with --debug
+// a span per tool call (its parameters, duration and record counts). They go to stderr, or to
+// --log-file, as text or, with --log-format json, one JSON object per line carrying the
+// enclosing spans' fields. RUST_LOG overrides the levels (e.g. RUST_LOG=vcf_mcp_server=trace).
+//
+// stdout is never written: the stdio transport speaks MCP on it. Entries for MCP clients
+// (notifications/message) are client_log's.
+
+use std::fs::OpenOptions;
+use std::io::IsTerminal;
+use std::path::Path;
+use std::sync::Mutex;
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
+use tracing_subscriber::EnvFilter;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum LogFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+impl LogFormat {
+    // The --log-format value
+    pub fn name(self) -> &'static str {
+        match self {
+            LogFormat::Text => "text",
+            LogFormat::Json => "json",
+        }
+    }
+}
+
+// This server's messages at info (debug with --debug); dependencies only warn
+fn default_filter(debug: bool) -> EnvFilter {
+    EnvFilter::try_from_default_env().unwrap_or_else(|_| {
+        EnvFilter::new(if debug {
+            "warn,vcf_mcp_server=debug"
+        } else {
+            "warn,vcf_mcp_server=info"
+        })
+    })
+}
+
+fn subscriber(
+    format: LogFormat,
+    writer: BoxMakeWriter,
+    filter: EnvFilter,
+    ansi: bool,
+) -> Box<dyn tracing::Subscriber + Send + Sync> {
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(writer);
+    match format {
+        LogFormat::Text => Box::new(builder.with_target(false).with_ansi(ansi).finish()),
+        LogFormat::Json => Box::new(
+            builder
+                .json()
+                .with_current_span(true)
+                .with_span_list(false)
+                .finish(),
+        ),
+    }
+}
+
+// Install the process-wide logger; `file` is appended to
+pub fn init(format: LogFormat, file: Option<&Path>, debug: bool) -> std::io::Result<()> {
+    let (writer, ansi) = match file {
+        Some(path) => {
+            let file = OpenOptions::new().create(true).append(true).open(path)?;
+            (BoxMakeWriter::new(Mutex::new(file)), false)
+        }
+        None => (
+            BoxMakeWriter::new(std::io::stderr),
+            std::io::stderr().is_terminal(),
+        ),
+    };
+    tracing::subscriber::set_global_default(subscriber(format, writer, default_filter(debug), ansi))
+        .map_err(std::io::Error::other)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_json_lines_carry_span_fields() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("server.log");
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .unwrap();
+        let subscriber = subscriber(
+            LogFormat::Json,
+            BoxMakeWriter::new(Mutex::new(file)),
+            EnvFilter::new("debug"),
+            false,
+        );
+        tracing::subscriber::with_default(subscriber, || {
+            let span = tracing::debug_span!("tool_call", tool = "query_by_id");
+            let _entered = span.enter();
+            tracing::debug!(records_scanned = 12u64, "tool call finished");
+        });
+
+        let text = std::fs::read_to_string(&path).unwrap();
+        let line: serde_json::Value = serde_json::from_str(text.lines().next().unwrap()).unwrap();
+        assert_eq!(line["level"], "DEBUG");
+        assert_eq!(line["fields"]["message"], "tool call finished");
+        assert_eq!(line["fields"]["records_scanned"], 12);
+        assert_eq!(line["span"]["name"], "tool_call");
+        assert_eq!(line["span"]["tool"], "query_by_id");
+    }
+}
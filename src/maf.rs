@@ -0,0 +1,444 @@
+// MAF-style rows for export_maf: one row per ALT allele and sample carrying it, with the
+// gene, consequence class and protein change read from the file's SnpEff ANN or VEP CSQ
+// annotations, for cancer genomics tools such as maftools.
+//
+// An annotation field qualifies when its ##INFO description lists an Allele subfield and an
+// Annotation (SnpEff) or Consequence (VEP) subfield; Gene_Name/SYMBOL and HGVS.p/HGVSp are
+// used when present. Of the entries for an allele (one per transcript), the one with the most
+// severe Sequence Ontology term is kept, and its term is mapped to a MAF
+// Variant_Classification as vcf2maf does.
+//
+// MAF alleles drop the bases shared with REF at the start, with "-" for an empty allele, and
+// Start_Position/End_Position span the REF bases left (for an insertion, the two bases around
+// it). Spanning deletions ('*') and symbolic ALTs have no MAF form and are skipped.
+
+use crate::vcf::HeaderDefinition;
+
+// Sequence Ontology terms from most to least severe (Ensembl's ordering, with SnpEff's own
+// terms next to their VEP counterparts)
+const SEVERITY: &[&str] = &[
+    "transcript_ablation",
+    "exon_loss_variant",
+    "splice_acceptor_variant",
+    "splice_donor_variant",
+    "stop_gained",
+    "frameshift_variant",
+    "stop_lost",
+    "start_lost",
+    "initiator_codon_variant",
+    "transcript_amplification",
+    "inframe_insertion",
+    "disruptive_inframe_insertion",
+    "conservative_inframe_insertion",
+    "inframe_deletion",
+    "disruptive_inframe_deletion",
+    "conservative_inframe_deletion",
+    "missense_variant",
+    "protein_altering_variant",
+    "splice_region_variant",
+    "incomplete_terminal_codon_variant",
+    "start_retained_variant",
+    "stop_retained_variant",
+    "synonymous_variant",
+    "coding_sequence_variant",
+    "mature_miRNA_variant",
+    "5_prime_UTR_premature_start_codon_gain_variant",
+    "5_prime_UTR_variant",
+    "3_prime_UTR_variant",
+    "non_coding_transcript_exon_variant",
+    "non_coding_exon_variant",
+    "intron_variant",
+    "NMD_transcript_variant",
+    "non_coding_transcript_variant",
+    "upstream_gene_variant",
+    "downstream_gene_variant",
+    "TFBS_ablation",
+    "TFBS_amplification",
+    "TF_binding_site_variant",
+    "regulatory_region_ablation",
+    "regulatory_region_amplification",
+    "feature_elongation",
+    "regulatory_region_variant",
+    "feature_truncation",
+    "intergenic_variant",
+    "intergenic_region",
+];
+
+// Subfield slots of one annotation INFO field
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct AnnotationLayout {
+    field: String,
+    allele: usize,
+    consequence: usize,
+    gene: Option<usize>,
+    protein: Option<usize>,
+}
+
+// The annotation fields of a file that carry consequences
+#[derive(Debug, Clone, Default)]
+pub struct ConsequenceAnnotations {
+    layouts: Vec<AnnotationLayout>,
+}
+
+// The most severe annotation of one ALT allele
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AlleleConsequence {
+    pub gene: Option<String>,
+    // Sequence Ontology term, e.g. "missense_variant"
+    pub term: String,
+    // As annotated, e.g. "p.Gly12Asp"
+    pub protein_change: Option<String>,
+}
+
+// An ALT allele in MAF form
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MafAllele {
+    pub start: u64,
+    pub end: u64,
+    pub reference: String,
+    pub alternate: String,
+    // SNP, DNP, TNP, ONP, INS or DEL
+    pub variant_type: &'static str,
+}
+
+impl ConsequenceAnnotations {
+    pub fn from_header(definitions: &[HeaderDefinition]) -> Self {
+        let layouts = definitions
+            .iter()
+            .filter_map(|definition| {
+                let subfields = definition.subfields.as_ref()?;
+                let slot = |names: &[&str]| {
+                    names.iter().find_map(|name| {
+                        subfields
+                            .iter()
+                            .position(|subfield| subfield.eq_ignore_ascii_case(name))
+                    })
+                };
+                Some(AnnotationLayout {
+                    field: definition.id.clone(),
+                    allele: slot(&["Allele"])?,
+                    consequence: slot(&["Annotation", "Consequence"])?,
+                    gene: slot(&["Gene_Name", "SYMBOL", "Gene"]),
+                    protein: slot(&["HGVS.p", "HGVSp"]),
+                })
+            })
+            .collect();
+        ConsequenceAnnotations { layouts }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.layouts.is_empty()
+    }
+
+    // The most severe annotation of ALT `alternate` in a record's INFO column. Entries are
+    // matched by their Allele, written in full (SnpEff) or trimmed like `maf_alternate`
+    // (VEP); a record with a single ALT takes every entry.
+    pub fn consequence(
+        &self,
+        info: &str,
+        alternate: &str,
+        maf_alternate: &str,
+        single_alternate: bool,
+    ) -> Option<AlleleConsequence> {
+        let mut best: Option<(usize, AlleleConsequence)> = None;
+        for field in info.split(';') {
+            let Some((key, value)) = field.split_once('=') else {
+                continue;
+            };
+            let Some(layout) = self.layouts.iter().find(|layout| layout.field == key) else {
+                continue;
+            };
+            for entry in value.split(',') {
+                let subfields: Vec<&str> = entry.split('|').collect();
+                let allele = subfields.get(layout.allele).copied().unwrap_or_default();
+                if !single_alternate && allele != alternate && allele != maf_alternate {
+                    continue;
+                }
+                let Some((rank, term)) = subfields
+                    .get(layout.consequence)
+                    .into_iter()
+                    .flat_map(|terms| terms.split('&'))
+                    .filter(|term| !term.is_empty())
+                    .map(|term| (severity(term), term))
+                    .min_by_key(|(rank, _)| *rank)
+                else {
+                    continue;
+                };
+                if best
+                    .as_ref()
+                    .is_some_and(|(best_rank, _)| *best_rank <= rank)
+                {
+                    continue;
+                }
+                let text = |slot: Option<usize>| {
+                    slot.and_then(|slot| subfields.get(slot))
+                        .filter(|value| !value.is_empty())
+                        .map(|value| value.to_string())
+                };
+                best = Some((
+                    rank,
+                    AlleleConsequence {
+                        gene: text(layout.gene),
+                        term: term.to_string(),
+                        protein_change: text(layout.protein),
+                    },
+                ));
+            }
+        }
+        best.map(|(_, consequence)| consequence)
+    }
+}
+
+// Rank of a Sequence Ontology term in SEVERITY; unknown terms rank last
+fn severity(term: &str) -> usize {
+    SEVERITY
+        .iter()
+        .position(|known| known.eq_ignore_ascii_case(term))
+        .unwrap_or(SEVERITY.len())
+}
+
+// An ALT allele of the record at `position` in MAF form; None for alleles without one
+pub fn maf_allele(position: u64, reference: &str, alternate: &str) -> Option<MafAllele> {
+    if alternate == "*"
+        || alternate == "."
+        || alternate.starts_with('<')
+        || alternate.contains(['[', ']'])
+    {
+        return None;
+    }
+    let shared = reference
+        .bytes()
+        .zip(alternate.bytes())
+        .take_while(|(a, b)| a.eq_ignore_ascii_case(b))
+        .count();
+    // A SNV or MNP keeps its bases; only length-changing alleles lose the shared start
+    let shared = if reference.len() == alternate.len() {
+        0
+    } else {
+        shared
+    };
+    let (reference, alternate) = (&reference[shared..], &alternate[shared..]);
+    let start = position + shared as u64;
+    let variant_type = match (reference.len(), alternate.len()) {
+        (0, _) => "INS",
+        (_, 0) => "DEL",
+        (r, a) if r == a => match r {
+            1 => "SNP",
+            2 => "DNP",
+            3 => "TNP",
+            _ => "ONP",
+        },
+        (r, a) if r > a => "DEL",
+        _ => "INS",
+    };
+    let (start, end) = if reference.is_empty() {
+        // An insertion sits between the shared base and the next one
+        (start - 1, start)
+    } else {
+        (start, start + reference.len() as u64 - 1)
+    };
+    let dash = |allele: &str| {
+        if allele.is_empty() {
+            "-".to_string()
+        } else {
+            allele.to_string()
+        }
+    };
+    Some(MafAllele {
+        start,
+        end,
+        reference: dash(reference),
+        alternate: dash(alternate),
+        variant_type,
+    })
+}
+
+// vcf2maf's Variant_Classification for a Sequence Ontology term
+pub fn variant_classification(term: &str, variant_type: &str) -> &'static str {
+    match term {
+        "transcript_ablation"
+        | "exon_loss_variant"
+        | "splice_acceptor_variant"
+        | "splice_donor_variant" => "Splice_Site",
+        "stop_gained" => "Nonsense_Mutation",
+        "frameshift_variant" if variant_type == "DEL" => "Frame_Shift_Del",
+        "frameshift_variant" if variant_type == "INS" => "Frame_Shift_Ins",
+        "stop_lost" => "Nonstop_Mutation",
+        "start_lost" | "initiator_codon_variant" => "Translation_Start_Site",
+        "inframe_insertion" | "disruptive_inframe_insertion" | "conservative_inframe_insertion" => {
+            "In_Frame_Ins"
+        }
+        "inframe_deletion" | "disruptive_inframe_deletion" | "conservative_inframe_deletion" => {
+            "In_Frame_Del"
+        }
+        "missense_variant"
+        | "coding_sequence_variant"
+        | "conservative_missense_variant"
+        | "rare_amino_acid_variant" => "Missense_Mutation",
+        "protein_altering_variant" if variant_type == "DEL" => "In_Frame_Del",
+        "protein_altering_variant" if variant_type == "INS" => "In_Frame_Ins",
+        "protein_altering_variant" => "Missense_Mutation",
+        "transcript_amplification" | "intron_variant" | "INTRAGENIC" | "intragenic_variant" => {
+            "Intron"
+        }
+        "splice_region_variant" => "Splice_Region",
+        "incomplete_terminal_codon_variant"
+        | "synonymous_variant"
+        | "stop_retained_variant"
+        | "start_retained_variant"
+        | "NMD_transcript_variant" => "Silent",
+        "mature_miRNA_variant"
+        | "non_coding_transcript_exon_variant"
+        | "non_coding_exon_variant"
+        | "non_coding_transcript_variant"
+        | "nc_transcript_variant" => "RNA",
+        "5_prime_UTR_variant" | "5_prime_UTR_premature_start_codon_gain_variant" => "5'UTR",
+        "3_prime_UTR_variant" => "3'UTR",
+        "upstream_gene_variant" => "5'Flank",
+        "downstream_gene_variant" => "3'Flank",
+        "TF_binding_site_variant"
+        | "regulatory_region_variant"
+        | "regulatory_region"
+        | "intergenic_variant"
+        | "intergenic_region" => "IGR",
+        _ => "Targeted_Region",
+    }
+}
+
+// HGVSp with one-letter amino acids, as in MAF's HGVSp_Short: "ENSP0001:p.Gly12Asp" becomes
+// "p.G12D" and "p.Trp24Ter" "p.W24*"
+pub fn short_protein_change(hgvsp: &str) -> String {
+    const CODES: &[(&str, &str)] = &[
+        ("Ala", "A"),
+        ("Arg", "R"),
+        ("Asn", "N"),
+        ("Asp", "D"),
+        ("Cys", "C"),
+        ("Gln", "Q"),
+        ("Glu", "E"),
+        ("Gly", "G"),
+        ("His", "H"),
+        ("Ile", "I"),
+        ("Leu", "L"),
+        ("Lys", "K"),
+        ("Met", "M"),
+        ("Phe", "F"),
+        ("Pro", "P"),
+        ("Ser", "S"),
+        ("Thr", "T"),
+        ("Trp", "W"),
+        ("Tyr", "Y"),
+        ("Val", "V"),
+        ("Sec", "U"),
+        ("Pyl", "O"),
+        ("Xaa", "X"),
+        ("Ter", "*"),
+    ];
+    let change = hgvsp
+        .rsplit_once(':')
+        .map_or(hgvsp, |(_, change)| change)
+        .replace("%3D", "=");
+    let mut short = String::with_capacity(change.len());
+    let mut rest = change.as_str();
+    while !rest.is_empty() {
+        if let Some((long, code)) = CODES.iter().find(|(long, _)| rest.starts_with(long)) {
+            short.push_str(code);
+            rest = &rest[long.len()..];
+        } else {
+            let c = rest.chars().next().unwrap_or_default();
+            short.push(c);
+            rest = &rest[c.len_utf8()..];
+        }
+    }
+    short
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vcf::header_definitions;
+
+    #[test]
+    fn test_maf_allele() {
+        let snv = maf_allele(100, "G", "A").unwrap();
+        assert_eq!((snv.start, snv.end, snv.variant_type), (100, 100, "SNP"));
+        let deletion = maf_allele(100, "ATG", "A").unwrap();
+        assert_eq!((deletion.start, deletion.end), (101, 102));
+        assert_eq!(
+            (deletion.reference.as_str(), deletion.alternate.as_str()),
+            ("TG", "-")
+        );
+        assert_eq!(deletion.variant_type, "DEL");
+        let insertion = maf_allele(100, "A", "ACC").unwrap();
+        assert_eq!((insertion.start, insertion.end), (100, 101));
+        assert_eq!(
+            (insertion.reference.as_str(), insertion.alternate.as_str()),
+            ("-", "CC")
+        );
+        assert_eq!(insertion.variant_type, "INS");
+        assert_eq!(maf_allele(100, "AT", "GC").unwrap().variant_type, "DNP");
+        assert!(maf_allele(100, "A", "*").is_none());
+        assert!(maf_allele(100, "A", "<DEL>").is_none());
+    }
+
+    #[test]
+    fn test_most_severe_consequence() {
+        let definitions = header_definitions(
+            "##INFO=<ID=ANN,Number=.,Type=String,Description=\"Functional annotations: 'Allele | Annotation | Annotation_Impact | Gene_Name | Gene_ID | Feature_Type | Feature_ID | Transcript_BioType | Rank | HGVS.c | HGVS.p'\">\n",
+        )
+        .info;
+        let annotations = ConsequenceAnnotations::from_header(&definitions);
+        assert!(!annotations.is_empty());
+        let info = "DP=10;ANN=A|intron_variant|MODIFIER|KRAS|ENSG1|transcript|T1|protein_coding|||,\
+                    A|missense_variant&splice_region_variant|MODERATE|KRAS|ENSG1|transcript|T2|protein_coding|2/6|c.35G>A|p.Gly12Asp,\
+                    C|stop_gained|HIGH|KRAS|ENSG1|transcript|T2|protein_coding|2/6|c.35G>C|p.Gly12Ter";
+        let consequence = annotations.consequence(info, "A", "A", false).unwrap();
+        assert_eq!(consequence.term, "missense_variant");
+        assert_eq!(consequence.gene.as_deref(), Some("KRAS"));
+        assert_eq!(consequence.protein_change.as_deref(), Some("p.Gly12Asp"));
+        assert_eq!(
+            annotations.consequence(info, "C", "C", false).unwrap().term,
+            "stop_gained"
+        );
+        assert!(annotations.consequence(info, "T", "T", false).is_none());
+        assert!(ConsequenceAnnotations::from_header(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_variant_classification() {
+        assert_eq!(
+            variant_classification("missense_variant", "SNP"),
+            "Missense_Mutation"
+        );
+        assert_eq!(
+            variant_classification("frameshift_variant", "DEL"),
+            "Frame_Shift_Del"
+        );
+        assert_eq!(
+            variant_classification("frameshift_variant", "INS"),
+            "Frame_Shift_Ins"
+        );
+        assert_eq!(
+            variant_classification("upstream_gene_variant", "SNP"),
+            "5'Flank"
+        );
+        assert_eq!(
+            variant_classification("something_new", "SNP"),
+            "Targeted_Region"
+        );
+    }
+
+    #[test]
+    fn test_short_protein_change() {
+        assert_eq!(
+            short_protein_change("ENSP00000256078.4:p.Gly12Asp"),
+            "p.G12D"
+        );
+        assert_eq!(short_protein_change("p.Trp24Ter"), "p.W24*");
+        assert_eq!(short_protein_change("p.Leu54%3D"), "p.L54=");
+        assert_eq!(
+            short_protein_change("p.Lys5_Ala6delinsGlu"),
+            "p.K5_A6delinsE"
+        );
+    }
+}
@@ -1,19 +1,72 @@
+mod beacon;
+mod bed;
+mod block_cache;
+mod browser;
+mod build_check;
+mod cache_admin;
+mod client_log;
+mod config;
+#[cfg(feature = "network")]
+mod dbsnp;
+mod http_limits;
+mod id_index;
+mod liftover;
+mod logging;
+mod maf;
+mod numeric;
+#[cfg(feature = "parquet-export")]
+mod parquet_export;
+mod pedigree;
+mod prompts;
+mod reference;
+mod replay;
+mod result_cache;
+mod shutdown;
+mod spdi;
+mod supervisor;
+mod validate;
 mod vcf;
-
-use clap::Parser;
+#[cfg(feature = "network")]
+mod vep;
+mod vrs;
+mod watch;
+
+use build_check::{Build, Marker, Verdict};
+use clap::{CommandFactory, Parser};
+use liftover::{ChainFile, LiftedPosition};
+use maf::ConsequenceAnnotations;
+#[cfg(feature = "parquet-export")]
+use parquet_export::{ParquetColumn, ParquetWriter};
+use pedigree::{MendelianCheck, Trio};
+use reference::ReferenceFasta;
 use rmcp::{
     handler::server::{router::tool::ToolRouter, tool::ToolCallContext, wrapper::Parameters},
     model::*,
     schemars,
-    service::RequestContext,
+    service::{Peer, RequestContext},
     tool, tool_router, ErrorData as McpError, RoleServer, ServerHandler, ServiceExt,
 };
+use spdi::Spdi;
 use std::collections::HashMap;
+use std::ops::ControlFlow;
 use std::path::PathBuf;
 use std::sync::Arc;
-use tokio::sync::Mutex;
+use tokio::sync::{Mutex, Semaphore};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, error, info, warn, Instrument};
 use uuid::Uuid;
-use vcf::{format_variant, load_vcf, Variant, VcfIndex};
+use vcf::{
+    bed_intervals, cluster_variants, compound_het_pairs, count_filter_types, filter_clauses,
+    format_variant, header_definitions, heterozygous_site, load_vcf_with_options,
+    merge_phased_mnvs, render_compact, render_table, sample_genotype, sample_value, select_samples,
+    unordered_alleles, AlleleCarriers, AlternateAllele, CallRateStats, ContigSummary,
+    CoordinateSystem, DetailLevel, ExcludedVariant, FilterExplanation, FrequencySpectrum,
+    HeaderDefinition, HeterozygousSite, Histogram, IdIndexStatus, IdMatch, LimitExceeded,
+    LoadOptions, MergedMnv, NearbyVariants, NearestVariants, PairPhase, QcMetrics, QueryLimits,
+    QueryOptions, ReadFailure, RecordFilter, RegionCursor, RegionEstimate, RetryPolicy, SortOrder,
+    TableColumn, Variant, VariantCluster, VariantType, VariantTypeStats, VcfIndex,
+};
+use vrs::VrsSource;
 
 // Embed documentation at compile time
 const README_DOCS: &str = include_str!("../README.md");
@@ -25,9 +78,30 @@ const STREAMING_FILTER_DOCS: &str = include_str!("../STREAMING_FILTER_EXAMPLES.m
 #[derive(Parser, Debug)]
 #[command(name = "vcf_mcp_server")]
 #[command(about = "VCF MCP Server - expose VCF files via MCP protocol", long_about = None)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// Path to the VCF file
-    vcf_file: PathBuf,
+    #[arg(required_unless_present = "manifest", conflicts_with = "manifest")]
+    vcf_file: Option<PathBuf>,
+
+    /// Read options from this TOML file (keys named like the options); command line flags and VCF_MCP_* environment variables override it
+    #[arg(long, value_name = "FILE")]
+    config: Option<PathBuf>,
+
+    /// Serve several VCF files from a JSON or TOML manifest (or a directory of VCFs), one worker process per file
+    #[arg(long, value_name = "FILE")]
+    manifest: Option<PathBuf>,
+
+    /// With --manifest: keep at most this many workers running, evicting the least recently used (0 means unlimited)
+    #[arg(long, value_name = "N", default_value_t = 0, requires = "manifest")]
+    max_loaded_datasets: usize,
+
+    /// With --manifest: evict least recently used workers while their combined memory exceeds this many MiB (0 means unlimited, Linux only)
+    #[arg(long, value_name = "MIB", default_value_t = 0, requires = "manifest")]
+    max_worker_memory_mb: u64,
 
     /// Run HTTP server on specified address (e.g., 127.0.0.1:8090)
     #[arg(long, value_name = "ADDR:PORT")]
@@ -37,9 +111,518 @@ struct Args {
     #[arg(long)]
     debug: bool,
 
+    /// Log line format: text, or json for one JSON object per line (with the tool call's span fields)
+    #[arg(long, value_enum, default_value = "text")]
+    log_format: logging::LogFormat,
+
+    /// Append logs to this file instead of writing them to stderr
+    #[arg(long)]
+    log_file: Option<PathBuf>,
+
     /// Never save the built tabix index to disk (for read-only/ephemeral environments)
     #[arg(long)]
     never_save_index: bool,
+
+    /// Build a missing ID index before serving instead of in the background
+    #[arg(long)]
+    wait_for_index: bool,
+
+    /// Build the ID index by external sort (spilling sorted runs to the temp directory), for files with too many IDs to index in RAM
+    #[arg(long)]
+    low_memory: bool,
+
+    /// Also record in the ID index which samples have an ALT allele at each variant, so query_by_region's present_in_sample skips other records without decoding them. Costs about one bit per sample per variant on disk; an existing index without it is rebuilt.
+    #[arg(long)]
+    sample_presence_index: bool,
+
+    /// Cache up to this many MiB of decompressed BGZF blocks so repeated queries in the same region skip decompression (0 disables the cache)
+    #[arg(long, value_name = "MIB", default_value_t = 64)]
+    block_cache_mb: u64,
+
+    /// Memory-map the VCF file instead of reading it through file handles, so queries avoid a system call per block and server processes on the same host share its pages (the ID index is always memory-mapped). Only for files nothing rewrites while served: reading a mapped file that was truncated or rewritten in place kills the process with SIGBUS. A reload (SIGHUP or reload_dataset) drops the map and reads the file through file handles from then on
+    #[arg(long, conflicts_with = "watch")]
+    mmap: bool,
+
+    /// Regions wider than this many bp make query_by_region ask for confirmation (confirm_large_query) before scanning them, and are refused as vcf:// region resources (0 removes the cap)
+    #[arg(long, value_name = "BP", default_value_t = DEFAULT_MAX_REGION_SIZE)]
+    max_region_size: u64,
+
+    /// Refuse region queries (query_by_region, export_bed, export_region, cluster_variants, sample_variants) spanning more than this many bp, even when confirmed; streaming sessions are exempt (0 means unlimited)
+    #[arg(long, value_name = "BP", default_value_t = 0)]
+    max_region_bp: u64,
+
+    /// Cap the variants one region query returns: pages are at most this size, export_bed, export_region or cluster_variants fail when more match, and sample_variants' count may not exceed it (0 means unlimited)
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    max_results: usize,
+
+    /// Stop region queries (including sample_variants and each streaming call) that scan for longer than this many seconds (0 means no timeout)
+    #[arg(long, value_name = "SECS", default_value_t = 0)]
+    query_timeout: u64,
+
+    /// Retry reads of the VCF that fail with a transient I/O error (interrupted, timed out, connection reset) this many times (0 disables retries)
+    #[arg(long, value_name = "N", default_value_t = 3)]
+    io_retries: u32,
+
+    /// Delay before the first retry of a failed read in milliseconds; doubled before each further retry
+    #[arg(long, value_name = "MS", default_value_t = 100)]
+    io_retry_delay_ms: u64,
+
+    /// Reference genome FASTA (uncompressed, ideally with a samtools .fai index) for the query tools' normalize and vrs options
+    #[arg(long, value_name = "FASTA", conflicts_with = "manifest")]
+    reference: Option<PathBuf>,
+
+    /// Second bgzipped, indexed VCF (e.g. gnomAD sites or ClinVar) whose records the query tools' annotate option matches by position and alleles
+    #[arg(long, value_name = "VCF", conflicts_with = "manifest")]
+    annotation: Option<PathBuf>,
+
+    /// INFO fields to take from --annotation records, comma-separated (e.g. AF,CLNSIG); all fields when omitted
+    #[arg(
+        long,
+        value_name = "KEYS",
+        value_delimiter = ',',
+        requires = "annotation"
+    )]
+    annotation_fields: Vec<String>,
+
+    /// UCSC chain file (optionally gzipped) from another build to the VCF's, e.g. hg19ToHg38.over.chain.gz, for liftover_position and the query tools' liftover option
+    #[arg(long, value_name = "FILE", conflicts_with = "manifest")]
+    chain: Option<PathBuf>,
+
+    /// Allow tools that send variants or IDs to external web services (annotate_via_vep, query_by_id's resolve_via_ncbi); off by default so no data leaves the machine
+    #[arg(long, conflicts_with = "manifest")]
+    allow_network: bool,
+
+    /// Ensembl REST server for annotate_via_vep (default: rest.ensembl.org, or grch37.rest.ensembl.org for GRCh37 files)
+    #[arg(long, value_name = "URL", requires = "allow_network")]
+    vep_server: Option<String>,
+
+    /// Directory query_by_bed may read BED files from (its path parameter); without it only inline BED text is accepted
+    #[arg(long, value_name = "DIR", conflicts_with = "manifest")]
+    bed_dir: Option<PathBuf>,
+
+    /// Directory export_region and export_parquet may write files to; without it both are unavailable
+    #[arg(long, value_name = "DIR", conflicts_with = "manifest")]
+    allow_write_dir: Option<PathBuf>,
+
+    /// PED file (family, individual, father, mother, sex, phenotype) naming the trios find_mendelian_errors checks when no samples are given
+    #[arg(long, value_name = "FILE", conflicts_with = "manifest")]
+    ped: Option<PathBuf>,
+
+    /// Interval between SSE keep-alive pings in seconds (0 disables keep-alive)
+    #[arg(long, value_name = "SECS", default_value_t = 15)]
+    sse_keep_alive: u64,
+
+    /// Keep HTTP sessions on the server (Mcp-Session-Id), so clients can open a standing event stream for server-initiated notifications and resume dropped streams; by default every request stands alone
+    #[arg(long)]
+    stateful_sessions: bool,
+
+    /// With --stateful-sessions: close a session after this many seconds without requests (0 keeps sessions until the client ends them)
+    #[arg(
+        long,
+        value_name = "SECS",
+        default_value_t = 600,
+        requires = "stateful_sessions"
+    )]
+    session_keep_alive: u64,
+
+    /// With --stateful-sessions: maximum number of open sessions; further `initialize` requests are answered with 503 until a session ends or expires (0 means unlimited)
+    #[arg(
+        long,
+        value_name = "N",
+        default_value_t = 0,
+        requires = "stateful_sessions"
+    )]
+    max_sessions: usize,
+
+    /// Close HTTP connections on which nothing was read or written for this many seconds, event streams included (0 disables the timeout); keep it above --sse-keep-alive so open streams survive between pings
+    #[arg(long, value_name = "SECS", default_value_t = 0)]
+    idle_timeout: u64,
+
+    /// Close HTTP/1 connections that send no request headers for this many seconds (0 disables the timeout); open event streams and HTTP/2 connections are not affected
+    #[arg(long, value_name = "SECS", default_value_t = 0)]
+    header_read_timeout: u64,
+
+    /// Maximum number of concurrent HTTP connections; extra connections are answered with 503 (0 means unlimited)
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    max_connections: usize,
+
+    /// Compress HTTP responses of at least this many bytes with gzip or deflate when the client accepts it (0 disables compression)
+    #[arg(long, value_name = "BYTES", default_value_t = 1024)]
+    compression_min_bytes: u16,
+
+    /// Mount the HTTP endpoints under this path prefix (e.g., /vcf) when behind a reverse proxy
+    #[arg(long, value_name = "PATH", value_parser = parse_base_path)]
+    base_path: Option<String>,
+
+    /// Let browser-based MCP clients on these origins call the HTTP transport (comma-separated, e.g. http://localhost:6274; * allows any origin)
+    #[arg(
+        long,
+        value_name = "ORIGINS",
+        value_delimiter = ',',
+        value_parser = parse_cors_origin
+    )]
+    cors_origins: Vec<String>,
+
+    /// Extra request headers browser clients may send (comma-separated, e.g. authorization), besides the ones MCP uses
+    #[arg(
+        long,
+        value_name = "HEADERS",
+        value_delimiter = ',',
+        value_parser = parse_header_name,
+        requires = "cors_origins"
+    )]
+    cors_headers: Vec<String>,
+
+    /// Also serve a GA4GH Beacon v2 endpoint at /beacon/g_variants (GET or POST) answering variant queries from the same file; no genotypes or sample names are returned
+    #[arg(long, requires = "sse", conflicts_with = "manifest")]
+    beacon: bool,
+
+    /// Reload the VCF when it or its .tbi/.csi index changes on disk (polled every 2 seconds), and send notifications/resources/updated to clients subscribed to resources
+    #[arg(long, conflicts_with = "manifest")]
+    watch: bool,
+
+    /// Most detailed answer the Beacon endpoint gives: boolean (whether variants exist), count, or record (variant descriptions)
+    #[arg(
+        long,
+        value_name = "GRANULARITY",
+        value_enum,
+        default_value = "record",
+        requires = "beacon"
+    )]
+    beacon_granularity: beacon::Granularity,
+
+    /// Only expose these tools (comma-separated tool names); all tools are exposed by default
+    #[arg(long, value_name = "TOOLS", value_delimiter = ',')]
+    enable_tools: Vec<String>,
+
+    /// Hide these tools (comma-separated tool names), e.g. for least-privilege deployments. Resources and the Beacon endpoint serving the same data are withdrawn with them
+    #[arg(long, value_name = "TOOLS", value_delimiter = ',')]
+    disable_tools: Vec<String>,
+
+    /// Serve no genotypes: FORMAT and sample columns are stripped from every variant, header and VCF written, sample names are not listed, and the tools reporting per-sample data are removed
+    #[arg(long)]
+    hide_genotypes: bool,
+
+    /// Maximum number of queries reading the VCF at once; further tool calls wait for a free slot (0 uses the number of CPUs)
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    max_concurrent_queries: usize,
+
+    /// On SIGINT or SIGTERM, wait this many seconds for running tool calls and index writes to finish before exiting
+    #[arg(long, value_name = "SECS", default_value_t = 30)]
+    drain_timeout: u64,
+
+    /// Serve repeated tool calls with identical arguments from memory for this many seconds (0 disables the result cache)
+    #[arg(long, value_name = "SECS", default_value_t = 0)]
+    result_cache_ttl: u64,
+
+    /// With --result-cache-ttl: maximum MiB of cached responses, least recently used evicted first
+    #[arg(long, value_name = "MIB", default_value_t = 32)]
+    result_cache_mb: u64,
+
+    /// Append every tool call and a digest of its response to this file (JSON Lines) for `replay`
+    #[arg(long, value_name = "FILE", conflicts_with = "manifest")]
+    record_queries: Option<PathBuf>,
+
+    /// With --record-queries: also store each response, so replay can show what changed and not only that something did
+    #[arg(long, requires = "record_queries")]
+    record_responses: bool,
+}
+
+#[derive(clap::Subcommand, Debug)]
+enum Command {
+    /// Re-run calls recorded with --record-queries and report responses that changed
+    Replay {
+        /// Recording file written by --record-queries
+        recording: PathBuf,
+
+        /// VCF file to run the recorded calls against
+        vcf_file: PathBuf,
+
+        /// Server binary to replay against (defaults to this binary)
+        #[arg(long, value_name = "PATH")]
+        binary: Option<PathBuf>,
+
+        /// Enable debug logging
+        #[arg(long)]
+        debug: bool,
+    },
+
+    /// Inspect and clean up the index, ID index and statistics files kept next to VCFs
+    Cache {
+        #[command(subcommand)]
+        action: CacheAction,
+    },
+
+    /// Build the tabix index, ID index and statistics of VCF files and exit, without serving
+    Index {
+        /// Bgzipped VCF files to index
+        #[arg(required = true)]
+        vcf_files: Vec<PathBuf>,
+
+        /// Index up to this many files at once
+        #[arg(long, value_name = "N", default_value_t = 1, value_parser = clap::value_parser!(u64).range(1..))]
+        threads: u64,
+
+        /// Rebuild the tabix index, ID index and statistics even when they are present and current
+        #[arg(long)]
+        force: bool,
+
+        /// Build the ID index by external sort with bounded memory, for dbSNP-sized files
+        #[arg(long)]
+        low_memory: bool,
+
+        /// Store in the ID index which samples have an ALT allele at each variant
+        #[arg(long)]
+        sample_presence_index: bool,
+
+        /// Enable debug logging
+        #[arg(long)]
+        debug: bool,
+    },
+
+    /// Check a VCF's compression, header, records, sort order and indexes and print a JSON report; exits with status 1 if it has errors
+    Validate {
+        /// VCF file to check
+        vcf_file: PathBuf,
+    },
+
+    /// Run one query_by_region, query_by_position or query_by_id call against a VCF and print the response, without an MCP client
+    #[command(group(clap::ArgGroup::new("target").required(true).args(["region", "position", "id"])))]
+    Query {
+        /// VCF file to query
+        vcf_file: PathBuf,
+
+        /// Region to query, as CHROM:START-END (1-based, inclusive), e.g. chr20:14000-18000
+        #[arg(long, value_name = "CHROM:START-END", value_parser = parse_region_arg)]
+        region: Option<Locus>,
+
+        /// Position to query, as CHROM:POS, e.g. chr20:14370
+        #[arg(long, value_name = "CHROM:POS", value_parser = parse_position_arg)]
+        position: Option<Locus>,
+
+        /// Variant ID to look up, e.g. rs6054257
+        #[arg(long)]
+        id: Option<String>,
+
+        /// With --region: filter expression, e.g. 'QUAL > 30 && FILTER == "PASS"'
+        #[arg(long, requires = "region")]
+        filter: Option<String>,
+
+        /// Output format; tsv, csv and vcf print the table or VCF text alone, json the whole response
+        #[arg(long, value_enum, default_value_t = OutputFormat::Json)]
+        format: OutputFormat,
+
+        /// Only include these fields (table columns with tsv/csv), e.g. chromosome,position,info.AF
+        #[arg(long, value_delimiter = ',')]
+        fields: Vec<String>,
+
+        /// With --region: next_cursor of a previous response, to print the following page
+        #[arg(long, requires = "region")]
+        cursor: Option<String>,
+
+        /// Never save a built index to disk
+        #[arg(long)]
+        never_save_index: bool,
+
+        /// Enable debug logging
+        #[arg(long)]
+        debug: bool,
+    },
+}
+
+#[derive(clap::Subcommand, Debug)]
+enum CacheAction {
+    /// List sidecar files with their size and age
+    Ls {
+        /// Directory holding the VCF files
+        #[arg(default_value = ".")]
+        dir: PathBuf,
+    },
+
+    /// Check every sidecar file against its VCF; exits with status 1 if any needs attention
+    Verify {
+        /// Directory holding the VCF files
+        #[arg(default_value = ".")]
+        dir: PathBuf,
+    },
+
+    /// Delete leftover temp files, sidecars of deleted VCFs, and stale or unreadable ID indexes and statistics
+    Prune {
+        /// Directory holding the VCF files
+        #[arg(default_value = ".")]
+        dir: PathBuf,
+
+        /// Only delete temp files at least this many seconds old, so builds in progress are left alone
+        #[arg(long, value_name = "SECS", default_value_t = 3600)]
+        min_temp_age: u64,
+
+        /// List what would be deleted without deleting it
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
+
+// A chromosome with a 1-based inclusive range, from the query subcommand's --region or
+// --position
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Locus {
+    chromosome: String,
+    start: u64,
+    end: u64,
+}
+
+// Parse CHROM:START-END, or CHROM:POS when `range` is false. Positions may have thousands
+// separators (chr20:14,000-18,000), and the chromosome may contain colons (HLA alleles).
+fn parse_locus(raw: &str, range: bool) -> Result<Locus, String> {
+    let expected = if range {
+        "CHROM:START-END, e.g. chr20:14000-18000"
+    } else {
+        "CHROM:POS, e.g. chr20:14370"
+    };
+    let invalid = || {
+        format!(
+            "invalid {} '{}': expected {}",
+            if range { "region" } else { "position" },
+            raw,
+            expected
+        )
+    };
+    let (chromosome, positions) = raw.trim().rsplit_once(':').ok_or_else(invalid)?;
+    let number = |text: &str| {
+        text.trim()
+            .replace(',', "")
+            .parse::<u64>()
+            .ok()
+            .filter(|&n| n > 0)
+    };
+    let (start, end) = if range {
+        let (start, end) = positions.split_once('-').ok_or_else(invalid)?;
+        (number(start), number(end))
+    } else {
+        (number(positions), number(positions))
+    };
+    match (start, end) {
+        (Some(start), Some(end)) if !chromosome.is_empty() && start <= end => Ok(Locus {
+            chromosome: chromosome.to_string(),
+            start,
+            end,
+        }),
+        _ => Err(invalid()),
+    }
+}
+
+fn parse_region_arg(raw: &str) -> Result<Locus, String> {
+    parse_locus(raw, true)
+}
+
+fn parse_position_arg(raw: &str) -> Result<Locus, String> {
+    parse_locus(raw, false)
+}
+
+// Normalize a --base-path value to "/segment[/segment...]" (empty string for the root)
+fn parse_base_path(raw: &str) -> Result<String, String> {
+    let trimmed = raw.trim().trim_matches('/');
+    if trimmed.is_empty() {
+        return Ok(String::new());
+    }
+    if trimmed
+        .split('/')
+        .any(|segment| segment.is_empty() || segment.contains(['?', '#', '{', '}', '*']))
+    {
+        return Err(format!("invalid base path '{}'", raw));
+    }
+    Ok(format!("/{}", trimmed))
+}
+
+// Check a --cors-origins value: "*" or a browser origin, scheme://host[:port] without a path
+fn parse_cors_origin(raw: &str) -> Result<String, String> {
+    let origin = raw.trim().trim_end_matches('/');
+    if origin == "*" {
+        return Ok(origin.to_string());
+    }
+    let valid = match origin.split_once("://") {
+        Some((scheme, host)) => {
+            scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+                && scheme
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'))
+                && !host.is_empty()
+                && !host.contains(['/', '?', '#', '@', ' '])
+                && axum::http::HeaderValue::from_str(origin).is_ok()
+        }
+        None => false,
+    };
+    if !valid {
+        return Err(format!(
+            "invalid origin '{}' (expected e.g. http://localhost:6274, or *)",
+            raw
+        ));
+    }
+    Ok(origin.to_ascii_lowercase())
+}
+
+// Check a --cors-headers value is a header name; names are matched case-insensitively
+fn parse_header_name(raw: &str) -> Result<String, String> {
+    axum::http::HeaderName::from_bytes(raw.trim().as_bytes())
+        .map(|name| name.as_str().to_string())
+        .map_err(|_| format!("invalid header name '{}'", raw))
+}
+
+// How long a reload waits for the old index's background ID index build to stop
+const RELOAD_BUILD_STOP_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(30);
+
+// Default --max-concurrent-queries: one query per CPU
+fn default_query_concurrency() -> usize {
+    std::thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(4)
+}
+
+// Tuning knobs for the streamable HTTP transport
+#[derive(Debug, Clone)]
+struct HttpOptions {
+    sse_keep_alive: Option<std::time::Duration>,
+    stateful: bool,
+    // Idle time after which a stateful session is closed; None keeps it
+    session_keep_alive: Option<std::time::Duration>,
+    // Open stateful sessions; None is unlimited
+    max_sessions: Option<usize>,
+    // Time without any bytes read or written after which a connection is closed
+    idle_timeout: Option<std::time::Duration>,
+    // How long an HTTP/1 connection may wait before sending request headers
+    header_read_timeout: Option<std::time::Duration>,
+    // Concurrent TCP connections; a stateful MCP session may span several of them
+    max_connections: Option<usize>,
+    // Smallest response body worth compressing; None disables compression
+    compression_min_bytes: Option<u16>,
+    base_path: Option<String>,
+    // Origins allowed to make cross-origin requests ("*" for any); empty disables CORS
+    cors_origins: Vec<String>,
+    // Request headers allowed besides MCP's own
+    cors_headers: Vec<String>,
+}
+
+impl HttpOptions {
+    fn from_args(args: &Args) -> Self {
+        HttpOptions {
+            sse_keep_alive: (args.sse_keep_alive > 0)
+                .then(|| std::time::Duration::from_secs(args.sse_keep_alive)),
+            stateful: args.stateful_sessions,
+            session_keep_alive: (args.session_keep_alive > 0)
+                .then(|| std::time::Duration::from_secs(args.session_keep_alive)),
+            max_sessions: (args.max_sessions > 0).then_some(args.max_sessions),
+            idle_timeout: (args.idle_timeout > 0)
+                .then(|| std::time::Duration::from_secs(args.idle_timeout)),
+            header_read_timeout: (args.header_read_timeout > 0)
+                .then(|| std::time::Duration::from_secs(args.header_read_timeout)),
+            max_connections: (args.max_connections > 0).then_some(args.max_connections),
+            compression_min_bytes: (args.compression_min_bytes > 0)
+                .then_some(args.compression_min_bytes),
+            base_path: args.base_path.clone().filter(|p| !p.is_empty()),
+            cors_origins: args.cors_origins.clone(),
+            cors_headers: args.cors_headers.clone(),
+        }
+    }
 }
 
 // Parameter structs for MCP tools
@@ -48,1114 +631,12034 @@ struct QueryByPositionParams {
     /// Chromosome name (e.g., '1', '2', 'X', 'chr1')
     chromosome: String,
     /// Genomic position (1-based)
+    #[serde(deserialize_with = "numeric::deserialize_u64")]
     position: u64,
+    /// Also return the variants within this many bp either side of the position, for context. Default 0 (the position only), at most 10,000.
+    #[serde(default, deserialize_with = "numeric::deserialize_u64")]
+    window: u64,
+    /// Only return variants carrying all of these INFO flags (e.g., ["DB", "SOMATIC"])
+    #[serde(default)]
+    has_info_flags: Vec<String>,
+    #[serde(flatten)]
+    shortcuts: FilterShortcuts,
+    /// Only include these keys in each variant, e.g. ["chromosome", "position", "id", "info.AF"]. "info.<KEY>" and "samples.<NAME>" select single INFO fields or samples. Omit to include all.
+    #[serde(default)]
+    fields: Option<Vec<String>>,
+    /// Also report each variant's alleles left-aligned and trimmed against the server's reference FASTA (in `normalized`); needs --reference
+    #[serde(default)]
+    normalize: bool,
+    /// Also report each ALT allele as a GA4GH VRS 2.0 Allele with its computed identifier (ga4gh:VA...) for referencing the variant across systems (in `vrs`, null for symbolic alleles); needs --reference on the VCF's build
+    #[serde(default)]
+    vrs: bool,
+    /// Also add IGV.js and UCSC Genome Browser URLs showing each variant on the file's build (in `browser_links`); needs a header naming GRCh37 or GRCh38
+    #[serde(default)]
+    browser_links: bool,
+    /// Also look each variant up in the server's annotation VCF and add the matching records' INFO fields per ALT allele (in `annotation`); needs --annotation
+    #[serde(default)]
+    annotate: bool,
+    /// The coordinates are on the --chain file's source build (e.g. GRCh37): lift them to the VCF's build before querying and echo both in `liftover`. Returned variants keep the VCF's coordinates. Needs --chain
+    #[serde(default)]
+    liftover: bool,
+    /// "json" (default), "compact", "vcf", "tsv" or "csv" (alias output_format): compact returns each variant in result.items as one line of text, e.g. "20:14370 G>A rs6054257 QUAL=29 PASS AF=0.5 het(NA00002)", to save context; vcf replaces result.items with result.vcf, the matching records as VCF text with a minimal header, ready for bcftools; tsv and csv replace it with result.table, a header row and one row per variant, whose columns are the fields given (default CHROM, POS, ID, REF, ALT, QUAL, FILTER; samples.<NAME> is the sample's GT). fields cannot be combined with compact or vcf.
+    #[serde(default, alias = "output_format")]
+    #[schemars(with = "Option<String>")]
+    format: OutputFormat,
 }
 
+// Widest window query_by_position accepts either side of the position; wider context is a
+// region query
+const MAX_POSITION_WINDOW: u64 = 10_000;
+
 #[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
-struct QueryByRegionParams {
+struct HasVariantAtParams {
     /// Chromosome name (e.g., '1', '2', 'X', 'chr1')
     chromosome: String,
-    /// Start position (1-based, inclusive)
-    start: u64,
-    /// End position (1-based, inclusive)
-    end: u64,
+    /// Genomic position (1-based)
+    #[serde(deserialize_with = "numeric::deserialize_u64")]
+    position: u64,
+    /// Also count variants starting up to this many bp either side of the position. Default 0, at most 1,000,000.
+    #[serde(default, deserialize_with = "numeric::deserialize_u64")]
+    window: u64,
 }
 
-#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
-struct QueryByIdParams {
-    /// Variant ID (e.g., 'rs6054257')
-    id: String,
-}
+// Widest window has_variant_at accepts either side of the position
+const MAX_VARIANT_WINDOW: u64 = 1_000_000;
 
-#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
-struct GetHeaderParams {
-    /// Optional search string to filter header lines (e.g., '##INFO', '##contig', '##FILTER'). If provided, only lines containing this string will be returned.
-    #[serde(default)]
-    search: Option<String>,
-}
+// Start positions listed by has_variant_at
+const NEARBY_POSITIONS_LIMIT: usize = 100;
 
 #[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
-struct GetStatisticsParams {
-    /// Maximum number of chromosomes to include in variants_per_chromosome. Default is 25 (top chromosomes by variant count). Set to 0 to include all chromosomes.
-    #[serde(default = "default_max_chromosomes")]
-    max_chromosomes: usize,
+struct FindNearestVariantParams {
+    /// Chromosome name (e.g., '1', '2', 'X', 'chr1')
+    chromosome: String,
+    /// Genomic position (1-based)
+    #[serde(deserialize_with = "numeric::deserialize_u64")]
+    position: u64,
+    /// Search at most this many bp either side of the position. Default 100,000, at most 10,000,000.
+    #[serde(
+        default = "default_nearest_distance",
+        deserialize_with = "numeric::deserialize_u64"
+    )]
+    max_distance: u64,
 }
 
-fn default_max_chromosomes() -> usize {
-    25
+fn default_nearest_distance() -> u64 {
+    100_000
 }
 
+// Farthest find_nearest_variant searches either side of the position
+const MAX_NEAREST_DISTANCE: u64 = 10_000_000;
+
 #[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
-struct StreamRegionParams {
+struct QueryByRegionParams {
     /// Chromosome name (e.g., '1', '2', 'X', 'chr1')
     chromosome: String,
     /// Start position (1-based, inclusive)
+    #[serde(deserialize_with = "numeric::deserialize_u64")]
     start: u64,
     /// End position (1-based, inclusive)
+    #[serde(deserialize_with = "numeric::deserialize_u64")]
     end: u64,
-    /// Optional filter expression (e.g., "QUAL > 30 AND FILTER == PASS"). Empty or omitted means no filtering.
+    /// Only return variants carrying all of these INFO flags (e.g., ["DB", "SOMATIC"])
+    #[serde(default)]
+    has_info_flags: Vec<String>,
+    /// Optional filter expression (e.g., "QUAL > 30 && FILTER == \"PASS\""). Empty or omitted means no filtering.
     #[serde(default)]
     filter: String,
+    #[serde(flatten)]
+    shortcuts: FilterShortcuts,
+    /// Sample name: also report adjacent SNVs phased onto the same haplotype of this sample merged into MNVs (in `mnvs`)
+    #[serde(default)]
+    mnv_sample: Option<String>,
+    /// Run the query even though the region exceeds the server's size cap. Set this only after a confirmation_required response.
+    #[serde(default)]
+    confirm_large_query: bool,
+    /// next_cursor from a previous response for the same region and filters, to fetch the following page
+    #[serde(default)]
+    cursor: Option<String>,
+    /// Maximum number of variants per page. Default and maximum is 10,000.
+    #[serde(
+        default = "default_region_page_size",
+        deserialize_with = "numeric::deserialize_usize"
+    )]
+    limit: usize,
+    /// Only include these INFO fields in each variant (e.g., ["AF", "DP"]). Omit to include all.
+    #[serde(default)]
+    info_fields: Option<Vec<String>>,
+    /// Only include these keys in each variant, e.g. ["chromosome", "position", "id", "info.AF"]. "info.<KEY>" and "samples.<NAME>" select single INFO fields or samples. Omit to include all.
+    #[serde(default)]
+    fields: Option<Vec<String>>,
+    /// Also report each variant's alleles left-aligned and trimmed against the server's reference FASTA (in `normalized`); needs --reference
+    #[serde(default)]
+    normalize: bool,
+    /// Also report each ALT allele as a GA4GH VRS 2.0 Allele with its computed identifier (ga4gh:VA...) for referencing the variant across systems (in `vrs`, null for symbolic alleles); needs --reference on the VCF's build
+    #[serde(default)]
+    vrs: bool,
+    /// Also add IGV.js and UCSC Genome Browser URLs showing each variant on the file's build (in `browser_links`); needs a header naming GRCh37 or GRCh38
+    #[serde(default)]
+    browser_links: bool,
+    /// Also look each variant up in the server's annotation VCF and add the matching records' INFO fields per ALT allele (in `annotation`); needs --annotation
+    #[serde(default)]
+    annotate: bool,
+    /// The coordinates are on the --chain file's source build (e.g. GRCh37): lift them to the VCF's build before querying and echo both in `liftover`. Returned variants keep the VCF's coordinates. Needs --chain
+    #[serde(default)]
+    liftover: bool,
+    /// "json" (default), "compact", "vcf", "tsv" or "csv" (alias output_format): compact returns each variant in result.items as one line of text, e.g. "20:14370 G>A rs6054257 QUAL=29 PASS AF=0.5 het(NA00002)", to save context; vcf replaces result.items with result.vcf, the matching records as VCF text with a minimal header, ready for bcftools; tsv and csv replace it with result.table, a header row and one row per variant, whose columns are the fields given (default CHROM, POS, ID, REF, ALT, QUAL, FILTER; samples.<NAME> is the sample's GT). fields cannot be combined with compact or vcf.
+    #[serde(default, alias = "output_format")]
+    #[schemars(with = "Option<String>")]
+    format: OutputFormat,
+    /// "full" (default) or "summary": summary leaves out INFO values and ALT classifications
+    #[serde(default)]
+    #[schemars(with = "Option<String>")]
+    detail: DetailLevel,
+    /// Sample names whose FORMAT values (e.g., GT, DP) are reported per variant in `samples`
+    #[serde(default)]
+    samples: Vec<String>,
+    /// Only variants where this sample has an ALT allele in its genotype (GT), e.g. "NA00001"
+    #[serde(default)]
+    present_in_sample: Option<String>,
+    /// "position" (default, file order) or "quality" (highest QUAL first, within the page)
+    #[serde(default)]
+    #[schemars(with = "Option<String>")]
+    sort: SortOrder,
+    /// "1-based-inclusive" (default: VCF) or "0-based-half-open" (BED: 0-based, end exclusive). Applies to start, end and reported positions.
+    #[serde(default, alias = "coordinates")]
+    #[schemars(with = "Option<String>")]
+    coordinate_system: CoordinateSystem,
+    /// Explain mode for debugging filters: list each returned variant's matching filter clauses in `matched_clauses`. The filter (including shortcuts) is split at top-level && (or ||) into clauses, listed in `filter_clauses`.
+    #[serde(default)]
+    explain: bool,
+    /// Also return up to this many variants the filter and shortcuts left out, each with the clauses and checks it failed (in `excluded`). Default 0, at most 20.
+    #[serde(default, deserialize_with = "numeric::deserialize_usize")]
+    explain_excluded: usize,
 }
 
-#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
-struct NextVariantParams {
-    /// Session ID from start_region_query or get_next_variant response
-    session_id: String,
+fn default_region_page_size() -> usize {
+    REGION_PAGE_SIZE
 }
 
-#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
-struct CloseSessionParams {
-    /// Session ID to close
-    session_id: String,
-}
+// Default cap on query_by_region's region size; wider regions need confirm_large_query
+const DEFAULT_MAX_REGION_SIZE: u64 = 10_000;
 
-#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
-struct GetDocumentationParams {
-    /// Which documentation to retrieve: "readme", "streaming", "filters", "streaming-filters", or "all"
-    #[serde(default = "default_doc_type")]
-    doc_type: String,
-}
+// Variants per query_by_region response; larger results continue via next_cursor
+const REGION_PAGE_SIZE: usize = 10_000;
 
-fn default_doc_type() -> String {
-    "readme".to_string()
-}
+// Cap on explain_excluded: a sample to debug a filter with, not a second result list
+const MAX_EXPLAIN_EXCLUDED: usize = 20;
 
-#[derive(Debug, serde::Serialize)]
-struct QueryResult<T>
-where
-    T: serde::Serialize,
-{
-    count: usize,
-    items: Vec<T>,
+// The library query options for a query_by_region call; parameters map onto them one to one
+fn region_query_options(params: &QueryByRegionParams) -> Result<QueryOptions, McpError> {
+    if params.limit == 0 || params.limit > REGION_PAGE_SIZE {
+        return Err(McpError::invalid_params(
+            format!(
+                "limit must be between 1 and {}, got {}",
+                REGION_PAGE_SIZE, params.limit
+            ),
+            None,
+        ));
+    }
+    if params.explain_excluded > MAX_EXPLAIN_EXCLUDED {
+        return Err(McpError::invalid_params(
+            format!(
+                "explain_excluded must be at most {}, got {}",
+                MAX_EXPLAIN_EXCLUDED, params.explain_excluded
+            ),
+            None,
+        ));
+    }
+    let (start, end) = params
+        .coordinate_system
+        .to_vcf_region(params.start, params.end);
+    Ok(QueryOptions {
+        filter: params.shortcuts.combine(&params.filter)?,
+        info_flags: params.has_info_flags.clone(),
+        record_filter: params.shortcuts.record_filter(),
+        info_fields: params.info_fields.clone(),
+        detail: params.detail,
+        samples: params.samples.clone(),
+        sort: params.sort,
+        limit: Some(params.limit),
+        cursor: params
+            .cursor
+            .as_deref()
+            .map(|cursor| parse_region_cursor(cursor, start, end))
+            .transpose()?,
+        coordinates: params.coordinate_system,
+        explain_excluded: params.explain_excluded,
+        present_in_sample: params.present_in_sample.clone(),
+    })
 }
 
-#[derive(Debug, serde::Serialize)]
+// Variant keys a `fields` projection can name; "info.<KEY>" and "samples.<NAME>" name a
+// single INFO field or sample
+const VARIANT_FIELDS: &[&str] = &[
+    "chromosome",
+    "position",
+    "id",
+    "ids",
+    "reference",
+    "alternate",
+    "alternate_alleles",
+    "variant_type",
+    "quality",
+    "filter",
+    "info",
+    "samples",
+    "normalized",
+    "clinvar",
+    "annotation",
+    "vrs",
+    "spdi",
+    "browser_links",
+];
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Deserialize, clap::ValueEnum)]
 #[serde(rename_all = "snake_case")]
-enum QueryStatus {
-    Ok,
-    ChromosomeNotFound,
-    NotFound,
-}
-
-#[derive(Debug, serde::Serialize)]
-struct PositionQuery {
-    chromosome: String,
-    position: u64,
+enum OutputFormat {
+    #[default]
+    Json,
+    // One line of text per variant (vcf::render_compact)
+    Compact,
+    // The records as a VCF file with a minimal header (VcfIndex::render_vcf)
+    Vcf,
+    // A table of the columns `fields` names, tab- or comma-separated (vcf::render_table)
+    Tsv,
+    Csv,
 }
 
-#[derive(Debug, serde::Serialize)]
-struct RegionQuery {
-    chromosome: String,
-    start: u64,
-    end: u64,
+impl OutputFormat {
+    // The `format` argument value
+    fn name(self) -> &'static str {
+        match self {
+            OutputFormat::Json => "json",
+            OutputFormat::Compact => "compact",
+            OutputFormat::Vcf => "vcf",
+            OutputFormat::Tsv => "tsv",
+            OutputFormat::Csv => "csv",
+        }
+    }
 }
 
-#[derive(Debug, serde::Serialize)]
-struct IdQuery {
-    id: String,
+// Columns of the tabular formats when `fields` is omitted
+const DEFAULT_TABLE_FIELDS: &[&str] = &[
+    "chromosome",
+    "position",
+    "id",
+    "reference",
+    "alternate",
+    "quality",
+    "filter",
+];
+
+// The table columns `fields` names; a table cell holds one value, so only single-valued keys,
+// "info.<KEY>" and "samples.<NAME>" (the sample's GT) can be columns
+fn table_columns(
+    index: &VcfIndex,
+    fields: Option<&[String]>,
+) -> Result<Vec<TableColumn>, McpError> {
+    let default_fields: Vec<String> = DEFAULT_TABLE_FIELDS
+        .iter()
+        .map(|field| field.to_string())
+        .collect();
+    let fields = fields
+        .filter(|fields| !fields.is_empty())
+        .unwrap_or(&default_fields);
+    fields
+        .iter()
+        .map(|field| {
+            Ok(match field.split_once('.') {
+                Some(("info", key)) => TableColumn::Info(key.to_string()),
+                Some(("samples", name)) => {
+                    let sample = index.sample_index(name).ok_or_else(|| {
+                        McpError::invalid_params(format!("Sample '{}' not found", name), None)
+                    })?;
+                    TableColumn::Genotype(name.to_string(), sample)
+                }
+                _ => match field.as_str() {
+                    "chromosome" => TableColumn::Chromosome,
+                    "position" => TableColumn::Position,
+                    "id" => TableColumn::Id,
+                    "reference" => TableColumn::Reference,
+                    "alternate" => TableColumn::Alternate,
+                    "quality" => TableColumn::Quality,
+                    "filter" => TableColumn::Filter,
+                    "variant_type" => TableColumn::VariantType,
+                    _ => {
+                        return Err(McpError::invalid_params(
+                            format!(
+                                "'{}' cannot be a table column. Columns: chromosome, position, id, reference, alternate, quality, filter, variant_type, info.<KEY>, samples.<NAME>",
+                                field
+                            ),
+                            None,
+                        ))
+                    }
+                },
+            })
+        })
+        .collect()
 }
 
-#[derive(Debug, serde::Serialize)]
-struct QueryByPositionResponse {
-    status: QueryStatus,
-    reference_genome: String,
-    query: PositionQuery,
-    matched_chromosome: Option<String>,
-    available_chromosomes_sample: Option<Vec<String>>,
-    alternate_chromosome_suggestion: Option<String>,
-    result: QueryResult<Variant>,
+// Compact lines and VCF records have no keys to project, so fields only go with the JSON
+// format and, as the column list, the tabular ones
+fn check_output_format(
+    format: OutputFormat,
+    fields: Option<&[String]>,
+    index: &VcfIndex,
+) -> Result<(), McpError> {
+    let name = match format {
+        OutputFormat::Json => return Ok(()),
+        OutputFormat::Tsv | OutputFormat::Csv => return table_columns(index, fields).map(|_| ()),
+        OutputFormat::Compact => "compact",
+        OutputFormat::Vcf => "vcf",
+    };
+    if fields.is_some_and(|fields| !fields.is_empty()) {
+        return Err(McpError::invalid_params(
+            format!("fields cannot be combined with format \"{}\"", name),
+            None,
+        ));
+    }
+    Ok(())
 }
 
-#[derive(Debug, serde::Serialize)]
-struct QueryByRegionResponse {
-    status: QueryStatus,
-    reference_genome: String,
-    query: RegionQuery,
-    matched_chromosome: Option<String>,
-    available_chromosomes_sample: Option<Vec<String>>,
-    alternate_chromosome_suggestion: Option<String>,
-    result: QueryResult<Variant>,
+fn check_fields(fields: Option<&[String]>) -> Result<(), McpError> {
+    for field in fields.unwrap_or_default() {
+        let valid = match field.split_once('.') {
+            Some((parent, key)) => matches!(parent, "info" | "samples") && !key.is_empty(),
+            None => VARIANT_FIELDS.contains(&field.as_str()),
+        };
+        if !valid {
+            return Err(McpError::invalid_params(
+                format!(
+                    "Unknown field '{}'. Valid fields: {}, info.<KEY>, samples.<NAME>",
+                    field,
+                    VARIANT_FIELDS.join(", ")
+                ),
+                None,
+            ));
+        }
+    }
+    Ok(())
 }
 
-#[derive(Debug, serde::Serialize)]
-struct QueryByIdResponse {
-    status: QueryStatus,
-    reference_genome: String,
-    query: IdQuery,
-    result: QueryResult<Variant>,
+// Add each item's matched_clauses (explain mode) after projection, so fields can't drop them
+fn annotate_matched_clauses(payload: &mut serde_json::Value, matched_clauses: Vec<Vec<String>>) {
+    if matched_clauses.is_empty() {
+        return;
+    }
+    let Some(items) = payload
+        .pointer_mut("/result/items")
+        .and_then(serde_json::Value::as_array_mut)
+    else {
+        return;
+    };
+    for (item, clauses) in items.iter_mut().zip(matched_clauses) {
+        if let Some(variant) = item.as_object_mut() {
+            variant.insert("matched_clauses".to_string(), serde_json::json!(clauses));
+        }
+    }
 }
 
-#[derive(Debug, serde::Serialize)]
-struct StreamQueryResponse {
-    /// Next variant in region, or null if exhausted
-    variant: Option<Variant>,
-    /// Session ID for subsequent calls, or null if query complete
-    session_id: Option<String>,
-    /// Whether more variants exist in this region
-    has_more: bool,
-    reference_genome: String,
-    matched_chromosome: Option<String>,
+// Keep only the requested keys of each variant in a response's result.items; an omitted or
+// empty `fields` keeps everything
+fn project_result_items(payload: &mut serde_json::Value, fields: Option<&[String]>) {
+    let Some(fields) = fields.filter(|fields| !fields.is_empty()) else {
+        return;
+    };
+    let Some(items) = payload
+        .pointer_mut("/result/items")
+        .and_then(serde_json::Value::as_array_mut)
+    else {
+        return;
+    };
+    for item in items {
+        let serde_json::Value::Object(variant) = item.take() else {
+            continue;
+        };
+        let mut projected = serde_json::Map::new();
+        for field in fields {
+            match field.split_once('.') {
+                Some((parent, key)) => {
+                    let Some(value) = variant.get(parent).and_then(|map| map.get(key)) else {
+                        continue;
+                    };
+                    let entry = projected
+                        .entry(parent)
+                        .or_insert_with(|| serde_json::Value::Object(serde_json::Map::new()));
+                    if let Some(map) = entry.as_object_mut() {
+                        map.insert(key.to_string(), value.clone());
+                    }
+                }
+                None => {
+                    if let Some(value) = variant.get(field) {
+                        projected.insert(field.clone(), value.clone());
+                    }
+                }
+            }
+        }
+        *item = serde_json::Value::Object(projected);
+    }
 }
 
-// Store iterator state for a streaming query
-struct QuerySession {
-    chromosome: String,
-    start: u64,
-    end: u64,
-    // Last position returned (to resume from next position)
-    last_position: Option<u64>,
-    created_at: std::time::Instant,
-    // Filter expression to apply to variants
-    filter: String,
-}
-
-// MCP Server implementation
-#[derive(Clone)]
-struct VcfServer {
-    index: Arc<Mutex<VcfIndex>>,
-    #[allow(dead_code)]
-    tool_router: ToolRouter<Self>,
-    debug: bool,
-    // Track active query sessions by session ID
-    query_sessions: Arc<Mutex<HashMap<String, QuerySession>>>,
-}
-
-#[tool_router]
-impl VcfServer {
-    fn new(index: VcfIndex, debug: bool) -> Self {
-        VcfServer {
-            index: Arc::new(Mutex::new(index)),
-            tool_router: Self::tool_router(),
-            debug,
-            query_sessions: Arc::new(Mutex::new(HashMap::new())),
+// Shape a response's result.items for the requested format: projected JSON objects, the
+// compact rendering of `variants` (the items before serialization), or, for vcf, tsv and csv,
+// their records as VCF text in result.vcf or a table in result.table in place of result.items
+fn format_result_items(
+    payload: &mut serde_json::Value,
+    format: OutputFormat,
+    fields: Option<&[String]>,
+    variants: &[Variant],
+    index: &VcfIndex,
+) {
+    match format {
+        OutputFormat::Json => project_result_items(payload, fields),
+        OutputFormat::Compact => {
+            if let Some(items) = payload.pointer_mut("/result/items") {
+                *items = serde_json::json!(variants.iter().map(render_compact).collect::<Vec<_>>());
+            }
+        }
+        OutputFormat::Vcf => {
+            if let Some(result) = payload
+                .pointer_mut("/result")
+                .and_then(serde_json::Value::as_object_mut)
+            {
+                result.remove("items");
+                result.insert(
+                    "vcf".to_string(),
+                    serde_json::json!(index.render_vcf(variants)),
+                );
+            }
+        }
+        OutputFormat::Tsv | OutputFormat::Csv => {
+            // Checked by check_output_format before the query ran
+            let Ok(columns) = table_columns(index, fields) else {
+                return;
+            };
+            let delimiter = if format == OutputFormat::Tsv {
+                '\t'
+            } else {
+                ','
+            };
+            if let Some(result) = payload
+                .pointer_mut("/result")
+                .and_then(serde_json::Value::as_object_mut)
+            {
+                result.remove("items");
+                result.insert(
+                    "table".to_string(),
+                    serde_json::json!(render_table(variants, &columns, delimiter)),
+                );
+            }
         }
     }
+}
 
-    /// Helper method to create a CallToolResult with optional debug logging
-    fn create_result_with_logging(
-        &self,
-        content: Content,
-        start_time: std::time::Instant,
-    ) -> Result<CallToolResult, McpError> {
-        if self.debug {
-            let elapsed = start_time.elapsed();
-            let size = serde_json::to_string(&content)
-                .map(|s| s.len())
-                .unwrap_or(0);
-            eprintln!(
-                "[DEBUG] Response time: {:.2}ms | Response size: {} bytes",
-                elapsed.as_secs_f64() * 1000.0,
-                size
-            );
-        }
-        Ok(CallToolResult::success(vec![content]))
+// Attach left-aligned and trimmed alleles to each variant, whose positions (original and
+// normalized) are in `coordinates`
+fn normalize_variants(
+    reference: Option<&ReferenceFasta>,
+    variants: &mut [Variant],
+    coordinates: CoordinateSystem,
+) {
+    let Some(reference) = reference else {
+        return;
+    };
+    for variant in variants {
+        let (position, _) = coordinates.to_vcf_region(variant.position, variant.position);
+        variant.normalized = reference
+            .normalize(
+                &variant.chromosome,
+                position,
+                &variant.reference,
+                &variant.alternate,
+            )
+            .map(|mut normalized| {
+                normalized.position = coordinates.from_vcf_position(normalized.position);
+                normalized
+            });
     }
+}
 
-    #[tool(
-        description = "Query variants at a specific genomic position. NOTE: Coordinates are genome build-specific (GRCh37 vs GRCh38). Check the reference_genome field in the response to verify which build is being queried."
-    )]
-    async fn query_by_position(
-        &self,
-        Parameters(QueryByPositionParams {
-            chromosome: requested_chromosome,
-            position,
-        }): Parameters<QueryByPositionParams>,
-    ) -> Result<CallToolResult, McpError> {
-        let start_time = std::time::Instant::now();
-        let query_context = PositionQuery {
-            chromosome: requested_chromosome.clone(),
+// Attach the GA4GH VRS Alleles of each variant's ALT alleles, for variants whose positions
+// are in `coordinates`
+fn vrs_variants(vrs: Option<&VrsSource>, variants: &mut [Variant], coordinates: CoordinateSystem) {
+    let Some(vrs) = vrs else {
+        return;
+    };
+    for variant in variants {
+        let (position, _) = coordinates.to_vcf_region(variant.position, variant.position);
+        variant.vrs = vrs.alleles(
+            &variant.chromosome,
             position,
-        };
+            &variant.reference,
+            &variant.alternate,
+        );
+    }
+}
 
-        let response = {
-            let index = self.index.lock().await;
-            let (variants, matched_chr) = index.query_by_position(&requested_chromosome, position);
-            let count = variants.len();
-            let items: Vec<Variant> = variants.into_iter().map(format_variant).collect();
-            let result = QueryResult { count, items };
+// Attach the SPDI expression of each variant's ALT alleles, on the chromosome's RefSeq
+// accession when the file is on GRCh37 or GRCh38; positions are in `coordinates`
+fn spdi_variants(index: &VcfIndex, variants: &mut [Variant], coordinates: CoordinateSystem) {
+    let build = Build::from_name(&index.get_metadata().reference_genome.build);
+    for variant in variants {
+        let (position, _) = coordinates.to_vcf_region(variant.position, variant.position);
+        let sequence = build
+            .and_then(|build| spdi::accession(&variant.chromosome, build))
+            .unwrap_or(variant.chromosome.as_str());
+        variant.spdi = Some(
+            variant
+                .alternate
+                .iter()
+                .map(|alternate| {
+                    Spdi::from_vcf(sequence, position, &variant.reference, alternate)
+                        .map(|spdi| spdi.to_string())
+                })
+                .collect(),
+        );
+    }
+}
 
-            let (status, available_sample, alternate_suggestion) =
-                build_chromosome_response(&index, &requested_chromosome, &matched_chr);
+// Attach IGV.js and UCSC Genome Browser links on `build` to each variant, whose position is in
+// `coordinates`
+fn link_variants(build: Option<Build>, variants: &mut [Variant], coordinates: CoordinateSystem) {
+    let Some(build) = build else {
+        return;
+    };
+    for variant in variants {
+        let (position, _) = coordinates.to_vcf_region(variant.position, variant.position);
+        let end = position + (variant.reference.len() as u64).max(1) - 1;
+        variant.browser_links = Some(browser::links(build, &variant.chromosome, position, end));
+    }
+}
 
-            let reference_genome = index.get_reference_genome();
+// A second VCF whose records annotate query results (--annotation)
+struct AnnotationSource {
+    index: VcfIndex,
+    // INFO fields to take from its records; all when empty
+    fields: Vec<String>,
+}
 
-            QueryByPositionResponse {
-                status,
-                reference_genome,
-                query: query_context,
-                matched_chromosome: matched_chr,
-                available_chromosomes_sample: available_sample,
-                alternate_chromosome_suggestion: alternate_suggestion,
-                result,
-            }
-        };
+// Attach the annotation VCF's matching records to each variant, whose position is in
+// `coordinates`; variants without a match get an empty list
+fn annotate_variants(
+    annotation: Option<&AnnotationSource>,
+    variants: &mut [Variant],
+    coordinates: CoordinateSystem,
+) -> Result<(), ReadFailure> {
+    let Some(annotation) = annotation else {
+        return Ok(());
+    };
+    for variant in variants {
+        let (position, _) = coordinates.to_vcf_region(variant.position, variant.position);
+        variant.annotation = Some(annotation.index.allele_annotations(
+            &variant.chromosome,
+            position,
+            &variant.reference,
+            &variant.alternate,
+            &annotation.fields,
+        )?);
+    }
+    Ok(())
+}
 
-        let payload = serde_json::to_value(response).map_err(|e| {
-            McpError::internal_error(
-                format!("Failed to serialize query_by_position response: {}", e),
+// Parse a query_by_region cursor, which must lie in the queried region
+fn parse_region_cursor(cursor: &str, start: u64, end: u64) -> Result<RegionCursor, McpError> {
+    RegionCursor::parse(cursor)
+        .filter(|parsed| (start..=end).contains(&parsed.position))
+        .ok_or_else(|| {
+            McpError::invalid_params(
+                format!(
+                    "Invalid cursor '{}': pass next_cursor from a previous query_by_region response unchanged",
+                    cursor
+                ),
                 None,
             )
-        })?;
+        })
+}
 
-        let content = Content::json(payload)?;
+// Common filters that can be requested without writing an expression; they are
+// combined with any `filter` expression using &&
+#[derive(Debug, Default, serde::Deserialize, schemars::JsonSchema)]
+struct FilterShortcuts {
+    /// Only variants with INFO AF at or below this allele frequency (0-1); variants without AF are excluded
+    #[serde(default, deserialize_with = "numeric::deserialize_option_f64")]
+    max_af: Option<f64>,
+    /// Only variants with INFO AF at or above this allele frequency (0-1); variants without AF are excluded
+    #[serde(default, deserialize_with = "numeric::deserialize_option_f64")]
+    min_af: Option<f64>,
+    /// Only variants with QUAL at or above this value
+    #[serde(default, deserialize_with = "numeric::deserialize_option_f64")]
+    min_qual: Option<f64>,
+    /// Only variants whose FILTER is PASS
+    #[serde(default)]
+    pass_only: bool,
+    /// Leave out variants whose FILTER lists any of these values (e.g., ["LowQual", "q10"])
+    #[serde(default)]
+    exclude_filters: Vec<String>,
+    /// Only variants of these types: "snp", "insertion", "deletion", "mnp", "sv" (symbolic or breakend ALT) or "complex" (ALT alleles of different kinds)
+    #[serde(default)]
+    #[schemars(with = "Vec<String>")]
+    variant_types: Vec<VariantType>,
+    /// Only variants carrying all of these INFO keys, with any value (e.g., ["CLNSIG"]). Unlike has_info_flags, the keys need not be flags.
+    #[serde(default)]
+    require_info: Vec<String>,
+    /// Only variants whose ClinVar CLNSIG includes Pathogenic or Likely pathogenic (conflicting interpretations don't count); requires CLNSIG in the header
+    #[serde(default)]
+    pathogenic_only: bool,
+}
 
-        self.create_result_with_logging(content, start_time)
-    }
+impl FilterShortcuts {
+    // The filter expression to evaluate: `filter` and every shortcut that was set, joined by &&
+    fn combine(&self, filter: &str) -> Result<String, McpError> {
+        let mut clauses = Vec::new();
+        if !filter.trim().is_empty() {
+            clauses.push(format!("({})", numeric::normalize_filter(filter.trim())));
+        }
 
-    #[tool(
-        description = "Query variants in a genomic region. Maximum region size is 10,000 bp (10 kb). Requests exceeding this limit will be rejected. NOTE: Coordinates are genome build-specific (GRCh37 vs GRCh38). Check the reference_genome field in the response to verify which build is being queried."
-    )]
-    async fn query_by_region(
-        &self,
-        Parameters(QueryByRegionParams {
-            chromosome: requested_chromosome,
-            start,
-            end,
-        }): Parameters<QueryByRegionParams>,
-    ) -> Result<CallToolResult, McpError> {
-        let start_time = std::time::Instant::now();
-        const MAX_WINDOW: u64 = 10000; // 10 kb maximum region size
+        for (name, value) in [("max_af", self.max_af), ("min_af", self.min_af)] {
+            if let Some(af) = value {
+                if !(0.0..=1.0).contains(&af) {
+                    return Err(McpError::invalid_params(
+                        format!("{} must be between 0 and 1, got {}", name, af),
+                        None,
+                    ));
+                }
+            }
+        }
+        if let (Some(min_af), Some(max_af)) = (self.min_af, self.max_af) {
+            if min_af > max_af {
+                return Err(McpError::invalid_params(
+                    format!("min_af ({}) is greater than max_af ({})", min_af, max_af),
+                    None,
+                ));
+            }
+        }
+        if let Some(min_qual) = self.min_qual {
+            if !min_qual.is_finite() || min_qual < 0.0 {
+                return Err(McpError::invalid_params(
+                    format!("min_qual must be a non-negative number, got {}", min_qual),
+                    None,
+                ));
+            }
+            clauses.push(format!("QUAL >= {}", min_qual));
+        }
 
-        // Validate region size
-        if end > start && (end - start) > MAX_WINDOW {
+        if let Some(min_af) = self.min_af {
+            clauses.push(format!("AF >= {}", min_af));
+        }
+        if let Some(max_af) = self.max_af {
+            clauses.push(format!("AF <= {}", max_af));
+        }
+        if self.pass_only {
+            clauses.push("FILTER == \"PASS\"".to_string());
+        }
+        // exclude_filters, variant_types and require_info are not part of the expression:
+        // they are checked per record (see RecordFilter)
+        if self
+            .exclude_filters
+            .iter()
+            .any(|name| name.trim().is_empty())
+        {
             return Err(McpError::invalid_params(
-                format!(
-                    "Requested region too large ({} bp). Maximum window is {} bp.",
-                    end - start,
-                    MAX_WINDOW
-                ),
+                "exclude_filters must not contain empty names",
                 None,
             ));
         }
 
-        let query_context = RegionQuery {
-            chromosome: requested_chromosome.clone(),
-            start,
-            end,
-        };
+        Ok(clauses.join(" && "))
+    }
+
+    // The checks made per record besides the expression
+    fn record_filter(&self) -> RecordFilter {
+        RecordFilter {
+            exclude_filters: self.exclude_filters.clone(),
+            variant_types: self.variant_types.clone(),
+            require_info: self.require_info.clone(),
+            pathogenic_only: self.pathogenic_only,
+        }
+    }
+}
 
-        let response = {
-            let index = self.index.lock().await;
-            let (variants, matched_chr) = index.query_by_region(&requested_chromosome, start, end);
-            let count = variants.len();
-            let items: Vec<Variant> = variants.into_iter().map(format_variant).collect();
-            let result = QueryResult { count, items };
+// Keep the variants passing a combined filter expression and the record filter, for tools
+// that look variants up rather than scan a region
+fn retain_matching(
+    index: &VcfIndex,
+    filter: &str,
+    record_filter: &RecordFilter,
+    variants: &mut Vec<Variant>,
+) {
+    let filter_engine = index.filter_engine();
+    variants.retain(|v| {
+        record_filter.admits(v)
+            && (filter.is_empty() || filter_engine.evaluate(filter, &v.raw_row).unwrap_or(false))
+    });
+}
 
-            let (status, available_sample, alternate_suggestion) =
-                build_chromosome_response(&index, &requested_chromosome, &matched_chr);
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+struct QueryByIdParams {
+    /// Variant ID (e.g., 'rs6054257')
+    id: String,
+    #[serde(flatten)]
+    shortcuts: FilterShortcuts,
+    /// Only include these keys in each variant, e.g. ["chromosome", "position", "id", "info.AF"]. "info.<KEY>" and "samples.<NAME>" select single INFO fields or samples. Omit to include all.
+    #[serde(default)]
+    fields: Option<Vec<String>>,
+    /// Also report each variant's alleles left-aligned and trimmed against the server's reference FASTA (in `normalized`); needs --reference
+    #[serde(default)]
+    normalize: bool,
+    /// Also report each ALT allele as a GA4GH VRS 2.0 Allele with its computed identifier (ga4gh:VA...) for referencing the variant across systems (in `vrs`, null for symbolic alleles); needs --reference on the VCF's build
+    #[serde(default)]
+    vrs: bool,
+    /// Also add IGV.js and UCSC Genome Browser URLs showing each variant on the file's build (in `browser_links`); needs a header naming GRCh37 or GRCh38
+    #[serde(default)]
+    browser_links: bool,
+    /// Also look each variant up in the server's annotation VCF and add the matching records' INFO fields per ALT allele (in `annotation`); needs --annotation
+    #[serde(default)]
+    annotate: bool,
+    /// When an rsID isn't in the file, look up its position on the file's build in dbSNP (NCBI Variation API) and return the file's records there with the same alleles, reported in `remote_resolution`; needs --allow-network
+    #[serde(default)]
+    resolve_via_ncbi: bool,
+    /// "json" (default), "compact", "vcf", "tsv" or "csv" (alias output_format): compact returns each variant in result.items as one line of text, e.g. "20:14370 G>A rs6054257 QUAL=29 PASS AF=0.5 het(NA00002)", to save context; vcf replaces result.items with result.vcf, the matching records as VCF text with a minimal header, ready for bcftools; tsv and csv replace it with result.table, a header row and one row per variant, whose columns are the fields given (default CHROM, POS, ID, REF, ALT, QUAL, FILTER; samples.<NAME> is the sample's GT). fields cannot be combined with compact or vcf.
+    #[serde(default, alias = "output_format")]
+    #[schemars(with = "Option<String>")]
+    format: OutputFormat,
+}
 
-            let reference_genome = index.get_reference_genome();
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+struct QueryBySpdiParams {
+    /// SPDI expression sequence:position:deletion:insertion with a 0-based position, e.g. 'NC_000020.11:14369:G:A'. The sequence is a RefSeq chromosome accession on the file's build or the VCF's chromosome name; the deletion may be given as a number of bases, e.g. 'NC_000020.11:1234567:2:'.
+    spdi: String,
+    #[serde(flatten)]
+    shortcuts: FilterShortcuts,
+    /// Only include these keys in each variant, e.g. ["chromosome", "position", "id", "info.AF"]. "info.<KEY>" and "samples.<NAME>" select single INFO fields or samples. Omit to include all.
+    #[serde(default)]
+    fields: Option<Vec<String>>,
+    /// Also report each variant's alleles left-aligned and trimmed against the server's reference FASTA (in `normalized`); needs --reference
+    #[serde(default)]
+    normalize: bool,
+    /// Also report each ALT allele as a GA4GH VRS 2.0 Allele with its computed identifier (ga4gh:VA...) for referencing the variant across systems (in `vrs`, null for symbolic alleles); needs --reference on the VCF's build
+    #[serde(default)]
+    vrs: bool,
+    /// Also add IGV.js and UCSC Genome Browser URLs showing each variant on the file's build (in `browser_links`); needs a header naming GRCh37 or GRCh38
+    #[serde(default)]
+    browser_links: bool,
+    /// Also look each variant up in the server's annotation VCF and add the matching records' INFO fields per ALT allele (in `annotation`); needs --annotation
+    #[serde(default)]
+    annotate: bool,
+    /// "json" (default), "compact", "vcf", "tsv" or "csv" (alias output_format): compact returns each variant in result.items as one line of text, e.g. "20:14370 G>A rs6054257 QUAL=29 PASS AF=0.5 het(NA00002)", to save context; vcf replaces result.items with result.vcf, the matching records as VCF text with a minimal header, ready for bcftools; tsv and csv replace it with result.table, a header row and one row per variant, whose columns are the fields given (default CHROM, POS, ID, REF, ALT, QUAL, FILTER; samples.<NAME> is the sample's GT). fields cannot be combined with compact or vcf.
+    #[serde(default, alias = "output_format")]
+    #[schemars(with = "Option<String>")]
+    format: OutputFormat,
+}
 
-            QueryByRegionResponse {
-                status,
-                reference_genome,
-                query: query_context,
-                matched_chromosome: matched_chr,
-                available_chromosomes_sample: available_sample,
-                alternate_chromosome_suggestion: alternate_suggestion,
-                result,
-            }
-        };
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+struct SearchIdsParams {
+    /// ID pattern. '*' matches any characters and '?' a single character (e.g., 'rs6054*', 'rs60?0355'). A pattern without wildcards matches IDs starting with it.
+    pattern: String,
+    /// Maximum number of matching IDs to return. Default is 100, maximum is 1000.
+    #[serde(
+        default = "default_search_limit",
+        deserialize_with = "numeric::deserialize_usize"
+    )]
+    limit: usize,
+}
 
-        let payload = serde_json::to_value(response).map_err(|e| {
-            McpError::internal_error(
-                format!("Failed to serialize query_by_region response: {}", e),
-                None,
-            )
-        })?;
+fn default_search_limit() -> usize {
+    100
+}
 
-        let content = Content::json(payload)?;
+const MAX_SEARCH_LIMIT: usize = 1000;
 
-        self.create_result_with_logging(content, start_time)
-    }
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+struct GetHeaderParams {
+    /// Optional search string to filter header lines (e.g., '##INFO', '##contig', '##FILTER'). If provided, only lines containing this string will be returned.
+    #[serde(default)]
+    search: Option<String>,
+    /// Also return the INFO, FORMAT, FILTER and contig definitions among the returned lines as JSON (id, number, type, description, ...) under "definitions". Default false.
+    #[serde(default)]
+    structured: bool,
+}
 
-    #[tool(
-        description = "Query variants by variant ID (e.g., rsID). Check the reference_genome field in the response to verify which genome build the coordinates use."
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+struct GetStatisticsParams {
+    /// Maximum number of chromosomes to include in variants_per_chromosome. Default is 25 (top chromosomes by variant count). Set to 0 to include all chromosomes.
+    #[serde(
+        default = "default_max_chromosomes",
+        deserialize_with = "numeric::deserialize_usize"
     )]
-    async fn query_by_id(
-        &self,
-        Parameters(QueryByIdParams { id: requested_id }): Parameters<QueryByIdParams>,
-    ) -> Result<CallToolResult, McpError> {
-        let start_time = std::time::Instant::now();
-        let response = {
-            let index = self.index.lock().await;
-            let variants = index.query_by_id(&requested_id);
-
-            let count = variants.len();
-            let items: Vec<Variant> = variants.into_iter().map(format_variant).collect();
-            let result = QueryResult { count, items };
+    max_chromosomes: usize,
+}
 
-            let status = if result.count > 0 {
-                QueryStatus::Ok
-            } else {
-                QueryStatus::NotFound
-            };
+fn default_max_chromosomes() -> usize {
+    25
+}
 
-            let reference_genome = index.get_reference_genome();
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+struct QcMetricsParams {
+    /// Chromosome name (e.g., '1', '2', 'X', 'chr1'). Omit chromosome, start and end to report the whole file from the statistics computed at startup.
+    #[serde(default)]
+    chromosome: Option<String>,
+    /// Start position (1-based, inclusive); required with chromosome
+    #[serde(default, deserialize_with = "numeric::deserialize_option_u64")]
+    start: Option<u64>,
+    /// End position (1-based, inclusive); required with chromosome
+    #[serde(default, deserialize_with = "numeric::deserialize_option_u64")]
+    end: Option<u64>,
+    /// "1-based-inclusive" (default: VCF) or "0-based-half-open" (BED: 0-based, end exclusive): how start and end count. Reported positions are 1-based.
+    #[serde(default)]
+    #[schemars(with = "Option<String>")]
+    coordinate_system: CoordinateSystem,
+}
 
-            QueryByIdResponse {
-                status,
-                reference_genome,
-                query: IdQuery {
-                    id: requested_id.clone(),
-                },
-                result,
-            }
-        };
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+struct QualityHistogramParams {
+    /// Chromosome name (e.g., '1', '2', 'X', 'chr1'). Omit chromosome, start and end to read the whole file.
+    #[serde(default)]
+    chromosome: Option<String>,
+    /// Start position (1-based, inclusive); required with chromosome
+    #[serde(default, deserialize_with = "numeric::deserialize_option_u64")]
+    start: Option<u64>,
+    /// End position (1-based, inclusive); required with chromosome
+    #[serde(default, deserialize_with = "numeric::deserialize_option_u64")]
+    end: Option<u64>,
+    /// "1-based-inclusive" (default: VCF) or "0-based-half-open" (BED: 0-based, end exclusive): how start and end count. Reported positions are 1-based.
+    #[serde(default)]
+    #[schemars(with = "Option<String>")]
+    coordinate_system: CoordinateSystem,
+    /// Number of equal-width bins between the lowest and highest value. Default is 20, maximum is 1000.
+    #[serde(
+        default = "default_histogram_bins",
+        deserialize_with = "numeric::deserialize_usize"
+    )]
+    bins: usize,
+    /// Also return a histogram of INFO/DP (site read depth). Default false.
+    #[serde(default)]
+    include_dp: bool,
+}
 
-        let payload = serde_json::to_value(response).map_err(|e| {
-            McpError::internal_error(
-                format!("Failed to serialize query_by_id response: {}", e),
-                None,
-            )
-        })?;
+fn default_histogram_bins() -> usize {
+    20
+}
 
-        let content = Content::json(payload)?;
+const MAX_HISTOGRAM_BINS: usize = 1000;
 
-        self.create_result_with_logging(content, start_time)
-    }
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+struct FilterSummaryParams {
+    /// Chromosome name (e.g., '1', '2', 'X', 'chr1'). Omit chromosome, start and end to report the whole file from the statistics computed at startup.
+    #[serde(default)]
+    chromosome: Option<String>,
+    /// Start position (1-based, inclusive); required with chromosome
+    #[serde(default, deserialize_with = "numeric::deserialize_option_u64")]
+    start: Option<u64>,
+    /// End position (1-based, inclusive); required with chromosome
+    #[serde(default, deserialize_with = "numeric::deserialize_option_u64")]
+    end: Option<u64>,
+    /// "1-based-inclusive" (default: VCF) or "0-based-half-open" (BED: 0-based, end exclusive): how start and end count. Reported positions are 1-based.
+    #[serde(default)]
+    #[schemars(with = "Option<String>")]
+    coordinate_system: CoordinateSystem,
+}
 
-    #[tool(
-        description = "Get the raw VCF file header containing metadata and format definitions. By default, ##contig lines are excluded to reduce clutter. To include contig definitions, use the search parameter with '##contig'. To filter for specific header types, provide a search string (e.g., '##INFO' for INFO definitions, '##FILTER' for filter definitions, '##FORMAT' for format definitions)."
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+struct FrequencySpectrumParams {
+    /// Chromosome name (e.g., '1', '2', 'X', 'chr1'). Omit chromosome, start and end to read the whole file.
+    #[serde(default)]
+    chromosome: Option<String>,
+    /// Start position (1-based, inclusive); required with chromosome
+    #[serde(default, deserialize_with = "numeric::deserialize_option_u64")]
+    start: Option<u64>,
+    /// End position (1-based, inclusive); required with chromosome
+    #[serde(default, deserialize_with = "numeric::deserialize_option_u64")]
+    end: Option<u64>,
+    /// "1-based-inclusive" (default: VCF) or "0-based-half-open" (BED: 0-based, end exclusive): how start and end count. Reported positions are 1-based.
+    #[serde(default)]
+    #[schemars(with = "Option<String>")]
+    coordinate_system: CoordinateSystem,
+    /// Number of equal-width frequency bins. Default is 10, maximum is 1000.
+    #[serde(
+        default = "default_spectrum_bins",
+        deserialize_with = "numeric::deserialize_usize"
     )]
-    async fn get_vcf_header(
-        &self,
-        Parameters(params): Parameters<GetHeaderParams>,
-    ) -> Result<CallToolResult, McpError> {
-        let start_time = std::time::Instant::now();
-        let header_text = {
-            let index = self.index.lock().await;
-            index.get_header_string(params.search.as_deref())
-        };
+    bins: usize,
+    /// Bin the minor allele frequency, min(AF, 1 - AF), over 0-0.5 instead of the ALT allele frequency over 0-1. Default false.
+    #[serde(default)]
+    folded: bool,
+}
 
-        let payload = serde_json::json!({
-            "header": header_text,
-            "line_count": header_text.lines().count(),
-            "search_applied": params.search,
-        });
+fn default_spectrum_bins() -> usize {
+    10
+}
 
-        let content = Content::json(payload)?;
-        self.create_result_with_logging(content, start_time)
-    }
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+struct VariantDensityParams {
+    /// Chromosome name (e.g., '1', '2', 'X', 'chr1')
+    chromosome: String,
+    /// Window width in bp (e.g., 100000)
+    #[serde(deserialize_with = "numeric::deserialize_u64")]
+    window_size: u64,
+    /// Start position (1-based, inclusive). Default is 1.
+    #[serde(default, deserialize_with = "numeric::deserialize_option_u64")]
+    start: Option<u64>,
+    /// End position (1-based, inclusive). Default is the chromosome's length from the header, or its last variant.
+    #[serde(default, deserialize_with = "numeric::deserialize_option_u64")]
+    end: Option<u64>,
+    /// "1-based-inclusive" (default: VCF) or "0-based-half-open" (BED: 0-based, end exclusive): how start and end count. Windows are reported 1-based.
+    #[serde(default)]
+    #[schemars(with = "Option<String>")]
+    coordinate_system: CoordinateSystem,
+}
 
-    #[tool(
-        description = "Get comprehensive summary statistics for the VCF file. Returns variant counts, quality statistics, filter distributions, chromosome information, and variant type breakdown. By default, limits variants_per_chromosome to top 25 chromosomes to reduce response size. Set max_chromosomes=0 to include all chromosomes. Statistics are computed once at server startup and cached for instant retrieval."
+// Windows variant_density returns; wider windows or a narrower region keep under it
+const MAX_DENSITY_WINDOWS: usize = 10_000;
+
+// Densest windows variant_density lists
+const DENSEST_WINDOWS: usize = 5;
+
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+struct SampleQcParams {
+    /// Chromosome name (e.g., '1', '2', 'X', 'chr1'). Omit chromosome, start and end to report the whole file from the statistics computed at startup.
+    #[serde(default)]
+    chromosome: Option<String>,
+    /// Start position (1-based, inclusive); required with chromosome
+    #[serde(default, deserialize_with = "numeric::deserialize_option_u64")]
+    start: Option<u64>,
+    /// End position (1-based, inclusive); required with chromosome
+    #[serde(default, deserialize_with = "numeric::deserialize_option_u64")]
+    end: Option<u64>,
+    /// "1-based-inclusive" (default: VCF) or "0-based-half-open" (BED: 0-based, end exclusive): how start and end count. Reported positions are 1-based.
+    #[serde(default)]
+    #[schemars(with = "Option<String>")]
+    coordinate_system: CoordinateSystem,
+    /// Region queries list sites whose fraction of called genotypes is below this (0.0-1.0). Default is 0.95.
+    #[serde(default, deserialize_with = "numeric::deserialize_option_f64")]
+    min_call_rate: Option<f64>,
+    /// Maximum number of samples to list, highest missing rate first. Default is 1000, maximum is 10,000.
+    #[serde(
+        default = "default_samples_limit",
+        deserialize_with = "numeric::deserialize_usize"
     )]
-    async fn get_statistics(
+    limit: usize,
+    /// Maximum number of low call rate sites to list. Default is 100, maximum is 10,000.
+    #[serde(
+        default = "default_trio_sites_limit",
+        deserialize_with = "numeric::deserialize_usize"
+    )]
+    site_limit: usize,
+}
+
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+struct ExplainFilterParams {
+    /// FILTER value as seen in query results (e.g., 'LowGQX'); several can be given separated by ';'
+    filter: String,
+}
+
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+struct FieldDefinitionsParams {
+    /// Only these field IDs (e.g., ["CSQ", "DP"]); omit to list every definition. IDs are matched case-insensitively when there is no exact match.
+    #[serde(default)]
+    ids: Vec<String>,
+}
+
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+struct GetSamplesParams {
+    /// Number of samples to skip, in header column order. Default is 0; use next_offset from the previous page.
+    #[serde(default, deserialize_with = "numeric::deserialize_usize")]
+    offset: usize,
+    /// Maximum number of sample names to return. Default is 1000, maximum is 10,000.
+    #[serde(
+        default = "default_samples_limit",
+        deserialize_with = "numeric::deserialize_usize"
+    )]
+    limit: usize,
+}
+
+fn default_samples_limit() -> usize {
+    1000
+}
+
+const MAX_SAMPLES_LIMIT: usize = 10_000;
+
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+struct FindCarriersParams {
+    /// Chromosome name (e.g., '1', '2', 'X', 'chr1')
+    chromosome: String,
+    /// Position of the variant (1-based)
+    #[serde(deserialize_with = "numeric::deserialize_u64")]
+    position: u64,
+    /// ALT allele as written in the ALT column (e.g., 'A', 'GTC', '<DEL>'); matched case-insensitively
+    alt: String,
+    /// Maximum number of sample names in each of the heterozygous and homozygous lists. Default is 1000, maximum is 10,000; the counts always cover every sample.
+    #[serde(
+        default = "default_samples_limit",
+        deserialize_with = "numeric::deserialize_usize"
+    )]
+    limit: usize,
+}
+
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+struct FindMendelianErrorsParams {
+    /// Chromosome name (e.g., '1', '2', 'X', 'chr1')
+    chromosome: String,
+    /// Start position (1-based, inclusive)
+    #[serde(deserialize_with = "numeric::deserialize_u64")]
+    start: u64,
+    /// End position (1-based, inclusive)
+    #[serde(deserialize_with = "numeric::deserialize_u64")]
+    end: u64,
+    /// "1-based-inclusive" (default: VCF) or "0-based-half-open" (BED: 0-based, end exclusive): how start and end count. Reported positions are 1-based.
+    #[serde(default)]
+    #[schemars(with = "Option<String>")]
+    coordinate_system: CoordinateSystem,
+    /// Child sample name. Omit to check the trio from the server's --ped file (which must define exactly one trio in the file).
+    #[serde(default)]
+    proband: Option<String>,
+    /// Mother sample name; taken from the --ped file when omitted
+    #[serde(default)]
+    mother: Option<String>,
+    /// Father sample name; taken from the --ped file when omitted
+    #[serde(default)]
+    father: Option<String>,
+    /// Maximum number of inconsistent sites to list. Default is 100, maximum is 10,000; error_count always covers the whole region.
+    #[serde(
+        default = "default_trio_sites_limit",
+        deserialize_with = "numeric::deserialize_usize"
+    )]
+    limit: usize,
+}
+
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+struct FindDeNovoParams {
+    /// Chromosome name (e.g., '1', '2', 'X', 'chr1')
+    chromosome: String,
+    /// Start position (1-based, inclusive)
+    #[serde(deserialize_with = "numeric::deserialize_u64")]
+    start: u64,
+    /// End position (1-based, inclusive)
+    #[serde(deserialize_with = "numeric::deserialize_u64")]
+    end: u64,
+    /// "1-based-inclusive" (default: VCF) or "0-based-half-open" (BED: 0-based, end exclusive): how start and end count. Reported positions are 1-based.
+    #[serde(default)]
+    #[schemars(with = "Option<String>")]
+    coordinate_system: CoordinateSystem,
+    /// Child sample name. Omit to check the trio from the server's --ped file (which must define exactly one trio in the file).
+    #[serde(default)]
+    proband: Option<String>,
+    /// Mother sample name; taken from the --ped file when omitted
+    #[serde(default)]
+    mother: Option<String>,
+    /// Father sample name; taken from the --ped file when omitted
+    #[serde(default)]
+    father: Option<String>,
+    /// Minimum genotype quality (FORMAT GQ) of all three calls. Default is 20; 0 also accepts calls without GQ.
+    #[serde(
+        default = "default_de_novo_min_gq",
+        deserialize_with = "numeric::deserialize_u64"
+    )]
+    min_gq: u64,
+    /// Minimum read depth (FORMAT DP) of all three calls. Default is 10; 0 also accepts calls without DP.
+    #[serde(
+        default = "default_de_novo_min_dp",
+        deserialize_with = "numeric::deserialize_u64"
+    )]
+    min_dp: u64,
+    /// Maximum number of candidates to list. Default is 100, maximum is 10,000; candidate_count always covers the whole region.
+    #[serde(
+        default = "default_trio_sites_limit",
+        deserialize_with = "numeric::deserialize_usize"
+    )]
+    limit: usize,
+}
+
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+struct FindCompoundHetParams {
+    /// Chromosome name (e.g., '1', '2', 'X', 'chr1')
+    chromosome: String,
+    /// Start position (1-based, inclusive), e.g. the gene's start
+    #[serde(deserialize_with = "numeric::deserialize_u64")]
+    start: u64,
+    /// End position (1-based, inclusive), e.g. the gene's end
+    #[serde(deserialize_with = "numeric::deserialize_u64")]
+    end: u64,
+    /// "1-based-inclusive" (default: VCF) or "0-based-half-open" (BED: 0-based, end exclusive): how start and end count. Reported positions are 1-based.
+    #[serde(default)]
+    #[schemars(with = "Option<String>")]
+    coordinate_system: CoordinateSystem,
+    /// Sample name to check
+    sample: String,
+    /// Gene name (e.g., 'BRCA2') to keep only sites annotated with it in the file's ANN/CSQ field, matched case-insensitively. Requires gene annotations in the file.
+    #[serde(default)]
+    gene: Option<String>,
+    /// Maximum number of pairs to list. Default is 100, maximum is 10,000; pair_count always covers the whole region.
+    #[serde(
+        default = "default_trio_sites_limit",
+        deserialize_with = "numeric::deserialize_usize"
+    )]
+    limit: usize,
+}
+
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+struct CompareSamplesParams {
+    /// Chromosome name (e.g., '1', '2', 'X', 'chr1')
+    chromosome: String,
+    /// Start position (1-based, inclusive)
+    #[serde(deserialize_with = "numeric::deserialize_u64")]
+    start: u64,
+    /// End position (1-based, inclusive)
+    #[serde(deserialize_with = "numeric::deserialize_u64")]
+    end: u64,
+    /// "1-based-inclusive" (default: VCF) or "0-based-half-open" (BED: 0-based, end exclusive): how start and end count. Reported positions are 1-based.
+    #[serde(default)]
+    #[schemars(with = "Option<String>")]
+    coordinate_system: CoordinateSystem,
+    /// First sample name (e.g., the normal of a tumor/normal pair)
+    sample_a: String,
+    /// Second sample name
+    sample_b: String,
+    /// Maximum number of discordant sites to list. Default is 100, maximum is 10,000; the counts always cover the whole region.
+    #[serde(
+        default = "default_trio_sites_limit",
+        deserialize_with = "numeric::deserialize_usize"
+    )]
+    limit: usize,
+}
+
+// Heterozygous sites find_compound_het pairs up; the pair count grows with its square
+const MAX_COMPOUND_HET_SITES: usize = 5_000;
+
+fn default_de_novo_min_gq() -> u64 {
+    20
+}
+
+fn default_de_novo_min_dp() -> u64 {
+    10
+}
+
+fn default_trio_sites_limit() -> usize {
+    100
+}
+
+// Sites listed by find_mendelian_errors, find_de_novo, compare_samples and sample_qc, and
+// pairs by find_compound_het
+const MAX_TRIO_SITES_LIMIT: usize = 10_000;
+
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+struct StreamRegionParams {
+    /// Chromosome name (e.g., '1', '2', 'X', 'chr1')
+    chromosome: String,
+    /// Start position (1-based, inclusive)
+    #[serde(deserialize_with = "numeric::deserialize_u64")]
+    start: u64,
+    /// End position (1-based, inclusive)
+    #[serde(deserialize_with = "numeric::deserialize_u64")]
+    end: u64,
+    /// "1-based-inclusive" (default: VCF) or "0-based-half-open" (BED: 0-based, end exclusive): how start and end count. Reported positions are 1-based.
+    #[serde(default)]
+    #[schemars(with = "Option<String>")]
+    coordinate_system: CoordinateSystem,
+    /// Optional filter expression (e.g., "QUAL > 30 AND FILTER == PASS"). Empty or omitted means no filtering.
+    #[serde(default)]
+    filter: String,
+    #[serde(flatten)]
+    shortcuts: FilterShortcuts,
+    /// Sample names whose FORMAT values (e.g., GT, DP) are reported per variant in `samples`
+    #[serde(default)]
+    samples: Vec<String>,
+}
+
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+struct SampleVariantsParams {
+    /// Chromosome name (e.g., '1', '2', 'X', 'chr1')
+    chromosome: String,
+    /// Start position (1-based, inclusive)
+    #[serde(deserialize_with = "numeric::deserialize_u64")]
+    start: u64,
+    /// End position (1-based, inclusive)
+    #[serde(deserialize_with = "numeric::deserialize_u64")]
+    end: u64,
+    /// "1-based-inclusive" (default: VCF) or "0-based-half-open" (BED: 0-based, end exclusive): how start and end count. Reported positions are 1-based.
+    #[serde(default)]
+    #[schemars(with = "Option<String>")]
+    coordinate_system: CoordinateSystem,
+    /// Number of variants to sample. Default is 10, maximum is 1000.
+    #[serde(
+        default = "default_sample_count",
+        deserialize_with = "numeric::deserialize_usize"
+    )]
+    count: usize,
+    /// Random seed. The same seed, region, count and filter always return the same sample. If omitted, a seed is chosen and echoed in the response.
+    #[serde(default, deserialize_with = "numeric::deserialize_option_u64")]
+    seed: Option<u64>,
+    /// Optional filter expression (e.g., "QUAL > 30 && FILTER == \"PASS\""); only passing variants are sampled
+    #[serde(default)]
+    filter: String,
+    #[serde(flatten)]
+    shortcuts: FilterShortcuts,
+    /// Sample names whose FORMAT values (e.g., GT, DP) are reported per variant in `samples`
+    #[serde(default)]
+    samples: Vec<String>,
+    /// Only include these keys in each variant, e.g. ["chromosome", "position", "id", "info.AF"]. "info.<KEY>" and "samples.<NAME>" select single INFO fields or samples. Omit to include all.
+    #[serde(default)]
+    fields: Option<Vec<String>>,
+    /// Also report each variant's alleles left-aligned and trimmed against the server's reference FASTA (in `normalized`); needs --reference
+    #[serde(default)]
+    normalize: bool,
+    /// Also report each ALT allele as a GA4GH VRS 2.0 Allele with its computed identifier (ga4gh:VA...) for referencing the variant across systems (in `vrs`, null for symbolic alleles); needs --reference on the VCF's build
+    #[serde(default)]
+    vrs: bool,
+    /// Also add IGV.js and UCSC Genome Browser URLs showing each variant on the file's build (in `browser_links`); needs a header naming GRCh37 or GRCh38
+    #[serde(default)]
+    browser_links: bool,
+    /// Also look each variant up in the server's annotation VCF and add the matching records' INFO fields per ALT allele (in `annotation`); needs --annotation
+    #[serde(default)]
+    annotate: bool,
+    /// "json" (default), "compact", "vcf", "tsv" or "csv" (alias output_format): compact returns each variant in result.items as one line of text, e.g. "20:14370 G>A rs6054257 QUAL=29 PASS AF=0.5 het(NA00002)", to save context; vcf replaces result.items with result.vcf, the matching records as VCF text with a minimal header, ready for bcftools; tsv and csv replace it with result.table, a header row and one row per variant, whose columns are the fields given (default CHROM, POS, ID, REF, ALT, QUAL, FILTER; samples.<NAME> is the sample's GT). fields cannot be combined with compact or vcf.
+    #[serde(default, alias = "output_format")]
+    #[schemars(with = "Option<String>")]
+    format: OutputFormat,
+}
+
+fn default_sample_count() -> usize {
+    10
+}
+
+const MAX_SAMPLE_COUNT: usize = 1000;
+
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+struct ExportBedParams {
+    /// Chromosome name (e.g., '1', '2', 'X', 'chr1')
+    chromosome: String,
+    /// Start position (1-based, inclusive)
+    #[serde(deserialize_with = "numeric::deserialize_u64")]
+    start: u64,
+    /// End position (1-based, inclusive)
+    #[serde(deserialize_with = "numeric::deserialize_u64")]
+    end: u64,
+    /// "1-based-inclusive" (default: VCF) or "0-based-half-open" (BED: 0-based, end exclusive): how start and end count. The exported BED is 0-based either way.
+    #[serde(default)]
+    #[schemars(with = "Option<String>")]
+    coordinate_system: CoordinateSystem,
+    /// Bases added to both sides of every interval. Default is 0.
+    #[serde(default, deserialize_with = "numeric::deserialize_u64")]
+    padding: u64,
+    /// Merge overlapping or adjacent intervals (after padding) into BED3 lines. Default is false: one BED4 line per variant, named by its ID.
+    #[serde(default)]
+    merge: bool,
+    /// Only export variants carrying all of these INFO flags (e.g., ["DB", "SOMATIC"])
+    #[serde(default)]
+    has_info_flags: Vec<String>,
+    /// Optional filter expression (e.g., "QUAL > 30 && FILTER == \"PASS\""). Empty or omitted means no filtering.
+    #[serde(default)]
+    filter: String,
+    #[serde(flatten)]
+    shortcuts: FilterShortcuts,
+}
+
+const MAX_BED_INTERVALS: usize = 10_000;
+
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+struct ExportMafParams {
+    /// Chromosome name (e.g., '1', '2', 'X', 'chr1')
+    chromosome: String,
+    /// Start position (1-based, inclusive)
+    #[serde(deserialize_with = "numeric::deserialize_u64")]
+    start: u64,
+    /// End position (1-based, inclusive)
+    #[serde(deserialize_with = "numeric::deserialize_u64")]
+    end: u64,
+    /// "1-based-inclusive" (default: VCF) or "0-based-half-open" (BED: 0-based, end exclusive): how start and end count. MAF positions are 1-based either way.
+    #[serde(default)]
+    #[schemars(with = "Option<String>")]
+    coordinate_system: CoordinateSystem,
+    /// Tumor samples to report (e.g., ["TUMOR"]); default is every sample. A row is written for each sample whose GT carries the allele.
+    #[serde(default)]
+    samples: Vec<String>,
+    /// Only export variants carrying all of these INFO flags (e.g., ["SOMATIC"])
+    #[serde(default)]
+    has_info_flags: Vec<String>,
+    /// Optional filter expression (e.g., "QUAL > 30 && FILTER == \"PASS\""). Empty or omitted means no filtering.
+    #[serde(default)]
+    filter: String,
+    #[serde(flatten)]
+    shortcuts: FilterShortcuts,
+}
+
+// Rows one export_maf call returns
+const MAX_MAF_ROWS: usize = 10_000;
+
+const MAF_COLUMNS: &[&str] = &[
+    "Hugo_Symbol",
+    "Chromosome",
+    "Start_Position",
+    "End_Position",
+    "Variant_Classification",
+    "Variant_Type",
+    "Reference_Allele",
+    "Tumor_Seq_Allele2",
+    "Tumor_Sample_Barcode",
+    "HGVSp_Short",
+    "dbSNP_RS",
+];
+
+#[cfg(feature = "parquet-export")]
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+struct ExportParquetParams {
+    /// Chromosome name (e.g., '1', '2', 'X', 'chr1'). Omit chromosome, start and end to export the whole file.
+    #[serde(default)]
+    chromosome: Option<String>,
+    /// Start position (1-based, inclusive); required with chromosome
+    #[serde(default, deserialize_with = "numeric::deserialize_option_u64")]
+    start: Option<u64>,
+    /// End position (1-based, inclusive); required with chromosome
+    #[serde(default, deserialize_with = "numeric::deserialize_option_u64")]
+    end: Option<u64>,
+    /// "1-based-inclusive" (default: VCF) or "0-based-half-open" (BED: 0-based, end exclusive): how start and end count. The position column is 1-based either way.
+    #[serde(default)]
+    #[schemars(with = "Option<String>")]
+    coordinate_system: CoordinateSystem,
+    /// Name of the file to write in the server's output directory, ending in .parquet (default: <chromosome>_<start>-<end>.parquet, or variants.parquet for the whole file)
+    #[serde(default)]
+    output: Option<String>,
+    /// Replace an existing file of that name. Default is false.
+    #[serde(default)]
+    overwrite: bool,
+    /// Optional filter expression (e.g., "QUAL > 30 && FILTER == \"PASS\""). Empty or omitted means no filtering.
+    #[serde(default)]
+    filter: String,
+    #[serde(flatten)]
+    shortcuts: FilterShortcuts,
+}
+
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+struct ExportRegionParams {
+    /// Chromosome name (e.g., '1', '2', 'X', 'chr1')
+    chromosome: String,
+    /// Start position (1-based, inclusive)
+    #[serde(deserialize_with = "numeric::deserialize_u64")]
+    start: u64,
+    /// End position (1-based, inclusive)
+    #[serde(deserialize_with = "numeric::deserialize_u64")]
+    end: u64,
+    /// "1-based-inclusive" (default: VCF) or "0-based-half-open" (BED: 0-based, end exclusive): how start and end count. The written VCF is 1-based either way.
+    #[serde(default)]
+    #[schemars(with = "Option<String>")]
+    coordinate_system: CoordinateSystem,
+    /// Name of the file to write in the server's output directory, ending in .vcf.gz (default: <chromosome>_<start>-<end>.vcf.gz, 1-based)
+    #[serde(default)]
+    output: Option<String>,
+    /// Replace an existing file of that name. Default is false.
+    #[serde(default)]
+    overwrite: bool,
+    /// Only export variants carrying all of these INFO flags (e.g., ["DB", "SOMATIC"])
+    #[serde(default)]
+    has_info_flags: Vec<String>,
+    /// Optional filter expression (e.g., "QUAL > 30 && FILTER == \"PASS\""). Empty or omitted means no filtering.
+    #[serde(default)]
+    filter: String,
+    #[serde(flatten)]
+    shortcuts: FilterShortcuts,
+}
+
+#[cfg(feature = "network")]
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+struct AnnotateViaVepParams {
+    /// Chromosome name (e.g., '1', '2', 'X', 'chr1')
+    chromosome: String,
+    /// Start position (1-based, inclusive)
+    #[serde(deserialize_with = "numeric::deserialize_u64")]
+    start: u64,
+    /// End position (1-based, inclusive)
+    #[serde(deserialize_with = "numeric::deserialize_u64")]
+    end: u64,
+    /// "1-based-inclusive" (default: VCF) or "0-based-half-open" (BED: 0-based, end exclusive): how start and end count. Reported positions are 1-based.
+    #[serde(default)]
+    #[schemars(with = "Option<String>")]
+    coordinate_system: CoordinateSystem,
+    /// Optional filter expression (e.g., "QUAL > 30 && FILTER == \"PASS\""). Empty or omitted means no filtering.
+    #[serde(default)]
+    filter: String,
+    #[serde(flatten)]
+    shortcuts: FilterShortcuts,
+    /// Maximum number of variants to send, in file order. Default is 50, maximum is 200 (one VEP request).
+    #[serde(
+        default = "default_vep_limit",
+        deserialize_with = "numeric::deserialize_usize"
+    )]
+    limit: usize,
+    /// Report only VEP's single most relevant consequence per variant (VEP's pick option) instead of one per overlapping transcript
+    #[serde(default)]
+    pick: bool,
+    /// Ensembl species name. Default is "homo_sapiens".
+    #[serde(default = "default_vep_species")]
+    species: String,
+}
+
+#[cfg(feature = "network")]
+fn default_vep_limit() -> usize {
+    50
+}
+
+#[cfg(feature = "network")]
+fn default_vep_species() -> String {
+    "homo_sapiens".to_string()
+}
+
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+struct QueryByBedParams {
+    /// Inline BED text: one interval per line, "chromosome start end [name]", 0-based and end-exclusive (e.g., "chr20\t14369\t17330\tGENE1")
+    #[serde(default)]
+    bed: Option<String>,
+    /// A BED file instead of inline text, relative to the server's --bed-dir
+    #[serde(default)]
+    path: Option<String>,
+    /// Maximum variants returned per interval. Default is 100, maximum is 10,000.
+    #[serde(
+        default = "default_bed_interval_limit",
+        deserialize_with = "numeric::deserialize_usize"
+    )]
+    limit_per_interval: usize,
+    /// Only return variants carrying all of these INFO flags (e.g., ["DB", "SOMATIC"])
+    #[serde(default)]
+    has_info_flags: Vec<String>,
+    /// Optional filter expression (e.g., "QUAL > 30 && FILTER == \"PASS\""). Empty or omitted means no filtering.
+    #[serde(default)]
+    filter: String,
+    #[serde(flatten)]
+    shortcuts: FilterShortcuts,
+}
+
+fn default_bed_interval_limit() -> usize {
+    100
+}
+
+// Intervals one query_by_bed call may list
+const MAX_BED_QUERY_INTERVALS: usize = 1000;
+
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+struct LiftoverPositionParams {
+    /// Chromosome name on the --chain file's source build (e.g., '1', 'chr1')
+    chromosome: String,
+    /// Position on the source build (1-based)
+    #[serde(deserialize_with = "numeric::deserialize_u64")]
+    position: u64,
+}
+
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+struct ClusterVariantsParams {
+    /// Chromosome name (e.g., '1', '2', 'X', 'chr1')
+    chromosome: String,
+    /// Start position (1-based, inclusive)
+    #[serde(deserialize_with = "numeric::deserialize_u64")]
+    start: u64,
+    /// End position (1-based, inclusive)
+    #[serde(deserialize_with = "numeric::deserialize_u64")]
+    end: u64,
+    /// "1-based-inclusive" (default: VCF) or "0-based-half-open" (BED: 0-based, end exclusive): how start and end count. Reported positions are 1-based.
+    #[serde(default)]
+    #[schemars(with = "Option<String>")]
+    coordinate_system: CoordinateSystem,
+    /// Maximum number of bases between neighbouring variants in the same cluster. Default is 10.
+    #[serde(
+        default = "default_max_gap",
+        deserialize_with = "numeric::deserialize_u64"
+    )]
+    max_gap: u64,
+    /// Only report clusters with at least this many variants. Default is 2.
+    #[serde(
+        default = "default_min_cluster_size",
+        deserialize_with = "numeric::deserialize_usize"
+    )]
+    min_cluster_size: usize,
+    /// Only cluster variants carrying all of these INFO flags (e.g., ["DB", "SOMATIC"])
+    #[serde(default)]
+    has_info_flags: Vec<String>,
+    /// Optional filter expression (e.g., "QUAL > 30 && FILTER == \"PASS\""). Empty or omitted means no filtering.
+    #[serde(default)]
+    filter: String,
+    #[serde(flatten)]
+    shortcuts: FilterShortcuts,
+}
+
+fn default_max_gap() -> u64 {
+    10
+}
+
+fn default_min_cluster_size() -> usize {
+    2
+}
+
+const MAX_CLUSTERS: usize = 1000;
+
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+struct NextVariantParams {
+    /// Session ID from start_region_query or get_next_variant response
+    session_id: String,
+}
+
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+struct CloseSessionParams {
+    /// Session ID to close
+    session_id: String,
+}
+
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+struct GetDocumentationParams {
+    /// Which documentation to retrieve: "readme", "streaming", "filters", "streaming-filters", or "all"
+    #[serde(default = "default_doc_type")]
+    doc_type: String,
+}
+
+fn default_doc_type() -> String {
+    "readme".to_string()
+}
+
+#[derive(Debug, serde::Serialize)]
+struct QueryResult<T>
+where
+    T: serde::Serialize,
+{
+    count: usize,
+    items: Vec<T>,
+}
+
+#[derive(Debug, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+enum QueryStatus {
+    Ok,
+    ChromosomeNotFound,
+    NotFound,
+    IndexBuilding,
+    // The VCF has a header but no variant records, so every query comes back empty
+    EmptyFile,
+    // The region exceeds the size cap; repeat the call with confirm_large_query to run it
+    ConfirmationRequired,
+    // The client cancelled the request before the scan finished; no results are returned
+    Cancelled,
+}
+
+#[derive(Debug, serde::Serialize)]
+struct PositionQuery {
+    chromosome: String,
+    position: u64,
+    window: u64,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    has_info_flags: Vec<String>,
+    // Filter expression built from the shortcut parameters
+    #[serde(skip_serializing_if = "String::is_empty")]
+    filter: String,
+    #[serde(flatten)]
+    record_filter: RecordFilter,
+}
+
+#[derive(Debug, serde::Serialize)]
+struct RegionQuery {
+    chromosome: String,
+    start: u64,
+    end: u64,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    has_info_flags: Vec<String>,
+    // Filter expression applied, including any shortcut parameters
+    #[serde(skip_serializing_if = "String::is_empty")]
+    filter: String,
+    #[serde(flatten)]
+    record_filter: RecordFilter,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mnv_sample: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cursor: Option<String>,
+    limit: usize,
+}
+
+#[derive(Debug, serde::Serialize)]
+struct IdQuery {
+    id: String,
+    // Filter expression built from the shortcut parameters
+    #[serde(skip_serializing_if = "String::is_empty")]
+    filter: String,
+    #[serde(flatten)]
+    record_filter: RecordFilter,
+}
+
+#[derive(Debug, serde::Serialize)]
+struct SpdiQuery {
+    spdi: String,
+    // Where the expression was looked for: the VCF chromosome and the 1-based position of
+    // the first deleted base (or of the base after an insertion)
+    chromosome: String,
+    position: u64,
+    // Filter expression built from the shortcut parameters
+    #[serde(skip_serializing_if = "String::is_empty")]
+    filter: String,
+    #[serde(flatten)]
+    record_filter: RecordFilter,
+}
+
+#[derive(Debug, serde::Serialize)]
+struct SampleQuery {
+    chromosome: String,
+    start: u64,
+    end: u64,
+    count: usize,
+    seed: u64,
+    filter: String,
+    #[serde(flatten)]
+    record_filter: RecordFilter,
+}
+
+#[derive(Debug, serde::Serialize)]
+struct FilterQuery {
+    filter: String,
+}
+
+#[derive(Debug, serde::Serialize)]
+struct IdSearchQuery {
+    pattern: String,
+    limit: usize,
+}
+
+#[derive(Debug, serde::Serialize)]
+struct QueryByPositionResponse {
+    status: QueryStatus,
+    reference_genome: String,
+    query: PositionQuery,
+    matched_chromosome: Option<String>,
+    available_chromosomes_sample: Option<Vec<String>>,
+    alternate_chromosome_suggestion: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    liftover: Option<QueryLiftover>,
+    result: QueryResult<Variant>,
+}
+
+// How liftover moved a query's coordinates to the VCF's build
+#[derive(Debug, serde::Serialize)]
+struct QueryLiftover {
+    /// The --chain file the coordinates were lifted with
+    chain: String,
+    chain_id: String,
+    /// "-" when the region maps to the reverse strand
+    strand: &'static str,
+    /// The coordinates as given, on the chain's source build
+    from: LiftoverRegion,
+    /// The coordinates queried, on the VCF's build
+    to: LiftoverRegion,
+}
+
+#[derive(Debug, serde::Serialize)]
+struct LiftoverRegion {
+    chromosome: String,
+    start: u64,
+    end: u64,
+}
+
+#[derive(Debug, serde::Serialize)]
+struct HasVariantAtResponse {
+    status: QueryStatus,
+    chromosome: String,
+    position: u64,
+    window: u64,
+    matched_chromosome: Option<String>,
+    available_chromosomes_sample: Option<Vec<String>>,
+    alternate_chromosome_suggestion: Option<String>,
+    /// True when a variant starts within window bp of position
+    has_variant: bool,
+    /// Start positions of those variants (at most 100), whether more were left out, and
+    /// whether the ID index answered without reading the VCF
+    #[serde(flatten)]
+    nearby: NearbyVariants,
+}
+
+#[derive(Debug, serde::Serialize)]
+struct FindNearestVariantResponse {
+    /// "not_found" when no variant starts within max_distance bp
+    status: QueryStatus,
+    reference_genome: String,
+    chromosome: String,
+    position: u64,
+    max_distance: u64,
+    matched_chromosome: Option<String>,
+    available_chromosomes_sample: Option<Vec<String>>,
+    alternate_chromosome_suggestion: Option<String>,
+    /// Records at the position, and the closest records below (upstream) and above
+    /// (downstream) it with their distance in bp
+    #[serde(flatten)]
+    nearest: NearestVariants,
+}
+
+#[derive(Debug, serde::Serialize)]
+struct MendelianQuery {
+    chromosome: String,
+    start: u64,
+    end: u64,
+}
+
+#[derive(Debug, serde::Serialize)]
+struct MendelianError {
+    position: u64,
+    id: String,
+    reference: String,
+    alternate: Vec<String>,
+    proband_gt: String,
+    mother_gt: String,
+    father_gt: String,
+    /// novel_allele (the child has an allele neither parent has) or parental_mismatch
+    kind: MendelianCheck,
+}
+
+#[derive(Debug, serde::Serialize)]
+struct MendelianErrorsResponse {
+    status: QueryStatus,
+    reference_genome: String,
+    query: MendelianQuery,
+    matched_chromosome: Option<String>,
+    available_chromosomes_sample: Option<Vec<String>>,
+    alternate_chromosome_suggestion: Option<String>,
+    trio: Trio,
+    /// Sites where all three genotypes are diploid calls without missing alleles
+    sites_checked: u64,
+    /// Sites skipped for a missing, partly missing or non-diploid genotype
+    sites_skipped: u64,
+    error_count: u64,
+    /// True when more inconsistent sites were found than the limit lists
+    truncated: bool,
+    errors: Vec<MendelianError>,
+}
+
+#[derive(Debug, serde::Serialize)]
+struct DeNovoQuery {
+    chromosome: String,
+    start: u64,
+    end: u64,
+    min_gq: u64,
+    min_dp: u64,
+}
+
+// One trio member's call at a site
+#[derive(Debug, serde::Serialize)]
+struct GenotypeCall {
+    gt: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    gq: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    dp: Option<u64>,
+    /// Allelic depths (FORMAT AD) as written, e.g. "12,9"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ad: Option<String>,
+}
+
+impl GenotypeCall {
+    fn of(variant: &Variant, column: usize) -> Self {
+        let value = |key: &str| sample_value(variant, column, key).filter(|field| *field != ".");
+        GenotypeCall {
+            gt: sample_genotype(variant, column).unwrap_or(".").to_string(),
+            gq: value("GQ").and_then(|gq| gq.parse().ok()),
+            dp: value("DP").and_then(|dp| dp.parse().ok()),
+            ad: value("AD").map(str::to_string),
+        }
+    }
+
+    // A minimum of 0 also accepts a call without the value
+    fn meets(&self, min_gq: u64, min_dp: u64) -> bool {
+        (min_gq == 0 || self.gq.is_some_and(|gq| gq >= min_gq as f32))
+            && (min_dp == 0 || self.dp.is_some_and(|dp| dp >= min_dp))
+    }
+}
+
+#[derive(Debug, serde::Serialize)]
+struct DeNovoCandidate {
+    position: u64,
+    id: String,
+    reference: String,
+    alternate: Vec<String>,
+    proband: GenotypeCall,
+    mother: GenotypeCall,
+    father: GenotypeCall,
+}
+
+#[derive(Debug, serde::Serialize)]
+struct DeNovoResponse {
+    status: QueryStatus,
+    reference_genome: String,
+    query: DeNovoQuery,
+    matched_chromosome: Option<String>,
+    available_chromosomes_sample: Option<Vec<String>>,
+    alternate_chromosome_suggestion: Option<String>,
+    trio: Trio,
+    candidate_count: u64,
+    /// Sites where the child has an ALT allele and both parents are hom-ref, left out
+    /// because a call's GQ or DP is below the minimum
+    filtered_by_quality: u64,
+    /// True when more candidates were found than the limit lists
+    truncated: bool,
+    candidates: Vec<DeNovoCandidate>,
+}
+
+#[derive(Debug, serde::Serialize)]
+struct CompoundHetQuery {
+    chromosome: String,
+    start: u64,
+    end: u64,
+    sample: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    gene: Option<String>,
+}
+
+#[derive(Debug, serde::Serialize)]
+struct CompoundHetCandidate {
+    phase: PairPhase,
+    first: HeterozygousSite,
+    second: HeterozygousSite,
+}
+
+#[derive(Debug, serde::Serialize)]
+struct CompoundHetResponse {
+    status: QueryStatus,
+    reference_genome: String,
+    query: CompoundHetQuery,
+    matched_chromosome: Option<String>,
+    available_chromosomes_sample: Option<Vec<String>>,
+    alternate_chromosome_suggestion: Option<String>,
+    /// True when the file's ANN/CSQ annotations were used to pair only sites sharing a
+    /// gene; otherwise every heterozygous site in the region is paired with every other
+    gene_annotated: bool,
+    heterozygous_sites: usize,
+    /// Pairs phased in trans come first, then pairs whose phase is unknown
+    pair_count: usize,
+    /// Pairs phased in cis (both ALT alleles on one haplotype), which leave one copy intact
+    cis_pairs_excluded: usize,
+    /// True when more pairs were found than the limit lists
+    truncated: bool,
+    pairs: Vec<CompoundHetCandidate>,
+}
+
+#[derive(Debug, serde::Serialize)]
+struct CompareSamplesQuery {
+    chromosome: String,
+    start: u64,
+    end: u64,
+    sample_a: String,
+    sample_b: String,
+}
+
+#[derive(Debug, serde::Serialize)]
+struct DiscordantSite {
+    position: u64,
+    id: String,
+    reference: String,
+    alternate: Vec<String>,
+    genotype_a: String,
+    genotype_b: String,
+}
+
+#[derive(Debug, serde::Serialize)]
+struct CompareSamplesResponse {
+    status: QueryStatus,
+    reference_genome: String,
+    query: CompareSamplesQuery,
+    matched_chromosome: Option<String>,
+    available_chromosomes_sample: Option<Vec<String>>,
+    alternate_chromosome_suggestion: Option<String>,
+    /// Sites where both samples have a GT without missing alleles
+    sites_compared: u64,
+    /// Sites skipped because either GT is missing or partly missing
+    sites_skipped: u64,
+    /// Compared sites with the same alleles, ignoring phasing and allele order
+    concordant: u64,
+    discordant: u64,
+    /// concordant / sites_compared; None when no site was compared
+    concordance: Option<f64>,
+    /// Compared sites where either sample carries an ALT allele
+    non_reference_sites: u64,
+    /// Concordance over non_reference_sites only, which shared hom-ref calls cannot inflate
+    non_reference_concordance: Option<f64>,
+    /// True when more discordant sites were found than the limit lists
+    truncated: bool,
+    discordant_sites: Vec<DiscordantSite>,
+}
+
+#[cfg(feature = "network")]
+#[derive(Debug, serde::Serialize)]
+struct VepQuery {
+    chromosome: String,
+    start: u64,
+    end: u64,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    filter: String,
+    #[serde(flatten)]
+    record_filter: RecordFilter,
+    species: String,
+    pick: bool,
+}
+
+#[cfg(feature = "network")]
+#[derive(Debug, serde::Serialize)]
+struct VepAnnotatedVariant {
+    position: u64,
+    id: String,
+    reference: String,
+    alternate: Vec<String>,
+    /// False when VEP returned nothing for the variant, e.g. because REF doesn't match the
+    /// Ensembl reference (a build mismatch) or the chromosome is unknown to Ensembl
+    annotated: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    most_severe_consequence: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    transcript_consequences: Vec<vep::TranscriptConsequence>,
+    /// IDs of known variants at the same location (dbSNP, COSMIC, ...)
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    colocated_ids: Vec<String>,
+    #[serde(skip_serializing)]
+    input: String,
+}
+
+#[cfg(feature = "network")]
+#[derive(Debug, serde::Serialize)]
+struct AnnotateViaVepResponse {
+    status: QueryStatus,
+    reference_genome: String,
+    /// The Ensembl REST server the variants were sent to
+    vep_server: String,
+    query: VepQuery,
+    matched_chromosome: Option<String>,
+    available_chromosomes_sample: Option<Vec<String>>,
+    alternate_chromosome_suggestion: Option<String>,
+    variant_count: usize,
+    annotated_count: usize,
+    /// True when more variants matched than the limit sends
+    truncated: bool,
+    variants: Vec<VepAnnotatedVariant>,
+}
+
+#[derive(Debug, serde::Serialize)]
+struct LiftoverPositionQuery {
+    chromosome: String,
+    position: u64,
+}
+
+#[derive(Debug, serde::Serialize)]
+struct LiftoverMapping {
+    #[serde(flatten)]
+    lifted: LiftedPosition,
+    /// The VCF's name for the lifted chromosome, when the file has it
+    matched_chromosome: Option<String>,
+}
+
+#[derive(Debug, serde::Serialize)]
+struct LiftoverPositionResponse {
+    /// "not_found" when the position falls in a gap of the chain file
+    status: QueryStatus,
+    reference_genome: String,
+    /// The --chain file the position was lifted with
+    chain: String,
+    query: LiftoverPositionQuery,
+    /// Every mapping, highest-scoring chain first; more than one for duplicated sequence
+    mappings: Vec<LiftoverMapping>,
+}
+
+#[derive(Debug, serde::Serialize)]
+struct BedFileQuery {
+    /// "inline", or the path under --bed-dir
+    source: String,
+    limit_per_interval: usize,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    has_info_flags: Vec<String>,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    filter: String,
+    #[serde(flatten)]
+    record_filter: RecordFilter,
+}
+
+#[derive(Debug, serde::Serialize)]
+struct BedIntervalResult {
+    /// The interval as given (0-based, end-exclusive); the variants keep VCF positions
+    #[serde(flatten)]
+    region: bed::BedRegion,
+    /// The file's name for the chromosome; absent when the file doesn't have it
+    matched_chromosome: Option<String>,
+    variant_count: usize,
+    /// True when more variants in the interval match than limit_per_interval
+    truncated: bool,
+    variants: Vec<Variant>,
+}
+
+// Intervals sharing a BED name; unnamed intervals are each a group of their own
+#[derive(Debug, serde::Serialize)]
+struct BedGroup {
+    name: Option<String>,
+    variant_count: usize,
+    intervals: Vec<BedIntervalResult>,
+}
+
+#[derive(Debug, serde::Serialize)]
+struct QueryByBedResponse {
+    status: QueryStatus,
+    reference_genome: String,
+    query: BedFileQuery,
+    interval_count: usize,
+    variant_count: usize,
+    /// Interval chromosomes the file doesn't have
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    unmatched_chromosomes: Vec<String>,
+    groups: Vec<BedGroup>,
+}
+
+#[derive(Debug, serde::Serialize)]
+struct ObservedMarker {
+    chromosome: String,
+    position: u64,
+    /// "id" when the record carries the rsID, "alleles" when a record without it has the marker's REF and ALT at a build's position
+    matched_by: &'static str,
+}
+
+#[derive(Debug, serde::Serialize)]
+struct MarkerCheck {
+    #[serde(flatten)]
+    marker: Marker,
+    /// Where the file has the marker; absent when it doesn't
+    observed: Option<ObservedMarker>,
+    /// The build whose position the marker was found at; absent when it wasn't found, or its rsID is at neither position
+    consistent_with: Option<Build>,
+}
+
+#[derive(Debug, serde::Serialize)]
+struct ValidateBuildResponse {
+    status: QueryStatus,
+    reference_genome: String,
+    /// The build the header's ##reference line or chr1 length names, if recognized
+    header_build: Option<Build>,
+    /// The build the markers' positions point to
+    verdict: Verdict,
+    /// Whether the verdict and header_build agree; absent unless both name a build
+    agrees_with_header: Option<bool>,
+    markers_checked: usize,
+    grch37_matches: usize,
+    grch38_matches: usize,
+    markers: Vec<MarkerCheck>,
+}
+
+// Region of a tool that reports the whole file when no region is given
+#[derive(Debug, serde::Serialize)]
+struct QcRegionQuery {
+    chromosome: String,
+    start: u64,
+    end: u64,
+}
+
+#[derive(Debug, serde::Serialize)]
+struct QcMetricsResponse {
+    status: QueryStatus,
+    /// "file" for whole-file statistics, "region" for a region scan
+    scope: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    query: Option<QcRegionQuery>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    matched_chromosome: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    available_chromosomes_sample: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    alternate_chromosome_suggestion: Option<String>,
+    #[serde(flatten)]
+    counts: QcMetrics,
+    /// Single-base substitution ALT alleles (transitions + transversions)
+    snvs: u64,
+    /// transitions / transversions; None when there are no transversions
+    ts_tv_ratio: Option<f64>,
+    /// heterozygous / homozygous_alt calls; None when there are no hom-alt calls
+    het_hom_ratio: Option<f64>,
+    /// indels / snvs; None when there are no SNVs
+    indel_snp_ratio: Option<f64>,
+}
+
+impl QcMetricsResponse {
+    fn new(scope: &'static str, counts: QcMetrics) -> Self {
+        let ratio = |part: u64, total: u64| (total > 0).then(|| part as f64 / total as f64);
+        QcMetricsResponse {
+            status: QueryStatus::Ok,
+            scope,
+            query: None,
+            matched_chromosome: None,
+            available_chromosomes_sample: None,
+            alternate_chromosome_suggestion: None,
+            snvs: counts.snvs(),
+            ts_tv_ratio: ratio(counts.transitions, counts.transversions),
+            het_hom_ratio: ratio(counts.heterozygous, counts.homozygous_alt),
+            indel_snp_ratio: ratio(counts.indels, counts.snvs()),
+            counts,
+        }
+    }
+}
+
+#[derive(Debug, serde::Serialize)]
+struct QualityHistogramResponse {
+    status: QueryStatus,
+    /// "file" for a whole-file scan, "region" for a region scan
+    scope: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    query: Option<QcRegionQuery>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    matched_chromosome: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    available_chromosomes_sample: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    alternate_chromosome_suggestion: Option<String>,
+    records: u64,
+    /// Records with QUAL '.'
+    missing_qual: u64,
+    /// None when no record has a QUAL
+    qual: Option<Histogram>,
+    /// With include_dp: records without a numeric INFO/DP
+    #[serde(skip_serializing_if = "Option::is_none")]
+    missing_dp: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    dp: Option<Histogram>,
+}
+
+// QUAL and INFO/DP values collected for quality_histogram
+#[derive(Default)]
+struct QualityValues {
+    records: u64,
+    qual: Vec<f64>,
+    dp: Vec<f64>,
+}
+
+impl QualityValues {
+    fn add(&mut self, variant: &Variant, include_dp: bool) {
+        self.records += 1;
+        if let Some(quality) = variant.quality {
+            self.qual.push(quality as f64);
+        }
+        if !include_dp {
+            return;
+        }
+        if let Some(dp) = variant.info.get("DP").and_then(|dp| dp.as_f64()) {
+            self.dp.push(dp);
+        }
+    }
+
+    fn into_response(
+        mut self,
+        scope: &'static str,
+        bins: usize,
+        include_dp: bool,
+    ) -> QualityHistogramResponse {
+        QualityHistogramResponse {
+            status: QueryStatus::Ok,
+            scope,
+            query: None,
+            matched_chromosome: None,
+            available_chromosomes_sample: None,
+            alternate_chromosome_suggestion: None,
+            records: self.records,
+            missing_qual: self.records - self.qual.len() as u64,
+            qual: Histogram::of(&mut self.qual, bins),
+            missing_dp: include_dp.then(|| self.records - self.dp.len() as u64),
+            dp: Histogram::of(&mut self.dp, bins),
+        }
+    }
+}
+
+// One FILTER value of filter_summary with the variant types of its records
+#[derive(Debug, serde::Serialize)]
+struct FilterBreakdown {
+    filter: String,
+    /// The ##FILTER description (PASS and "." need none)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    description: Option<String>,
+    count: u64,
+    /// count / records
+    fraction: f64,
+    #[serde(flatten)]
+    variant_types: VariantTypeStats,
+}
+
+#[derive(Debug, serde::Serialize)]
+struct FilterSummaryResponse {
+    status: QueryStatus,
+    /// "file" for whole-file statistics, "region" for a region scan
+    scope: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    query: Option<QcRegionQuery>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    matched_chromosome: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    available_chromosomes_sample: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    alternate_chromosome_suggestion: Option<String>,
+    records: u64,
+    /// Most common first; a record failing several filters counts under each, and records
+    /// without a FILTER value under "."
+    filters: Vec<FilterBreakdown>,
+}
+
+impl FilterSummaryResponse {
+    fn new(
+        index: &VcfIndex,
+        scope: &'static str,
+        records: u64,
+        counts: HashMap<String, VariantTypeStats>,
+    ) -> Self {
+        let mut filters: Vec<FilterBreakdown> = counts
+            .into_iter()
+            .map(|(filter, variant_types)| {
+                let count = variant_types.total();
+                FilterBreakdown {
+                    description: index
+                        .explain_filter(&filter)
+                        .into_iter()
+                        .next()
+                        .and_then(|explanation| explanation.description),
+                    filter,
+                    count,
+                    fraction: if records > 0 {
+                        count as f64 / records as f64
+                    } else {
+                        0.0
+                    },
+                    variant_types,
+                }
+            })
+            .collect();
+        filters.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.filter.cmp(&b.filter)));
+        FilterSummaryResponse {
+            status: QueryStatus::Ok,
+            scope,
+            query: None,
+            matched_chromosome: None,
+            available_chromosomes_sample: None,
+            alternate_chromosome_suggestion: None,
+            records,
+            filters,
+        }
+    }
+}
+
+#[derive(Debug, serde::Serialize)]
+struct FrequencySpectrumResponse {
+    status: QueryStatus,
+    /// "file" for a whole-file scan, "region" for a region scan
+    scope: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    query: Option<QcRegionQuery>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    matched_chromosome: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    available_chromosomes_sample: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    alternate_chromosome_suggestion: Option<String>,
+    records: u64,
+    #[serde(flatten)]
+    spectrum: FrequencySpectrum,
+}
+
+impl FrequencySpectrumResponse {
+    fn new(scope: &'static str, records: u64, spectrum: FrequencySpectrum) -> Self {
+        FrequencySpectrumResponse {
+            status: QueryStatus::Ok,
+            scope,
+            query: None,
+            matched_chromosome: None,
+            available_chromosomes_sample: None,
+            alternate_chromosome_suggestion: None,
+            records,
+            spectrum,
+        }
+    }
+}
+
+#[derive(Debug, serde::Serialize)]
+struct DensityQuery {
+    chromosome: String,
+    window_size: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    start: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    end: Option<u64>,
+}
+
+#[derive(Debug, serde::Serialize)]
+struct DensityWindow {
+    start: u64,
+    end: u64,
+    count: u64,
+}
+
+#[derive(Debug, serde::Serialize)]
+struct VariantDensityResponse {
+    status: QueryStatus,
+    reference_genome: String,
+    query: DensityQuery,
+    matched_chromosome: Option<String>,
+    available_chromosomes_sample: Option<Vec<String>>,
+    alternate_chromosome_suggestion: Option<String>,
+    /// The region covered, after filling in defaults
+    #[serde(skip_serializing_if = "Option::is_none")]
+    start: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    end: Option<u64>,
+    /// Distinct variant positions in the region (records sharing a position count once)
+    total: u64,
+    window_count: usize,
+    mean_per_window: f64,
+    empty_windows: usize,
+    /// The windows with the most variants, densest first
+    densest: Vec<DensityWindow>,
+    /// Count per window in order: window i covers start + i * window_size up to the next
+    /// window, the last ending at end
+    counts: Vec<u64>,
+    /// Counted from the ID index's variant positions rather than by reading the VCF
+    from_index: bool,
+}
+
+#[derive(Debug, serde::Serialize)]
+struct SampleQcQuery {
+    chromosome: String,
+    start: u64,
+    end: u64,
+    min_call_rate: f64,
+}
+
+#[derive(Debug, serde::Serialize)]
+struct SampleMissingness {
+    sample: String,
+    missing: u64,
+    missing_rate: f64,
+}
+
+#[derive(Debug, serde::Serialize)]
+struct LowCallRateSite {
+    position: u64,
+    id: String,
+    call_rate: f64,
+}
+
+#[derive(Debug, serde::Serialize)]
+struct SampleQcResponse {
+    status: QueryStatus,
+    /// "file" for whole-file statistics, "region" for a region scan
+    scope: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    query: Option<SampleQcQuery>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    matched_chromosome: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    available_chromosomes_sample: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    alternate_chromosome_suggestion: Option<String>,
+    /// Records with a GT field
+    sites: u64,
+    sample_count: usize,
+    /// Mean fraction of samples called per site; None without sites
+    mean_call_rate: Option<f64>,
+    /// Sites by call rate: bin i holds rates in [i/10, (i+1)/10), the last bin also 1.0
+    site_call_rate_bins: [u64; 10],
+    /// Highest missing rate first
+    samples: Vec<SampleMissingness>,
+    samples_truncated: bool,
+    /// Region scans only: sites below min_call_rate, in position order
+    #[serde(skip_serializing_if = "Option::is_none")]
+    low_call_rate_site_count: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    low_call_rate_sites: Option<Vec<LowCallRateSite>>,
+}
+
+impl SampleQcResponse {
+    // Whole-file or region report from genotype call counts, listing up to `limit` samples
+    fn new(
+        index: &VcfIndex,
+        scope: &'static str,
+        call_rates: &CallRateStats,
+        limit: usize,
+    ) -> Self {
+        let names = index.sample_names(0, call_rates.missing_per_sample.len());
+        let rate = |part: u64| {
+            if call_rates.sites > 0 {
+                part as f64 / call_rates.sites as f64
+            } else {
+                0.0
+            }
+        };
+        let mut samples: Vec<SampleMissingness> = names
+            .into_iter()
+            .zip(&call_rates.missing_per_sample)
+            .map(|(sample, &missing)| SampleMissingness {
+                sample,
+                missing,
+                missing_rate: rate(missing),
+            })
+            .collect();
+        samples.sort_by_key(|sample| std::cmp::Reverse(sample.missing));
+        let sample_count = samples.len();
+        samples.truncate(limit);
+        let total_missing: u64 = call_rates.missing_per_sample.iter().sum();
+        SampleQcResponse {
+            status: QueryStatus::Ok,
+            scope,
+            query: None,
+            matched_chromosome: None,
+            available_chromosomes_sample: None,
+            alternate_chromosome_suggestion: None,
+            sites: call_rates.sites,
+            sample_count,
+            mean_call_rate: (call_rates.sites > 0 && sample_count > 0).then(|| {
+                1.0 - total_missing as f64 / (call_rates.sites * sample_count as u64) as f64
+            }),
+            site_call_rate_bins: call_rates.site_call_rate_bins,
+            samples_truncated: sample_count > samples.len(),
+            samples,
+            low_call_rate_site_count: None,
+            low_call_rate_sites: None,
+        }
+    }
+}
+
+#[derive(Debug, serde::Serialize)]
+struct CarrierQuery {
+    chromosome: String,
+    position: u64,
+    alt: String,
+}
+
+#[derive(Debug, serde::Serialize)]
+struct FindCarriersResponse {
+    status: QueryStatus,
+    reference_genome: String,
+    query: CarrierQuery,
+    matched_chromosome: Option<String>,
+    available_chromosomes_sample: Option<Vec<String>>,
+    alternate_chromosome_suggestion: Option<String>,
+    /// REF allele of the record carrying the requested ALT
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reference: Option<String>,
+    /// Alleles ("REF>ALT") of the records starting at the position, listed when none has
+    /// the requested ALT
+    #[serde(skip_serializing_if = "Option::is_none")]
+    alleles_at_position: Option<Vec<String>>,
+    /// Sample columns in the file
+    total_samples: usize,
+    /// Carrier counts and sample lists by zygosity, when the allele was found
+    #[serde(flatten)]
+    carriers: Option<AlleleCarriers>,
+}
+
+#[derive(Debug, serde::Serialize)]
+struct QueryByRegionResponse {
+    status: QueryStatus,
+    reference_genome: String,
+    query: RegionQuery,
+    matched_chromosome: Option<String>,
+    available_chromosomes_sample: Option<Vec<String>>,
+    alternate_chromosome_suggestion: Option<String>,
+    result: QueryResult<Variant>,
+    /// Phased MNVs of the requested sample; the component SNVs are still listed in result
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mnvs: Option<Vec<MergedMnv>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    liftover: Option<QueryLiftover>,
+    /// Set with status confirmation_required: how large the region is and what scanning it would cost
+    #[serde(skip_serializing_if = "Option::is_none")]
+    confirmation: Option<LargeQueryConfirmation>,
+    /// Pass as `cursor` to fetch the next page; absent on the last page
+    #[serde(skip_serializing_if = "Option::is_none")]
+    next_cursor: Option<String>,
+    /// Explain mode: the clauses the filter was split into
+    #[serde(skip_serializing_if = "Option::is_none")]
+    filter_clauses: Option<Vec<String>>,
+    /// With explain_excluded: variants the filters left out and why
+    #[serde(skip_serializing_if = "Option::is_none")]
+    excluded: Option<Vec<ExcludedVariant>>,
+    // Explain mode: the clauses each item matched, added to the items once projected
+    #[serde(skip)]
+    matched_clauses: Vec<Vec<String>>,
+}
+
+#[derive(Debug, serde::Serialize)]
+struct LargeQueryConfirmation {
+    region_size_bp: u64,
+    max_region_size_bp: u64,
+    #[serde(flatten)]
+    estimate: RegionEstimate,
+    message: String,
+}
+
+#[derive(Debug, serde::Serialize)]
+struct SampleVariantsResponse {
+    status: QueryStatus,
+    reference_genome: String,
+    query: SampleQuery,
+    matched_chromosome: Option<String>,
+    available_chromosomes_sample: Option<Vec<String>>,
+    alternate_chromosome_suggestion: Option<String>,
+    // Number of variants in the region (after filtering) the sample was drawn from
+    population: usize,
+    result: QueryResult<Variant>,
+}
+
+#[derive(Debug, serde::Serialize)]
+struct BedQuery {
+    chromosome: String,
+    start: u64,
+    end: u64,
+    padding: u64,
+    merge: bool,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    has_info_flags: Vec<String>,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    filter: String,
+    #[serde(flatten)]
+    record_filter: RecordFilter,
+}
+
+#[derive(Debug, serde::Serialize)]
+struct ExportBedResponse {
+    status: QueryStatus,
+    reference_genome: String,
+    query: BedQuery,
+    matched_chromosome: Option<String>,
+    available_chromosomes_sample: Option<Vec<String>>,
+    alternate_chromosome_suggestion: Option<String>,
+    variant_count: usize,
+    interval_count: usize,
+    /// True when more intervals than the maximum were produced and the rest were left out
+    truncated: bool,
+    /// BED text (0-based, end-exclusive coordinates), one interval per line
+    bed: String,
+}
+
+#[derive(Debug, serde::Serialize)]
+struct ExportRegionQuery {
+    chromosome: String,
+    start: u64,
+    end: u64,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    has_info_flags: Vec<String>,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    filter: String,
+    #[serde(flatten)]
+    record_filter: RecordFilter,
+}
+
+#[derive(Debug, serde::Serialize)]
+struct ExportRegionResponse {
+    status: QueryStatus,
+    reference_genome: String,
+    query: ExportRegionQuery,
+    matched_chromosome: Option<String>,
+    available_chromosomes_sample: Option<Vec<String>>,
+    alternate_chromosome_suggestion: Option<String>,
+    /// The bgzipped VCF written on the server; absent when the query was cancelled
+    #[serde(skip_serializing_if = "Option::is_none")]
+    path: Option<String>,
+    /// Its tabix index
+    #[serde(skip_serializing_if = "Option::is_none")]
+    index_path: Option<String>,
+    /// Records written
+    record_count: usize,
+}
+
+#[cfg(feature = "parquet-export")]
+#[derive(Debug, serde::Serialize)]
+struct ExportParquetQuery {
+    /// "file" or "region"
+    scope: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    chromosome: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    start: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    end: Option<u64>,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    filter: String,
+    #[serde(flatten)]
+    record_filter: RecordFilter,
+}
+
+#[cfg(feature = "parquet-export")]
+#[derive(Debug, serde::Serialize)]
+struct ExportParquetResponse {
+    status: QueryStatus,
+    reference_genome: String,
+    query: ExportParquetQuery,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    matched_chromosome: Option<String>,
+    /// The Parquet file written on the server; absent when the export was cancelled
+    #[serde(skip_serializing_if = "Option::is_none")]
+    path: Option<String>,
+    /// Rows written, one per record
+    row_count: usize,
+    /// The file's columns with their Arrow types
+    columns: Vec<ParquetColumn>,
+}
+
+#[derive(Debug, serde::Serialize)]
+struct MafQuery {
+    chromosome: String,
+    start: u64,
+    end: u64,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    samples: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    has_info_flags: Vec<String>,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    filter: String,
+    #[serde(flatten)]
+    record_filter: RecordFilter,
+}
+
+#[derive(Debug, serde::Serialize)]
+struct ExportMafResponse {
+    status: QueryStatus,
+    reference_genome: String,
+    query: MafQuery,
+    matched_chromosome: Option<String>,
+    available_chromosomes_sample: Option<Vec<String>>,
+    alternate_chromosome_suggestion: Option<String>,
+    variant_count: usize,
+    row_count: usize,
+    /// True when more rows than the maximum were produced and the rest were left out
+    truncated: bool,
+    /// Tab-separated MAF text: a "#version 2.4" line, the column names, then one row per ALT allele and carrier sample
+    maf: String,
+}
+
+#[derive(Debug, serde::Serialize)]
+struct ClusterQuery {
+    chromosome: String,
+    start: u64,
+    end: u64,
+    max_gap: u64,
+    min_cluster_size: usize,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    has_info_flags: Vec<String>,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    filter: String,
+    #[serde(flatten)]
+    record_filter: RecordFilter,
+}
+
+#[derive(Debug, serde::Serialize)]
+struct ClusterVariantsResponse {
+    status: QueryStatus,
+    reference_genome: String,
+    query: ClusterQuery,
+    matched_chromosome: Option<String>,
+    available_chromosomes_sample: Option<Vec<String>>,
+    alternate_chromosome_suggestion: Option<String>,
+    variant_count: usize,
+    cluster_count: usize,
+    /// True when more clusters than the maximum were found and the rest were left out
+    truncated: bool,
+    clusters: Vec<VariantCluster>,
+}
+
+#[derive(Debug, serde::Serialize)]
+struct QueryByIdResponse {
+    status: QueryStatus,
+    reference_genome: String,
+    query: IdQuery,
+    // Build progress while the ID index is not ready yet
+    #[serde(skip_serializing_if = "Option::is_none")]
+    index_build: Option<IdIndexStatus>,
+    // Set when the ID was missing from the file and resolve_via_ncbi looked it up in dbSNP
+    #[cfg(feature = "network")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    remote_resolution: Option<RemoteIdResolution>,
+    result: QueryResult<Variant>,
+}
+
+#[derive(Debug, serde::Serialize)]
+struct QueryBySpdiResponse {
+    status: QueryStatus,
+    reference_genome: String,
+    query: SpdiQuery,
+    matched_chromosome: Option<String>,
+    available_chromosomes_sample: Option<Vec<String>>,
+    alternate_chromosome_suggestion: Option<String>,
+    result: QueryResult<Variant>,
+}
+
+#[cfg(feature = "network")]
+#[derive(Debug, serde::Serialize)]
+struct RemoteIdResolution {
+    /// Where the coordinates came from
+    source: &'static str,
+    url: String,
+    /// dbSNP's placement of the rsID on the file's build; None when dbSNP doesn't know it
+    placement: Option<dbsnp::Placement>,
+    /// Records at the placement whose alleles match (returned in result), ignoring their IDs
+    matched_records: usize,
+}
+
+#[derive(Debug, serde::Serialize)]
+struct ExplainFilterResponse {
+    status: QueryStatus,
+    query: FilterQuery,
+    filters: Vec<FilterExplanation>,
+    /// Records in the file, the denominator of each fraction
+    #[serde(skip_serializing_if = "Option::is_none")]
+    total_variants: Option<u64>,
+    /// True while statistics (and so the counts) are still being computed
+    counts_pending: bool,
+    /// The header's FILTER names, listed when a requested one is not declared
+    #[serde(skip_serializing_if = "Option::is_none")]
+    declared_filters: Option<Vec<String>>,
+}
+
+#[derive(Debug, serde::Serialize)]
+struct FieldDefinitionsResponse {
+    status: QueryStatus,
+    count: usize,
+    definitions: Vec<HeaderDefinition>,
+    /// Requested IDs the header does not define
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    not_found: Vec<String>,
+}
+
+#[derive(Debug, serde::Serialize)]
+struct GetSamplesResponse {
+    status: QueryStatus,
+    /// Sample columns in the file
+    total_samples: usize,
+    offset: usize,
+    count: usize,
+    samples: Vec<String>,
+    /// offset of the next page; absent on the last one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    next_offset: Option<usize>,
+}
+
+#[derive(Debug, serde::Serialize)]
+struct GetContigsResponse {
+    status: QueryStatus,
+    count: usize,
+    /// Name, declared length (null when the header gives none) and record count of each
+    /// contig, in natural order
+    contigs: Vec<ContigSummary>,
+    /// True while statistics (and so the counts) are still being computed
+    counts_pending: bool,
+}
+
+#[derive(Debug, serde::Serialize)]
+struct SearchIdsResponse {
+    status: QueryStatus,
+    reference_genome: String,
+    query: IdSearchQuery,
+    /// True when more IDs matched than the limit allowed
+    truncated: bool,
+    /// Build progress while the ID index is not ready yet
+    #[serde(skip_serializing_if = "Option::is_none")]
+    index_build: Option<IdIndexStatus>,
+    result: QueryResult<IdMatch>,
+}
+
+#[derive(Debug, serde::Serialize)]
+struct StreamQueryResponse {
+    /// Next variant in region, or null if exhausted
+    variant: Option<Variant>,
+    /// Session ID for subsequent calls, or null if query complete
+    session_id: Option<String>,
+    /// Whether more variants exist in this region
+    has_more: bool,
+    reference_genome: String,
+    matched_chromosome: Option<String>,
+    /// Set to empty_file when the VCF has no variant records
+    #[serde(skip_serializing_if = "Option::is_none")]
+    status: Option<QueryStatus>,
+}
+
+// What a long-running tool call takes from its request: where to send progress
+// notifications, if the client asked for them with a progress token in _meta, and the
+// token rmcp cancels when the client sends notifications/cancelled for the request
+#[derive(Default)]
+struct CallContext {
+    progress: Option<(ProgressToken, Peer<RoleServer>)>,
+    cancellation: CancellationToken,
+    scanned: ScanCounter,
+}
+
+// Records read by a tool call's scans, for its log entry. call_tool puts one in the request's
+// extensions; calls made without one count into a throwaway
+#[derive(Debug, Clone, Default)]
+struct ScanCounter(Arc<std::sync::atomic::AtomicU64>);
+
+impl ScanCounter {
+    fn get(&self) -> u64 {
+        self.0.load(std::sync::atomic::Ordering::Relaxed)
+    }
+}
+
+impl CallContext {
+    fn from_request(context: &RequestContext<RoleServer>) -> Self {
+        CallContext {
+            progress: context
+                .meta
+                .get_progress_token()
+                .map(|token| (token, context.peer.clone())),
+            cancellation: context.ct.clone(),
+            scanned: context
+                .extensions
+                .get::<ScanCounter>()
+                .cloned()
+                .unwrap_or_default(),
+        }
+    }
+}
+
+// Minimum time between progress notifications for one query
+const PROGRESS_INTERVAL: std::time::Duration = std::time::Duration::from_millis(500);
+
+// Handed to query work running on the blocking pool: forwards throttled progress updates
+// to the async side (or drops them when the client did not ask for progress) and tells the
+// work when the request was cancelled
+struct CallMonitor {
+    progress: Option<(
+        ProgressToken,
+        tokio::sync::mpsc::UnboundedSender<ProgressNotificationParam>,
+    )>,
+    cancellation: CancellationToken,
+    last_sent: std::time::Instant,
+    scanned: ScanCounter,
+}
+
+impl CallMonitor {
+    fn report(&mut self, progress: f64, total: f64, message: impl FnOnce() -> String) {
+        let Some((token, sender)) = &self.progress else {
+            return;
+        };
+        if self.last_sent.elapsed() < PROGRESS_INTERVAL {
+            return;
+        }
+        self.last_sent = std::time::Instant::now();
+        let _ = sender.send(ProgressNotificationParam {
+            progress_token: token.clone(),
+            progress,
+            total: Some(total),
+            message: Some(message()),
+        });
+    }
+
+    // Count a record read by a scan; Break once the request was cancelled
+    fn scanned(&self) -> ControlFlow<()> {
+        self.scanned
+            .0
+            .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        self.proceed()
+    }
+
+    // Whether a scan should carry on: Break once the request was cancelled
+    fn proceed(&self) -> ControlFlow<()> {
+        if self.cancellation.is_cancelled() {
+            ControlFlow::Break(())
+        } else {
+            ControlFlow::Continue(())
+        }
+    }
+}
+
+// Store iterator state for a streaming query
+struct QuerySession {
+    chromosome: String,
+    start: u64,
+    end: u64,
+    // Where the next get_next_variant call resumes
+    cursor: RegionCursor,
+    created_at: std::time::Instant,
+    // Filter expression to apply to variants
+    filter: String,
+    // Per-record checks besides the filter expression
+    record_filter: RecordFilter,
+    // Samples (name and column) whose FORMAT values each variant reports
+    samples: Vec<(String, usize)>,
+}
+
+// Tools that report per-sample data, removed by --hide-genotypes
+const GENOTYPE_TOOLS: [&str; 9] = [
+    "find_carriers",
+    "find_mendelian_errors",
+    "find_de_novo",
+    "find_compound_het",
+    "compare_samples",
+    "get_samples",
+    "sample_qc",
+    "get_format_field_definitions",
+    "export_maf",
+];
+
+// Remove GENOTYPE_TOOLS from a tool router (shared with supervisor mode)
+fn hide_genotype_tools(router: &mut ToolRouter<VcfServer>) {
+    for name in GENOTYPE_TOOLS {
+        router.remove_route(name);
+    }
+}
+
+// The tool whose data a resource URI (or URI template) serves; resources are withdrawn along
+// with their tool, so disabling a tool locks its data away on every path
+fn mirrored_tool(uri: &str) -> Option<&'static str> {
+    if uri == "vcf://metadata" {
+        Some("get_vcf_header")
+    } else if uri == "vcf://ids" || uri.starts_with("vcf://ids?") {
+        Some("search_ids")
+    } else if uri.starts_with("vcf://chromosome/") {
+        Some("get_contigs")
+    } else if uri == REGION_TEMPLATE || parse_region_uri(uri).is_some() {
+        Some("query_by_region")
+    } else {
+        None
+    }
+}
+
+// Apply --enable-tools/--disable-tools to a tool router (shared with supervisor mode)
+fn restrict_tool_router(
+    router: &mut ToolRouter<VcfServer>,
+    enable: &[String],
+    disable: &[String],
+) -> Result<(), String> {
+    let known: Vec<String> = router
+        .list_all()
+        .into_iter()
+        .map(|tool| tool.name.to_string())
+        .collect();
+
+    if let Some(unknown) = enable
+        .iter()
+        .chain(disable.iter())
+        .find(|name| !known.contains(name))
+    {
+        return Err(format!(
+            "Unknown tool '{}'. Available tools: {}",
+            unknown,
+            known.join(", ")
+        ));
+    }
+
+    for name in &known {
+        let enabled = enable.is_empty() || enable.contains(name);
+        if !enabled || disable.contains(name) {
+            router.remove_route(name);
+        }
+    }
+
+    Ok(())
+}
+
+// MCP Server implementation
+#[derive(Clone)]
+struct VcfServer {
+    // VcfIndex is thread-safe and queries run concurrently, each on the index it started with;
+    // the lock only guards --watch swapping in a reloaded one
+    index: Arc<std::sync::RwLock<Arc<VcfIndex>>>,
+    #[allow(dead_code)]
+    tool_router: ToolRouter<Self>,
+    debug: bool,
+    // Track active query sessions by session ID
+    query_sessions: Arc<Mutex<HashMap<String, QuerySession>>>,
+    // Bounds how many queries run on the blocking thread pool at once
+    query_permits: Arc<Semaphore>,
+    // Set by --record-queries
+    recorder: Option<Arc<replay::QueryRecorder>>,
+    // Set by --result-cache-ttl
+    result_cache: Option<Arc<result_cache::ResultCache>>,
+    // query_by_region regions wider than this need confirm_large_query (0 means no cap)
+    max_region_size: u64,
+    // Set by --reference
+    reference: Option<Arc<ReferenceFasta>>,
+    vrs: Option<Arc<VrsSource>>,
+    // Set by --annotation
+    annotation: Option<Arc<AnnotationSource>>,
+    // Set by --chain
+    chains: Option<Arc<ChainFile>>,
+    // Set by --allow-network
+    #[cfg(feature = "network")]
+    vep: Option<Arc<vep::VepClient>>,
+    #[cfg(feature = "network")]
+    dbsnp: Option<Arc<dbsnp::DbSnpClient>>,
+    // Set by --ped
+    trios: Arc<Vec<Trio>>,
+    // Set by --bed-dir, canonicalized
+    bed_dir: Option<PathBuf>,
+    // Set by --allow-write-dir, canonicalized
+    write_dir: Option<PathBuf>,
+    // Set by --watch
+    subscriptions: Option<Arc<watch::Subscriptions>>,
+    // notifications/message entries and the level each client asked for
+    log: Arc<client_log::ClientLog>,
+    // Tool calls running, refused once shutdown begins
+    drain: Arc<shutdown::Drain>,
+    // The file and options --watch, SIGHUP and reload_dataset load again
+    reload_from: Option<(PathBuf, LoadOptions)>,
+    // Held while a reload runs, so concurrent ones don't build indexes side by side
+    reloading: Arc<Mutex<()>>,
+}
+
+#[tool_router]
+impl VcfServer {
+    fn new(index: VcfIndex, debug: bool) -> Self {
+        VcfServer {
+            index: Arc::new(std::sync::RwLock::new(Arc::new(index))),
+            tool_router: Self::tools(),
+            debug,
+            query_sessions: Arc::new(Mutex::new(HashMap::new())),
+            query_permits: Arc::new(Semaphore::new(default_query_concurrency())),
+            recorder: None,
+            result_cache: None,
+            max_region_size: DEFAULT_MAX_REGION_SIZE,
+            reference: None,
+            vrs: None,
+            annotation: None,
+            chains: None,
+            #[cfg(feature = "network")]
+            vep: None,
+            #[cfg(feature = "network")]
+            dbsnp: None,
+            trios: Arc::new(Vec::new()),
+            bed_dir: None,
+            write_dir: None,
+            subscriptions: None,
+            log: Arc::new(client_log::ClientLog::new(debug)),
+            drain: Arc::default(),
+            reload_from: None,
+            reloading: Arc::default(),
+        }
+    }
+
+    /// Normalize variant alleles against this reference when a query asks for it.
+    fn use_reference(&mut self, reference: ReferenceFasta) {
+        let reference = Arc::new(reference);
+        self.vrs = Some(Arc::new(VrsSource::new(Arc::clone(&reference))));
+        self.reference = Some(reference);
+    }
+
+    /// Annotate query results with the INFO fields (all when `fields` is empty) of this VCF's matching records.
+    fn use_annotation(&mut self, index: VcfIndex, fields: Vec<String>) {
+        self.annotation = Some(Arc::new(AnnotationSource { index, fields }));
+    }
+
+    /// Lift coordinates from another build with this chain file when a query asks for it.
+    fn use_chain(&mut self, chains: ChainFile) {
+        self.chains = Some(Arc::new(chains));
+    }
+
+    /// Every tool this build provides, before --enable-tools/--disable-tools.
+    fn tools() -> ToolRouter<Self> {
+        #[allow(unused_mut)]
+        let mut tools = Self::tool_router();
+        #[cfg(feature = "network")]
+        {
+            tools = tools + Self::network_tool_router();
+        }
+        #[cfg(feature = "parquet-export")]
+        {
+            tools = tools + Self::parquet_tool_router();
+        }
+        tools
+    }
+
+    /// Let annotate_via_vep send variants to this Ensembl REST client.
+    #[cfg(feature = "network")]
+    fn allow_vep(&mut self, client: vep::VepClient) {
+        self.vep = Some(Arc::new(client));
+    }
+
+    /// Let query_by_id resolve missing rsIDs with this NCBI Variation API client.
+    #[cfg(feature = "network")]
+    fn allow_dbsnp(&mut self, client: dbsnp::DbSnpClient) {
+        self.dbsnp = Some(Arc::new(client));
+    }
+
+    /// Whether --allow-network let annotate_via_vep and resolve_via_ncbi reach the network.
+    #[cfg(feature = "network")]
+    fn network_allowed(&self) -> bool {
+        self.vep.is_some()
+    }
+
+    #[cfg(not(feature = "network"))]
+    fn network_allowed(&self) -> bool {
+        false
+    }
+
+    /// Check these trios in find_mendelian_errors when a call names no samples.
+    fn use_pedigree(&mut self, trios: Vec<Trio>) {
+        self.trios = Arc::new(trios);
+    }
+
+    /// Let query_by_bed read BED files under this directory.
+    fn allow_bed_dir(&mut self, dir: PathBuf) {
+        self.bed_dir = Some(dir);
+    }
+
+    /// Let export_region and export_parquet write files into this directory.
+    fn allow_write_dir(&mut self, dir: PathBuf) {
+        self.write_dir = Some(dir);
+    }
+
+    // Where an export tool writes `name` (a bare file name ending in `extension`) in
+    // --allow-write-dir; an existing file, or a tabix index beside it, is only replaced with
+    // `overwrite`
+    fn export_path(
+        &self,
+        tool: &str,
+        name: &str,
+        extension: &str,
+        overwrite: bool,
+    ) -> Result<PathBuf, McpError> {
+        let Some(dir) = &self.write_dir else {
+            return Err(McpError::invalid_request(
+                format!(
+                    "{} is unavailable: the server was started without --allow-write-dir",
+                    tool
+                ),
+                None,
+            ));
+        };
+        if !name.ends_with(extension) || name.starts_with('.') || name.contains(['/', '\\']) {
+            return Err(McpError::invalid_params(
+                format!(
+                    "output must be a file name ending in {}, without directories, got '{}'",
+                    extension, name
+                ),
+                None,
+            ));
+        }
+        let path = dir.join(name);
+        let index_path = PathBuf::from(format!("{}.tbi", path.display()));
+        if !overwrite && (path.exists() || index_path.exists()) {
+            return Err(McpError::invalid_params(
+                format!(
+                    "{} already exists in the output directory; pass overwrite: true to replace it",
+                    name
+                ),
+                None,
+            ));
+        }
+        Ok(path)
+    }
+
+    // The intervals of a query_by_bed call: its inline BED text, or the file at `path` under
+    // --bed-dir (relative paths are taken from there)
+    fn bed_regions(
+        &self,
+        bed: Option<&str>,
+        path: Option<&str>,
+    ) -> Result<Vec<bed::BedRegion>, McpError> {
+        let regions = match (bed, path) {
+            (Some(text), None) => bed::parse_bed(text),
+            (None, Some(path)) => {
+                let Some(dir) = &self.bed_dir else {
+                    return Err(McpError::invalid_params(
+                        "path needs a BED directory, but the server was started without --bed-dir; pass the intervals inline in bed".to_string(),
+                        None,
+                    ));
+                };
+                let resolved = dir.join(path).canonicalize().map_err(|e| {
+                    McpError::invalid_params(format!("Cannot open BED file {}: {}", path, e), None)
+                })?;
+                if !resolved.starts_with(dir) {
+                    return Err(McpError::invalid_params(
+                        format!("BED file {} is outside --bed-dir", path),
+                        None,
+                    ));
+                }
+                bed::load_bed(&resolved)
+            }
+            _ => {
+                return Err(McpError::invalid_params(
+                    "Give exactly one of bed (inline BED text) and path".to_string(),
+                    None,
+                ))
+            }
+        }
+        .map_err(|e| McpError::invalid_params(e.to_string(), None))?;
+        if regions.is_empty() {
+            return Err(McpError::invalid_params(
+                "The BED input has no intervals".to_string(),
+                None,
+            ));
+        }
+        if regions.len() > MAX_BED_QUERY_INTERVALS {
+            return Err(McpError::invalid_params(
+                format!(
+                    "The BED input has {} intervals; at most {} can be queried at once",
+                    regions.len(),
+                    MAX_BED_QUERY_INTERVALS
+                ),
+                None,
+            ));
+        }
+        Ok(regions)
+    }
+
+    // The trio a find_mendelian_errors call asks about: the named samples, with missing parents
+    // taken from --ped, or else the only --ped trio whose samples are all in the file
+    fn resolve_trio(
+        &self,
+        proband: Option<String>,
+        mother: Option<String>,
+        father: Option<String>,
+    ) -> Result<Trio, McpError> {
+        let Some(proband) = proband else {
+            if mother.is_some() || father.is_some() {
+                return Err(McpError::invalid_params(
+                    "proband is required when mother or father is given".to_string(),
+                    None,
+                ));
+            }
+            let in_file: Vec<&Trio> = self
+                .trios
+                .iter()
+                .filter(|trio| {
+                    [&trio.proband, &trio.mother, &trio.father]
+                        .iter()
+                        .all(|sample| self.index().sample_index(sample).is_some())
+                })
+                .collect();
+            return match in_file.as_slice() {
+                [trio] => Ok((*trio).clone()),
+                [] => Err(McpError::invalid_params(
+                    "Name the proband, mother and father: no trio from --ped has all three samples in the file"
+                        .to_string(),
+                    None,
+                )),
+                trios => Err(McpError::invalid_params(
+                    format!(
+                        "--ped defines several trios in the file; name the proband: {}",
+                        trios
+                            .iter()
+                            .map(|trio| trio.proband.as_str())
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    ),
+                    None,
+                )),
+            };
+        };
+
+        let from_ped = self.trios.iter().find(|trio| trio.proband == proband);
+        let parent = |given: Option<String>, role: &str, of_trio: fn(&Trio) -> &String| {
+            given
+                .or_else(|| from_ped.map(|trio| of_trio(trio).clone()))
+                .ok_or_else(|| {
+                    McpError::invalid_params(
+                        format!(
+                            "{} is required: --ped lists no parents for '{}'",
+                            role, proband
+                        ),
+                        None,
+                    )
+                })
+        };
+        Ok(Trio {
+            mother: parent(mother, "mother", |trio| &trio.mother)?,
+            father: parent(father, "father", |trio| &trio.father)?,
+            proband,
+        })
+    }
+
+    // The reference to normalize a query's variants against, if it asked for normalization
+    fn normalizer(&self, normalize: bool) -> Result<Option<Arc<ReferenceFasta>>, McpError> {
+        if !normalize {
+            return Ok(None);
+        }
+        self.reference.clone().map(Some).ok_or_else(|| {
+            McpError::invalid_params(
+                "normalize needs a reference FASTA, but the server was started without --reference"
+                    .to_string(),
+                None,
+            )
+        })
+    }
+
+    // What computes VRS identifiers for a query's variants, if it asked for them
+    fn vrs_source(&self, vrs: bool) -> Result<Option<Arc<VrsSource>>, McpError> {
+        if !vrs {
+            return Ok(None);
+        }
+        self.vrs.clone().map(Some).ok_or_else(|| {
+            McpError::invalid_params(
+                "vrs needs a reference FASTA to compute sequence digests, but the server was started without --reference"
+                    .to_string(),
+                None,
+            )
+        })
+    }
+
+    // The build to link a query's variants to genome browsers on, if it asked for links
+    fn browser_build(&self, browser_links: bool) -> Result<Option<Build>, McpError> {
+        if !browser_links {
+            return Ok(None);
+        }
+        let build = self.index().get_metadata().reference_genome.build;
+        Build::from_name(&build).map(Some).ok_or_else(|| {
+            McpError::invalid_params(
+                format!(
+                    "browser_links needs the file's genome build, and its header names neither GRCh37 nor GRCh38 (reference genome: {})",
+                    build
+                ),
+                None,
+            )
+        })
+    }
+
+    // Every resource listed by list_resources: the metadata, the ID map and one per contig
+    fn resources(&self) -> Vec<Annotated<RawResource>> {
+        let mut resources = vec![
+            Annotated::new(
+                RawResource {
+                    uri: "vcf://metadata".to_string(),
+                    name: "VCF Metadata".to_string(),
+                    title: None,
+                    description: Some(
+                        "Metadata from the VCF file header including file format, contigs, and samples".to_string()
+                    ),
+                    mime_type: Some("application/json".to_string()),
+                    size: None,
+                    icons: None,
+                    meta: None,
+                },
+                None,
+            ),
+            Annotated::new(
+                RawResource {
+                    uri: "vcf://ids".to_string(),
+                    name: "Variant IDs".to_string(),
+                    title: None,
+                    description: Some(format!(
+                        "Every variant ID with its chromosome and position, sorted by ID, {} IDs per page. Read vcf://ids?page=N for further pages",
+                        ID_PAGE_SIZE
+                    )),
+                    mime_type: Some("application/json".to_string()),
+                    size: None,
+                    icons: None,
+                    meta: None,
+                },
+                None,
+            ),
+        ];
+        resources.extend(self.index().contig_summaries().into_iter().map(|contig| {
+            let mut details = Vec::new();
+            if let Some(length) = contig.length {
+                details.push(format!("{} bp", length));
+            }
+            if let Some(count) = contig.variant_count {
+                details.push(format!("{} variants", count));
+            }
+            Annotated::new(
+                RawResource {
+                    uri: format!("vcf://chromosome/{}", contig.name),
+                    name: format!("Chromosome {}", contig.name),
+                    title: None,
+                    description: Some(if details.is_empty() {
+                        "Summary statistics of the contig".to_string()
+                    } else {
+                        format!("Summary statistics of the contig ({})", details.join(", "))
+                    }),
+                    mime_type: Some("application/json".to_string()),
+                    size: None,
+                    icons: None,
+                    meta: None,
+                },
+                None,
+            )
+        }));
+        resources
+    }
+
+    // Summary statistics of the contig named by a vcf://chromosome/{name} resource
+    fn read_chromosome_resource(
+        &self,
+        uri: &str,
+        name: &str,
+    ) -> Result<ReadResourceResult, McpError> {
+        // Read before the contigs, as get_contigs does
+        let counts_pending = !self.index().statistics_ready();
+        let Some(contig) = self
+            .index()
+            .contig_summaries()
+            .into_iter()
+            .find(|contig| contig.name == name)
+        else {
+            return Err(McpError::resource_not_found(
+                format!(
+                    "{}: chromosome '{}' is not in the VCF; list_resources or get_contigs gives its contig names",
+                    uri, name
+                ),
+                None,
+            ));
+        };
+        let range = self
+            .index()
+            .get_metadata()
+            .variant_ranges
+            .and_then(|ranges| ranges.into_iter().find(|range| range.chromosome == name));
+        let resource = ChromosomeResource {
+            reference_genome: self.index().get_reference_genome(),
+            variants_per_mb: contig
+                .length
+                .zip(contig.variant_count)
+                .filter(|(length, _)| *length > 0)
+                .map(|(length, count)| count as f64 * 1_000_000.0 / length as f64),
+            first_position: range.as_ref().map(|range| range.first_position),
+            last_position: range.as_ref().map(|range| range.last_position),
+            name: contig.name,
+            length: contig.length,
+            variant_count: contig.variant_count,
+            counts_pending,
+        };
+        let text = serde_json::to_string(&resource).map_err(|e| {
+            McpError::internal_error(
+                format!("Failed to serialize chromosome resource: {}", e),
+                None,
+            )
+        })?;
+        Ok(ReadResourceResult {
+            contents: vec![ResourceContents::TextResourceContents {
+                uri: uri.to_string(),
+                mime_type: Some("application/json".to_string()),
+                text,
+                meta: None,
+            }],
+        })
+    }
+
+    // The variants of a vcf://{chromosome}/{start}-{end} resource, as JSON or VCF text
+    async fn read_region_resource(
+        &self,
+        uri: &str,
+        slice: RegionSlice,
+    ) -> Result<ReadResourceResult, McpError> {
+        let region_size = slice.end - slice.start;
+        if self.max_region_size > 0 && region_size > self.max_region_size {
+            return Err(McpError::invalid_params(
+                format!(
+                    "{} spans {} bp, more than the {} bp a region resource may cover; use query_by_region or start_region_query for wider regions",
+                    uri, region_size, self.max_region_size
+                ),
+                None,
+            ));
+        }
+        let vcf = slice.vcf;
+        let resource_uri = uri.to_string();
+        let text = self
+            .run_query(move |index| {
+                index
+                    .check_region_span(slice.start, slice.end)
+                    .map_err(|exceeded| limit_error("region resource", &exceeded))?;
+                let options = QueryOptions {
+                    limit: Some(REGION_PAGE_SIZE),
+                    ..Default::default()
+                };
+                let page = index.query_region(&slice.chromosome, slice.start, slice.end, &options);
+                if let Some(exceeded) = &page.limit_exceeded {
+                    return Err(limit_error("region resource", exceeded));
+                }
+                if let Some(failure) = &page.read_failure {
+                    return Err(read_error("region resource", failure));
+                }
+                let Some(matched_chromosome) = page.matched_chromosome else {
+                    return Err(McpError::resource_not_found(
+                        format!(
+                            "{}: chromosome '{}' is not in the VCF; see vcf://metadata for its contigs",
+                            resource_uri, slice.chromosome
+                        ),
+                        None,
+                    ));
+                };
+                let variants: Vec<Variant> =
+                    page.variants.into_iter().map(format_variant).collect();
+                if vcf {
+                    return Ok(index.render_vcf(&variants));
+                }
+                serde_json::to_string(&RegionResource {
+                    reference_genome: index.get_reference_genome(),
+                    chromosome: slice.chromosome,
+                    start: slice.start,
+                    end: slice.end,
+                    matched_chromosome,
+                    count: variants.len(),
+                    truncated: page.next_cursor.is_some(),
+                    variants,
+                })
+                .map_err(|e| {
+                    McpError::internal_error(
+                        format!("Failed to serialize region resource: {}", e),
+                        None,
+                    )
+                })
+            })
+            .await?;
+
+        Ok(ReadResourceResult {
+            contents: vec![ResourceContents::TextResourceContents {
+                uri: uri.to_string(),
+                mime_type: Some(
+                    if vcf {
+                        "text/x-vcf"
+                    } else {
+                        "application/json"
+                    }
+                    .to_string(),
+                ),
+                text,
+                meta: None,
+            }],
+        })
+    }
+
+    // The annotation VCF to look a query's variants up in, if it asked for annotation
+    fn annotator(&self, annotate: bool) -> Result<Option<Arc<AnnotationSource>>, McpError> {
+        if !annotate {
+            return Ok(None);
+        }
+        self.annotation.clone().map(Some).ok_or_else(|| {
+            McpError::invalid_params(
+                "annotate needs an annotation VCF, but the server was started without --annotation"
+                    .to_string(),
+                None,
+            )
+        })
+    }
+
+    // The chain file to lift a query's coordinates with, if it asked for liftover
+    fn lifter(&self, liftover: bool) -> Result<Option<Arc<ChainFile>>, McpError> {
+        if !liftover {
+            return Ok(None);
+        }
+        self.chains.clone().map(Some).ok_or_else(|| {
+            McpError::invalid_params(
+                "liftover needs a chain file, but the server was started without --chain"
+                    .to_string(),
+                None,
+            )
+        })
+    }
+
+    // The 1-based inclusive region to query for `chromosome`:`start`-`end`, lifted to the VCF's
+    // build when the query asked for liftover
+    fn lift_query(
+        &self,
+        liftover: bool,
+        chromosome: String,
+        start: u64,
+        end: u64,
+    ) -> Result<(String, u64, u64, Option<QueryLiftover>), McpError> {
+        let Some(chains) = self.lifter(liftover)? else {
+            return Ok((chromosome, start, end, None));
+        };
+        let lifted = chains
+            .lift_region(&chromosome, start, end)
+            .map_err(|message| McpError::invalid_params(message, None))?;
+        let liftover = QueryLiftover {
+            chain: chains.name().to_string(),
+            chain_id: lifted.chain_id,
+            strand: lifted.strand,
+            from: LiftoverRegion {
+                chromosome,
+                start,
+                end,
+            },
+            to: LiftoverRegion {
+                chromosome: lifted.chromosome.clone(),
+                start: lifted.start,
+                end: lifted.end,
+            },
+        };
+        Ok((lifted.chromosome, lifted.start, lifted.end, Some(liftover)))
+    }
+
+    // The index queries run against; --watch replaces it when the file changes
+    fn index(&self) -> Arc<VcfIndex> {
+        Arc::clone(&self.index.read().unwrap_or_else(|e| e.into_inner()))
+    }
+
+    // Serve a reloaded index from now on; cached responses came from the old one
+    fn replace_index(&self, index: VcfIndex) {
+        *self.index.write().unwrap_or_else(|e| e.into_inner()) = Arc::new(index);
+        if let Some(cache) = &self.result_cache {
+            cache.clear();
+        }
+    }
+
+    /// Reload this file with these options on --watch changes, SIGHUP and reload_dataset.
+    fn enable_reload(&mut self, path: PathBuf, options: LoadOptions) {
+        self.reload_from = Some((path, options));
+    }
+
+    // Re-open the VCF and its indexes and swap them in; an ID index or statistics that no
+    // longer match the file are rebuilt. Queries already running finish on the old index, and a
+    // failed load keeps it. Returns how many resource subscriptions were notified.
+    async fn reload(&self, reason: &str) -> Result<usize, String> {
+        let Some((path, mut options)) = self.reload_from.clone() else {
+            return Err("This server has no VCF file to reload".to_string());
+        };
+        // A reload means the file is being changed, and reading a mapping of a file truncated or
+        // rewritten in place raises SIGBUS, so the new index reads it through file handles
+        if options.mmap {
+            info!(
+                "Reloading {} without --mmap: a file that changes on disk must not stay mapped",
+                path.display()
+            );
+            options.mmap = false;
+        }
+        let _reloading = self.reloading.lock().await;
+        let current = self.index();
+        let load_path = path.clone();
+        let loaded = tokio::task::spawn_blocking(move || {
+            // Its ID index build would race the new one for the same temp file
+            current.stop_background_indexing(RELOAD_BUILD_STOP_TIMEOUT);
+            load_vcf_with_options(&load_path, &options)
+        })
+        .await
+        .map_err(|e| e.to_string())
+        .and_then(|loaded| loaded.map_err(|e| e.to_string()));
+        let index = match loaded {
+            Ok(index) => index,
+            Err(e) => {
+                warn!(
+                    "Reloading {} ({}) failed, still serving the previous version: {}",
+                    path.display(),
+                    reason,
+                    e
+                );
+                return Err(format!(
+                    "Reloading {} failed, still serving the previous version: {}",
+                    path.display(),
+                    e
+                ));
+            }
+        };
+        self.replace_index(index);
+        let notified = match &self.subscriptions {
+            Some(subscriptions) => subscriptions.notify_updated().await,
+            None => 0,
+        };
+        info!(
+            "Reloaded {} ({}); notified {} resource subscription(s)",
+            path.display(),
+            reason,
+            notified
+        );
+        Ok(notified)
+    }
+
+    // Refuse new tool calls, let running ones finish and stop any background indexing, all
+    // within `timeout`
+    async fn shut_down(&self, timeout: std::time::Duration) {
+        let deadline = std::time::Instant::now() + timeout;
+        let running = self.drain.in_flight();
+        if running > 0 {
+            info!(
+                "Waiting up to {:?} for {} running tool call(s)",
+                timeout, running
+            );
+        }
+        if !self.drain.drain(timeout).await {
+            warn!(
+                "Drain timeout reached; abandoning {} running tool call(s)",
+                self.drain.in_flight()
+            );
+        }
+        let index = self.index();
+        let remaining = deadline.saturating_duration_since(std::time::Instant::now());
+        let stopped =
+            tokio::task::spawn_blocking(move || index.stop_background_indexing(remaining))
+                .await
+                .unwrap_or(false);
+        if !stopped {
+            warn!("ID index still being written at exit; it is rebuilt on the next start");
+        }
+    }
+
+    // What the prompts' instructions are tailored to: the file's build and annotations and the
+    // tools and options this server has
+    fn prompt_context(&self) -> prompts::PromptContext {
+        let index = self.index();
+        let metadata = index.get_metadata();
+        prompts::PromptContext {
+            build: metadata.reference_genome.build,
+            gene_annotations: index.gene_annotations(),
+            sample_count: metadata.samples.len(),
+            tools: self
+                .tool_router
+                .list_all()
+                .into_iter()
+                .map(|tool| tool.name.to_string())
+                .collect(),
+            network: self.network_allowed(),
+            liftover: self.chains.is_some(),
+        }
+    }
+
+    /// Accept resource subscriptions, notified when --watch reloads the file.
+    fn watch_resources(&mut self) {
+        self.subscriptions = Some(Arc::new(watch::Subscriptions::default()));
+    }
+
+    /// Ask for confirmation before query_by_region scans regions wider than `bp` (0 never asks).
+    fn cap_region_size(&mut self, bp: u64) {
+        self.max_region_size = bp;
+    }
+
+    /// Answer repeated tool calls from a cache of recent responses.
+    fn cache_results(&mut self, cache: result_cache::ResultCache) {
+        self.result_cache = Some(Arc::new(cache));
+    }
+
+    /// Allow at most `limit` queries to read the VCF concurrently.
+    fn limit_concurrent_queries(&mut self, limit: usize) {
+        self.query_permits = Arc::new(Semaphore::new(limit.max(1)));
+    }
+
+    // Run blocking index work on tokio's blocking thread pool so file reads never stall
+    // the async runtime; waits for a permit when the concurrency limit is reached
+    async fn run_query<T, F>(&self, work: F) -> Result<T, McpError>
+    where
+        F: FnOnce(&VcfIndex) -> Result<T, McpError> + Send + 'static,
+        T: Send + 'static,
+    {
+        let _permit = Arc::clone(&self.query_permits)
+            .acquire_owned()
+            .await
+            .map_err(|e| McpError::internal_error(format!("Query limiter closed: {}", e), None))?;
+        let index = self.index();
+        // The tool call's span follows the work, so the index's log events are attributed to it
+        let span = tracing::Span::current();
+        tokio::task::spawn_blocking(move || span.in_scope(|| work(&index)))
+            .await
+            .map_err(|e| McpError::internal_error(format!("Query task failed: {}", e), None))?
+    }
+
+    // Like run_query, but the work can report progress, sent to the client as MCP progress
+    // notifications while it runs, and check whether the request was cancelled
+    async fn run_monitored_query<T, F>(&self, context: CallContext, work: F) -> Result<T, McpError>
+    where
+        F: FnOnce(&VcfIndex, &mut CallMonitor) -> Result<T, McpError> + Send + 'static,
+        T: Send + 'static,
+    {
+        let CallContext {
+            progress,
+            cancellation,
+            scanned,
+        } = context;
+        let Some((token, peer)) = progress else {
+            let mut monitor = CallMonitor {
+                progress: None,
+                cancellation,
+                last_sent: std::time::Instant::now(),
+                scanned,
+            };
+            return self.run_query(move |index| work(index, &mut monitor)).await;
+        };
+
+        let (sender, mut updates) = tokio::sync::mpsc::unbounded_channel();
+        let mut monitor = CallMonitor {
+            progress: Some((token, sender)),
+            cancellation,
+            last_sent: std::time::Instant::now(),
+            scanned,
+        };
+        let query = self.run_query(move |index| work(index, &mut monitor));
+        tokio::pin!(query);
+        loop {
+            tokio::select! {
+                result = &mut query => return result,
+                Some(update) = updates.recv() => {
+                    // A client that stopped listening just misses the update
+                    if let Err(e) = peer.notify_progress(update).await {
+                        if self.debug {
+                            debug!("Failed to send progress notification: {}", e);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Record every tool call and its response digest for later replay.
+    fn record_queries(&mut self, recorder: replay::QueryRecorder) {
+        self.recorder = Some(Arc::new(recorder));
+    }
+
+    /// Remove tools from the router so they are neither listed nor callable.
+    /// An empty `enable` list keeps every tool; `disable` is applied afterwards.
+    fn restrict_tools(&mut self, enable: &[String], disable: &[String]) -> Result<(), String> {
+        restrict_tool_router(&mut self.tool_router, enable, disable)
+    }
+
+    /// Remove the tools reporting per-sample data (--hide-genotypes); the index strips the
+    /// sample columns themselves.
+    fn hide_genotypes(&mut self) {
+        hide_genotype_tools(&mut self.tool_router);
+    }
+
+    /// Whether a tool is offered, i.e. not removed by restrict_tools or hide_genotypes.
+    fn tool_enabled(&self, name: &str) -> bool {
+        self.tool_router
+            .list_all()
+            .iter()
+            .any(|tool| tool.name == name)
+    }
+
+    /// Refuse a resource whose tool is disabled, as it would serve the same data.
+    fn check_resource_allowed(&self, uri: &str) -> Result<(), McpError> {
+        match mirrored_tool(uri) {
+            Some(tool) if !self.tool_enabled(tool) => Err(McpError::invalid_request(
+                format!(
+                    "{} serves the same data as the {} tool, which is disabled on this server",
+                    uri, tool
+                ),
+                None,
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    #[tool(
+        description = "Query variants at a specific genomic position. NOTE: Coordinates are genome build-specific (GRCh37 vs GRCh38). Check the reference_genome field in the response to verify which build is being queried.",
+        annotations(
+            title = "Query variants at a position",
+            read_only_hint = true,
+            destructive_hint = false,
+            idempotent_hint = true,
+            open_world_hint = false
+        )
+    )]
+    async fn query_by_position(
+        &self,
+        Parameters(QueryByPositionParams {
+            chromosome: requested_chromosome,
+            position,
+            window,
+            has_info_flags,
+            shortcuts,
+            fields,
+            normalize,
+            vrs,
+            browser_links,
+            annotate,
+            liftover,
+            format,
+        }): Parameters<QueryByPositionParams>,
+    ) -> Result<CallToolResult, McpError> {
+        if window > MAX_POSITION_WINDOW {
+            return Err(McpError::invalid_params(
+                format!(
+                    "window must be at most {} bp, got {}; use query_by_region for wider context",
+                    MAX_POSITION_WINDOW, window
+                ),
+                None,
+            ));
+        }
+        let (requested_chromosome, position, _, liftover) =
+            self.lift_query(liftover, requested_chromosome, position, position)?;
+        let filter = shortcuts.combine("")?;
+        check_fields(fields.as_deref())?;
+        check_output_format(format, fields.as_deref(), &self.index())?;
+        let reference = self.normalizer(normalize)?;
+        let vrs = self.vrs_source(vrs)?;
+        let browser = self.browser_build(browser_links)?;
+        let annotation = self.annotator(annotate)?;
+
+        let response = self
+            .run_query(move |index| {
+                check_info_flags(index, &has_info_flags)?;
+                check_filter(index, &filter)?;
+                check_required_info(index, &shortcuts.record_filter().required_info_keys())?;
+                let mut variants = Vec::new();
+                let matched_chr = index
+                    .try_scan_region(
+                        &requested_chromosome,
+                        position.saturating_sub(window).max(1),
+                        position.saturating_add(window),
+                        &has_info_flags,
+                        &mut |variant| {
+                            variants.push(variant);
+                            true
+                        },
+                    )
+                    .map_err(|failure| read_error("query_by_position", &failure))?;
+                retain_matching(index, &filter, &shortcuts.record_filter(), &mut variants);
+                let count = variants.len();
+                let mut items: Vec<Variant> = variants.into_iter().map(format_variant).collect();
+                normalize_variants(reference.as_deref(), &mut items, CoordinateSystem::OneBased);
+                vrs_variants(vrs.as_deref(), &mut items, CoordinateSystem::OneBased);
+                spdi_variants(index, &mut items, CoordinateSystem::OneBased);
+                link_variants(browser, &mut items, CoordinateSystem::OneBased);
+                annotate_variants(
+                    annotation.as_deref(),
+                    &mut items,
+                    CoordinateSystem::OneBased,
+                )
+                .map_err(|failure| read_error("query_by_position (--annotation)", &failure))?;
+                let result = QueryResult { count, items };
+
+                let (status, available_sample, alternate_suggestion) =
+                    build_chromosome_response(index, &requested_chromosome, &matched_chr);
+
+                let reference_genome = index.get_reference_genome();
+
+                Ok(QueryByPositionResponse {
+                    status,
+                    reference_genome,
+                    query: PositionQuery {
+                        chromosome: requested_chromosome,
+                        position,
+                        window,
+                        has_info_flags,
+                        filter,
+                        record_filter: shortcuts.record_filter(),
+                    },
+                    matched_chromosome: matched_chr,
+                    available_chromosomes_sample: available_sample,
+                    alternate_chromosome_suggestion: alternate_suggestion,
+                    liftover,
+                    result,
+                })
+            })
+            .await?;
+
+        let mut payload = serde_json::to_value(&response).map_err(|e| {
+            McpError::internal_error(
+                format!("Failed to serialize query_by_position response: {}", e),
+                None,
+            )
+        })?;
+        format_result_items(
+            &mut payload,
+            format,
+            fields.as_deref(),
+            &response.result.items,
+            &self.index(),
+        );
+
+        let content = Content::json(payload)?;
+
+        Ok(CallToolResult::success(vec![content]))
+    }
+
+    #[tool(
+        description = "Query variants in a genomic region. Regions wider than the server's cap (10,000 bp by default) return status confirmation_required with an estimate of the scan's size instead of variants; repeat the call with confirm_large_query: true to run it anyway, or use start_region_query to stream. Results are paged at 10,000 variants: pass next_cursor back as cursor to get the next page. Long scans send MCP progress notifications when the request carries a progress token. NOTE: Coordinates are genome build-specific (GRCh37 vs GRCh38). Check the reference_genome field in the response to verify which build is being queried.",
+        annotations(
+            title = "Query variants in a region",
+            read_only_hint = true,
+            destructive_hint = false,
+            idempotent_hint = true,
+            open_world_hint = false
+        )
+    )]
+    async fn query_by_region(
+        &self,
+        Parameters(params): Parameters<QueryByRegionParams>,
+        context: RequestContext<RoleServer>,
+    ) -> Result<CallToolResult, McpError> {
+        self.query_region_page(params, CallContext::from_request(&context))
+            .await
+    }
+
+    // query_by_region without the request context, so tests can call it directly
+    async fn query_region_page(
+        &self,
+        mut params: QueryByRegionParams,
+        context: CallContext,
+    ) -> Result<CallToolResult, McpError> {
+        // Liftover works on VCF coordinates; a 0-based start moves back, the end is the same
+        let (vcf_start, vcf_end) = params
+            .coordinate_system
+            .to_vcf_region(params.start, params.end);
+        let (chromosome, lifted_start, lifted_end, liftover) = self.lift_query(
+            params.liftover,
+            params.chromosome.clone(),
+            vcf_start,
+            vcf_end,
+        )?;
+        let liftover = liftover.map(|mut liftover| {
+            liftover.from.start = params.start;
+            liftover.to.start = params.coordinate_system.from_vcf_position(lifted_start);
+            liftover
+        });
+        if let Some(liftover) = &liftover {
+            params.chromosome = chromosome;
+            params.start = liftover.to.start;
+            params.end = lifted_end;
+        }
+        let requested_chromosome = params.chromosome.clone();
+        let (start, end) = (params.start, params.end);
+        let (vcf_start, vcf_end) = params.coordinate_system.to_vcf_region(start, end);
+        let region_size = vcf_end.saturating_sub(vcf_start);
+        let max_region_size = self.max_region_size;
+        // A cursor means the caller already went ahead with this region
+        let needs_confirmation = max_region_size > 0
+            && region_size > max_region_size
+            && !params.confirm_large_query
+            && params.cursor.is_none();
+        let options = region_query_options(&params)?;
+        check_fields(params.fields.as_deref())?;
+        check_output_format(params.format, params.fields.as_deref(), &self.index())?;
+        let fields = params.fields.clone();
+        let reference = self.normalizer(params.normalize)?;
+        let vrs = self.vrs_source(params.vrs)?;
+        let browser = self.browser_build(params.browser_links)?;
+        let annotation = self.annotator(params.annotate)?;
+        let mnv_sample = params.mnv_sample.clone();
+        let explain = params.explain;
+        let format = params.format;
+
+        let query_context = RegionQuery {
+            chromosome: params.chromosome,
+            start,
+            end,
+            has_info_flags: params.has_info_flags,
+            filter: options.filter.clone(),
+            record_filter: options.record_filter.clone(),
+            mnv_sample: params.mnv_sample,
+            cursor: params.cursor,
+            limit: params.limit,
+        };
+
+        let mut response = self
+            .run_monitored_query(context, move |index, monitor| {
+                check_info_flags(index, &options.info_flags)?;
+                check_filter(index, &options.filter)?;
+                check_required_info(index, &options.record_filter.required_info_keys())?;
+                let mnv_sample = mnv_sample
+                    .as_deref()
+                    .map(|sample| check_sample(index, sample))
+                    .transpose()?;
+                for sample in options.samples.iter().chain(&options.present_in_sample) {
+                    check_sample(index, sample)?;
+                }
+                // A region over the hard limit is refused outright rather than confirmed
+                index
+                    .check_region_span(vcf_start, vcf_end)
+                    .map_err(|exceeded| limit_error("query_by_region", &exceeded))?;
+
+                // Over-large regions are only scanned once the caller confirms; an unknown
+                // chromosome falls through to the usual chromosome_not_found response
+                let estimate = if needs_confirmation && !index.is_empty_file() {
+                    index.estimate_region(&requested_chromosome, vcf_start, vcf_end)
+                } else {
+                    None
+                };
+                if let Some((estimate, matched_chr)) = estimate {
+                    return Ok(QueryByRegionResponse {
+                        status: QueryStatus::ConfirmationRequired,
+                        reference_genome: index.get_reference_genome(),
+                        query: query_context,
+                        matched_chromosome: Some(matched_chr),
+                        available_chromosomes_sample: None,
+                        alternate_chromosome_suggestion: None,
+                        result: QueryResult {
+                            count: 0,
+                            items: Vec::new(),
+                        },
+                        mnvs: None,
+                        liftover: None,
+                        confirmation: Some(LargeQueryConfirmation {
+                            region_size_bp: region_size,
+                            max_region_size_bp: max_region_size,
+                            estimate,
+                            message: format!(
+                                "Region spans {} bp, more than the {} bp cap. Call query_by_region again with confirm_large_query: true to scan it, or use start_region_query to stream it.",
+                                region_size, max_region_size
+                            ),
+                        }),
+                        next_cursor: None,
+                        filter_clauses: None,
+                        excluded: None,
+                        matched_clauses: Vec::new(),
+                    });
+                }
+
+                let total = (region_size + 1) as f64;
+                let page = index.query_region_with_progress(
+                    &requested_chromosome,
+                    start,
+                    end,
+                    &options,
+                    &mut |scan| {
+                        monitor.report(
+                            scan.position.saturating_sub(vcf_start) as f64,
+                            total,
+                            || {
+                                format!(
+                                    "{} records read, {} matching, at {}:{}",
+                                    scan.records_read,
+                                    scan.matching,
+                                    requested_chromosome,
+                                    scan.position
+                                )
+                            },
+                        );
+                        monitor.scanned()
+                    },
+                );
+                if let Some(exceeded) = &page.limit_exceeded {
+                    return Err(limit_error("query_by_region", exceeded));
+                }
+                if let Some(failure) = &page.read_failure {
+                    return Err(read_error("query_by_region", failure));
+                }
+                let matched_chr = page.matched_chromosome;
+                if page.cancelled {
+                    return Ok(QueryByRegionResponse {
+                        status: QueryStatus::Cancelled,
+                        reference_genome: index.get_reference_genome(),
+                        query: query_context,
+                        matched_chromosome: matched_chr,
+                        available_chromosomes_sample: None,
+                        alternate_chromosome_suggestion: None,
+                        result: QueryResult {
+                            count: 0,
+                            items: Vec::new(),
+                        },
+                        mnvs: None,
+                        liftover: None,
+                        confirmation: None,
+                        next_cursor: None,
+                        filter_clauses: None,
+                        excluded: None,
+                        matched_clauses: Vec::new(),
+                    });
+                }
+
+                let mnvs = mnv_sample.map(|sample| merge_phased_mnvs(&page.variants, sample));
+                let count = page.variants.len();
+                let mut items: Vec<Variant> =
+                    page.variants.into_iter().map(format_variant).collect();
+                normalize_variants(reference.as_deref(), &mut items, options.coordinates);
+                vrs_variants(vrs.as_deref(), &mut items, options.coordinates);
+                spdi_variants(index, &mut items, options.coordinates);
+                link_variants(browser, &mut items, options.coordinates);
+                annotate_variants(annotation.as_deref(), &mut items, options.coordinates)
+                    .map_err(|failure| read_error("query_by_region (--annotation)", &failure))?;
+                let matched_clauses = if explain {
+                    items
+                        .iter()
+                        .map(|variant| index.evaluate_clauses(&options.filter, variant).0)
+                        .collect()
+                } else {
+                    Vec::new()
+                };
+                let result = QueryResult { count, items };
+
+                let (status, available_sample, alternate_suggestion) =
+                    build_chromosome_response(index, &requested_chromosome, &matched_chr);
+
+                let reference_genome = index.get_reference_genome();
+
+                Ok(QueryByRegionResponse {
+                    status,
+                    reference_genome,
+                    query: query_context,
+                    matched_chromosome: matched_chr,
+                    available_chromosomes_sample: available_sample,
+                    alternate_chromosome_suggestion: alternate_suggestion,
+                    result,
+                    mnvs,
+                    liftover: None,
+                    confirmation: None,
+                    next_cursor: page.next_cursor.map(|cursor| cursor.encode()),
+                    filter_clauses: explain.then(|| filter_clauses(&options.filter)),
+                    excluded: (options.explain_excluded > 0).then_some(page.excluded),
+                    matched_clauses,
+                })
+            })
+            .await?;
+
+        let matched_clauses = std::mem::take(&mut response.matched_clauses);
+        response.liftover = liftover;
+        let mut payload = serde_json::to_value(&response).map_err(|e| {
+            McpError::internal_error(
+                format!("Failed to serialize query_by_region response: {}", e),
+                None,
+            )
+        })?;
+        format_result_items(
+            &mut payload,
+            format,
+            fields.as_deref(),
+            &response.result.items,
+            &self.index(),
+        );
+        annotate_matched_clauses(&mut payload, matched_clauses);
+
+        let content = Content::json(payload)?;
+
+        Ok(CallToolResult::success(vec![content]))
+    }
+
+    #[tool(
+        description = "Randomly sample variants from a genomic region (not capped by the confirm_large_query size, as only the sample is kept in memory), optionally restricted by a filter expression. Results are reproducible: pass the same seed to get the same sample, and the seed used is always echoed in the response.",
+        annotations(
+            title = "Sample random variants",
+            read_only_hint = true,
+            destructive_hint = false,
+            idempotent_hint = false,
+            open_world_hint = false
+        )
+    )]
+    async fn sample_variants(
+        &self,
+        Parameters(params): Parameters<SampleVariantsParams>,
+        context: RequestContext<RoleServer>,
+    ) -> Result<CallToolResult, McpError> {
+        self.sample_region_variants(params, CallContext::from_request(&context))
+            .await
+    }
+
+    // sample_variants without the request context, so tests can call it directly
+    async fn sample_region_variants(
+        &self,
+        mut params: SampleVariantsParams,
+        context: CallContext,
+    ) -> Result<CallToolResult, McpError> {
+        (params.start, params.end) = params
+            .coordinate_system
+            .to_vcf_region(params.start, params.end);
+
+        // --max-results caps the sample like any other query's results
+        let max_count = self
+            .index()
+            .limits()
+            .max_results
+            .map_or(MAX_SAMPLE_COUNT, |max| max.min(MAX_SAMPLE_COUNT));
+        if params.count == 0 || params.count > max_count {
+            return Err(McpError::invalid_params(
+                format!("count must be between 1 and {}", max_count),
+                None,
+            ));
+        }
+
+        // Without an explicit seed, pick one so the response can still be reproduced
+        let seed = params.seed.unwrap_or_else(|| {
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|d| d.as_nanos() as u64)
+                .unwrap_or(0)
+        });
+
+        let filter = params.shortcuts.combine(&params.filter)?;
+        let record_filter = params.shortcuts.record_filter();
+        check_fields(params.fields.as_deref())?;
+        check_output_format(params.format, params.fields.as_deref(), &self.index())?;
+        let fields = params.fields.clone();
+        let format = params.format;
+        let reference = self.normalizer(params.normalize)?;
+        let vrs = self.vrs_source(params.vrs)?;
+        let browser = self.browser_build(params.browser_links)?;
+        let annotation = self.annotator(params.annotate)?;
+
+        let response = self
+            .run_monitored_query(context, move |index, monitor| {
+                check_filter(index, &filter)?;
+                check_required_info(index, &record_filter.required_info_keys())?;
+                let samples = sample_columns(index, &params.samples)?;
+
+                let sample = index.sample_region_with_progress(
+                    &params.chromosome,
+                    params.start,
+                    params.end,
+                    params.count,
+                    seed,
+                    &filter,
+                    &record_filter,
+                    &mut |_| monitor.scanned(),
+                );
+                if let Some(exceeded) = &sample.limit_exceeded {
+                    return Err(limit_error("sample_variants", exceeded));
+                }
+                if let Some(failure) = &sample.read_failure {
+                    return Err(read_error("sample_variants", failure));
+                }
+                let (variants, population, matched_chr) = (
+                    sample.variants,
+                    sample.population,
+                    sample.matched_chromosome,
+                );
+                let query = SampleQuery {
+                    chromosome: params.chromosome,
+                    start: params.start,
+                    end: params.end,
+                    count: params.count,
+                    seed,
+                    filter,
+                    record_filter,
+                };
+                // Part of the region is no uniform sample of all of it
+                if sample.cancelled {
+                    return Ok(SampleVariantsResponse {
+                        status: QueryStatus::Cancelled,
+                        reference_genome: index.get_reference_genome(),
+                        query,
+                        matched_chromosome: matched_chr,
+                        available_chromosomes_sample: None,
+                        alternate_chromosome_suggestion: None,
+                        population: 0,
+                        result: QueryResult {
+                            count: 0,
+                            items: Vec::new(),
+                        },
+                    });
+                }
+                let count = variants.len();
+                let mut items: Vec<Variant> = variants.into_iter().map(format_variant).collect();
+                normalize_variants(reference.as_deref(), &mut items, CoordinateSystem::OneBased);
+                vrs_variants(vrs.as_deref(), &mut items, CoordinateSystem::OneBased);
+                spdi_variants(index, &mut items, CoordinateSystem::OneBased);
+                link_variants(browser, &mut items, CoordinateSystem::OneBased);
+                annotate_variants(
+                    annotation.as_deref(),
+                    &mut items,
+                    CoordinateSystem::OneBased,
+                )
+                .map_err(|failure| read_error("sample_variants (--annotation)", &failure))?;
+                for item in &mut items {
+                    select_samples(item, &samples);
+                }
+
+                let (status, available_sample, alternate_suggestion) =
+                    build_chromosome_response(index, &query.chromosome, &matched_chr);
+
+                Ok(SampleVariantsResponse {
+                    status,
+                    reference_genome: index.get_reference_genome(),
+                    query,
+                    matched_chromosome: matched_chr,
+                    available_chromosomes_sample: available_sample,
+                    alternate_chromosome_suggestion: alternate_suggestion,
+                    population,
+                    result: QueryResult { count, items },
+                })
+            })
+            .await?;
+
+        let mut payload = serde_json::to_value(&response).map_err(|e| {
+            McpError::internal_error(
+                format!("Failed to serialize sample_variants response: {}", e),
+                None,
+            )
+        })?;
+        format_result_items(
+            &mut payload,
+            format,
+            fields.as_deref(),
+            &response.result.items,
+            &self.index(),
+        );
+
+        let content = Content::json(payload)?;
+        Ok(CallToolResult::success(vec![content]))
+    }
+
+    #[tool(
+        description = "Export BED intervals (0-based, end-exclusive) covering the variants in a region, optionally filtered, padded and merged, for use with coverage tools and genome browsers. No region size limit; output is capped at 10,000 intervals.",
+        annotations(
+            title = "Export variants as BED",
+            read_only_hint = true,
+            destructive_hint = false,
+            idempotent_hint = true,
+            open_world_hint = false
+        )
+    )]
+    async fn export_bed(
+        &self,
+        Parameters(mut params): Parameters<ExportBedParams>,
+        context: RequestContext<RoleServer>,
+    ) -> Result<CallToolResult, McpError> {
+        (params.start, params.end) = params
+            .coordinate_system
+            .to_vcf_region(params.start, params.end);
+        let context = CallContext::from_request(&context);
+        let query = BedQuery {
+            filter: params.shortcuts.combine(&params.filter)?,
+            record_filter: params.shortcuts.record_filter(),
+            chromosome: params.chromosome,
+            start: params.start,
+            end: params.end,
+            padding: params.padding,
+            merge: params.merge,
+            has_info_flags: params.has_info_flags,
+        };
+
+        let response = self
+            .run_monitored_query(context, move |index, monitor| {
+                check_info_flags(index, &query.has_info_flags)?;
+                check_filter(index, &query.filter)?;
+                check_required_info(index, &query.record_filter.required_info_keys())?;
+
+                let options = QueryOptions {
+                    filter: query.filter.clone(),
+                    info_flags: query.has_info_flags.clone(),
+                    record_filter: query.record_filter.clone(),
+                    ..Default::default()
+                };
+                let page = index.query_region_with_progress(
+                    &query.chromosome,
+                    query.start,
+                    query.end,
+                    &options,
+                    &mut |_| monitor.scanned(),
+                );
+                if let Some(exceeded) = &page.limit_exceeded {
+                    return Err(limit_error("export_bed", exceeded));
+                }
+                if let Some(failure) = &page.read_failure {
+                    return Err(read_error("export_bed", failure));
+                }
+                let (variants, matched_chr) = (page.variants, page.matched_chromosome);
+                if page.cancelled {
+                    return Ok(ExportBedResponse {
+                        status: QueryStatus::Cancelled,
+                        reference_genome: index.get_reference_genome(),
+                        query,
+                        matched_chromosome: matched_chr,
+                        available_chromosomes_sample: None,
+                        alternate_chromosome_suggestion: None,
+                        variant_count: 0,
+                        interval_count: 0,
+                        truncated: false,
+                        bed: String::new(),
+                    });
+                }
+
+                let mut intervals = bed_intervals(&variants, query.padding, query.merge);
+                let truncated = intervals.len() > MAX_BED_INTERVALS;
+                intervals.truncate(MAX_BED_INTERVALS);
+                let bed: String = intervals
+                    .iter()
+                    .map(|interval| interval.to_bed_line() + "\n")
+                    .collect();
+
+                let (status, available_sample, alternate_suggestion) =
+                    build_chromosome_response(index, &query.chromosome, &matched_chr);
+
+                Ok(ExportBedResponse {
+                    status,
+                    reference_genome: index.get_reference_genome(),
+                    query,
+                    matched_chromosome: matched_chr,
+                    available_chromosomes_sample: available_sample,
+                    alternate_chromosome_suggestion: alternate_suggestion,
+                    variant_count: variants.len(),
+                    interval_count: intervals.len(),
+                    truncated,
+                    bed,
+                })
+            })
+            .await?;
+
+        let payload = serde_json::to_value(response).map_err(|e| {
+            McpError::internal_error(
+                format!("Failed to serialize export_bed response: {}", e),
+                None,
+            )
+        })?;
+
+        let content = Content::json(payload)?;
+        Ok(CallToolResult::success(vec![content]))
+    }
+
+    #[tool(
+        description = "Write the variants of a region, optionally filtered, to a bgzipped and tabix-indexed VCF in the server's output directory (--allow-write-dir), keeping the file's full header and every sample, for use with bcftools and other tools on the server. Returns the file's path and record count. Existing files are only replaced with overwrite: true.",
+        annotations(
+            title = "Export a region as VCF",
+            read_only_hint = false,
+            destructive_hint = true,
+            idempotent_hint = true,
+            open_world_hint = false
+        )
+    )]
+    async fn export_region(
+        &self,
+        Parameters(params): Parameters<ExportRegionParams>,
+        context: RequestContext<RoleServer>,
+    ) -> Result<CallToolResult, McpError> {
+        self.export_region_file(params, CallContext::from_request(&context))
+            .await
+    }
+
+    // export_region without the request context, so tests can call it directly
+    async fn export_region_file(
+        &self,
+        params: ExportRegionParams,
+        context: CallContext,
+    ) -> Result<CallToolResult, McpError> {
+        let (start, end) = params
+            .coordinate_system
+            .to_vcf_region(params.start, params.end);
+        let output = params
+            .output
+            .unwrap_or_else(|| format!("{}_{}-{}.vcf.gz", params.chromosome, start, end));
+        let path = self.export_path("export_region", &output, ".vcf.gz", params.overwrite)?;
+        let query = ExportRegionQuery {
+            filter: params.shortcuts.combine(&params.filter)?,
+            record_filter: params.shortcuts.record_filter(),
+            chromosome: params.chromosome,
+            start,
+            end,
+            has_info_flags: params.has_info_flags,
+        };
+
+        let response = self
+            .run_monitored_query(context, move |index, monitor| {
+                check_info_flags(index, &query.has_info_flags)?;
+                check_filter(index, &query.filter)?;
+                check_required_info(index, &query.record_filter.required_info_keys())?;
+
+                let options = QueryOptions {
+                    filter: query.filter.clone(),
+                    info_flags: query.has_info_flags.clone(),
+                    record_filter: query.record_filter.clone(),
+                    ..Default::default()
+                };
+                let page = index.query_region_with_progress(
+                    &query.chromosome,
+                    query.start,
+                    query.end,
+                    &options,
+                    &mut |_| monitor.scanned(),
+                );
+                if let Some(exceeded) = &page.limit_exceeded {
+                    return Err(limit_error("export_region", exceeded));
+                }
+                if let Some(failure) = &page.read_failure {
+                    return Err(read_error("export_region", failure));
+                }
+                let (variants, matched_chr) = (page.variants, page.matched_chromosome);
+                if page.cancelled {
+                    return Ok(ExportRegionResponse {
+                        status: QueryStatus::Cancelled,
+                        reference_genome: index.get_reference_genome(),
+                        query,
+                        matched_chromosome: matched_chr,
+                        available_chromosomes_sample: None,
+                        alternate_chromosome_suggestion: None,
+                        path: None,
+                        index_path: None,
+                        record_count: 0,
+                    });
+                }
+
+                let index_path = PathBuf::from(format!("{}.tbi", path.display()));
+                if let Err(e) = index.write_bgzipped_vcf(&path, &variants) {
+                    // Leave no partial file behind
+                    let _ = std::fs::remove_file(&path);
+                    let _ = std::fs::remove_file(&index_path);
+                    return Err(McpError::internal_error(
+                        format!("Failed to write {}: {}", path.display(), e),
+                        None,
+                    ));
+                }
+
+                let (status, available_sample, alternate_suggestion) =
+                    build_chromosome_response(index, &query.chromosome, &matched_chr);
+
+                Ok(ExportRegionResponse {
+                    status,
+                    reference_genome: index.get_reference_genome(),
+                    query,
+                    matched_chromosome: matched_chr,
+                    available_chromosomes_sample: available_sample,
+                    alternate_chromosome_suggestion: alternate_suggestion,
+                    path: Some(path.display().to_string()),
+                    index_path: Some(index_path.display().to_string()),
+                    record_count: variants.len(),
+                })
+            })
+            .await?;
+
+        let payload = serde_json::to_value(response).map_err(|e| {
+            McpError::internal_error(
+                format!("Failed to serialize export_region response: {}", e),
+                None,
+            )
+        })?;
+
+        let content = Content::json(payload)?;
+        Ok(CallToolResult::success(vec![content]))
+    }
+
+    #[tool(
+        description = "Export the variants of a region as a minimal MAF (Mutation Annotation Format) table for cancer genomics tools such as maftools: Hugo_Symbol, positions, Variant_Classification (from the most severe SnpEff ANN or VEP CSQ consequence, mapped as vcf2maf does), Variant_Type, MAF-style alleles, Tumor_Sample_Barcode, HGVSp_Short and dbSNP_RS. One row per ALT allele and sample carrying it (samples defaults to all). Needs ANN or CSQ annotations. At most 10,000 rows.",
+        annotations(
+            title = "Export as MAF",
+            read_only_hint = true,
+            destructive_hint = false,
+            idempotent_hint = true,
+            open_world_hint = false
+        )
+    )]
+    async fn export_maf(
+        &self,
+        Parameters(params): Parameters<ExportMafParams>,
+        context: RequestContext<RoleServer>,
+    ) -> Result<CallToolResult, McpError> {
+        self.export_maf_rows(params, CallContext::from_request(&context))
+            .await
+    }
+
+    // export_maf without the request context, so tests can call it directly
+    async fn export_maf_rows(
+        &self,
+        params: ExportMafParams,
+        context: CallContext,
+    ) -> Result<CallToolResult, McpError> {
+        let annotations = ConsequenceAnnotations::from_header(
+            &header_definitions(&self.index().get_header_string(None)).info,
+        );
+        if annotations.is_empty() {
+            return Err(McpError::invalid_params(
+                "export_maf needs consequence annotations (an ANN or CSQ INFO field with Allele and Annotation or Consequence subfields), and this file has none; annotate it with SnpEff or VEP first, or use annotate_via_vep for a few variants".to_string(),
+                None,
+            ));
+        }
+        let (start, end) = params
+            .coordinate_system
+            .to_vcf_region(params.start, params.end);
+        let query = MafQuery {
+            filter: params.shortcuts.combine(&params.filter)?,
+            record_filter: params.shortcuts.record_filter(),
+            chromosome: params.chromosome,
+            start,
+            end,
+            samples: params.samples,
+            has_info_flags: params.has_info_flags,
+        };
+
+        let response = self
+            .run_monitored_query(context, move |index, monitor| {
+                check_info_flags(index, &query.has_info_flags)?;
+                check_filter(index, &query.filter)?;
+                check_required_info(index, &query.record_filter.required_info_keys())?;
+                let samples: Vec<(String, usize)> = if query.samples.is_empty() {
+                    index
+                        .sample_names(0, index.sample_count())
+                        .into_iter()
+                        .enumerate()
+                        .map(|(column, name)| (name, column))
+                        .collect()
+                } else {
+                    query
+                        .samples
+                        .iter()
+                        .map(|name| Ok((name.clone(), check_sample(index, name)?)))
+                        .collect::<Result<_, McpError>>()?
+                };
+
+                let options = QueryOptions {
+                    filter: query.filter.clone(),
+                    info_flags: query.has_info_flags.clone(),
+                    record_filter: query.record_filter.clone(),
+                    ..Default::default()
+                };
+                let page = index.query_region_with_progress(
+                    &query.chromosome,
+                    query.start,
+                    query.end,
+                    &options,
+                    &mut |_| monitor.scanned(),
+                );
+                if let Some(exceeded) = &page.limit_exceeded {
+                    return Err(limit_error("export_maf", exceeded));
+                }
+                if let Some(failure) = &page.read_failure {
+                    return Err(read_error("export_maf", failure));
+                }
+                let (variants, matched_chr) = (page.variants, page.matched_chromosome);
+                let mut response = ExportMafResponse {
+                    status: QueryStatus::Cancelled,
+                    reference_genome: index.get_reference_genome(),
+                    query,
+                    matched_chromosome: matched_chr,
+                    available_chromosomes_sample: None,
+                    alternate_chromosome_suggestion: None,
+                    variant_count: 0,
+                    row_count: 0,
+                    truncated: false,
+                    maf: String::new(),
+                };
+                if page.cancelled {
+                    return Ok(response);
+                }
+
+                let mut rows: Vec<String> = Vec::new();
+                for variant in &variants {
+                    let info = variant.raw_row.split('\t').nth(7).unwrap_or_default();
+                    let single_alternate = variant.alternate.len() == 1;
+                    for (allele_index, alternate) in variant.alternate.iter().enumerate() {
+                        let Some(allele) =
+                            maf::maf_allele(variant.position, &variant.reference, alternate)
+                        else {
+                            continue;
+                        };
+                        let consequence = annotations.consequence(
+                            info,
+                            alternate,
+                            &allele.alternate,
+                            single_alternate,
+                        );
+                        let carries = |column: usize| {
+                            sample_genotype(variant, column).is_some_and(|genotype| {
+                                genotype
+                                    .split(['/', '|'])
+                                    .any(|a| a.parse::<usize>() == Ok(allele_index + 1))
+                            })
+                        };
+                        // A sites-only file has no samples to attribute the allele to
+                        let barcodes: Vec<&str> = if index.sample_count() == 0 {
+                            vec![""]
+                        } else {
+                            samples
+                                .iter()
+                                .filter(|(_, column)| carries(*column))
+                                .map(|(name, _)| name.as_str())
+                                .collect()
+                        };
+                        for barcode in barcodes {
+                            rows.push(
+                                [
+                                    consequence
+                                        .as_ref()
+                                        .and_then(|c| c.gene.clone())
+                                        .unwrap_or_else(|| "Unknown".to_string()),
+                                    variant.chromosome.clone(),
+                                    allele.start.to_string(),
+                                    allele.end.to_string(),
+                                    consequence
+                                        .as_ref()
+                                        .map(|c| {
+                                            maf::variant_classification(
+                                                &c.term,
+                                                allele.variant_type,
+                                            )
+                                        })
+                                        .unwrap_or("Targeted_Region")
+                                        .to_string(),
+                                    allele.variant_type.to_string(),
+                                    allele.reference.clone(),
+                                    allele.alternate.clone(),
+                                    barcode.to_string(),
+                                    consequence
+                                        .as_ref()
+                                        .and_then(|c| c.protein_change.as_deref())
+                                        .map(maf::short_protein_change)
+                                        .unwrap_or_default(),
+                                    variant
+                                        .ids
+                                        .iter()
+                                        .find(|id| id.starts_with("rs"))
+                                        .cloned()
+                                        .unwrap_or_default(),
+                                ]
+                                .join("\t"),
+                            );
+                        }
+                    }
+                }
+                let truncated = rows.len() > MAX_MAF_ROWS;
+                rows.truncate(MAX_MAF_ROWS);
+
+                let (status, available_sample, alternate_suggestion) = build_chromosome_response(
+                    index,
+                    &response.query.chromosome,
+                    &response.matched_chromosome,
+                );
+                response.status = status;
+                response.available_chromosomes_sample = available_sample;
+                response.alternate_chromosome_suggestion = alternate_suggestion;
+                response.variant_count = variants.len();
+                response.row_count = rows.len();
+                response.truncated = truncated;
+                response.maf = format!("#version 2.4\n{}\n", MAF_COLUMNS.join("\t"));
+                for row in rows {
+                    response.maf.push_str(&row);
+                    response.maf.push('\n');
+                }
+                Ok(response)
+            })
+            .await?;
+
+        let payload = serde_json::to_value(response).map_err(|e| {
+            McpError::internal_error(
+                format!("Failed to serialize export_maf response: {}", e),
+                None,
+            )
+        })?;
+
+        let content = Content::json(payload)?;
+        Ok(CallToolResult::success(vec![content]))
+    }
+
+    #[tool(
+        description = "Query the variants of many intervals at once from BED text (inline in bed, or a file under the server's --bed-dir in path). BED coordinates are 0-based and end-exclusive: \"chr1 99 200\" covers VCF positions 100-200. Results are per interval, grouped by the optional name column (e.g., the exons of a gene); variants keep their VCF positions. At most 1,000 intervals.",
+        annotations(
+            title = "Query BED intervals",
+            read_only_hint = true,
+            destructive_hint = false,
+            idempotent_hint = true,
+            open_world_hint = false
+        )
+    )]
+    async fn query_by_bed(
+        &self,
+        Parameters(params): Parameters<QueryByBedParams>,
+        context: RequestContext<RoleServer>,
+    ) -> Result<CallToolResult, McpError> {
+        self.query_bed_intervals(params, CallContext::from_request(&context))
+            .await
+    }
+
+    // query_by_bed without the request context, so tests can call it directly
+    async fn query_bed_intervals(
+        &self,
+        params: QueryByBedParams,
+        context: CallContext,
+    ) -> Result<CallToolResult, McpError> {
+        if params.limit_per_interval == 0 || params.limit_per_interval > REGION_PAGE_SIZE {
+            return Err(McpError::invalid_params(
+                format!(
+                    "limit_per_interval must be between 1 and {}, got {}",
+                    REGION_PAGE_SIZE, params.limit_per_interval
+                ),
+                None,
+            ));
+        }
+        let regions = self.bed_regions(params.bed.as_deref(), params.path.as_deref())?;
+        let query = BedFileQuery {
+            source: params.path.unwrap_or_else(|| "inline".to_string()),
+            limit_per_interval: params.limit_per_interval,
+            has_info_flags: params.has_info_flags,
+            filter: params.shortcuts.combine(&params.filter)?,
+            record_filter: params.shortcuts.record_filter(),
+        };
+
+        let response = self
+            .run_monitored_query(context, move |index, monitor| {
+                check_info_flags(index, &query.has_info_flags)?;
+                check_filter(index, &query.filter)?;
+                check_required_info(index, &query.record_filter.required_info_keys())?;
+                let options = QueryOptions {
+                    filter: query.filter.clone(),
+                    info_flags: query.has_info_flags.clone(),
+                    record_filter: query.record_filter.clone(),
+                    limit: Some(query.limit_per_interval),
+                    ..Default::default()
+                };
+
+                let interval_count = regions.len();
+                let mut groups: Vec<BedGroup> = Vec::new();
+                let mut unmatched_chromosomes: Vec<String> = Vec::new();
+                let mut cancelled = false;
+                for region in regions {
+                    let (start, end) = region.vcf_region();
+                    let page = index.query_region_with_progress(
+                        &region.chromosome,
+                        start,
+                        end,
+                        &options,
+                        &mut |_| monitor.scanned(),
+                    );
+                    if let Some(exceeded) = &page.limit_exceeded {
+                        return Err(limit_error("query_by_bed", exceeded));
+                    }
+                    if let Some(failure) = &page.read_failure {
+                        return Err(read_error("query_by_bed", failure));
+                    }
+                    if page.matched_chromosome.is_none()
+                        && !unmatched_chromosomes.contains(&region.chromosome)
+                    {
+                        unmatched_chromosomes.push(region.chromosome.clone());
+                    }
+                    let result = BedIntervalResult {
+                        matched_chromosome: page.matched_chromosome,
+                        variant_count: page.variants.len(),
+                        truncated: page.next_cursor.is_some(),
+                        variants: page.variants,
+                        region,
+                    };
+                    let group = match result.region.name.as_ref().and_then(|name| {
+                        groups
+                            .iter()
+                            .position(|group| group.name.as_ref() == Some(name))
+                    }) {
+                        Some(group) => &mut groups[group],
+                        None => {
+                            groups.push(BedGroup {
+                                name: result.region.name.clone(),
+                                variant_count: 0,
+                                intervals: Vec::new(),
+                            });
+                            groups.last_mut().expect("group just added")
+                        }
+                    };
+                    group.variant_count += result.variant_count;
+                    group.intervals.push(result);
+                    if page.cancelled {
+                        cancelled = true;
+                        break;
+                    }
+                }
+
+                Ok(QueryByBedResponse {
+                    status: if cancelled {
+                        QueryStatus::Cancelled
+                    } else {
+                        QueryStatus::Ok
+                    },
+                    reference_genome: index.get_reference_genome(),
+                    query,
+                    interval_count,
+                    variant_count: groups.iter().map(|group| group.variant_count).sum(),
+                    unmatched_chromosomes,
+                    groups,
+                })
+            })
+            .await?;
+
+        let payload = serde_json::to_value(response).map_err(|e| {
+            McpError::internal_error(
+                format!("Failed to serialize query_by_bed response: {}", e),
+                None,
+            )
+        })?;
+
+        let content = Content::json(payload)?;
+        Ok(CallToolResult::success(vec![content]))
+    }
+
+    #[tool(
+        description = "Cluster the variants in a region by proximity: neighbouring variants at most max_gap bases apart form one cluster. Reports each cluster's span, variant count and IDs. Dense clusters often mark alignment artifacts, repeats or complex loci whose individual calls deserve skepticism. No region size limit; output is capped at 1,000 clusters.",
+        annotations(
+            title = "Find variant clusters",
+            read_only_hint = true,
+            destructive_hint = false,
+            idempotent_hint = true,
+            open_world_hint = false
+        )
+    )]
+    async fn cluster_variants(
+        &self,
+        Parameters(mut params): Parameters<ClusterVariantsParams>,
+        context: RequestContext<RoleServer>,
+    ) -> Result<CallToolResult, McpError> {
+        (params.start, params.end) = params
+            .coordinate_system
+            .to_vcf_region(params.start, params.end);
+        let context = CallContext::from_request(&context);
+        let query = ClusterQuery {
+            filter: params.shortcuts.combine(&params.filter)?,
+            record_filter: params.shortcuts.record_filter(),
+            chromosome: params.chromosome,
+            start: params.start,
+            end: params.end,
+            max_gap: params.max_gap,
+            min_cluster_size: params.min_cluster_size,
+            has_info_flags: params.has_info_flags,
+        };
+
+        let response = self
+            .run_monitored_query(context, move |index, monitor| {
+                check_info_flags(index, &query.has_info_flags)?;
+                check_filter(index, &query.filter)?;
+                check_required_info(index, &query.record_filter.required_info_keys())?;
+
+                let options = QueryOptions {
+                    filter: query.filter.clone(),
+                    info_flags: query.has_info_flags.clone(),
+                    record_filter: query.record_filter.clone(),
+                    ..Default::default()
+                };
+                let page = index.query_region_with_progress(
+                    &query.chromosome,
+                    query.start,
+                    query.end,
+                    &options,
+                    &mut |_| monitor.scanned(),
+                );
+                if let Some(exceeded) = &page.limit_exceeded {
+                    return Err(limit_error("cluster_variants", exceeded));
+                }
+                if let Some(failure) = &page.read_failure {
+                    return Err(read_error("cluster_variants", failure));
+                }
+                let (variants, matched_chr) = (page.variants, page.matched_chromosome);
+                if page.cancelled {
+                    return Ok(ClusterVariantsResponse {
+                        status: QueryStatus::Cancelled,
+                        reference_genome: index.get_reference_genome(),
+                        query,
+                        matched_chromosome: matched_chr,
+                        available_chromosomes_sample: None,
+                        alternate_chromosome_suggestion: None,
+                        variant_count: 0,
+                        cluster_count: 0,
+                        truncated: false,
+                        clusters: Vec::new(),
+                    });
+                }
+
+                let mut clusters =
+                    cluster_variants(&variants, query.max_gap, query.min_cluster_size);
+                let truncated = clusters.len() > MAX_CLUSTERS;
+                clusters.truncate(MAX_CLUSTERS);
+
+                let (status, available_sample, alternate_suggestion) =
+                    build_chromosome_response(index, &query.chromosome, &matched_chr);
+
+                Ok(ClusterVariantsResponse {
+                    status,
+                    reference_genome: index.get_reference_genome(),
+                    query,
+                    matched_chromosome: matched_chr,
+                    available_chromosomes_sample: available_sample,
+                    alternate_chromosome_suggestion: alternate_suggestion,
+                    variant_count: variants.len(),
+                    cluster_count: clusters.len(),
+                    truncated,
+                    clusters,
+                })
+            })
+            .await?;
+
+        let payload = serde_json::to_value(response).map_err(|e| {
+            McpError::internal_error(
+                format!("Failed to serialize cluster_variants response: {}", e),
+                None,
+            )
+        })?;
+
+        let content = Content::json(payload)?;
+        Ok(CallToolResult::success(vec![content]))
+    }
+
+    #[tool(
+        description = "Query variants by variant ID (e.g., rsID). Check the reference_genome field in the response to verify which genome build the coordinates use.",
+        annotations(
+            title = "Look up variants by ID",
+            read_only_hint = true,
+            destructive_hint = false,
+            idempotent_hint = true,
+            open_world_hint = true
+        )
+    )]
+    async fn query_by_id(
+        &self,
+        Parameters(QueryByIdParams {
+            id: requested_id,
+            shortcuts,
+            fields,
+            normalize,
+            vrs,
+            browser_links,
+            annotate,
+            resolve_via_ncbi,
+            format,
+        }): Parameters<QueryByIdParams>,
+    ) -> Result<CallToolResult, McpError> {
+        let filter = shortcuts.combine("")?;
+        #[cfg(feature = "network")]
+        let record_filter = shortcuts.record_filter();
+        check_fields(fields.as_deref())?;
+        check_output_format(format, fields.as_deref(), &self.index())?;
+        let reference = self.normalizer(normalize)?;
+        let vrs = self.vrs_source(vrs)?;
+        let browser = self.browser_build(browser_links)?;
+        let annotation = self.annotator(annotate)?;
+        #[cfg(feature = "network")]
+        let dbsnp = match (resolve_via_ncbi, &self.dbsnp) {
+            (false, _) => None,
+            (true, Some(client)) => Some(Arc::clone(client)),
+            (true, None) => {
+                return Err(McpError::invalid_request(
+                    "resolve_via_ncbi sends the ID to the NCBI Variation API, and this server was started without --allow-network".to_string(),
+                    None,
+                ))
+            }
+        };
+        #[cfg(not(feature = "network"))]
+        if resolve_via_ncbi {
+            return Err(McpError::invalid_request(
+                "resolve_via_ncbi sends the ID to the NCBI Variation API, and this server was built without the network feature".to_string(),
+                None,
+            ));
+        }
+        #[cfg(feature = "network")]
+        let (lookup_reference, lookup_vrs, lookup_annotation) =
+            (reference.clone(), vrs.clone(), annotation.clone());
+        #[cfg_attr(not(feature = "network"), allow(unused_mut))]
+        let mut response = self
+            .run_query(move |index| {
+                let index_build = id_index_pending(index)?;
+                check_filter(index, &filter)?;
+                check_required_info(index, &shortcuts.record_filter().required_info_keys())?;
+                let mut variants = index
+                    .lookup_id(&requested_id)
+                    .map_err(|failure| read_error("query_by_id", &failure))?;
+                retain_matching(index, &filter, &shortcuts.record_filter(), &mut variants);
+
+                let count = variants.len();
+                let mut items: Vec<Variant> = variants.into_iter().map(format_variant).collect();
+                normalize_variants(reference.as_deref(), &mut items, CoordinateSystem::OneBased);
+                vrs_variants(vrs.as_deref(), &mut items, CoordinateSystem::OneBased);
+                spdi_variants(index, &mut items, CoordinateSystem::OneBased);
+                link_variants(browser, &mut items, CoordinateSystem::OneBased);
+                annotate_variants(
+                    annotation.as_deref(),
+                    &mut items,
+                    CoordinateSystem::OneBased,
+                )
+                .map_err(|failure| read_error("query_by_id (--annotation)", &failure))?;
+                let result = QueryResult { count, items };
+
+                let status = if index.is_empty_file() {
+                    QueryStatus::EmptyFile
+                } else if index_build.is_some() {
+                    QueryStatus::IndexBuilding
+                } else if result.count > 0 {
+                    QueryStatus::Ok
+                } else {
+                    QueryStatus::NotFound
+                };
+
+                let reference_genome = index.get_reference_genome();
+
+                Ok(QueryByIdResponse {
+                    status,
+                    reference_genome,
+                    query: IdQuery {
+                        id: requested_id,
+                        filter,
+                        record_filter: shortcuts.record_filter(),
+                    },
+                    index_build,
+                    #[cfg(feature = "network")]
+                    remote_resolution: None,
+                    result,
+                })
+            })
+            .await?;
+
+        #[cfg(feature = "network")]
+        let number = dbsnp::rsid_number(&response.query.id).map(str::to_string);
+        #[cfg(feature = "network")]
+        let missing = matches!(response.status, QueryStatus::NotFound);
+        #[cfg(feature = "network")]
+        if let (Some(client), Some(number), true) = (dbsnp, number, missing) {
+            let build = self.index().get_metadata().reference_genome.build;
+            let placement = client.resolve(&number, &build).await.map_err(|e| {
+                let advice = if e.transient {
+                    "Repeating the call later may succeed."
+                } else {
+                    "The rsID can't be placed on this file's build."
+                };
+                McpError::internal_error(format!("{}. {}", e.message, advice), None)
+            })?;
+            let url = client.refsnp_url(&number);
+            let filter = response.query.filter.clone();
+            let (items, matched_records) = match placement.clone() {
+                None => (Vec::new(), 0),
+                Some(placement) => {
+                    self.run_query(move |index| {
+                        // One base before the placement for the anchor base of VCF indels
+                        let start = placement.position.saturating_sub(1).max(1);
+                        let end = placement.position + placement.reference.len() as u64;
+                        let mut variants = Vec::new();
+                        index
+                            .try_scan_region(&placement.chromosome, start, end, &[], &mut |v| {
+                                if dbsnp::matches_placement(&v, &placement) {
+                                    variants.push(v);
+                                }
+                                true
+                            })
+                            .map_err(|failure| {
+                                read_error("query_by_id (dbSNP position)", &failure)
+                            })?;
+                        let matched_records = variants.len();
+                        retain_matching(index, &filter, &record_filter, &mut variants);
+                        let mut items: Vec<Variant> =
+                            variants.into_iter().map(format_variant).collect();
+                        normalize_variants(
+                            lookup_reference.as_deref(),
+                            &mut items,
+                            CoordinateSystem::OneBased,
+                        );
+                        vrs_variants(
+                            lookup_vrs.as_deref(),
+                            &mut items,
+                            CoordinateSystem::OneBased,
+                        );
+                        spdi_variants(index, &mut items, CoordinateSystem::OneBased);
+                        link_variants(browser, &mut items, CoordinateSystem::OneBased);
+                        annotate_variants(
+                            lookup_annotation.as_deref(),
+                            &mut items,
+                            CoordinateSystem::OneBased,
+                        )
+                        .map_err(|failure| read_error("query_by_id (--annotation)", &failure))?;
+                        Ok((items, matched_records))
+                    })
+                    .await?
+                }
+            };
+            if !items.is_empty() {
+                response.status = QueryStatus::Ok;
+            }
+            response.result = QueryResult {
+                count: items.len(),
+                items,
+            };
+            response.remote_resolution = Some(RemoteIdResolution {
+                source: "NCBI Variation API (dbSNP)",
+                url,
+                placement,
+                matched_records,
+            });
+        }
+
+        let mut payload = serde_json::to_value(&response).map_err(|e| {
+            McpError::internal_error(
+                format!("Failed to serialize query_by_id response: {}", e),
+                None,
+            )
+        })?;
+        format_result_items(
+            &mut payload,
+            format,
+            fields.as_deref(),
+            &response.result.items,
+            &self.index(),
+        );
+
+        let content = Content::json(payload)?;
+
+        Ok(CallToolResult::success(vec![content]))
+    }
+
+    #[tool(
+        description = "Query variants by SPDI expression (sequence:position:deletion:insertion with a 0-based position, e.g. 'NC_000020.11:14369:G:A'), as dbSNP and ClinVar report them. RefSeq chromosome accessions are mapped to the VCF's chromosome names and must be on the file's build. Returns the records with an ALT allele making the same change, compared without anchor bases; each variant's own SPDI is in its spdi field.",
+        annotations(
+            title = "Look up a variant by SPDI",
+            read_only_hint = true,
+            destructive_hint = false,
+            idempotent_hint = true,
+            open_world_hint = false
+        )
+    )]
+    async fn query_by_spdi(
+        &self,
+        Parameters(QueryBySpdiParams {
+            spdi: expression,
+            shortcuts,
+            fields,
+            normalize,
+            vrs,
+            browser_links,
+            annotate,
+            format,
+        }): Parameters<QueryBySpdiParams>,
+    ) -> Result<CallToolResult, McpError> {
+        let requested =
+            Spdi::parse(&expression).map_err(|message| McpError::invalid_params(message, None))?;
+        let filter = shortcuts.combine("")?;
+        check_fields(fields.as_deref())?;
+        check_output_format(format, fields.as_deref(), &self.index())?;
+        let reference = self.normalizer(normalize)?;
+        let vrs = self.vrs_source(vrs)?;
+        let browser = self.browser_build(browser_links)?;
+        let annotation = self.annotator(annotate)?;
+
+        let response = self
+            .run_query(move |index| {
+                check_filter(index, &filter)?;
+                check_required_info(index, &shortcuts.record_filter().required_info_keys())?;
+                let chromosome = match spdi::accession_chromosome(&requested.sequence) {
+                    None => requested.sequence.clone(),
+                    Some((chromosome, accession_build)) => {
+                        let build = Build::from_name(&index.get_metadata().reference_genome.build);
+                        if let (Some(accession_build), Some(build)) = (accession_build, build) {
+                            if accession_build != build {
+                                return Err(McpError::invalid_params(
+                                    format!(
+                                        "{} is chromosome {} of {}, but this file is on {}, where it is {}",
+                                        requested.sequence,
+                                        chromosome,
+                                        spdi::build_name(accession_build),
+                                        spdi::build_name(build),
+                                        spdi::accession(chromosome, build).unwrap_or_default()
+                                    ),
+                                    None,
+                                ));
+                            }
+                        }
+                        chromosome.to_string()
+                    }
+                };
+
+                let trimmed = requested.trimmed();
+                // From the base before the change, for the anchor base of VCF indels
+                let start = trimmed.position.max(1);
+                let end = trimmed.position + trimmed.deletion.length() + 1;
+                let mut variants = Vec::new();
+                let matched_chr = index
+                    .try_scan_region(&chromosome, start, end, &[], &mut |variant| {
+                        let same_change = variant.alternate.iter().any(|alternate| {
+                            Spdi::from_vcf(
+                                &chromosome,
+                                variant.position,
+                                &variant.reference,
+                                alternate,
+                            )
+                            .is_some_and(|allele| trimmed.matches(&allele))
+                        });
+                        if same_change {
+                            variants.push(variant);
+                        }
+                        true
+                    })
+                    .map_err(|failure| read_error("query_by_spdi", &failure))?;
+                retain_matching(index, &filter, &shortcuts.record_filter(), &mut variants);
+                let count = variants.len();
+                let mut items: Vec<Variant> = variants.into_iter().map(format_variant).collect();
+                normalize_variants(reference.as_deref(), &mut items, CoordinateSystem::OneBased);
+                vrs_variants(vrs.as_deref(), &mut items, CoordinateSystem::OneBased);
+                spdi_variants(index, &mut items, CoordinateSystem::OneBased);
+                link_variants(browser, &mut items, CoordinateSystem::OneBased);
+                annotate_variants(
+                    annotation.as_deref(),
+                    &mut items,
+                    CoordinateSystem::OneBased,
+                )
+                .map_err(|failure| read_error("query_by_spdi (--annotation)", &failure))?;
+                let result = QueryResult { count, items };
+
+                let (status, available_sample, alternate_suggestion) =
+                    build_chromosome_response(index, &chromosome, &matched_chr);
+
+                Ok(QueryBySpdiResponse {
+                    status,
+                    reference_genome: index.get_reference_genome(),
+                    query: SpdiQuery {
+                        spdi: expression,
+                        chromosome,
+                        position: trimmed.position + 1,
+                        filter,
+                        record_filter: shortcuts.record_filter(),
+                    },
+                    matched_chromosome: matched_chr,
+                    available_chromosomes_sample: available_sample,
+                    alternate_chromosome_suggestion: alternate_suggestion,
+                    result,
+                })
+            })
+            .await?;
+
+        let mut payload = serde_json::to_value(&response).map_err(|e| {
+            McpError::internal_error(
+                format!("Failed to serialize query_by_spdi response: {}", e),
+                None,
+            )
+        })?;
+        format_result_items(
+            &mut payload,
+            format,
+            fields.as_deref(),
+            &response.result.items,
+            &self.index(),
+        );
+
+        let content = Content::json(payload)?;
+
+        Ok(CallToolResult::success(vec![content]))
+    }
+
+    #[tool(
+        description = "Search variant IDs by prefix or wildcard pattern (e.g., 'rs6054*'). Returns matching IDs with their chromosome/position locations, sorted by ID and capped by the limit parameter. Use query_by_id to fetch the full variant records.",
+        annotations(
+            title = "Search variant IDs",
+            read_only_hint = true,
+            destructive_hint = false,
+            idempotent_hint = true,
+            open_world_hint = false
+        )
+    )]
+    async fn search_ids(
+        &self,
+        Parameters(SearchIdsParams { pattern, limit }): Parameters<SearchIdsParams>,
+    ) -> Result<CallToolResult, McpError> {
+        if pattern.trim().is_empty() {
+            return Err(McpError::invalid_params(
+                "Pattern must not be empty. Use e.g. 'rs6054*' to search by prefix.",
+                None,
+            ));
+        }
+        let limit = limit.clamp(1, MAX_SEARCH_LIMIT);
+
+        let response = self
+            .run_query(move |index| {
+                let index_build = id_index_pending(index)?;
+                let (items, truncated) = index.search_ids(&pattern, limit);
+                let status = if index.is_empty_file() {
+                    QueryStatus::EmptyFile
+                } else if index_build.is_some() {
+                    QueryStatus::IndexBuilding
+                } else if items.is_empty() {
+                    QueryStatus::NotFound
+                } else {
+                    QueryStatus::Ok
+                };
+
+                Ok(SearchIdsResponse {
+                    status,
+                    reference_genome: index.get_reference_genome(),
+                    query: IdSearchQuery { pattern, limit },
+                    truncated,
+                    index_build,
+                    result: QueryResult {
+                        count: items.len(),
+                        items,
+                    },
+                })
+            })
+            .await?;
+
+        let payload = serde_json::to_value(response).map_err(|e| {
+            McpError::internal_error(
+                format!("Failed to serialize search_ids response: {}", e),
+                None,
+            )
+        })?;
+
+        let content = Content::json(payload)?;
+        Ok(CallToolResult::success(vec![content]))
+    }
+
+    #[tool(
+        description = "Get the raw VCF file header containing metadata and format definitions. By default, ##contig lines are excluded to reduce clutter. To include contig definitions, use the search parameter with '##contig'. To filter for specific header types, provide a search string (e.g., '##INFO' for INFO definitions, '##FILTER' for filter definitions, '##FORMAT' for format definitions). Set structured=true to also get those definitions as JSON with their Number, Type and Description, e.g. to find which INFO fields can be filtered on.",
+        annotations(
+            title = "VCF header",
+            read_only_hint = true,
+            destructive_hint = false,
+            idempotent_hint = true,
+            open_world_hint = false
+        )
+    )]
+    async fn get_vcf_header(
+        &self,
+        Parameters(params): Parameters<GetHeaderParams>,
+    ) -> Result<CallToolResult, McpError> {
+        let header_text = {
+            let index = &self.index();
+            index.get_header_string(params.search.as_deref())
+        };
+
+        let mut payload = serde_json::json!({
+            "header": header_text,
+            "line_count": header_text.lines().count(),
+            "search_applied": params.search,
+        });
+        if params.structured {
+            payload["definitions"] = serde_json::to_value(header_definitions(&header_text))
+                .map_err(|e| {
+                    McpError::internal_error(
+                        format!("Failed to serialize header definitions: {}", e),
+                        None,
+                    )
+                })?;
+        }
+
+        let content = Content::json(payload)?;
+        Ok(CallToolResult::success(vec![content]))
+    }
+
+    #[tool(
+        description = "Explain a FILTER value seen in query results (e.g., 'LowGQX'): returns its description from the VCF header's ##FILTER line and how many records in the file carry it. Several values can be given separated by ';'. Unknown values return status not_found with the list of declared filters.",
+        annotations(
+            title = "Explain a FILTER value",
+            read_only_hint = true,
+            destructive_hint = false,
+            idempotent_hint = true,
+            open_world_hint = false
+        )
+    )]
+    async fn explain_filter_value(
+        &self,
+        Parameters(ExplainFilterParams { filter }): Parameters<ExplainFilterParams>,
+    ) -> Result<CallToolResult, McpError> {
+        if filter.trim().is_empty() {
+            return Err(McpError::invalid_params(
+                "filter must name at least one FILTER value".to_string(),
+                None,
+            ));
+        }
+
+        let response = self
+            .run_query(move |index| {
+                let filters = index.explain_filter(&filter);
+                let statistics = index.compute_statistics().ok();
+                let all_declared = filters.iter().all(|f| f.declared);
+                Ok(ExplainFilterResponse {
+                    status: if all_declared {
+                        QueryStatus::Ok
+                    } else {
+                        QueryStatus::NotFound
+                    },
+                    query: FilterQuery { filter },
+                    filters,
+                    total_variants: statistics.as_ref().map(|stats| stats.total_variants),
+                    counts_pending: statistics.is_none(),
+                    declared_filters: (!all_declared).then(|| index.declared_filters()),
+                })
+            })
+            .await?;
+
+        let payload = serde_json::to_value(response).map_err(|e| {
+            McpError::internal_error(
+                format!("Failed to serialize explain_filter_value response: {}", e),
+                None,
+            )
+        })?;
+
+        let content = Content::json(payload)?;
+        Ok(CallToolResult::success(vec![content]))
+    }
+
+    #[tool(
+        description = "List the INFO field definitions from the VCF header: id, number, type and description of each, plus the subfield names of annotation fields such as CSQ (VEP) or ANN (SnpEff). Use it to see which INFO fields can be filtered on and to explain a field to the user. Pass ids to look up specific fields.",
+        annotations(
+            title = "INFO field definitions",
+            read_only_hint = true,
+            destructive_hint = false,
+            idempotent_hint = true,
+            open_world_hint = false
+        )
+    )]
+    async fn get_info_field_definitions(
+        &self,
+        Parameters(FieldDefinitionsParams { ids }): Parameters<FieldDefinitionsParams>,
+    ) -> Result<CallToolResult, McpError> {
+        let declared = header_definitions(&self.index().get_header_string(None)).info;
+        let payload = serde_json::to_value(select_definitions(declared, &ids)).map_err(|e| {
+            McpError::internal_error(
+                format!(
+                    "Failed to serialize get_info_field_definitions response: {}",
+                    e
+                ),
+                None,
+            )
+        })?;
+
+        let content = Content::json(payload)?;
+        Ok(CallToolResult::success(vec![content]))
+    }
+
+    #[tool(
+        description = "List the FORMAT (per-sample) field definitions from the VCF header, such as GT, AD or DP: id, number, type and description of each. Pass ids to look up specific fields.",
+        annotations(
+            title = "FORMAT field definitions",
+            read_only_hint = true,
+            destructive_hint = false,
+            idempotent_hint = true,
+            open_world_hint = false
+        )
+    )]
+    async fn get_format_field_definitions(
+        &self,
+        Parameters(FieldDefinitionsParams { ids }): Parameters<FieldDefinitionsParams>,
+    ) -> Result<CallToolResult, McpError> {
+        let declared = header_definitions(&self.index().get_header_string(None)).format;
+        let payload = serde_json::to_value(select_definitions(declared, &ids)).map_err(|e| {
+            McpError::internal_error(
+                format!(
+                    "Failed to serialize get_format_field_definitions response: {}",
+                    e
+                ),
+                None,
+            )
+        })?;
+
+        let content = Content::json(payload)?;
+        Ok(CallToolResult::success(vec![content]))
+    }
+
+    #[tool(
+        description = "Check whether any variant starts at a position, or within `window` bp of it, without fetching the variants. Answered from the ID index in microseconds when it is ready. Returns has_variant and up to 100 start positions.",
+        annotations(
+            title = "Check for a variant at a position",
+            read_only_hint = true,
+            destructive_hint = false,
+            idempotent_hint = true,
+            open_world_hint = false
+        )
+    )]
+    async fn has_variant_at(
+        &self,
+        Parameters(HasVariantAtParams {
+            chromosome,
+            position,
+            window,
+        }): Parameters<HasVariantAtParams>,
+    ) -> Result<CallToolResult, McpError> {
+        if window > MAX_VARIANT_WINDOW {
+            return Err(McpError::invalid_params(
+                format!(
+                    "window must be at most {} bp, got {}",
+                    MAX_VARIANT_WINDOW, window
+                ),
+                None,
+            ));
+        }
+
+        let response = self
+            .run_query(move |index| {
+                let (nearby, matched_chr) = index
+                    .variant_starts_near(&chromosome, position, window, NEARBY_POSITIONS_LIMIT)
+                    .map_err(|failure| read_error("has_variant_at", &failure))?;
+                let (status, available_sample, alternate_suggestion) =
+                    build_chromosome_response(index, &chromosome, &matched_chr);
+                Ok(HasVariantAtResponse {
+                    status,
+                    chromosome,
+                    position,
+                    window,
+                    matched_chromosome: matched_chr,
+                    available_chromosomes_sample: available_sample,
+                    alternate_chromosome_suggestion: alternate_suggestion,
+                    has_variant: !nearby.positions.is_empty(),
+                    nearby,
+                })
+            })
+            .await?;
+        let payload = serde_json::to_value(response).map_err(|e| {
+            McpError::internal_error(
+                format!("Failed to serialize has_variant_at response: {}", e),
+                None,
+            )
+        })?;
+
+        let content = Content::json(payload)?;
+        Ok(CallToolResult::success(vec![content]))
+    }
+
+    #[tool(
+        description = "Find the variants closest to a locus: those starting at the position, and the nearest ones upstream (lower coordinates) and downstream (higher coordinates) with their distance in bp. Searches outward from the position through the tabix index, widening until both sides have a variant or max_distance (default 100 kb) is reached. For \"is there anything near this locus\" questions.",
+        annotations(
+            title = "Find the nearest variants",
+            read_only_hint = true,
+            destructive_hint = false,
+            idempotent_hint = true,
+            open_world_hint = false
+        )
+    )]
+    async fn find_nearest_variant(
+        &self,
+        Parameters(FindNearestVariantParams {
+            chromosome,
+            position,
+            max_distance,
+        }): Parameters<FindNearestVariantParams>,
+    ) -> Result<CallToolResult, McpError> {
+        if max_distance > MAX_NEAREST_DISTANCE {
+            return Err(McpError::invalid_params(
+                format!(
+                    "max_distance must be at most {} bp, got {}",
+                    MAX_NEAREST_DISTANCE, max_distance
+                ),
+                None,
+            ));
+        }
+
+        let response = self
+            .run_query(move |index| {
+                let (nearest, matched_chr) = index
+                    .nearest_variants(&chromosome, position, max_distance)
+                    .map_err(|failure| read_error("find_nearest_variant", &failure))?;
+                let (mut status, available_sample, alternate_suggestion) =
+                    build_chromosome_response(index, &chromosome, &matched_chr);
+                if matches!(status, QueryStatus::Ok)
+                    && nearest.at_position.is_empty()
+                    && nearest.upstream.is_none()
+                    && nearest.downstream.is_none()
+                {
+                    status = QueryStatus::NotFound;
+                }
+                Ok(FindNearestVariantResponse {
+                    status,
+                    reference_genome: index.get_reference_genome(),
+                    chromosome,
+                    position,
+                    max_distance,
+                    matched_chromosome: matched_chr,
+                    available_chromosomes_sample: available_sample,
+                    alternate_chromosome_suggestion: alternate_suggestion,
+                    nearest,
+                })
+            })
+            .await?;
+        let payload = serde_json::to_value(response).map_err(|e| {
+            McpError::internal_error(
+                format!("Failed to serialize find_nearest_variant response: {}", e),
+                None,
+            )
+        })?;
+
+        let content = Content::json(payload)?;
+        Ok(CallToolResult::success(vec![content]))
+    }
+
+    #[tool(
+        description = "Find which samples carry an ALT allele at a site: decodes every sample's genotype (GT) of the record at chromosome:position with that ALT and returns heterozygous and homozygous-ALT sample lists with their counts, plus the number of no-calls. For cohort VCFs. If no record there has the ALT, status is not_found and the alleles present are listed.",
+        annotations(
+            title = "Find carriers of an allele",
+            read_only_hint = true,
+            destructive_hint = false,
+            idempotent_hint = true,
+            open_world_hint = false
+        )
+    )]
+    async fn find_carriers(
+        &self,
+        Parameters(FindCarriersParams {
+            chromosome,
+            position,
+            alt,
+            limit,
+        }): Parameters<FindCarriersParams>,
+    ) -> Result<CallToolResult, McpError> {
+        if alt.trim().is_empty() {
+            return Err(McpError::invalid_params(
+                "alt must name an ALT allele".to_string(),
+                None,
+            ));
+        }
+        let limit = limit.clamp(1, MAX_SAMPLES_LIMIT);
+
+        let response = self
+            .run_query(move |index| {
+                // Records overlapping the position may start before it; only those starting
+                // there describe this site
+                let mut records = Vec::new();
+                let matched_chr = index
+                    .try_scan_region(&chromosome, position, position, &[], &mut |variant| {
+                        if variant.position == position {
+                            records.push(variant);
+                        }
+                        true
+                    })
+                    .map_err(|failure| read_error("find_carriers", &failure))?;
+                let (mut status, available_sample, alternate_suggestion) =
+                    build_chromosome_response(index, &chromosome, &matched_chr);
+
+                let found = records.iter().find_map(|variant| {
+                    let slot = variant
+                        .alternate
+                        .iter()
+                        .position(|allele| allele.eq_ignore_ascii_case(alt.trim()))?;
+                    Some((variant, slot + 1))
+                });
+                let carriers =
+                    found.map(|(variant, allele)| index.allele_carriers(variant, allele, limit));
+                if matched_chr.is_some() && found.is_none() {
+                    status = QueryStatus::NotFound;
+                }
+
+                Ok(FindCarriersResponse {
+                    status,
+                    reference_genome: index.get_reference_genome(),
+                    reference: found.map(|(variant, _)| variant.reference.clone()),
+                    alleles_at_position: (matched_chr.is_some() && found.is_none()).then(|| {
+                        records
+                            .iter()
+                            .map(|variant| {
+                                format!("{}>{}", variant.reference, variant.alternate.join(","))
+                            })
+                            .collect()
+                    }),
+                    query: CarrierQuery {
+                        chromosome,
+                        position,
+                        alt,
+                    },
+                    matched_chromosome: matched_chr,
+                    available_chromosomes_sample: available_sample,
+                    alternate_chromosome_suggestion: alternate_suggestion,
+                    total_samples: index.sample_count(),
+                    carriers,
+                })
+            })
+            .await?;
+        let payload = serde_json::to_value(response).map_err(|e| {
+            McpError::internal_error(
+                format!("Failed to serialize find_carriers response: {}", e),
+                None,
+            )
+        })?;
+
+        let content = Content::json(payload)?;
+        Ok(CallToolResult::success(vec![content]))
+    }
+
+    #[tool(
+        description = "Find sites in a region where a trio's genotypes violate Mendelian inheritance: the child's (proband's) two alleles cannot be split into one from the mother and one from the father. Name the proband, mother and father samples, or omit them to use the trio from the server's --ped file. Reports each inconsistent site with the three GTs and whether the child has an allele neither parent carries (novel_allele, a de novo candidate) or not (parental_mismatch). Only diploid calls without missing alleles are checked.",
+        annotations(
+            title = "Find Mendelian errors",
+            read_only_hint = true,
+            destructive_hint = false,
+            idempotent_hint = true,
+            open_world_hint = false
+        )
+    )]
+    async fn find_mendelian_errors(
+        &self,
+        Parameters(FindMendelianErrorsParams {
+            chromosome,
+            start,
+            end,
+            coordinate_system,
+            proband,
+            mother,
+            father,
+            limit,
+        }): Parameters<FindMendelianErrorsParams>,
+    ) -> Result<CallToolResult, McpError> {
+        let (start, end) = coordinate_system.to_vcf_region(start, end);
+        if start > end {
+            return Err(McpError::invalid_params(
+                format!("start ({}) must not be after end ({})", start, end),
+                None,
+            ));
+        }
+        let limit = limit.clamp(1, MAX_TRIO_SITES_LIMIT);
+        let trio = self.resolve_trio(proband, mother, father)?;
+
+        let response = self
+            .run_query(move |index| {
+                let columns = [
+                    check_sample(index, &trio.proband)?,
+                    check_sample(index, &trio.mother)?,
+                    check_sample(index, &trio.father)?,
+                ];
+                let mut errors = Vec::new();
+                let (mut sites_checked, mut sites_skipped, mut error_count) = (0, 0, 0);
+                let matched_chr = index
+                    .try_scan_region(&chromosome, start, end, &[], &mut |variant| {
+                        let [proband_gt, mother_gt, father_gt] =
+                            columns.map(|column| sample_genotype(&variant, column).unwrap_or("."));
+                        let kind = pedigree::check_trio(proband_gt, mother_gt, father_gt);
+                        match kind {
+                            MendelianCheck::Unchecked => sites_skipped += 1,
+                            MendelianCheck::Consistent => sites_checked += 1,
+                            MendelianCheck::NovelAllele | MendelianCheck::ParentalMismatch => {
+                                sites_checked += 1;
+                                error_count += 1;
+                                if errors.len() < limit {
+                                    errors.push(MendelianError {
+                                        position: variant.position,
+                                        id: variant.id.clone(),
+                                        reference: variant.reference.clone(),
+                                        alternate: variant.alternate.clone(),
+                                        proband_gt: proband_gt.to_string(),
+                                        mother_gt: mother_gt.to_string(),
+                                        father_gt: father_gt.to_string(),
+                                        kind,
+                                    });
+                                }
+                            }
+                        }
+                        true
+                    })
+                    .map_err(|failure| read_error("find_mendelian_errors", &failure))?;
+                let (status, available_sample, alternate_suggestion) =
+                    build_chromosome_response(index, &chromosome, &matched_chr);
+
+                Ok(MendelianErrorsResponse {
+                    status,
+                    reference_genome: index.get_reference_genome(),
+                    query: MendelianQuery {
+                        chromosome,
+                        start,
+                        end,
+                    },
+                    matched_chromosome: matched_chr,
+                    available_chromosomes_sample: available_sample,
+                    alternate_chromosome_suggestion: alternate_suggestion,
+                    trio,
+                    sites_checked,
+                    sites_skipped,
+                    error_count,
+                    truncated: error_count > errors.len() as u64,
+                    errors,
+                })
+            })
+            .await?;
+        let payload = serde_json::to_value(response).map_err(|e| {
+            McpError::internal_error(
+                format!("Failed to serialize find_mendelian_errors response: {}", e),
+                None,
+            )
+        })?;
+
+        let content = Content::json(payload)?;
+        Ok(CallToolResult::success(vec![content]))
+    }
+
+    #[tool(
+        description = "Find de novo candidates in a region: variants where a trio's child (proband) has an ALT allele and both parents are called hom-ref (0/0), with every call meeting min_gq (default 20) and min_dp (default 10). Name the proband, mother and father samples, or omit them to use the trio from the server's --ped file. Returns each candidate with the three calls' GT, GQ, DP and AD.",
+        annotations(
+            title = "Find de novo candidates",
+            read_only_hint = true,
+            destructive_hint = false,
+            idempotent_hint = true,
+            open_world_hint = false
+        )
+    )]
+    async fn find_de_novo(
+        &self,
+        Parameters(FindDeNovoParams {
+            chromosome,
+            start,
+            end,
+            coordinate_system,
+            proband,
+            mother,
+            father,
+            min_gq,
+            min_dp,
+            limit,
+        }): Parameters<FindDeNovoParams>,
+    ) -> Result<CallToolResult, McpError> {
+        let (start, end) = coordinate_system.to_vcf_region(start, end);
+        if start > end {
+            return Err(McpError::invalid_params(
+                format!("start ({}) must not be after end ({})", start, end),
+                None,
+            ));
+        }
+        let limit = limit.clamp(1, MAX_TRIO_SITES_LIMIT);
+        let trio = self.resolve_trio(proband, mother, father)?;
+
+        let response = self
+            .run_query(move |index| {
+                let columns = [
+                    check_sample(index, &trio.proband)?,
+                    check_sample(index, &trio.mother)?,
+                    check_sample(index, &trio.father)?,
+                ];
+                let mut candidates = Vec::new();
+                let (mut candidate_count, mut filtered_by_quality) = (0, 0);
+                let matched_chr = index
+                    .try_scan_region(&chromosome, start, end, &[], &mut |variant| {
+                        let [proband_gt, mother_gt, father_gt] =
+                            columns.map(|column| sample_genotype(&variant, column).unwrap_or("."));
+                        if !pedigree::is_de_novo(proband_gt, mother_gt, father_gt) {
+                            return true;
+                        }
+                        let [proband, mother, father] =
+                            columns.map(|column| GenotypeCall::of(&variant, column));
+                        if ![&proband, &mother, &father]
+                            .iter()
+                            .all(|call| call.meets(min_gq, min_dp))
+                        {
+                            filtered_by_quality += 1;
+                            return true;
+                        }
+                        candidate_count += 1;
+                        if candidates.len() < limit {
+                            candidates.push(DeNovoCandidate {
+                                position: variant.position,
+                                id: variant.id.clone(),
+                                reference: variant.reference.clone(),
+                                alternate: variant.alternate.clone(),
+                                proband,
+                                mother,
+                                father,
+                            });
+                        }
+                        true
+                    })
+                    .map_err(|failure| read_error("find_de_novo", &failure))?;
+                let (status, available_sample, alternate_suggestion) =
+                    build_chromosome_response(index, &chromosome, &matched_chr);
+
+                Ok(DeNovoResponse {
+                    status,
+                    reference_genome: index.get_reference_genome(),
+                    query: DeNovoQuery {
+                        chromosome,
+                        start,
+                        end,
+                        min_gq,
+                        min_dp,
+                    },
+                    matched_chromosome: matched_chr,
+                    available_chromosomes_sample: available_sample,
+                    alternate_chromosome_suggestion: alternate_suggestion,
+                    trio,
+                    candidate_count,
+                    filtered_by_quality,
+                    truncated: candidate_count > candidates.len() as u64,
+                    candidates,
+                })
+            })
+            .await?;
+        let payload = serde_json::to_value(response).map_err(|e| {
+            McpError::internal_error(
+                format!("Failed to serialize find_de_novo response: {}", e),
+                None,
+            )
+        })?;
+
+        let content = Content::json(payload)?;
+        Ok(CallToolResult::success(vec![content]))
+    }
+
+    #[tool(
+        description = "Find compound heterozygous candidates for one sample in a region (typically a gene): pairs of heterozygous variants that may hit both copies of the gene. With ANN/CSQ gene annotations in the file only sites sharing a gene are paired, and `gene` keeps sites of one gene. Phased genotypes (GT with '|') classify each pair as trans (kept, listed first) or cis (excluded and counted); unphased pairs are listed with phase 'unknown'.",
+        annotations(
+            title = "Find compound heterozygotes",
+            read_only_hint = true,
+            destructive_hint = false,
+            idempotent_hint = true,
+            open_world_hint = false
+        )
+    )]
+    async fn find_compound_het(
+        &self,
+        Parameters(FindCompoundHetParams {
+            chromosome,
+            start,
+            end,
+            coordinate_system,
+            sample,
+            gene,
+            limit,
+        }): Parameters<FindCompoundHetParams>,
+    ) -> Result<CallToolResult, McpError> {
+        let (start, end) = coordinate_system.to_vcf_region(start, end);
+        if start > end {
+            return Err(McpError::invalid_params(
+                format!("start ({}) must not be after end ({})", start, end),
+                None,
+            ));
+        }
+        let limit = limit.clamp(1, MAX_TRIO_SITES_LIMIT);
+        let annotations = self.index().gene_annotations();
+        if gene.is_some() && annotations.is_empty() {
+            return Err(McpError::invalid_params(
+                "gene requires gene annotations (an ANN or CSQ INFO field with a Gene_Name, SYMBOL or Gene subfield), and this file has none; give the gene's start and end instead".to_string(),
+                None,
+            ));
+        }
+
+        let response = self
+            .run_query(move |index| {
+                let column = check_sample(index, &sample)?;
+                let mut sites: Vec<HeterozygousSite> = Vec::new();
+                let matched_chr = index
+                    .try_scan_region(&chromosome, start, end, &[], &mut |variant| {
+                        if let Some(site) = heterozygous_site(&variant, column, &annotations) {
+                            let in_gene = gene.as_ref().is_none_or(|gene| {
+                                site.genes
+                                    .iter()
+                                    .any(|name| name.eq_ignore_ascii_case(gene))
+                            });
+                            if in_gene {
+                                sites.push(site);
+                            }
+                        }
+                        sites.len() <= MAX_COMPOUND_HET_SITES
+                    })
+                    .map_err(|failure| read_error("find_compound_het", &failure))?;
+                if sites.len() > MAX_COMPOUND_HET_SITES {
+                    return Err(McpError::invalid_params(
+                        format!(
+                            "Sample {} has more than {} heterozygous sites in {}:{}-{}; narrow the region to a gene",
+                            sample, MAX_COMPOUND_HET_SITES, chromosome, start, end
+                        ),
+                        None,
+                    ));
+                }
+                let (status, available_sample, alternate_suggestion) =
+                    build_chromosome_response(index, &chromosome, &matched_chr);
+
+                let gene_annotated = !annotations.is_empty();
+                let (pairs, cis_pairs_excluded) = compound_het_pairs(&sites, gene_annotated);
+                let listed = pairs
+                    .iter()
+                    .take(limit)
+                    .map(|pair| CompoundHetCandidate {
+                        phase: pair.phase,
+                        first: sites[pair.sites[0]].clone(),
+                        second: sites[pair.sites[1]].clone(),
+                    })
+                    .collect();
+                Ok(CompoundHetResponse {
+                    status,
+                    reference_genome: index.get_reference_genome(),
+                    query: CompoundHetQuery {
+                        chromosome,
+                        start,
+                        end,
+                        sample,
+                        gene,
+                    },
+                    matched_chromosome: matched_chr,
+                    available_chromosomes_sample: available_sample,
+                    alternate_chromosome_suggestion: alternate_suggestion,
+                    gene_annotated,
+                    heterozygous_sites: sites.len(),
+                    pair_count: pairs.len(),
+                    cis_pairs_excluded,
+                    truncated: pairs.len() > limit,
+                    pairs: listed,
+                })
+            })
+            .await?;
+        let payload = serde_json::to_value(response).map_err(|e| {
+            McpError::internal_error(
+                format!("Failed to serialize find_compound_het response: {}", e),
+                None,
+            )
+        })?;
+
+        let content = Content::json(payload)?;
+        Ok(CallToolResult::success(vec![content]))
+    }
+
+    #[tool(
+        description = "Lift a position from another genome build to the VCF's build with the server's --chain file (e.g., hg19ToHg38.over.chain.gz). Returns every mapping with its strand and chain, highest-scoring first, and the VCF's name for the lifted chromosome. Positions in chain gaps have no mapping. The query tools accept liftover: true to lift their coordinates the same way.",
+        annotations(
+            title = "Lift over a position",
+            read_only_hint = true,
+            destructive_hint = false,
+            idempotent_hint = true,
+            open_world_hint = false
+        )
+    )]
+    async fn liftover_position(
+        &self,
+        Parameters(LiftoverPositionParams {
+            chromosome,
+            position,
+        }): Parameters<LiftoverPositionParams>,
+    ) -> Result<CallToolResult, McpError> {
+        let Some(chains) = self.chains.clone() else {
+            return Err(McpError::invalid_request(
+                "liftover_position needs a chain file, and this server was started without --chain"
+                    .to_string(),
+                None,
+            ));
+        };
+
+        let response = self
+            .run_query(move |index| {
+                let mappings: Vec<LiftoverMapping> = chains
+                    .lift(&chromosome, position)
+                    .into_iter()
+                    .map(|lifted| LiftoverMapping {
+                        matched_chromosome: index
+                            .chromosome_extent(&lifted.chromosome)
+                            .map(|(name, _)| name),
+                        lifted,
+                    })
+                    .collect();
+                Ok(LiftoverPositionResponse {
+                    status: if mappings.is_empty() {
+                        QueryStatus::NotFound
+                    } else {
+                        QueryStatus::Ok
+                    },
+                    reference_genome: index.get_reference_genome(),
+                    chain: chains.name().to_string(),
+                    query: LiftoverPositionQuery {
+                        chromosome,
+                        position,
+                    },
+                    mappings,
+                })
+            })
+            .await?;
+        let payload = serde_json::to_value(response).map_err(|e| {
+            McpError::internal_error(
+                format!("Failed to serialize liftover_position response: {}", e),
+                None,
+            )
+        })?;
+
+        let content = Content::json(payload)?;
+        Ok(CallToolResult::success(vec![content]))
+    }
+
+    #[tool(
+        description = "Check the file's genome build against its records rather than its header: looks for well-known rsIDs (APOE, MTHFR, F5, HBB, LCT, ...) whose GRCh37 and GRCh38 positions differ, by ID or by REF/ALT at either build's position, and reports which build the observed coordinates are consistent with, next to the build the header names or its chr1 length implies. Files carrying none of the markers (small panels) are undetermined.",
+        annotations(
+            title = "Validate the reference build",
+            read_only_hint = true,
+            destructive_hint = false,
+            idempotent_hint = true,
+            open_world_hint = false
+        )
+    )]
+    async fn validate_build(&self) -> Result<CallToolResult, McpError> {
+        let response = self
+            .run_query(|index| {
+                let mut markers = Vec::with_capacity(build_check::MARKERS.len());
+                for marker in build_check::MARKERS {
+                    let by_id = index
+                        .lookup_id(marker.rsid)
+                        .map_err(|failure| read_error("validate_build", &failure))?;
+                    let mut check = MarkerCheck {
+                        marker: *marker,
+                        observed: None,
+                        consistent_with: None,
+                    };
+                    // A record with the rsID at one of the marker's positions, else the first one
+                    let record = by_id
+                        .iter()
+                        .find(|v| marker.build_at(&v.chromosome, v.position).is_some())
+                        .or(by_id.first());
+                    if let Some(v) = record {
+                        check.consistent_with = marker.build_at(&v.chromosome, v.position);
+                        check.observed = Some(ObservedMarker {
+                            chromosome: v.chromosome.clone(),
+                            position: v.position,
+                            matched_by: "id",
+                        });
+                        markers.push(check);
+                        continue;
+                    }
+                    for build in [Build::Grch37, Build::Grch38] {
+                        let position = marker.position(build);
+                        let mut found = None;
+                        index
+                            .try_scan_region(marker.chromosome, position, position, &[], &mut |v| {
+                                if v.position == position
+                                    && marker.alleles_match(&v.reference, &v.alternate)
+                                {
+                                    found = Some(v.chromosome);
+                                    return false;
+                                }
+                                true
+                            })
+                            .map_err(|failure| read_error("validate_build", &failure))?;
+                        if let Some(chromosome) = found {
+                            check.consistent_with = Some(build);
+                            check.observed = Some(ObservedMarker {
+                                chromosome,
+                                position,
+                                matched_by: "alleles",
+                            });
+                            break;
+                        }
+                    }
+                    markers.push(check);
+                }
+
+                let matches = |build: Build| {
+                    markers
+                        .iter()
+                        .filter(|check| check.consistent_with == Some(build))
+                        .count()
+                };
+                let (grch37_matches, grch38_matches) =
+                    (matches(Build::Grch37), matches(Build::Grch38));
+                let verdict = build_check::verdict(grch37_matches, grch38_matches);
+                let header_build = Build::from_name(&index.get_metadata().reference_genome.build);
+                let observed_build = match verdict {
+                    Verdict::Grch37 => Some(Build::Grch37),
+                    Verdict::Grch38 => Some(Build::Grch38),
+                    Verdict::Conflicting | Verdict::Undetermined => None,
+                };
+                Ok(ValidateBuildResponse {
+                    status: QueryStatus::Ok,
+                    reference_genome: index.get_reference_genome(),
+                    header_build,
+                    verdict,
+                    agrees_with_header: header_build
+                        .zip(observed_build)
+                        .map(|(header, observed)| header == observed),
+                    markers_checked: markers.len(),
+                    grch37_matches,
+                    grch38_matches,
+                    markers,
+                })
+            })
+            .await?;
+        let payload = serde_json::to_value(response).map_err(|e| {
+            McpError::internal_error(
+                format!("Failed to serialize validate_build response: {}", e),
+                None,
+            )
+        })?;
+
+        let content = Content::json(payload)?;
+        Ok(CallToolResult::success(vec![content]))
+    }
+
+    #[tool(
+        description = "Compare two samples' genotypes over a region to sanity-check replicates or tumor/normal pairs. Counts concordant and discordant sites (same alleles regardless of phasing and order), reports overall and non-reference concordance, and lists the discordant sites with both GTs. Sites where either GT is missing are skipped.",
+        annotations(
+            title = "Compare two samples",
+            read_only_hint = true,
+            destructive_hint = false,
+            idempotent_hint = true,
+            open_world_hint = false
+        )
+    )]
+    async fn compare_samples(
+        &self,
+        Parameters(CompareSamplesParams {
+            chromosome,
+            start,
+            end,
+            coordinate_system,
+            sample_a,
+            sample_b,
+            limit,
+        }): Parameters<CompareSamplesParams>,
+    ) -> Result<CallToolResult, McpError> {
+        let (start, end) = coordinate_system.to_vcf_region(start, end);
+        if start > end {
+            return Err(McpError::invalid_params(
+                format!("start ({}) must not be after end ({})", start, end),
+                None,
+            ));
+        }
+        let limit = limit.clamp(1, MAX_TRIO_SITES_LIMIT);
+
+        let response = self
+            .run_query(move |index| {
+                let columns = [
+                    check_sample(index, &sample_a)?,
+                    check_sample(index, &sample_b)?,
+                ];
+                let mut discordant_sites = Vec::new();
+                let (mut sites_compared, mut sites_skipped) = (0, 0);
+                let (mut concordant, mut non_reference_sites, mut non_reference_concordant) =
+                    (0, 0, 0);
+                let matched_chr = index
+                    .try_scan_region(&chromosome, start, end, &[], &mut |variant| {
+                        let [genotype_a, genotype_b] =
+                            columns.map(|column| sample_genotype(&variant, column).unwrap_or("."));
+                        let (Some(alleles_a), Some(alleles_b)) =
+                            (unordered_alleles(genotype_a), unordered_alleles(genotype_b))
+                        else {
+                            sites_skipped += 1;
+                            return true;
+                        };
+                        sites_compared += 1;
+                        let non_reference =
+                            alleles_a.iter().chain(&alleles_b).any(|&allele| allele > 0);
+                        if non_reference {
+                            non_reference_sites += 1;
+                        }
+                        if alleles_a == alleles_b {
+                            concordant += 1;
+                            if non_reference {
+                                non_reference_concordant += 1;
+                            }
+                        } else if discordant_sites.len() < limit {
+                            discordant_sites.push(DiscordantSite {
+                                position: variant.position,
+                                id: variant.id.clone(),
+                                reference: variant.reference.clone(),
+                                alternate: variant.alternate.clone(),
+                                genotype_a: genotype_a.to_string(),
+                                genotype_b: genotype_b.to_string(),
+                            });
+                        }
+                        true
+                    })
+                    .map_err(|failure| read_error("compare_samples", &failure))?;
+                let (status, available_sample, alternate_suggestion) =
+                    build_chromosome_response(index, &chromosome, &matched_chr);
+                let rate = |part: u64, total: u64| (total > 0).then(|| part as f64 / total as f64);
+                let discordant = sites_compared - concordant;
+
+                Ok(CompareSamplesResponse {
+                    status,
+                    reference_genome: index.get_reference_genome(),
+                    query: CompareSamplesQuery {
+                        chromosome,
+                        start,
+                        end,
+                        sample_a,
+                        sample_b,
+                    },
+                    matched_chromosome: matched_chr,
+                    available_chromosomes_sample: available_sample,
+                    alternate_chromosome_suggestion: alternate_suggestion,
+                    sites_compared,
+                    sites_skipped,
+                    concordant,
+                    discordant,
+                    concordance: rate(concordant, sites_compared),
+                    non_reference_sites,
+                    non_reference_concordance: rate(non_reference_concordant, non_reference_sites),
+                    truncated: discordant > discordant_sites.len() as u64,
+                    discordant_sites,
+                })
+            })
+            .await?;
+        let payload = serde_json::to_value(response).map_err(|e| {
+            McpError::internal_error(
+                format!("Failed to serialize compare_samples response: {}", e),
+                None,
+            )
+        })?;
+
+        let content = Content::json(payload)?;
+        Ok(CallToolResult::success(vec![content]))
+    }
+
+    #[tool(
+        description = "List the file's sample names in header column order, a page at a time (offset/limit, default 1000 per page), with the total sample count. Use next_offset to fetch the following page.",
+        annotations(
+            title = "List samples",
+            read_only_hint = true,
+            destructive_hint = false,
+            idempotent_hint = true,
+            open_world_hint = false
+        )
+    )]
+    async fn get_samples(
+        &self,
+        Parameters(GetSamplesParams { offset, limit }): Parameters<GetSamplesParams>,
+    ) -> Result<CallToolResult, McpError> {
+        let limit = limit.clamp(1, MAX_SAMPLES_LIMIT);
+        let total_samples = self.index().sample_count();
+        let samples = self.index().sample_names(offset, limit);
+        let end = offset.saturating_add(samples.len());
+        let response = GetSamplesResponse {
+            status: if samples.is_empty() && offset > 0 {
+                QueryStatus::NotFound
+            } else {
+                QueryStatus::Ok
+            },
+            total_samples,
+            offset,
+            count: samples.len(),
+            samples,
+            next_offset: (end < total_samples).then_some(end),
+        };
+        let payload = serde_json::to_value(response).map_err(|e| {
+            McpError::internal_error(
+                format!("Failed to serialize get_samples response: {}", e),
+                None,
+            )
+        })?;
+
+        let content = Content::json(payload)?;
+        Ok(CallToolResult::success(vec![content]))
+    }
+
+    #[tool(
+        description = "List every contig (chromosome) of the file in natural order (1..22, X, Y, MT, then other scaffolds) with its declared length and, once statistics have been computed, its variant count. Use it to find the exact chromosome names the file uses.",
+        annotations(
+            title = "List contigs",
+            read_only_hint = true,
+            destructive_hint = false,
+            idempotent_hint = true,
+            open_world_hint = false
+        )
+    )]
+    async fn get_contigs(&self) -> Result<CallToolResult, McpError> {
+        // Read before the contigs, so statistics finishing in between cannot leave counts
+        // missing while the flag says they are ready
+        let counts_pending = !self.index().statistics_ready();
+        let contigs = self.index().contig_summaries();
+        let response = GetContigsResponse {
+            status: if self.index().is_empty_file() {
+                QueryStatus::EmptyFile
+            } else {
+                QueryStatus::Ok
+            },
+            count: contigs.len(),
+            counts_pending,
+            contigs,
+        };
+        let payload = serde_json::to_value(response).map_err(|e| {
+            McpError::internal_error(
+                format!("Failed to serialize get_contigs response: {}", e),
+                None,
+            )
+        })?;
+
+        let content = Content::json(payload)?;
+        Ok(CallToolResult::success(vec![content]))
+    }
+
+    #[tool(
+        description = "Get comprehensive summary statistics for the VCF file. Returns variant counts, quality statistics, filter distributions, chromosome information, and variant type breakdown. By default, limits variants_per_chromosome to top 25 chromosomes to reduce response size. Set max_chromosomes=0 to include all chromosomes. Statistics are computed once at server startup and cached for instant retrieval.",
+        annotations(
+            title = "File statistics",
+            read_only_hint = true,
+            destructive_hint = false,
+            idempotent_hint = true,
+            open_world_hint = false
+        )
+    )]
+    async fn get_statistics(
+        &self,
+        Parameters(params): Parameters<GetStatisticsParams>,
+    ) -> Result<CallToolResult, McpError> {
+        let mut stats = self
+            .run_query(|index| index.compute_statistics().map_err(statistics_error))
+            .await?;
+
+        // Limit variants_per_chromosome if requested
+        if params.max_chromosomes > 0
+            && stats.variants_per_chromosome.len() > params.max_chromosomes
+        {
+            // Sort chromosomes by variant count (descending) and keep top N
+            let mut chr_counts: Vec<_> = stats.variants_per_chromosome.iter().collect();
+            chr_counts.sort_by(|a, b| b.1.cmp(a.1));
+
+            let limited: HashMap<String, u64> = chr_counts
+                .into_iter()
+                .take(params.max_chromosomes)
+                .map(|(k, v)| (k.clone(), *v))
+                .collect();
+
+            stats.variants_per_chromosome = limited;
+        }
+
+        let mut payload = serde_json::to_value(stats).map_err(|e| {
+            McpError::internal_error(format!("Failed to serialize statistics: {}", e), None)
+        })?;
+        // Per-sample counts grow with the cohort; sample_qc reports them
+        if let Some(call_rates) = payload["call_rates"].as_object_mut() {
+            call_rates.remove("missing_per_sample");
+        }
+
+        let content = Content::json(payload)?;
+        Ok(CallToolResult::success(vec![content]))
+    }
+
+    #[tool(
+        description = "Standard QC ratios over the whole file (omit chromosome/start/end; uses the startup statistics) or a region: transition/transversion ratio of SNV alleles (ts_tv_ratio, ~2.0-2.1 expected for human WGS, ~3 for exomes), het/hom-alt ratio of diploid calls across all samples (het_hom_ratio) and indel/SNV ratio (indel_snp_ratio), with the underlying counts.",
+        annotations(
+            title = "QC metrics",
+            read_only_hint = true,
+            destructive_hint = false,
+            idempotent_hint = true,
+            open_world_hint = false
+        )
+    )]
+    async fn compute_qc_metrics(
+        &self,
+        Parameters(QcMetricsParams {
+            chromosome,
+            start,
+            end,
+            coordinate_system,
+        }): Parameters<QcMetricsParams>,
+    ) -> Result<CallToolResult, McpError> {
+        let response = match optional_region(chromosome, start, end, coordinate_system)? {
+            None => {
+                self.run_query(|index| {
+                    let stats = index.compute_statistics().map_err(statistics_error)?;
+                    Ok(QcMetricsResponse::new("file", stats.qc_metrics))
+                })
+                .await?
+            }
+            Some((chromosome, start, end)) => {
+                self.run_query(move |index| {
+                    let mut counts = QcMetrics::default();
+                    let matched_chr = index
+                        .try_scan_region(&chromosome, start, end, &[], &mut |variant| {
+                            counts.add(&variant);
+                            true
+                        })
+                        .map_err(|failure| read_error("compute_qc_metrics", &failure))?;
+                    let (status, available_sample, alternate_suggestion) =
+                        build_chromosome_response(index, &chromosome, &matched_chr);
+
+                    let mut response = QcMetricsResponse::new("region", counts);
+                    response.status = status;
+                    response.query = Some(QcRegionQuery {
+                        chromosome,
+                        start,
+                        end,
+                    });
+                    response.matched_chromosome = matched_chr;
+                    response.available_chromosomes_sample = available_sample;
+                    response.alternate_chromosome_suggestion = alternate_suggestion;
+                    Ok(response)
+                })
+                .await?
+            }
+        };
+        let payload = serde_json::to_value(response).map_err(|e| {
+            McpError::internal_error(
+                format!("Failed to serialize compute_qc_metrics response: {}", e),
+                None,
+            )
+        })?;
+
+        let content = Content::json(payload)?;
+        Ok(CallToolResult::success(vec![content]))
+    }
+
+    #[tool(
+        description = "Histogram of QUAL over the whole file (omit chromosome/start/end; reads every record) or a region, with min, max, mean and 5th/25th/50th/75th/95th percentiles to describe the distribution and suggest thresholds. bins (default 20) equal-width bins span the lowest to highest value. Set include_dp=true for the same summary of INFO/DP.",
+        annotations(
+            title = "QUAL histogram",
+            read_only_hint = true,
+            destructive_hint = false,
+            idempotent_hint = true,
+            open_world_hint = false
+        )
+    )]
+    async fn quality_histogram(
+        &self,
+        Parameters(QualityHistogramParams {
+            chromosome,
+            start,
+            end,
+            coordinate_system,
+            bins,
+            include_dp,
+        }): Parameters<QualityHistogramParams>,
+    ) -> Result<CallToolResult, McpError> {
+        let bins = bins.clamp(1, MAX_HISTOGRAM_BINS);
+        let region = optional_region(chromosome, start, end, coordinate_system)?;
+
+        let response = self
+            .run_query(move |index| {
+                let mut values = QualityValues::default();
+                let mut visit = |variant: Variant| {
+                    values.add(&variant, include_dp);
+                    true
+                };
+                let Some((chromosome, start, end)) = region else {
+                    index
+                        .try_scan_file(&mut visit)
+                        .map_err(|failure| read_error("quality_histogram", &failure))?;
+                    return Ok(values.into_response("file", bins, include_dp));
+                };
+                let matched_chr = index
+                    .try_scan_region(&chromosome, start, end, &[], &mut visit)
+                    .map_err(|failure| read_error("quality_histogram", &failure))?;
+                let (status, available_sample, alternate_suggestion) =
+                    build_chromosome_response(index, &chromosome, &matched_chr);
+
+                let mut response = values.into_response("region", bins, include_dp);
+                response.status = status;
+                response.query = Some(QcRegionQuery {
+                    chromosome,
+                    start,
+                    end,
+                });
+                response.matched_chromosome = matched_chr;
+                response.available_chromosomes_sample = available_sample;
+                response.alternate_chromosome_suggestion = alternate_suggestion;
+                Ok(response)
+            })
+            .await?;
+        let payload = serde_json::to_value(response).map_err(|e| {
+            McpError::internal_error(
+                format!("Failed to serialize quality_histogram response: {}", e),
+                None,
+            )
+        })?;
+
+        let content = Content::json(payload)?;
+        Ok(CallToolResult::success(vec![content]))
+    }
+
+    #[tool(
+        description = "Count records per FILTER value (PASS, LowQual, ...) over the whole file (omit chromosome/start/end; uses the startup statistics) or a region, cross-tabulated with variant type (SNPs, insertions, deletions, MNPs, complex). Each FILTER comes with its ##FILTER description and share of records, most common first.",
+        annotations(
+            title = "FILTER summary",
+            read_only_hint = true,
+            destructive_hint = false,
+            idempotent_hint = true,
+            open_world_hint = false
+        )
+    )]
+    async fn filter_summary(
+        &self,
+        Parameters(FilterSummaryParams {
+            chromosome,
+            start,
+            end,
+            coordinate_system,
+        }): Parameters<FilterSummaryParams>,
+    ) -> Result<CallToolResult, McpError> {
+        let region = optional_region(chromosome, start, end, coordinate_system)?;
+
+        let response = self
+            .run_query(move |index| {
+                let Some((chromosome, start, end)) = region else {
+                    let stats = index.compute_statistics().map_err(statistics_error)?;
+                    return Ok(FilterSummaryResponse::new(
+                        index,
+                        "file",
+                        stats.total_variants,
+                        stats.filter_variant_types,
+                    ));
+                };
+                let mut counts = HashMap::new();
+                let mut records = 0;
+                let matched_chr = index
+                    .try_scan_region(&chromosome, start, end, &[], &mut |variant| {
+                        records += 1;
+                        count_filter_types(&mut counts, &variant);
+                        true
+                    })
+                    .map_err(|failure| read_error("filter_summary", &failure))?;
+                let (status, available_sample, alternate_suggestion) =
+                    build_chromosome_response(index, &chromosome, &matched_chr);
+
+                let mut response = FilterSummaryResponse::new(index, "region", records, counts);
+                response.status = status;
+                response.query = Some(QcRegionQuery {
+                    chromosome,
+                    start,
+                    end,
+                });
+                response.matched_chromosome = matched_chr;
+                response.available_chromosomes_sample = available_sample;
+                response.alternate_chromosome_suggestion = alternate_suggestion;
+                Ok(response)
+            })
+            .await?;
+        let payload = serde_json::to_value(response).map_err(|e| {
+            McpError::internal_error(
+                format!("Failed to serialize filter_summary response: {}", e),
+                None,
+            )
+        })?;
+
+        let content = Content::json(payload)?;
+        Ok(CallToolResult::success(vec![content]))
+    }
+
+    #[tool(
+        description = "Site frequency spectrum over the whole file (omit chromosome/start/end; reads every record) or a region: ALT allele frequencies binned into equal-width bins (default 10) over 0-1, or folded into minor allele frequencies over 0-0.5. Uses INFO/AF when present, else the frequency among the called GT alleles of all samples. Returns each bin's start, end and count.",
+        annotations(
+            title = "Allele frequency spectrum",
+            read_only_hint = true,
+            destructive_hint = false,
+            idempotent_hint = true,
+            open_world_hint = false
+        )
+    )]
+    async fn allele_frequency_spectrum(
+        &self,
+        Parameters(FrequencySpectrumParams {
+            chromosome,
+            start,
+            end,
+            coordinate_system,
+            bins,
+            folded,
+        }): Parameters<FrequencySpectrumParams>,
+    ) -> Result<CallToolResult, McpError> {
+        let bins = bins.clamp(1, MAX_HISTOGRAM_BINS);
+        let region = optional_region(chromosome, start, end, coordinate_system)?;
+
+        let response = self
+            .run_query(move |index| {
+                let mut spectrum = FrequencySpectrum::new(bins, folded);
+                let mut records = 0;
+                let mut visit = |variant: Variant| {
+                    records += 1;
+                    spectrum.add(&variant);
+                    true
+                };
+                let Some((chromosome, start, end)) = region else {
+                    index
+                        .try_scan_file(&mut visit)
+                        .map_err(|failure| read_error("allele_frequency_spectrum", &failure))?;
+                    return Ok(FrequencySpectrumResponse::new("file", records, spectrum));
+                };
+                let matched_chr = index
+                    .try_scan_region(&chromosome, start, end, &[], &mut visit)
+                    .map_err(|failure| read_error("allele_frequency_spectrum", &failure))?;
+                let (status, available_sample, alternate_suggestion) =
+                    build_chromosome_response(index, &chromosome, &matched_chr);
+
+                let mut response = FrequencySpectrumResponse::new("region", records, spectrum);
+                response.status = status;
+                response.query = Some(QcRegionQuery {
+                    chromosome,
+                    start,
+                    end,
+                });
+                response.matched_chromosome = matched_chr;
+                response.available_chromosomes_sample = available_sample;
+                response.alternate_chromosome_suggestion = alternate_suggestion;
+                Ok(response)
+            })
+            .await?;
+        let payload = serde_json::to_value(response).map_err(|e| {
+            McpError::internal_error(
+                format!(
+                    "Failed to serialize allele_frequency_spectrum response: {}",
+                    e
+                ),
+                None,
+            )
+        })?;
+
+        let content = Content::json(payload)?;
+        Ok(CallToolResult::success(vec![content]))
+    }
+
+    #[tool(
+        description = "Count variants per fixed-size window along a chromosome (or part of it, via start/end) to spot hypervariable regions and variant deserts without pulling every record. Returns the per-window counts in order, the densest windows, the number of empty windows and the mean per window. Answered from the ID index without reading the VCF when it holds variant positions. At most 10,000 windows.",
+        annotations(
+            title = "Variant density",
+            read_only_hint = true,
+            destructive_hint = false,
+            idempotent_hint = true,
+            open_world_hint = false
+        )
+    )]
+    async fn variant_density(
+        &self,
+        Parameters(VariantDensityParams {
+            chromosome,
+            window_size,
+            start,
+            end,
+            coordinate_system,
+        }): Parameters<VariantDensityParams>,
+    ) -> Result<CallToolResult, McpError> {
+        // Only the start moves: a half-open end is the inclusive 1-based end
+        let start = start.map(|start| coordinate_system.to_vcf_region(start, start).0);
+        if window_size == 0 {
+            return Err(McpError::invalid_params(
+                "window_size must be at least 1".to_string(),
+                None,
+            ));
+        }
+
+        let response = self
+            .run_query(move |index| {
+                let query = DensityQuery {
+                    chromosome: chromosome.clone(),
+                    window_size,
+                    start,
+                    end,
+                };
+                let extent = index.chromosome_extent(&chromosome);
+                let matched_chr = extent.as_ref().map(|(name, _)| name.clone());
+                let (status, available_sample, alternate_suggestion) =
+                    build_chromosome_response(index, &chromosome, &matched_chr);
+                let mut response = VariantDensityResponse {
+                    status,
+                    reference_genome: index.get_reference_genome(),
+                    query,
+                    matched_chromosome: matched_chr,
+                    available_chromosomes_sample: available_sample,
+                    alternate_chromosome_suggestion: alternate_suggestion,
+                    start: None,
+                    end: None,
+                    total: 0,
+                    window_count: 0,
+                    mean_per_window: 0.0,
+                    empty_windows: 0,
+                    densest: Vec::new(),
+                    counts: Vec::new(),
+                    from_index: false,
+                };
+                let Some((matching_chr, chromosome_end)) = extent else {
+                    return Ok(response);
+                };
+
+                let start = start.unwrap_or(1);
+                let end = end.or(chromosome_end).ok_or_else(|| {
+                    McpError::invalid_params(
+                        format!(
+                            "The length of {} is unknown (no ##contig length and no indexed variant positions); give end",
+                            matching_chr
+                        ),
+                        None,
+                    )
+                })?;
+                if start > end {
+                    return Err(McpError::invalid_params(
+                        format!("start ({}) must not be after end ({})", start, end),
+                        None,
+                    ));
+                }
+                let windows = (end - start) / window_size + 1;
+                if windows > MAX_DENSITY_WINDOWS as u64 {
+                    return Err(McpError::invalid_params(
+                        format!(
+                            "{}:{}-{} in {} bp windows is {} windows, more than the maximum of {}; use windows of at least {} bp",
+                            matching_chr,
+                            start,
+                            end,
+                            window_size,
+                            windows,
+                            MAX_DENSITY_WINDOWS,
+                            (end - start) / MAX_DENSITY_WINDOWS as u64 + 1
+                        ),
+                        None,
+                    ));
+                }
+
+                let density = index
+                    .variant_density(&matching_chr, start, end, window_size)
+                    .map_err(|failure| read_error("variant_density", &failure))?;
+                let window = |i: usize| DensityWindow {
+                    start: start + i as u64 * window_size,
+                    end: (start + i as u64 * window_size)
+                        .saturating_add(window_size - 1)
+                        .min(end),
+                    count: density.counts[i],
+                };
+                let mut densest: Vec<usize> = (0..density.counts.len())
+                    .filter(|&i| density.counts[i] > 0)
+                    .collect();
+                densest.sort_by_key(|&i| std::cmp::Reverse(density.counts[i]));
+                densest.truncate(DENSEST_WINDOWS);
+
+                response.total = density.counts.iter().sum();
+                response.window_count = density.counts.len();
+                response.mean_per_window = response.total as f64 / density.counts.len() as f64;
+                response.empty_windows = density.counts.iter().filter(|&&count| count == 0).count();
+                response.densest = densest.into_iter().map(window).collect();
+                response.start = Some(start);
+                response.end = Some(end);
+                response.from_index = density.from_index;
+                response.counts = density.counts;
+                Ok(response)
+            })
+            .await?;
+        let payload = serde_json::to_value(response).map_err(|e| {
+            McpError::internal_error(
+                format!("Failed to serialize variant_density response: {}", e),
+                None,
+            )
+        })?;
+
+        let content = Content::json(payload)?;
+        Ok(CallToolResult::success(vec![content]))
+    }
+
+    #[tool(
+        description = "Genotype missingness QC: each sample's missing-genotype count and rate (highest first) and the distribution of per-site call rates (fraction of samples called), over the whole file (omit chromosome/start/end; uses the startup statistics) or a region. Region queries also list sites whose call rate is below min_call_rate (default 0.95). A GT with any missing allele ('./.', './1') counts as missing.",
+        annotations(
+            title = "Sample missingness QC",
+            read_only_hint = true,
+            destructive_hint = false,
+            idempotent_hint = true,
+            open_world_hint = false
+        )
+    )]
+    async fn sample_qc(
+        &self,
+        Parameters(SampleQcParams {
+            chromosome,
+            start,
+            end,
+            coordinate_system,
+            min_call_rate,
+            limit,
+            site_limit,
+        }): Parameters<SampleQcParams>,
+    ) -> Result<CallToolResult, McpError> {
+        let limit = limit.clamp(1, MAX_SAMPLES_LIMIT);
+        let site_limit = site_limit.clamp(1, MAX_TRIO_SITES_LIMIT);
+        let min_call_rate = min_call_rate.unwrap_or(0.95);
+        if !(0.0..=1.0).contains(&min_call_rate) {
+            return Err(McpError::invalid_params(
+                format!(
+                    "min_call_rate must be between 0 and 1, got {}",
+                    min_call_rate
+                ),
+                None,
+            ));
+        }
+
+        let response = match optional_region(chromosome, start, end, coordinate_system)? {
+            None => {
+                self.run_query(move |index| {
+                    let stats = index.compute_statistics().map_err(statistics_error)?;
+                    Ok(SampleQcResponse::new(
+                        index,
+                        "file",
+                        &stats.call_rates,
+                        limit,
+                    ))
+                })
+                .await?
+            }
+            Some((chromosome, start, end)) => {
+                self.run_query(move |index| {
+                    let mut call_rates = CallRateStats::new(index.sample_count());
+                    let mut low_call_rate_sites = Vec::new();
+                    let mut low_call_rate_site_count = 0;
+                    let matched_chr = index
+                        .try_scan_region(&chromosome, start, end, &[], &mut |variant| {
+                            let call_rate = call_rates.add(&variant.raw_row);
+                            if let Some(call_rate) =
+                                call_rate.filter(|&call_rate| call_rate < min_call_rate)
+                            {
+                                low_call_rate_site_count += 1;
+                                if low_call_rate_sites.len() < site_limit {
+                                    low_call_rate_sites.push(LowCallRateSite {
+                                        position: variant.position,
+                                        id: variant.id.clone(),
+                                        call_rate,
+                                    });
+                                }
+                            }
+                            true
+                        })
+                        .map_err(|failure| read_error("sample_qc", &failure))?;
+                    let (status, available_sample, alternate_suggestion) =
+                        build_chromosome_response(index, &chromosome, &matched_chr);
+
+                    let mut response = SampleQcResponse::new(index, "region", &call_rates, limit);
+                    response.status = status;
+                    response.query = Some(SampleQcQuery {
+                        chromosome,
+                        start,
+                        end,
+                        min_call_rate,
+                    });
+                    response.matched_chromosome = matched_chr;
+                    response.available_chromosomes_sample = available_sample;
+                    response.alternate_chromosome_suggestion = alternate_suggestion;
+                    response.low_call_rate_site_count = Some(low_call_rate_site_count);
+                    response.low_call_rate_sites = Some(low_call_rate_sites);
+                    Ok(response)
+                })
+                .await?
+            }
+        };
+        let payload = serde_json::to_value(response).map_err(|e| {
+            McpError::internal_error(
+                format!("Failed to serialize sample_qc response: {}", e),
+                None,
+            )
+        })?;
+
+        let content = Content::json(payload)?;
+        Ok(CallToolResult::success(vec![content]))
+    }
+
+    #[tool(
+        description = "Start a new streaming query session for a genomic region of any size. Returns the first variant and a session_id for subsequent calls. Use get_next_variant to retrieve remaining variants one at a time; each call reads only up to the next matching variant. Optionally filter variants using a filter expression (e.g., 'QUAL > 30 AND FILTER == PASS').",
+        annotations(
+            title = "Start a streaming region query",
+            read_only_hint = false,
+            destructive_hint = false,
+            idempotent_hint = false,
+            open_world_hint = false
+        )
+    )]
+    async fn start_region_query(
+        &self,
+        Parameters(StreamRegionParams {
+            chromosome: requested_chromosome,
+            start,
+            end,
+            coordinate_system,
+            filter,
+            shortcuts,
+            samples,
+        }): Parameters<StreamRegionParams>,
+    ) -> Result<CallToolResult, McpError> {
+        let (start, end) = coordinate_system.to_vcf_region(start, end);
+        let filter = shortcuts.combine(&filter)?;
+        // Validate filter expression before processing
+        check_filter(&self.index(), &filter)?;
+        check_required_info(
+            &self.index(),
+            &shortcuts.record_filter().required_info_keys(),
+        )?;
+        let samples = sample_columns(&self.index(), &samples)?;
+
+        let index = &self.index();
+
+        // Nothing to stream; say so instead of reporting an unknown chromosome
+        if index.is_empty_file() {
+            let response = StreamQueryResponse {
+                variant: None,
+                session_id: None,
+                has_more: false,
+                reference_genome: index.get_reference_genome(),
+                matched_chromosome: None,
+                status: Some(QueryStatus::EmptyFile),
+            };
+            let payload = serde_json::to_value(response).map_err(|e| {
+                McpError::internal_error(
+                    format!("Failed to serialize start_region_query response: {}", e),
+                    None,
+                )
+            })?;
+            let content = Content::json(payload)?;
+            return Ok(CallToolResult::success(vec![content]));
+        }
+
+        // Find matching chromosome (handles chr1 vs 1 normalization)
+        let matched_chr = index.get_available_chromosomes().into_iter().find(|chr| {
+            chr.to_lowercase() == requested_chromosome.to_lowercase()
+                || chr.to_lowercase() == format!("chr{}", requested_chromosome).to_lowercase()
+                || chr.to_lowercase()
+                    == requested_chromosome
+                        .strip_prefix("chr")
+                        .unwrap_or(&requested_chromosome)
+                        .to_lowercase()
+        });
+
+        let matched_chr_name = matched_chr.ok_or_else(|| {
+            McpError::internal_error(
+                format!(
+                    "Chromosome '{}' not found in VCF file",
+                    requested_chromosome
+                ),
+                None,
+            )
+        })?;
+
+        // Find the first variant that passes the filter; a page of one also tells whether
+        // more follow, so the scan never reads further than the next match
+        let (first_variant, next_cursor) = {
+            let chromosome = matched_chr_name.clone();
+            let options = QueryOptions {
+                filter: filter.clone(),
+                record_filter: shortcuts.record_filter(),
+                limit: Some(1),
+                ..Default::default()
+            };
+            self.run_query(move |index| {
+                let page = index.stream_region(&chromosome, start, end, &options);
+                if let Some(exceeded) = &page.limit_exceeded {
+                    return Err(limit_error("start_region_query", exceeded));
+                }
+                if let Some(failure) = &page.read_failure {
+                    return Err(read_error("start_region_query", failure));
+                }
+                Ok((
+                    page.variants.into_iter().next().map(format_variant),
+                    page.next_cursor,
+                ))
+            })
+            .await?
+        };
+
+        // If no variants found, return graceful response (consistent with get_next_variant)
+        if first_variant.is_none() {
+            let reference_genome = index.get_reference_genome();
+            let response = StreamQueryResponse {
+                variant: None,
+                session_id: None,
+                has_more: false,
+                reference_genome,
+                matched_chromosome: Some(matched_chr_name),
+                status: None,
+            };
+
+            let payload = serde_json::to_value(response).map_err(|e| {
+                McpError::internal_error(
+                    format!("Failed to serialize start_region_query response: {}", e),
+                    None,
+                )
+            })?;
+
+            let content = Content::json(payload)?;
+            return Ok(CallToolResult::success(vec![content]));
+        }
+
+        let mut first_variant = first_variant.unwrap();
+        select_samples(&mut first_variant, &samples);
+
+        // Create a session only when there is more to fetch
+        let session_id = match next_cursor {
+            Some(cursor) => {
+                let session_id = Uuid::new_v4().to_string();
+                let session = QuerySession {
+                    chromosome: matched_chr_name.clone(),
+                    start,
+                    end,
+                    cursor,
+                    created_at: std::time::Instant::now(),
+                    filter: filter.clone(),
+                    record_filter: shortcuts.record_filter(),
+                    samples,
+                };
+                let mut sessions = self.query_sessions.lock().await;
+                sessions.insert(session_id.clone(), session);
+                Some(session_id)
+            }
+            None => None,
+        };
+
+        let reference_genome = index.get_reference_genome();
+
+        let response = StreamQueryResponse {
+            variant: Some(first_variant),
+            has_more: session_id.is_some(),
+            session_id,
+            reference_genome,
+            matched_chromosome: Some(matched_chr_name),
+            status: None,
+        };
+
+        let payload = serde_json::to_value(response).map_err(|e| {
+            McpError::internal_error(
+                format!("Failed to serialize start_region_query response: {}", e),
+                None,
+            )
+        })?;
+
+        let content = Content::json(payload)?;
+        Ok(CallToolResult::success(vec![content]))
+    }
+
+    #[tool(
+        description = "Get the next variant from an active streaming query session. Returns one variant at a time. When has_more is false, the session is complete and automatically closed.",
+        annotations(
+            title = "Next streamed variants",
+            read_only_hint = false,
+            destructive_hint = false,
+            idempotent_hint = false,
+            open_world_hint = false
+        )
+    )]
+    async fn get_next_variant(
+        &self,
+        Parameters(NextVariantParams { session_id }): Parameters<NextVariantParams>,
+    ) -> Result<CallToolResult, McpError> {
+        let mut sessions = self.query_sessions.lock().await;
+
+        let session = sessions.get(&session_id).ok_or_else(|| {
+            McpError::internal_error(
+                "Session not found or expired. Start a new query with start_region_query.",
+                None,
+            )
+        })?;
+
+        // Check session timeout (5 minutes)
+        if session.created_at.elapsed().as_secs() > 300 {
+            sessions.remove(&session_id);
+            return Err(McpError::internal_error(
+                "Session expired. Start a new query.",
+                None,
+            ));
+        }
+
+        // Get session details before releasing lock
+        let chromosome = session.chromosome.clone();
+        let (start, end) = (session.start, session.end);
+        let options = QueryOptions {
+            filter: session.filter.clone(),
+            record_filter: session.record_filter.clone(),
+            limit: Some(1),
+            cursor: Some(session.cursor),
+            ..Default::default()
+        };
+        let samples = session.samples.clone();
+        drop(sessions);
+
+        // Resume after the variants already returned and read up to the next match (filter
+        // errors count as non-match); the page's cursor tells whether more follow
+        let (next_variant, next_cursor) = {
+            let chromosome = chromosome.clone();
+            self.run_query(move |index| {
+                let page = index.stream_region(&chromosome, start, end, &options);
+                if let Some(exceeded) = &page.limit_exceeded {
+                    return Err(limit_error("get_next_variant", exceeded));
+                }
+                if let Some(failure) = &page.read_failure {
+                    return Err(read_error("get_next_variant", failure));
+                }
+                Ok((
+                    page.variants.into_iter().next().map(format_variant),
+                    page.next_cursor,
+                ))
+            })
+            .await?
+        };
+        let has_more = next_cursor.is_some();
+
+        if next_variant.is_none() {
+            // No more variants - close session
+            let mut sessions = self.query_sessions.lock().await;
+            sessions.remove(&session_id);
+            drop(sessions);
+
+            let reference_genome = self.index().get_reference_genome();
+
+            let response = StreamQueryResponse {
+                variant: None,
+                session_id: None,
+                has_more: false,
+                reference_genome,
+                matched_chromosome: Some(chromosome),
+                status: None,
+            };
+
+            let payload = serde_json::to_value(response).map_err(|e| {
+                McpError::internal_error(
+                    format!("Failed to serialize get_next_variant response: {}", e),
+                    None,
+                )
+            })?;
+
+            let content = Content::json(payload)?;
+            return Ok(CallToolResult::success(vec![content]));
+        }
+
+        // Get next variant
+        let mut next_variant_data = next_variant.unwrap();
+        select_samples(&mut next_variant_data, &samples);
+
+        let reference_genome = self.index().get_reference_genome();
+
+        // Update session with where to resume
+        let mut sessions = self.query_sessions.lock().await;
+        if let (Some(session), Some(cursor)) = (sessions.get_mut(&session_id), next_cursor) {
+            session.cursor = cursor;
+        }
+
+        // If no more variants, remove session
+        if !has_more {
+            sessions.remove(&session_id);
+        }
+        drop(sessions);
+
+        let response = StreamQueryResponse {
+            variant: Some(next_variant_data),
+            session_id: if has_more { Some(session_id) } else { None },
+            has_more,
+            reference_genome,
+            matched_chromosome: Some(chromosome),
+            status: None,
+        };
+
+        let payload = serde_json::to_value(response).map_err(|e| {
+            McpError::internal_error(
+                format!("Failed to serialize get_next_variant response: {}", e),
+                None,
+            )
+        })?;
+
+        let content = Content::json(payload)?;
+        Ok(CallToolResult::success(vec![content]))
+    }
+
+    #[tool(
+        description = "Close an active streaming query session and free resources. Sessions are automatically closed when exhausted or after 5 minutes of inactivity.",
+        annotations(
+            title = "Close a streaming query",
+            read_only_hint = false,
+            destructive_hint = false,
+            idempotent_hint = true,
+            open_world_hint = false
+        )
+    )]
+    async fn close_query_session(
+        &self,
+        Parameters(CloseSessionParams { session_id }): Parameters<CloseSessionParams>,
+    ) -> Result<CallToolResult, McpError> {
+        let mut sessions = self.query_sessions.lock().await;
+        let existed = sessions.remove(&session_id).is_some();
+
+        let payload = serde_json::json!({
+            "closed": existed,
+            "message": if existed { "Session closed" } else { "Session not found" }
+        });
+
+        let content = Content::json(payload)?;
+        Ok(CallToolResult::success(vec![content]))
+    }
+
+    #[tool(
+        description = "Get embedded documentation for the VCF MCP server. Available types: 'readme' (main documentation), 'streaming' (streaming query guide), 'filters' (filter syntax examples), 'streaming-filters' (streaming with filters guide), 'all' (complete documentation).",
+        annotations(
+            title = "Server documentation",
+            read_only_hint = true,
+            destructive_hint = false,
+            idempotent_hint = true,
+            open_world_hint = false
+        )
+    )]
+    async fn get_documentation(
+        &self,
+        Parameters(GetDocumentationParams { doc_type }): Parameters<GetDocumentationParams>,
+    ) -> Result<CallToolResult, McpError> {
+        let (content, doc_name) = match doc_type.to_lowercase().as_str() {
+            "readme" | "main" => (README_DOCS, "README.md"),
+            "streaming" => (STREAMING_DOCS, "STREAMING.md"),
+            "filters" | "filter" => (FILTER_DOCS, "FILTER_EXAMPLES.md"),
+            "streaming-filters" | "streaming_filters" => {
+                (STREAMING_FILTER_DOCS, "STREAMING_FILTER_EXAMPLES.md")
+            }
+            "all" => {
+                let combined = format!(
+                    "# VCF MCP Server - Complete Documentation\n\n\
+                     ---\n\n\
+                     # Main Documentation\n\n{}\n\n\
+                     ---\n\n\
+                     # Streaming Queries\n\n{}\n\n\
+                     ---\n\n\
+                     # Filter Examples\n\n{}\n\n\
+                     ---\n\n\
+                     # Streaming with Filters\n\n{}",
+                    README_DOCS, STREAMING_DOCS, FILTER_DOCS, STREAMING_FILTER_DOCS
+                );
+                let payload = serde_json::json!({
+                    "doc_type": "all",
+                    "content": combined,
+                    "format": "markdown",
+                    "sections": ["README.md", "STREAMING.md", "FILTER_EXAMPLES.md", "STREAMING_FILTER_EXAMPLES.md"]
+                });
+                let content = Content::json(payload)?;
+                return Ok(CallToolResult::success(vec![content]));
+            }
+            unknown => {
+                return Err(McpError::invalid_params(
+                    format!(
+                        "Unknown doc_type '{}'. Available: readme, streaming, filters, streaming-filters, all",
+                        unknown
+                    ),
+                    None,
+                ));
+            }
+        };
+
+        let payload = serde_json::json!({
+            "doc_type": doc_type,
+            "document_name": doc_name,
+            "content": content,
+            "format": "markdown"
+        });
+
+        let content = Content::json(payload)?;
+        Ok(CallToolResult::success(vec![content]))
+    }
+
+    #[tool(
+        description = "Admin: re-open the VCF file and its indexes from disk and switch to them, for files that are updated in place (e.g. nightly). An ID index or statistics that no longer match the file are rebuilt. Queries already running finish on the previous version; if loading fails, the previous version is still served and the error is returned.",
+        annotations(
+            title = "Reload the dataset",
+            read_only_hint = false,
+            destructive_hint = false,
+            idempotent_hint = true,
+            open_world_hint = false
+        )
+    )]
+    async fn reload_dataset(&self) -> Result<CallToolResult, McpError> {
+        let notified = self
+            .reload("reload_dataset")
+            .await
+            .map_err(|e| McpError::internal_error(e, None))?;
+        let index = self.index();
+        let payload = serde_json::json!({
+            "status": "reloaded",
+            "file": self.reload_from.as_ref().map(|(path, _)| path.display().to_string()),
+            "reference_genome": index.get_reference_genome(),
+            "id_index": index.id_index_status(),
+            "notified_subscriptions": notified,
+        });
+
+        let content = Content::json(payload)?;
+        Ok(CallToolResult::success(vec![content]))
+    }
+
+    // Helper method for chromosome not found responses
+    // fn build_chromosome_not_found_response(
+    //     &self,
+    //     index: &VcfIndex,
+    //     requested_chromosome: &str,
+    // ) -> Result<CallToolResult, McpError> {
+    //     let sample_chroms: Vec<String> = index
+    //         .get_available_chromosomes()
+    //         .into_iter()
+    //         .take(5)
+    //         .collect();
+    //     let alternate = if requested_chromosome.starts_with("chr") {
+    //         requested_chromosome
+    //             .strip_prefix("chr")
+    //             .unwrap_or(requested_chromosome)
+    //             .to_string()
+    //     } else {
+    //         format!("chr{}", requested_chromosome)
+    //     };
+
+    //     Err(McpError::internal_error(
+    //         format!(
+    //             "Chromosome '{}' not found. Try '{}'? Available chromosomes (first 5): {:?}",
+    //             requested_chromosome, alternate, sample_chroms
+    //         ),
+    //         None,
+    //     ))
+    // }
+}
+
+// Parquet export, only built with the parquet-export feature
+#[cfg(feature = "parquet-export")]
+#[tool_router(router = parquet_tool_router)]
+impl VcfServer {
+    #[tool(
+        description = "Write the records of a region, or of the whole file (omit chromosome/start/end), optionally filtered, to a Parquet file in the server's output directory (--allow-write-dir) for DataFrame analysis (pandas, polars, DuckDB, R arrow). Columns are typed: chromosome, position, id, reference, alternate (list), quality, filter (list), variant_type, and info_<KEY> for every INFO field in the header, typed from its ##INFO line. Samples are not exported. Returns the file's path, row count and columns.",
+        annotations(
+            title = "Export to Parquet",
+            read_only_hint = false,
+            destructive_hint = true,
+            idempotent_hint = true,
+            open_world_hint = false
+        )
+    )]
+    async fn export_parquet(
+        &self,
+        Parameters(params): Parameters<ExportParquetParams>,
+        context: RequestContext<RoleServer>,
+    ) -> Result<CallToolResult, McpError> {
+        self.export_parquet_file(params, CallContext::from_request(&context))
+            .await
+    }
+
+    // export_parquet without the request context, so tests can call it directly
+    async fn export_parquet_file(
+        &self,
+        params: ExportParquetParams,
+        context: CallContext,
+    ) -> Result<CallToolResult, McpError> {
+        let region = optional_region(
+            params.chromosome,
+            params.start,
+            params.end,
+            params.coordinate_system,
+        )?;
+        let output = params.output.unwrap_or_else(|| match &region {
+            Some((chromosome, start, end)) => format!("{}_{}-{}.parquet", chromosome, start, end),
+            None => "variants.parquet".to_string(),
+        });
+        let path = self.export_path("export_parquet", &output, ".parquet", params.overwrite)?;
+        let query = ExportParquetQuery {
+            scope: if region.is_some() { "region" } else { "file" },
+            chromosome: region.as_ref().map(|(chromosome, _, _)| chromosome.clone()),
+            start: region.as_ref().map(|&(_, start, _)| start),
+            end: region.as_ref().map(|&(_, _, end)| end),
+            filter: params.shortcuts.combine(&params.filter)?,
+            record_filter: params.shortcuts.record_filter(),
+        };
+
+        let response = self
+            .run_monitored_query(context, move |index, monitor| {
+                check_filter(index, &query.filter)?;
+                check_required_info(index, &query.record_filter.required_info_keys())?;
+
+                let definitions = header_definitions(&index.get_header_string(None));
+                let mut writer = ParquetWriter::create(&path, &definitions.info).map_err(|e| {
+                    McpError::internal_error(
+                        format!("Failed to create {}: {}", path.display(), e),
+                        None,
+                    )
+                })?;
+                let columns = writer.columns();
+                let filter_engine = index.filter_engine();
+                let mut write_error = None;
+                let mut cancelled = false;
+                let mut visit = |variant: Variant| {
+                    if monitor.scanned().is_break() {
+                        cancelled = true;
+                        return false;
+                    }
+                    if !query.record_filter.admits(&variant)
+                        || !(query.filter.is_empty()
+                            || filter_engine
+                                .evaluate(&query.filter, &variant.raw_row)
+                                .unwrap_or(false))
+                    {
+                        return true;
+                    }
+                    if let Err(e) = writer.push(variant) {
+                        write_error = Some(e);
+                        return false;
+                    }
+                    true
+                };
+                let scanned = match &region {
+                    Some((chromosome, start, end)) => index
+                        .try_scan_region(chromosome, *start, *end, &[], &mut visit)
+                        .map(Some),
+                    None => index.try_scan_file(&mut visit).map(|_| None),
+                };
+                let write_failed = |e: std::io::Error| {
+                    McpError::internal_error(
+                        format!("Failed to write {}: {}", path.display(), e),
+                        None,
+                    )
+                };
+                let scanned = match (scanned, write_error) {
+                    (Err(failure), _) => Err(read_error("export_parquet", &failure)),
+                    (Ok(_), Some(e)) => Err(write_failed(e)),
+                    (Ok(matched_chr), None) => Ok(matched_chr.flatten()),
+                };
+                let matched_chr = match scanned {
+                    Ok(matched_chr) if !cancelled => matched_chr,
+                    scanned => {
+                        // Leave no partial file behind
+                        let _ = std::fs::remove_file(&path);
+                        return scanned.map(|matched_chr| ExportParquetResponse {
+                            status: QueryStatus::Cancelled,
+                            reference_genome: index.get_reference_genome(),
+                            query,
+                            matched_chromosome: matched_chr,
+                            path: None,
+                            row_count: 0,
+                            columns,
+                        });
+                    }
+                };
+                let row_count = writer.finish().map_err(|e| {
+                    let _ = std::fs::remove_file(&path);
+                    write_failed(e)
+                })?;
+
+                let status = match &region {
+                    Some(_) if matched_chr.is_none() => QueryStatus::ChromosomeNotFound,
+                    _ => QueryStatus::Ok,
+                };
+                Ok(ExportParquetResponse {
+                    status,
+                    reference_genome: index.get_reference_genome(),
+                    query,
+                    matched_chromosome: matched_chr,
+                    path: Some(path.display().to_string()),
+                    row_count,
+                    columns,
+                })
+            })
+            .await?;
+
+        let payload = serde_json::to_value(response).map_err(|e| {
+            McpError::internal_error(
+                format!("Failed to serialize export_parquet response: {}", e),
+                None,
+            )
+        })?;
+
+        let content = Content::json(payload)?;
+        Ok(CallToolResult::success(vec![content]))
+    }
+}
+
+// Tools that send data off the machine, only built with the network feature
+#[cfg(feature = "network")]
+#[tool_router(router = network_tool_router)]
+impl VcfServer {
+    #[tool(
+        description = "Annotate the variants of a region with the Ensembl VEP REST API (consequences, impact, genes, transcripts, SIFT/PolyPhen, co-located known variants), for files without ANN/CSQ annotations. Sends up to 200 variants to Ensembl, so it is only available when the server was started with --allow-network. The Ensembl server matches the file's genome build.",
+        annotations(
+            title = "Annotate with Ensembl VEP",
+            read_only_hint = true,
+            destructive_hint = false,
+            idempotent_hint = true,
+            open_world_hint = true
+        )
+    )]
+    async fn annotate_via_vep(
+        &self,
+        Parameters(AnnotateViaVepParams {
+            chromosome,
+            start,
+            end,
+            coordinate_system,
+            filter,
+            shortcuts,
+            limit,
+            pick,
+            species,
+        }): Parameters<AnnotateViaVepParams>,
+    ) -> Result<CallToolResult, McpError> {
+        let (start, end) = coordinate_system.to_vcf_region(start, end);
+        let Some(client) = self.vep.clone() else {
+            return Err(McpError::invalid_request(
+                "annotate_via_vep sends variants to the Ensembl REST API, and this server was started without --allow-network".to_string(),
+                None,
+            ));
+        };
+        if start > end {
+            return Err(McpError::invalid_params(
+                format!("start ({}) must not be after end ({})", start, end),
+                None,
+            ));
+        }
+        let limit = limit.clamp(1, vep::MAX_BATCH);
+        let query = VepQuery {
+            filter: shortcuts.combine(&filter)?,
+            record_filter: shortcuts.record_filter(),
+            chromosome,
+            start,
+            end,
+            species,
+            pick,
+        };
+
+        let mut response = self
+            .run_query(move |index| {
+                check_filter(index, &query.filter)?;
+                check_required_info(index, &query.record_filter.required_info_keys())?;
+                let filter_engine = index.filter_engine();
+                let mut variants = Vec::new();
+                let matched_chr = index
+                    .try_scan_region(&query.chromosome, query.start, query.end, &[], &mut |v| {
+                        if query.record_filter.admits(&v)
+                            && (query.filter.is_empty()
+                                || filter_engine
+                                    .evaluate(&query.filter, &v.raw_row)
+                                    .unwrap_or(false))
+                        {
+                            variants.push(v);
+                        }
+                        variants.len() <= limit
+                    })
+                    .map_err(|failure| read_error("annotate_via_vep", &failure))?;
+                let truncated = variants.len() > limit;
+                variants.truncate(limit);
+                let (status, available_sample, alternate_suggestion) =
+                    build_chromosome_response(index, &query.chromosome, &matched_chr);
+
+                Ok(AnnotateViaVepResponse {
+                    status,
+                    reference_genome: index.get_reference_genome(),
+                    vep_server: client.server().to_string(),
+                    query,
+                    matched_chromosome: matched_chr,
+                    available_chromosomes_sample: available_sample,
+                    alternate_chromosome_suggestion: alternate_suggestion,
+                    variant_count: variants.len(),
+                    annotated_count: 0,
+                    truncated,
+                    variants: variants
+                        .into_iter()
+                        .map(|v| VepAnnotatedVariant {
+                            input: vep::vep_input(&v),
+                            position: v.position,
+                            id: v.id,
+                            reference: v.reference,
+                            alternate: v.alternate,
+                            annotated: false,
+                            most_severe_consequence: None,
+                            transcript_consequences: Vec::new(),
+                            colocated_ids: Vec::new(),
+                        })
+                        .collect(),
+                })
+            })
+            .await?;
+
+        if !response.variants.is_empty() {
+            let inputs: Vec<String> = response.variants.iter().map(|v| v.input.clone()).collect();
+            let results = client
+                .annotate(&response.query.species, &inputs, response.query.pick)
+                .await
+                .map_err(|e| {
+                    let advice = if e.transient {
+                        "Repeating the call later may succeed."
+                    } else {
+                        "Check the species and that the file's build matches the Ensembl server."
+                    };
+                    McpError::internal_error(format!("{}. {}", e.message, advice), None)
+                })?;
+            let mut by_input: HashMap<String, vep::VepResult> = results
+                .into_iter()
+                .map(|result| (result.input.clone(), result))
+                .collect();
+            for variant in &mut response.variants {
+                let Some(result) = by_input.remove(&variant.input) else {
+                    continue;
+                };
+                variant.annotated = true;
+                variant.most_severe_consequence = result.most_severe_consequence;
+                variant.transcript_consequences = result.transcript_consequences;
+                variant.colocated_ids = result
+                    .colocated_variants
+                    .into_iter()
+                    .filter_map(|colocated| colocated.id)
+                    .collect();
+            }
+            response.annotated_count = response.variants.iter().filter(|v| v.annotated).count();
+        }
+
+        let payload = serde_json::to_value(response).map_err(|e| {
+            McpError::internal_error(
+                format!("Failed to serialize annotate_via_vep response: {}", e),
+                None,
+            )
+        })?;
+
+        let content = Content::json(payload)?;
+        Ok(CallToolResult::success(vec![content]))
+    }
+}
+
+// Reject filter expressions the engine cannot parse, before any variants are read
+fn check_filter(index: &VcfIndex, filter: &str) -> Result<(), McpError> {
+    if filter.trim().is_empty() {
+        return Ok(());
+    }
+    index
+        .filter_engine()
+        .parse_filter(filter)
+        .map(|_| ())
+        .map_err(|e| McpError::invalid_params(format!("Invalid filter expression: {}", e), None))
+}
+
+// The definitions named in `ids`, in request order (all of them when `ids` is empty); IDs
+// are matched case-insensitively when there is no exact match
+fn select_definitions(declared: Vec<HeaderDefinition>, ids: &[String]) -> FieldDefinitionsResponse {
+    let mut not_found = Vec::new();
+    let definitions = if ids.is_empty() {
+        declared
+    } else {
+        let mut selected = Vec::new();
+        for id in ids {
+            let found = declared
+                .iter()
+                .find(|definition| definition.id == *id)
+                .or_else(|| {
+                    declared
+                        .iter()
+                        .find(|definition| definition.id.eq_ignore_ascii_case(id))
+                });
+            match found {
+                Some(definition) => selected.push(definition.clone()),
+                None => not_found.push(id.clone()),
+            }
+        }
+        selected
+    };
+    FieldDefinitionsResponse {
+        status: if not_found.is_empty() {
+            QueryStatus::Ok
+        } else {
+            QueryStatus::NotFound
+        },
+        count: definitions.len(),
+        definitions,
+        not_found,
+    }
+}
+
+// Reject require_info keys (and CLNSIG for pathogenic_only) the header does not declare with
+// ##INFO (likely typos)
+fn check_required_info(index: &VcfIndex, keys: &[String]) -> Result<(), McpError> {
+    let undeclared = index.undeclared_info_keys(keys);
+    if undeclared.is_empty() {
+        return Ok(());
+    }
+    Err(McpError::invalid_params(
+        format!(
+            "Not declared as INFO fields in the VCF header: {}. Use get_vcf_header with search '##INFO' to list them.",
+            undeclared.join(", ")
+        ),
+        None,
+    ))
+}
+
+// Reject INFO flag names the header does not declare as flags (likely typos)
+fn check_info_flags(index: &VcfIndex, flags: &[String]) -> Result<(), McpError> {
+    let undeclared = index.undeclared_info_flags(flags);
+    if undeclared.is_empty() {
+        return Ok(());
+    }
+    Err(McpError::invalid_params(
+        format!(
+            "Not declared as INFO flags in the VCF header: {}. Use get_vcf_header with search '##INFO' to list them.",
+            undeclared.join(", ")
+        ),
+        None,
+    ))
+}
+
+// Structured error for a region query stopped by a server-side limit: the message says how
+// to narrow the query and `data` carries the limit and where the scan got to
+fn limit_error(tool: &str, exceeded: &LimitExceeded) -> McpError {
+    let message = match exceeded {
+        LimitExceeded::RegionSpan {
+            region_bp,
+            max_region_bp,
+        } => format!(
+            "{}: region spans {} bp but this server allows at most {} bp per query. Split it into regions of at most {} bp, or stream it with start_region_query.",
+            tool, region_bp, max_region_bp, max_region_bp
+        ),
+        LimitExceeded::Results {
+            max_results,
+            position,
+        } => format!(
+            "{}: more than {} variants match (the limit was reached at position {}). Narrow the region, for example to end before {}, or add a more selective filter.",
+            tool, max_results, position, position
+        ),
+        LimitExceeded::Timeout {
+            timeout_ms,
+            position,
+            records_read,
+        } => format!(
+            "{}: query timed out after {} ms at position {} ({} records read). Narrow the region, for example to end before {}, and query the rest separately.",
+            tool, timeout_ms, position, records_read, position
+        ),
+    };
+    McpError::invalid_params(message, serde_json::to_value(exceeded).ok())
+}
+
+// The region of a tool that covers the whole file when chromosome, start and end are all
+// omitted, in 1-based inclusive VCF coordinates
+fn optional_region(
+    chromosome: Option<String>,
+    start: Option<u64>,
+    end: Option<u64>,
+    coordinates: CoordinateSystem,
+) -> Result<Option<(String, u64, u64)>, McpError> {
+    match (chromosome, start, end) {
+        (None, None, None) => Ok(None),
+        (Some(_), Some(start), Some(end)) if start > end => Err(McpError::invalid_params(
+            format!("start ({}) must not be after end ({})", start, end),
+            None,
+        )),
+        (Some(chromosome), Some(start), Some(end)) => {
+            let (start, end) = coordinates.to_vcf_region(start, end);
+            Ok(Some((chromosome, start, end)))
+        }
+        _ => Err(McpError::invalid_params(
+            "Give chromosome, start and end together for a region, or none of them for the whole file".to_string(),
+            None,
+        )),
+    }
+}
+
+// Error for statistics that are unavailable, or still being computed in the background
+// (not a server failure)
+fn statistics_error(e: std::io::Error) -> McpError {
+    if e.kind() == std::io::ErrorKind::WouldBlock {
+        McpError::invalid_request(format!("{}. Try again shortly.", e), None)
+    } else {
+        McpError::internal_error(format!("Failed to compute statistics: {}", e), None)
+    }
+}
+
+// Error for a query whose read of the VCF failed, so it is not mistaken for a variant being
+// absent from the file
+fn read_error(tool: &str, failure: &ReadFailure) -> McpError {
+    let advice = if failure.transient {
+        "The error looks transient; repeating the query may succeed."
+    } else {
+        "Repeating the query is unlikely to help; the file may be damaged or unreadable."
+    };
+    McpError::internal_error(
+        format!(
+            "{}: reading the VCF failed after {} attempt(s): {}. This is a read failure, not evidence that no variants match. {}",
+            tool, failure.attempts, failure.message, advice
+        ),
+        serde_json::to_value(failure).ok(),
+    )
+}
+
+// Progress to report while the ID index is still being built; errors if building it failed
+// IDs per page of the vcf://ids resource
+const ID_PAGE_SIZE: usize = 10_000;
+
+// Page requested by a vcf://ids or vcf://ids?page=N URI; None for other URIs
+fn parse_ids_page(uri: &str) -> Option<Result<usize, McpError>> {
+    let query = uri.strip_prefix("vcf://ids")?;
+    if query.is_empty() {
+        return Some(Ok(0));
+    }
+    let page = query.strip_prefix("?page=")?;
+    Some(page.parse().map_err(|_| {
+        McpError::invalid_params(
+            format!(
+                "Invalid page '{}' in {}: expected a number from 0",
+                page, uri
+            ),
+            None,
+        )
+    }))
+}
+
+// The client session a request belongs to: its Mcp-Session-Id over HTTP, the only one on stdio.
+// None for HTTP requests without a session (the stateless transport), which share no state.
+fn session_key(context: &RequestContext<RoleServer>) -> Option<String> {
+    match context.extensions.get::<axum::http::request::Parts>() {
+        None => Some("stdio".to_string()),
+        Some(parts) => parts
+            .headers
+            .get("mcp-session-id")
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string),
+    }
+}
+
+// Reload the VCF whenever it or its index changes on disk (--watch), then notify the clients
+// subscribed to resources. A failed reload keeps the previous index.
+async fn watch_vcf(server: VcfServer, path: PathBuf) {
+    let paths = watch::watched_paths(&path);
+    let mut detector = watch::ChangeDetector::new(watch::FileState::read(&paths));
+    loop {
+        tokio::time::sleep(watch::WATCH_INTERVAL).await;
+        let state = watch::FileState::read(&paths);
+        if !detector.poll(state.clone()) {
+            continue;
+        }
+        // Logged either way; not retried until the files change again
+        let _ = server.reload("changed on disk").await;
+        detector.loaded(state);
+    }
+}
+
+// Run `reload` on every SIGHUP, the conventional signal to re-read files
+#[cfg(unix)]
+async fn on_hangup<F, Fut>(reload: F)
+where
+    F: Fn() -> Fut,
+    Fut: std::future::Future<Output = ()>,
+{
+    use tokio::signal::unix::{signal, SignalKind};
+    let mut hangup = match signal(SignalKind::hangup()) {
+        Ok(hangup) => hangup,
+        Err(e) => {
+            warn!("Cannot listen for SIGHUP: {}", e);
+            return;
+        }
+    };
+    while hangup.recv().await.is_some() {
+        reload().await;
+    }
+}
+
+// Resources per list_resources page; files with thousands of scaffolds list their contigs
+// over several pages
+const RESOURCE_PAGE_SIZE: usize = 1000;
+
+// The page of `items` starting at `cursor` (an offset from a previous page's next_cursor),
+// with the cursor of the next page
+fn paginate<T>(
+    items: Vec<T>,
+    cursor: Option<&str>,
+    page_size: usize,
+) -> Result<(Vec<T>, Option<String>), McpError> {
+    let offset = match cursor {
+        None => 0,
+        Some(cursor) => cursor
+            .parse::<usize>()
+            .ok()
+            .filter(|offset| *offset <= items.len())
+            .ok_or_else(|| {
+                McpError::invalid_params(
+                    format!(
+                        "Invalid cursor '{}': pass the next_cursor of the previous page",
+                        cursor
+                    ),
+                    None,
+                )
+            })?,
+    };
+    let end = offset.saturating_add(page_size).min(items.len());
+    let next_cursor = (end < items.len()).then(|| end.to_string());
+    Ok((
+        items.into_iter().skip(offset).take(end - offset).collect(),
+        next_cursor,
+    ))
+}
+
+// What reading a vcf://chromosome/{name} resource returns
+#[derive(Debug, serde::Serialize)]
+struct ChromosomeResource {
+    reference_genome: String,
+    name: String,
+    // Declared in the header's ##contig line
+    length: Option<usize>,
+    // None until statistics have been computed
+    variant_count: Option<u64>,
+    variants_per_mb: Option<f64>,
+    // From the ID index; None while it is being built
+    first_position: Option<u64>,
+    last_position: Option<u64>,
+    counts_pending: bool,
+}
+
+// Region slice named by a vcf://{chromosome}/{start}-{end} resource URI
+#[derive(Debug, PartialEq, Eq)]
+struct RegionSlice {
+    chromosome: String,
+    // 1-based, inclusive
+    start: u64,
+    end: u64,
+    // The URI ends in .vcf: serve VCF text instead of JSON
+    vcf: bool,
+}
+
+// URI template of the region slices parse_region_uri reads
+const REGION_TEMPLATE: &str = "vcf://{chromosome}/{start}-{end}";
+
+// Region slice requested by a vcf://{chromosome}/{start}-{end} URI, optionally ending in .json
+// or .vcf; None for other URIs
+fn parse_region_uri(uri: &str) -> Option<Result<RegionSlice, McpError>> {
+    let (chromosome, range) = uri.strip_prefix("vcf://")?.rsplit_once('/')?;
+    let (range, vcf) = match range.strip_suffix(".vcf") {
+        Some(range) => (range, true),
+        None => (range.strip_suffix(".json").unwrap_or(range), false),
+    };
+    let invalid = || {
+        McpError::invalid_params(
+            format!(
+                "Invalid region in {}: expected vcf://{{chromosome}}/{{start}}-{{end}} with 1-based inclusive positions, e.g. vcf://20/14000-18000",
+                uri
+            ),
+            None,
+        )
+    };
+    let Some((start, end)) = range.split_once('-') else {
+        return Some(Err(invalid()));
+    };
+    let (Ok(start), Ok(end)) = (start.parse::<u64>(), end.parse::<u64>()) else {
+        return Some(Err(invalid()));
+    };
+    if chromosome.is_empty() || start == 0 || end < start {
+        return Some(Err(invalid()));
+    }
+    Some(Ok(RegionSlice {
+        chromosome: chromosome.to_string(),
+        start,
+        end,
+        vcf,
+    }))
+}
+
+#[derive(Debug, serde::Serialize)]
+struct RegionResource {
+    reference_genome: String,
+    chromosome: String,
+    start: u64,
+    end: u64,
+    matched_chromosome: String,
+    count: usize,
+    // More variants are in the region than the REGION_PAGE_SIZE served
+    truncated: bool,
+    variants: Vec<Variant>,
+}
+
+// Names and columns of the samples a request asks FORMAT values for
+fn sample_columns(index: &VcfIndex, samples: &[String]) -> Result<Vec<(String, usize)>, McpError> {
+    samples
+        .iter()
+        .map(|sample| Ok((sample.clone(), check_sample(index, sample)?)))
+        .collect()
+}
+
+// Column index of a sample named in a request
+fn check_sample(index: &VcfIndex, sample: &str) -> Result<usize, McpError> {
+    index.sample_index(sample).ok_or_else(|| {
+        let samples = index.get_metadata().samples;
+        McpError::invalid_params(
+            format!(
+                "Unknown sample '{}'. Available samples: {}",
+                sample,
+                samples
+                    .iter()
+                    .take(10)
+                    .cloned()
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            None,
+        )
+    })
+}
+
+fn id_index_pending(index: &VcfIndex) -> Result<Option<IdIndexStatus>, McpError> {
+    match index.id_index_status() {
+        IdIndexStatus::Ready => Ok(None),
+        IdIndexStatus::Failed { error } => Err(McpError::internal_error(
+            format!("ID index could not be built: {}", error),
+            None,
+        )),
+        building => Ok(Some(building)),
+    }
+}
+
+// Helper function to build chromosome match response metadata
+fn build_chromosome_response(
+    index: &VcfIndex,
+    requested_chromosome: &str,
+    matched_chr: &Option<String>,
+) -> (QueryStatus, Option<Vec<String>>, Option<String>) {
+    if index.is_empty_file() {
+        return (QueryStatus::EmptyFile, None, None);
+    }
+    match matched_chr {
+        Some(_) => (QueryStatus::Ok, None, None),
+        None => {
+            let sample_chroms: Vec<String> = index
+                .get_available_chromosomes()
+                .into_iter()
+                .take(5)
+                .collect();
+            let alternate = if requested_chromosome.starts_with("chr") {
+                requested_chromosome
+                    .strip_prefix("chr")
+                    .unwrap_or(requested_chromosome)
+                    .to_string()
+            } else {
+                format!("chr{}", requested_chromosome)
+            };
+            (
+                QueryStatus::ChromosomeNotFound,
+                Some(sample_chroms),
+                Some(alternate),
+            )
+        }
+    }
+}
+
+impl ServerHandler for VcfServer {
+    fn get_info(&self) -> ServerInfo {
+        let mut capabilities = ServerCapabilities::builder()
+            .enable_tools()
+            .enable_resources()
+            .enable_prompts()
+            .enable_logging()
+            .build();
+        if let (Some(resources), Some(_)) = (&mut capabilities.resources, &self.subscriptions) {
+            resources.subscribe = Some(true);
+        }
+        ServerInfo {
+            protocol_version: ProtocolVersion::V_2024_11_05,
+            capabilities,
+            server_info: Implementation::from_build_env(),
+            instructions: Some(
+                "This server provides VCF variant query tools (query_by_position, query_by_region, query_by_id, start_region_query, get_next_variant, close_query_session) and a metadata resource (vcf://metadata). For large regions, use streaming tools (start_region_query + get_next_variant) to fetch variants one at a time. IMPORTANT: Genomic coordinates are specific to the reference genome build (GRCh37 vs GRCh38). Always check the reference_genome field in responses.".to_string()
+            ),
+        }
+    }
+
+    async fn list_resources(
+        &self,
+        request: Option<PaginatedRequestParam>,
+        _: RequestContext<RoleServer>,
+    ) -> Result<ListResourcesResult, McpError> {
+        let cursor = request.and_then(|request| request.cursor);
+        let mut resources = self.resources();
+        resources.retain(|resource| self.check_resource_allowed(&resource.raw.uri).is_ok());
+        let (resources, next_cursor) = paginate(resources, cursor.as_deref(), RESOURCE_PAGE_SIZE)?;
+        Ok(ListResourcesResult {
+            resources,
+            next_cursor,
+            meta: None,
+        })
+    }
+
+    async fn read_resource(
+        &self,
+        request: ReadResourceRequestParam,
+        _: RequestContext<RoleServer>,
+    ) -> Result<ReadResourceResult, McpError> {
+        self.check_resource_allowed(&request.uri)?;
+        if request.uri.as_str() == "vcf://metadata" {
+            let index = &self.index();
+            let metadata = index.get_metadata();
+            let metadata_json = serde_json::to_string_pretty(&metadata).map_err(|e| {
+                McpError::internal_error(format!("Failed to serialize metadata: {}", e), None)
+            })?;
+
+            Ok(ReadResourceResult {
+                contents: vec![ResourceContents::TextResourceContents {
+                    uri: request.uri.to_string(),
+                    mime_type: Some("application/json".to_string()),
+                    text: metadata_json,
+                    meta: None,
+                }],
+            })
+        } else if let Some(page) = parse_ids_page(&request.uri) {
+            let page = page?;
+            let id_page = self
+                .run_query(move |index| {
+                    id_index_pending(index)?;
+                    index.id_page(page, ID_PAGE_SIZE).ok_or_else(|| {
+                        McpError::invalid_request(
+                            "The ID index is still being built. Try again shortly.",
+                            None,
+                        )
+                    })
+                })
+                .await?;
+            let text = serde_json::to_string(&id_page).map_err(|e| {
+                McpError::internal_error(format!("Failed to serialize ID page: {}", e), None)
+            })?;
+
+            Ok(ReadResourceResult {
+                contents: vec![ResourceContents::TextResourceContents {
+                    uri: request.uri.to_string(),
+                    mime_type: Some("application/json".to_string()),
+                    text,
+                    meta: None,
+                }],
+            })
+        } else if let Some(name) = request.uri.strip_prefix("vcf://chromosome/") {
+            self.read_chromosome_resource(&request.uri, name)
+        } else if let Some(slice) = parse_region_uri(&request.uri) {
+            self.read_region_resource(&request.uri, slice?).await
+        } else {
+            Err(McpError::resource_not_found(
+                format!("Resource not found: {}", request.uri),
+                None,
+            ))
+        }
+    }
+
+    async fn subscribe(
+        &self,
+        request: SubscribeRequestParam,
+        context: RequestContext<RoleServer>,
+    ) -> Result<(), McpError> {
+        let Some(subscriptions) = &self.subscriptions else {
+            return Err(McpError::invalid_request(
+                "Resource subscriptions need the server to watch the VCF for changes (--watch)"
+                    .to_string(),
+                None,
+            ));
+        };
+        let Some(session) = session_key(&context) else {
+            return Err(McpError::invalid_request(
+                "Resource subscriptions over HTTP need a session to notify; start the server with --stateful-sessions"
+                    .to_string(),
+                None,
+            ));
+        };
+        subscriptions.subscribe(session, context.peer.clone(), request.uri);
+        Ok(())
+    }
+
+    async fn unsubscribe(
+        &self,
+        request: UnsubscribeRequestParam,
+        context: RequestContext<RoleServer>,
+    ) -> Result<(), McpError> {
+        if let (Some(subscriptions), Some(session)) = (&self.subscriptions, session_key(&context)) {
+            subscriptions.unsubscribe(&session, &request.uri);
+        }
+        Ok(())
+    }
+
+    async fn list_prompts(
+        &self,
+        _request: Option<PaginatedRequestParam>,
+        _: RequestContext<RoleServer>,
+    ) -> Result<ListPromptsResult, McpError> {
+        Ok(ListPromptsResult {
+            next_cursor: None,
+            prompts: prompts::prompts(),
+            meta: None,
+        })
+    }
+
+    async fn get_prompt(
+        &self,
+        request: GetPromptRequestParam,
+        _: RequestContext<RoleServer>,
+    ) -> Result<GetPromptResult, McpError> {
+        let arguments = request.arguments.unwrap_or_default();
+        prompts::get_prompt(&request.name, &arguments, &self.prompt_context())
+    }
+
+    async fn list_resource_templates(
+        &self,
+        _request: Option<PaginatedRequestParam>,
+        _: RequestContext<RoleServer>,
+    ) -> Result<ListResourceTemplatesResult, McpError> {
+        let mut resource_templates = vec![Annotated::new(
+                RawResourceTemplate {
+                    uri_template: "vcf://ids?page={page}".to_string(),
+                    name: "Variant IDs by page".to_string(),
+                    title: None,
+                    description: Some(format!(
+                        "Page N (from 0) of every variant ID with its chromosome and position, sorted by ID, {} IDs per page. Each page reports total_pages and next_page",
+                        ID_PAGE_SIZE
+                    )),
+                    mime_type: Some("application/json".to_string()),
+                    icons: None,
+                },
+                None,
+            ), Annotated::new(
+                RawResourceTemplate {
+                    uri_template: "vcf://chromosome/{name}".to_string(),
+                    name: "Chromosome summary".to_string(),
+                    title: None,
+                    description: Some(
+                        "Summary statistics of one contig: declared length, variant count and density, and first and last variant position".to_string()
+                    ),
+                    mime_type: Some("application/json".to_string()),
+                    icons: None,
+                },
+                None,
+            ), Annotated::new(
+                RawResourceTemplate {
+                    uri_template: REGION_TEMPLATE.to_string(),
+                    name: "Region slice".to_string(),
+                    title: None,
+                    description: Some(format!(
+                        "The variants from start to end (1-based, inclusive) of a chromosome as JSON, at most {}; end the URI in .vcf for VCF text instead (e.g. vcf://20/14000-18000.vcf)",
+                        REGION_PAGE_SIZE
+                    )),
+                    mime_type: Some("application/json".to_string()),
+                    icons: None,
+                },
+                None,
+            )];
+        resource_templates.retain(|template| {
+            self.check_resource_allowed(&template.raw.uri_template)
+                .is_ok()
+        });
+        Ok(ListResourceTemplatesResult {
+            next_cursor: None,
+            resource_templates,
+            meta: None,
+        })
+    }
+
+    async fn initialize(
+        &self,
+        request: InitializeRequestParam,
+        context: RequestContext<RoleServer>,
+    ) -> Result<InitializeResult, McpError> {
+        self.log
+            .log(
+                &session_key(&context).unwrap_or_default(),
+                &context.peer,
+                LoggingLevel::Debug,
+                serde_json::json!({
+                    "event": "initialize",
+                    "client": request.client_info,
+                    "protocol_version": request.protocol_version,
+                }),
+            )
+            .await;
+        Ok(self.get_info())
+    }
+
+    async fn set_level(
+        &self,
+        request: SetLevelRequestParam,
+        context: RequestContext<RoleServer>,
+    ) -> Result<(), McpError> {
+        // Without a session the level would apply to no later request
+        if let Some(session) = session_key(&context) {
+            self.log.set_level(session, &request.level);
+        }
+        Ok(())
+    }
+
+    async fn list_tools(
+        &self,
+        _request: Option<PaginatedRequestParam>,
+        _: RequestContext<RoleServer>,
+    ) -> Result<ListToolsResult, McpError> {
+        Ok(ListToolsResult {
+            tools: self.tool_router.list_all(),
+            next_cursor: None,
+            meta: None,
+        })
+    }
+
+    async fn call_tool(
+        &self,
+        request: CallToolRequestParam,
+        mut ctx: RequestContext<RoleServer>,
+    ) -> Result<CallToolResult, McpError> {
+        let Some(_in_flight) = self.drain.enter() else {
+            return Err(shutdown::shutting_down_error());
+        };
+        let start_time = std::time::Instant::now();
+        let session = session_key(&ctx).unwrap_or_default();
+        let peer = ctx.peer.clone();
+        let tool = request.name.to_string();
+        self.log
+            .log(
+                &session,
+                &peer,
+                LoggingLevel::Debug,
+                serde_json::json!({
+                    "event": "tool_call",
+                    "tool": tool,
+                    "arguments": request.arguments,
+                }),
+            )
+            .await;
+        let span = tracing::debug_span!("tool_call", tool = %tool, session = %session);
+        span.in_scope(|| {
+            debug!(
+                arguments = %serde_json::Value::from(request.arguments.clone()),
+                "tool call received"
+            )
+        });
+        let scanned = ScanCounter::default();
+        ctx.extensions.insert(scanned.clone());
+        let recorded_request = self.recorder.as_ref().map(|_| request.clone());
+        let cache_key = self
+            .result_cache
+            .as_ref()
+            .and_then(|_| result_cache::ResultCache::key(&request));
+        let cached = match (&self.result_cache, &cache_key) {
+            (Some(cache), Some(key)) => cache.get(key),
+            _ => None,
+        };
+        let cache_hit = cached.is_some();
+
+        let mut result = match cached {
+            Some(result) => Ok(result),
+            None => {
+                let tool_ctx = ToolCallContext::new(self, request, ctx);
+                self.tool_router
+                    .call(tool_ctx)
+                    .instrument(span.clone())
+                    .await
+            }
+        };
+
+        if let (Some(recorder), Some(request)) = (&self.recorder, &recorded_request) {
+            recorder.record(request, &result);
+        }
+
+        if let (Some(cache), Some(key), Ok(result)) = (&self.result_cache, cache_key, &mut result) {
+            if cache_hit {
+                result_cache::mark_cache_hit(result);
+            } else if matches!(self.index().id_index_status(), IdIndexStatus::Ready) {
+                // Responses given while the ID index is building would go stale
+                cache.insert(key, result);
+            }
+        }
+
+        let duration_ms = start_time.elapsed().as_secs_f64() * 1000.0;
+        let (level, entry) = match &result {
+            Ok(result) => {
+                let response_bytes = serde_json::to_string(&result.content)
+                    .map(|s| s.len())
+                    .unwrap_or(0);
+                let records_scanned = scanned.get();
+                span.in_scope(|| {
+                    debug!(
+                        duration_ms,
+                        response_bytes, records_scanned, cache_hit, "tool call finished"
+                    )
+                });
+                let mut entry = serde_json::json!({
+                    "event": "tool_result",
+                    "tool": tool,
+                    "duration_ms": duration_ms,
+                    "response_bytes": response_bytes,
+                    "records_scanned": records_scanned,
+                    "cache_hit": cache_hit,
+                    "block_cache": self.index().block_cache_stats(),
+                });
+                if let (true, Some(stats)) = (tool == "query_by_id", self.index().id_bloom_stats())
+                {
+                    entry["id_bloom_filter"] = serde_json::json!({
+                        "lookups": stats.lookups,
+                        "rejected": stats.rejected,
+                        "false_positives": stats.false_positives,
+                        "observed_fp_rate": stats.observed_fp_rate(),
+                        "expected_fp_rate": stats.expected_fp_rate,
+                    });
+                }
+                (LoggingLevel::Info, entry)
+            }
+            Err(e) => {
+                span.in_scope(|| warn!(duration_ms, error = %e.message, "tool call failed"));
+                (
+                    LoggingLevel::Warning,
+                    serde_json::json!({
+                        "event": "tool_error",
+                        "tool": tool,
+                        "duration_ms": duration_ms,
+                        "error": e.message,
+                    }),
+                )
+            }
+        };
+        self.log.log(&session, &peer, level, entry).await;
+
+        result
+    }
+}
+
+#[tokio::main]
+async fn main() -> std::io::Result<()> {
+    let command = Args::command();
+    let argv = config::expand_args(&command, std::env::args_os().collect()).unwrap_or_else(|e| {
+        command
+            .clone()
+            .error(clap::error::ErrorKind::InvalidValue, e)
+            .exit()
+    });
+    let args = Args::parse_from(argv);
+
+    if let Some(Command::Replay {
+        recording,
+        vcf_file,
+        binary,
+        debug,
+    }) = &args.command
+    {
+        logging::init(logging::LogFormat::Text, None, *debug)?;
+        return run_replay(recording, vcf_file, binary.as_deref(), *debug).await;
+    }
+
+    if let Some(Command::Cache { action }) = &args.command {
+        return run_cache(action);
+    }
+
+    if let Some(Command::Index {
+        vcf_files,
+        threads,
+        force,
+        low_memory,
+        sample_presence_index,
+        debug,
+    }) = &args.command
+    {
+        logging::init(logging::LogFormat::Text, None, *debug)?;
+        let options = LoadOptions {
+            debug: *debug,
+            save_index: true,
+            background: false,
+            low_memory: *low_memory,
+            sample_presence: *sample_presence_index,
+            ..Default::default()
+        };
+        return run_index(vcf_files, *threads as usize, *force, &options);
+    }
+
+    if let Some(Command::Validate { vcf_file }) = &args.command {
+        return run_validate(vcf_file);
+    }
+
+    if let Some(Command::Query {
+        vcf_file,
+        region,
+        position,
+        id,
+        filter,
+        format,
+        fields,
+        cursor,
+        never_save_index,
+        debug,
+    }) = &args.command
+    {
+        logging::init(logging::LogFormat::Text, None, *debug)?;
+        let (tool, mut arguments) = match (region, position, id) {
+            (Some(region), _, _) => (
+                "query_by_region",
+                serde_json::json!({
+                    "chromosome": region.chromosome,
+                    "start": region.start,
+                    "end": region.end,
+                    "filter": filter.clone().unwrap_or_default(),
+                    "cursor": cursor,
+                    "confirm_large_query": true,
+                }),
+            ),
+            (_, Some(position), _) => (
+                "query_by_position",
+                serde_json::json!({
+                    "chromosome": position.chromosome,
+                    "position": position.start,
+                }),
+            ),
+            // clap requires one of the three
+            _ => (
+                "query_by_id",
+                serde_json::json!({ "id": id.clone().unwrap_or_default() }),
+            ),
+        };
+        arguments["format"] = serde_json::json!(format.name());
+        if !fields.is_empty() {
+            arguments["fields"] = serde_json::json!(fields);
+        }
+        let options = LoadOptions {
+            debug: *debug,
+            save_index: !never_save_index,
+            ..Default::default()
+        };
+        return run_query(vcf_file, tool, arguments, *format, &options).await;
+    }
+
+    logging::init(args.log_format, args.log_file.as_deref(), args.debug)?;
+    if let Some(path) = &args.config {
+        info!("Options read from {}", path.display());
+    }
+    let stopping = shutdown::listen();
+    let drain_timeout = std::time::Duration::from_secs(args.drain_timeout);
+
+    if let Some(manifest) = &args.manifest {
+        return run_supervisor(&args, manifest, stopping).await;
+    }
+
+    // clap guarantees the positional file when no manifest is given
+    let vcf_file = args.vcf_file.as_ref().expect("VCF file argument");
+    if !vcf_file.exists() {
+        error!("VCF file not found: {}", vcf_file.display());
+        std::process::exit(1);
+    }
+
+    // Load the VCF file; unless asked to wait, a missing ID index is built in the background
+    let save_index = !args.never_save_index;
+    let options = LoadOptions {
+        debug: args.debug,
+        save_index,
+        background: !args.wait_for_index,
+        low_memory: args.low_memory,
+        sample_presence: args.sample_presence_index,
+        block_cache_bytes: (args.block_cache_mb * 1024 * 1024) as usize,
+        mmap: args.mmap,
+        hide_genotypes: args.hide_genotypes,
+        limits: QueryLimits {
+            max_region_bp: (args.max_region_bp > 0).then_some(args.max_region_bp),
+            max_results: (args.max_results > 0).then_some(args.max_results),
+            timeout: (args.query_timeout > 0)
+                .then(|| std::time::Duration::from_secs(args.query_timeout)),
+        },
+        retry: RetryPolicy {
+            retries: args.io_retries,
+            initial_delay: std::time::Duration::from_millis(args.io_retry_delay_ms),
+        },
+    };
+    let index = load_vcf_with_options(vcf_file, &options)?;
+
+    // Create the MCP server
+    let mut server = VcfServer::new(index, args.debug);
+    if let Err(e) = server.restrict_tools(&args.enable_tools, &args.disable_tools) {
+        error!("{}", e);
+        std::process::exit(1);
+    }
+    if args.hide_genotypes {
+        server.hide_genotypes();
+        info!("Genotypes hidden: FORMAT and sample columns are never served");
+    }
+    // The Beacon endpoint answers region queries, so it must not outlive a disabled one
+    if args.beacon && !server.tool_enabled("query_by_region") {
+        error!("--beacon serves the same data as query_by_region, which is disabled");
+        std::process::exit(1);
+    }
+    if args.max_concurrent_queries > 0 {
+        server.limit_concurrent_queries(args.max_concurrent_queries);
+    }
+    server.cap_region_size(args.max_region_size);
+    if let Some(path) = &args.reference {
+        server.use_reference(ReferenceFasta::open(path)?);
+        info!("Normalizing alleles against {}", path.display());
+    }
+    if let Some(path) = &args.annotation {
+        let annotation = load_vcf_with_options(path, &options)?;
+        let undeclared = annotation.undeclared_info_keys(&args.annotation_fields);
+        if !undeclared.is_empty() {
+            error!(
+                "--annotation-fields not declared as INFO fields in {}: {}",
+                path.display(),
+                undeclared.join(", ")
+            );
+            std::process::exit(1);
+        }
+        server.use_annotation(annotation, args.annotation_fields.clone());
+        info!("Annotating query results from {}", path.display());
+    }
+    if let Some(path) = &args.chain {
+        server.use_chain(ChainFile::open(path)?);
+        info!("Lifting coordinates with {}", path.display());
+    }
+    #[cfg(not(feature = "network"))]
+    if args.allow_network {
+        error!("--allow-network needs a server built with the network feature");
+        std::process::exit(1);
+    }
+    #[cfg(feature = "network")]
+    if args.allow_network {
+        let server_url = args.vep_server.clone().unwrap_or_else(|| {
+            vep::server_for_build(&server.index().get_metadata().reference_genome.build).to_string()
+        });
+        let client = vep::VepClient::new(&server_url).map_err(std::io::Error::other)?;
+        server.allow_vep(client);
+        let client =
+            dbsnp::DbSnpClient::new(dbsnp::NCBI_VARIATION_SERVER).map_err(std::io::Error::other)?;
+        server.allow_dbsnp(client);
+        info!(
+            "Network access allowed: annotate_via_vep sends variants to {}, query_by_id resolves rsIDs at {}",
+            server_url,
+            dbsnp::NCBI_VARIATION_SERVER
+        );
+    }
+    if let Some(dir) = &args.bed_dir {
+        server.allow_bed_dir(dir.canonicalize()?);
+        info!("query_by_bed may read BED files under {}", dir.display());
+    }
+    if let Some(dir) = &args.allow_write_dir {
+        server.allow_write_dir(dir.canonicalize()?);
+        info!(
+            "export_region and export_parquet may write files to {}",
+            dir.display()
+        );
+    }
+    if let Some(path) = &args.ped {
+        let trios = pedigree::load_ped(path)?;
+        info!("Loaded {} trios from {}", trios.len(), path.display());
+        server.use_pedigree(trios);
+    }
+    if args.result_cache_ttl > 0 {
+        server.cache_results(result_cache::ResultCache::new(
+            std::time::Duration::from_secs(args.result_cache_ttl),
+            (args.result_cache_mb * 1024 * 1024) as usize,
+        ));
+    }
+    if let Some(path) = &args.record_queries {
+        let header = replay::RecordingHeader {
+            server_args: recorded_server_args(args)?,
+        };
+        server.record_queries(replay::QueryRecorder::create(
+            path,
+            &header,
+            args.record_responses,
+        )?);
+        info!("Recording tool calls to {}", path.display());
+    }
+
+    server.enable_reload(vcf_file.clone(), options);
+    if args.watch {
+        server.watch_resources();
+        tokio::spawn(watch_vcf(server.clone(), vcf_file.clone()));
+        info!("Watching {} and its index for changes", vcf_file.display());
+    }
+    #[cfg(unix)]
+    {
+        let server = server.clone();
+        tokio::spawn(on_hangup(move || {
+            let server = server.clone();
+            async move {
+                let _ = server.reload("SIGHUP").await;
+            }
+        }));
+    }
+
+    // Run server with appropriate transport
+    if let Some(addr) = &args.sse {
+        info!(
+            "VCF MCP Server ready. Starting SSE transport on {}...",
+            addr
+        );
+        let routes = if args.beacon {
+            let dataset = vcf_file
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            info!(
+                "Beacon v2 endpoint at /beacon/g_variants (up to {:?} granularity)",
+                args.beacon_granularity
+            );
+            BeaconEndpoint {
+                server: server.clone(),
+                dataset,
+                max_granularity: args.beacon_granularity,
+            }
+            .routes()
+        } else {
+            axum::Router::new()
+        };
+        run_sse_server(
+            server.clone(),
+            args.debug,
+            addr,
+            routes,
+            HttpOptions::from_args(&args),
+            stopping,
+        )
+        .await?;
+        server.shut_down(drain_timeout).await;
+    } else {
+        info!("VCF MCP Server ready. Starting stdio transport...");
+
+        // Run the server using stdio transport
+        let service = server
+            .clone()
+            .serve(rmcp::transport::stdio())
+            .await
+            .map_err(|e| std::io::Error::other(e.to_string()))?;
+
+        // Running calls still answer on stdout while draining; the transport closes after
+        let closing = service.cancellation_token();
+        let waiting = service.waiting();
+        tokio::pin!(waiting);
+        let signalled = tokio::select! {
+            result = &mut waiting => {
+                result.map_err(|e| std::io::Error::other(e.to_string()))?;
+                false
+            }
+            _ = stopping.cancelled() => true,
+        };
+        server.shut_down(drain_timeout).await;
+        if signalled {
+            closing.cancel();
+            let _ = waiting.await;
+        }
+    }
+
+    Ok(())
+}
+
+// The options that change tool responses, as passed to the server replaying a recording
+fn recorded_server_args(args: &Args) -> std::io::Result<Vec<String>> {
+    let mut server_args = Vec::new();
+    // Replay may run from another directory
+    let paths = [
+        ("--reference", &args.reference),
+        ("--annotation", &args.annotation),
+        ("--chain", &args.chain),
+        ("--bed-dir", &args.bed_dir),
+        ("--allow-write-dir", &args.allow_write_dir),
+        ("--ped", &args.ped),
+    ];
+    for (flag, path) in paths {
+        if let Some(path) = path {
+            server_args.push(flag.to_string());
+            server_args.push(path.canonicalize()?.display().to_string());
+        }
+    }
+    if !args.annotation_fields.is_empty() {
+        server_args.push("--annotation-fields".to_string());
+        server_args.push(args.annotation_fields.join(","));
+    }
+    if args.allow_network {
+        server_args.push("--allow-network".to_string());
+    }
+    if args.hide_genotypes {
+        server_args.push("--hide-genotypes".to_string());
+    }
+    if let Some(url) = &args.vep_server {
+        server_args.push("--vep-server".to_string());
+        server_args.push(url.clone());
+    }
+    if args.sample_presence_index {
+        server_args.push("--sample-presence-index".to_string());
+    }
+    for (flag, value) in [
+        ("--max-region-size", args.max_region_size),
+        ("--max-region-bp", args.max_region_bp),
+        ("--max-results", args.max_results as u64),
+        ("--query-timeout", args.query_timeout),
+    ] {
+        server_args.push(flag.to_string());
+        server_args.push(value.to_string());
+    }
+    for (flag, tools) in [
+        ("--enable-tools", &args.enable_tools),
+        ("--disable-tools", &args.disable_tools),
+    ] {
+        if !tools.is_empty() {
+            server_args.push(flag.to_string());
+            server_args.push(tools.join(","));
+        }
+    }
+    Ok(server_args)
+}
+
+// Replay recorded tool calls and exit non-zero if any response changed
+async fn run_replay(
+    recording: &std::path::Path,
+    vcf_file: &std::path::Path,
+    binary: Option<&std::path::Path>,
+    debug: bool,
+) -> std::io::Result<()> {
+    if !vcf_file.exists() {
+        eprintln!("Error: VCF file not found: {}", vcf_file.display());
+        std::process::exit(1);
+    }
+
+    let report = replay::replay(recording, vcf_file, binary, debug).await?;
+    for mismatch in &report.mismatches {
+        println!(
+            "CHANGED #{} {} {}: recorded {}, got {}",
+            mismatch.number,
+            mismatch.call.tool,
+            serde_json::to_string(&mismatch.call.arguments).unwrap_or_default(),
+            mismatch.call.digest,
+            mismatch.digest
+        );
+        if let Some(differences) = &mismatch.differences {
+            println!("{}", serde_json::to_string(differences).unwrap_or_default());
+        }
+    }
+    println!(
+        "Replayed {} calls: {} unchanged, {} changed",
+        report.total,
+        report.total - report.mismatches.len(),
+        report.mismatches.len()
+    );
+
+    if !report.mismatches.is_empty() {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+// Cache subcommands, run against the sidecar files in a directory
+fn run_cache(action: &CacheAction) -> std::io::Result<()> {
+    match action {
+        CacheAction::Ls { dir } => {
+            let sidecars = cache_admin::scan(dir)?;
+            for sidecar in &sidecars {
+                println!(
+                    "{:<9} {:>10} {:>5}  {}{}{}",
+                    sidecar.kind.label(),
+                    cache_admin::format_size(sidecar.size),
+                    cache_admin::format_age(sidecar.age()),
+                    sidecar.path.display(),
+                    if sidecar.temporary {
+                        " (temp file)"
+                    } else {
+                        ""
+                    },
+                    if sidecar.orphaned() {
+                        " (VCF missing)"
+                    } else {
+                        ""
+                    },
+                );
+            }
+            let total: u64 = sidecars.iter().map(|sidecar| sidecar.size).sum();
+            println!(
+                "{} files, {}",
+                sidecars.len(),
+                cache_admin::format_size(total)
+            );
+        }
+        CacheAction::Verify { dir } => {
+            let mut problems = 0;
+            for sidecar in cache_admin::scan(dir)? {
+                let status = cache_admin::verify(&sidecar);
+                match status.detail() {
+                    Some(detail) => println!(
+                        "{:<12} {}: {}",
+                        status.label(),
+                        sidecar.path.display(),
+                        detail
+                    ),
+                    None => println!("{:<12} {}", status.label(), sidecar.path.display()),
+                }
+                if status.is_problem() {
+                    problems += 1;
+                }
+            }
+            if problems > 0 {
+                println!("{} files need attention (see `cache prune`)", problems);
+                std::process::exit(1);
+            }
+        }
+        CacheAction::Prune {
+            dir,
+            min_temp_age,
+            dry_run,
+        } => {
+            let min_temp_age = std::time::Duration::from_secs(*min_temp_age);
+            let (mut removed, mut freed) = (0, 0);
+            for sidecar in cache_admin::scan(dir)? {
+                let status = cache_admin::verify(&sidecar);
+                if !cache_admin::should_prune(&sidecar, &status, min_temp_age) {
+                    continue;
+                }
+                if !dry_run {
+                    if let Err(e) = std::fs::remove_file(&sidecar.path) {
+                        eprintln!(
+                            "Warning: Failed to remove {}: {}",
+                            sidecar.path.display(),
+                            e
+                        );
+                        continue;
+                    }
+                }
+                println!(
+                    "{} {} ({}, {})",
+                    if *dry_run { "Would remove" } else { "Removed" },
+                    sidecar.path.display(),
+                    status.label(),
+                    cache_admin::format_size(sidecar.size)
+                );
+                removed += 1;
+                freed += sidecar.size;
+            }
+            println!(
+                "{} {} files, {}",
+                if *dry_run { "Would remove" } else { "Removed" },
+                removed,
+                cache_admin::format_size(freed)
+            );
+        }
+    }
+    Ok(())
+}
+
+// Build the indexes and statistics of one VCF that are missing or stale (all of them with
+// `force`); an error if one of them could not be saved
+fn index_file(path: &PathBuf, force: bool, options: &LoadOptions) -> std::io::Result<()> {
+    use cache_admin::SidecarKind;
+
+    if !path.is_file() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            "VCF file not found",
+        ));
+    }
+    if force {
+        cache_admin::remove_built(path)?;
+    }
+    drop(load_vcf_with_options(path, options)?);
+
+    // Saving is best effort when serving, so check what actually reached the disk
+    let saved = |kind| cache_admin::sidecar_path(path, kind).is_file();
+    let mut missing = Vec::new();
+    if !saved(SidecarKind::Tabix) && !saved(SidecarKind::Csi) {
+        missing.push(SidecarKind::Tabix.label());
+    }
+    for kind in [SidecarKind::IdIndex, SidecarKind::Statistics] {
+        if !saved(kind) {
+            missing.push(kind.label());
+        }
+    }
+    if !missing.is_empty() {
+        return Err(std::io::Error::other(format!(
+            "could not save {} (see the warnings above)",
+            missing.join(", ")
+        )));
+    }
+    Ok(())
+}
+
+// Index VCF files, `threads` at a time, and exit non-zero if any failed
+fn run_index(
+    vcf_files: &[PathBuf],
+    threads: usize,
+    force: bool,
+    options: &LoadOptions,
+) -> std::io::Result<()> {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let next = AtomicUsize::new(0);
+    let failed = AtomicUsize::new(0);
+    std::thread::scope(|scope| {
+        for _ in 0..threads.min(vcf_files.len()) {
+            scope.spawn(|| {
+                while let Some(path) = vcf_files.get(next.fetch_add(1, Ordering::Relaxed)) {
+                    let _span = tracing::info_span!("index", file = %path.display()).entered();
+                    let started = std::time::Instant::now();
+                    match index_file(path, force, options) {
+                        Ok(()) => info!(
+                            "Indexed {} in {:.1}s",
+                            path.display(),
+                            started.elapsed().as_secs_f64()
+                        ),
+                        Err(e) => {
+                            error!("Failed to index {}: {}", path.display(), e);
+                            failed.fetch_add(1, Ordering::Relaxed);
+                        }
+                    }
+                }
+            });
+        }
+    });
+
+    let failed = failed.into_inner();
+    println!(
+        "Indexed {} of {} files",
+        vcf_files.len() - failed,
+        vcf_files.len()
+    );
+    if failed > 0 {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+// Print a validation report; exits with status 1 if the file has errors and 2 if it can't be read
+fn run_validate(vcf_file: &std::path::Path) -> std::io::Result<()> {
+    let report = match validate::validate(vcf_file) {
+        Ok(report) => report,
+        Err(e) => {
+            eprintln!("Error: Failed to read {}: {}", vcf_file.display(), e);
+            std::process::exit(2);
+        }
+    };
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&report).map_err(std::io::Error::other)?
+    );
+    if !report.valid {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+// Call a query tool on the file in-process and print its response: the JSON payload, or with
+// the text formats just the table, VCF or compact lines. Exits with status 1 if the call fails.
+async fn run_query(
+    vcf_file: &PathBuf,
+    tool: &str,
+    arguments: serde_json::Value,
+    format: OutputFormat,
+    options: &LoadOptions,
+) -> std::io::Result<()> {
+    if !vcf_file.exists() {
+        eprintln!("Error: VCF file not found: {}", vcf_file.display());
+        std::process::exit(1);
+    }
+    let server = VcfServer::new(load_vcf_with_options(vcf_file, options)?, options.debug);
+
+    let result = match tool {
+        "query_by_region" => match serde_json::from_value(arguments) {
+            Ok(params) => {
+                server
+                    .query_region_page(params, CallContext::default())
+                    .await
+            }
+            Err(e) => Err(McpError::invalid_params(e.to_string(), None)),
+        },
+        "query_by_position" => match serde_json::from_value(arguments) {
+            Ok(params) => server.query_by_position(Parameters(params)).await,
+            Err(e) => Err(McpError::invalid_params(e.to_string(), None)),
+        },
+        _ => match serde_json::from_value(arguments) {
+            Ok(params) => server.query_by_id(Parameters(params)).await,
+            Err(e) => Err(McpError::invalid_params(e.to_string(), None)),
+        },
+    };
+    let payload: serde_json::Value = match result {
+        Ok(result) => result
+            .content
+            .first()
+            .and_then(|content| content.as_text())
+            .and_then(|text| serde_json::from_str(&text.text).ok())
+            .unwrap_or_default(),
+        Err(e) => {
+            eprintln!("Error: {} failed: {}", tool, e.message);
+            std::process::exit(1);
+        }
+    };
+
+    let text = match format {
+        OutputFormat::Json => None,
+        OutputFormat::Tsv | OutputFormat::Csv => {
+            payload["result"]["table"].as_str().map(str::to_string)
+        }
+        OutputFormat::Vcf => payload["result"]["vcf"].as_str().map(str::to_string),
+        OutputFormat::Compact => payload["result"]["items"].as_array().map(|items| {
+            items
+                .iter()
+                .filter_map(|item| item.as_str())
+                .map(|line| format!("{}\n", line))
+                .collect()
+        }),
+    };
+    match text {
+        Some(text) => {
+            // What the response says besides the variants goes to stderr
+            if payload["status"] != "ok" {
+                eprintln!(
+                    "Status: {}",
+                    payload["status"].as_str().unwrap_or("unknown")
+                );
+                if let Some(suggestion) = payload["alternate_chromosome_suggestion"].as_str() {
+                    eprintln!("Did you mean chromosome {}?", suggestion);
+                }
+            }
+            print!("{}", text);
+        }
+        None => println!(
+            "{}",
+            serde_json::to_string_pretty(&payload).map_err(std::io::Error::other)?
+        ),
+    }
+    if let Some(cursor) = payload["next_cursor"].as_str() {
+        eprintln!("More variants follow; print them with --cursor {}", cursor);
+    }
+    Ok(())
+}
+
+// Supervisor mode: route tool calls to one worker process per manifest dataset
+async fn run_supervisor(
+    args: &Args,
+    manifest_path: &std::path::Path,
+    stopping: CancellationToken,
+) -> std::io::Result<()> {
+    let manifest = supervisor::Manifest::load(manifest_path)?;
+    let options = supervisor::WorkerOptions {
+        debug: args.debug,
+        log_format: args.log_format,
+        log_file: args.log_file.clone(),
+        never_save_index: args.never_save_index,
+        wait_for_index: args.wait_for_index,
+        low_memory: args.low_memory,
+        sample_presence_index: args.sample_presence_index,
+        block_cache_mb: Some(args.block_cache_mb),
+        mmap: args.mmap,
+        max_region_size: Some(args.max_region_size),
+        max_region_bp: args.max_region_bp,
+        max_results: args.max_results,
+        query_timeout: args.query_timeout,
+        io_retries: Some(args.io_retries),
+        io_retry_delay_ms: Some(args.io_retry_delay_ms),
+        drain_timeout: Some(args.drain_timeout),
+        enable_tools: args.enable_tools.clone(),
+        disable_tools: args.disable_tools.clone(),
+        hide_genotypes: args.hide_genotypes,
+        max_concurrent_queries: args.max_concurrent_queries,
+        result_cache_ttl: args.result_cache_ttl,
+        result_cache_mb: Some(args.result_cache_mb),
+        extra_args: Vec::new(),
+    };
+    let drain_timeout = std::time::Duration::from_secs(args.drain_timeout);
+    let limits = supervisor::EvictionLimits {
+        max_loaded: (args.max_loaded_datasets > 0).then_some(args.max_loaded_datasets),
+        max_memory_bytes: (args.max_worker_memory_mb > 0)
+            .then(|| args.max_worker_memory_mb * 1024 * 1024),
+    };
+
+    info!(
+        "Supervisor serving {} datasets from {}",
+        manifest.datasets.len(),
+        manifest_path.display()
+    );
+    let server = match supervisor::SupervisorServer::new(manifest, options, limits) {
+        Ok(server) => server,
+        Err(e) => {
+            error!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    #[cfg(unix)]
+    {
+        let server = server.clone();
+        tokio::spawn(on_hangup(move || {
+            let server = server.clone();
+            async move { server.reload_workers().await }
+        }));
+    }
+
+    if let Some(addr) = &args.sse {
+        info!(
+            "VCF MCP Supervisor ready. Starting SSE transport on {}...",
+            addr
+        );
+        let options = HttpOptions::from_args(args);
+        run_sse_server(
+            server.clone(),
+            args.debug,
+            addr,
+            axum::Router::new(),
+            options,
+            stopping,
+        )
+        .await?;
+        server.shut_down(drain_timeout).await;
+        Ok(())
+    } else {
+        info!("VCF MCP Supervisor ready. Starting stdio transport...");
+        let service = server
+            .clone()
+            .serve(rmcp::transport::stdio())
+            .await
+            .map_err(|e| std::io::Error::other(e.to_string()))?;
+
+        let closing = service.cancellation_token();
+        let waiting = service.waiting();
+        tokio::pin!(waiting);
+        tokio::select! {
+            result = &mut waiting => {
+                result.map_err(|e| std::io::Error::other(e.to_string()))?;
+            }
+            _ = stopping.cancelled() => {
+                server.shut_down(drain_timeout).await;
+                closing.cancel();
+                let _ = waiting.await;
+            }
+        }
+        Ok(())
+    }
+}
+
+// The GA4GH Beacon v2 endpoint (--beacon), answering variant queries from the MCP server's index
+#[derive(Clone)]
+struct BeaconEndpoint {
+    server: VcfServer,
+    // File name, the id of the Beacon's one result set
+    dataset: String,
+    max_granularity: beacon::Granularity,
+}
+
+impl BeaconEndpoint {
+    fn routes(self) -> axum::Router {
+        use axum::extract::{Query, State};
+        use axum::routing::get;
+
+        async fn get_variants(
+            State(endpoint): State<BeaconEndpoint>,
+            Query(query): Query<HashMap<String, String>>,
+        ) -> (axum::http::StatusCode, axum::Json<serde_json::Value>) {
+            endpoint
+                .answer(beacon::VariantRequest::from_query(&query))
+                .await
+        }
+
+        async fn post_variants(
+            State(endpoint): State<BeaconEndpoint>,
+            body: axum::body::Bytes,
+        ) -> (axum::http::StatusCode, axum::Json<serde_json::Value>) {
+            let request = serde_json::from_slice::<serde_json::Value>(&body)
+                .map_err(|e| format!("Request body is not JSON: {}", e))
+                .and_then(|body| beacon::VariantRequest::from_body(&body));
+            endpoint.answer(request).await
+        }
+
+        axum::Router::new()
+            .route("/beacon/g_variants", get(get_variants).post(post_variants))
+            .with_state(self)
+    }
+
+    async fn answer(
         &self,
-        Parameters(params): Parameters<GetStatisticsParams>,
-    ) -> Result<CallToolResult, McpError> {
-        let start_time = std::time::Instant::now();
-        let mut stats = {
-            let index = self.index.lock().await;
-            index.compute_statistics().map_err(|e| {
-                McpError::internal_error(format!("Failed to compute statistics: {}", e), None)
-            })?
+        request: Result<beacon::VariantRequest, String>,
+    ) -> (axum::http::StatusCode, axum::Json<serde_json::Value>) {
+        use axum::http::StatusCode;
+
+        let error = |status: StatusCode, message: &str| {
+            (
+                status,
+                axum::Json(beacon::error_response(status.as_u16(), message)),
+            )
+        };
+        let request = match request {
+            Ok(request) => request,
+            Err(message) => return error(StatusCode::BAD_REQUEST, &message),
+        };
+        if let Some(assembly_id) = &request.assembly_id {
+            let build = self.server.index().get_metadata().reference_genome.build;
+            if !beacon::assembly_matches(assembly_id, &build) {
+                return error(
+                    StatusCode::BAD_REQUEST,
+                    &format!(
+                        "assemblyId {} does not match this dataset's build, {}",
+                        assembly_id, build
+                    ),
+                );
+            }
+        }
+
+        let query = request.clone();
+        let matches = self
+            .server
+            .run_query(move |index| {
+                let (start, end) = query.vcf_region();
+                let page =
+                    index.query_region(&query.reference_name, start, end, &QueryOptions::default());
+                if let Some(exceeded) = &page.limit_exceeded {
+                    return Err(limit_error("beacon", exceeded));
+                }
+                if let Some(failure) = &page.read_failure {
+                    return Err(read_error("beacon", failure));
+                }
+                let matches: Vec<(&Variant, &AlternateAllele)> = page
+                    .variants
+                    .iter()
+                    .flat_map(|variant| {
+                        query
+                            .matching_alleles(variant)
+                            .into_iter()
+                            .map(move |allele| (variant, allele))
+                    })
+                    .collect();
+                let results = matches
+                    .iter()
+                    .skip(query.skip)
+                    .take(query.limit)
+                    .map(|(variant, allele)| beacon::g_variant(variant, allele))
+                    .collect::<Vec<_>>();
+                Ok((results, matches.len()))
+            })
+            .await;
+        match matches {
+            Ok((results, total)) => (
+                StatusCode::OK,
+                axum::Json(beacon::result_sets_response(
+                    &self.dataset,
+                    &request,
+                    self.max_granularity,
+                    results,
+                    total,
+                )),
+            ),
+            Err(e) if e.code == ErrorCode::INVALID_PARAMS => {
+                error(StatusCode::BAD_REQUEST, &e.message)
+            }
+            Err(e) => error(StatusCode::INTERNAL_SERVER_ERROR, &e.message),
+        }
+    }
+}
+
+// Headers browser-based MCP clients send on the streamable HTTP transport
+const MCP_REQUEST_HEADERS: [&str; 5] = [
+    "content-type",
+    "accept",
+    "mcp-session-id",
+    "mcp-protocol-version",
+    "last-event-id",
+];
+
+// CORS for --cors-origins: answers preflight requests and lets scripts read the responses and
+// the session header. None when no origins are configured, so browsers keep refusing
+// cross-origin calls.
+fn cors_layer(options: &HttpOptions) -> Option<tower_http::cors::CorsLayer> {
+    use axum::http::{header::HeaderName, HeaderValue, Method};
+    use tower_http::cors::{AllowOrigin, CorsLayer};
+
+    if options.cors_origins.is_empty() {
+        return None;
+    }
+    let origin = if options.cors_origins.iter().any(|origin| origin == "*") {
+        AllowOrigin::any()
+    } else {
+        AllowOrigin::list(
+            options
+                .cors_origins
+                .iter()
+                .filter_map(|origin| HeaderValue::from_str(origin).ok()),
+        )
+    };
+    let headers: Vec<HeaderName> = MCP_REQUEST_HEADERS
+        .iter()
+        .copied()
+        .chain(options.cors_headers.iter().map(String::as_str))
+        .filter_map(|name| HeaderName::from_bytes(name.as_bytes()).ok())
+        .collect();
+    Some(
+        CorsLayer::new()
+            .allow_origin(origin)
+            .allow_methods([Method::GET, Method::POST, Method::DELETE, Method::OPTIONS])
+            .allow_headers(headers)
+            .expose_headers([HeaderName::from_static("mcp-session-id")])
+            .max_age(std::time::Duration::from_secs(3600)),
+    )
+}
+
+async fn run_sse_server<S>(
+    server: S,
+    debug: bool,
+    addr: &str,
+    // Routes served next to the MCP endpoint, e.g. --beacon's
+    routes: axum::Router,
+    options: HttpOptions,
+    // Cancelled to stop accepting connections; open ones are left to the caller's drain
+    stopping: CancellationToken,
+) -> std::io::Result<()>
+where
+    S: ServerHandler + Clone + Send + Sync + 'static,
+{
+    use axum::{
+        extract::Request,
+        middleware::{self, Next},
+        response::Response,
+        Router,
+    };
+    use hyper_util::rt::{TokioExecutor, TokioIo, TokioTimer};
+    use hyper_util::server::conn::auto;
+    use hyper_util::service::TowerToHyperService;
+    use rmcp::transport::streamable_http_server::{
+        session::local::LocalSessionManager, StreamableHttpServerConfig, StreamableHttpService,
+    };
+    use tower_http::compression::{
+        predicate::{NotForContentType, Predicate, SizeAbove},
+        CompressionLayer,
+    };
+
+    let bind_addr: std::net::SocketAddr = addr
+        .parse()
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+
+    // Stateless by default: each POST is answered on its own event stream and nothing is kept
+    // between requests. Stateful sessions also keep a standing GET stream for notifications
+    // (resource updates, log entries) and replay missed events after a reconnect.
+    let config = StreamableHttpServerConfig {
+        sse_keep_alive: options.sse_keep_alive,
+        sse_retry: Some(std::time::Duration::from_secs(5)),
+        stateful_mode: options.stateful,
+        cancellation_token: CancellationToken::new(),
+    };
+
+    let mut session_manager = LocalSessionManager::default();
+    session_manager.session_config.keep_alive = options.session_keep_alive;
+    let session_manager = Arc::new(session_manager);
+
+    let service = StreamableHttpService::new(move || Ok(server.clone()), session_manager, config);
+
+    // Turn away new sessions once --max-sessions are open. Requests that open one are
+    // serialized, so two concurrent `initialize` calls can't both take the last slot.
+    async fn limit_sessions(
+        req: Request,
+        next: Next,
+        sessions: Arc<http_limits::SessionTable>,
+        opening: Arc<tokio::sync::Mutex<()>>,
+    ) -> Response {
+        let session = req
+            .headers()
+            .get("mcp-session-id")
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string);
+        match session {
+            Some(id) => {
+                let deleting = req.method() == axum::http::Method::DELETE;
+                let response = next.run(req).await;
+                if deleting || response.status() == axum::http::StatusCode::NOT_FOUND {
+                    sessions.closed(&id);
+                } else {
+                    sessions.used(&id);
+                }
+                response
+            }
+            None if req.method() == axum::http::Method::POST => {
+                let _opening = opening.lock().await;
+                if !sessions.has_room() {
+                    let mut response =
+                        Response::new(axum::body::Body::from("Too many MCP sessions, retry later"));
+                    *response.status_mut() = axum::http::StatusCode::SERVICE_UNAVAILABLE;
+                    return response;
+                }
+                let response = next.run(req).await;
+                if let Some(id) = response
+                    .headers()
+                    .get("mcp-session-id")
+                    .and_then(|value| value.to_str().ok())
+                {
+                    sessions.opened(id);
+                }
+                response
+            }
+            None => next.run(req).await,
+        }
+    }
+    let service = match options.max_sessions {
+        Some(max) => {
+            let sessions = Arc::new(http_limits::SessionTable::new(
+                max,
+                options.session_keep_alive,
+            ));
+            let opening = Arc::new(tokio::sync::Mutex::new(()));
+            Router::new()
+                .fallback_service(service)
+                .layer(middleware::from_fn(move |req, next| {
+                    limit_sessions(req, next, Arc::clone(&sessions), Arc::clone(&opening))
+                }))
+        }
+        None => Router::new().fallback_service(service),
+    };
+
+    // Logging middleware
+    async fn log_request(req: Request, next: Next, debug: bool) -> Response {
+        if debug {
+            debug!("HTTP {} {}", req.method(), req.uri());
+            debug!("Headers: {:?}", req.headers());
+        }
+        next.run(req).await
+    }
+
+    // The MCP endpoint answers every path the extra routes don't. axum doesn't allow nesting
+    // at the root, so only nest when a prefix is configured.
+    let router = routes.fallback_service(service);
+    let router = match &options.base_path {
+        Some(base_path) => Router::new().nest_service(base_path, router),
+        None => router,
+    };
+    // Event streams are never compressed: the compressor holds back progress notifications
+    // and keep-alive pings until enough output accumulates, which for a tool call is usually
+    // when the result arrives
+    let predicate = SizeAbove::new(options.compression_min_bytes.unwrap_or_default())
+        .and(NotForContentType::GRPC)
+        .and(NotForContentType::IMAGES)
+        .and(NotForContentType::SSE);
+    let router = match options.compression_min_bytes {
+        Some(_) => router.layer(CompressionLayer::new().compress_when(predicate)),
+        None => router,
+    };
+    // Preflight requests are answered here, before reaching the MCP endpoint
+    let router = match cors_layer(&options) {
+        Some(cors) => router.layer(cors),
+        None => router,
+    };
+    let app = router.layer(middleware::from_fn(move |req, next| {
+        log_request(req, next, debug)
+    }));
+
+    let listener = tokio::net::TcpListener::bind(bind_addr).await?;
+
+    info!(
+        "Streamable HTTP MCP server listening on http://{}{}",
+        bind_addr,
+        options.base_path.as_deref().unwrap_or("")
+    );
+    if debug {
+        debug!(
+            "HTTP options: stateful={}, session keep-alive={:?}, max sessions={:?}, keep-alive={:?}, idle timeout={:?}, header read timeout={:?}, max connections={:?}, compression min bytes={:?}, CORS origins={:?}",
+            options.stateful, options.session_keep_alive, options.max_sessions, options.sse_keep_alive, options.idle_timeout, options.header_read_timeout, options.max_connections, options.compression_min_bytes, options.cors_origins
+        );
+    }
+
+    // hyper closes an HTTP/1 connection once no request headers arrive within the timeout;
+    // it has no equivalent for HTTP/2 or for a response stream that is still open
+    let mut builder = auto::Builder::new(TokioExecutor::new());
+    if let Some(header_read_timeout) = options.header_read_timeout {
+        builder
+            .http1()
+            .timer(TokioTimer::new())
+            .header_read_timeout(header_read_timeout);
+    }
+
+    let connection_limit = options.max_connections.map(|n| Arc::new(Semaphore::new(n)));
+
+    loop {
+        let (stream, peer_addr) = tokio::select! {
+            accepted = listener.accept() => accepted?,
+            _ = stopping.cancelled() => return Ok(()),
+        };
+
+        // Each accepted connection holds one permit until it closes
+        let permit = match &connection_limit {
+            Some(limit) => match Arc::clone(limit).try_acquire_owned() {
+                Ok(permit) => Some(permit),
+                Err(_) => {
+                    if debug {
+                        debug!(
+                            "Rejecting connection from {}: connection limit reached",
+                            peer_addr
+                        );
+                    }
+                    tokio::spawn(reject_connection(builder.clone(), stream));
+                    continue;
+                }
+            },
+            None => None,
         };
 
-        // Limit variants_per_chromosome if requested
-        if params.max_chromosomes > 0
-            && stats.variants_per_chromosome.len() > params.max_chromosomes
-        {
-            // Sort chromosomes by variant count (descending) and keep top N
-            let mut chr_counts: Vec<_> = stats.variants_per_chromosome.iter().collect();
-            chr_counts.sort_by(|a, b| b.1.cmp(a.1));
+        let builder = builder.clone();
+        let hyper_service = TowerToHyperService::new(app.clone());
+        let idle_timeout = options.idle_timeout;
+        tokio::spawn(async move {
+            let _permit = permit;
+            let activity = http_limits::Activity::new();
+            let stream = http_limits::IdleTracked::new(stream, activity.clone());
+            let serving =
+                builder.serve_connection_with_upgrades(TokioIo::new(stream), hyper_service);
+            // Dropping the connection closes it, abandoning any response still open on it
+            let result = match idle_timeout {
+                Some(timeout) => tokio::select! {
+                    result = serving => result,
+                    _ = activity.until_idle(timeout) => {
+                        if debug {
+                            debug!("Closing idle connection from {}", peer_addr);
+                        }
+                        return;
+                    }
+                },
+                None => serving.await,
+            };
+            if let Err(e) = result {
+                if debug {
+                    debug!("Connection from {} closed: {}", peer_addr, e);
+                }
+            }
+        });
+    }
+}
+
+// Answer a connection that exceeds --max-connections with 503 and close it
+async fn reject_connection(
+    builder: hyper_util::server::conn::auto::Builder<hyper_util::rt::TokioExecutor>,
+    stream: tokio::net::TcpStream,
+) {
+    let service = hyper::service::service_fn(|_req: hyper::Request<hyper::body::Incoming>| async {
+        let mut response = axum::response::Response::new(axum::body::Body::from(
+            "Too many concurrent connections, retry later",
+        ));
+        *response.status_mut() = hyper::StatusCode::SERVICE_UNAVAILABLE;
+        Ok::<_, std::convert::Infallible>(response)
+    });
+    let _ = builder
+        .serve_connection(hyper_util::rt::TokioIo::new(stream), service)
+        .await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vcf::load_vcf;
+
+    fn create_test_index() -> VcfIndex {
+        let vcf_path = PathBuf::from("sample_data/sample.compressed.vcf.gz");
+        load_vcf(&vcf_path, false, false).expect("Failed to load test VCF")
+    }
+
+    #[test]
+    fn test_build_chromosome_response_when_matched() {
+        let index = create_test_index();
+        let matched_chr = Some("20".to_string());
+
+        let (status, available, alternate) = build_chromosome_response(&index, "20", &matched_chr);
+
+        assert!(matches!(status, QueryStatus::Ok));
+        assert_eq!(available, None);
+        assert_eq!(alternate, None);
+    }
+
+    #[test]
+    fn test_build_chromosome_response_when_not_found() {
+        let index = create_test_index();
+        let matched_chr = None;
+
+        let (status, available, alternate) = build_chromosome_response(&index, "99", &matched_chr);
+
+        assert!(matches!(status, QueryStatus::ChromosomeNotFound));
+        assert!(available.is_some());
+        assert!(alternate.is_some());
+        assert_eq!(alternate, Some("chr99".to_string()));
+    }
+
+    #[test]
+    fn test_build_chromosome_response_suggests_without_chr_prefix() {
+        let index = create_test_index();
+        let matched_chr = None;
+
+        let (status, available, alternate) =
+            build_chromosome_response(&index, "chr99", &matched_chr);
 
-            let limited: HashMap<String, u64> = chr_counts
-                .into_iter()
-                .take(params.max_chromosomes)
-                .map(|(k, v)| (k.clone(), *v))
-                .collect();
+        assert!(matches!(status, QueryStatus::ChromosomeNotFound));
+        assert!(available.is_some());
+        assert_eq!(alternate, Some("99".to_string()));
+    }
 
-            stats.variants_per_chromosome = limited;
-        }
+    #[test]
+    fn test_build_chromosome_response_suggests_with_chr_prefix() {
+        let index = create_test_index();
+        let matched_chr = None;
 
-        let payload = serde_json::to_value(stats).map_err(|e| {
-            McpError::internal_error(format!("Failed to serialize statistics: {}", e), None)
-        })?;
+        let (status, _available, alternate) = build_chromosome_response(&index, "99", &matched_chr);
 
-        let content = Content::json(payload)?;
-        self.create_result_with_logging(content, start_time)
+        assert!(matches!(status, QueryStatus::ChromosomeNotFound));
+        assert_eq!(alternate, Some("chr99".to_string()));
     }
 
-    #[tool(
-        description = "Start a new streaming query session for a genomic region. Returns the first variant and a session_id for subsequent calls. Use get_next_variant to retrieve remaining variants one at a time. Optionally filter variants using a filter expression (e.g., 'QUAL > 30 AND FILTER == PASS')."
-    )]
-    async fn start_region_query(
-        &self,
-        Parameters(StreamRegionParams {
-            chromosome: requested_chromosome,
-            start,
-            end,
-            filter,
-        }): Parameters<StreamRegionParams>,
-    ) -> Result<CallToolResult, McpError> {
-        let start_time = std::time::Instant::now();
-        // Validate filter expression before processing
-        let index = self.index.lock().await;
+    #[test]
+    fn test_build_chromosome_response_includes_sample_chromosomes() {
+        let index = create_test_index();
+        let matched_chr = None;
 
-        if !filter.trim().is_empty() {
-            let filter_engine = index.filter_engine();
-            drop(index); // Drop lock before potentially expensive operation
-            if let Err(e) = filter_engine.parse_filter(&filter) {
-                return Err(McpError::invalid_params(
-                    format!("Invalid filter expression: {}", e),
-                    None,
-                ));
-            }
-        } else {
-            drop(index); // Drop lock if no validation needed
-        }
+        let (_status, available, _alternate) =
+            build_chromosome_response(&index, "99", &matched_chr);
 
-        let index = self.index.lock().await;
+        assert!(available.is_some());
+        let chroms = available.unwrap();
+        assert!(!chroms.is_empty());
+        assert!(chroms.len() <= 5, "Should limit to 5 chromosomes");
+    }
 
-        // Find matching chromosome (handles chr1 vs 1 normalization)
-        let matched_chr = index.get_available_chromosomes().into_iter().find(|chr| {
-            chr.to_lowercase() == requested_chromosome.to_lowercase()
-                || chr.to_lowercase() == format!("chr{}", requested_chromosome).to_lowercase()
-                || chr.to_lowercase()
-                    == requested_chromosome
-                        .strip_prefix("chr")
-                        .unwrap_or(&requested_chromosome)
-                        .to_lowercase()
-        });
+    fn tool_names(server: &VcfServer) -> Vec<String> {
+        server
+            .tool_router
+            .list_all()
+            .into_iter()
+            .map(|tool| tool.name.to_string())
+            .collect()
+    }
 
-        let matched_chr_name = matched_chr.ok_or_else(|| {
-            McpError::internal_error(
-                format!(
-                    "Chromosome '{}' not found in VCF file",
-                    requested_chromosome
-                ),
-                None,
+    #[test]
+    fn test_restrict_tools_disable() {
+        let mut server = VcfServer::new(create_test_index(), false);
+        server
+            .restrict_tools(&[], &["query_by_id".to_string()])
+            .expect("Known tool should be accepted");
+
+        let names = tool_names(&server);
+        assert!(!names.contains(&"query_by_id".to_string()));
+        assert!(names.contains(&"query_by_position".to_string()));
+    }
+
+    #[test]
+    fn test_disabled_tools_withdraw_their_resources() {
+        let mut server = VcfServer::new(create_test_index(), false);
+        assert!(server.check_resource_allowed("vcf://20/1-100").is_ok());
+        server
+            .restrict_tools(
+                &[],
+                &["query_by_region".to_string(), "get_vcf_header".to_string()],
             )
-        })?;
+            .unwrap();
+
+        assert_eq!(mirrored_tool(REGION_TEMPLATE), Some("query_by_region"));
+        assert!(server.check_resource_allowed("vcf://20/1-100.vcf").is_err());
+        assert!(server.check_resource_allowed("vcf://metadata").is_err());
+        assert!(server.check_resource_allowed("vcf://ids?page=1").is_ok());
+        assert!(server.check_resource_allowed("vcf://unknown").is_ok());
+
+        server.hide_genotypes();
+        let names = tool_names(&server);
+        assert!(!names.contains(&"find_carriers".to_string()));
+        assert!(!names.contains(&"get_samples".to_string()));
+        assert!(names.contains(&"query_by_position".to_string()));
+    }
 
-        // Query the region and find first variant that passes filter
-        let (region_variants, _) = index.query_by_region(&matched_chr_name, start, end);
-        let filter_engine = index.filter_engine();
+    #[test]
+    fn test_restrict_tools_enable_allowlist() {
+        let mut server = VcfServer::new(create_test_index(), false);
+        server
+            .restrict_tools(
+                &[
+                    "query_by_position".to_string(),
+                    "get_vcf_header".to_string(),
+                ],
+                &["get_vcf_header".to_string()],
+            )
+            .expect("Known tools should be accepted");
 
-        let first_variant = region_variants.into_iter().map(format_variant).find(|v| {
-            // Use vcf-filter to evaluate filter expression
-            filter_engine.evaluate(&filter, &v.raw_row).unwrap_or(false)
-        });
+        assert_eq!(tool_names(&server), vec!["query_by_position".to_string()]);
+    }
 
-        // If no variants found, return graceful response (consistent with get_next_variant)
-        if first_variant.is_none() {
-            let reference_genome = index.get_reference_genome();
-            let response = StreamQueryResponse {
-                variant: None,
-                session_id: None,
-                has_more: false,
-                reference_genome,
-                matched_chromosome: Some(matched_chr_name),
-            };
+    #[test]
+    fn test_tool_annotations() {
+        let tools = VcfServer::tools().list_all();
+        for tool in &tools {
+            let annotations = tool.annotations.as_ref().expect("every tool is annotated");
+            assert!(annotations.title.is_some(), "{} has no title", tool.name);
+            assert!(annotations.read_only_hint.is_some(), "{}", tool.name);
+        }
+        let annotations = |name: &str| {
+            tools
+                .iter()
+                .find(|tool| tool.name == name)
+                .and_then(|tool| tool.annotations.clone())
+                .unwrap()
+        };
+        assert_eq!(annotations("query_by_region").read_only_hint, Some(true));
+        assert_eq!(annotations("query_by_region").idempotent_hint, Some(true));
+        // Writes files, replacing them with overwrite: true
+        assert_eq!(annotations("export_region").read_only_hint, Some(false));
+        assert_eq!(annotations("export_region").destructive_hint, Some(true));
+        // Sends variants to Ensembl
+        #[cfg(feature = "network")]
+        assert_eq!(annotations("annotate_via_vep").open_world_hint, Some(true));
+        // Random unless seeded
+        assert_eq!(annotations("sample_variants").idempotent_hint, Some(false));
+        // Swaps the index, but reloading twice does no more than once
+        assert_eq!(annotations("reload_dataset").read_only_hint, Some(false));
+        assert_eq!(annotations("reload_dataset").idempotent_hint, Some(true));
+    }
 
-            let payload = serde_json::to_value(response).map_err(|e| {
-                McpError::internal_error(
-                    format!("Failed to serialize start_region_query response: {}", e),
-                    None,
-                )
-            })?;
+    #[test]
+    fn test_restrict_tools_rejects_unknown_name() {
+        let mut server = VcfServer::new(create_test_index(), false);
+        let result = server.restrict_tools(&[], &["drop_database".to_string()]);
+        assert!(result.is_err());
+        assert!(tool_names(&server).contains(&"query_by_id".to_string()));
+    }
 
-            let content = Content::json(payload)?;
-            return self.create_result_with_logging(content, start_time);
-        }
+    #[test]
+    fn test_prompt_context() {
+        let mut server = VcfServer::new(create_test_index(), false);
+        server
+            .restrict_tools(&[], &["find_carriers".to_string()])
+            .expect("Known tool should be accepted");
+
+        let context = server.prompt_context();
+        assert!(context.build.contains("NCBI36"));
+        assert_eq!(context.sample_count, 3);
+        assert!(context.tools.contains("query_by_position"));
+        assert!(!context.tools.contains("find_carriers"));
+
+        // The disabled tool's step is left out of the instructions
+        let arguments = serde_json::json!({"chromosome": "20", "position": "14370"});
+        let prompt =
+            prompts::get_prompt("triage_variant", arguments.as_object().unwrap(), &context)
+                .unwrap();
+        let text = serde_json::to_string(&prompt).unwrap();
+        assert!(text.contains("query_by_position"));
+        assert!(!text.contains("find_carriers"));
+    }
 
-        let first_variant = first_variant.unwrap();
+    #[tokio::test]
+    async fn test_run_query_with_single_permit() {
+        let mut server = VcfServer::new(create_test_index(), false);
+        server.limit_concurrent_queries(1);
+
+        // Queries beyond the limit wait for a permit instead of failing
+        let query = || server.run_query(|index| Ok(index.query_by_position("20", 14370).0.len()));
+        let (first, second, third) = tokio::join!(query(), query(), query());
+        assert_eq!(first.unwrap(), 1);
+        assert_eq!(second.unwrap(), 1);
+        assert_eq!(third.unwrap(), 1);
+        assert_eq!(server.query_permits.available_permits(), 1);
+    }
 
-        // Create session
-        let session_id = Uuid::new_v4().to_string();
-        let session = QuerySession {
-            chromosome: matched_chr_name.clone(),
-            start,
-            end,
-            last_position: Some(first_variant.position),
-            created_at: std::time::Instant::now(),
-            filter: filter.clone(),
+    #[tokio::test]
+    async fn test_large_region_requires_confirmation() {
+        let server = VcfServer::new(create_test_index(), false);
+        let params = |confirm: bool| {
+            serde_json::from_value::<QueryByRegionParams>(serde_json::json!({
+                "chromosome": "20",
+                "start": 1,
+                "end": 2_000_000,
+                "confirm_large_query": confirm,
+            }))
+            .unwrap()
+        };
+        let payload = |result: CallToolResult| -> serde_json::Value {
+            serde_json::from_str(&result.content[0].as_text().unwrap().text).unwrap()
         };
 
-        drop(index); // Release lock before acquiring sessions lock
-        let mut sessions = self.query_sessions.lock().await;
-        sessions.insert(session_id.clone(), session);
-        drop(sessions);
+        let unconfirmed = payload(
+            server
+                .query_region_page(params(false), CallContext::default())
+                .await
+                .unwrap(),
+        );
+        assert_eq!(unconfirmed["status"], "confirmation_required");
+        assert_eq!(unconfirmed["result"]["count"], 0);
+        assert_eq!(unconfirmed["confirmation"]["region_size_bp"], 1_999_999);
+        assert_eq!(
+            unconfirmed["confirmation"]["max_region_size_bp"],
+            DEFAULT_MAX_REGION_SIZE
+        );
 
-        let index = self.index.lock().await;
-        let reference_genome = index.get_reference_genome();
+        let confirmed = payload(
+            server
+                .query_region_page(params(true), CallContext::default())
+                .await
+                .unwrap(),
+        );
+        assert_eq!(confirmed["status"], "ok");
+        assert!(confirmed["result"]["count"].as_u64().unwrap() > 0);
+        assert!(confirmed.get("confirmation").is_none());
+        // Everything fits on one page
+        assert!(confirmed.get("next_cursor").is_none());
+    }
 
-        let response = StreamQueryResponse {
-            variant: Some(first_variant),
-            session_id: Some(session_id),
-            has_more: true, // Assume yes until we check
-            reference_genome,
-            matched_chromosome: Some(matched_chr_name),
+    #[tokio::test]
+    async fn test_region_cursor_resumes_after_returned_variants() {
+        let server = VcfServer::new(create_test_index(), false);
+        let params = serde_json::from_value::<QueryByRegionParams>(serde_json::json!({
+            "chromosome": "20",
+            "start": 10_000,
+            "end": 20_000,
+            "cursor": "14370:1",
+        }))
+        .unwrap();
+        let result = server
+            .query_region_page(params, CallContext::default())
+            .await
+            .unwrap();
+        let payload: serde_json::Value =
+            serde_json::from_str(&result.content[0].as_text().unwrap().text).unwrap();
+
+        // rs6054257 at 14370 was on the previous page
+        let positions: Vec<u64> = payload["result"]["items"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|v| v["position"].as_u64().unwrap())
+            .collect();
+        assert_eq!(positions, vec![17330]);
+        assert_eq!(payload["query"]["cursor"], "14370:1");
+    }
+
+    #[tokio::test]
+    async fn test_region_explain_mode() {
+        let server = VcfServer::new(create_test_index(), false);
+        let params = serde_json::from_value::<QueryByRegionParams>(serde_json::json!({
+            "chromosome": "20",
+            "start": 10_000,
+            "end": 20_000,
+            "filter": "DP > 12",
+            "pass_only": true,
+            "exclude_filters": ["q10"],
+            "explain": true,
+            "explain_excluded": 5,
+            "fields": ["position"],
+        }))
+        .unwrap();
+        let result = server
+            .query_region_page(params, CallContext::default())
+            .await
+            .unwrap();
+        let payload: serde_json::Value =
+            serde_json::from_str(&result.content[0].as_text().unwrap().text).unwrap();
+
+        let clauses = serde_json::json!(["DP > 12", "FILTER == \"PASS\""]);
+        assert_eq!(payload["filter_clauses"], clauses);
+        // rs6054257 (DP=14, PASS) matched both clauses, which survive the projection
+        let items = payload["result"]["items"].as_array().unwrap();
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0]["position"], 14370);
+        assert_eq!(items[0]["matched_clauses"], clauses);
+        // 17330 (DP=11, q10) failed every check
+        let excluded = payload["excluded"].as_array().unwrap();
+        assert_eq!(excluded.len(), 1);
+        assert_eq!(excluded[0]["position"], 17330);
+        assert_eq!(
+            excluded[0]["failed"],
+            serde_json::json!(["exclude_filters: q10", "DP > 12", "FILTER == \"PASS\""])
+        );
+
+        let too_many = serde_json::from_value::<QueryByRegionParams>(serde_json::json!({
+            "chromosome": "20",
+            "start": 10_000,
+            "end": 20_000,
+            "explain_excluded": MAX_EXPLAIN_EXCLUDED + 1,
+        }))
+        .unwrap();
+        assert!(region_query_options(&too_many).is_err());
+    }
+
+    #[test]
+    fn test_region_cursor_round_trip() {
+        let cursor = RegionCursor {
+            position: 14370,
+            skip: 2,
         };
+        assert_eq!(
+            parse_region_cursor(&cursor.encode(), 1, 20_000).unwrap(),
+            cursor
+        );
+        assert!(parse_region_cursor("14370", 1, 20_000).is_err());
+        assert!(parse_region_cursor("x:1", 1, 20_000).is_err());
+        // Outside the queried region
+        assert!(parse_region_cursor("14370:0", 15_000, 20_000).is_err());
+    }
 
-        let payload = serde_json::to_value(response).map_err(|e| {
-            McpError::internal_error(
-                format!("Failed to serialize start_region_query response: {}", e),
-                None,
-            )
-        })?;
+    #[test]
+    fn test_limit_error_names_the_limit() {
+        let error = limit_error(
+            "export_bed",
+            &LimitExceeded::Timeout {
+                timeout_ms: 30_000,
+                position: 1_110_696,
+                records_read: 3,
+            },
+        );
+        assert!(error.message.contains("end before 1110696"));
+        let data = error.data.unwrap();
+        assert_eq!(data["limit"], "timeout");
+        assert_eq!(data["position"], 1_110_696);
+    }
 
-        let content = Content::json(payload)?;
-        self.create_result_with_logging(content, start_time)
+    #[test]
+    fn test_read_error_is_not_reported_as_absence() {
+        let failure = ReadFailure {
+            message: "connection reset by peer".to_string(),
+            transient: true,
+            attempts: 4,
+        };
+        let error = read_error("query_by_id", &failure);
+        assert!(error
+            .message
+            .contains("not evidence that no variants match"));
+        assert!(error.message.contains("may succeed"));
+        let data = error.data.unwrap();
+        assert_eq!(data["transient"], true);
+        assert_eq!(data["attempts"], 4);
     }
 
-    #[tool(
-        description = "Get the next variant from an active streaming query session. Returns one variant at a time. When has_more is false, the session is complete and automatically closed."
-    )]
-    async fn get_next_variant(
-        &self,
-        Parameters(NextVariantParams { session_id }): Parameters<NextVariantParams>,
-    ) -> Result<CallToolResult, McpError> {
-        let start_time = std::time::Instant::now();
-        let mut sessions = self.query_sessions.lock().await;
+    #[test]
+    fn test_parse_region_uri() {
+        assert_eq!(
+            parse_region_uri("vcf://20/14000-18000").unwrap().unwrap(),
+            RegionSlice {
+                chromosome: "20".to_string(),
+                start: 14000,
+                end: 18000,
+                vcf: false,
+            }
+        );
+        let slice = parse_region_uri("vcf://chr20/14000-18000.vcf")
+            .unwrap()
+            .unwrap();
+        assert_eq!((slice.chromosome.as_str(), slice.vcf), ("chr20", true));
+        assert!(!parse_region_uri("vcf://20/1-2.json").unwrap().unwrap().vcf);
+        assert!(parse_region_uri("vcf://20/18000-14000").unwrap().is_err());
+        assert!(parse_region_uri("vcf://20/0-100").unwrap().is_err());
+        assert!(parse_region_uri("vcf://20/14000").unwrap().is_err());
+        assert!(parse_region_uri("vcf://metadata").is_none());
+        assert!(parse_region_uri("vcf://ids?page=1").is_none());
+    }
 
-        let session = sessions.get(&session_id).ok_or_else(|| {
-            McpError::internal_error(
-                "Session not found or expired. Start a new query with start_region_query.",
-                None,
-            )
-        })?;
+    #[tokio::test]
+    async fn test_read_region_resource() {
+        let server = VcfServer::new(create_test_index(), false);
+        let read = |uri: &str| {
+            let slice = parse_region_uri(uri).unwrap().unwrap();
+            server.read_region_resource(uri, slice)
+        };
+        let text = |result: ReadResourceResult| match &result.contents[0] {
+            ResourceContents::TextResourceContents { text, .. } => text.clone(),
+            _ => panic!("expected text contents"),
+        };
 
-        // Check session timeout (5 minutes)
-        if session.created_at.elapsed().as_secs() > 300 {
-            sessions.remove(&session_id);
-            return Err(McpError::internal_error(
-                "Session expired. Start a new query.",
-                None,
-            ));
-        }
+        let json: serde_json::Value =
+            serde_json::from_str(&text(read("vcf://20/14000-18000").await.unwrap())).unwrap();
+        assert_eq!(json["matched_chromosome"], "20");
+        assert_eq!(json["count"], 2);
+        assert_eq!(json["variants"][0]["id"], "rs6054257");
+
+        let vcf = text(read("vcf://20/14000-18000.vcf").await.unwrap());
+        assert!(vcf.starts_with("##fileformat=VCFv4"));
+        assert_eq!(vcf.lines().filter(|line| !line.starts_with('#')).count(), 2);
+
+        assert!(read("vcf://chr7/1-100").await.is_err());
+        assert!(read("vcf://20/1-20000").await.is_err());
+    }
+
+    #[test]
+    fn test_replace_index() {
+        let mut server = VcfServer::new(create_test_index(), false);
+        server.cache_results(result_cache::ResultCache::new(
+            std::time::Duration::from_secs(60),
+            1024 * 1024,
+        ));
+        let cache = server.result_cache.clone().unwrap();
+        cache.insert(
+            "query_by_id".to_string(),
+            &CallToolResult::success(vec![Content::text("{}")]),
+        );
+        let clone = server.clone();
+        let before = server.index();
+
+        server.replace_index(create_test_index());
+        // Every clone (e.g. each HTTP session's) serves the new index
+        assert!(!Arc::ptr_eq(&before, &clone.index()));
+        assert!(Arc::ptr_eq(&server.index(), &clone.index()));
+        assert!(cache.get("query_by_id").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_reload_dataset() {
+        let mut server = VcfServer::new(create_test_index(), false);
+        assert!(server.reload_dataset().await.is_err());
+
+        server.enable_reload(
+            PathBuf::from("sample_data/sample.compressed.vcf.gz"),
+            LoadOptions::default(),
+        );
+        let before = server.index();
+        let result = server.reload_dataset().await.unwrap();
+        let payload: serde_json::Value =
+            serde_json::from_str(&result.content[0].as_text().unwrap().text).unwrap();
+        assert_eq!(payload["status"], "reloaded");
+        assert_eq!(payload["id_index"]["state"], "ready");
+        assert!(!Arc::ptr_eq(&before, &server.index()));
+
+        // A failed load keeps serving the current index
+        let current = server.index();
+        server.enable_reload(
+            PathBuf::from("sample_data/missing.vcf.gz"),
+            LoadOptions::default(),
+        );
+        assert!(server.reload_dataset().await.is_err());
+        assert!(Arc::ptr_eq(&current, &server.index()));
+    }
+
+    #[test]
+    fn test_paginate() {
+        let items: Vec<u32> = (0..5).collect();
+        let (page, next) = paginate(items.clone(), None, 2).unwrap();
+        assert_eq!((page, next.as_deref()), (vec![0, 1], Some("2")));
+        let (page, next) = paginate(items.clone(), Some("4"), 2).unwrap();
+        assert_eq!((page, next), (vec![4], None));
+        assert!(paginate(items.clone(), Some("6"), 2).is_err());
+        assert!(paginate(items, Some("abc"), 2).is_err());
+    }
+
+    #[test]
+    fn test_chromosome_resources() {
+        let server = VcfServer::new(create_test_index(), false);
+        let uris: Vec<String> = server
+            .resources()
+            .into_iter()
+            .map(|resource| resource.raw.uri)
+            .collect();
+        assert_eq!(uris[..2], ["vcf://metadata", "vcf://ids"]);
+        assert!(uris.contains(&"vcf://chromosome/20".to_string()));
+
+        let result = server
+            .read_chromosome_resource("vcf://chromosome/20", "20")
+            .unwrap();
+        let ResourceContents::TextResourceContents { text, .. } = &result.contents[0] else {
+            panic!("expected text contents");
+        };
+        let summary: serde_json::Value = serde_json::from_str(text).unwrap();
+        assert_eq!(summary["name"], "20");
+        assert!(server
+            .read_chromosome_resource("vcf://chromosome/7", "7")
+            .is_err());
+    }
+
+    #[test]
+    fn test_parse_ids_page() {
+        assert_eq!(parse_ids_page("vcf://ids").unwrap().unwrap(), 0);
+        assert_eq!(parse_ids_page("vcf://ids?page=3").unwrap().unwrap(), 3);
+        assert!(parse_ids_page("vcf://ids?page=-1").unwrap().is_err());
+        assert!(parse_ids_page("vcf://metadata").is_none());
+        assert!(parse_ids_page("vcf://ids?offset=3").is_none());
+    }
+
+    #[test]
+    fn test_parse_base_path_normalizes_slashes() {
+        assert_eq!(parse_base_path("vcf"), Ok("/vcf".to_string()));
+        assert_eq!(parse_base_path("/vcf/"), Ok("/vcf".to_string()));
+        assert_eq!(parse_base_path("/a/b"), Ok("/a/b".to_string()));
+        assert_eq!(parse_base_path("/"), Ok(String::new()));
+    }
+
+    #[test]
+    fn test_parse_base_path_rejects_invalid() {
+        assert!(parse_base_path("/a//b").is_err());
+        assert!(parse_base_path("/vcf?x=1").is_err());
+        assert!(parse_base_path("/{id}").is_err());
+    }
+
+    #[test]
+    fn test_http_options_connection_limits() {
+        let args = Args::try_parse_from(["vcf_mcp_server", "a.vcf.gz"]).unwrap();
+        let options = HttpOptions::from_args(&args);
+        assert_eq!(options.header_read_timeout, None);
+        assert_eq!(options.max_connections, None);
+        assert_eq!(options.idle_timeout, None);
+        assert_eq!(
+            options.sse_keep_alive,
+            Some(std::time::Duration::from_secs(args.sse_keep_alive))
+        );
+
+        let args = Args::try_parse_from([
+            "vcf_mcp_server",
+            "a.vcf.gz",
+            "--header-read-timeout",
+            "30",
+            "--max-connections",
+            "8",
+            "--idle-timeout",
+            "120",
+            "--sse-keep-alive",
+            "0",
+        ])
+        .unwrap();
+        let options = HttpOptions::from_args(&args);
+        assert_eq!(
+            options.header_read_timeout,
+            Some(std::time::Duration::from_secs(30))
+        );
+        assert_eq!(options.max_connections, Some(8));
+        assert_eq!(
+            options.idle_timeout,
+            Some(std::time::Duration::from_secs(120))
+        );
+        assert_eq!(options.sse_keep_alive, None);
+    }
+
+    #[test]
+    fn test_http_options_session_mode() {
+        let args =
+            Args::try_parse_from(["vcf_mcp_server", "a.vcf.gz", "--sse", "127.0.0.1:0"]).unwrap();
+        let options = HttpOptions::from_args(&args);
+        assert!(!options.stateful);
+
+        let args = Args::try_parse_from([
+            "vcf_mcp_server",
+            "a.vcf.gz",
+            "--stateful-sessions",
+            "--session-keep-alive",
+            "0",
+            "--max-sessions",
+            "16",
+        ])
+        .unwrap();
+        let options = HttpOptions::from_args(&args);
+        assert!(options.stateful);
+        assert_eq!(options.session_keep_alive, None);
+        assert_eq!(options.max_sessions, Some(16));
+
+        // Only meaningful with sessions kept
+        assert!(
+            Args::try_parse_from(["vcf_mcp_server", "a.vcf.gz", "--session-keep-alive", "60"])
+                .is_err()
+        );
+        assert!(
+            Args::try_parse_from(["vcf_mcp_server", "a.vcf.gz", "--max-sessions", "4"]).is_err()
+        );
+    }
+
+    #[test]
+    fn test_mmap_conflicts_with_watch() {
+        // A watched file changes on disk, and a mapped file rewritten in place raises SIGBUS
+        assert!(Args::try_parse_from(["vcf_mcp_server", "a.vcf.gz", "--mmap", "--watch"]).is_err());
+        assert!(Args::try_parse_from(["vcf_mcp_server", "a.vcf.gz", "--mmap"]).is_ok());
+    }
+
+    #[test]
+    fn test_parse_cors_origin() {
+        assert_eq!(
+            parse_cors_origin("http://localhost:6274/"),
+            Ok("http://localhost:6274".to_string())
+        );
+        assert_eq!(
+            parse_cors_origin("HTTPS://Inspector.Example.org"),
+            Ok("https://inspector.example.org".to_string())
+        );
+        assert_eq!(parse_cors_origin(" * "), Ok("*".to_string()));
+        assert!(parse_cors_origin("localhost:6274").is_err());
+        assert!(parse_cors_origin("http://localhost:6274/mcp").is_err());
+        assert!(parse_cors_origin("http://").is_err());
+        assert_eq!(
+            parse_header_name("Authorization"),
+            Ok("authorization".to_string())
+        );
+        assert!(parse_header_name("bad header").is_err());
+    }
+
+    #[test]
+    fn test_parse_query_locus() {
+        let locus = |chromosome: &str, start, end| Locus {
+            chromosome: chromosome.to_string(),
+            start,
+            end,
+        };
+        assert_eq!(
+            parse_region_arg("chr20:14,000-18,000"),
+            Ok(locus("chr20", 14000, 18000))
+        );
+        assert_eq!(
+            parse_region_arg("HLA-A*01:01:1-500"),
+            Ok(locus("HLA-A*01:01", 1, 500))
+        );
+        assert_eq!(
+            parse_position_arg("20:14370"),
+            Ok(locus("20", 14370, 14370))
+        );
+        assert!(parse_region_arg("chr20:18000-14000").is_err());
+        assert!(parse_region_arg("chr20:0-100").is_err());
+        assert!(parse_region_arg("chr20").is_err());
+        assert!(parse_position_arg("chr20:1-2").is_err());
+
+        // Exactly one of --region, --position and --id
+        assert!(Args::try_parse_from(["vcf_mcp_server", "query", "a.vcf.gz"]).is_err());
+        assert!(Args::try_parse_from([
+            "vcf_mcp_server",
+            "query",
+            "a.vcf.gz",
+            "--id",
+            "rs1",
+            "--position",
+            "20:1"
+        ])
+        .is_err());
+        assert!(Args::try_parse_from([
+            "vcf_mcp_server",
+            "query",
+            "--region",
+            "chr20:14000-18000",
+            "a.vcf.gz",
+            "--format",
+            "tsv"
+        ])
+        .is_ok());
+    }
+
+    #[test]
+    fn test_filter_shortcuts_combine() {
+        let none = FilterShortcuts::default();
+        assert_eq!(none.combine("").unwrap(), "");
+        assert_eq!(none.combine(" DP > 10 ").unwrap(), "(DP > 10)");
+
+        let shortcuts = FilterShortcuts {
+            max_af: Some(0.01),
+            min_af: None,
+            min_qual: Some(30.0),
+            pass_only: true,
+            // Checked per record while reading, not part of the expression
+            exclude_filters: vec!["q10".to_string()],
+            variant_types: vec![VariantType::Snp],
+            ..Default::default()
+        };
+        assert_eq!(
+            shortcuts.combine("DP > 10").unwrap(),
+            "(DP > 10) && QUAL >= 30 && AF <= 0.01 && FILTER == \"PASS\""
+        );
+        assert_eq!(
+            shortcuts.record_filter(),
+            RecordFilter {
+                exclude_filters: vec!["q10".to_string()],
+                variant_types: vec![VariantType::Snp],
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn test_retain_matching_applies_shortcuts() {
+        let index = create_test_index();
+        let (mut variants, _) = index.query_by_region("20", 1, 2_000_000);
+        let total = variants.len();
+        let excluded = RecordFilter {
+            exclude_filters: vec!["Q10".to_string()],
+            ..Default::default()
+        };
+        retain_matching(&index, "", &excluded, &mut variants);
+        assert_eq!(variants.len(), total - 1);
+        assert!(variants.iter().all(|v| v.position != 17330));
+
+        let shortcuts = FilterShortcuts {
+            min_qual: Some(50.0),
+            ..Default::default()
+        };
+        let filter = shortcuts.combine("").unwrap();
+        retain_matching(&index, &filter, &RecordFilter::default(), &mut variants);
+        assert!(variants
+            .iter()
+            .all(|v| v.quality.is_some_and(|q| q >= 50.0)));
+
+        let params: QueryByPositionParams = serde_json::from_value(serde_json::json!({
+            "chromosome": "20",
+            "position": 1234567,
+            "variant_types": ["deletion", "insertion"],
+        }))
+        .unwrap();
+        // microsat1 (GTC -> G,GTCT) deletes and inserts, so it is complex
+        let (mut variants, _) = index.query_by_position("20", 1234567);
+        assert_eq!(variants[0].variant_type, Some(VariantType::Complex));
+        retain_matching(&index, "", &params.shortcuts.record_filter(), &mut variants);
+        assert!(variants.is_empty());
+    }
+
+    #[test]
+    fn test_require_info_keeps_variants_carrying_every_key() {
+        let index = create_test_index();
+        let (mut variants, _) = index.query_by_region("20", 1, 2_000_000);
+        let required = RecordFilter {
+            require_info: vec!["DB".to_string(), "AF".to_string()],
+            ..Default::default()
+        };
+        retain_matching(&index, "", &required, &mut variants);
+        let positions: Vec<u64> = variants.iter().map(|v| v.position).collect();
+        assert_eq!(positions, vec![14370, 1110696]);
 
-        // Get session details before releasing lock
-        let chromosome = session.chromosome.clone();
-        let last_pos = session.last_position.unwrap_or(session.start);
-        let end = session.end;
-        let filter = session.filter.clone();
-        drop(sessions);
+        assert!(check_required_info(&index, &["DB".to_string()]).is_ok());
+        let error = check_required_info(&index, &["CLNSIG".to_string()]).unwrap_err();
+        assert!(error.message.contains("CLNSIG"));
+    }
 
-        let index = self.index.lock().await;
+    #[test]
+    fn test_pathogenic_only_needs_clinvar_annotations() {
+        let index = create_test_index();
+        let shortcuts: FilterShortcuts =
+            serde_json::from_value(serde_json::json!({ "pathogenic_only": true })).unwrap();
+        let record_filter = shortcuts.record_filter();
+        assert_eq!(record_filter.required_info_keys(), vec!["CLNSIG"]);
+        // The sample VCF carries no ClinVar fields, so the header check rejects the flag
+        let error = check_required_info(&index, &record_filter.required_info_keys()).unwrap_err();
+        assert!(error.message.contains("CLNSIG"));
+
+        let (mut variants, _) = index.query_by_region("20", 1, 2_000_000);
+        assert!(variants.iter().all(|v| v.clinvar.is_none()));
+        retain_matching(&index, "", &record_filter, &mut variants);
+        assert!(variants.is_empty());
+    }
 
-        // Query from next position after last returned variant
-        let next_pos = last_pos + 1;
-        let (variants, _) = index.query_by_region(&chromosome, next_pos, end);
-        let filter_engine = index.filter_engine();
+    #[test]
+    fn test_region_params_accept_lenient_numbers() {
+        let params: QueryByRegionParams = serde_json::from_value(serde_json::json!({
+            "chromosome": "20",
+            "start": "1_000",
+            "end": 1.5e6,
+            "filter": "DP > 1e1",
+            "min_qual": "3e1",
+        }))
+        .unwrap();
+        assert_eq!((params.start, params.end), (1000, 1_500_000));
+        assert_eq!(
+            params.shortcuts.combine(&params.filter).unwrap(),
+            "(DP > 10) && QUAL >= 30"
+        );
+    }
 
-        // Find next variant that passes filter
-        let next_variant = variants.into_iter().map(format_variant).find(|v| {
-            filter_engine.evaluate(&filter, &v.raw_row).unwrap_or(false) // Treat filter errors as non-match
+    #[test]
+    fn test_project_result_items() {
+        let mut payload = serde_json::json!({
+            "status": "ok",
+            "result": {"count": 1, "items": [{
+                "chromosome": "20",
+                "position": 14370,
+                "id": "rs6054257",
+                "quality": 29.0,
+                "info": {"AF": [0.5], "DP": 14, "NS": 3},
+            }]},
         });
+        let fields: Vec<String> = ["position", "id", "info.AF", "info.MISSING"]
+            .iter()
+            .map(|f| f.to_string())
+            .collect();
+        assert!(check_fields(Some(&fields)).is_ok());
+        project_result_items(&mut payload, Some(&fields));
+        assert_eq!(
+            payload["result"]["items"][0],
+            serde_json::json!({"position": 14370, "id": "rs6054257", "info": {"AF": [0.5]}})
+        );
+        assert_eq!(payload["status"], "ok");
 
-        if next_variant.is_none() {
-            // No more variants - close session
-            drop(index);
-            let mut sessions = self.query_sessions.lock().await;
-            sessions.remove(&session_id);
+        assert!(check_fields(Some(&["pos".to_string()])).is_err());
+        assert!(check_fields(Some(&["quality.x".to_string()])).is_err());
+        assert!(check_fields(None).is_ok());
+    }
 
-            let index = self.index.lock().await;
-            let reference_genome = index.get_reference_genome();
+    #[cfg(feature = "network")]
+    #[tokio::test]
+    async fn test_annotate_via_vep_needs_allow_network() {
+        let server = VcfServer::new(create_test_index(), false);
+        let params = serde_json::from_value::<AnnotateViaVepParams>(serde_json::json!({
+            "chromosome": "20",
+            "start": 14000,
+            "end": 18000,
+        }))
+        .unwrap();
+        assert_eq!(params.limit, 50);
+        assert_eq!(params.species, "homo_sapiens");
+        let error = server
+            .annotate_via_vep(Parameters(params))
+            .await
+            .unwrap_err();
+        assert!(error.message.contains("--allow-network"));
+    }
 
-            let response = StreamQueryResponse {
-                variant: None,
-                session_id: None,
-                has_more: false,
-                reference_genome,
-                matched_chromosome: Some(chromosome),
-            };
+    #[tokio::test]
+    async fn test_liftover_position_needs_chain() {
+        let server = VcfServer::new(create_test_index(), false);
+        let params = serde_json::from_value::<LiftoverPositionParams>(serde_json::json!({
+            "chromosome": "chr20",
+            "position": "10370",
+        }))
+        .unwrap();
+        let error = server
+            .liftover_position(Parameters(params))
+            .await
+            .unwrap_err();
+        assert!(error.message.contains("--chain"));
+    }
 
-            let payload = serde_json::to_value(response).map_err(|e| {
-                McpError::internal_error(
-                    format!("Failed to serialize get_next_variant response: {}", e),
-                    None,
-                )
-            })?;
+    #[cfg(feature = "network")]
+    #[tokio::test]
+    async fn test_resolve_via_ncbi_needs_allow_network() {
+        let server = VcfServer::new(create_test_index(), false);
+        let params = serde_json::from_value::<QueryByIdParams>(serde_json::json!({
+            "id": "rs999999999",
+            "resolve_via_ncbi": true,
+        }))
+        .unwrap();
+        let error = server.query_by_id(Parameters(params)).await.unwrap_err();
+        assert!(error.message.contains("--allow-network"));
+    }
 
-            let content = Content::json(payload)?;
-            return self.create_result_with_logging(content, start_time);
-        }
+    #[tokio::test]
+    async fn test_query_by_position_window() {
+        let server = VcfServer::new(create_test_index(), false);
+        let query = |window: u64| {
+            let params = serde_json::from_value::<QueryByPositionParams>(serde_json::json!({
+                "chromosome": "20",
+                "position": 15000,
+                "window": window,
+            }))
+            .unwrap();
+            server.query_by_position(Parameters(params))
+        };
 
-        // Get next variant
-        let next_variant_data = next_variant.unwrap();
-        let new_position = next_variant_data.position;
+        let result = query(0).await.unwrap();
+        let payload: serde_json::Value =
+            serde_json::from_str(&result.content[0].as_text().unwrap().text).unwrap();
+        assert_eq!(payload["result"]["count"], 0);
 
-        // Check if there are more variants after this one that pass the filter
-        let (peek_variants, _) = index.query_by_region(&chromosome, new_position + 1, end);
-        let has_more = peek_variants.into_iter().map(format_variant).any(|v| {
-            filter_engine.evaluate(&filter, &v.raw_row).unwrap_or(false) // Treat filter errors as non-match
-        });
+        // 12000-18000 holds 14370 and 17330
+        let result = query(3000).await.unwrap();
+        let payload: serde_json::Value =
+            serde_json::from_str(&result.content[0].as_text().unwrap().text).unwrap();
+        assert_eq!(payload["query"]["window"], 3000);
+        assert_eq!(payload["result"]["count"], 2);
+        assert_eq!(payload["result"]["items"][1]["position"], 17330);
 
-        let reference_genome = index.get_reference_genome();
-        drop(index);
+        assert!(query(MAX_POSITION_WINDOW + 1).await.is_err());
+    }
 
-        // Update session with new position
-        let mut sessions = self.query_sessions.lock().await;
-        if let Some(session) = sessions.get_mut(&session_id) {
-            session.last_position = Some(new_position);
-        }
+    #[tokio::test]
+    async fn test_compact_format() {
+        let server = VcfServer::new(create_test_index(), false);
+        let params = serde_json::from_value::<QueryByPositionParams>(serde_json::json!({
+            "chromosome": "20",
+            "position": 14370,
+            "format": "compact",
+        }))
+        .unwrap();
+        let result = server.query_by_position(Parameters(params)).await.unwrap();
+        let payload: serde_json::Value =
+            serde_json::from_str(&result.content[0].as_text().unwrap().text).unwrap();
+        let line = payload["result"]["items"][0].as_str().unwrap();
+        assert!(line.starts_with("20:14370 G>A rs6054257 QUAL=29 PASS "));
+        assert!(line.contains(" AF=0.5 "));
+        assert_eq!(payload["result"]["count"], 1);
+
+        let params = serde_json::from_value::<QueryByPositionParams>(serde_json::json!({
+            "chromosome": "20",
+            "position": 14370,
+            "format": "compact",
+            "fields": ["id"],
+        }))
+        .unwrap();
+        assert!(server.query_by_position(Parameters(params)).await.is_err());
+    }
 
-        // If no more variants, remove session
-        if !has_more {
-            sessions.remove(&session_id);
-        }
-        drop(sessions);
+    #[tokio::test]
+    async fn test_vcf_output_format() {
+        let server = VcfServer::new(create_test_index(), false);
+        let params = serde_json::from_value::<QueryByPositionParams>(serde_json::json!({
+            "chromosome": "20",
+            "position": 14370,
+            "window": 5000,
+            "output_format": "vcf",
+        }))
+        .unwrap();
+        let result = server.query_by_position(Parameters(params)).await.unwrap();
+        let payload: serde_json::Value =
+            serde_json::from_str(&result.content[0].as_text().unwrap().text).unwrap();
+        assert!(payload["result"].get("items").is_none());
+        let vcf = payload["result"]["vcf"].as_str().unwrap();
+        let lines: Vec<&str> = vcf.lines().collect();
+        assert!(lines[0].starts_with("##fileformat=VCFv4"));
+        assert!(lines.iter().any(|line| line.starts_with("##INFO=<ID=AF,")));
+        assert!(lines.iter().any(|line| line.starts_with("##contig=<ID=20")));
+        assert!(!lines.iter().any(|line| line.starts_with("##reference")));
+        let column_line = lines
+            .iter()
+            .position(|line| line.starts_with("#CHROM"))
+            .unwrap();
+        assert!(lines[column_line].ends_with("NA00001\tNA00002\tNA00003"));
+        let records = &lines[column_line + 1..];
+        assert_eq!(records.len(), 2);
+        assert!(records[0].starts_with("20\t14370\trs6054257\tG\tA\t"));
+        assert!(records[1].starts_with("20\t17330\t"));
+
+        let params = serde_json::from_value::<QueryByPositionParams>(serde_json::json!({
+            "chromosome": "20",
+            "position": 14370,
+            "format": "vcf",
+            "fields": ["id"],
+        }))
+        .unwrap();
+        assert!(server.query_by_position(Parameters(params)).await.is_err());
+    }
 
-        let response = StreamQueryResponse {
-            variant: Some(next_variant_data),
-            session_id: if has_more { Some(session_id) } else { None },
-            has_more,
-            reference_genome,
-            matched_chromosome: Some(chromosome),
+    #[tokio::test]
+    async fn test_table_output_formats() {
+        let server = VcfServer::new(create_test_index(), false);
+        let table = |format: &str, fields: serde_json::Value| {
+            let params = serde_json::from_value::<QueryByPositionParams>(serde_json::json!({
+                "chromosome": "20",
+                "position": 14370,
+                "output_format": format,
+                "fields": fields,
+            }))
+            .unwrap();
+            server.query_by_position(Parameters(params))
         };
 
-        let payload = serde_json::to_value(response).map_err(|e| {
-            McpError::internal_error(
-                format!("Failed to serialize get_next_variant response: {}", e),
-                None,
-            )
-        })?;
+        let result = table("tsv", serde_json::Value::Null).await.unwrap();
+        let payload: serde_json::Value =
+            serde_json::from_str(&result.content[0].as_text().unwrap().text).unwrap();
+        assert!(payload["result"].get("items").is_none());
+        assert_eq!(
+            payload["result"]["table"],
+            "CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\n20\t14370\trs6054257\tG\tA\t29\tPASS\n"
+        );
 
-        let content = Content::json(payload)?;
-        self.create_result_with_logging(content, start_time)
+        let result = table(
+            "csv",
+            serde_json::json!(["id", "info.AF", "samples.NA00002"]),
+        )
+        .await
+        .unwrap();
+        let payload: serde_json::Value =
+            serde_json::from_str(&result.content[0].as_text().unwrap().text).unwrap();
+        assert_eq!(
+            payload["result"]["table"],
+            "ID,AF,NA00002\nrs6054257,0.5,1|0\n"
+        );
+
+        assert!(table("csv", serde_json::json!(["info"])).await.is_err());
+        assert!(table("tsv", serde_json::json!(["samples.NOBODY"]))
+            .await
+            .is_err());
     }
 
-    #[tool(
-        description = "Close an active streaming query session and free resources. Sessions are automatically closed when exhausted or after 5 minutes of inactivity."
-    )]
-    async fn close_query_session(
-        &self,
-        Parameters(CloseSessionParams { session_id }): Parameters<CloseSessionParams>,
-    ) -> Result<CallToolResult, McpError> {
-        let start_time = std::time::Instant::now();
-        let mut sessions = self.query_sessions.lock().await;
-        let existed = sessions.remove(&session_id).is_some();
+    #[test]
+    fn test_normalize_variants_keeps_requested_coordinates() {
+        let dir = tempfile::TempDir::new().expect("temp dir");
+        let path = dir.path().join("ref.fa");
+        std::fs::write(&path, b">20\nGCACACACAT\n").unwrap();
+        let reference = ReferenceFasta::open(&path).expect("open FASTA");
+
+        let server = VcfServer::new(create_test_index(), false);
+        assert!(server.normalizer(true).is_err());
+        assert!(server.normalizer(false).unwrap().is_none());
+
+        let (mut variants, _) = create_test_index().query_by_position("20", 14370);
+        let mut variant = variants.remove(0);
+        variant.position = 6; // 0-based position of the CA deletion at 7
+        variant.reference = "ACA".to_string();
+        variant.alternate = vec!["A".to_string()];
+        let mut variants = vec![variant];
+        normalize_variants(Some(&reference), &mut variants, CoordinateSystem::ZeroBased);
+        let normalized = variants[0].normalized.as_ref().expect("normalized");
+        assert_eq!(normalized.position, 0);
+        assert_eq!(normalized.reference, "GCA");
+    }
 
-        let payload = serde_json::json!({
-            "closed": existed,
-            "message": if existed { "Session closed" } else { "Session not found" }
-        });
+    #[test]
+    fn test_vrs_variants_attach_alleles() {
+        let dir = tempfile::TempDir::new().expect("temp dir");
+        let path = dir.path().join("ref.fa");
+        std::fs::write(&path, b">20\nGCACACACAT\nTTTGA\n").unwrap();
+
+        let mut server = VcfServer::new(create_test_index(), false);
+        assert!(server.vrs_source(true).is_err());
+        server.use_reference(ReferenceFasta::open(&path).expect("open FASTA"));
+        let vrs = server.vrs_source(true).unwrap().expect("VRS source");
+
+        let (mut variants, _) = create_test_index().query_by_position("20", 14370);
+        let mut variant = variants.remove(0);
+        variant.position = 7;
+        variant.reference = "ACA".to_string();
+        variant.alternate = vec!["A".to_string(), "<DEL>".to_string()];
+        let mut mismatched = variant.clone();
+        mismatched.reference = "TTT".to_string();
+        let mut variants = vec![variant, mismatched];
+        vrs_variants(Some(&vrs), &mut variants, CoordinateSystem::OneBased);
+
+        let alleles = variants[0].vrs.as_ref().expect("VRS alleles");
+        let deletion = alleles[0].as_ref().expect("deletion allele");
+        assert!(deletion.id.starts_with("ga4gh:VA."));
+        assert_eq!(
+            deletion.location.sequence_reference.refget_accession,
+            format!("SQ.{}", vrs::sha512t24u(b"GCACACACATTTTGA"))
+        );
+        assert_eq!((deletion.location.start, deletion.location.end), (1, 9));
+        assert_eq!(alleles[1], None);
+        // REF doesn't match the FASTA
+        assert_eq!(variants[1].vrs, None);
+    }
 
-        let content = Content::json(payload)?;
-        self.create_result_with_logging(content, start_time)
+    #[test]
+    fn test_annotate_variants_attaches_matching_records() {
+        let mut server = VcfServer::new(create_test_index(), false);
+        assert!(server.annotator(true).is_err());
+        server.use_annotation(create_test_index(), vec!["AF".to_string()]);
+        let annotation = server.annotator(true).unwrap().expect("annotation source");
+        assert!(server.annotator(false).unwrap().is_none());
+
+        let (mut variants, _) = create_test_index().query_by_region("20", 14370, 17330);
+        assert_eq!(variants.len(), 2);
+        // The second variant's ALT no longer matches its annotation record
+        variants[1].alternate = vec!["C".to_string()];
+        for variant in &mut variants {
+            variant.position -= 1;
+        }
+        annotate_variants(
+            Some(&annotation),
+            &mut variants,
+            CoordinateSystem::ZeroBased,
+        )
+        .unwrap();
+        let first = variants[0].annotation.as_ref().expect("annotated");
+        assert_eq!(first.len(), 1);
+        assert_eq!(first[0].id.as_deref(), Some("rs6054257"));
+        assert_eq!(first[0].info.get("AF"), Some(&serde_json::json!(0.5)));
+        assert_eq!(variants[1].annotation, Some(Vec::new()));
     }
 
-    #[tool(
-        description = "Get embedded documentation for the VCF MCP server. Available types: 'readme' (main documentation), 'streaming' (streaming query guide), 'filters' (filter syntax examples), 'streaming-filters' (streaming with filters guide), 'all' (complete documentation)."
-    )]
-    async fn get_documentation(
-        &self,
-        Parameters(GetDocumentationParams { doc_type }): Parameters<GetDocumentationParams>,
-    ) -> Result<CallToolResult, McpError> {
-        let start_time = std::time::Instant::now();
-        let (content, doc_name) = match doc_type.to_lowercase().as_str() {
-            "readme" | "main" => (README_DOCS, "README.md"),
-            "streaming" => (STREAMING_DOCS, "STREAMING.md"),
-            "filters" | "filter" => (FILTER_DOCS, "FILTER_EXAMPLES.md"),
-            "streaming-filters" | "streaming_filters" => {
-                (STREAMING_FILTER_DOCS, "STREAMING_FILTER_EXAMPLES.md")
-            }
-            "all" => {
-                let combined = format!(
-                    "# VCF MCP Server - Complete Documentation\n\n\
-                     ---\n\n\
-                     # Main Documentation\n\n{}\n\n\
-                     ---\n\n\
-                     # Streaming Queries\n\n{}\n\n\
-                     ---\n\n\
-                     # Filter Examples\n\n{}\n\n\
-                     ---\n\n\
-                     # Streaming with Filters\n\n{}",
-                    README_DOCS, STREAMING_DOCS, FILTER_DOCS, STREAMING_FILTER_DOCS
-                );
-                let payload = serde_json::json!({
-                    "doc_type": "all",
-                    "content": combined,
-                    "format": "markdown",
-                    "sections": ["README.md", "STREAMING.md", "FILTER_EXAMPLES.md", "STREAMING_FILTER_EXAMPLES.md"]
-                });
-                let content = Content::json(payload)?;
-                return self.create_result_with_logging(content, start_time);
-            }
-            unknown => {
-                return Err(McpError::invalid_params(
-                    format!(
-                        "Unknown doc_type '{}'. Available: readme, streaming, filters, streaming-filters, all",
-                        unknown
-                    ),
-                    None,
-                ));
-            }
+    #[test]
+    fn test_lift_query_with_chain() {
+        let mut server = VcfServer::new(create_test_index(), false);
+        assert!(server
+            .lift_query(true, "20".to_string(), 10370, 10370)
+            .is_err());
+        // Source chr20:10001-20000 is chr20:14001-24000 on the VCF's build
+        let chains = ChainFile::parse(
+            "test.chain".to_string(),
+            "chain 1000 chr20 64444167 + 10000 20000 chr20 64444167 + 14000 24000 7\n10000\n"
+                .as_bytes(),
+        )
+        .unwrap();
+        server.use_chain(chains);
+
+        let (chromosome, start, end, liftover) = server
+            .lift_query(true, "20".to_string(), 10370, 13330)
+            .unwrap();
+        assert_eq!((chromosome.as_str(), start, end), ("chr20", 14370, 17330));
+        let liftover = liftover.expect("liftover echoed");
+        assert_eq!(liftover.chain, "test.chain");
+        assert_eq!((liftover.from.start, liftover.from.end), (10370, 13330));
+        // Without the flag the coordinates are queried as given
+        let (_, start, _, liftover) = server
+            .lift_query(false, "20".to_string(), 10370, 10370)
+            .unwrap();
+        assert_eq!(start, 10370);
+        assert!(liftover.is_none());
+        // A gap in the chain file
+        assert!(server.lift_query(true, "20".to_string(), 5, 5).is_err());
+    }
+
+    #[test]
+    fn test_filter_shortcuts_reject_invalid_values() {
+        let out_of_range = FilterShortcuts {
+            max_af: Some(1.5),
+            ..Default::default()
         };
+        assert!(out_of_range.combine("").is_err());
 
-        let payload = serde_json::json!({
-            "doc_type": doc_type,
-            "document_name": doc_name,
-            "content": content,
-            "format": "markdown"
-        });
+        let inverted = FilterShortcuts {
+            min_af: Some(0.5),
+            max_af: Some(0.1),
+            ..Default::default()
+        };
+        assert!(inverted.combine("").is_err());
 
-        let content = Content::json(payload)?;
-        self.create_result_with_logging(content, start_time)
-    }
+        let negative_qual = FilterShortcuts {
+            min_qual: Some(-1.0),
+            ..Default::default()
+        };
+        assert!(negative_qual.combine("").is_err());
 
-    // Helper method for chromosome not found responses
-    // fn build_chromosome_not_found_response(
-    //     &self,
-    //     index: &VcfIndex,
-    //     requested_chromosome: &str,
-    // ) -> Result<CallToolResult, McpError> {
-    //     let sample_chroms: Vec<String> = index
-    //         .get_available_chromosomes()
-    //         .into_iter()
-    //         .take(5)
-    //         .collect();
-    //     let alternate = if requested_chromosome.starts_with("chr") {
-    //         requested_chromosome
-    //             .strip_prefix("chr")
-    //             .unwrap_or(requested_chromosome)
-    //             .to_string()
-    //     } else {
-    //         format!("chr{}", requested_chromosome)
-    //     };
+        let empty_name = FilterShortcuts {
+            exclude_filters: vec![" ".to_string()],
+            ..Default::default()
+        };
+        assert!(empty_name.combine("").is_err());
+    }
 
-    //     Err(McpError::internal_error(
-    //         format!(
-    //             "Chromosome '{}' not found. Try '{}'? Available chromosomes (first 5): {:?}",
-    //             requested_chromosome, alternate, sample_chroms
-    //         ),
-    //         None,
-    //     ))
-    // }
-}
+    #[test]
+    fn test_select_definitions() {
+        let index = create_test_index();
+        let declared = header_definitions(&index.get_header_string(None)).info;
+        let all = select_definitions(declared.clone(), &[]);
+        assert!(matches!(all.status, QueryStatus::Ok));
+        assert_eq!(all.count, declared.len());
+
+        let selected = select_definitions(
+            declared,
+            &["db".to_string(), "DP".to_string(), "CSQ".to_string()],
+        );
+        assert!(matches!(selected.status, QueryStatus::NotFound));
+        let ids: Vec<&str> = selected.definitions.iter().map(|d| d.id.as_str()).collect();
+        assert_eq!(ids, vec!["DB", "DP"]);
+        assert_eq!(selected.definitions[0].ty.as_deref(), Some("Flag"));
+        assert_eq!(selected.not_found, vec!["CSQ"]);
+    }
 
-// Helper function to build chromosome match response metadata
-fn build_chromosome_response(
-    index: &VcfIndex,
-    requested_chromosome: &str,
-    matched_chr: &Option<String>,
-) -> (QueryStatus, Option<Vec<String>>, Option<String>) {
-    match matched_chr {
-        Some(_) => (QueryStatus::Ok, None, None),
-        None => {
-            let sample_chroms: Vec<String> = index
-                .get_available_chromosomes()
-                .into_iter()
-                .take(5)
-                .collect();
-            let alternate = if requested_chromosome.starts_with("chr") {
-                requested_chromosome
-                    .strip_prefix("chr")
-                    .unwrap_or(requested_chromosome)
-                    .to_string()
-            } else {
-                format!("chr{}", requested_chromosome)
+    #[tokio::test]
+    async fn test_has_variant_at() {
+        let server = VcfServer::new(create_test_index(), false);
+        let check = |chromosome: &str, position: u64, window: u64| {
+            let params = HasVariantAtParams {
+                chromosome: chromosome.to_string(),
+                position,
+                window,
             };
-            (
-                QueryStatus::ChromosomeNotFound,
-                Some(sample_chroms),
-                Some(alternate),
-            )
-        }
-    }
-}
+            let server = &server;
+            async move {
+                let result = server.has_variant_at(Parameters(params)).await.unwrap();
+                serde_json::from_str::<serde_json::Value>(
+                    &result.content[0].as_text().unwrap().text,
+                )
+                .unwrap()
+            }
+        };
 
-impl ServerHandler for VcfServer {
-    fn get_info(&self) -> ServerInfo {
-        ServerInfo {
-            protocol_version: ProtocolVersion::V_2024_11_05,
-            capabilities: ServerCapabilities::builder()
-                .enable_tools()
-                .enable_resources()
-                .build(),
-            server_info: Implementation::from_build_env(),
-            instructions: Some(
-                "This server provides VCF variant query tools (query_by_position, query_by_region, query_by_id, start_region_query, get_next_variant, close_query_session) and a metadata resource (vcf://metadata). For large regions, use streaming tools (start_region_query + get_next_variant) to fetch variants one at a time. IMPORTANT: Genomic coordinates are specific to the reference genome build (GRCh37 vs GRCh38). Always check the reference_genome field in responses.".to_string()
-            ),
-        }
+        let hit = check("20", 14370, 0).await;
+        assert_eq!(hit["has_variant"], true);
+        assert_eq!(hit["positions"], serde_json::json!([14370]));
+        assert_eq!(hit["from_index"], true);
+
+        assert_eq!(check("20", 14371, 0).await["has_variant"], false);
+        let near = check("chr20", 14375, 3000).await;
+        assert_eq!(near["matched_chromosome"], "20");
+        assert_eq!(near["positions"], serde_json::json!([14370, 17330]));
+
+        let unknown = check("7", 100, 0).await;
+        assert_eq!(unknown["status"], "chromosome_not_found");
+        assert_eq!(unknown["has_variant"], false);
+
+        // The bitmap only holds starts; a position inside a multi-base REF still finds it
+        let index = create_test_index();
+        assert_eq!(index.query_by_position("20", 1234569).0.len(), 1);
+        assert!(index.query_by_position("20", 1234571).0.is_empty());
     }
 
-    async fn list_resources(
-        &self,
-        _request: Option<PaginatedRequestParam>,
-        _: RequestContext<RoleServer>,
-    ) -> Result<ListResourcesResult, McpError> {
-        Ok(ListResourcesResult {
-            resources: vec![Annotated::new(
-                RawResource {
-                    uri: "vcf://metadata".to_string(),
-                    name: "VCF Metadata".to_string(),
-                    title: None,
-                    description: Some(
-                        "Metadata from the VCF file header including file format, contigs, and samples".to_string()
-                    ),
-                    mime_type: Some("application/json".to_string()),
-                    size: None,
-                    icons: None,
-                    meta: None,
-                },
-                None
-            )],
-            next_cursor: None,
-            meta: None,
-        })
+    #[tokio::test]
+    async fn test_query_by_bed_groups_by_name() {
+        let mut server = VcfServer::new(create_test_index(), false);
+        let params =
+            |value: serde_json::Value| serde_json::from_value::<QueryByBedParams>(value).unwrap();
+        let query = |params: QueryByBedParams| {
+            let server = &server;
+            async move {
+                let result = server
+                    .query_bed_intervals(params, CallContext::default())
+                    .await
+                    .unwrap();
+                serde_json::from_str::<serde_json::Value>(
+                    &result.content[0].as_text().unwrap().text,
+                )
+                .unwrap()
+            }
+        };
+
+        // 14369-14370 is VCF position 14370 only; 17329 in the next interval is 17330
+        let response = query(params(serde_json::json!({
+            "bed": "20\t14369\t14370\tGENE1\n20\t17329\t17330\tGENE1\n20\t14370\t17329\n7\t0\t10\n",
+        })))
+        .await;
+        assert_eq!(response["interval_count"], 4);
+        assert_eq!(response["unmatched_chromosomes"], serde_json::json!(["7"]));
+        let groups = response["groups"].as_array().unwrap();
+        assert_eq!(groups.len(), 3);
+        assert_eq!(groups[0]["name"], "GENE1");
+        assert_eq!(groups[0]["variant_count"], 2);
+        assert_eq!(groups[0]["intervals"][0]["start"], 14369);
+        assert_eq!(groups[0]["intervals"][0]["variants"][0]["position"], 14370);
+        assert!(groups[1]["name"].is_null());
+        assert_eq!(groups[1]["variant_count"], 0);
+
+        let truncated = query(params(serde_json::json!({
+            "bed": "20 0 2000000",
+            "limit_per_interval": 1,
+        })))
+        .await;
+        assert_eq!(truncated["groups"][0]["intervals"][0]["truncated"], true);
+
+        let error = server
+            .query_bed_intervals(
+                params(serde_json::json!({"path": "panel.bed"})),
+                CallContext::default(),
+            )
+            .await
+            .unwrap_err();
+        assert!(error.message.contains("--bed-dir"));
+
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("panel.bed"), "chr20 14369 14370 GENE1\n").unwrap();
+        server.allow_bed_dir(dir.path().canonicalize().unwrap());
+        let result = server
+            .query_bed_intervals(
+                params(serde_json::json!({"path": "panel.bed"})),
+                CallContext::default(),
+            )
+            .await
+            .unwrap();
+        let response: serde_json::Value =
+            serde_json::from_str(&result.content[0].as_text().unwrap().text).unwrap();
+        assert_eq!(response["variant_count"], 1);
+        assert_eq!(response["query"]["source"], "panel.bed");
+        let outside = server
+            .query_bed_intervals(
+                params(serde_json::json!({"path": "../panel.bed"})),
+                CallContext::default(),
+            )
+            .await;
+        assert!(outside.is_err());
     }
 
-    async fn read_resource(
-        &self,
-        request: ReadResourceRequestParam,
-        _: RequestContext<RoleServer>,
-    ) -> Result<ReadResourceResult, McpError> {
-        if request.uri.as_str() == "vcf://metadata" {
-            let index = self.index.lock().await;
-            let metadata = index.get_metadata();
-            let metadata_json = serde_json::to_string_pretty(&metadata).map_err(|e| {
-                McpError::internal_error(format!("Failed to serialize metadata: {}", e), None)
-            })?;
+    #[tokio::test]
+    async fn test_export_region_writes_indexed_vcf() {
+        let mut server = VcfServer::new(create_test_index(), false);
+        let params =
+            |value: serde_json::Value| serde_json::from_value::<ExportRegionParams>(value).unwrap();
+        let region = serde_json::json!({"chromosome": "20", "start": 1, "end": 20000});
+        let error = server
+            .export_region_file(params(region.clone()), CallContext::default())
+            .await
+            .unwrap_err();
+        assert!(error.message.contains("--allow-write-dir"));
 
-            Ok(ReadResourceResult {
-                contents: vec![ResourceContents::TextResourceContents {
-                    uri: request.uri.to_string(),
-                    mime_type: Some("application/json".to_string()),
-                    text: metadata_json,
-                    meta: None,
-                }],
-            })
-        } else {
-            Err(McpError::resource_not_found(
-                format!("Resource not found: {}", request.uri),
-                None,
-            ))
+        let dir = tempfile::tempdir().unwrap();
+        server.allow_write_dir(dir.path().canonicalize().unwrap());
+        let result = server
+            .export_region_file(params(region.clone()), CallContext::default())
+            .await
+            .unwrap();
+        let response: serde_json::Value =
+            serde_json::from_str(&result.content[0].as_text().unwrap().text).unwrap();
+        assert_eq!(response["record_count"], 2);
+        let path = PathBuf::from(response["path"].as_str().unwrap());
+        assert!(path.ends_with("20_1-20000.vcf.gz"));
+        assert!(PathBuf::from(response["index_path"].as_str().unwrap()).exists());
+
+        // The slice reads back through its tabix index, header and all
+        let slice = load_vcf(&path, false, false).unwrap();
+        assert_eq!(slice.sample_count(), 3);
+        let (variants, _) = slice.query_by_position("20", 17330);
+        assert_eq!(variants.len(), 1);
+
+        // Existing files are kept unless overwrite is set
+        assert!(server
+            .export_region_file(params(region), CallContext::default())
+            .await
+            .is_err());
+        let result = server
+            .export_region_file(
+                params(serde_json::json!({
+                    "chromosome": "20",
+                    "start": 1,
+                    "end": 20000,
+                    "output": "20_1-20000.vcf.gz",
+                    "overwrite": true,
+                    "pass_only": true,
+                })),
+                CallContext::default(),
+            )
+            .await
+            .unwrap();
+        let response: serde_json::Value =
+            serde_json::from_str(&result.content[0].as_text().unwrap().text).unwrap();
+        assert_eq!(response["record_count"], 1);
+
+        for output in ["../escape.vcf.gz", "slice.vcf", ".hidden.vcf.gz"] {
+            let region = serde_json::json!({
+                "chromosome": "20", "start": 1, "end": 20000, "output": output,
+            });
+            assert!(server
+                .export_region_file(params(region), CallContext::default())
+                .await
+                .is_err());
         }
     }
 
-    async fn list_resource_templates(
-        &self,
-        _request: Option<PaginatedRequestParam>,
-        _: RequestContext<RoleServer>,
-    ) -> Result<ListResourceTemplatesResult, McpError> {
-        Ok(ListResourceTemplatesResult {
-            next_cursor: None,
-            resource_templates: Vec::new(),
-            meta: None,
-        })
+    #[cfg(feature = "parquet-export")]
+    #[tokio::test]
+    async fn test_export_parquet() {
+        let mut server = VcfServer::new(create_test_index(), false);
+        let params = |value: serde_json::Value| {
+            serde_json::from_value::<ExportParquetParams>(value).unwrap()
+        };
+        let error = server
+            .export_parquet_file(params(serde_json::json!({})), CallContext::default())
+            .await
+            .unwrap_err();
+        assert!(error.message.contains("--allow-write-dir"));
+
+        let dir = tempfile::tempdir().unwrap();
+        server.allow_write_dir(dir.path().canonicalize().unwrap());
+        let result = server
+            .export_parquet_file(params(serde_json::json!({})), CallContext::default())
+            .await
+            .unwrap();
+        let response: serde_json::Value =
+            serde_json::from_str(&result.content[0].as_text().unwrap().text).unwrap();
+        assert_eq!(response["query"]["scope"], "file");
+        assert_eq!(response["row_count"], 5);
+        assert!(response["path"]
+            .as_str()
+            .unwrap()
+            .ends_with("variants.parquet"));
+        let columns = response["columns"].as_array().unwrap();
+        assert_eq!(columns[1]["name"], "position");
+        assert!(columns
+            .iter()
+            .any(|column| column["name"] == "info_DP" && column["type"] == "Int64"));
+
+        let result = server
+            .export_parquet_file(
+                params(serde_json::json!({
+                    "chromosome": "20",
+                    "start": 1,
+                    "end": 2000000,
+                    "pass_only": true,
+                })),
+                CallContext::default(),
+            )
+            .await
+            .unwrap();
+        let response: serde_json::Value =
+            serde_json::from_str(&result.content[0].as_text().unwrap().text).unwrap();
+        assert_eq!(response["row_count"], 4);
+        assert!(dir.path().join("20_1-2000000.parquet").exists());
+
+        // Existing files are kept unless overwrite is set
+        assert!(server
+            .export_parquet_file(params(serde_json::json!({})), CallContext::default())
+            .await
+            .is_err());
+        assert!(server
+            .export_parquet_file(
+                params(serde_json::json!({"output": "variants.csv"})),
+                CallContext::default()
+            )
+            .await
+            .is_err());
     }
 
-    async fn initialize(
-        &self,
-        request: InitializeRequestParam,
-        _: RequestContext<RoleServer>,
-    ) -> Result<InitializeResult, McpError> {
-        if self.debug {
-            eprintln!(
-                "[DEBUG] Initialize request: {}",
-                serde_json::to_string_pretty(&request).unwrap_or_else(|_| format!("{:?}", request))
-            );
-        }
-        Ok(self.get_info())
+    #[tokio::test]
+    async fn test_export_maf_needs_consequence_annotations() {
+        let server = VcfServer::new(create_test_index(), false);
+        let params = serde_json::from_value::<ExportMafParams>(serde_json::json!({
+            "chromosome": "20",
+            "start": 1,
+            "end": 20000,
+            "samples": ["NA00001"],
+        }))
+        .unwrap();
+        assert_eq!(params.samples, vec!["NA00001".to_string()]);
+        let error = server
+            .export_maf_rows(params, CallContext::default())
+            .await
+            .unwrap_err();
+        assert!(error.message.contains("ANN or CSQ"));
+        assert!(error.message.contains("annotate_via_vep"));
     }
 
-    async fn list_tools(
-        &self,
-        _request: Option<PaginatedRequestParam>,
-        _: RequestContext<RoleServer>,
-    ) -> Result<ListToolsResult, McpError> {
-        Ok(ListToolsResult {
-            tools: self.tool_router.list_all(),
-            next_cursor: None,
-            meta: None,
-        })
+    #[test]
+    fn test_link_variants() {
+        let server = VcfServer::new(create_test_index(), false);
+        // The sample file's NCBI36 header names neither build
+        let error = server.browser_build(true).unwrap_err();
+        assert!(error.message.contains("GRCh37 nor GRCh38"));
+        assert_eq!(server.browser_build(false).unwrap(), None);
+
+        let (mut variants, _) = create_test_index().query_by_position("20", 1234567);
+        link_variants(
+            Some(Build::Grch38),
+            &mut variants,
+            CoordinateSystem::OneBased,
+        );
+        let links = variants[0].browser_links.as_ref().expect("browser links");
+        assert_eq!(
+            links.igv,
+            "https://igv.org/app/?genome=hg38&locus=chr20:1234517-1234619"
+        );
+        assert!(links
+            .ucsc
+            .ends_with("&highlight=hg38.chr20:1234567-1234569"));
     }
 
-    async fn call_tool(
-        &self,
-        request: CallToolRequestParam,
-        ctx: RequestContext<RoleServer>,
-    ) -> Result<CallToolResult, McpError> {
-        if self.debug {
-            eprintln!(
-                "[DEBUG] Tool call: {}",
-                serde_json::to_string_pretty(&request).unwrap_or_else(|_| format!("{:?}", request))
-            );
-        }
-        let tool_ctx = ToolCallContext::new(self, request, ctx);
-        let result = self.tool_router.call(tool_ctx).await;
+    #[tokio::test]
+    async fn test_query_by_spdi() {
+        let server = VcfServer::new(create_test_index(), false);
+        let query = |spdi: &str| {
+            let params = serde_json::from_value::<QueryBySpdiParams>(serde_json::json!({
+                "spdi": spdi,
+            }))
+            .unwrap();
+            server.query_by_spdi(Parameters(params))
+        };
+        let payload = |result: CallToolResult| -> serde_json::Value {
+            serde_json::from_str(&result.content[0].as_text().unwrap().text).unwrap()
+        };
 
-        // Log errors in debug mode
-        if self.debug {
-            if let Err(ref e) = result {
-                eprintln!("[DEBUG] Tool call error: {:?}", e);
-            }
+        // The sample file's build (NCBI36) has no accessions, so any version is accepted
+        let response = payload(query("NC_000020.11:14369:G:A").await.unwrap());
+        assert_eq!(response["query"]["chromosome"], "20");
+        assert_eq!(response["query"]["position"], 14370);
+        assert_eq!(response["result"]["count"], 1);
+        let variant = &response["result"]["items"][0];
+        assert_eq!(variant["id"], "rs6054257");
+        assert_eq!(variant["spdi"], serde_json::json!(["20:14369:G:A"]));
+
+        // GTC>G,GTCT at 1234567, by either allele and by deletion length
+        for spdi in ["20:1234567:TC:", "20:1234567:2:", "20:1234566:GTC:GTCT"] {
+            let response = payload(query(spdi).await.unwrap());
+            assert_eq!(response["result"]["count"], 1, "{}", spdi);
+            assert_eq!(
+                response["result"]["items"][0]["spdi"],
+                serde_json::json!(["20:1234567:TC:", "20:1234569::T"])
+            );
         }
+        let response = payload(query("20:1234567:1:").await.unwrap());
+        assert_eq!(response["result"]["count"], 0);
 
-        result
+        assert!(query("NC_000020.11:14369:G").await.is_err());
     }
-}
 
-#[tokio::main]
-async fn main() -> std::io::Result<()> {
-    let args = Args::parse();
+    #[tokio::test]
+    async fn test_beacon_g_variants() {
+        let endpoint = BeaconEndpoint {
+            server: VcfServer::new(create_test_index(), false),
+            dataset: "sample.compressed.vcf.gz".to_string(),
+            max_granularity: beacon::Granularity::Record,
+        };
+        let query = |pairs: &[(&str, &str)]| {
+            let query: HashMap<String, String> = pairs
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect();
+            beacon::VariantRequest::from_query(&query)
+        };
 
-    if !args.vcf_file.exists() {
-        eprintln!("Error: VCF file not found: {}", args.vcf_file.display());
-        std::process::exit(1);
+        let (status, axum::Json(response)) = endpoint
+            .answer(query(&[
+                ("referenceName", "20"),
+                ("start", "14369"),
+                ("alternateBases", "A"),
+                ("requestedGranularity", "record"),
+            ]))
+            .await;
+        assert_eq!(status, axum::http::StatusCode::OK);
+        assert_eq!(response["responseSummary"]["exists"], true);
+        let results = &response["response"]["resultSets"][0]["results"];
+        assert_eq!(results.as_array().unwrap().len(), 1);
+        assert_eq!(results[0]["variation"]["referenceBases"], "G");
+        assert_eq!(
+            results[0]["identifiers"]["variantAlternativeIds"][0]["id"],
+            "rs6054257"
+        );
+        assert!(results[0].get("caseLevelData").is_none());
+
+        let (_, axum::Json(response)) = endpoint
+            .answer(query(&[
+                ("referenceName", "20"),
+                ("start", "14369"),
+                ("alternateBases", "T"),
+            ]))
+            .await;
+        assert_eq!(
+            response["responseSummary"],
+            serde_json::json!({"exists": false})
+        );
+
+        // Both ALT alleles of the record at 1110696; the ALT of 1230237 is missing
+        let (_, axum::Json(response)) = endpoint
+            .answer(query(&[
+                ("referenceName", "20"),
+                ("start", "1000000"),
+                ("end", "1300000"),
+                ("requestedGranularity", "record"),
+                ("limit", "1"),
+            ]))
+            .await;
+        assert_eq!(response["responseSummary"]["numTotalResults"], 2);
+        let result_set = &response["response"]["resultSets"][0];
+        assert_eq!(result_set["id"], "sample.compressed.vcf.gz");
+        assert_eq!(result_set["results"].as_array().unwrap().len(), 1);
+
+        let (status, axum::Json(response)) =
+            endpoint.answer(query(&[("referenceName", "20")])).await;
+        assert_eq!(status, axum::http::StatusCode::BAD_REQUEST);
+        assert_eq!(response["error"]["errorCode"], 400);
     }
 
-    // Load and index the VCF file
-    let save_index = !args.never_save_index;
-    let index = load_vcf(&args.vcf_file, args.debug, save_index)?;
+    #[test]
+    fn test_coordinate_system_param() {
+        let params = |value: serde_json::Value| {
+            serde_json::from_value::<QueryByRegionParams>(value)
+                .unwrap()
+                .coordinate_system
+        };
+        let region = serde_json::json!({"chromosome": "20", "start": 14369, "end": 14370});
+        assert_eq!(params(region.clone()), CoordinateSystem::OneBased);
+        let mut half_open = region.clone();
+        half_open["coordinate_system"] = "0-based-half-open".into();
+        assert_eq!(params(half_open), CoordinateSystem::ZeroBased);
+        // The earlier parameter name and values still work
+        let mut legacy = region;
+        legacy["coordinates"] = "zero_based".into();
+        assert_eq!(params(legacy), CoordinateSystem::ZeroBased);
+
+        // Region tools normalize to VCF coordinates: BED 14369-14370 is position 14370
+        assert_eq!(
+            optional_region(
+                Some("20".to_string()),
+                Some(14369),
+                Some(14370),
+                CoordinateSystem::ZeroBased
+            )
+            .unwrap(),
+            Some(("20".to_string(), 14370, 14370))
+        );
+        assert_eq!(
+            optional_region(None, None, None, CoordinateSystem::ZeroBased).unwrap(),
+            None
+        );
+    }
 
-    // Create the MCP server
-    let server = VcfServer::new(index, args.debug);
+    #[tokio::test]
+    async fn test_find_nearest_variant() {
+        let server = VcfServer::new(create_test_index(), false);
+        let find = |position: u64, max_distance: u64| {
+            let params = FindNearestVariantParams {
+                chromosome: "20".to_string(),
+                position,
+                max_distance,
+            };
+            let server = &server;
+            async move {
+                let result = server.find_nearest_variant(Parameters(params)).await?;
+                Ok::<_, McpError>(
+                    serde_json::from_str::<serde_json::Value>(
+                        &result.content[0].as_text().unwrap().text,
+                    )
+                    .unwrap(),
+                )
+            }
+        };
 
-    // Run server with appropriate transport
-    if let Some(addr) = args.sse {
-        eprintln!(
-            "VCF MCP Server ready. Starting SSE transport on {}...",
-            addr
-        );
-        run_sse_server(server, &addr).await?;
-    } else {
-        eprintln!("VCF MCP Server ready. Starting stdio transport...");
+        let found = find(15000, 100_000).await.unwrap();
+        assert_eq!(found["status"], "ok");
+        assert_eq!(found["upstream"]["distance"], 630);
+        assert_eq!(found["upstream"]["variants"][0]["position"], 14370);
+        assert_eq!(found["downstream"]["variants"][0]["position"], 17330);
 
-        // Run the server using stdio transport
-        let service = server
-            .serve(rmcp::transport::stdio())
-            .await
-            .map_err(|e| std::io::Error::other(e.to_string()))?;
+        let nothing = find(15000, 100).await.unwrap();
+        assert_eq!(nothing["status"], "not_found");
+        assert!(nothing["upstream"].is_null());
+        assert_eq!(nothing["searched_distance"], 100);
 
-        service
-            .waiting()
-            .await
-            .map_err(|e| std::io::Error::other(e.to_string()))?;
+        assert!(find(15000, MAX_NEAREST_DISTANCE + 1).await.is_err());
     }
 
-    Ok(())
-}
+    #[tokio::test]
+    async fn test_validate_build_without_markers() {
+        let server = VcfServer::new(create_test_index(), false);
+        let result = server.validate_build().await.unwrap();
+        let response: serde_json::Value =
+            serde_json::from_str(&result.content[0].as_text().unwrap().text).unwrap();
+        // The chr20 test file carries none of the markers, and its NCBI36 header names neither build
+        assert_eq!(response["verdict"], "undetermined");
+        assert_eq!(response["markers_checked"], build_check::MARKERS.len());
+        assert_eq!(response["grch37_matches"], 0);
+        assert!(response["header_build"].is_null());
+        assert!(response["agrees_with_header"].is_null());
+        assert!(response["markers"][0]["observed"].is_null());
+        assert_eq!(response["markers"][0]["rsid"], "rs1801133");
+    }
 
-async fn run_sse_server(server: VcfServer, addr: &str) -> std::io::Result<()> {
-    use axum::{
-        extract::Request,
-        middleware::{self, Next},
-        response::Response,
-        Router,
-    };
-    use rmcp::transport::streamable_http_server::{
-        session::local::LocalSessionManager, StreamableHttpServerConfig, StreamableHttpService,
-    };
+    #[tokio::test]
+    async fn test_get_samples_pages() {
+        let server = VcfServer::new(create_test_index(), false);
+        let page = |offset: usize, limit: usize| {
+            let params = serde_json::from_value::<GetSamplesParams>(serde_json::json!({
+                "offset": offset,
+                "limit": limit,
+            }))
+            .unwrap();
+            let server = &server;
+            async move {
+                let result = server.get_samples(Parameters(params)).await.unwrap();
+                serde_json::from_str::<serde_json::Value>(
+                    &result.content[0].as_text().unwrap().text,
+                )
+                .unwrap()
+            }
+        };
 
-    let bind_addr: std::net::SocketAddr = addr
-        .parse()
-        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+        let first = page(0, 2).await;
+        assert_eq!(first["total_samples"], 3);
+        assert_eq!(first["samples"], serde_json::json!(["NA00001", "NA00002"]));
+        assert_eq!(first["next_offset"], 2);
 
-    let config = StreamableHttpServerConfig {
-        sse_keep_alive: Some(std::time::Duration::from_secs(15)),
-        sse_retry: Some(std::time::Duration::from_secs(5)),
-        stateful_mode: false,
-        cancellation_token: tokio_util::sync::CancellationToken::new(),
-    };
+        let last = page(2, 2).await;
+        assert_eq!(last["samples"], serde_json::json!(["NA00003"]));
+        assert!(last.get("next_offset").is_none());
 
-    let session_manager = Arc::new(LocalSessionManager::default());
+        let past_end = page(5, 2).await;
+        assert_eq!(past_end["status"], "not_found");
+        assert_eq!(past_end["count"], 0);
+    }
 
-    let debug = server.debug;
-    let service = StreamableHttpService::new(move || Ok(server.clone()), session_manager, config);
+    #[tokio::test]
+    async fn test_stream_and_sample_queries_report_requested_samples() {
+        let server = VcfServer::new(create_test_index(), false);
+        let payload = |result: CallToolResult| {
+            serde_json::from_str::<serde_json::Value>(&result.content[0].as_text().unwrap().text)
+                .unwrap()
+        };
 
-    // Logging middleware
-    async fn log_request(req: Request, next: Next, debug: bool) -> Response {
-        if debug {
-            eprintln!("[DEBUG] HTTP {} {}", req.method(), req.uri());
-            eprintln!("[DEBUG] Headers: {:?}", req.headers());
+        let params = serde_json::from_value::<StreamRegionParams>(serde_json::json!({
+            "chromosome": "20",
+            "start": 14370,
+            "end": 17330,
+            "filter": "QUAL > 0",
+            "samples": ["NA00002"],
+        }))
+        .unwrap();
+        let first = payload(server.start_region_query(Parameters(params)).await.unwrap());
+        let samples = first["variant"]["samples"].as_object().unwrap();
+        assert_eq!(samples.keys().collect::<Vec<_>>(), vec!["NA00002"]);
+        assert_eq!(samples["NA00002"]["GT"], "1|0");
+        let params = NextVariantParams {
+            session_id: first["session_id"].as_str().unwrap().to_string(),
+        };
+        let next = payload(server.get_next_variant(Parameters(params)).await.unwrap());
+        assert_eq!(next["variant"]["samples"]["NA00002"]["GT"], "0|1");
+
+        let params = serde_json::from_value::<SampleVariantsParams>(serde_json::json!({
+            "chromosome": "20",
+            "start": 1,
+            "end": 2000000,
+            "seed": 7,
+            "samples": ["NA00003"],
+        }))
+        .unwrap();
+        let sampled = payload(
+            server
+                .sample_region_variants(params, CallContext::default())
+                .await
+                .unwrap(),
+        );
+        let items = sampled["result"]["items"].as_array().unwrap();
+        assert!(!items.is_empty());
+        for item in items {
+            let samples = item["samples"].as_object().unwrap();
+            assert_eq!(samples.keys().collect::<Vec<_>>(), vec!["NA00003"]);
         }
-        next.run(req).await
+
+        let params = serde_json::from_value::<StreamRegionParams>(serde_json::json!({
+            "chromosome": "20",
+            "start": 14370,
+            "end": 17330,
+            "samples": ["NA99999"],
+        }))
+        .unwrap();
+        assert!(server.start_region_query(Parameters(params)).await.is_err());
     }
 
-    let app = Router::new()
-        .fallback_service(service)
-        .layer(middleware::from_fn(move |req, next| {
-            log_request(req, next, debug)
-        }));
+    #[tokio::test]
+    async fn test_find_mendelian_errors() {
+        let mut server = VcfServer::new(create_test_index(), false);
+        async fn check(server: &VcfServer, arguments: serde_json::Value) -> serde_json::Value {
+            let params = serde_json::from_value::<FindMendelianErrorsParams>(arguments).unwrap();
+            let result = server
+                .find_mendelian_errors(Parameters(params))
+                .await
+                .unwrap();
+            serde_json::from_str(&result.content[0].as_text().unwrap().text).unwrap()
+        }
 
-    let listener = tokio::net::TcpListener::bind(bind_addr).await?;
+        // NA00003 is 1/1 at 14370 and 1234567, where its mother (NA00001) has no ALT
+        let report = check(
+            &server,
+            serde_json::json!({
+                "chromosome": "20",
+                "start": 1,
+                "end": 2000000,
+                "proband": "NA00003",
+                "mother": "NA00001",
+                "father": "NA00002",
+            }),
+        )
+        .await;
+        assert_eq!(report["sites_checked"], 5);
+        assert_eq!(report["error_count"], 2);
+        let errors = report["errors"].as_array().unwrap();
+        assert_eq!(errors[0]["position"], 14370);
+        assert_eq!(errors[0]["kind"], "parental_mismatch");
+        assert_eq!(errors[0]["proband_gt"], "1/1");
+        assert_eq!(errors[0]["mother_gt"], "0|0");
+        assert_eq!(errors[1]["position"], 1234567);
+
+        // The trio can come from --ped instead
+        server.use_pedigree(pedigree::parse_ped("FAM NA00003 NA00002 NA00001 2 2\n").unwrap());
+        let report = check(
+            &server,
+            serde_json::json!({"chromosome": "20", "start": 1, "end": 20000, "limit": 1}),
+        )
+        .await;
+        assert_eq!(report["trio"]["mother"], "NA00001");
+        assert_eq!(report["error_count"], 1);
+
+        assert!(server
+            .resolve_trio(None, Some("NA00001".to_string()), None)
+            .is_err());
+        assert!(server
+            .resolve_trio(Some("NA00001".to_string()), None, None)
+            .is_err());
+    }
 
-    eprintln!(
-        "Streamable HTTP MCP server listening on http://{}",
-        bind_addr
-    );
+    #[tokio::test]
+    async fn test_find_de_novo() {
+        let server = VcfServer::new(create_test_index(), false);
+        let find = |min_gq: u64, min_dp: u64| {
+            let params = serde_json::from_value::<FindDeNovoParams>(serde_json::json!({
+                "chromosome": "20",
+                "start": 1,
+                "end": 2000000,
+                "proband": "NA00002",
+                "mother": "NA00001",
+                "father": "NA00003",
+                "min_gq": min_gq,
+                "min_dp": min_dp,
+            }))
+            .unwrap();
+            let server = &server;
+            async move {
+                let result = server.find_de_novo(Parameters(params)).await.unwrap();
+                serde_json::from_str::<serde_json::Value>(
+                    &result.content[0].as_text().unwrap().text,
+                )
+                .unwrap()
+            }
+        };
 
-    axum::serve(listener, app)
-        .await
-        .map_err(std::io::Error::other)
-}
+        // At 17330 the child is 0|1 and both parents 0/0
+        let report = find(0, 0).await;
+        assert_eq!(report["candidate_count"], 1);
+        let candidate = &report["candidates"][0];
+        assert_eq!(candidate["position"], 17330);
+        assert_eq!(candidate["proband"]["gt"], "0|1");
+        assert_eq!(candidate["mother"]["gt"], "0|0");
+        assert_eq!(candidate["father"]["gt"], "0/0");
+
+        // The child's call has GQ 3, below the default minimum
+        let report = find(20, 0).await;
+        assert_eq!(report["candidate_count"], 0);
+        assert_eq!(report["filtered_by_quality"], 1);
+    }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    #[tokio::test]
+    async fn test_find_compound_het() {
+        let server = VcfServer::new(create_test_index(), false);
+        let find = |sample: &str| {
+            let params = serde_json::from_value::<FindCompoundHetParams>(serde_json::json!({
+                "chromosome": "20",
+                "start": 1,
+                "end": 2000000,
+                "sample": sample,
+            }))
+            .unwrap();
+            let server = &server;
+            async move {
+                let result = server.find_compound_het(Parameters(params)).await.unwrap();
+                serde_json::from_str::<serde_json::Value>(
+                    &result.content[0].as_text().unwrap().text,
+                )
+                .unwrap()
+            }
+        };
 
-    fn create_test_index() -> VcfIndex {
-        let vcf_path = PathBuf::from("sample_data/sample.compressed.vcf.gz");
-        load_vcf(&vcf_path, false, false).expect("Failed to load test VCF")
+        // NA00002 is 1|0, 0|1 and 2|1 at the first three sites and 0/2 at 1234567
+        let report = find("NA00002").await;
+        assert_eq!(report["gene_annotated"], false);
+        assert_eq!(report["heterozygous_sites"], 4);
+        assert_eq!(report["pair_count"], 6);
+        assert_eq!(report["cis_pairs_excluded"], 0);
+        let phases: Vec<&str> = report["pairs"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|pair| pair["phase"].as_str().unwrap())
+            .collect();
+        assert_eq!(
+            phases,
+            ["trans", "trans", "trans", "unknown", "unknown", "unknown"]
+        );
+        assert_eq!(report["pairs"][0]["first"]["position"], 14370);
+        assert_eq!(report["pairs"][0]["second"]["position"], 17330);
+
+        // NA00001 is het only at 1110696 (1|2) and 1234567 (0/1)
+        let report = find("NA00001").await;
+        assert_eq!(report["pair_count"], 1);
+        assert_eq!(report["pairs"][0]["phase"], "unknown");
+
+        // The example file has no gene annotations to filter on
+        let params = serde_json::from_value::<FindCompoundHetParams>(serde_json::json!({
+            "chromosome": "20",
+            "start": 1,
+            "end": 2000000,
+            "sample": "NA00001",
+            "gene": "BRCA2",
+        }))
+        .unwrap();
+        assert!(server.find_compound_het(Parameters(params)).await.is_err());
     }
 
-    #[test]
-    fn test_build_chromosome_response_when_matched() {
-        let index = create_test_index();
-        let matched_chr = Some("20".to_string());
+    #[tokio::test]
+    async fn test_compare_samples() {
+        let server = VcfServer::new(create_test_index(), false);
+        let params = serde_json::from_value::<CompareSamplesParams>(serde_json::json!({
+            "chromosome": "20",
+            "start": 1,
+            "end": 2000000,
+            "sample_a": "NA00001",
+            "sample_b": "NA00002",
+        }))
+        .unwrap();
+        let result = server.compare_samples(Parameters(params)).await.unwrap();
+        let report: serde_json::Value =
+            serde_json::from_str(&result.content[0].as_text().unwrap().text).unwrap();
+
+        // 1|2 vs 2|1 and 0/0 vs 0/0 agree; 14370, 17330 and 1234567 do not
+        assert_eq!(report["sites_compared"], 5);
+        assert_eq!(report["concordant"], 2);
+        assert_eq!(report["discordant"], 3);
+        assert_eq!(report["non_reference_sites"], 4);
+        assert_eq!(report["non_reference_concordance"], 0.25);
+        let positions: Vec<u64> = report["discordant_sites"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|site| site["position"].as_u64().unwrap())
+            .collect();
+        assert_eq!(positions, [14370, 17330, 1234567]);
+        assert_eq!(report["discordant_sites"][2]["genotype_a"], "0/1");
+        assert_eq!(report["discordant_sites"][2]["genotype_b"], "0/2");
+    }
 
-        let (status, available, alternate) = build_chromosome_response(&index, "20", &matched_chr);
+    #[tokio::test]
+    async fn test_compute_qc_metrics() {
+        let server = VcfServer::new(create_test_index(), false);
+        let metrics = |arguments: serde_json::Value| {
+            let params = serde_json::from_value::<QcMetricsParams>(arguments).unwrap();
+            let server = &server;
+            async move {
+                let result = server.compute_qc_metrics(Parameters(params)).await.unwrap();
+                serde_json::from_str::<serde_json::Value>(
+                    &result.content[0].as_text().unwrap().text,
+                )
+                .unwrap()
+            }
+        };
 
-        assert!(matches!(status, QueryStatus::Ok));
-        assert_eq!(available, None);
-        assert_eq!(alternate, None);
+        // G>A and A>G are transitions, T>A and A>T transversions; microsat1 has two indels
+        let file = metrics(serde_json::json!({})).await;
+        assert_eq!(file["scope"], "file");
+        assert_eq!(file["transitions"], 2);
+        assert_eq!(file["transversions"], 2);
+        assert_eq!(file["indels"], 2);
+        assert_eq!(file["ts_tv_ratio"], 1.0);
+        assert_eq!(file["indel_snp_ratio"], 0.5);
+        assert_eq!(file["heterozygous"], 6);
+        assert_eq!(file["homozygous_alt"], 3);
+        assert_eq!(file["het_hom_ratio"], 2.0);
+
+        // Only rs6054257 (G>A; 0|0, 1|0, 1/1) and the T>A site at 17330
+        let region =
+            metrics(serde_json::json!({"chromosome": "20", "start": 1, "end": 20000})).await;
+        assert_eq!(region["scope"], "region");
+        assert_eq!(region["transitions"], 1);
+        assert_eq!(region["transversions"], 1);
+        assert_eq!(region["heterozygous"], 2);
+        assert_eq!(region["homozygous_alt"], 1);
+        assert_eq!(region["indel_snp_ratio"], 0.0);
     }
 
-    #[test]
-    fn test_build_chromosome_response_when_not_found() {
-        let index = create_test_index();
-        let matched_chr = None;
+    #[tokio::test]
+    async fn test_quality_histogram() {
+        let server = VcfServer::new(create_test_index(), false);
+        let histogram = |arguments: serde_json::Value| {
+            let params = serde_json::from_value::<QualityHistogramParams>(arguments).unwrap();
+            let server = &server;
+            async move {
+                let result = server.quality_histogram(Parameters(params)).await.unwrap();
+                serde_json::from_str::<serde_json::Value>(
+                    &result.content[0].as_text().unwrap().text,
+                )
+                .unwrap()
+            }
+        };
 
-        let (status, available, alternate) = build_chromosome_response(&index, "99", &matched_chr);
+        // QUALs 29, 3, 67, 47 and 50
+        let file = histogram(serde_json::json!({"bins": 4, "include_dp": true})).await;
+        assert_eq!(file["scope"], "file");
+        assert_eq!(file["records"], 5);
+        assert_eq!(file["missing_qual"], 0);
+        assert_eq!(file["qual"]["min"], 3.0);
+        assert_eq!(file["qual"]["max"], 67.0);
+        assert_eq!(file["qual"]["percentiles"]["p50"], 47.0);
+        let counts: Vec<u64> = file["qual"]["bins"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|bin| bin["count"].as_u64().unwrap())
+            .collect();
+        assert_eq!(counts, [1, 1, 2, 1]);
+        assert_eq!(file["dp"]["count"], 5);
+
+        // Without include_dp there is no DP summary
+        let region =
+            histogram(serde_json::json!({"chromosome": "20", "start": 1, "end": 20000})).await;
+        assert_eq!(region["records"], 2);
+        assert_eq!(region["qual"]["bins"].as_array().unwrap().len(), 20);
+        assert!(region.get("dp").is_none());
+    }
 
-        assert!(matches!(status, QueryStatus::ChromosomeNotFound));
-        assert!(available.is_some());
-        assert!(alternate.is_some());
-        assert_eq!(alternate, Some("chr99".to_string()));
+    #[tokio::test]
+    async fn test_filter_summary() {
+        let server = VcfServer::new(create_test_index(), false);
+        let summary = |arguments: serde_json::Value| {
+            let params = serde_json::from_value::<FilterSummaryParams>(arguments).unwrap();
+            let server = &server;
+            async move {
+                let result = server.filter_summary(Parameters(params)).await.unwrap();
+                serde_json::from_str::<serde_json::Value>(
+                    &result.content[0].as_text().unwrap().text,
+                )
+                .unwrap()
+            }
+        };
+
+        // Four PASS records; 17330 fails q10
+        let file = summary(serde_json::json!({})).await;
+        assert_eq!(file["scope"], "file");
+        assert_eq!(file["records"], 5);
+        assert_eq!(file["filters"][0]["filter"], "PASS");
+        assert_eq!(file["filters"][0]["count"], 4);
+        assert_eq!(file["filters"][1]["filter"], "q10");
+        assert_eq!(file["filters"][1]["snps"], 1);
+        assert_eq!(file["filters"][1]["fraction"], 0.2);
+        assert_eq!(file["filters"][1]["description"], "Quality below 10");
+
+        let region =
+            summary(serde_json::json!({"chromosome": "20", "start": 1, "end": 15000})).await;
+        assert_eq!(region["records"], 1);
+        assert_eq!(region["filters"].as_array().unwrap().len(), 1);
+        assert_eq!(region["filters"][0]["snps"], 1);
     }
 
-    #[test]
-    fn test_build_chromosome_response_suggests_without_chr_prefix() {
-        let index = create_test_index();
-        let matched_chr = None;
+    #[tokio::test]
+    async fn test_allele_frequency_spectrum() {
+        let server = VcfServer::new(create_test_index(), false);
+        let spectrum = |arguments: serde_json::Value| {
+            let params = serde_json::from_value::<FrequencySpectrumParams>(arguments).unwrap();
+            let server = &server;
+            async move {
+                let result = server
+                    .allele_frequency_spectrum(Parameters(params))
+                    .await
+                    .unwrap();
+                serde_json::from_str::<serde_json::Value>(
+                    &result.content[0].as_text().unwrap().text,
+                )
+                .unwrap()
+            }
+        };
+        let counts = |report: &serde_json::Value| -> Vec<u64> {
+            report["bins"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|bin| bin["count"].as_u64().unwrap())
+                .collect()
+        };
 
-        let (status, available, alternate) =
-            build_chromosome_response(&index, "chr99", &matched_chr);
+        // INFO/AF 0.5, 0.017 and 0.333,0.667; microsat1 has no AF, so its GTs (0/1, 0/2,
+        // 1/1) give 0.5 and 0.167; the record without an ALT allele is skipped
+        let file = spectrum(serde_json::json!({})).await;
+        assert_eq!(file["records"], 5);
+        assert_eq!(file["records_skipped"], 1);
+        assert_eq!(file["alleles_from_info"], 4);
+        assert_eq!(file["alleles_from_genotypes"], 2);
+        assert_eq!(counts(&file), [1, 1, 0, 1, 0, 2, 1, 0, 0, 0]);
+
+        let folded = spectrum(serde_json::json!({"folded": true, "bins": 5})).await;
+        assert_eq!(folded["folded"], true);
+        assert_eq!(counts(&folded), [1, 1, 0, 2, 2]);
+
+        let region =
+            spectrum(serde_json::json!({"chromosome": "20", "start": 1, "end": 20000})).await;
+        assert_eq!(region["scope"], "region");
+        assert_eq!(region["records"], 2);
+    }
 
-        assert!(matches!(status, QueryStatus::ChromosomeNotFound));
-        assert!(available.is_some());
-        assert_eq!(alternate, Some("99".to_string()));
+    #[tokio::test]
+    async fn test_variant_density() {
+        let server = VcfServer::new(create_test_index(), false);
+        let density = |arguments: serde_json::Value| {
+            let params = serde_json::from_value::<VariantDensityParams>(arguments).unwrap();
+            let server = &server;
+            async move { server.variant_density(Parameters(params)).await }
+        };
+        let report = |result: CallToolResult| -> serde_json::Value {
+            serde_json::from_str(&result.content[0].as_text().unwrap().text).unwrap()
+        };
+
+        // Positions 14370, 17330, 1110696, 1230237 and 1234567
+        let windows = report(
+            density(serde_json::json!({
+                "chromosome": "20",
+                "window_size": 500000,
+                "start": 1,
+                "end": 1500000,
+            }))
+            .await
+            .unwrap(),
+        );
+        assert_eq!(windows["counts"], serde_json::json!([2, 0, 3]));
+        assert_eq!(windows["total"], 5);
+        assert_eq!(windows["empty_windows"], 1);
+        assert_eq!(windows["densest"][0]["start"], 1000001);
+        assert_eq!(windows["densest"][0]["end"], 1500000);
+        assert_eq!(windows["densest"][0]["count"], 3);
+
+        // Too many windows for the whole chromosome at 1 bp
+        assert!(
+            density(serde_json::json!({"chromosome": "20", "window_size": 1}))
+                .await
+                .is_err()
+        );
+
+        let missing = report(
+            density(serde_json::json!({"chromosome": "21", "window_size": 1000}))
+                .await
+                .unwrap(),
+        );
+        assert_eq!(missing["status"], "chromosome_not_found");
     }
 
-    #[test]
-    fn test_build_chromosome_response_suggests_with_chr_prefix() {
-        let index = create_test_index();
-        let matched_chr = None;
+    #[tokio::test]
+    async fn test_sample_qc() {
+        let server = VcfServer::new(create_test_index(), false);
+        let qc = |arguments: serde_json::Value| {
+            let params = serde_json::from_value::<SampleQcParams>(arguments).unwrap();
+            let server = &server;
+            async move { server.sample_qc(Parameters(params)).await }
+        };
+        let report = |result: CallToolResult| -> serde_json::Value {
+            serde_json::from_str(&result.content[0].as_text().unwrap().text).unwrap()
+        };
 
-        let (status, _available, alternate) = build_chromosome_response(&index, "99", &matched_chr);
+        // Every genotype in the example file is called
+        let file = report(qc(serde_json::json!({})).await.unwrap());
+        assert_eq!(file["scope"], "file");
+        assert_eq!(file["sites"], 5);
+        assert_eq!(file["sample_count"], 3);
+        assert_eq!(file["mean_call_rate"], 1.0);
+        assert_eq!(file["site_call_rate_bins"][9], 5);
+        assert_eq!(file["samples"][0]["missing"], 0);
+
+        let region = report(
+            qc(serde_json::json!({"chromosome": "20", "start": 1, "end": 20000}))
+                .await
+                .unwrap(),
+        );
+        assert_eq!(region["scope"], "region");
+        assert_eq!(region["status"], "ok");
+        assert_eq!(region["sites"], 2);
+        assert_eq!(region["low_call_rate_site_count"], 0);
 
-        assert!(matches!(status, QueryStatus::ChromosomeNotFound));
-        assert_eq!(alternate, Some("chr99".to_string()));
+        // A region needs all three of chromosome, start and end
+        assert!(qc(serde_json::json!({"chromosome": "20"})).await.is_err());
     }
 
-    #[test]
-    fn test_build_chromosome_response_includes_sample_chromosomes() {
-        let index = create_test_index();
-        let matched_chr = None;
+    #[tokio::test]
+    async fn test_find_carriers() {
+        let server = VcfServer::new(create_test_index(), false);
+        let find = |position: u64, alt: &str| {
+            let params = serde_json::from_value::<FindCarriersParams>(serde_json::json!({
+                "chromosome": "20",
+                "position": position,
+                "alt": alt,
+            }))
+            .unwrap();
+            let server = &server;
+            async move {
+                let result = server.find_carriers(Parameters(params)).await.unwrap();
+                serde_json::from_str::<serde_json::Value>(
+                    &result.content[0].as_text().unwrap().text,
+                )
+                .unwrap()
+            }
+        };
 
-        let (_status, available, _alternate) =
-            build_chromosome_response(&index, "99", &matched_chr);
+        // GTs 0|0, 1|0 and 1/1
+        let site = find(14370, "a").await;
+        assert_eq!(site["status"], "ok");
+        assert_eq!(site["reference"], "G");
+        assert_eq!(site["heterozygous"], serde_json::json!(["NA00002"]));
+        assert_eq!(site["homozygous"], serde_json::json!(["NA00003"]));
+        assert_eq!(site["heterozygous_count"], 1);
+        assert_eq!(site["no_call_count"], 0);
+
+        // Second ALT of a multi-allelic record: GTs 1|2, 2|1 and 2/2
+        let second_alt = find(1110696, "T").await;
+        assert_eq!(
+            second_alt["heterozygous"],
+            serde_json::json!(["NA00001", "NA00002"])
+        );
+        assert_eq!(second_alt["homozygous_count"], 1);
 
-        assert!(available.is_some());
-        let chroms = available.unwrap();
-        assert!(!chroms.is_empty());
-        assert!(chroms.len() <= 5, "Should limit to 5 chromosomes");
+        let missing = find(14370, "C").await;
+        assert_eq!(missing["status"], "not_found");
+        assert_eq!(missing["alleles_at_position"], serde_json::json!(["G>A"]));
+        assert!(missing.get("heterozygous").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_get_contigs() {
+        let server = VcfServer::new(create_test_index(), false);
+        let result = server.get_contigs().await.unwrap();
+        let payload: serde_json::Value =
+            serde_json::from_str(&result.content[0].as_text().unwrap().text).unwrap();
+        assert_eq!(payload["status"], "ok");
+        assert_eq!(payload["counts_pending"], false);
+        let contig_20 = payload["contigs"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|contig| contig["name"] == "20")
+            .expect("chromosome 20 is listed");
+        assert_eq!(contig_20["variant_count"], 5);
     }
 
     #[test]
@@ -1182,4 +12685,31 @@ mod tests {
         let line_count = header_string.lines().filter(|l| l.starts_with('#')).count();
         assert!(line_count > 0, "Header should have at least one line");
     }
+
+    #[test]
+    fn test_index_file_builds_sidecars() {
+        let dir = tempfile::tempdir().unwrap();
+        let vcf = dir.path().join("sample.vcf.gz");
+        std::fs::copy("sample_data/sample.compressed.vcf.gz", &vcf).unwrap();
+        let options = LoadOptions {
+            save_index: true,
+            ..Default::default()
+        };
+        let sidecar = |extension: &str| PathBuf::from(format!("{}.{}", vcf.display(), extension));
+
+        index_file(&vcf, false, &options).unwrap();
+        for extension in ["tbi", "idx", "stats"] {
+            assert!(sidecar(extension).is_file(), "{} written", extension);
+        }
+
+        // A damaged tabix index is used as it is unless rebuilt with force
+        std::fs::write(sidecar("tbi"), b"not an index").unwrap();
+        assert!(index_file(&vcf, false, &options).is_err());
+        index_file(&vcf, true, &options).unwrap();
+        assert!(noodles::tabix::fs::read(sidecar("tbi")).is_ok());
+
+        let missing = dir.path().join("missing.vcf.gz");
+        let error = index_file(&missing, false, &options).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::NotFound);
+    }
 }
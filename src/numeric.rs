@@ -0,0 +1,324 @@
+// Lenient number parsing for tool parameters and filter expressions.
+//
+// Models routinely write numbers as `1e6` or `1_000_000`, and sometimes send them as
+// strings. Numeric parameters accept all of these (as JSON numbers or strings), and numeric
+// literals in filter expressions are rewritten to plain decimals before the filter engine
+// sees them, so the expression echoed back in responses shows the value actually used.
+// Parsing never depends on the locale: `.` is the only decimal separator and `_` the only
+// digit separator.
+
+use serde::de::{self, Deserializer, Visitor};
+use std::fmt;
+
+enum Number {
+    Integer(u64),
+    Float(f64),
+}
+
+struct NumberVisitor;
+
+impl Visitor<'_> for NumberVisitor {
+    type Value = Number;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str(
+            "a number, optionally with '_' separators or an exponent (e.g. 1_000_000, 1e6)",
+        )
+    }
+
+    fn visit_u64<E: de::Error>(self, value: u64) -> Result<Number, E> {
+        Ok(Number::Integer(value))
+    }
+
+    fn visit_i64<E: de::Error>(self, value: i64) -> Result<Number, E> {
+        Ok(match u64::try_from(value) {
+            Ok(value) => Number::Integer(value),
+            Err(_) => Number::Float(value as f64),
+        })
+    }
+
+    fn visit_f64<E: de::Error>(self, value: f64) -> Result<Number, E> {
+        Ok(Number::Float(value))
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<Number, E> {
+        parse_number(value).ok_or_else(|| E::invalid_value(de::Unexpected::Str(value), &self))
+    }
+}
+
+// Parse "1_000_000", "1e6", "2.5E-3", " 42 " and the like
+fn parse_number(text: &str) -> Option<Number> {
+    let text = text.trim();
+    let cleaned = strip_digit_separators(text)?;
+    if let Ok(value) = cleaned.parse::<u64>() {
+        return Some(Number::Integer(value));
+    }
+    // Rust's float parser also accepts "inf" and "NaN", which are never meant here
+    if !cleaned
+        .chars()
+        .all(|c| c.is_ascii_digit() || matches!(c, '.' | 'e' | 'E' | '+' | '-'))
+    {
+        return None;
+    }
+    cleaned.parse::<f64>().ok().map(Number::Float)
+}
+
+// Remove `_` digit separators; each must sit between two digits
+fn strip_digit_separators(text: &str) -> Option<String> {
+    let bytes = text.as_bytes();
+    let mut cleaned = String::with_capacity(text.len());
+    for (i, c) in text.char_indices() {
+        if c == '_' {
+            let between_digits = i > 0
+                && bytes[i - 1].is_ascii_digit()
+                && bytes.get(i + 1).is_some_and(u8::is_ascii_digit);
+            if !between_digits {
+                return None;
+            }
+        } else {
+            cleaned.push(c);
+        }
+    }
+    Some(cleaned)
+}
+
+fn to_u64<E: de::Error>(number: Number) -> Result<u64, E> {
+    match number {
+        Number::Integer(value) => Ok(value),
+        Number::Float(value)
+            if value.fract() == 0.0 && (0.0..=u64::MAX as f64).contains(&value) =>
+        {
+            Ok(value as u64)
+        }
+        Number::Float(value) => Err(E::custom(format!(
+            "expected a non-negative whole number, got {}",
+            value
+        ))),
+    }
+}
+
+fn to_f64(number: Number) -> f64 {
+    match number {
+        Number::Integer(value) => value as f64,
+        Number::Float(value) => value,
+    }
+}
+
+pub fn deserialize_u64<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+    to_u64(deserializer.deserialize_any(NumberVisitor)?)
+}
+
+pub fn deserialize_usize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<usize, D::Error> {
+    let value = deserialize_u64(deserializer)?;
+    usize::try_from(value).map_err(|_| de::Error::custom(format!("{} is too large", value)))
+}
+
+pub fn deserialize_option_u64<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<u64>, D::Error> {
+    deserializer
+        .deserialize_option(OptionVisitor)?
+        .map(to_u64)
+        .transpose()
+}
+
+pub fn deserialize_option_f64<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<f64>, D::Error> {
+    Ok(deserializer.deserialize_option(OptionVisitor)?.map(to_f64))
+}
+
+struct OptionVisitor;
+
+impl<'de> Visitor<'de> for OptionVisitor {
+    type Value = Option<Number>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        NumberVisitor.expecting(formatter)
+    }
+
+    fn visit_none<E: de::Error>(self) -> Result<Self::Value, E> {
+        Ok(None)
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<Self::Value, E> {
+        Ok(None)
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_any(NumberVisitor).map(Some)
+    }
+}
+
+// Rewrite numeric literals with `_` separators or an exponent as plain decimals, leaving
+// quoted strings and identifiers (e.g. `1000G_AF`) untouched
+pub fn normalize_filter(filter: &str) -> String {
+    let chars: Vec<char> = filter.chars().collect();
+    let mut normalized = String::with_capacity(filter.len());
+    let mut quote: Option<char> = None;
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        if let Some(q) = quote {
+            normalized.push(c);
+            if c == '\\' && i + 1 < chars.len() {
+                normalized.push(chars[i + 1]);
+                i += 1;
+            } else if c == q {
+                quote = None;
+            }
+            i += 1;
+            continue;
+        }
+        if c == '"' || c == '\'' {
+            quote = Some(c);
+            normalized.push(c);
+            i += 1;
+            continue;
+        }
+
+        let starts_literal = c.is_ascii_digit()
+            && (i == 0 || !(chars[i - 1].is_alphanumeric() || matches!(chars[i - 1], '_' | '.')));
+        if !starts_literal {
+            normalized.push(c);
+            i += 1;
+            continue;
+        }
+
+        let end = literal_end(&chars, i);
+        let literal: String = chars[i..end].iter().collect();
+        let part_of_identifier = chars
+            .get(end)
+            .is_some_and(|&next| next.is_alphanumeric() || next == '_');
+        normalized.push_str(&if part_of_identifier {
+            literal
+        } else {
+            normalize_literal(&literal)
+        });
+        i = end;
+    }
+
+    normalized
+}
+
+// End of the numeric literal starting at `start`: digits and separators, an optional
+// fraction, and an exponent only when digits follow it
+fn literal_end(chars: &[char], start: usize) -> usize {
+    let digits = |mut i: usize| {
+        while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '_') {
+            i += 1;
+        }
+        i
+    };
+
+    let mut end = digits(start);
+    if chars.get(end) == Some(&'.') {
+        end = digits(end + 1);
+    }
+    if matches!(chars.get(end), Some('e' | 'E')) {
+        let mut exponent = end + 1;
+        if matches!(chars.get(exponent), Some('+' | '-')) {
+            exponent += 1;
+        }
+        if chars.get(exponent).is_some_and(char::is_ascii_digit) {
+            end = digits(exponent);
+        }
+    }
+    end
+}
+
+fn normalize_literal(literal: &str) -> String {
+    if !literal.contains(['_', 'e', 'E']) {
+        return literal.to_string();
+    }
+    let Some(cleaned) = strip_digit_separators(literal) else {
+        // Malformed separators are left for the filter engine to reject
+        return literal.to_string();
+    };
+    if !cleaned.contains(['e', 'E']) {
+        return cleaned;
+    }
+    match cleaned.parse::<f64>() {
+        // Display never uses an exponent, so 1e6 becomes 1000000 and 1e-3 becomes 0.001
+        Ok(value) if value.is_finite() => value.to_string(),
+        _ => literal.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, serde::Deserialize)]
+    struct Params {
+        #[serde(deserialize_with = "deserialize_u64")]
+        position: u64,
+        #[serde(default, deserialize_with = "deserialize_option_f64")]
+        min_qual: Option<f64>,
+    }
+
+    fn params(value: serde_json::Value) -> Result<Params, serde_json::Error> {
+        serde_json::from_value(value)
+    }
+
+    #[test]
+    fn test_numeric_parameters_accept_separators_and_exponents() {
+        for position in [
+            serde_json::json!(1000000),
+            serde_json::json!(1e6),
+            serde_json::json!("1e6"),
+            serde_json::json!("1_000_000"),
+            serde_json::json!(" 1000000 "),
+        ] {
+            assert_eq!(
+                params(serde_json::json!({"position": position}))
+                    .unwrap()
+                    .position,
+                1_000_000
+            );
+        }
+
+        let parsed = params(serde_json::json!({"position": 1, "min_qual": "2.5e1"})).unwrap();
+        assert_eq!(parsed.min_qual, Some(25.0));
+        let parsed = params(serde_json::json!({"position": 1, "min_qual": null})).unwrap();
+        assert_eq!(parsed.min_qual, None);
+    }
+
+    #[test]
+    fn test_numeric_parameters_reject_invalid_values() {
+        for position in [
+            serde_json::json!(1.5),
+            serde_json::json!(-3),
+            serde_json::json!("1,000,000"),
+            serde_json::json!("_1000"),
+            serde_json::json!("1__000"),
+            serde_json::json!("inf"),
+            serde_json::json!("ten"),
+        ] {
+            assert!(
+                params(serde_json::json!({"position": position})).is_err(),
+                "{}",
+                position
+            );
+        }
+    }
+
+    #[test]
+    fn test_normalize_filter() {
+        assert_eq!(normalize_filter("DP >= 1_000"), "DP >= 1000");
+        assert_eq!(
+            normalize_filter("AF < 1e-3 && QUAL > 2.5E1"),
+            "AF < 0.001 && QUAL > 25"
+        );
+        assert_eq!(normalize_filter("POS > 1.5e6"), "POS > 1500000");
+        // Plain numbers, identifiers and quoted strings are left alone
+        assert_eq!(normalize_filter("QUAL > 30.0"), "QUAL > 30.0");
+        assert_eq!(normalize_filter("1000G_AF > 0.1"), "1000G_AF > 0.1");
+        assert_eq!(normalize_filter("AF_1e5 > 0"), "AF_1e5 > 0");
+        assert_eq!(
+            normalize_filter("GENE == \"1e6_x 1_000\""),
+            "GENE == \"1e6_x 1_000\""
+        );
+    }
+}
@@ -0,0 +1,337 @@
+// Parquet export for export_parquet: one row per record, with typed columns for the core
+// fields and one column per INFO key declared in the header, for loading into pandas, polars,
+// DuckDB or R's arrow package.
+//
+// Core columns are chromosome, position (1-based), id, reference, alternate (list), quality,
+// filter (list) and variant_type. INFO columns are named info_<KEY> and typed from the
+// header's ##INFO line: Flag as boolean (false when absent), Integer as int64, Float as
+// float64 and String or Character as string, each a list unless Number is 1. Values that do
+// not parse as the declared type are null. Sample columns are not exported.
+//
+// Rows are written in batches of BATCH_ROWS, so a whole-file export holds one batch in
+// memory at a time.
+
+use crate::vcf::{HeaderDefinition, Variant};
+use arrow::array::{
+    ArrayRef, BooleanArray, Float32Array, Float64Array, Int64Array, ListArray, ListBuilder,
+    StringArray, StringBuilder, UInt64Array,
+};
+use arrow::datatypes::{DataType, Field, Float64Type, Int64Type, Schema, SchemaRef};
+use arrow::record_batch::RecordBatch;
+use parquet::arrow::ArrowWriter;
+use parquet::basic::Compression;
+use parquet::file::properties::WriterProperties;
+use std::fs::File;
+use std::path::Path;
+use std::sync::Arc;
+
+const BATCH_ROWS: usize = 8192;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum InfoKind {
+    Flag,
+    Integer,
+    Float,
+    Text,
+}
+
+// A column holding one INFO key
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct InfoColumn {
+    key: String,
+    kind: InfoKind,
+    // Number is not 1 (A, R, G, . or several values)
+    list: bool,
+}
+
+impl InfoColumn {
+    fn from_definition(definition: &HeaderDefinition) -> Self {
+        let kind = match definition.ty.as_deref() {
+            Some("Flag") => InfoKind::Flag,
+            Some("Integer") => InfoKind::Integer,
+            Some("Float") => InfoKind::Float,
+            _ => InfoKind::Text,
+        };
+        InfoColumn {
+            key: definition.id.clone(),
+            kind,
+            list: kind != InfoKind::Flag && definition.number.as_deref() != Some("1"),
+        }
+    }
+
+    fn field(&self) -> Field {
+        let item = match self.kind {
+            InfoKind::Flag => DataType::Boolean,
+            InfoKind::Integer => DataType::Int64,
+            InfoKind::Float => DataType::Float64,
+            InfoKind::Text => DataType::Utf8,
+        };
+        let data_type = if self.list { list_of(item) } else { item };
+        Field::new(
+            format!("info_{}", self.key),
+            data_type,
+            self.kind != InfoKind::Flag,
+        )
+    }
+
+    // The key's values in a variant: a list INFO value as its elements, a scalar as one
+    fn values<'a>(&self, variant: &'a Variant) -> Option<Vec<&'a serde_json::Value>> {
+        match variant.info.get(&self.key)? {
+            serde_json::Value::Array(values) => Some(values.iter().collect()),
+            value => Some(vec![value]),
+        }
+    }
+
+    fn array(&self, variants: &[Variant]) -> ArrayRef {
+        let first = |variant: &Variant| {
+            self.values(variant)
+                .and_then(|values| values.first().copied())
+        };
+        match (self.kind, self.list) {
+            (InfoKind::Flag, _) => Arc::new(BooleanArray::from(
+                variants
+                    .iter()
+                    .map(|variant| variant.info.contains_key(&self.key))
+                    .collect::<Vec<_>>(),
+            )),
+            (InfoKind::Integer, false) => Arc::new(Int64Array::from_iter(
+                variants.iter().map(|v| first(v).and_then(|x| x.as_i64())),
+            )),
+            (InfoKind::Float, false) => Arc::new(Float64Array::from_iter(
+                variants.iter().map(|v| first(v).and_then(|x| x.as_f64())),
+            )),
+            (InfoKind::Text, false) => Arc::new(StringArray::from_iter(
+                variants.iter().map(|v| first(v).map(text)),
+            )),
+            (InfoKind::Integer, true) => Arc::new(
+                ListArray::from_iter_primitive::<Int64Type, _, _>(variants.iter().map(|v| {
+                    self.values(v)
+                        .map(|values| values.iter().map(|x| x.as_i64()).collect::<Vec<_>>())
+                })),
+            ),
+            (InfoKind::Float, true) => Arc::new(
+                ListArray::from_iter_primitive::<Float64Type, _, _>(variants.iter().map(|v| {
+                    self.values(v)
+                        .map(|values| values.iter().map(|x| x.as_f64()).collect::<Vec<_>>())
+                })),
+            ),
+            (InfoKind::Text, true) => string_lists(variants.iter().map(|v| {
+                self.values(v)
+                    .map(|values| values.into_iter().map(text).collect())
+            })),
+        }
+    }
+}
+
+fn list_of(item: DataType) -> DataType {
+    DataType::List(Arc::new(Field::new("item", item, true)))
+}
+
+// An INFO value as text: strings as they are, anything else in its JSON form
+fn text(value: &serde_json::Value) -> String {
+    match value {
+        serde_json::Value::String(text) => text.clone(),
+        other => other.to_string(),
+    }
+}
+
+fn string_lists(rows: impl Iterator<Item = Option<Vec<String>>>) -> ArrayRef {
+    let mut builder = ListBuilder::new(StringBuilder::new());
+    for row in rows {
+        match row {
+            Some(values) => {
+                for value in values {
+                    builder.values().append_value(value);
+                }
+                builder.append(true);
+            }
+            None => builder.append(false),
+        }
+    }
+    Arc::new(builder.finish())
+}
+
+fn to_io_error(error: impl std::fmt::Display) -> std::io::Error {
+    std::io::Error::other(error.to_string())
+}
+
+// A column of the written file, for the tool's response
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct ParquetColumn {
+    pub name: String,
+    // Arrow type, e.g. "Int64" or "List(Utf8)"
+    #[serde(rename = "type")]
+    pub ty: String,
+}
+
+pub struct ParquetWriter {
+    writer: ArrowWriter<File>,
+    schema: SchemaRef,
+    info: Vec<InfoColumn>,
+    batch: Vec<Variant>,
+    rows: usize,
+}
+
+impl ParquetWriter {
+    // Create `path` with the core columns and one column per INFO definition
+    pub fn create(path: &Path, info: &[HeaderDefinition]) -> std::io::Result<Self> {
+        let info: Vec<InfoColumn> = info.iter().map(InfoColumn::from_definition).collect();
+        let mut fields = vec![
+            Field::new("chromosome", DataType::Utf8, false),
+            Field::new("position", DataType::UInt64, false),
+            Field::new("id", DataType::Utf8, true),
+            Field::new("reference", DataType::Utf8, false),
+            Field::new("alternate", list_of(DataType::Utf8), false),
+            Field::new("quality", DataType::Float32, true),
+            Field::new("filter", list_of(DataType::Utf8), false),
+            Field::new("variant_type", DataType::Utf8, true),
+        ];
+        fields.extend(info.iter().map(InfoColumn::field));
+        let schema = Arc::new(Schema::new(fields));
+        let properties = WriterProperties::builder()
+            .set_compression(Compression::SNAPPY)
+            .build();
+        let writer = ArrowWriter::try_new(File::create(path)?, schema.clone(), Some(properties))
+            .map_err(to_io_error)?;
+        Ok(ParquetWriter {
+            writer,
+            schema,
+            info,
+            batch: Vec::with_capacity(BATCH_ROWS),
+            rows: 0,
+        })
+    }
+
+    pub fn columns(&self) -> Vec<ParquetColumn> {
+        self.schema
+            .fields()
+            .iter()
+            .map(|field| ParquetColumn {
+                name: field.name().clone(),
+                ty: match field.data_type() {
+                    DataType::List(item) => format!("List({})", item.data_type()),
+                    other => other.to_string(),
+                },
+            })
+            .collect()
+    }
+
+    pub fn push(&mut self, variant: Variant) -> std::io::Result<()> {
+        self.batch.push(variant);
+        if self.batch.len() >= BATCH_ROWS {
+            self.write_batch()?;
+        }
+        Ok(())
+    }
+
+    // Write the remaining rows and the file footer; returns the number of rows
+    pub fn finish(mut self) -> std::io::Result<usize> {
+        self.write_batch()?;
+        self.writer.close().map_err(to_io_error)?;
+        Ok(self.rows)
+    }
+
+    fn write_batch(&mut self) -> std::io::Result<()> {
+        if self.batch.is_empty() {
+            return Ok(());
+        }
+        let variants = std::mem::take(&mut self.batch);
+        let id =
+            |variant: &Variant| Some(variant.id.clone()).filter(|id| !id.is_empty() && id != ".");
+        let mut columns: Vec<ArrayRef> = vec![
+            Arc::new(StringArray::from_iter_values(
+                variants.iter().map(|v| v.chromosome.as_str()),
+            )),
+            Arc::new(UInt64Array::from_iter_values(
+                variants.iter().map(|v| v.position),
+            )),
+            Arc::new(StringArray::from_iter(variants.iter().map(id))),
+            Arc::new(StringArray::from_iter_values(
+                variants.iter().map(|v| v.reference.as_str()),
+            )),
+            string_lists(variants.iter().map(|v| Some(v.alternate.clone()))),
+            Arc::new(Float32Array::from_iter(variants.iter().map(|v| v.quality))),
+            string_lists(variants.iter().map(|v| Some(v.filter.clone()))),
+            Arc::new(StringArray::from_iter(variants.iter().map(|v| {
+                v.variant_type.map(|variant_type| variant_type.as_str())
+            }))),
+        ];
+        columns.extend(self.info.iter().map(|column| column.array(&variants)));
+        let batch = RecordBatch::try_new(self.schema.clone(), columns).map_err(to_io_error)?;
+        self.writer.write(&batch).map_err(to_io_error)?;
+        self.rows += variants.len();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vcf::header_definitions;
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+    #[test]
+    fn test_info_column_types() {
+        let definitions = header_definitions(
+            "##INFO=<ID=DP,Number=1,Type=Integer,Description=\"Depth\">\n\
+             ##INFO=<ID=AF,Number=A,Type=Float,Description=\"Allele Frequency\">\n\
+             ##INFO=<ID=DB,Number=0,Type=Flag,Description=\"dbSNP\">\n\
+             ##INFO=<ID=AA,Number=1,Type=String,Description=\"Ancestral Allele\">\n",
+        )
+        .info;
+        let fields: Vec<Field> = definitions
+            .iter()
+            .map(|definition| InfoColumn::from_definition(definition).field())
+            .collect();
+        assert_eq!(fields[0].name(), "info_DP");
+        assert_eq!(fields[0].data_type(), &DataType::Int64);
+        assert_eq!(fields[1].data_type(), &list_of(DataType::Float64));
+        assert_eq!(fields[2].data_type(), &DataType::Boolean);
+        assert!(!fields[2].is_nullable());
+        assert_eq!(fields[3].data_type(), &DataType::Utf8);
+    }
+
+    #[test]
+    fn test_write_parquet() {
+        let definitions = header_definitions(
+            "##INFO=<ID=DP,Number=1,Type=Integer,Description=\"Depth\">\n\
+             ##INFO=<ID=AF,Number=A,Type=Float,Description=\"Allele Frequency\">\n",
+        )
+        .info;
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("variants.parquet");
+        let mut writer = ParquetWriter::create(&path, &definitions).unwrap();
+        assert_eq!(writer.columns()[4].ty, "List(Utf8)");
+        let index =
+            crate::vcf::load_vcf(&"sample_data/sample.compressed.vcf.gz".into(), false, false)
+                .unwrap();
+        let (variants, _) = index.query_by_region("20", 1, 2_000_000);
+        let count = variants.len();
+        for variant in variants {
+            writer.push(variant).unwrap();
+        }
+        assert_eq!(writer.finish().unwrap(), count);
+
+        let reader = ParquetRecordBatchReaderBuilder::try_new(File::open(&path).unwrap())
+            .unwrap()
+            .build()
+            .unwrap();
+        let batches: Vec<RecordBatch> = reader.map(Result::unwrap).collect();
+        let rows: usize = batches.iter().map(RecordBatch::num_rows).sum();
+        assert_eq!(rows, count);
+        let positions = batches[0]
+            .column_by_name("position")
+            .unwrap()
+            .as_any()
+            .downcast_ref::<UInt64Array>()
+            .unwrap();
+        assert_eq!(positions.value(0), 14370);
+        let depth = batches[0]
+            .column_by_name("info_DP")
+            .unwrap()
+            .as_any()
+            .downcast_ref::<Int64Array>()
+            .unwrap();
+        assert_eq!(depth.value(0), 14);
+    }
+}
@@ -0,0 +1,162 @@
+// Trios from a PED file (--ped) and the per-site trio checks of find_mendelian_errors and
+// find_de_novo.
+//
+// A PED file has one line per individual: family, individual, father, mother, sex and
+// phenotype, separated by whitespace, with "0" for a parent not in the study. Lines starting
+// with '#' are comments. Every individual with both parents listed forms a trio.
+//
+// A trio's genotypes are consistent when the child's two alleles can be split into one
+// present in the mother and one present in the father. Only diploid calls without missing
+// alleles are checked, so haploid calls (male chrX, chrY, MT) are never reported.
+//
+// A de novo candidate is stricter: the child carries an ALT allele while both parents are
+// called hom-ref, rather than merely lacking that allele.
+
+use std::path::Path;
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct Trio {
+    pub proband: String,
+    pub mother: String,
+    pub father: String,
+}
+
+// Outcome of checking one site of a trio
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MendelianCheck {
+    Consistent,
+    // The child has an allele neither parent carries: a candidate de novo variant
+    NovelAllele,
+    // Both child alleles occur in the parents, but not one in each (1/1 from 0/1 and 0/0)
+    ParentalMismatch,
+    // A genotype is missing, partly missing or not diploid
+    Unchecked,
+}
+
+fn invalid_data(message: String) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, message)
+}
+
+pub fn load_ped(path: &Path) -> std::io::Result<Vec<Trio>> {
+    parse_ped(&std::fs::read_to_string(path)?)
+}
+
+// Trios of a PED file, in file order
+pub fn parse_ped(text: &str) -> std::io::Result<Vec<Trio>> {
+    let mut trios = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let columns: Vec<&str> = line.split_whitespace().collect();
+        if columns.len() < 6 {
+            return Err(invalid_data(format!(
+                "PED line {} has {} columns, expected 6 (family, individual, father, mother, sex, phenotype)",
+                number + 1,
+                columns.len()
+            )));
+        }
+        let (father, mother) = (columns[2], columns[3]);
+        if father != "0" && mother != "0" {
+            trios.push(Trio {
+                proband: columns[1].to_string(),
+                mother: mother.to_string(),
+                father: father.to_string(),
+            });
+        }
+    }
+    Ok(trios)
+}
+
+// Both allele indexes of a diploid GT ("0/1", "1|2"); None for missing or other ploidies
+fn diploid(genotype: &str) -> Option<[usize; 2]> {
+    let mut alleles = genotype
+        .split(['/', '|'])
+        .map(|allele| allele.parse::<usize>().ok());
+    match (alleles.next(), alleles.next(), alleles.next()) {
+        (Some(Some(first)), Some(Some(second)), None) => Some([first, second]),
+        _ => None,
+    }
+}
+
+// Whether the child's GT can be inherited from the parents' GTs
+pub fn check_trio(child: &str, mother: &str, father: &str) -> MendelianCheck {
+    let (Some(child), Some(mother), Some(father)) =
+        (diploid(child), diploid(mother), diploid(father))
+    else {
+        return MendelianCheck::Unchecked;
+    };
+    let [first, second] = child;
+    if (mother.contains(&first) && father.contains(&second))
+        || (mother.contains(&second) && father.contains(&first))
+    {
+        MendelianCheck::Consistent
+    } else if child
+        .iter()
+        .any(|allele| !mother.contains(allele) && !father.contains(allele))
+    {
+        MendelianCheck::NovelAllele
+    } else {
+        MendelianCheck::ParentalMismatch
+    }
+}
+
+// Whether the child has a diploid call with an ALT allele while both parents are diploid
+// hom-ref calls ("0/0"); genotype quality and depth are up to the caller
+pub fn is_de_novo(child: &str, mother: &str, father: &str) -> bool {
+    diploid(child).is_some_and(|alleles| alleles.iter().any(|&allele| allele > 0))
+        && diploid(mother) == Some([0, 0])
+        && diploid(father) == Some([0, 0])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_ped() {
+        let trios = parse_ped(
+            "# family individual father mother sex phenotype\n\
+             FAM1 kid dad mum 1 2\n\
+             FAM1\tdad\t0\t0\t1\t1\n\
+             FAM1 mum 0 0 2 1\n\
+             FAM2 half dad 0 2 1\n",
+        )
+        .unwrap();
+        assert_eq!(
+            trios,
+            vec![Trio {
+                proband: "kid".to_string(),
+                mother: "mum".to_string(),
+                father: "dad".to_string(),
+            }]
+        );
+        assert!(parse_ped("FAM1 kid dad mum\n").is_err());
+    }
+
+    #[test]
+    fn test_check_trio() {
+        assert_eq!(check_trio("0/1", "0/0", "1/1"), MendelianCheck::Consistent);
+        assert_eq!(check_trio("1|2", "2|1", "0/1"), MendelianCheck::Consistent);
+        assert_eq!(check_trio("0/1", "0/0", "0/0"), MendelianCheck::NovelAllele);
+        assert_eq!(
+            check_trio("1/1", "0/0", "0/1"),
+            MendelianCheck::ParentalMismatch
+        );
+        assert_eq!(check_trio("./1", "0/0", "1/1"), MendelianCheck::Unchecked);
+        assert_eq!(check_trio("1", "0/0", "1/1"), MendelianCheck::Unchecked);
+    }
+
+    #[test]
+    fn test_is_de_novo() {
+        assert!(is_de_novo("0/1", "0/0", "0|0"));
+        assert!(is_de_novo("1|2", "0/0", "0/0"));
+        assert!(!is_de_novo("0/0", "0/0", "0/0"));
+        assert!(!is_de_novo("0/1", "0/1", "0/0"));
+        // A missing parental call is not evidence of absence
+        assert!(!is_de_novo("0/1", "./.", "0/0"));
+        assert!(!is_de_novo("1", "0/0", "0/0"));
+    }
+}
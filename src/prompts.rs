@@ -0,0 +1,544 @@
+// MCP prompts: common genomics workflows (triaging a variant, summarizing a gene, reviewing a
+// sample) that expand into step-by-step instructions for calling this server's tools.
+//
+// The instructions are written for the loaded file: they name its reference build, use its
+// gene annotation fields in filter expressions, and leave out steps whose tool is disabled
+// (--enable-tools/--disable-tools) or needs a server option that wasn't given.
+
+use rmcp::model::{
+    GetPromptResult, JsonObject, Prompt, PromptArgument, PromptMessage, PromptMessageRole,
+};
+use rmcp::ErrorData as McpError;
+use serde_json::json;
+use std::collections::HashSet;
+
+use crate::vcf::GeneAnnotations;
+
+// What the instructions need to know about the file and the server
+pub struct PromptContext {
+    pub build: String,
+    pub gene_annotations: GeneAnnotations,
+    pub sample_count: usize,
+    // Names of the tools clients can call
+    pub tools: HashSet<String>,
+    // Set by --allow-network, which annotate_via_vep needs
+    pub network: bool,
+    // Set by --chain, which liftover_position needs
+    pub liftover: bool,
+}
+
+impl PromptContext {
+    fn has_tool(&self, name: &str) -> bool {
+        self.tools.contains(name)
+    }
+}
+
+fn argument(name: &str, description: &str, required: bool) -> PromptArgument {
+    PromptArgument {
+        name: name.to_string(),
+        title: None,
+        description: Some(description.to_string()),
+        required: Some(required),
+    }
+}
+
+// Every prompt listed by list_prompts
+pub fn prompts() -> Vec<Prompt> {
+    vec![
+        Prompt::new(
+            "triage_variant",
+            Some("Triage the variant at chromosome:position: what the file records there, how well supported it is, who carries it and what it may do"),
+            Some(vec![
+                argument("chromosome", "Chromosome name (e.g., '20', 'chr20')", true),
+                argument("position", "Position (1-based) on the file's reference build", true),
+                argument("alt", "ALT allele to focus on (e.g., 'A'), when the site is multi-allelic", false),
+            ]),
+        ),
+        Prompt::new(
+            "summarize_gene",
+            Some("Summarize the variants in a gene: how many, of which types, how many pass filters, their frequencies and the ones worth a closer look"),
+            Some(vec![
+                argument("gene", "Gene name (e.g., 'BRCA2')", true),
+                argument("chromosome", "Chromosome the gene is on, when known", false),
+                argument("start", "Gene start (1-based) on the file's build, when known", false),
+                argument("end", "Gene end (1-based, inclusive) on the file's build, when known", false),
+            ]),
+        ),
+        Prompt::new(
+            "review_sample",
+            Some("Review one sample's data quality (missingness, Ts/Tv, heterozygosity) and list its variants in a region"),
+            Some(vec![
+                argument("sample", "Sample name as in the VCF header", true),
+                argument("chromosome", "Chromosome of a region to list the sample's variants in", false),
+                argument("start", "Region start (1-based)", false),
+                argument("end", "Region end (1-based, inclusive)", false),
+            ]),
+        ),
+    ]
+}
+
+// A prompt argument as given, None when missing or blank. Arguments are strings, but clients
+// sending a number are understood too
+fn optional(arguments: &JsonObject, name: &str) -> Option<String> {
+    let value = match arguments.get(name)? {
+        serde_json::Value::String(value) => value.trim().to_string(),
+        serde_json::Value::Number(value) => value.to_string(),
+        _ => return None,
+    };
+    (!value.is_empty()).then_some(value)
+}
+
+fn required(prompt: &str, arguments: &JsonObject, name: &str) -> Result<String, McpError> {
+    optional(arguments, name).ok_or_else(|| {
+        McpError::invalid_params(
+            format!("Prompt {} needs the argument {}", prompt, name),
+            None,
+        )
+    })
+}
+
+// A 1-based position; thousands separators are allowed ("14,370")
+fn position(name: &str, value: &str) -> Result<u64, McpError> {
+    value
+        .replace(',', "")
+        .parse::<u64>()
+        .ok()
+        .filter(|&position| position > 0)
+        .ok_or_else(|| {
+            McpError::invalid_params(
+                format!("{} must be a 1-based position, got '{}'", name, value),
+                None,
+            )
+        })
+}
+
+// The optional chromosome/start/end region of a prompt: all three or none
+fn region(prompt: &str, arguments: &JsonObject) -> Result<Option<(String, u64, u64)>, McpError> {
+    let given = [
+        optional(arguments, "chromosome"),
+        optional(arguments, "start"),
+        optional(arguments, "end"),
+    ];
+    match given {
+        [None, None, None] => Ok(None),
+        [Some(chromosome), Some(start), Some(end)] => {
+            let (start, end) = (position("start", &start)?, position("end", &end)?);
+            if start > end {
+                return Err(McpError::invalid_params(
+                    format!("start ({}) is after end ({})", start, end),
+                    None,
+                ));
+            }
+            Ok(Some((chromosome, start, end)))
+        }
+        _ => Err(McpError::invalid_params(
+            format!(
+                "Prompt {} takes a region as chromosome, start and end together",
+                prompt
+            ),
+            None,
+        )),
+    }
+}
+
+// A tool call as written in the instructions, e.g. `has_variant_at` with {"position":14370}
+fn call(tool: &str, arguments: serde_json::Value) -> String {
+    format!("`{}` with {}", tool, arguments)
+}
+
+// Numbered steps, skipping those whose tool isn't available
+struct Steps<'a> {
+    context: &'a PromptContext,
+    steps: Vec<String>,
+}
+
+impl<'a> Steps<'a> {
+    fn new(context: &'a PromptContext) -> Self {
+        Steps {
+            context,
+            steps: Vec::new(),
+        }
+    }
+
+    fn add(&mut self, tool: &str, step: String) {
+        if self.context.has_tool(tool) {
+            self.push(step);
+        }
+    }
+
+    fn push(&mut self, step: String) {
+        self.steps.push(step);
+    }
+
+    fn render(&self) -> String {
+        self.steps
+            .iter()
+            .enumerate()
+            .map(|(i, step)| format!("{}. {}", i + 1, step))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+// A message reminding the caller which build the file's coordinates are on
+fn build_note(context: &PromptContext) -> String {
+    format!(
+        "The VCF is on reference build {}; coordinates must be on that build.",
+        context.build
+    )
+}
+
+fn triage_variant(
+    arguments: &JsonObject,
+    context: &PromptContext,
+) -> Result<(String, String), McpError> {
+    let chromosome = required("triage_variant", arguments, "chromosome")?;
+    let position = position(
+        "position",
+        &required("triage_variant", arguments, "position")?,
+    )?;
+    let alt = optional(arguments, "alt");
+    let locus = format!("{}:{}", chromosome, position);
+
+    let mut steps = Steps::new(context);
+    if context.liftover {
+        steps.add(
+            "liftover_position",
+            format!(
+                "If {} comes from another build than {}, lift it first with {} and use the lifted position below.",
+                locus,
+                context.build,
+                call(
+                    "liftover_position",
+                    json!({"chromosome": chromosome, "position": position})
+                )
+            ),
+        );
+    }
+    steps.add(
+        "query_by_position",
+        format!(
+            "Fetch the record with {}. Note its ID, REF/ALT, QUAL, FILTER and INFO values such as AF, DP and any ClinVar or ANN/CSQ annotations.",
+            call(
+                "query_by_position",
+                json!({"chromosome": chromosome, "position": position, "browser_links": true})
+            )
+        ),
+    );
+    steps.add(
+        "find_nearest_variant",
+        format!(
+            "If nothing starts at {}, report that and look for nearby records with {} (an indel may start a few bases earlier).",
+            locus,
+            call(
+                "find_nearest_variant",
+                json!({"chromosome": chromosome, "position": position, "max_distance": 1000})
+            )
+        ),
+    );
+    steps.add(
+        "explain_filter_value",
+        "If FILTER is not PASS, explain each value with `explain_filter_value` and say how common it is in the file.".to_string(),
+    );
+    steps.add(
+        "get_info_field_definitions",
+        "Look up INFO keys whose meaning is unclear with `get_info_field_definitions` rather than guessing.".to_string(),
+    );
+    if context.sample_count > 0 {
+        let alt = alt.as_deref().unwrap_or("<the ALT allele>");
+        steps.add(
+            "find_carriers",
+            format!(
+                "List the samples carrying it with {}, once per ALT allele at multi-allelic sites.",
+                call(
+                    "find_carriers",
+                    json!({"chromosome": chromosome, "position": position, "alt": alt})
+                )
+            ),
+        );
+    }
+    if context.gene_annotations.is_empty() && context.network {
+        steps.add(
+            "annotate_via_vep",
+            format!(
+                "The file has no gene annotations; get the predicted consequence with {}.",
+                call(
+                    "annotate_via_vep",
+                    json!({"chromosome": chromosome, "start": position, "end": position, "pick": true})
+                )
+            ),
+        );
+    }
+
+    let focus = alt
+        .as_ref()
+        .map(|alt| format!(" (ALT {})", alt))
+        .unwrap_or_default();
+    let text = format!(
+        "Triage the variant at {}{} in this VCF. {}\n\n{}\n\nFinish with a short assessment: the alleles and any known ID, whether the call looks reliable (QUAL, FILTER, depth), how frequent it is, who carries it, its likely consequence, and whether it deserves follow-up. Include the browser links so the call can be inspected. Only state what the tool results show.",
+        locus,
+        focus,
+        build_note(context),
+        steps.render()
+    );
+    Ok((format!("Triage of the variant at {}", locus), text))
+}
+
+fn summarize_gene(
+    arguments: &JsonObject,
+    context: &PromptContext,
+) -> Result<(String, String), McpError> {
+    let gene = required("summarize_gene", arguments, "gene")?;
+    let filter = context.gene_annotations.filter(&gene);
+
+    let mut steps = Steps::new(context);
+    let (chromosome, start, end) = match region("summarize_gene", arguments)? {
+        Some((chromosome, start, end)) => (chromosome, json!(start), json!(end)),
+        None => {
+            steps.push(format!(
+                "Find {}'s chromosome, start and end on {}. If you are not sure of them, ask the user instead of guessing.",
+                gene, context.build
+            ));
+            ("<chromosome>".to_string(), json!("<start>"), json!("<end>"))
+        }
+    };
+    steps.add(
+        "get_contigs",
+        "Check the file's chromosome naming (`1` or `chr1`) with `get_contigs`.".to_string(),
+    );
+    let mut query = json!({
+        "chromosome": chromosome,
+        "start": start,
+        "end": end,
+        "format": "compact",
+    });
+    if let Some(filter) = &filter {
+        query["filter"] = json!(filter);
+    }
+    steps.add(
+        "query_by_region",
+        format!(
+            "List the gene's variants with {}. Genes are often wider than the region size cap: when the response asks for confirmation, repeat it with `confirm_large_query: true`, and follow `next_cursor` until every page is read.{}",
+            call("query_by_region", query),
+            if filter.is_some() {
+                " The filter keeps records annotated with the gene; drop it to also see intronic or intergenic records the annotation doesn't attribute to it."
+            } else {
+                ""
+            }
+        ),
+    );
+    let region = json!({"chromosome": chromosome, "start": start, "end": end});
+    steps.add(
+        "filter_summary",
+        format!(
+            "Break the records down by FILTER and variant type with {}.",
+            call("filter_summary", region.clone())
+        ),
+    );
+    steps.add(
+        "allele_frequency_spectrum",
+        format!(
+            "See how many variants are rare with {}.",
+            call(
+                "allele_frequency_spectrum",
+                json!({"chromosome": chromosome, "start": start, "end": end, "folded": true})
+            )
+        ),
+    );
+    steps.add(
+        "compute_qc_metrics",
+        format!(
+            "Check Ts/Tv and het/hom ratios with {}.",
+            call("compute_qc_metrics", region.clone())
+        ),
+    );
+    if filter.is_none() && context.network {
+        steps.add(
+            "annotate_via_vep",
+            format!(
+                "The file has no gene annotations; predict consequences for the passing variants with {} (at most 200 variants per call).",
+                call(
+                    "annotate_via_vep",
+                    json!({"chromosome": chromosome, "start": start, "end": end, "pass_only": true, "pick": true})
+                )
+            ),
+        );
+    }
+
+    let text = format!(
+        "Summarize the variants in {} in this VCF. {}\n\n{}\n\nReport the number of variants by type, the fraction passing filters, the frequency distribution, and list the variants worth a closer look (high-impact or clinically annotated, rare and passing) with their positions and IDs. Only state what the tool results show.",
+        gene,
+        build_note(context),
+        steps.render()
+    );
+    Ok((format!("Summary of the variants in {}", gene), text))
+}
+
+fn review_sample(
+    arguments: &JsonObject,
+    context: &PromptContext,
+) -> Result<(String, String), McpError> {
+    let sample = required("review_sample", arguments, "sample")?;
+    let region = region("review_sample", arguments)?;
+
+    let mut steps = Steps::new(context);
+    steps.add(
+        "get_samples",
+        format!(
+            "Check that {} is one of the file's samples with `get_samples` (names are case-sensitive).",
+            sample
+        ),
+    );
+    steps.add(
+        "sample_qc",
+        format!(
+            "Get genotype missingness with `sample_qc` (no region, for the whole file) and compare {}'s missing_rate with the other samples.",
+            sample
+        ),
+    );
+    steps.add(
+        "compute_qc_metrics",
+        "Put the sample in context of the call set's Ts/Tv and het/hom ratios from `compute_qc_metrics`.".to_string(),
+    );
+    if let Some((chromosome, start, end)) = region {
+        steps.add(
+            "query_by_region",
+            format!(
+                "List the sample's variants in the region with {}.",
+                call(
+                    "query_by_region",
+                    json!({
+                        "chromosome": chromosome,
+                        "start": start,
+                        "end": end,
+                        "present_in_sample": sample,
+                        "samples": [sample],
+                        "format": "compact",
+                    })
+                )
+            ),
+        );
+        steps.add(
+            "sample_qc",
+            format!(
+                "List poorly called sites in the region with {}.",
+                call(
+                    "sample_qc",
+                    json!({"chromosome": chromosome, "start": start, "end": end})
+                )
+            ),
+        );
+    }
+    steps.add(
+        "find_mendelian_errors",
+        "If the server has a PED file and the sample is a child in a trio, check a region for inheritance errors with `find_mendelian_errors`; many errors point to a sample swap.".to_string(),
+    );
+
+    let text = format!(
+        "Review sample {} in this VCF. {}\n\n{}\n\nConclude whether the sample's data look usable, naming any metric that stands out from the rest of the cohort. Only state what the tool results show.",
+        sample,
+        build_note(context),
+        steps.render()
+    );
+    Ok((format!("Review of sample {}", sample), text))
+}
+
+// Expand a prompt into its instructions
+pub fn get_prompt(
+    name: &str,
+    arguments: &JsonObject,
+    context: &PromptContext,
+) -> Result<GetPromptResult, McpError> {
+    let (description, text) = match name {
+        "triage_variant" => triage_variant(arguments, context)?,
+        "summarize_gene" => summarize_gene(arguments, context)?,
+        "review_sample" => review_sample(arguments, context)?,
+        _ => {
+            let names: Vec<String> = prompts().into_iter().map(|prompt| prompt.name).collect();
+            return Err(McpError::invalid_params(
+                format!("Unknown prompt: {} (available: {})", name, names.join(", ")),
+                None,
+            ));
+        }
+    };
+    Ok(GetPromptResult {
+        description: Some(description),
+        messages: vec![PromptMessage::new_text(PromptMessageRole::User, text)],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn context(tools: &[&str]) -> PromptContext {
+        PromptContext {
+            build: "GRCh38".to_string(),
+            gene_annotations: GeneAnnotations::default(),
+            sample_count: 3,
+            tools: tools.iter().map(|tool| tool.to_string()).collect(),
+            network: false,
+            liftover: false,
+        }
+    }
+
+    fn arguments(value: serde_json::Value) -> JsonObject {
+        value.as_object().unwrap().clone()
+    }
+
+    fn text(result: &GetPromptResult) -> String {
+        serde_json::to_value(&result.messages[0]).unwrap()["content"]["text"]
+            .as_str()
+            .unwrap()
+            .to_string()
+    }
+
+    #[test]
+    fn test_triage_variant_prompt() {
+        let context = context(&["query_by_position", "find_carriers", "annotate_via_vep"]);
+        let result = get_prompt(
+            "triage_variant",
+            &arguments(json!({"chromosome": "20", "position": "14,370"})),
+            &context,
+        )
+        .unwrap();
+        let text = text(&result);
+        assert!(text.contains("variant at 20:14370"));
+        assert!(text.contains("reference build GRCh38"));
+        assert!(text.contains("1. Fetch the record with `query_by_position` with {"));
+        assert!(text.contains("\"position\":14370"));
+        assert!(text.contains("2. List the samples carrying it"));
+        // Disabled tools and annotate_via_vep without --allow-network are left out
+        assert!(!text.contains("explain_filter_value"));
+        assert!(!text.contains("annotate_via_vep"));
+    }
+
+    #[test]
+    fn test_prompt_arguments() {
+        let context = context(&["query_by_region"]);
+        let missing = get_prompt(
+            "triage_variant",
+            &arguments(json!({"chromosome": "20"})),
+            &context,
+        );
+        assert!(missing.unwrap_err().message.contains("position"));
+        let partial_region = get_prompt(
+            "summarize_gene",
+            &arguments(json!({"gene": "BRCA2", "chromosome": "13"})),
+            &context,
+        );
+        assert!(partial_region.is_err());
+        assert!(get_prompt("unknown", &JsonObject::new(), &context).is_err());
+
+        // Without a region the gene's coordinates are looked up first
+        let result = get_prompt(
+            "summarize_gene",
+            &arguments(json!({"gene": "BRCA2"})),
+            &context,
+        )
+        .unwrap();
+        let text = text(&result);
+        assert!(text.contains("1. Find BRCA2's chromosome, start and end on GRCh38"));
+        assert!(text.contains("2. List the gene's variants"));
+    }
+}
@@ -0,0 +1,377 @@
+// Reference genome FASTA (--reference) for normalizing variant alleles on output.
+//
+// The FASTA is memory-mapped and located through its samtools `.fai` index, which is built in
+// memory when the file has none. Only uncompressed FASTA is supported: bgzip-compressed FASTA
+// would need a `.gzi` block index as well.
+//
+// Normalization follows the usual left-align-and-trim procedure (as in `bcftools norm` and
+// `vt normalize`): while every allele ends in the same base, drop it, extending all alleles
+// one base to the left from the reference when one becomes empty; then drop leading bases
+// shared by every allele, keeping at least one. Databases that store normalized alleles then
+// compare equal to records written with a different but equivalent representation.
+
+use memmap2::Mmap;
+use std::collections::HashMap;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+// One line of a .fai index
+#[derive(Debug, Clone, Copy)]
+struct FaiRecord {
+    length: u64,
+    // Byte offset of the first base
+    offset: u64,
+    line_bases: u64,
+    // Bytes per line including the line terminator
+    line_width: u64,
+}
+
+pub struct ReferenceFasta {
+    data: Mmap,
+    sequences: HashMap<String, FaiRecord>,
+}
+
+// Alleles after left-alignment and trimming, next to the record's original ones
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct NormalizedAlleles {
+    pub position: u64,
+    pub reference: String,
+    pub alternate: Vec<String>,
+    // False when the record was already normalized
+    pub changed: bool,
+}
+
+fn invalid_data(message: String) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, message)
+}
+
+impl ReferenceFasta {
+    pub fn open(path: &Path) -> std::io::Result<Self> {
+        let file = File::open(path)?;
+        // Safety: like the VCF under --mmap, the FASTA must not be modified while it is served
+        let data = unsafe { Mmap::map(&file)? };
+        if data.starts_with(&[0x1f, 0x8b]) {
+            return Err(invalid_data(format!(
+                "{} is compressed; --reference needs an uncompressed FASTA",
+                path.display()
+            )));
+        }
+
+        let fai_path = PathBuf::from(format!("{}.fai", path.display()));
+        let sequences = if fai_path.exists() {
+            parse_fai(&std::fs::read_to_string(&fai_path)?)?
+        } else {
+            build_fai(&data)?
+        };
+        Ok(ReferenceFasta { data, sequences })
+    }
+
+    // Name of the FASTA sequence for a VCF chromosome, allowing for a "chr" prefix on
+    // either side
+    pub fn sequence_name(&self, chromosome: &str) -> Option<String> {
+        let alternate = match chromosome.strip_prefix("chr") {
+            Some(stripped) => stripped.to_string(),
+            None => format!("chr{}", chromosome),
+        };
+        [chromosome.to_string(), alternate]
+            .into_iter()
+            .find(|name| self.sequences.contains_key(name))
+    }
+
+    // Base at a 1-based position, uppercased; None outside the sequence
+    pub fn base(&self, sequence: &str, position: u64) -> Option<u8> {
+        let record = self.sequences.get(sequence)?;
+        if position == 0 || position > record.length {
+            return None;
+        }
+        let index = position - 1;
+        let offset = record.offset
+            + (index / record.line_bases) * record.line_width
+            + index % record.line_bases;
+        self.data
+            .get(offset as usize)
+            .map(|base| base.to_ascii_uppercase())
+    }
+
+    // The bases of a whole sequence, one FASTA line at a time, as stored (not uppercased)
+    pub fn sequence_lines(&self, sequence: &str) -> Option<impl Iterator<Item = &[u8]> + '_> {
+        let record = *self.sequences.get(sequence)?;
+        let lines = record.length.div_ceil(record.line_bases);
+        Some((0..lines).filter_map(move |line| {
+            let start = record.offset + line * record.line_width;
+            let bases = record
+                .line_bases
+                .min(record.length - line * record.line_bases);
+            self.data.get(start as usize..(start + bases) as usize)
+        }))
+    }
+
+    // Left-align and trim a record's alleles. None when an allele is not a plain sequence
+    // (symbolic, breakend, '*' or missing), the chromosome is not in the FASTA, or REF does
+    // not match the reference (usually a FASTA for a different genome build).
+    pub fn normalize(
+        &self,
+        chromosome: &str,
+        position: u64,
+        reference: &str,
+        alternates: &[String],
+    ) -> Option<NormalizedAlleles> {
+        let sequence = self.sequence_name(chromosome)?;
+        let matches_reference = reference.bytes().enumerate().all(|(i, base)| {
+            self.base(&sequence, position + i as u64) == Some(base.to_ascii_uppercase())
+        });
+        if !matches_reference {
+            return None;
+        }
+        normalize_alleles(position, reference, alternates, |position| {
+            self.base(&sequence, position)
+        })
+    }
+}
+
+fn parse_fai(text: &str) -> std::io::Result<HashMap<String, FaiRecord>> {
+    let mut sequences = HashMap::new();
+    for (number, line) in text.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let columns: Vec<&str> = line.split('\t').collect();
+        let field = |i: usize| -> std::io::Result<u64> {
+            columns
+                .get(i)
+                .and_then(|value| value.parse().ok())
+                .ok_or_else(|| invalid_data(format!("Malformed .fai line {}", number + 1)))
+        };
+        let record = FaiRecord {
+            length: field(1)?,
+            offset: field(2)?,
+            line_bases: field(3)?,
+            line_width: field(4)?,
+        };
+        if record.line_bases == 0 || record.line_width < record.line_bases {
+            return Err(invalid_data(format!("Malformed .fai line {}", number + 1)));
+        }
+        sequences.insert(columns[0].to_string(), record);
+    }
+    Ok(sequences)
+}
+
+// Index a FASTA the way `samtools faidx` would; every line of a sequence but the last must
+// have the same length
+fn build_fai(data: &[u8]) -> std::io::Result<HashMap<String, FaiRecord>> {
+    let mut sequences = HashMap::new();
+    let mut current: Option<(String, FaiRecord)> = None;
+    // Set once a sequence has a line shorter than the first, which must be its last
+    let mut short_line_seen = false;
+    let mut offset = 0usize;
+
+    for line in data.split_inclusive(|&byte| byte == b'\n') {
+        let line_start = offset;
+        offset += line.len();
+        let bases = line
+            .strip_suffix(b"\n")
+            .map(|line| line.strip_suffix(b"\r").unwrap_or(line))
+            .unwrap_or(line);
+
+        if let Some(header) = bases.strip_prefix(b">") {
+            if let Some((name, record)) = current.take() {
+                sequences.insert(name, record);
+            }
+            let header = String::from_utf8_lossy(header);
+            let name = header.split_whitespace().next().unwrap_or_default();
+            current = Some((
+                name.to_string(),
+                FaiRecord {
+                    length: 0,
+                    offset: offset as u64,
+                    line_bases: 0,
+                    line_width: 0,
+                },
+            ));
+            short_line_seen = false;
+            continue;
+        }
+
+        let Some((name, record)) = current.as_mut() else {
+            if bases.is_empty() {
+                continue;
+            }
+            return Err(invalid_data(
+                "FASTA does not start with a '>' header".to_string(),
+            ));
+        };
+        if bases.is_empty() {
+            continue;
+        }
+        if record.line_bases == 0 {
+            record.offset = line_start as u64;
+            record.line_bases = bases.len() as u64;
+            record.line_width = line.len() as u64;
+        } else if short_line_seen || bases.len() as u64 > record.line_bases {
+            return Err(invalid_data(format!(
+                "Sequence {} has lines of differing length; index it with samtools faidx",
+                name
+            )));
+        }
+        short_line_seen |= (bases.len() as u64) < record.line_bases;
+        record.length += bases.len() as u64;
+    }
+    if let Some((name, record)) = current {
+        sequences.insert(name, record);
+    }
+    Ok(sequences)
+}
+
+// Left-align and trim alleles, fetching bases to the left from `base_at` (1-based)
+fn normalize_alleles(
+    position: u64,
+    reference: &str,
+    alternates: &[String],
+    base_at: impl Fn(u64) -> Option<u8>,
+) -> Option<NormalizedAlleles> {
+    let is_sequence = |allele: &str| {
+        !allele.is_empty()
+            && allele
+                .bytes()
+                .all(|base| matches!(base.to_ascii_uppercase(), b'A' | b'C' | b'G' | b'T' | b'N'))
+    };
+    if !is_sequence(reference)
+        || alternates.is_empty()
+        || !alternates.iter().all(|a| is_sequence(a))
+    {
+        return None;
+    }
+
+    let mut alleles: Vec<Vec<u8>> = std::iter::once(reference)
+        .chain(alternates.iter().map(String::as_str))
+        .map(|allele| allele.to_ascii_uppercase().into_bytes())
+        .collect();
+    let mut start = position;
+
+    while let Some(last) = alleles[0].last().copied() {
+        if !alleles.iter().all(|allele| allele.last() == Some(&last)) {
+            break;
+        }
+        for allele in &mut alleles {
+            allele.pop();
+        }
+        if alleles.iter().any(Vec::is_empty) {
+            let Some(base) = (start > 1).then(|| base_at(start - 1)).flatten() else {
+                // Nothing to extend with at the start of the sequence: undo the trim
+                for allele in &mut alleles {
+                    allele.push(last);
+                }
+                break;
+            };
+            start -= 1;
+            for allele in &mut alleles {
+                allele.insert(0, base);
+            }
+        }
+    }
+
+    while alleles.iter().all(|allele| allele.len() > 1)
+        && alleles.iter().all(|allele| allele[0] == alleles[0][0])
+    {
+        for allele in &mut alleles {
+            allele.remove(0);
+        }
+        start += 1;
+    }
+
+    let mut alleles = alleles
+        .into_iter()
+        .map(|allele| String::from_utf8(allele).unwrap_or_default());
+    let normalized_reference = alleles.next().unwrap_or_default();
+    let normalized_alternates: Vec<String> = alleles.collect();
+    let changed = start != position
+        || !normalized_reference.eq_ignore_ascii_case(reference)
+        || normalized_alternates
+            .iter()
+            .zip(alternates)
+            .any(|(normalized, original)| !normalized.eq_ignore_ascii_case(original));
+    Some(NormalizedAlleles {
+        position: start,
+        reference: normalized_reference,
+        alternate: normalized_alternates,
+        changed,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // chr1: position 1 is the first base
+    const SEQUENCE: &[u8] = b"GCACACACAT";
+
+    fn normalize(position: u64, reference: &str, alternates: &[&str]) -> NormalizedAlleles {
+        let alternates: Vec<String> = alternates.iter().map(|a| a.to_string()).collect();
+        normalize_alleles(position, reference, &alternates, |position| {
+            SEQUENCE.get(position as usize - 1).copied()
+        })
+        .expect("sequence alleles")
+    }
+
+    #[test]
+    fn test_deletion_in_repeat_is_left_aligned() {
+        // Deleting the last "CA" of the repeat is the same as deleting the first
+        let normalized = normalize(7, "ACA", &["A"]);
+        assert_eq!(normalized.position, 1);
+        assert_eq!(normalized.reference, "GCA");
+        assert_eq!(normalized.alternate, vec!["G"]);
+        assert!(normalized.changed);
+
+        // Already left-aligned
+        let normalized = normalize(1, "GCA", &["G"]);
+        assert_eq!((normalized.position, normalized.changed), (1, false));
+    }
+
+    #[test]
+    fn test_shared_bases_are_trimmed() {
+        let normalized = normalize(9, "AT", &["GT"]);
+        assert_eq!(normalized.position, 9);
+        assert_eq!(normalized.reference, "A");
+        assert_eq!(normalized.alternate, vec!["G"]);
+
+        // Insertion written with extra context on both sides
+        let normalized = normalize(2, "CAC", &["CACAC"]);
+        assert_eq!(normalized.position, 1);
+        assert_eq!(normalized.reference, "G");
+        assert_eq!(normalized.alternate, vec!["GCA"]);
+    }
+
+    #[test]
+    fn test_symbolic_alleles_are_not_normalized() {
+        let alternates = vec!["<DEL>".to_string()];
+        assert!(normalize_alleles(5, "C", &alternates, |_| Some(b'A')).is_none());
+        let alternates = vec!["*".to_string()];
+        assert!(normalize_alleles(5, "C", &alternates, |_| Some(b'A')).is_none());
+    }
+
+    #[test]
+    fn test_reference_fasta_lookup() {
+        let dir = tempfile::TempDir::new().expect("temp dir");
+        let path = dir.path().join("ref.fa");
+        std::fs::write(&path, b">chr1 test\nGCACA\nCACAT\n>chr2\nTT\n").unwrap();
+        let reference = ReferenceFasta::open(&path).expect("open FASTA");
+
+        assert_eq!(reference.sequence_name("1").as_deref(), Some("chr1"));
+        assert_eq!(reference.base("chr1", 6), Some(b'C'));
+        assert_eq!(reference.base("chr1", 10), Some(b'T'));
+        assert_eq!(reference.base("chr1", 11), None);
+        assert_eq!(reference.base("chr2", 1), Some(b'T'));
+
+        let normalized = reference
+            .normalize("1", 7, "ACA", &["A".to_string()])
+            .expect("normalized");
+        assert_eq!(
+            (normalized.position, normalized.reference.as_str()),
+            (1, "GCA")
+        );
+        // REF disagrees with the FASTA
+        assert!(reference
+            .normalize("1", 7, "GCA", &["G".to_string()])
+            .is_none());
+    }
+}
@@ -0,0 +1,556 @@
+// Query recording and replay for regression testing.
+//
+// With --record-queries every tool call is appended to a JSON Lines file together with a
+// digest of its response (and with --record-responses the response itself). `replay` re-runs
+// the recorded calls against a VCF file (and optionally a different server binary) and reports
+// every call whose response changed, with the differences when the response was recorded.
+//
+// The file starts with a header line holding the server options that shape responses
+// (--reference, --annotation, --allow-write-dir, ...), which replay passes on to the server it
+// starts. Streaming session IDs are random, so they are excluded from digests and the recorded
+// IDs are mapped to the ones issued during replay. sample_variants calls without a seed are
+// recorded with the seed their response echoes, so they draw the same sample again.
+
+use rmcp::model::{CallToolRequestParam, CallToolResult, JsonObject};
+use rmcp::ErrorData as McpError;
+use std::collections::{BTreeSet, HashMap};
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+use std::sync::Mutex;
+use tracing::{debug, warn};
+
+use crate::id_index::fnv1a;
+use crate::supervisor::{spawn_worker, WorkerOptions};
+
+// First line of a recording file
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct RecordingHeader {
+    // Command line options of the recording server that change responses, passed on to the
+    // server replaying the calls
+    pub server_args: Vec<String>,
+}
+
+// One line of a recording file after the header
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct RecordedCall {
+    pub tool: String,
+    #[serde(default)]
+    pub arguments: Option<JsonObject>,
+    pub digest: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session_id: Option<String>,
+    // The response the digest was taken of, with --record-responses
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub response: Option<serde_json::Value>,
+}
+
+impl RecordedCall {
+    fn new(
+        request: &CallToolRequestParam,
+        result: &Result<CallToolResult, McpError>,
+        keep_response: bool,
+    ) -> Self {
+        let (digest, session_id, response) = digest_result(result);
+        let mut arguments = request.arguments.clone();
+        if request.name == "sample_variants" {
+            record_sample_seed(&mut arguments, &response);
+        }
+        RecordedCall {
+            tool: request.name.to_string(),
+            arguments,
+            digest,
+            session_id,
+            response: keep_response.then_some(response),
+        }
+    }
+}
+
+// Add the seed a sample_variants response echoes to arguments that chose none, so replay draws
+// the same sample
+fn record_sample_seed(arguments: &mut Option<JsonObject>, response: &serde_json::Value) {
+    let Some(seed) = response
+        .pointer("/content/0/query/seed")
+        .filter(|seed| seed.is_u64())
+    else {
+        return;
+    };
+    let arguments = arguments.get_or_insert_with(JsonObject::new);
+    if arguments.get("seed").is_none_or(serde_json::Value::is_null) {
+        arguments.insert("seed".to_string(), seed.clone());
+    }
+}
+
+// Appends tool calls to the --record-queries file
+pub struct QueryRecorder {
+    file: Mutex<std::fs::File>,
+    keep_responses: bool,
+}
+
+impl QueryRecorder {
+    // Start a recording, or continue one made with the same server options
+    pub fn create(
+        path: &Path,
+        header: &RecordingHeader,
+        keep_responses: bool,
+    ) -> std::io::Result<Self> {
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)?;
+        if file.metadata()?.len() == 0 {
+            let mut line = serde_json::to_string(header).map_err(std::io::Error::other)?;
+            line.push('\n');
+            file.write_all(line.as_bytes())?;
+        } else {
+            let recorded = load_recording(path)?.header;
+            if recorded != *header {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    format!(
+                        "{} was recorded with other server options ({}); record to a new file",
+                        path.display(),
+                        recorded.server_args.join(" ")
+                    ),
+                ));
+            }
+        }
+        Ok(QueryRecorder {
+            file: Mutex::new(file),
+            keep_responses,
+        })
+    }
+
+    pub fn record(
+        &self,
+        request: &CallToolRequestParam,
+        result: &Result<CallToolResult, McpError>,
+    ) {
+        let call = RecordedCall::new(request, result, self.keep_responses);
+        let Ok(mut line) = serde_json::to_string(&call) else {
+            return;
+        };
+        line.push('\n');
+
+        // Recording must never fail the tool call itself
+        let mut file = self.file.lock().unwrap();
+        if let Err(e) = file.write_all(line.as_bytes()) {
+            warn!("Failed to record query: {}", e);
+        }
+    }
+}
+
+// Digest of a tool response with session IDs removed, plus the session ID if there was one and
+// the response the digest was taken of
+fn digest_result(
+    result: &Result<CallToolResult, McpError>,
+) -> (String, Option<String>, serde_json::Value) {
+    let mut value = match result {
+        Ok(result) => {
+            let content: Vec<serde_json::Value> = result
+                .content
+                .iter()
+                .map(|content| match content.as_text() {
+                    // JSON payloads are re-parsed so session IDs can be stripped
+                    Some(text) => serde_json::from_str(&text.text)
+                        .unwrap_or_else(|_| serde_json::Value::String(text.text.clone())),
+                    None => serde_json::to_value(content).unwrap_or_default(),
+                })
+                .collect();
+            serde_json::json!({
+                "is_error": result.is_error.unwrap_or(false),
+                "content": content,
+            })
+        }
+        Err(e) => serde_json::json!({
+            "error_code": e.code.0,
+            "error": e.message,
+        }),
+    };
+
+    let session_id = take_session_ids(&mut value);
+    let digest = format!("{:016x}", fnv1a(value.to_string().as_bytes()));
+    (digest, session_id, value)
+}
+
+// Remove every "session_id" field, returning the first non-null one
+fn take_session_ids(value: &mut serde_json::Value) -> Option<String> {
+    match value {
+        serde_json::Value::Object(map) => {
+            let mut found = map
+                .remove("session_id")
+                .and_then(|id| id.as_str().map(str::to_string));
+            for child in map.values_mut() {
+                let child_id = take_session_ids(child);
+                found = found.or(child_id);
+            }
+            found
+        }
+        serde_json::Value::Array(items) => items.iter_mut().fold(None, |found, item| {
+            let item_id = take_session_ids(item);
+            found.or(item_id)
+        }),
+        _ => None,
+    }
+}
+
+// A recording file: its header (empty for files written before headers were added) and calls
+#[derive(Debug, Default)]
+pub struct Recording {
+    pub header: RecordingHeader,
+    pub calls: Vec<RecordedCall>,
+}
+
+pub fn load_recording(path: &Path) -> std::io::Result<Recording> {
+    let reader = BufReader::new(std::fs::File::open(path)?);
+    let mut recording = Recording::default();
+    for (line_number, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let invalid = |e: serde_json::Error| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("{}:{}: {}", path.display(), line_number + 1, e),
+            )
+        };
+        let value: serde_json::Value = serde_json::from_str(&line).map_err(invalid)?;
+        if line_number == 0 && value.get("server_args").is_some() {
+            recording.header = serde_json::from_value(value).map_err(invalid)?;
+        } else {
+            recording
+                .calls
+                .push(serde_json::from_value(value).map_err(invalid)?);
+        }
+    }
+    Ok(recording)
+}
+
+// Differences listed per changed call
+const MAX_DIFFERENCES: usize = 20;
+
+// A value that differs between the recorded and the replayed response; None where one of
+// them has no value at the path
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct Difference {
+    // JSON Pointer into the response ("" for the whole response)
+    pub path: String,
+    pub recorded: Option<serde_json::Value>,
+    pub replayed: Option<serde_json::Value>,
+}
+
+// The values that differ between two responses, at most MAX_DIFFERENCES of them
+pub fn diff_responses(
+    recorded: &serde_json::Value,
+    replayed: &serde_json::Value,
+) -> Vec<Difference> {
+    let mut differences = Vec::new();
+    diff_values(
+        String::new(),
+        Some(recorded),
+        Some(replayed),
+        &mut differences,
+    );
+    differences
+}
+
+fn diff_values(
+    path: String,
+    recorded: Option<&serde_json::Value>,
+    replayed: Option<&serde_json::Value>,
+    differences: &mut Vec<Difference>,
+) {
+    use serde_json::Value;
+
+    if differences.len() >= MAX_DIFFERENCES || recorded == replayed {
+        return;
+    }
+    // JSON Pointer escaping of a key
+    let child = |key: &str| format!("{}/{}", path, key.replace('~', "~0").replace('/', "~1"));
+    match (recorded, replayed) {
+        (Some(Value::Object(recorded)), Some(Value::Object(replayed))) => {
+            let keys: BTreeSet<&String> = recorded.keys().chain(replayed.keys()).collect();
+            for key in keys {
+                diff_values(
+                    child(key),
+                    recorded.get(key),
+                    replayed.get(key),
+                    differences,
+                );
+            }
+        }
+        (Some(Value::Array(recorded)), Some(Value::Array(replayed))) => {
+            for i in 0..recorded.len().max(replayed.len()) {
+                diff_values(
+                    child(&i.to_string()),
+                    recorded.get(i),
+                    replayed.get(i),
+                    differences,
+                );
+            }
+        }
+        _ => differences.push(Difference {
+            path,
+            recorded: recorded.cloned(),
+            replayed: replayed.cloned(),
+        }),
+    }
+}
+
+// A recorded call whose response changed
+#[derive(Debug)]
+pub struct Mismatch {
+    // 1-based position in the recording
+    pub number: usize,
+    pub call: RecordedCall,
+    // Digest observed during replay
+    pub digest: String,
+    // What changed, when the recording holds the response
+    pub differences: Option<Vec<Difference>>,
+}
+
+// Outcome of replaying a recording
+#[derive(Debug, Default)]
+pub struct ReplayReport {
+    pub total: usize,
+    pub mismatches: Vec<Mismatch>,
+}
+
+// Re-run recorded calls against `vcf_path`, served by `binary` (this binary when None)
+pub async fn replay(
+    recording: &Path,
+    vcf_path: &Path,
+    binary: Option<&Path>,
+    debug: bool,
+) -> std::io::Result<ReplayReport> {
+    let Recording { header, calls } = load_recording(recording)?;
+    // Replayed ID lookups must not race a background index build
+    let options = WorkerOptions {
+        debug,
+        wait_for_index: true,
+        extra_args: header.server_args,
+        ..Default::default()
+    };
+    let (client, _pid) = spawn_worker(binary, vcf_path, &options).await?;
+
+    let mut report = ReplayReport {
+        total: calls.len(),
+        ..Default::default()
+    };
+    // Recorded session ID -> session ID issued during this replay
+    let mut sessions: HashMap<String, String> = HashMap::new();
+
+    for (i, call) in calls.into_iter().enumerate() {
+        let mut arguments = call.arguments.clone();
+        if let Some(arguments) = arguments.as_mut() {
+            let replayed_session = arguments
+                .get("session_id")
+                .and_then(|id| id.as_str())
+                .and_then(|id| sessions.get(id));
+            if let Some(session_id) = replayed_session.cloned() {
+                arguments.insert("session_id".to_string(), session_id.into());
+            }
+        }
+
+        let request = CallToolRequestParam {
+            name: call.tool.clone().into(),
+            arguments,
+        };
+        let result = match client.call_tool(request.clone()).await {
+            Ok(result) => Ok(result),
+            Err(rmcp::ServiceError::McpError(e)) => Err(e),
+            Err(e) => return Err(std::io::Error::other(e.to_string())),
+        };
+
+        let replayed = RecordedCall::new(&request, &result, call.response.is_some());
+        if let (Some(recorded), Some(current)) = (&call.session_id, &replayed.session_id) {
+            sessions.insert(recorded.clone(), current.clone());
+        }
+        debug!(
+            "Replay #{} {}: recorded {}, replayed {}",
+            i + 1,
+            call.tool,
+            call.digest,
+            replayed.digest
+        );
+        if replayed.digest != call.digest {
+            let differences = call
+                .response
+                .as_ref()
+                .zip(replayed.response.as_ref())
+                .map(|(recorded, replayed)| diff_responses(recorded, replayed));
+            report.mismatches.push(Mismatch {
+                number: i + 1,
+                call,
+                digest: replayed.digest,
+                differences,
+            });
+        }
+    }
+
+    let _ = client.cancel().await;
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rmcp::model::Content;
+
+    fn json_result(value: serde_json::Value) -> Result<CallToolResult, McpError> {
+        Ok(CallToolResult::success(vec![
+            Content::json(value).expect("json content")
+        ]))
+    }
+
+    #[test]
+    fn test_digest_ignores_session_ids() {
+        let (first, first_session, _) = digest_result(&json_result(serde_json::json!({
+            "status": "ok",
+            "session_id": "aaaa",
+            "variant": {"position": 14370},
+        })));
+        let (second, second_session, _) = digest_result(&json_result(serde_json::json!({
+            "status": "ok",
+            "session_id": "bbbb",
+            "variant": {"position": 14370},
+        })));
+        assert_eq!(first, second);
+        assert_eq!(first_session.as_deref(), Some("aaaa"));
+        assert_eq!(second_session.as_deref(), Some("bbbb"));
+
+        let (changed, _, _) = digest_result(&json_result(serde_json::json!({
+            "status": "ok",
+            "session_id": "aaaa",
+            "variant": {"position": 17330},
+        })));
+        assert_ne!(first, changed);
+    }
+
+    #[test]
+    fn test_digest_distinguishes_errors() {
+        let (ok, _, _) = digest_result(&json_result(serde_json::json!({"status": "ok"})));
+        let (err, session, _) =
+            digest_result(&Err(McpError::invalid_params("Unknown chromosome", None)));
+        assert_ne!(ok, err);
+        assert!(session.is_none());
+    }
+
+    #[test]
+    fn test_recorder_appends_json_lines() {
+        let dir = tempfile::TempDir::new().expect("temp dir");
+        let path = dir.path().join("queries.jsonl");
+        let header = RecordingHeader {
+            server_args: vec!["--reference".to_string(), "/data/ref.fa".to_string()],
+        };
+        let recorder = QueryRecorder::create(&path, &header, false).expect("create recorder");
+
+        let mut arguments = JsonObject::new();
+        arguments.insert("id".to_string(), "rs6054257".into());
+        let request = CallToolRequestParam {
+            name: "query_by_id".into(),
+            arguments: Some(arguments),
+        };
+        recorder.record(&request, &json_result(serde_json::json!({"status": "ok"})));
+        recorder.record(
+            &request,
+            &json_result(serde_json::json!({"status": "not_found"})),
+        );
+        drop(recorder);
+
+        let recording = load_recording(&path).expect("load recording");
+        assert_eq!(recording.header, header);
+        let calls = recording.calls;
+        assert_eq!(calls.len(), 2);
+        assert_eq!(calls[0].tool, "query_by_id");
+        assert_eq!(calls[0].arguments, request.arguments);
+        assert_ne!(calls[0].digest, calls[1].digest);
+        assert!(calls[0].response.is_none());
+
+        // Appending needs the same server options
+        assert!(QueryRecorder::create(&path, &header, true).is_ok());
+        let err = QueryRecorder::create(&path, &RecordingHeader::default(), false)
+            .err()
+            .expect("other options rejected");
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn test_recording_without_header_loads() {
+        let dir = tempfile::TempDir::new().expect("temp dir");
+        let path = dir.path().join("queries.jsonl");
+        std::fs::write(
+            &path,
+            "{\"tool\":\"list_chromosomes\",\"arguments\":null,\"digest\":\"0123456789abcdef\"}\n",
+        )
+        .expect("write recording");
+
+        let recording = load_recording(&path).expect("load recording");
+        assert!(recording.header.server_args.is_empty());
+        assert_eq!(recording.calls.len(), 1);
+        assert_eq!(recording.calls[0].tool, "list_chromosomes");
+    }
+
+    #[test]
+    fn test_recorded_sample_seed() {
+        let result = json_result(serde_json::json!({
+            "query": {"chromosome": "20", "count": 2, "seed": 42},
+            "variants": [],
+        }));
+        let request = CallToolRequestParam {
+            name: "sample_variants".into(),
+            arguments: Some(JsonObject::new()),
+        };
+        let call = RecordedCall::new(&request, &result, true);
+        let arguments = call.arguments.expect("arguments");
+        assert_eq!(arguments.get("seed"), Some(&serde_json::json!(42)));
+        assert!(call.response.is_some());
+
+        // A seed chosen by the caller is kept
+        let mut arguments = JsonObject::new();
+        arguments.insert("seed".to_string(), 7.into());
+        let request = CallToolRequestParam {
+            name: "sample_variants".into(),
+            arguments: Some(arguments),
+        };
+        let call = RecordedCall::new(&request, &result, false);
+        assert_eq!(
+            call.arguments.expect("arguments").get("seed"),
+            Some(&serde_json::json!(7))
+        );
+        assert!(call.response.is_none());
+    }
+
+    #[test]
+    fn test_diff_responses() {
+        let recorded = serde_json::json!({
+            "is_error": false,
+            "content": [{"count": 2, "variants": [{"position": 100}, {"position": 200}], "a/b": 1}],
+        });
+        let replayed = serde_json::json!({
+            "is_error": false,
+            "content": [{"count": 1, "variants": [{"position": 100}], "note": "x", "a/b": 2}],
+        });
+        let differences = diff_responses(&recorded, &replayed);
+        let paths: Vec<&str> = differences.iter().map(|d| d.path.as_str()).collect();
+        assert_eq!(
+            paths,
+            vec![
+                "/content/0/a~1b",
+                "/content/0/count",
+                "/content/0/note",
+                "/content/0/variants/1"
+            ]
+        );
+        assert_eq!(differences[1].recorded, Some(serde_json::json!(2)));
+        assert_eq!(differences[1].replayed, Some(serde_json::json!(1)));
+        assert_eq!(differences[2].recorded, None);
+        assert_eq!(
+            differences[3].recorded,
+            Some(serde_json::json!({"position": 200}))
+        );
+        assert_eq!(differences[3].replayed, None);
+
+        assert!(diff_responses(&recorded, &recorded).is_empty());
+    }
+}
@@ -0,0 +1,265 @@
+// In-memory cache of recent tool responses.
+//
+// Agents often repeat the same question within a conversation. With --result-cache-ttl the
+// server keeps successful responses keyed by tool name and arguments, for at most the TTL
+// and within a total size budget (least recently used entries are evicted first). Cached
+// responses are marked with "cache_hit": true so callers can tell them apart.
+
+use rmcp::model::{CallToolRequestParam, CallToolResult, Content};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// Tools whose responses depend on more than their arguments, or that write files a cache hit
+// would skip
+const UNCACHEABLE_TOOLS: &[&str] = &[
+    "start_region_query",
+    "get_next_variant",
+    "close_query_session",
+    "reload_dataset",
+    "export_region",
+    "export_parquet",
+];
+
+struct CacheEntry {
+    result: CallToolResult,
+    size: usize,
+    stored_at: Instant,
+    last_used: u64,
+}
+
+#[derive(Default)]
+struct CacheState {
+    entries: HashMap<String, CacheEntry>,
+    size: usize,
+    clock: u64,
+}
+
+pub struct ResultCache {
+    ttl: Duration,
+    max_bytes: usize,
+    state: Mutex<CacheState>,
+}
+
+impl ResultCache {
+    pub fn new(ttl: Duration, max_bytes: usize) -> Self {
+        ResultCache {
+            ttl,
+            max_bytes,
+            state: Mutex::new(CacheState::default()),
+        }
+    }
+
+    // Cache key for a call, or None when the call must always run
+    pub fn key(request: &CallToolRequestParam) -> Option<String> {
+        if UNCACHEABLE_TOOLS.contains(&&*request.name) {
+            return None;
+        }
+        // Without a seed every sample is drawn afresh
+        let arguments = request.arguments.clone().unwrap_or_default();
+        if request.name == "sample_variants" && !arguments.contains_key("seed") {
+            return None;
+        }
+        // Argument maps are sorted by key, so equal arguments serialize identically
+        let arguments = serde_json::to_string(&arguments).ok()?;
+        Some(format!("{}:{}", request.name, arguments))
+    }
+
+    pub fn get(&self, key: &str) -> Option<CallToolResult> {
+        let mut state = self.state.lock().unwrap();
+        let expired = state
+            .entries
+            .get(key)
+            .is_some_and(|entry| entry.stored_at.elapsed() > self.ttl);
+        if expired {
+            if let Some(entry) = state.entries.remove(key) {
+                state.size -= entry.size;
+            }
+            return None;
+        }
+
+        state.clock += 1;
+        let clock = state.clock;
+        let entry = state.entries.get_mut(key)?;
+        entry.last_used = clock;
+        Some(entry.result.clone())
+    }
+
+    // Store a successful response; errors and cancelled scans are never cached
+    pub fn insert(&self, key: String, result: &CallToolResult) {
+        if result.is_error == Some(true) || was_cancelled(result) {
+            return;
+        }
+        let size = key.len() + result_size(result);
+        if size > self.max_bytes {
+            return;
+        }
+
+        let mut state = self.state.lock().unwrap();
+        state.clock += 1;
+        let entry = CacheEntry {
+            result: result.clone(),
+            size,
+            stored_at: Instant::now(),
+            last_used: state.clock,
+        };
+        if let Some(replaced) = state.entries.insert(key, entry) {
+            state.size -= replaced.size;
+        }
+        state.size += size;
+
+        while state.size > self.max_bytes {
+            let Some(oldest) = state
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| key.clone())
+            else {
+                break;
+            };
+            if let Some(evicted) = state.entries.remove(&oldest) {
+                state.size -= evicted.size;
+            }
+        }
+    }
+
+    // Forget every response, e.g. after the VCF was reloaded
+    pub fn clear(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.entries.clear();
+        state.size = 0;
+    }
+}
+
+// Approximate memory held by a response: the length of its text content
+fn result_size(result: &CallToolResult) -> usize {
+    result
+        .content
+        .iter()
+        .map(|content| content.as_text().map_or(0, |text| text.text.len()))
+        .sum()
+}
+
+// Whether a response reports "status": "cancelled", i.e. the scan stopped part way
+fn was_cancelled(result: &CallToolResult) -> bool {
+    result.content.iter().any(|content| {
+        content.as_text().is_some_and(|text| {
+            serde_json::from_str::<serde_json::Value>(&text.text)
+                .is_ok_and(|payload| payload["status"] == "cancelled")
+        })
+    })
+}
+
+// Add "cache_hit": true to the JSON payloads of a response served from the cache
+pub fn mark_cache_hit(result: &mut CallToolResult) {
+    for content in &mut result.content {
+        let Some(text) = content.as_text() else {
+            continue;
+        };
+        let Ok(serde_json::Value::Object(mut payload)) = serde_json::from_str(&text.text) else {
+            continue;
+        };
+        payload.insert("cache_hit".to_string(), serde_json::Value::Bool(true));
+        if let Ok(marked) = Content::json(serde_json::Value::Object(payload)) {
+            *content = marked;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rmcp::model::JsonObject;
+
+    fn request(tool: &str, arguments: serde_json::Value) -> CallToolRequestParam {
+        let arguments: JsonObject = serde_json::from_value(arguments).unwrap();
+        CallToolRequestParam {
+            name: tool.to_string().into(),
+            arguments: Some(arguments),
+        }
+    }
+
+    fn json_result(value: serde_json::Value) -> CallToolResult {
+        CallToolResult::success(vec![Content::json(value).unwrap()])
+    }
+
+    #[test]
+    fn test_key_ignores_argument_order_and_skips_stateful_tools() {
+        let a = request(
+            "query_by_position",
+            serde_json::json!({"chromosome": "20", "position": 14370}),
+        );
+        let b = request(
+            "query_by_position",
+            serde_json::json!({"position": 14370, "chromosome": "20"}),
+        );
+        assert_eq!(ResultCache::key(&a), ResultCache::key(&b));
+
+        let other = request(
+            "query_by_position",
+            serde_json::json!({"chromosome": "20", "position": 17330}),
+        );
+        assert_ne!(ResultCache::key(&a), ResultCache::key(&other));
+
+        let stream = request("get_next_variant", serde_json::json!({"session_id": "x"}));
+        assert!(ResultCache::key(&stream).is_none());
+        let unseeded = request("sample_variants", serde_json::json!({"chromosome": "20"}));
+        assert!(ResultCache::key(&unseeded).is_none());
+        let export = request(
+            "export_region",
+            serde_json::json!({"chromosome": "20", "start": 1, "end": 20000}),
+        );
+        assert!(ResultCache::key(&export).is_none());
+        let parquet = request("export_parquet", serde_json::json!({}));
+        assert!(ResultCache::key(&parquet).is_none());
+    }
+
+    #[test]
+    fn test_entries_expire_after_ttl() {
+        let cache = ResultCache::new(Duration::from_millis(50), 1 << 20);
+        cache.insert(
+            "k".to_string(),
+            &json_result(serde_json::json!({"status": "ok"})),
+        );
+        assert!(cache.get("k").is_some());
+
+        std::thread::sleep(Duration::from_millis(80));
+        assert!(cache.get("k").is_none());
+    }
+
+    #[test]
+    fn test_size_limit_evicts_least_recently_used() {
+        let payload = json_result(serde_json::json!({"data": "x".repeat(100)}));
+        let entry_size = "a".len() + result_size(&payload);
+        let cache = ResultCache::new(Duration::from_secs(60), entry_size * 2);
+
+        cache.insert("a".to_string(), &payload);
+        cache.insert("b".to_string(), &payload);
+        assert!(cache.get("a").is_some());
+        cache.insert("c".to_string(), &payload);
+
+        assert!(cache.get("b").is_none());
+        assert!(cache.get("a").is_some());
+        assert!(cache.get("c").is_some());
+    }
+
+    #[test]
+    fn test_cancelled_responses_are_not_cached() {
+        let cache = ResultCache::new(Duration::from_secs(60), 1 << 20);
+        cache.insert(
+            "k".to_string(),
+            &json_result(serde_json::json!({"status": "cancelled"})),
+        );
+        assert!(cache.get("k").is_none());
+    }
+
+    #[test]
+    fn test_mark_cache_hit() {
+        let mut result = json_result(serde_json::json!({"status": "ok"}));
+        mark_cache_hit(&mut result);
+        let text = &result.content[0].as_text().unwrap().text;
+        let payload: serde_json::Value = serde_json::from_str(text).unwrap();
+        assert_eq!(payload["cache_hit"], true);
+        assert_eq!(payload["status"], "ok");
+    }
+}
@@ -0,0 +1,141 @@
+// Graceful shutdown on SIGINT or SIGTERM: the transport stops taking new requests, tool calls
+// already running get --drain-timeout seconds to finish, and a background ID index build is
+// stopped cleanly (its temp file removed) before the process exits. A second signal exits at
+// once.
+//
+// Requests arriving on open connections while draining are refused with an error, so clients
+// can retry elsewhere instead of waiting on a server that is going away.
+
+use rmcp::ErrorData as McpError;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::Duration;
+use tokio::sync::Notify;
+use tokio_util::sync::CancellationToken;
+use tracing::{info, warn};
+
+// Exit status after a second signal, as for a process killed by SIGINT
+const FORCED_EXIT_CODE: i32 = 130;
+
+// Resolves on Ctrl-C, or on SIGTERM where there is one
+async fn signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                tokio::select! {
+                    _ = tokio::signal::ctrl_c() => {}
+                    _ = terminate.recv() => {}
+                }
+            }
+            Err(_) => {
+                let _ = tokio::signal::ctrl_c().await;
+            }
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = tokio::signal::ctrl_c().await;
+    }
+}
+
+// A token cancelled by the first shutdown signal; the second one exits immediately
+pub fn listen() -> CancellationToken {
+    let token = CancellationToken::new();
+    let stopping = token.clone();
+    tokio::spawn(async move {
+        signal().await;
+        info!("Shutting down: finishing running tool calls (signal again to exit now)");
+        stopping.cancel();
+        signal().await;
+        warn!("Exiting without waiting for running tool calls");
+        std::process::exit(FORCED_EXIT_CODE);
+    });
+    token
+}
+
+// The error a tool call gets once draining has begun
+pub fn shutting_down_error() -> McpError {
+    McpError::internal_error(
+        "Server is shutting down; retry the call once it is back",
+        None,
+    )
+}
+
+// Tool calls in flight, and whether new ones are still accepted
+#[derive(Default)]
+pub struct Drain {
+    draining: AtomicBool,
+    in_flight: AtomicUsize,
+    idle: Notify,
+}
+
+// Held for the duration of a tool call
+pub struct InFlight<'a>(&'a Drain);
+
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        if self.0.in_flight.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.0.idle.notify_waiters();
+        }
+    }
+}
+
+impl Drain {
+    // Register a tool call; None once draining has begun
+    pub fn enter(&self) -> Option<InFlight<'_>> {
+        // Counted before the check, so drain() can't miss a call that got in
+        self.in_flight.fetch_add(1, Ordering::SeqCst);
+        let call = InFlight(self);
+        (!self.draining.load(Ordering::SeqCst)).then_some(call)
+    }
+
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.load(Ordering::SeqCst)
+    }
+
+    // Refuse new calls and wait for the running ones; false if some are still running after
+    // `timeout`
+    pub async fn drain(&self, timeout: Duration) -> bool {
+        self.draining.store(true, Ordering::SeqCst);
+        let deadline = tokio::time::Instant::now() + timeout;
+        loop {
+            let idle = self.idle.notified();
+            tokio::pin!(idle);
+            idle.as_mut().enable();
+            if self.in_flight() == 0 {
+                return true;
+            }
+            if tokio::time::timeout_at(deadline, idle).await.is_err() {
+                return self.in_flight() == 0;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn test_drain_waits_for_running_calls() {
+        let drain = Arc::new(Drain::default());
+        let call = drain.enter().expect("accepting calls");
+        assert_eq!(drain.in_flight(), 1);
+
+        // Times out while the call runs, and refuses new ones from then on
+        assert!(!drain.drain(Duration::from_millis(10)).await);
+        assert!(drain.enter().is_none());
+        assert_eq!(drain.in_flight(), 1);
+
+        let waiting = {
+            let drain = Arc::clone(&drain);
+            tokio::spawn(async move { drain.drain(Duration::from_secs(10)).await })
+        };
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        drop(call);
+        assert!(waiting.await.unwrap());
+        assert_eq!(drain.in_flight(), 0);
+    }
+}
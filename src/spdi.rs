@@ -0,0 +1,345 @@
+// SPDI variant expressions (sequence:position:deletion:insertion) for query_by_spdi and the
+// spdi field of returned variants.
+//
+// The position is 0-based and counts the bases before the deletion, so the VCF SNV
+// 20:14370 G>A on GRCh38 is NC_000020.11:14369:G:A. The deletion is a sequence or, in the
+// short form, a number of bases. SPDI alleles carry no anchor base: a VCF allele is written
+// without the bases it shares with REF, first at the end and then at the start (the order
+// dbSNP trims in), so the insertion GTC>GTCT at 1234567 is 20:1234569::T. The sequence is the
+// chromosome's RefSeq accession on the file's build, from the contig alias table below, or
+// the VCF's chromosome name for other builds and contigs.
+
+use crate::build_check::Build;
+
+// The RefSeq accessions of a human chromosome on GRCh37 and GRCh38
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ContigAlias {
+    // Without a "chr" prefix
+    pub chromosome: &'static str,
+    pub grch37: &'static str,
+    pub grch38: &'static str,
+}
+
+const fn alias(
+    chromosome: &'static str,
+    grch37: &'static str,
+    grch38: &'static str,
+) -> ContigAlias {
+    ContigAlias {
+        chromosome,
+        grch37,
+        grch38,
+    }
+}
+
+pub const CONTIG_ALIASES: &[ContigAlias] = &[
+    alias("1", "NC_000001.10", "NC_000001.11"),
+    alias("2", "NC_000002.11", "NC_000002.12"),
+    alias("3", "NC_000003.11", "NC_000003.12"),
+    alias("4", "NC_000004.11", "NC_000004.12"),
+    alias("5", "NC_000005.9", "NC_000005.10"),
+    alias("6", "NC_000006.11", "NC_000006.12"),
+    alias("7", "NC_000007.13", "NC_000007.14"),
+    alias("8", "NC_000008.10", "NC_000008.11"),
+    alias("9", "NC_000009.11", "NC_000009.12"),
+    alias("10", "NC_000010.10", "NC_000010.11"),
+    alias("11", "NC_000011.9", "NC_000011.10"),
+    alias("12", "NC_000012.11", "NC_000012.12"),
+    alias("13", "NC_000013.10", "NC_000013.11"),
+    alias("14", "NC_000014.8", "NC_000014.9"),
+    alias("15", "NC_000015.9", "NC_000015.10"),
+    alias("16", "NC_000016.9", "NC_000016.10"),
+    alias("17", "NC_000017.10", "NC_000017.11"),
+    alias("18", "NC_000018.9", "NC_000018.10"),
+    alias("19", "NC_000019.9", "NC_000019.10"),
+    alias("20", "NC_000020.10", "NC_000020.11"),
+    alias("21", "NC_000021.8", "NC_000021.9"),
+    alias("22", "NC_000022.10", "NC_000022.11"),
+    alias("X", "NC_000023.10", "NC_000023.11"),
+    alias("Y", "NC_000024.9", "NC_000024.10"),
+    // The rCRS mitochondrial sequence, shared by both builds
+    alias("MT", "NC_012920.1", "NC_012920.1"),
+];
+
+pub fn build_name(build: Build) -> &'static str {
+    match build {
+        Build::Grch37 => "GRCh37",
+        Build::Grch38 => "GRCh38",
+    }
+}
+
+// The RefSeq accession of a VCF chromosome name ("20", "chr20", "chrM") on `build`
+pub fn accession(chromosome: &str, build: Build) -> Option<&'static str> {
+    let name = chromosome.strip_prefix("chr").unwrap_or(chromosome);
+    let name = if name == "M" { "MT" } else { name };
+    let alias = CONTIG_ALIASES
+        .iter()
+        .find(|alias| alias.chromosome.eq_ignore_ascii_case(name))?;
+    Some(match build {
+        Build::Grch37 => alias.grch37,
+        Build::Grch38 => alias.grch38,
+    })
+}
+
+// The chromosome (without "chr") an accession names, with its build when the version
+// identifies one; an accession without a version matches the chromosome on either build
+pub fn accession_chromosome(accession: &str) -> Option<(&'static str, Option<Build>)> {
+    let unversioned = |versioned: &str| versioned.split('.').next() == Some(accession);
+    CONTIG_ALIASES.iter().find_map(|alias| {
+        let grch37 = alias.grch37 == accession;
+        let grch38 = alias.grch38 == accession;
+        let build = match (grch37, grch38) {
+            (true, false) => Some(Build::Grch37),
+            (false, true) => Some(Build::Grch38),
+            (true, true) => None,
+            (false, false) if unversioned(alias.grch38) => None,
+            (false, false) => return None,
+        };
+        Some((alias.chromosome, build))
+    })
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Deletion {
+    Sequence(String),
+    // The short form, which gives only how many bases are deleted
+    Length(u64),
+}
+
+impl Deletion {
+    pub fn length(&self) -> u64 {
+        match self {
+            Deletion::Sequence(bases) => bases.len() as u64,
+            Deletion::Length(length) => *length,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Spdi {
+    pub sequence: String,
+    // 0-based: the number of bases before the deletion
+    pub position: u64,
+    pub deletion: Deletion,
+    pub insertion: String,
+}
+
+impl std::fmt::Display for Spdi {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}:", self.sequence, self.position)?;
+        match &self.deletion {
+            Deletion::Sequence(bases) => write!(f, "{}", bases)?,
+            Deletion::Length(length) => write!(f, "{}", length)?,
+        }
+        write!(f, ":{}", self.insertion)
+    }
+}
+
+fn is_bases(text: &str) -> bool {
+    text.bytes().all(|b| b.is_ascii_alphabetic())
+}
+
+// The 0-based position, deletion and insertion of REF and ALT at 1-based `position`, without
+// the bases they share at the end and then at the start
+fn trim<'a>(position: u64, reference: &'a str, alternate: &'a str) -> (u64, &'a str, &'a str) {
+    let (mut reference, mut alternate) = (reference.as_bytes(), alternate.as_bytes());
+    while let (Some(r), Some(a)) = (reference.last(), alternate.last()) {
+        if !r.eq_ignore_ascii_case(a) {
+            break;
+        }
+        reference = &reference[..reference.len() - 1];
+        alternate = &alternate[..alternate.len() - 1];
+    }
+    let mut start = position.saturating_sub(1);
+    while let (Some(r), Some(a)) = (reference.first(), alternate.first()) {
+        if !r.eq_ignore_ascii_case(a) {
+            break;
+        }
+        reference = &reference[1..];
+        alternate = &alternate[1..];
+        start += 1;
+    }
+    // Only whole ASCII bases were removed, so both are still valid UTF-8
+    (
+        start,
+        std::str::from_utf8(reference).unwrap_or_default(),
+        std::str::from_utf8(alternate).unwrap_or_default(),
+    )
+}
+
+impl Spdi {
+    pub fn parse(text: &str) -> Result<Spdi, String> {
+        let parts: Vec<&str> = text.trim().split(':').collect();
+        let [sequence, position, deletion, insertion] = parts[..] else {
+            return Err(format!(
+                "'{}' is not an SPDI expression; expected sequence:position:deletion:insertion, e.g. NC_000020.11:14369:G:A",
+                text
+            ));
+        };
+        if sequence.is_empty() {
+            return Err(format!("SPDI '{}' has no sequence", text));
+        }
+        let position = position.parse::<u64>().map_err(|_| {
+            format!(
+                "SPDI '{}': position '{}' is not a non-negative integer (SPDI positions are 0-based)",
+                text, position
+            )
+        })?;
+        let deletion = if !deletion.is_empty() && deletion.bytes().all(|b| b.is_ascii_digit()) {
+            Deletion::Length(deletion.parse().map_err(|_| {
+                format!(
+                    "SPDI '{}': deletion length '{}' is too large",
+                    text, deletion
+                )
+            })?)
+        } else if is_bases(deletion) {
+            Deletion::Sequence(deletion.to_ascii_uppercase())
+        } else {
+            return Err(format!(
+                "SPDI '{}': deletion '{}' is neither bases nor a length",
+                text, deletion
+            ));
+        };
+        if !is_bases(insertion) {
+            return Err(format!(
+                "SPDI '{}': insertion '{}' is not a sequence of bases",
+                text, insertion
+            ));
+        }
+        Ok(Spdi {
+            sequence: sequence.to_string(),
+            position,
+            deletion,
+            insertion: insertion.to_ascii_uppercase(),
+        })
+    }
+
+    // The SPDI of ALT allele `alternate` of a VCF record at 1-based `position`; None for
+    // symbolic alleles, breakends and missing or spanning-deletion ALTs
+    pub fn from_vcf(
+        sequence: &str,
+        position: u64,
+        reference: &str,
+        alternate: &str,
+    ) -> Option<Spdi> {
+        if alternate.is_empty() || !is_bases(reference) || !is_bases(alternate) {
+            return None;
+        }
+        let (start, deleted, inserted) = trim(position, reference, alternate);
+        Some(Spdi {
+            sequence: sequence.to_string(),
+            position: start,
+            deletion: Deletion::Sequence(deleted.to_ascii_uppercase()),
+            insertion: inserted.to_ascii_uppercase(),
+        })
+    }
+
+    // Without the bases the deletion and insertion share, as from_vcf gives variants; the
+    // short form is kept as it is
+    pub fn trimmed(&self) -> Spdi {
+        match &self.deletion {
+            Deletion::Length(_) => self.clone(),
+            Deletion::Sequence(deleted) => {
+                let (start, deleted, inserted) = trim(self.position + 1, deleted, &self.insertion);
+                Spdi {
+                    sequence: self.sequence.clone(),
+                    position: start,
+                    deletion: Deletion::Sequence(deleted.to_string()),
+                    insertion: inserted.to_string(),
+                }
+            }
+        }
+    }
+
+    // Whether `variant` (a trimmed SPDI, as from_vcf gives) is the same change, ignoring the
+    // sequence names; a short-form deletion matches any deleted bases of its length
+    pub fn matches(&self, variant: &Spdi) -> bool {
+        let query = self.trimmed();
+        let deletion = match (&query.deletion, &variant.deletion) {
+            (Deletion::Sequence(a), Deletion::Sequence(b)) => a.eq_ignore_ascii_case(b),
+            (deletion, other) => deletion.length() == other.length(),
+        };
+        query.position == variant.position
+            && deletion
+            && query.insertion.eq_ignore_ascii_case(&variant.insertion)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_contig_aliases() {
+        assert_eq!(accession("chr20", Build::Grch38), Some("NC_000020.11"));
+        assert_eq!(accession("20", Build::Grch37), Some("NC_000020.10"));
+        assert_eq!(accession("chrM", Build::Grch38), Some("NC_012920.1"));
+        assert_eq!(accession("chrUn_gl000220", Build::Grch38), None);
+
+        assert_eq!(
+            accession_chromosome("NC_000020.11"),
+            Some(("20", Some(Build::Grch38)))
+        );
+        assert_eq!(
+            accession_chromosome("NC_000023.10"),
+            Some(("X", Some(Build::Grch37)))
+        );
+        assert_eq!(accession_chromosome("NC_000020"), Some(("20", None)));
+        assert_eq!(accession_chromosome("NC_012920.1"), Some(("MT", None)));
+        assert_eq!(accession_chromosome("NC_000020.9"), None);
+        assert_eq!(accession_chromosome("NM_000546.6"), None);
+    }
+
+    #[test]
+    fn test_parse_spdi() {
+        let spdi = Spdi::parse("NC_000020.11:14369:g:A").unwrap();
+        assert_eq!(spdi.position, 14369);
+        assert_eq!(spdi.deletion, Deletion::Sequence("G".to_string()));
+        assert_eq!(spdi.to_string(), "NC_000020.11:14369:G:A");
+
+        let spdi = Spdi::parse("NC_000020.11:1234567:2:").unwrap();
+        assert_eq!(spdi.deletion, Deletion::Length(2));
+        assert_eq!(spdi.insertion, "");
+        assert_eq!(spdi.to_string(), "NC_000020.11:1234567:2:");
+
+        assert!(Spdi::parse("NC_000020.11:14369:G").is_err());
+        assert!(Spdi::parse(":14369:G:A").is_err());
+        assert!(Spdi::parse("NC_000020.11:-1:G:A").is_err());
+        assert!(Spdi::parse("NC_000020.11:14369:G:<DEL>").is_err());
+    }
+
+    #[test]
+    fn test_spdi_from_vcf() {
+        let spdi = |reference: &str, alternate: &str| {
+            Spdi::from_vcf("20", 1234567, reference, alternate).map(|spdi| spdi.to_string())
+        };
+        assert_eq!(spdi("G", "A").as_deref(), Some("20:1234566:G:A"));
+        // Deletion and insertion, without their anchor base
+        assert_eq!(spdi("GTC", "G").as_deref(), Some("20:1234567:TC:"));
+        assert_eq!(spdi("GTC", "GTCT").as_deref(), Some("20:1234569::T"));
+        assert_eq!(spdi("G", "<DEL>"), None);
+        assert_eq!(spdi("G", "*"), None);
+        assert_eq!(spdi("G", "G]17:198982]"), None);
+    }
+
+    #[test]
+    fn test_spdi_matches() {
+        let variant = Spdi::from_vcf("20", 1234567, "GTC", "G").unwrap();
+        assert!(Spdi::parse("NC_000020.11:1234567:TC:")
+            .unwrap()
+            .matches(&variant));
+        assert!(Spdi::parse("NC_000020.11:1234567:2:")
+            .unwrap()
+            .matches(&variant));
+        // dbSNP-style SPDI spanning more bases than the change
+        assert!(Spdi::parse("NC_000020.11:1234566:GTC:G")
+            .unwrap()
+            .matches(&variant));
+        assert!(!Spdi::parse("NC_000020.11:1234567:1:")
+            .unwrap()
+            .matches(&variant));
+        assert!(!Spdi::parse("NC_000020.11:1234568:TC:")
+            .unwrap()
+            .matches(&variant));
+    }
+}
@@ -0,0 +1,794 @@
+// Supervisor mode: serve many VCF files behind one MCP endpoint.
+//
+// Each dataset from the manifest is served by its own worker process (this binary in
+// stdio mode). Workers are spawned on the first call for their dataset, so the memory
+// held by an index is only paid for files that are actually queried. When the number
+// of running workers or their combined memory exceeds the configured limits, the least
+// recently used workers are shut down and respawned on their next call. Tool calls carry
+// a `dataset` argument which the supervisor strips before forwarding the call.
+
+use rmcp::{
+    model::*,
+    service::{RequestContext, RunningService},
+    transport::{ConfigureCommandExt, TokioChildProcess},
+    ErrorData as McpError, RoleClient, RoleServer, ServerHandler, ServiceExt,
+};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+use tracing::{debug, info, warn};
+
+use crate::logging::LogFormat;
+use crate::shutdown::{shutting_down_error, Drain};
+use crate::{hide_genotype_tools, restrict_tool_router, VcfServer};
+
+const LIST_DATASETS_TOOL: &str = "list_datasets";
+const SERVER_STATS_TOOL: &str = "server_stats";
+// The workers' tool re-opening their file
+const RELOAD_DATASET_TOOL: &str = "reload_dataset";
+
+// Manifest file listing the datasets to serve
+#[derive(Debug, serde::Deserialize)]
+pub struct Manifest {
+    pub datasets: Vec<DatasetEntry>,
+}
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct DatasetEntry {
+    // Name clients pass as the `dataset` argument
+    pub name: String,
+    // Path to the VCF file; relative paths are resolved against the manifest directory
+    pub path: PathBuf,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+}
+
+impl Manifest {
+    // Load a JSON manifest, the [[datasets]] of a TOML one (such as a --config file), or build
+    // one from every VCF file in a directory
+    pub fn load(path: &Path) -> std::io::Result<Manifest> {
+        if path.is_dir() {
+            let manifest = Self::from_directory(path)?;
+            manifest.validate()?;
+            return Ok(manifest);
+        }
+
+        let text = std::fs::read_to_string(path)?;
+        let parsed = if path.extension().is_some_and(|ext| ext == "toml") {
+            toml::from_str(&text).map_err(|e| e.to_string())
+        } else {
+            serde_json::from_str(&text).map_err(|e| e.to_string())
+        };
+        let mut manifest: Manifest = parsed.map_err(|e| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("Invalid manifest {}: {}", path.display(), e),
+            )
+        })?;
+
+        let base_dir = path.parent().unwrap_or_else(|| Path::new("."));
+        for dataset in &mut manifest.datasets {
+            if dataset.path.is_relative() {
+                dataset.path = base_dir.join(&dataset.path);
+            }
+        }
+        manifest.validate()?;
+        Ok(manifest)
+    }
+
+    // One dataset per .vcf/.vcf.gz file, named after the file without its extension
+    fn from_directory(dir: &Path) -> std::io::Result<Manifest> {
+        let mut datasets = Vec::new();
+        for entry in std::fs::read_dir(dir)? {
+            let path = entry?.path();
+            let Some(file_name) = path.file_name().and_then(|n| n.to_str()) else {
+                continue;
+            };
+            let name = file_name
+                .strip_suffix(".vcf.gz")
+                .or_else(|| file_name.strip_suffix(".vcf"));
+            if let Some(name) = name.filter(|_| path.is_file()) {
+                datasets.push(DatasetEntry {
+                    name: name.to_string(),
+                    path: path.clone(),
+                    description: None,
+                });
+            }
+        }
+        datasets.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(Manifest { datasets })
+    }
+
+    fn validate(&self) -> std::io::Result<()> {
+        let invalid = |msg: String| std::io::Error::new(std::io::ErrorKind::InvalidData, msg);
+
+        if self.datasets.is_empty() {
+            return Err(invalid("Manifest contains no datasets".to_string()));
+        }
+        for (i, dataset) in self.datasets.iter().enumerate() {
+            if dataset.name.trim().is_empty() {
+                return Err(invalid(format!("Dataset #{} has an empty name", i + 1)));
+            }
+            if self.datasets[..i].iter().any(|d| d.name == dataset.name) {
+                return Err(invalid(format!(
+                    "Duplicate dataset name '{}'",
+                    dataset.name
+                )));
+            }
+            if !dataset.path.exists() {
+                return Err(invalid(format!(
+                    "VCF file for dataset '{}' not found: {}",
+                    dataset.name,
+                    dataset.path.display()
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+// Options forwarded to every worker process
+#[derive(Debug, Clone, Default)]
+pub struct WorkerOptions {
+    pub debug: bool,
+    pub log_format: LogFormat,
+    // --log-file for workers; they append to the supervisor's file
+    pub log_file: Option<PathBuf>,
+    pub never_save_index: bool,
+    pub wait_for_index: bool,
+    pub low_memory: bool,
+    pub sample_presence_index: bool,
+    // --block-cache-mb for workers; None leaves the worker default
+    pub block_cache_mb: Option<u64>,
+    pub mmap: bool,
+    // --max-region-size for workers; None leaves the worker default
+    pub max_region_size: Option<u64>,
+    // --max-region-bp, --max-results and --query-timeout for workers (0 means unlimited)
+    pub max_region_bp: u64,
+    pub max_results: usize,
+    pub query_timeout: u64,
+    // --io-retries and --io-retry-delay-ms for workers; None leaves the worker default
+    pub io_retries: Option<u32>,
+    pub io_retry_delay_ms: Option<u64>,
+    // --drain-timeout for workers; None leaves the worker default
+    pub drain_timeout: Option<u64>,
+    pub enable_tools: Vec<String>,
+    pub disable_tools: Vec<String>,
+    pub hide_genotypes: bool,
+    // --max-concurrent-queries and --result-cache-ttl for workers (0 leaves the worker default)
+    pub max_concurrent_queries: usize,
+    pub result_cache_ttl: u64,
+    // --result-cache-mb for workers; None leaves the worker default
+    pub result_cache_mb: Option<u64>,
+    // Further command line options, passed after all others (replay uses the recorded ones)
+    pub extra_args: Vec<String>,
+}
+
+// Limits on running workers; exceeding either evicts the least recently used ones
+#[derive(Debug, Clone, Default)]
+pub struct EvictionLimits {
+    pub max_loaded: Option<usize>,
+    // Combined resident memory of all workers (measured on Linux only)
+    pub max_memory_bytes: Option<u64>,
+}
+
+pub(crate) type WorkerClient = RunningService<RoleClient, ()>;
+
+struct Worker {
+    client: Arc<WorkerClient>,
+    pid: Option<u32>,
+    started_at: Instant,
+    last_used: Instant,
+}
+
+struct Dataset {
+    entry: DatasetEntry,
+    // None until the first call for this dataset spawns the worker, and again after eviction
+    worker: Mutex<Option<Worker>>,
+    calls: AtomicU64,
+    loads: AtomicU64,
+    evictions: AtomicU64,
+}
+
+#[derive(Clone)]
+pub struct SupervisorServer {
+    datasets: Arc<Vec<Dataset>>,
+    tools: Arc<Vec<Tool>>,
+    options: Arc<WorkerOptions>,
+    limits: Arc<EvictionLimits>,
+    started_at: Instant,
+    // Forwarded calls running, refused once shutdown begins
+    drain: Arc<Drain>,
+}
+
+impl SupervisorServer {
+    pub fn new(
+        manifest: Manifest,
+        options: WorkerOptions,
+        limits: EvictionLimits,
+    ) -> Result<Self, String> {
+        // Workers run this same binary, so their tool list is known without spawning one
+        let mut router = VcfServer::tools();
+        restrict_tool_router(&mut router, &options.enable_tools, &options.disable_tools)?;
+        if options.hide_genotypes {
+            hide_genotype_tools(&mut router);
+        }
+
+        let dataset_names: Vec<String> = manifest.datasets.iter().map(|d| d.name.clone()).collect();
+        let mut tools: Vec<Tool> = router
+            .list_all()
+            .into_iter()
+            .map(|tool| with_dataset_parameter(tool, &dataset_names))
+            .collect();
+        tools.push(list_datasets_tool());
+        tools.push(server_stats_tool());
+
+        let datasets = manifest
+            .datasets
+            .into_iter()
+            .map(|entry| Dataset {
+                entry,
+                worker: Mutex::new(None),
+                calls: AtomicU64::new(0),
+                loads: AtomicU64::new(0),
+                evictions: AtomicU64::new(0),
+            })
+            .collect();
+
+        Ok(SupervisorServer {
+            datasets: Arc::new(datasets),
+            tools: Arc::new(tools),
+            options: Arc::new(options),
+            limits: Arc::new(limits),
+            started_at: Instant::now(),
+            drain: Arc::default(),
+        })
+    }
+
+    pub fn debug(&self) -> bool {
+        self.options.debug
+    }
+
+    // Have every running worker reload its file (on SIGHUP); workers started later read the
+    // current file anyway
+    pub async fn reload_workers(&self) {
+        for dataset in self.datasets.iter() {
+            let client = match dataset.worker.lock().await.as_ref() {
+                Some(worker) => Arc::clone(&worker.client),
+                None => continue,
+            };
+            let request = CallToolRequestParam {
+                name: RELOAD_DATASET_TOOL.into(),
+                arguments: None,
+            };
+            match client.call_tool(request).await {
+                Ok(_) => info!("Reloaded dataset '{}'", dataset.entry.name),
+                Err(e) => warn!("Reloading dataset '{}' failed: {}", dataset.entry.name, e),
+            }
+        }
+    }
+
+    // Refuse new calls and let forwarded ones finish within `timeout`; workers see their
+    // stdin close when the supervisor exits and finish their own indexing
+    pub async fn shut_down(&self, timeout: Duration) {
+        if !self.drain.drain(timeout).await {
+            warn!(
+                "Drain timeout reached; abandoning {} running tool call(s)",
+                self.drain.in_flight()
+            );
+        }
+    }
+
+    fn find_dataset(&self, name: &str) -> Result<&Dataset, McpError> {
+        self.datasets
+            .iter()
+            .find(|d| d.entry.name == name)
+            .ok_or_else(|| {
+                let available: Vec<&str> = self
+                    .datasets
+                    .iter()
+                    .map(|d| d.entry.name.as_str())
+                    .collect();
+                McpError::invalid_params(
+                    format!(
+                        "Unknown dataset '{}'. Available datasets: {}",
+                        name,
+                        available.join(", ")
+                    ),
+                    None,
+                )
+            })
+    }
+
+    // Return the dataset's worker, spawning it on first use
+    async fn worker(&self, dataset: &Dataset) -> Result<Arc<WorkerClient>, McpError> {
+        let client = {
+            let mut worker = dataset.worker.lock().await;
+            if let Some(worker) = worker.as_mut() {
+                worker.last_used = Instant::now();
+                return Ok(Arc::clone(&worker.client));
+            }
+
+            info!(
+                "Starting worker for dataset '{}' ({})",
+                dataset.entry.name,
+                dataset.entry.path.display()
+            );
+            let (client, pid) = spawn_worker(None, &dataset.entry.path, &self.options)
+                .await
+                .map_err(|e| {
+                    McpError::internal_error(
+                        format!(
+                            "Failed to start worker for dataset '{}': {}",
+                            dataset.entry.name, e
+                        ),
+                        None,
+                    )
+                })?;
+            let client = Arc::new(client);
+            let now = Instant::now();
+            *worker = Some(Worker {
+                client: Arc::clone(&client),
+                pid,
+                started_at: now,
+                last_used: now,
+            });
+            dataset.loads.fetch_add(1, Ordering::Relaxed);
+            client
+        };
+
+        // Lock released above: eviction locks the other datasets one at a time
+        self.enforce_limits(&dataset.entry.name).await;
+        Ok(client)
+    }
+
+    // Evict least recently used workers (never `keep`) until within the limits
+    async fn enforce_limits(&self, keep: &str) {
+        if self.limits.max_loaded.is_none() && self.limits.max_memory_bytes.is_none() {
+            return;
+        }
+
+        loop {
+            let mut loaded = Vec::new();
+            let mut total_memory = 0;
+            for dataset in self.datasets.iter() {
+                if let Some(worker) = dataset.worker.lock().await.as_ref() {
+                    total_memory += worker.pid.and_then(process_memory_bytes).unwrap_or(0);
+                    loaded.push((dataset, worker.last_used));
+                }
+            }
+
+            let over_count = self.limits.max_loaded.is_some_and(|max| loaded.len() > max);
+            let over_memory = self
+                .limits
+                .max_memory_bytes
+                .is_some_and(|max| total_memory > max);
+            if !over_count && !over_memory {
+                return;
+            }
+
+            let Some((victim, _)) = loaded
+                .into_iter()
+                .filter(|(dataset, _)| dataset.entry.name != keep)
+                .min_by_key(|(_, last_used)| *last_used)
+            else {
+                return;
+            };
+
+            info!(
+                "Evicting worker for dataset '{}' ({})",
+                victim.entry.name,
+                if over_count {
+                    "too many loaded datasets"
+                } else {
+                    "memory limit exceeded"
+                }
+            );
+            // Dropping the last handle shuts the worker down; in-flight calls keep theirs
+            if victim.worker.lock().await.take().is_some() {
+                victim.evictions.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    fn list_datasets(&self) -> Result<CallToolResult, McpError> {
+        let datasets: Vec<serde_json::Value> = self
+            .datasets
+            .iter()
+            .map(|d| {
+                serde_json::json!({
+                    "name": d.entry.name,
+                    "path": d.entry.path.display().to_string(),
+                    "description": d.entry.description,
+                    // try_lock fails only while a worker is starting or being replaced
+                    "loaded": d.worker.try_lock().map(|w| w.is_some()).unwrap_or(true),
+                })
+            })
+            .collect();
+
+        let payload = serde_json::json!({
+            "count": datasets.len(),
+            "datasets": datasets,
+        });
+        Ok(CallToolResult::success(vec![Content::json(payload)?]))
+    }
+
+    async fn server_stats(&self) -> Result<CallToolResult, McpError> {
+        let mut datasets = Vec::with_capacity(self.datasets.len());
+        let mut loaded_count = 0;
+        let mut total_memory = 0;
+        for dataset in self.datasets.iter() {
+            let worker = dataset.worker.lock().await;
+            let memory = worker
+                .as_ref()
+                .and_then(|w| w.pid)
+                .and_then(process_memory_bytes);
+            if worker.is_some() {
+                loaded_count += 1;
+                total_memory += memory.unwrap_or(0);
+            }
+            datasets.push(serde_json::json!({
+                "name": dataset.entry.name,
+                "loaded": worker.is_some(),
+                "pid": worker.as_ref().and_then(|w| w.pid),
+                "memory_bytes": memory,
+                "uptime_secs": worker.as_ref().map(|w| w.started_at.elapsed().as_secs()),
+                "idle_secs": worker.as_ref().map(|w| w.last_used.elapsed().as_secs()),
+                "calls": dataset.calls.load(Ordering::Relaxed),
+                "loads": dataset.loads.load(Ordering::Relaxed),
+                "evictions": dataset.evictions.load(Ordering::Relaxed),
+            }));
+        }
+
+        let payload = serde_json::json!({
+            "uptime_secs": self.started_at.elapsed().as_secs(),
+            "dataset_count": self.datasets.len(),
+            "loaded_count": loaded_count,
+            "memory_bytes": total_memory,
+            "limits": {
+                "max_loaded_datasets": self.limits.max_loaded,
+                "max_worker_memory_bytes": self.limits.max_memory_bytes,
+            },
+            "datasets": datasets,
+        });
+        Ok(CallToolResult::success(vec![Content::json(payload)?]))
+    }
+}
+
+// Resident memory of a process, read from /proc (None where unavailable)
+fn process_memory_bytes(pid: u32) -> Option<u64> {
+    let status = std::fs::read_to_string(format!("/proc/{}/status", pid)).ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmRSS:"))?;
+    let kb: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kb * 1024)
+}
+
+// Start `exe` (this binary when None) serving `vcf_path` over stdio and connect to it
+pub(crate) async fn spawn_worker(
+    exe: Option<&Path>,
+    vcf_path: &Path,
+    options: &WorkerOptions,
+) -> std::io::Result<(WorkerClient, Option<u32>)> {
+    let exe = match exe {
+        Some(exe) => exe.to_path_buf(),
+        None => std::env::current_exe()?,
+    };
+    let transport = TokioChildProcess::new(tokio::process::Command::new(exe).configure(|cmd| {
+        cmd.arg(vcf_path);
+        if options.debug {
+            cmd.arg("--debug");
+        }
+        cmd.arg("--log-format").arg(options.log_format.name());
+        if let Some(path) = &options.log_file {
+            cmd.arg("--log-file").arg(path);
+        }
+        if options.never_save_index {
+            cmd.arg("--never-save-index");
+        }
+        if options.wait_for_index {
+            cmd.arg("--wait-for-index");
+        }
+        if options.low_memory {
+            cmd.arg("--low-memory");
+        }
+        if options.sample_presence_index {
+            cmd.arg("--sample-presence-index");
+        }
+        if let Some(mb) = options.block_cache_mb {
+            cmd.arg("--block-cache-mb").arg(mb.to_string());
+        }
+        if options.mmap {
+            cmd.arg("--mmap");
+        }
+        if let Some(bp) = options.max_region_size {
+            cmd.arg("--max-region-size").arg(bp.to_string());
+        }
+        if options.max_region_bp > 0 {
+            cmd.arg("--max-region-bp")
+                .arg(options.max_region_bp.to_string());
+        }
+        if options.max_results > 0 {
+            cmd.arg("--max-results")
+                .arg(options.max_results.to_string());
+        }
+        if options.query_timeout > 0 {
+            cmd.arg("--query-timeout")
+                .arg(options.query_timeout.to_string());
+        }
+        if let Some(retries) = options.io_retries {
+            cmd.arg("--io-retries").arg(retries.to_string());
+        }
+        if let Some(ms) = options.io_retry_delay_ms {
+            cmd.arg("--io-retry-delay-ms").arg(ms.to_string());
+        }
+        if let Some(secs) = options.drain_timeout {
+            cmd.arg("--drain-timeout").arg(secs.to_string());
+        }
+        if !options.enable_tools.is_empty() {
+            cmd.arg("--enable-tools")
+                .arg(options.enable_tools.join(","));
+        }
+        if !options.disable_tools.is_empty() {
+            cmd.arg("--disable-tools")
+                .arg(options.disable_tools.join(","));
+        }
+        if options.hide_genotypes {
+            cmd.arg("--hide-genotypes");
+        }
+        if options.max_concurrent_queries > 0 {
+            cmd.arg("--max-concurrent-queries")
+                .arg(options.max_concurrent_queries.to_string());
+        }
+        if options.result_cache_ttl > 0 {
+            cmd.arg("--result-cache-ttl")
+                .arg(options.result_cache_ttl.to_string());
+            if let Some(mb) = options.result_cache_mb {
+                cmd.arg("--result-cache-mb").arg(mb.to_string());
+            }
+        }
+        cmd.args(&options.extra_args);
+    }))?;
+    let pid = transport.id();
+
+    let client = ().serve(transport).await.map_err(|e| std::io::Error::other(e.to_string()))?;
+    Ok((client, pid))
+}
+
+// Add the required `dataset` argument to a worker tool's input schema
+fn with_dataset_parameter(mut tool: Tool, dataset_names: &[String]) -> Tool {
+    let mut schema = (*tool.input_schema).clone();
+
+    let properties = schema
+        .entry("properties")
+        .or_insert_with(|| serde_json::Value::Object(Default::default()));
+    if let Some(properties) = properties.as_object_mut() {
+        properties.insert(
+            "dataset".to_string(),
+            serde_json::json!({
+                "type": "string",
+                "description": "Dataset to query (see list_datasets)",
+                "enum": dataset_names,
+            }),
+        );
+    }
+
+    let required = schema
+        .entry("required")
+        .or_insert_with(|| serde_json::Value::Array(Vec::new()));
+    if let Some(required) = required.as_array_mut() {
+        required.push(serde_json::Value::String("dataset".to_string()));
+    }
+
+    tool.input_schema = Arc::new(schema);
+    tool
+}
+
+fn list_datasets_tool() -> Tool {
+    let schema = serde_json::json!({
+        "type": "object",
+        "properties": {},
+    });
+    let mut tool = Tool::new(
+        LIST_DATASETS_TOOL,
+        "List the datasets (VCF files) served by this endpoint. Every other tool requires a 'dataset' argument naming one of them.",
+        Arc::new(schema.as_object().cloned().unwrap_or_default()),
+    );
+    tool.annotations = Some(read_only_annotations("List datasets"));
+    tool
+}
+
+fn server_stats_tool() -> Tool {
+    let schema = serde_json::json!({
+        "type": "object",
+        "properties": {},
+    });
+    let mut tool = Tool::new(
+        SERVER_STATS_TOOL,
+        "Show which datasets currently have a running worker, their memory use, call counts, and how often they were loaded and evicted.",
+        Arc::new(schema.as_object().cloned().unwrap_or_default()),
+    );
+    tool.annotations = Some(read_only_annotations("Server statistics"));
+    tool
+}
+
+// Annotations of the supervisor's own tools, which only report on the endpoint
+fn read_only_annotations(title: &str) -> ToolAnnotations {
+    ToolAnnotations {
+        title: Some(title.to_string()),
+        read_only_hint: Some(true),
+        destructive_hint: Some(false),
+        idempotent_hint: Some(true),
+        open_world_hint: Some(false),
+    }
+}
+
+impl ServerHandler for SupervisorServer {
+    fn get_info(&self) -> ServerInfo {
+        ServerInfo {
+            protocol_version: ProtocolVersion::V_2024_11_05,
+            capabilities: ServerCapabilities::builder().enable_tools().build(),
+            server_info: Implementation::from_build_env(),
+            instructions: Some(
+                "This server provides VCF variant query tools over several datasets. Call list_datasets first, then pass the dataset name as the 'dataset' argument of every other tool. IMPORTANT: Genomic coordinates are specific to the reference genome build (GRCh37 vs GRCh38), which may differ between datasets. Always check the reference_genome field in responses.".to_string()
+            ),
+        }
+    }
+
+    async fn list_tools(
+        &self,
+        _request: Option<PaginatedRequestParam>,
+        _: RequestContext<RoleServer>,
+    ) -> Result<ListToolsResult, McpError> {
+        Ok(ListToolsResult {
+            tools: self.tools.as_ref().clone(),
+            next_cursor: None,
+            meta: None,
+        })
+    }
+
+    async fn call_tool(
+        &self,
+        mut request: CallToolRequestParam,
+        _: RequestContext<RoleServer>,
+    ) -> Result<CallToolResult, McpError> {
+        let Some(_in_flight) = self.drain.enter() else {
+            return Err(shutting_down_error());
+        };
+        if self.debug() {
+            debug!(
+                "Supervisor tool call: {}",
+                serde_json::to_string(&request).unwrap_or_else(|_| format!("{:?}", request))
+            );
+        }
+
+        if request.name == LIST_DATASETS_TOOL {
+            return self.list_datasets();
+        }
+        if request.name == SERVER_STATS_TOOL {
+            return self.server_stats().await;
+        }
+        if !self.tools.iter().any(|tool| tool.name == request.name) {
+            return Err(McpError::invalid_params(
+                format!("Unknown tool '{}'", request.name),
+                None,
+            ));
+        }
+
+        let dataset_name = request
+            .arguments
+            .as_mut()
+            .and_then(|arguments| arguments.remove("dataset"))
+            .and_then(|value| value.as_str().map(str::to_string))
+            .ok_or_else(|| {
+                McpError::invalid_params(
+                    "Missing 'dataset' argument. Call list_datasets to see available datasets.",
+                    None,
+                )
+            })?;
+        let dataset = self.find_dataset(&dataset_name)?;
+        dataset.calls.fetch_add(1, Ordering::Relaxed);
+        let client = self.worker(dataset).await?;
+
+        match client.call_tool(request).await {
+            Ok(result) => Ok(result),
+            // Errors reported by the worker's tool are passed through unchanged
+            Err(rmcp::ServiceError::McpError(e)) => Err(e),
+            Err(e) => {
+                // Transport failure: drop the worker so the next call starts a fresh one
+                let mut worker = dataset.worker.lock().await;
+                if worker
+                    .as_ref()
+                    .is_some_and(|current| Arc::ptr_eq(&current.client, &client))
+                {
+                    *worker = None;
+                }
+                Err(McpError::internal_error(
+                    format!("Worker for dataset '{}' failed: {}", dataset_name, e),
+                    None,
+                ))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_with_dataset_parameter_adds_required_property() {
+        let tool = VcfServer::tool_router()
+            .list_all()
+            .into_iter()
+            .find(|tool| tool.name == "query_by_id")
+            .expect("query_by_id tool");
+
+        let tool = with_dataset_parameter(tool, &["a".to_string(), "b".to_string()]);
+        let schema = serde_json::Value::Object((*tool.input_schema).clone());
+
+        assert_eq!(schema["properties"]["dataset"]["type"], "string");
+        assert_eq!(
+            schema["properties"]["dataset"]["enum"],
+            serde_json::json!(["a", "b"])
+        );
+        let required = schema["required"].as_array().expect("required array");
+        assert!(required.contains(&serde_json::json!("dataset")));
+        assert!(required.contains(&serde_json::json!("id")));
+    }
+
+    #[test]
+    fn test_manifest_rejects_duplicates_and_missing_files() {
+        let duplicate: Manifest = serde_json::from_str(
+            r#"{"datasets": [
+                {"name": "a", "path": "Cargo.toml"},
+                {"name": "a", "path": "Cargo.toml"}
+            ]}"#,
+        )
+        .expect("parse manifest");
+        assert!(duplicate.validate().is_err());
+
+        let missing: Manifest =
+            serde_json::from_str(r#"{"datasets": [{"name": "a", "path": "missing.vcf.gz"}]}"#)
+                .expect("parse manifest");
+        assert!(missing.validate().is_err());
+
+        let empty: Manifest = serde_json::from_str(r#"{"datasets": []}"#).expect("parse");
+        assert!(empty.validate().is_err());
+    }
+
+    #[test]
+    fn test_supervisor_lists_dataset_tool_and_respects_tool_policy() {
+        let manifest: Manifest = serde_json::from_str(
+            r#"{"datasets": [{"name": "sample", "path": "sample_data/sample.compressed.vcf.gz"}]}"#,
+        )
+        .expect("parse manifest");
+        let options = WorkerOptions {
+            disable_tools: vec!["get_statistics".to_string()],
+            ..Default::default()
+        };
+
+        let server = SupervisorServer::new(manifest, options, EvictionLimits::default())
+            .expect("create supervisor");
+        let names: Vec<&str> = server.tools.iter().map(|t| t.name.as_ref()).collect();
+        assert!(names.contains(&LIST_DATASETS_TOOL));
+        assert!(names.contains(&SERVER_STATS_TOOL));
+        assert!(names.contains(&"query_by_position"));
+        assert!(!names.contains(&"get_statistics"));
+    }
+
+    #[test]
+    fn test_manifest_from_directory_uses_file_stems() {
+        let manifest = Manifest::from_directory(Path::new("sample_data")).expect("scan directory");
+        for dataset in &manifest.datasets {
+            assert!(!dataset.name.ends_with(".vcf"));
+            assert!(dataset.path.is_file());
+        }
+        let names: Vec<&str> = manifest.datasets.iter().map(|d| d.name.as_str()).collect();
+        assert!(names.contains(&"sample.compressed"));
+        let mut sorted = names.clone();
+        sorted.sort();
+        assert_eq!(names, sorted);
+    }
+}
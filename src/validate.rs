@@ -0,0 +1,525 @@
+// Offline checks of a VCF file for the `validate` subcommand: the BGZF container (every block
+// decompresses, and the file ends with the empty block bgzip writes, which truncated copies
+// lack), the header, each record's CHROM and POS, the sort order tabix needs (positions
+// ascending within a chromosome, each chromosome in one run), and the index files next to it.
+//
+// The server only trips over most of these once a query reaches the bad block or record, with
+// an error that doesn't say what is wrong with the file. Problems are reported as JSON, each
+// with the check that found it and a stable code, so pipelines can act on them.
+
+use noodles::bgzf;
+use noodles::core::region::Interval;
+use noodles::core::Position;
+use noodles::csi::{self, BinningIndex};
+use noodles::tabix;
+use noodles::vcf;
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+use crate::cache_admin::{self, SidecarKind, SidecarStatus};
+
+// The empty block bgzip ends every file with
+const BGZF_EOF: [u8; 28] = [
+    0x1f, 0x8b, 0x08, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0xff, 0x06, 0x00, 0x42, 0x43, 0x02, 0x00,
+    0x1b, 0x00, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+];
+
+// Problems listed in a report; `errors` and `warnings` count all of them
+const MAX_LISTED_PROBLEMS: usize = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    // The server can't load the file, or returns wrong or incomplete results from it
+    Error,
+    // Worth fixing, but served correctly (the server rebuilds what is missing or stale)
+    Warning,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct Problem {
+    pub severity: Severity,
+    // "bgzf", "header", "records", "sort_order" or "index"
+    pub check: &'static str,
+    pub code: &'static str,
+    pub message: String,
+    // 1-based number of the record it concerns
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub record: Option<u64>,
+}
+
+#[derive(Debug, Default, serde::Serialize)]
+pub struct Report {
+    pub file: String,
+    // No errors (warnings allowed)
+    pub valid: bool,
+    pub records: u64,
+    pub chromosomes: usize,
+    pub errors: usize,
+    pub warnings: usize,
+    pub problems: Vec<Problem>,
+    // More problems were found than are listed
+    pub problems_truncated: bool,
+}
+
+impl Report {
+    fn add(
+        &mut self,
+        severity: Severity,
+        check: &'static str,
+        code: &'static str,
+        message: String,
+        record: Option<u64>,
+    ) {
+        match severity {
+            Severity::Error => self.errors += 1,
+            Severity::Warning => self.warnings += 1,
+        }
+        if self.problems.len() < MAX_LISTED_PROBLEMS {
+            self.problems.push(Problem {
+                severity,
+                check,
+                code,
+                message,
+                record,
+            });
+        } else {
+            self.problems_truncated = true;
+        }
+    }
+
+    fn error(&mut self, check: &'static str, code: &'static str, message: String) {
+        self.add(Severity::Error, check, code, message, None);
+    }
+
+    fn warning(&mut self, check: &'static str, code: &'static str, message: String) {
+        self.add(Severity::Warning, check, code, message, None);
+    }
+
+    // Whether any problem has this code
+    #[cfg(test)]
+    fn has(&self, code: &str) -> bool {
+        self.problems.iter().any(|problem| problem.code == code)
+    }
+}
+
+// Run every check on the file; an error only if it can't be opened at all
+pub fn validate(path: &Path) -> std::io::Result<Report> {
+    let mut file = File::open(path)?;
+    let mut report = Report {
+        file: path.display().to_string(),
+        ..Default::default()
+    };
+
+    if check_container(&mut file, &mut report)? {
+        file.seek(SeekFrom::Start(0))?;
+        if let Some((header, first_positions)) = check_contents(file, &mut report) {
+            check_indexes(path, &header, &first_positions, &mut report);
+        }
+    }
+
+    report.valid = report.errors == 0;
+    Ok(report)
+}
+
+// Check that the file is BGZF and ends with the EOF block; false if it can't be read as BGZF
+fn check_container(file: &mut File, report: &mut Report) -> std::io::Result<bool> {
+    let mut magic = [0u8; 16];
+    let length = file.metadata()?.len();
+    if length < magic.len() as u64 {
+        report.error(
+            "bgzf",
+            "not_compressed",
+            "file is too short to be bgzip-compressed".to_string(),
+        );
+        return Ok(false);
+    }
+    file.read_exact(&mut magic)?;
+    if magic[..2] != [0x1f, 0x8b] {
+        report.error(
+            "bgzf",
+            "not_compressed",
+            "file is not gzip-compressed; compress it with bgzip".to_string(),
+        );
+        return Ok(false);
+    }
+    // BGZF is gzip with a "BC" extra subfield holding the block size
+    if magic[3] & 0x04 == 0 || magic[12..14] != *b"BC" {
+        report.error(
+            "bgzf",
+            "not_bgzf",
+            "file is gzip- but not BGZF-compressed, so it can't be indexed; recompress it with \
+             bgzip"
+                .to_string(),
+        );
+        return Ok(false);
+    }
+
+    let mut tail = [0u8; BGZF_EOF.len()];
+    file.seek(SeekFrom::End(-(BGZF_EOF.len() as i64)))?;
+    file.read_exact(&mut tail)?;
+    if tail != BGZF_EOF {
+        report.error(
+            "bgzf",
+            "missing_eof_block",
+            "file does not end with the BGZF end-of-file block; it may be truncated".to_string(),
+        );
+    }
+    Ok(true)
+}
+
+// Read the header and every record, checking positions and sort order. Returns the header and
+// each chromosome's first record position, or None when the header can't be read.
+fn check_contents(
+    file: File,
+    report: &mut Report,
+) -> Option<(vcf::Header, Vec<(String, Position)>)> {
+    let mut reader = vcf::io::Reader::new(bgzf::io::Reader::new(file));
+    let header = match reader.read_header() {
+        Ok(header) => header,
+        Err(e) => {
+            report.error("header", "invalid_header", e.to_string());
+            return None;
+        }
+    };
+    if header.contigs().is_empty() {
+        report.warning(
+            "header",
+            "no_contigs",
+            "header has no ##contig lines, so chromosome lengths are unknown".to_string(),
+        );
+    }
+
+    let mut first_positions: Vec<(String, Position)> = Vec::new();
+    let mut seen = HashSet::new();
+    let mut undeclared = HashSet::new();
+    let mut last = None;
+    let mut record = vcf::Record::default();
+    loop {
+        let number = report.records + 1;
+        match reader.read_record(&mut record) {
+            Ok(0) => break,
+            Ok(_) => report.records = number,
+            Err(e) => {
+                // A block that doesn't decompress, or a line that isn't a record; nothing after
+                // it can be trusted
+                report.add(
+                    Severity::Error,
+                    "records",
+                    "unreadable_record",
+                    format!("reading stopped: {}", e),
+                    Some(number),
+                );
+                break;
+            }
+        }
+
+        let chromosome = record.reference_sequence_name();
+        let position = match record.variant_start() {
+            Some(Ok(position)) => position,
+            Some(Err(e)) => {
+                report.add(
+                    Severity::Error,
+                    "records",
+                    "invalid_position",
+                    format!("{}: invalid POS: {}", chromosome, e),
+                    Some(number),
+                );
+                continue;
+            }
+            None => {
+                report.add(
+                    Severity::Error,
+                    "records",
+                    "invalid_position",
+                    format!("{}: POS is missing or 0", chromosome),
+                    Some(number),
+                );
+                continue;
+            }
+        };
+
+        if !header.contigs().is_empty()
+            && !header.contigs().contains_key(chromosome)
+            && undeclared.insert(chromosome.to_string())
+        {
+            report.add(
+                Severity::Warning,
+                "header",
+                "undeclared_contig",
+                format!("chromosome {} has no ##contig line", chromosome),
+                Some(number),
+            );
+        }
+
+        match &last {
+            Some((current, previous)) if current == chromosome => {
+                if position < *previous {
+                    report.add(
+                        Severity::Error,
+                        "sort_order",
+                        "unsorted",
+                        format!(
+                            "{}:{} comes after {}:{}; sort the file (bcftools sort)",
+                            chromosome, position, chromosome, previous
+                        ),
+                        Some(number),
+                    );
+                }
+            }
+            _ => {
+                if seen.insert(chromosome.to_string()) {
+                    first_positions.push((chromosome.to_string(), position));
+                } else {
+                    report.add(
+                        Severity::Error,
+                        "sort_order",
+                        "chromosome_split",
+                        format!(
+                            "records of {} resume after other chromosomes; sort the file \
+                             (bcftools sort)",
+                            chromosome
+                        ),
+                        Some(number),
+                    );
+                }
+            }
+        }
+        last = Some((chromosome.to_string(), position));
+    }
+
+    report.chromosomes = seen.len();
+    Some((header, first_positions))
+}
+
+// Check the sidecars next to the VCF, and that its tabix or CSI index finds each chromosome's
+// first record
+fn check_indexes(
+    path: &Path,
+    header: &vcf::Header,
+    first_positions: &[(String, Position)],
+    report: &mut Report,
+) {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let sidecars = match cache_admin::scan(dir) {
+        Ok(sidecars) => sidecars,
+        Err(e) => {
+            report.warning(
+                "index",
+                "unreadable_directory",
+                format!("could not list {}: {}", dir.display(), e),
+            );
+            Vec::new()
+        }
+    };
+    for sidecar in sidecars
+        .iter()
+        .filter(|sidecar| sidecar.vcf.file_name() == path.file_name())
+    {
+        let status = cache_admin::verify(sidecar);
+        if status == SidecarStatus::Ok {
+            continue;
+        }
+        // The server can't load the file with an unreadable tabix or CSI index, but rebuilds
+        // ID indexes and statistics. A tabix or CSI index older than the VCF is only suspect
+        // (copies don't always keep modification times); the coverage check below tells.
+        let severity = match status {
+            SidecarStatus::Unreadable(_)
+                if matches!(sidecar.kind, SidecarKind::Tabix | SidecarKind::Csi) =>
+            {
+                Severity::Error
+            }
+            _ => Severity::Warning,
+        };
+        let message = match status.detail() {
+            Some(detail) => format!("{}: {}", sidecar.path.display(), detail),
+            None => sidecar.path.display().to_string(),
+        };
+        let code = match status {
+            SidecarStatus::Temporary => "temporary_file",
+            SidecarStatus::Stale(_) => "stale_index",
+            SidecarStatus::NewerFormat(_) => "newer_format_index",
+            _ => "unreadable_index",
+        };
+        report.add(severity, "index", code, message, None);
+    }
+
+    let tbi_path = cache_admin::sidecar_path(path, SidecarKind::Tabix);
+    let csi_path = cache_admin::sidecar_path(path, SidecarKind::Csi);
+    if tbi_path.is_file() {
+        if let Ok(index) = tabix::fs::read(&tbi_path) {
+            check_index_coverage(&index, header, first_positions, report);
+        }
+    } else if csi_path.is_file() {
+        if let Ok(index) = csi::fs::read(&csi_path) {
+            check_index_coverage(&index, header, first_positions, report);
+        }
+    } else {
+        report.warning(
+            "index",
+            "no_index",
+            "no .tbi or .csi index; the server builds a tabix index when it loads the file \
+             (or run `index` beforehand)"
+                .to_string(),
+        );
+    }
+}
+
+fn check_index_coverage<I: BinningIndex>(
+    index: &I,
+    header: &vcf::Header,
+    first_positions: &[(String, Position)],
+    report: &mut Report,
+) {
+    for (chromosome, position) in first_positions {
+        // Resolved the way queries resolve it: the index's own names, else the header contigs
+        let reference_sequence_id = match index.header() {
+            Some(index_header) => index_header
+                .reference_sequence_names()
+                .get_index_of(chromosome.as_bytes()),
+            None => header.contigs().get_index_of(chromosome.as_str()),
+        };
+        let Some(reference_sequence_id) = reference_sequence_id else {
+            report.error(
+                "index",
+                "chromosome_not_indexed",
+                format!(
+                    "the index has no entry for {}, so its records can't be queried; rebuild \
+                     the index",
+                    chromosome
+                ),
+            );
+            continue;
+        };
+        let found = index
+            .query(reference_sequence_id, Interval::from(*position..=*position))
+            .is_ok_and(|chunks| !chunks.is_empty());
+        if !found {
+            report.error(
+                "index",
+                "index_mismatch",
+                format!(
+                    "the index finds no data at {}:{}, where the file has a record; rebuild \
+                     the index",
+                    chromosome, position
+                ),
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    const HEADER: &str = "##fileformat=VCFv4.3\n##contig=<ID=20>\n##contig=<ID=21>\n\
+                          #CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\n";
+
+    fn write_bgzf(path: &Path, text: &str) {
+        let mut writer = bgzf::io::Writer::new(File::create(path).unwrap());
+        writer.write_all(text.as_bytes()).unwrap();
+        writer.finish().unwrap();
+    }
+
+    fn write_tabix(path: &Path) {
+        let index = vcf::fs::index(path).unwrap();
+        let tbi = File::create(cache_admin::sidecar_path(path, SidecarKind::Tabix)).unwrap();
+        tabix::io::Writer::new(tbi).write_index(&index).unwrap();
+    }
+
+    #[test]
+    fn test_valid_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("ok.vcf.gz");
+        write_bgzf(
+            &path,
+            &format!(
+                "{}20\t100\t.\tA\tG\t.\tPASS\t.\n20\t200\t.\tC\tT\t.\tPASS\t.\n\
+                 21\t50\t.\tG\tA\t.\tPASS\t.\n",
+                HEADER
+            ),
+        );
+
+        let report = validate(&path).unwrap();
+        assert!(report.valid);
+        assert_eq!(report.records, 3);
+        assert_eq!(report.chromosomes, 2);
+        assert!(report.has("no_index"));
+
+        write_tabix(&path);
+        let report = validate(&path).unwrap();
+        assert!(report.valid);
+        assert!(report.problems.is_empty(), "{:?}", report.problems);
+    }
+
+    #[test]
+    fn test_unsorted_and_truncated_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("bad.vcf.gz");
+        write_bgzf(
+            &path,
+            &format!(
+                "{}20\t200\t.\tA\tG\t.\tPASS\t.\n20\t100\t.\tC\tT\t.\tPASS\t.\n\
+                 22\t50\t.\tG\tA\t.\tPASS\t.\n20\t300\t.\tG\tA\t.\tPASS\t.\n",
+                HEADER
+            ),
+        );
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::write(&path, &bytes[..bytes.len() - BGZF_EOF.len()]).unwrap();
+
+        let report = validate(&path).unwrap();
+        assert!(!report.valid);
+        assert_eq!(report.records, 4);
+        assert!(report.has("missing_eof_block"));
+        assert!(report.has("unsorted"));
+        assert!(report.has("chromosome_split"));
+        assert!(report.has("undeclared_contig"));
+        let unsorted = report
+            .problems
+            .iter()
+            .find(|problem| problem.code == "unsorted")
+            .unwrap();
+        assert_eq!(unsorted.record, Some(2));
+    }
+
+    #[test]
+    fn test_container_and_index_problems() {
+        let dir = tempfile::tempdir().unwrap();
+        let plain = dir.path().join("plain.vcf");
+        std::fs::write(&plain, format!("{}20\t100\t.\tA\tG\t.\tPASS\t.\n", HEADER)).unwrap();
+        let report = validate(&plain).unwrap();
+        assert!(report.has("not_compressed"));
+        assert_eq!(report.records, 0);
+
+        // Indexed before chromosome 21 was added
+        let path = dir.path().join("grown.vcf.gz");
+        write_bgzf(&path, &format!("{}20\t100\t.\tA\tG\t.\tPASS\t.\n", HEADER));
+        write_tabix(&path);
+        write_bgzf(
+            &path,
+            &format!(
+                "{}20\t100\t.\tA\tG\t.\tPASS\t.\n21\t50\t.\tG\tA\t.\tPASS\t.\n",
+                HEADER
+            ),
+        );
+        let report = validate(&path).unwrap();
+        assert!(!report.valid);
+        assert!(report.has("chromosome_not_indexed"));
+
+        std::fs::write(
+            cache_admin::sidecar_path(&path, SidecarKind::Tabix),
+            b"junk",
+        )
+        .unwrap();
+        let report = validate(&path).unwrap();
+        assert!(report.has("unreadable_index"));
+
+        assert!(validate(&dir.path().join("missing.vcf.gz")).is_err());
+    }
+}
@@ -1,15 +1,32 @@
+use memmap2::Mmap;
 use noodles::bgzf;
+use noodles::core::region::Interval;
 use noodles::core::{Position, Region};
 use noodles::csi::{self, BinningIndex};
 use noodles::tabix;
 use noodles::vcf;
+use noodles::vcf::header::record::value::map::info::Number as InfoNumber;
+use noodles::vcf::header::record::value::map::info::Type as InfoType;
 use noodles::vcf::variant::record::{AlternateBases, Filters, Ids};
 use std::collections::HashMap;
 use std::fs::File;
-use std::path::PathBuf;
-use std::sync::{Arc, Mutex};
+use std::io::{Cursor, Read, Seek, SeekFrom};
+use std::ops::ControlFlow;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use tracing::{debug, error, info, warn};
 use vcf_filter::FilterEngine;
 
+use crate::block_cache::{BlockCache, BlockCacheStats, CachedBgzfReader};
+use crate::id_index::{
+    BloomStats, ExternalIdIndexBuilder, IdIndex, IdIndexBuilder, SampleCarriers, SourceFingerprint,
+    DEFAULT_RUN_ENTRIES,
+};
+use crate::reference::NormalizedAlleles;
+use crate::vrs::VrsAllele;
+
 // Genomic index enum - supports both tabix (.tbi) and CSI (.csi) indices
 #[derive(Debug)]
 pub enum GenomicIndex {
@@ -33,15 +50,276 @@ pub struct Variant {
     pub chromosome: String,
     pub position: u64,
     pub id: String,
+    pub ids: Vec<String>,
     pub reference: String,
     pub alternate: Vec<String>,
+    pub alternate_alleles: Vec<AlternateAllele>,
+    // None for records without a real ALT allele (e.g. gVCF reference blocks)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub variant_type: Option<VariantType>,
     pub quality: Option<f32>,
     pub filter: Vec<String>,
     pub info: HashMap<String, serde_json::Value>,
+    // FORMAT values of the samples requested with QueryOptions::samples, by sample name
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub samples: Option<HashMap<String, HashMap<String, String>>>,
+    // Left-aligned and trimmed alleles, when normalization against a reference was requested
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub normalized: Option<NormalizedAlleles>,
+    // ClinVar's CLNSIG, CLNREVSTAT and CLNDN decoded, for records carrying any of them
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub clinvar: Option<ClinVar>,
+    // Matching records of the annotation VCF, per ALT allele, when annotation was requested
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub annotation: Option<Vec<AlleleAnnotation>>,
+    // GA4GH VRS Alleles with their identifiers, one per ALT allele (None for alleles VRS can't
+    // express), when requested
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub vrs: Option<Vec<Option<VrsAllele>>>,
+    // SPDI expression of each ALT allele (None for symbolic alleles), on the chromosome's
+    // RefSeq accession when the build is known; set by the query tools
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub spdi: Option<Vec<Option<String>>>,
+    // Genome browser views of the variant, when requested
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub browser_links: Option<BrowserLinks>,
     #[serde(skip_serializing)]
     pub raw_row: String,
 }
 
+// URLs showing a variant in the IGV.js web app and the UCSC Genome Browser
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct BrowserLinks {
+    pub igv: String,
+    pub ucsc: String,
+}
+
+// What an annotation VCF (e.g. gnomAD sites or ClinVar) records for one ALT allele of a
+// variant: the INFO fields of its record with the same position, REF and ALT
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct AlleleAnnotation {
+    pub allele: String,
+    // The annotation record's ID, when it has one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    pub info: HashMap<String, serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub clinvar: Option<ClinVar>,
+}
+
+// ClinVar INFO fields decoded from their VCF encoding, where '_' stands for a space, '|'
+// separates conditions or secondary significances, '/' joins significances and ",_" joins
+// a modifier (e.g. "Pathogenic/Likely_pathogenic|risk_factor")
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct ClinVar {
+    // CLNSIG terms, e.g. ["Pathogenic", "Likely pathogenic", "risk factor"]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub significance: Vec<String>,
+    // CLNREVSTAT, e.g. "criteria provided, multiple submitters, no conflicts"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub review_status: Option<String>,
+    // ClinVar's gold stars for the review status (0-4)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub review_stars: Option<u8>,
+    // CLNDN condition names
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub conditions: Vec<String>,
+}
+
+impl ClinVar {
+    // Pathogenic or likely pathogenic, including modifiers such as "Pathogenic, low
+    // penetrance" but not "Conflicting interpretations of pathogenicity"
+    pub fn is_pathogenic(&self) -> bool {
+        self.significance.iter().any(|term| {
+            let term = term.to_ascii_lowercase();
+            term.starts_with("pathogenic") || term.starts_with("likely pathogenic")
+        })
+    }
+}
+
+// Raw value of an INFO key in a record's INFO column, percent-encoding and all
+fn raw_info_value<'a>(raw_row: &'a str, key: &str) -> Option<&'a str> {
+    raw_row.split('\t').nth(7)?.split(';').find_map(|field| {
+        field
+            .strip_prefix(key)
+            .and_then(|rest| rest.strip_prefix('='))
+    })
+}
+
+// A ClinVar value with '_' as spaces and the VCF percent-encodings undone
+fn clinvar_text(value: &str) -> String {
+    let mut text = value.replace('_', " ");
+    for (encoded, decoded) in [
+        ("%3A", ":"),
+        ("%3B", ";"),
+        ("%3D", "="),
+        ("%2C", ","),
+        ("%0D", "\r"),
+        ("%0A", "\n"),
+        ("%09", "\t"),
+        ("%25", "%"),
+    ] {
+        text = text.replace(encoded, decoded);
+    }
+    text.trim().to_string()
+}
+
+// The ClinVar fields of a record; None when it has none of CLNSIG, CLNREVSTAT and CLNDN
+pub fn decode_clinvar(raw_row: &str) -> Option<ClinVar> {
+    let significance = raw_info_value(raw_row, "CLNSIG");
+    let review_status = raw_info_value(raw_row, "CLNREVSTAT");
+    let conditions = raw_info_value(raw_row, "CLNDN");
+    if significance.is_none() && review_status.is_none() && conditions.is_none() {
+        return None;
+    }
+    let terms = |value: Option<&str>, separators: &[&str]| -> Vec<String> {
+        let mut parts = vec![value.unwrap_or_default().to_string()];
+        for separator in separators {
+            parts = parts
+                .iter()
+                .flat_map(|part| part.split(separator))
+                .map(str::to_string)
+                .collect();
+        }
+        parts
+            .iter()
+            .map(|part| clinvar_text(part))
+            .filter(|part| !part.is_empty() && part != ".")
+            .collect()
+    };
+    let review_status = review_status
+        .map(clinvar_text)
+        .filter(|status| !status.is_empty() && status != ".");
+    Some(ClinVar {
+        significance: terms(significance, &["|", "/", ",_"]),
+        review_stars: review_status.as_deref().and_then(review_stars),
+        review_status,
+        conditions: terms(conditions, &["|"]),
+    })
+}
+
+// ClinVar's star rating of a (decoded) CLNREVSTAT
+fn review_stars(status: &str) -> Option<u8> {
+    Some(match status {
+        "practice guideline" => 4,
+        "reviewed by expert panel" => 3,
+        "criteria provided, multiple submitters, no conflicts" => 2,
+        "criteria provided, single submitter"
+        | "criteria provided, conflicting interpretations"
+        | "criteria provided, conflicting classifications" => 1,
+        "no assertion criteria provided"
+        | "no assertion provided"
+        | "no interpretation for the single variant"
+        | "no classification provided"
+        | "no classification for the single variant" => 0,
+        _ => return None,
+    })
+}
+
+// Classification of an ALT allele so symbolic values aren't mistaken for bases
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AlleleType {
+    Sequence,         // Literal bases, e.g. A or GTC
+    SpanningDeletion, // '*' - allele removed by an upstream deletion
+    NonRef,           // <NON_REF> or <*> - any unobserved alternate (gVCF)
+    Symbolic,         // Structural variant symbol, e.g. <DEL>, <DUP:TANDEM>
+    Breakend,         // Breakend notation, e.g. G]17:198982]
+    Missing,          // '.'
+}
+
+// ALT allele with its classification
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct AlternateAllele {
+    pub allele: String,
+    pub allele_type: AlleleType,
+    // Symbol without angle brackets for symbolic alleles (e.g. "DEL")
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub symbol: Option<String>,
+}
+
+// Kind of change a record makes, derived from REF and its ALT alleles
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VariantType {
+    Snp,       // Single base substitutions
+    Insertion, // ALT longer than REF
+    Deletion,  // ALT shorter than REF
+    Mnp,       // Substitution of several bases, same length
+    Sv,        // Symbolic or breakend ALT allele
+    Complex,   // ALT alleles of different kinds
+}
+
+impl VariantType {
+    // The name used in tool parameters and responses
+    pub fn as_str(self) -> &'static str {
+        match self {
+            VariantType::Snp => "snp",
+            VariantType::Insertion => "insertion",
+            VariantType::Deletion => "deletion",
+            VariantType::Mnp => "mnp",
+            VariantType::Sv => "sv",
+            VariantType::Complex => "complex",
+        }
+    }
+}
+
+// Classify a record from its REF and ALT alleles. Any symbolic or breakend allele makes it
+// an SV; '*', <NON_REF> and missing alleles don't count, and a record with no other ALT
+// allele has no type.
+pub fn classify_variant(reference: &str, alleles: &[AlternateAllele]) -> Option<VariantType> {
+    let types: Vec<VariantType> = alleles
+        .iter()
+        .filter_map(|alt| match alt.allele_type {
+            AlleleType::Symbolic | AlleleType::Breakend => Some(VariantType::Sv),
+            AlleleType::Sequence => Some(match (reference.len(), alt.allele.len()) {
+                (1, 1) => VariantType::Snp,
+                (ref_len, alt_len) if ref_len < alt_len => VariantType::Insertion,
+                (ref_len, alt_len) if ref_len > alt_len => VariantType::Deletion,
+                _ => VariantType::Mnp,
+            }),
+            AlleleType::SpanningDeletion | AlleleType::NonRef | AlleleType::Missing => None,
+        })
+        .collect();
+    if types.contains(&VariantType::Sv) {
+        return Some(VariantType::Sv);
+    }
+    let first = *types.first()?;
+    Some(if types.iter().all(|&kind| kind == first) {
+        first
+    } else {
+        VariantType::Complex
+    })
+}
+
+// Classify a raw ALT allele string
+pub fn classify_allele(allele: &str) -> AlternateAllele {
+    let (allele_type, symbol) = if allele == "*" {
+        (AlleleType::SpanningDeletion, None)
+    } else if allele == "." || allele.is_empty() {
+        (AlleleType::Missing, None)
+    } else if let Some(symbol) = allele.strip_prefix('<').and_then(|a| a.strip_suffix('>')) {
+        if symbol == "NON_REF" || symbol == "*" {
+            (AlleleType::NonRef, Some(symbol.to_string()))
+        } else {
+            (AlleleType::Symbolic, Some(symbol.to_string()))
+        }
+    } else if allele.contains('[')
+        || allele.contains(']')
+        || (allele.len() > 1 && (allele.starts_with('.') || allele.ends_with('.')))
+    {
+        (AlleleType::Breakend, None)
+    } else {
+        (AlleleType::Sequence, None)
+    };
+
+    AlternateAllele {
+        allele: allele.to_string(),
+        allele_type,
+        symbol,
+    }
+}
+
 // VCF metadata structure extracted from header
 #[derive(Debug, Clone, serde::Serialize)]
 pub struct VcfMetadata {
@@ -49,6 +327,16 @@ pub struct VcfMetadata {
     pub reference_genome: ReferenceGenomeInfo,
     pub contigs: Vec<ContigInfo>,
     pub samples: Vec<String>,
+    // First/last variant position per chromosome, from the ID index
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub variant_ranges: Option<Vec<ChromosomeRange>>,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ChromosomeRange {
+    pub chromosome: String,
+    pub first_position: u64,
+    pub last_position: u64,
 }
 
 // Information about the reference genome build
@@ -67,11 +355,332 @@ pub enum ReferenceGenomeSource {
     Unknown,
 }
 
+// ID matched by search_ids with every location it occurs at
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct IdMatch {
+    pub id: String,
+    pub locations: Vec<IdLocation>,
+}
+
+// Per-call options for region queries. New query features add a field here instead of
+// another positional argument, and the MCP tool parameters map onto it one to one.
+#[derive(Debug, Clone, Default)]
+pub struct QueryOptions {
+    // Filter expression evaluated by the file's filter engine (empty: no filtering)
+    pub filter: String,
+    // Only variants carrying every one of these INFO flags
+    pub info_flags: Vec<String>,
+    // Checks on FILTER values and variant type, made while records are read
+    pub record_filter: RecordFilter,
+    // Projection: keep only these INFO keys in each variant (None keeps all of them)
+    pub info_fields: Option<Vec<String>>,
+    pub detail: DetailLevel,
+    // Sample subset: report the FORMAT values of these samples in `samples`
+    pub samples: Vec<String>,
+    pub sort: SortOrder,
+    // Pagination: return at most `limit` variants (None: all), resuming after `cursor`
+    pub limit: Option<usize>,
+    pub cursor: Option<RegionCursor>,
+    pub coordinates: CoordinateSystem,
+    // Explain mode: collect up to this many of the variants the filters leave out, with the
+    // checks each failed, in RegionPage::excluded
+    pub explain_excluded: usize,
+    // Only variants where this sample has an ALT allele in GT. With sample presence bitmaps in
+    // the ID index, records the sample doesn't carry are skipped before being parsed.
+    pub present_in_sample: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DetailLevel {
+    #[default]
+    Full,
+    // Core columns only: INFO values and ALT classifications are left empty
+    Summary,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SortOrder {
+    // File order
+    #[default]
+    Position,
+    // Highest QUAL first, variants without QUAL last; applied within each page
+    Quality,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Deserialize)]
+pub enum CoordinateSystem {
+    // VCF convention: 1-based positions, region end inclusive
+    #[default]
+    #[serde(rename = "1-based-inclusive", alias = "one_based")]
+    OneBased,
+    // BED convention: 0-based positions, region end exclusive
+    #[serde(rename = "0-based-half-open", alias = "zero_based")]
+    ZeroBased,
+}
+
+impl CoordinateSystem {
+    // A region in these coordinates as 1-based inclusive VCF coordinates
+    pub fn to_vcf_region(self, start: u64, end: u64) -> (u64, u64) {
+        match self {
+            Self::OneBased => (start, end),
+            Self::ZeroBased => (start.saturating_add(1), end),
+        }
+    }
+
+    // A 1-based VCF position in these coordinates
+    pub fn from_vcf_position(self, position: u64) -> u64 {
+        match self {
+            Self::OneBased => position,
+            Self::ZeroBased => position.saturating_sub(1),
+        }
+    }
+}
+
+// Where a paged region query resumes: the (1-based) position of the last variant returned
+// and how many matching variants at that position were already returned
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RegionCursor {
+    pub position: u64,
+    pub skip: usize,
+}
+
+impl RegionCursor {
+    // Cursors are exchanged as "position:skip"
+    pub fn parse(cursor: &str) -> Option<Self> {
+        let (position, skip) = cursor.split_once(':')?;
+        Some(RegionCursor {
+            position: position.parse().ok()?,
+            skip: skip.parse().ok()?,
+        })
+    }
+
+    pub fn encode(&self) -> String {
+        format!("{}:{}", self.position, self.skip)
+    }
+}
+
+// One page of a region query
+#[derive(Debug, Clone)]
+pub struct RegionPage {
+    pub variants: Vec<Variant>,
+    pub matched_chromosome: Option<String>,
+    // Set when more variants match after this page
+    pub next_cursor: Option<RegionCursor>,
+    // The progress callback stopped the scan; the page is incomplete
+    pub cancelled: bool,
+    // A server-side limit refused or stopped the query; the page is empty or incomplete
+    pub limit_exceeded: Option<LimitExceeded>,
+    // Reading the file failed (after any retries); the page is empty or incomplete
+    pub read_failure: Option<ReadFailure>,
+    // The first variants the filters left out, up to QueryOptions::explain_excluded
+    pub excluded: Vec<ExcludedVariant>,
+}
+
+// A variant a filtered query left out, with the filter clauses (see filter_clauses) and
+// record checks it failed, e.g. "QUAL >= 30" or "exclude_filters: q10"
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ExcludedVariant {
+    pub chromosome: String,
+    pub position: u64,
+    pub id: String,
+    pub reference: String,
+    pub alternate: Vec<String>,
+    pub failed: Vec<String>,
+}
+
+// A read of the VCF that failed, as opposed to a region that has no variants
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct ReadFailure {
+    pub message: String,
+    // The error looked transient (interrupted, timed out, connection dropped): the same
+    // query may succeed when repeated
+    pub transient: bool,
+    // Reads attempted, including retries
+    pub attempts: u32,
+}
+
+// How reads of the VCF are retried after transient I/O errors, e.g. on network file systems
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    // Retries after the first failed attempt (0 never retries)
+    pub retries: u32,
+    // Delay before the first retry, doubled before each further one
+    pub initial_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            retries: 0,
+            initial_delay: Duration::from_millis(100),
+        }
+    }
+}
+
+// Server-side limits on region queries (VcfIndex::query_region), set when the file is
+// loaded; None leaves that dimension unlimited
+#[derive(Debug, Clone, Copy, Default)]
+pub struct QueryLimits {
+    // Widest region, in bp, a query may span
+    pub max_region_bp: Option<u64>,
+    // Most variants one query may return; paged queries get pages of at most this size
+    pub max_results: Option<usize>,
+    // Longest one query may spend scanning
+    pub timeout: Option<Duration>,
+}
+
+// Which limit a region query ran into, with what the caller needs to narrow the query
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+#[serde(tag = "limit", rename_all = "snake_case")]
+pub enum LimitExceeded {
+    RegionSpan {
+        region_bp: u64,
+        max_region_bp: u64,
+    },
+    // More than `max_results` variants matched; the scan stopped at `position`
+    Results {
+        max_results: usize,
+        position: u64,
+    },
+    // The scan ran out of time at `position`, having read `records_read` records
+    Timeout {
+        timeout_ms: u64,
+        position: u64,
+        records_read: u64,
+    },
+}
+
+// How far a region scan has got, reported once per record read; the callback receiving it
+// returns ControlFlow::Break to abandon the scan (e.g. when the request was cancelled)
+#[derive(Debug, Clone, Copy)]
+pub struct ScanProgress {
+    pub records_read: u64,
+    pub matching: usize,
+    pub position: u64,
+}
+
+// A random sample of a region's variants (VcfIndex::sample_region_with_progress)
+#[derive(Debug, Clone, Default)]
+pub struct RegionSample {
+    // In file order
+    pub variants: Vec<Variant>,
+    // Matching variants the sample was drawn from; only those scanned when the scan stopped
+    pub population: usize,
+    pub matched_chromosome: Option<String>,
+    // The progress callback stopped the scan; the sample covers part of the region
+    pub cancelled: bool,
+    // A server-side limit refused or stopped the scan
+    pub limit_exceeded: Option<LimitExceeded>,
+    // Reading the file failed (after any retries)
+    pub read_failure: Option<ReadFailure>,
+}
+
+// Rough cost of a region query, worked out from the genomic index without reading records
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct RegionEstimate {
+    // Compressed bytes of the VCF the query would decompress and parse
+    pub compressed_bytes: u64,
+    // Variants expected in those bytes at the file's average record size; None until the
+    // statistics (and so the total variant count) are available
+    pub estimated_variants: Option<u64>,
+}
+
+// One page of the genome-wide ID map, one row per (ID, location)
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct IdPage {
+    pub page: usize,
+    pub page_size: usize,
+    pub total_ids: usize,
+    pub total_pages: usize,
+    pub next_page: Option<usize>,
+    pub ids: Vec<IdRow>,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct IdRow {
+    pub id: String,
+    pub chromosome: String,
+    pub position: u64,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct IdLocation {
+    pub chromosome: String,
+    pub position: u64,
+}
+
+// What one FILTER value means and how often it occurs in the file
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct FilterExplanation {
+    // The name as declared in the header (the requested name when it is not declared)
+    pub filter: String,
+    // Declared by a ##FILTER line; PASS, reserved by the VCF specification, always is
+    pub declared: bool,
+    pub description: Option<String>,
+    // Records carrying the filter and their share of all records; None while statistics
+    // are still being computed
+    pub count: Option<u64>,
+    pub fraction: Option<f64>,
+}
+
 #[derive(Debug, Clone, serde::Serialize)]
 pub struct ContigInfo {
     pub id: String,
 }
 
+// Samples carrying one ALT allele of a record, split by zygosity
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize)]
+pub struct AlleleCarriers {
+    pub heterozygous_count: usize,
+    pub homozygous_count: usize,
+    // Samples without a called allele in their GT ("./.", ".", or no GT at all)
+    pub no_call_count: usize,
+    pub heterozygous: Vec<String>,
+    pub homozygous: Vec<String>,
+    // True when either list was cut at the limit; the counts still cover every sample
+    pub truncated: bool,
+}
+
+// A chromosome with its declared length and, once statistics are ready, its record count
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct ContigSummary {
+    pub name: String,
+    pub length: Option<usize>,
+    pub variant_count: Option<u64>,
+}
+
+// One ##INFO, ##FORMAT, ##FILTER or ##contig header line as key/value pairs
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize)]
+pub struct HeaderDefinition {
+    pub id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub number: Option<String>,
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    pub ty: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    // Contig length in bp
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub length: Option<u64>,
+    // Any further keys of the line (Source, Version, assembly, ...)
+    #[serde(skip_serializing_if = "std::collections::BTreeMap::is_empty")]
+    pub other: std::collections::BTreeMap<String, String>,
+    // Names of the '|'-separated parts of annotation INFO fields such as VEP's CSQ or
+    // SnpEff's ANN, as listed in their descriptions
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub subfields: Option<Vec<String>>,
+}
+
+#[derive(Debug, Clone, Default, serde::Serialize)]
+pub struct HeaderDefinitions {
+    pub info: Vec<HeaderDefinition>,
+    pub format: Vec<HeaderDefinition>,
+    pub filter: Vec<HeaderDefinition>,
+    pub contig: Vec<HeaderDefinition>,
+}
+
 // VCF summary statistics structures
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct VcfStatistics {
@@ -86,7 +695,11 @@ pub struct VcfStatistics {
     pub missing_ids: u64,
     pub quality_stats: Option<QualityStats>,
     pub filter_counts: HashMap<String, u64>,
+    // Variant types of the records carrying each FILTER value ("." for records without one)
+    pub filter_variant_types: HashMap<String, VariantTypeStats>,
     pub variant_types: VariantTypeStats,
+    pub call_rates: CallRateStats,
+    pub qc_metrics: QcMetrics,
 }
 
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
@@ -96,7 +709,7 @@ pub struct QualityStats {
     pub mean: f32,
 }
 
-#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
 pub struct VariantTypeStats {
     pub snps: u64,
     pub insertions: u64,
@@ -105,1082 +718,5304 @@ pub struct VariantTypeStats {
     pub complex: u64,
 }
 
-// VCF index structure - supports both tabix (.tbi) and CSI (.csi) indices for efficient queries
-pub struct VcfIndex {
-    #[allow(dead_code)]
-    path: PathBuf,
-    index: GenomicIndex,
-    header: vcf::Header,
-    reader: Mutex<vcf::io::Reader<bgzf::io::Reader<File>>>,
-    id_index: HashMap<String, Vec<(String, u64)>>, // ID -> [(chromosome, position)]
-    filter_engine: Arc<FilterEngine>,              // Thread-safe filter engine
-    statistics: VcfStatistics,                     // Cached statistics computed at load time
-}
-
-impl VcfIndex {
-    // Helper to get alternate chromosome name
-    fn get_chromosome_variants(chromosome: &str) -> Vec<String> {
-        let mut variants = vec![chromosome.to_string()];
-        if let Some(stripped) = chromosome.strip_prefix("chr") {
-            variants.push(stripped.to_string());
+impl VariantTypeStats {
+    // Count a record by its REF and single ALT lengths; multi-allelic records and symbolic
+    // or '*' alleles (no comparable length) count as complex
+    pub fn add(&mut self, variant: &Variant) {
+        let ref_len = variant.reference.len();
+        if variant.alternate_alleles.len() == 1
+            && variant.alternate_alleles[0].allele_type == AlleleType::Sequence
+        {
+            let alt_len = variant.alternate[0].len();
+            if ref_len == 1 && alt_len == 1 {
+                self.snps += 1;
+            } else if ref_len < alt_len {
+                self.insertions += 1;
+            } else if ref_len > alt_len {
+                self.deletions += 1;
+            } else if ref_len == alt_len && ref_len > 1 {
+                self.mnps += 1;
+            } else {
+                self.complex += 1;
+            }
         } else {
-            variants.push(format!("chr{}", chromosome));
+            self.complex += 1;
         }
-        variants
     }
 
-    // Get list of chromosomes present in the VCF file
-    pub fn get_available_chromosomes(&self) -> Vec<String> {
-        // Try to get chromosomes from VCF header contigs first
-        let from_header: Vec<String> = self
-            .header
-            .contigs()
-            .keys()
-            .map(|k| k.to_string())
-            .collect();
+    pub fn total(&self) -> u64 {
+        self.snps + self.insertions + self.deletions + self.mnps + self.complex
+    }
+}
 
-        if !from_header.is_empty() {
-            return from_header;
+// Count a record's variant type under each of its FILTER values, or under "." when it has
+// none; a record failing several filters counts under each
+pub fn count_filter_types(counts: &mut HashMap<String, VariantTypeStats>, variant: &Variant) {
+    if variant.filter.is_empty() {
+        counts.entry(".".to_string()).or_default().add(variant);
+    }
+    for filter in &variant.filter {
+        counts.entry(filter.clone()).or_default().add(variant);
+    }
+}
+
+// Genotype call counts behind per-sample missingness and per-site call rates. A genotype is
+// called when its GT has no missing allele; "./1" and a sample without a GT count as missing.
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+pub struct CallRateStats {
+    // Records with a GT field
+    pub sites: u64,
+    // Records with a missing genotype, per sample in header column order
+    pub missing_per_sample: Vec<u64>,
+    // Records by the fraction of samples called: bin i holds rates in [i/10, (i+1)/10), the
+    // last bin also 1.0
+    pub site_call_rate_bins: [u64; 10],
+}
+
+impl CallRateStats {
+    pub fn new(sample_count: usize) -> Self {
+        CallRateStats {
+            missing_per_sample: vec![0; sample_count],
+            ..Default::default()
         }
+    }
 
-        // Fall back to index if header has no contigs
-        if let Some(header) = self.index.header() {
-            header
-                .reference_sequence_names()
-                .iter()
-                .map(|s| s.to_string())
-                .collect()
-        } else {
-            Vec::new()
+    // Count one record's genotypes; returns its call rate, or None for a record without GT
+    // (or a file without samples)
+    pub fn add(&mut self, raw_row: &str) -> Option<f64> {
+        let mut columns = raw_row.split('\t');
+        let slot = columns.nth(8)?.split(':').position(|field| field == "GT")?;
+        let sample_count = self.missing_per_sample.len();
+        if sample_count == 0 {
+            return None;
+        }
+        let mut called = 0;
+        for missing in self.missing_per_sample.iter_mut() {
+            let genotype = columns.next().and_then(|value| value.split(':').nth(slot));
+            if genotype.and_then(unordered_alleles).is_some() {
+                called += 1;
+            } else {
+                *missing += 1;
+            }
         }
+        self.sites += 1;
+        let rate = called as f64 / sample_count as f64;
+        self.site_call_rate_bins[((rate * 10.0) as usize).min(9)] += 1;
+        Some(rate)
     }
+}
 
-    // Check if a chromosome (or its variant) exists in the header
-    fn find_matching_chromosome(&self, chromosome: &str) -> Option<String> {
-        let variants = Self::get_chromosome_variants(chromosome);
-        let available = self.get_available_chromosomes();
+// Counts behind the standard QC ratios (Ti/Tv, het/hom, indel/SNP). SNV and indel counts are
+// per sequence ALT allele; genotype counts are per diploid sample call.
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct QcMetrics {
+    // Purine to purine or pyrimidine to pyrimidine (A<->G, C<->T)
+    pub transitions: u64,
+    pub transversions: u64,
+    // ALT alleles of a different length than REF
+    pub indels: u64,
+    // Calls with two different alleles, at least one ALT
+    pub heterozygous: u64,
+    // Calls whose two alleles are the same ALT
+    pub homozygous_alt: u64,
+}
 
-        variants
-            .into_iter()
-            .find(|variant| available.contains(variant))
-    }
+impl QcMetrics {
+    pub fn add(&mut self, variant: &Variant) {
+        for (alternate, allele) in variant.alternate.iter().zip(&variant.alternate_alleles) {
+            if allele.allele_type != AlleleType::Sequence {
+                continue;
+            }
+            if alternate.len() != variant.reference.len() {
+                self.indels += 1;
+            } else if alternate.len() == 1 {
+                match is_transition(&variant.reference, alternate) {
+                    Some(true) => self.transitions += 1,
+                    Some(false) => self.transversions += 1,
+                    None => {}
+                }
+            }
+        }
 
-    pub fn query_by_position(
-        &self,
-        chromosome: &str,
-        position: u64,
-    ) -> (Vec<Variant>, Option<String>) {
-        // Try to find the matching chromosome format
-        if let Some(matching_chr) = self.find_matching_chromosome(chromosome) {
-            let mut reader = self.reader.lock().unwrap();
-            let results = match &self.index {
-                GenomicIndex::Tabix(idx) => query_indexed_region(
-                    &mut reader,
-                    idx,
-                    &self.header,
-                    &matching_chr,
-                    position,
-                    position,
-                ),
-                GenomicIndex::Csi(idx) => query_indexed_region(
-                    &mut reader,
-                    idx,
-                    &self.header,
-                    &matching_chr,
-                    position,
-                    position,
-                ),
+        let mut columns = variant.raw_row.split('\t');
+        let Some(slot) = columns
+            .nth(8)
+            .and_then(|format| format.split(':').position(|field| field == "GT"))
+        else {
+            return;
+        };
+        for values in columns {
+            let Some(alleles) = values.split(':').nth(slot).and_then(unordered_alleles) else {
+                continue;
             };
-            return (results, Some(matching_chr));
+            match alleles[..] {
+                [first, second] if first != second => self.heterozygous += 1,
+                [first, second] if first > 0 => self.homozygous_alt += 1,
+                _ => {}
+            }
         }
-        (Vec::new(), None)
     }
 
-    pub fn query_by_region(
-        &self,
-        chromosome: &str,
-        start: u64,
-        end: u64,
-    ) -> (Vec<Variant>, Option<String>) {
-        // Try to find the matching chromosome format
-        if let Some(matching_chr) = self.find_matching_chromosome(chromosome) {
-            let mut reader = self.reader.lock().unwrap();
-            let results = match &self.index {
-                GenomicIndex::Tabix(idx) => {
-                    query_indexed_region(&mut reader, idx, &self.header, &matching_chr, start, end)
-                }
-                GenomicIndex::Csi(idx) => {
-                    query_indexed_region(&mut reader, idx, &self.header, &matching_chr, start, end)
-                }
-            };
-            return (results, Some(matching_chr));
-        }
-        (Vec::new(), None)
+    pub fn snvs(&self) -> u64 {
+        self.transitions + self.transversions
     }
+}
 
-    pub fn query_by_id(&self, id: &str) -> Vec<Variant> {
-        // Use the ID index for O(1) lookup
-        if let Some(locations) = self.id_index.get(id) {
-            let mut results = Vec::new();
-            let mut reader = self.reader.lock().unwrap();
-
-            for (chromosome, position) in locations {
-                let variants = match &self.index {
-                    GenomicIndex::Tabix(idx) => query_indexed_region(
-                        &mut reader,
-                        idx,
-                        &self.header,
-                        chromosome,
-                        *position,
-                        *position,
-                    ),
-                    GenomicIndex::Csi(idx) => query_indexed_region(
-                        &mut reader,
-                        idx,
-                        &self.header,
-                        chromosome,
-                        *position,
-                        *position,
-                    ),
-                };
-                results.extend(variants);
-            }
-
-            results
-        } else {
-            Vec::new()
-        }
+// Whether a single-base substitution is a transition; None for a non-ACGT base or no change
+fn is_transition(reference: &str, alternate: &str) -> Option<bool> {
+    let purine = |base: &str| match base.to_ascii_uppercase().as_str() {
+        "A" | "G" => Some(true),
+        "C" | "T" => Some(false),
+        _ => None,
+    };
+    if reference.eq_ignore_ascii_case(alternate) {
+        return None;
     }
+    Some(purine(reference)? == purine(alternate)?)
+}
 
-    pub fn get_metadata(&self) -> VcfMetadata {
-        extract_metadata(&self.header)
-    }
+// Equal-width histogram between the smallest and largest value, with the percentiles most
+// useful for picking a threshold
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct Histogram {
+    pub count: usize,
+    pub min: f64,
+    pub max: f64,
+    pub mean: f64,
+    pub percentiles: Percentiles,
+    pub bins: Vec<HistogramBin>,
+}
 
-    pub fn get_reference_genome(&self) -> String {
-        let metadata = self.get_metadata();
-        format!(
-            "{} ({})",
-            metadata.reference_genome.build,
-            match metadata.reference_genome.source {
-                ReferenceGenomeSource::HeaderLine => "from header",
-                ReferenceGenomeSource::InferredFromContigLengths => "inferred from contigs",
-                ReferenceGenomeSource::Unknown => "unknown source",
-            }
-        )
-    }
+// Nearest-rank percentiles
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct Percentiles {
+    pub p5: f64,
+    pub p25: f64,
+    pub p50: f64,
+    pub p75: f64,
+    pub p95: f64,
+}
 
-    pub fn get_header_string(&self, search: Option<&str>) -> String {
-        let mut buffer = Vec::new();
-        let mut writer = vcf::io::Writer::new(&mut buffer);
-        if writer.write_header(&self.header).is_ok() {
-            let full_header = String::from_utf8_lossy(&buffer).to_string();
+// Values in [start, end); the last bin also holds `end`
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct HistogramBin {
+    pub start: f64,
+    pub end: f64,
+    pub count: u64,
+}
 
-            // Apply search filter if provided, otherwise exclude ##contig lines by default
-            if let Some(search_str) = search {
-                full_header
-                    .lines()
-                    .filter(|line| line.contains(search_str))
-                    .collect::<Vec<_>>()
-                    .join("\n")
+impl Histogram {
+    // None without values; all-equal values make a single bin
+    pub fn of(values: &mut [f64], bins: usize) -> Option<Self> {
+        values.sort_by(f64::total_cmp);
+        let (&min, &max) = (values.first()?, values.last()?);
+        let bins = if max > min { bins.max(1) } else { 1 };
+        let width = (max - min) / bins as f64;
+        let mut counts = vec![0u64; bins];
+        for &value in values.iter() {
+            let bin = if width > 0.0 {
+                ((value - min) / width) as usize
             } else {
-                // Exclude ##contig lines by default (noodles doesn't include them anyway)
-                // This prevents clutter while keeping useful metadata
-                full_header
-                    .lines()
-                    .filter(|line| !line.starts_with("##contig"))
-                    .collect::<Vec<_>>()
-                    .join("\n")
-            }
-        } else {
-            "Error formatting header".to_string()
+                0
+            };
+            counts[bin.min(bins - 1)] += 1;
         }
+        let percentile = |p: usize| values[(values.len() * p).div_ceil(100).max(1) - 1];
+        Some(Histogram {
+            count: values.len(),
+            min,
+            max,
+            mean: values.iter().sum::<f64>() / values.len() as f64,
+            percentiles: Percentiles {
+                p5: percentile(5),
+                p25: percentile(25),
+                p50: percentile(50),
+                p75: percentile(75),
+                p95: percentile(95),
+            },
+            bins: counts
+                .into_iter()
+                .enumerate()
+                .map(|(i, count)| HistogramBin {
+                    start: min + width * i as f64,
+                    end: if i + 1 == bins {
+                        max
+                    } else {
+                        min + width * (i + 1) as f64
+                    },
+                    count,
+                })
+                .collect(),
+        })
     }
+}
 
-    // Get reference to the filter engine for evaluating filters
-    pub fn filter_engine(&self) -> Arc<FilterEngine> {
-        Arc::clone(&self.filter_engine)
+// Site frequency spectrum: ALT allele frequencies binned into equal-width bins over [0, 1],
+// or over [0, 0.5] of the minor allele frequency when folded
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct FrequencySpectrum {
+    pub folded: bool,
+    pub bins: Vec<HistogramBin>,
+    // ALT alleles counted, by where their frequency came from
+    pub alleles_from_info: u64,
+    pub alleles_from_genotypes: u64,
+    // Records with neither INFO/AF nor a called genotype, or without a real ALT allele
+    pub records_skipped: u64,
+}
+
+impl FrequencySpectrum {
+    pub fn new(bins: usize, folded: bool) -> Self {
+        let bins = bins.max(1);
+        let top = if folded { 0.5 } else { 1.0 };
+        let width = top / bins as f64;
+        FrequencySpectrum {
+            folded,
+            bins: (0..bins)
+                .map(|i| HistogramBin {
+                    start: width * i as f64,
+                    end: if i + 1 == bins {
+                        top
+                    } else {
+                        width * (i + 1) as f64
+                    },
+                    count: 0,
+                })
+                .collect(),
+            alleles_from_info: 0,
+            alleles_from_genotypes: 0,
+            records_skipped: 0,
+        }
     }
 
-    // Compute comprehensive statistics about the VCF file
-    pub fn compute_statistics(&self) -> std::io::Result<VcfStatistics> {
-        // Return cached statistics (computed at load time)
-        Ok(self.statistics.clone())
+    pub fn add(&mut self, variant: &Variant) {
+        let Some((frequencies, from_info)) = alternate_frequencies(variant) else {
+            self.records_skipped += 1;
+            return;
+        };
+        let top = self.bins.last().map_or(1.0, |bin| bin.end);
+        for frequency in frequencies {
+            let frequency = if self.folded {
+                frequency.min(1.0 - frequency)
+            } else {
+                frequency
+            };
+            let bin = (frequency.clamp(0.0, top) / top * self.bins.len() as f64) as usize;
+            let last = self.bins.len() - 1;
+            self.bins[bin.min(last)].count += 1;
+            if from_info {
+                self.alleles_from_info += 1;
+            } else {
+                self.alleles_from_genotypes += 1;
+            }
+        }
     }
 }
 
-// Helper function to query indexed VCF by region (generic over BinningIndex trait)
-fn query_indexed_region<I: BinningIndex>(
-    reader: &mut vcf::io::Reader<bgzf::io::Reader<File>>,
-    index: &I,
-    header: &vcf::Header,
-    chromosome: &str,
-    start: u64,
-    end: u64,
-) -> Vec<Variant> {
-    let mut results = Vec::new();
-
-    // Create region with Position types
-    let start_pos = match Position::try_from(start as usize) {
-        Ok(p) => p,
-        Err(_) => return results,
-    };
-    let end_pos = match Position::try_from(end as usize) {
-        Ok(p) => p,
-        Err(_) => return results,
-    };
-    let region = Region::new(chromosome, start_pos..=end_pos);
-
-    let query_result = match reader.query(header, index, &region) {
-        Ok(q) => q,
-        Err(_) => return results,
+// Frequency of each ALT allele, and whether it came from INFO/AF (one value per ALT) rather
+// than from the called GT alleles of all samples. None for a record with neither, or
+// without a real ALT allele.
+pub fn alternate_frequencies(variant: &Variant) -> Option<(Vec<f64>, bool)> {
+    let alternates = variant.alternate.len();
+    if variant
+        .alternate_alleles
+        .iter()
+        .all(|allele| allele.allele_type == AlleleType::Missing)
+    {
+        return None;
+    }
+    let from_info: Option<Vec<f64>> = match variant.info.get("AF") {
+        Some(serde_json::Value::Array(values)) => {
+            values.iter().map(|value| value.as_f64()).collect()
+        }
+        Some(value) => value.as_f64().map(|frequency| vec![frequency]),
+        None => None,
     };
+    if let Some(frequencies) = from_info.filter(|frequencies| frequencies.len() == alternates) {
+        return Some((frequencies, true));
+    }
 
-    for record in query_result.records().flatten() {
-        if let Ok(variant) = parse_variant_record(&record, header) {
-            results.push(variant);
+    let mut columns = variant.raw_row.split('\t');
+    let slot = columns.nth(8)?.split(':').position(|field| field == "GT")?;
+    let mut counts = vec![0u64; alternates + 1];
+    for values in columns {
+        let Some(genotype) = values.split(':').nth(slot) else {
+            continue;
+        };
+        for allele in genotype.split(['/', '|']) {
+            if let Some(count) = allele.parse::<usize>().ok().and_then(|a| counts.get_mut(a)) {
+                *count += 1;
+            }
         }
     }
+    let called: u64 = counts.iter().sum();
+    if called == 0 {
+        return None;
+    }
+    Some((
+        counts[1..]
+            .iter()
+            .map(|&count| count as f64 / called as f64)
+            .collect(),
+        false,
+    ))
+}
 
-    results
+// Largest position a tabix index can address (2^29 - 1)
+const MAX_TABIX_POSITION: u64 = (1 << 29) - 1;
+
+// VCF index structure - supports both tabix (.tbi) and CSI (.csi) indices for efficient queries
+pub struct VcfIndex {
+    #[allow(dead_code)]
+    path: PathBuf,
+    index: GenomicIndex,
+    header: vcf::Header,
+    readers: ReaderPool,
+    // The file has a header but no variant records
+    empty: bool,
+    limits: QueryLimits,
+    retry: RetryPolicy,
+    id_index: Arc<OnceLock<IdIndex>>, // ID -> [(chromosome, position)], FST-backed; unset while building
+    id_index_progress: Arc<BuildProgress>,
+    filter_engine: Arc<FilterEngine>, // Thread-safe filter engine
+    statistics: Arc<OnceLock<VcfStatistics>>, // Computed after the ID index (needs its ID count)
+    // The header as served when genotypes are hidden (LoadOptions::hide_genotypes): no FORMAT
+    // definitions and no samples. Records are still parsed with the file's own header.
+    redacted_header: Option<vcf::Header>,
 }
 
-// Helper function to infer genome build from contig lengths
-// GRCh37/hg19: chr1 = 249,250,621 bp
-// GRCh38/hg38: chr1 = 248,956,422 bp
-fn infer_genome_build_from_contigs(header: &vcf::Header) -> Option<String> {
-    const CHR1_GRCH37_LENGTH: usize = 249_250_621;
-    const CHR1_GRCH38_LENGTH: usize = 248_956_422;
-    const TOLERANCE: usize = 1000; // Allow small differences
+type VcfReader = vcf::io::Reader<CachedBgzfReader<VcfSource>>;
 
-    // Try both "chr1" and "1" naming conventions
-    for chr_name in ["chr1", "1"] {
-        if let Some(contig) = header.contigs().get(chr_name) {
-            if let Some(length) = contig.length() {
-                let diff_grch37 =
-                    (length as i64 - CHR1_GRCH37_LENGTH as i64).unsigned_abs() as usize;
-                let diff_grch38 =
-                    (length as i64 - CHR1_GRCH38_LENGTH as i64).unsigned_abs() as usize;
+// Readers over the VCF file shared by queries. Each query takes an idle reader (or opens a
+// new one) and returns it when done, so concurrent queries don't queue behind one reader.
+struct ReaderPool {
+    path: PathBuf,
+    // The whole file mapped once and shared by every reader (--mmap)
+    mapped: Option<Arc<Mmap>>,
+    // Decompressed blocks shared by all readers
+    block_cache: Arc<BlockCache>,
+    idle: Mutex<Vec<VcfReader>>,
+    // Readers kept open for reuse; extra ones opened under load are closed after use
+    max_idle: usize,
+}
 
-                if diff_grch37 < TOLERANCE {
-                    return Some("GRCh37".to_string());
-                } else if diff_grch38 < TOLERANCE {
-                    return Some("GRCh38".to_string());
-                }
+impl ReaderPool {
+    fn new(path: &PathBuf, block_cache_bytes: usize, mmap: bool) -> std::io::Result<Self> {
+        let max_idle = std::thread::available_parallelism().map_or(4, |n| n.get());
+        let mapped = if mmap {
+            let file = File::open(path)?;
+            // Safety: the VCF must not be modified in place while it is served, the same
+            // requirement the genomic and ID indexes already place on it
+            Some(Arc::new(unsafe { Mmap::map(&file)? }))
+        } else {
+            None
+        };
+        Ok(ReaderPool {
+            path: path.clone(),
+            mapped,
+            block_cache: Arc::new(BlockCache::new(block_cache_bytes)),
+            idle: Mutex::new(Vec::new()),
+            max_idle,
+        })
+    }
+
+    fn get(&self) -> std::io::Result<PooledReader<'_>> {
+        let idle = self.idle.lock().unwrap().pop();
+        let reader = match idle {
+            Some(mut reader) => {
+                // The last query may have been a bulk scan
+                reader.get_mut().set_fill_cache(true);
+                reader
             }
-        }
+            None => {
+                let source = match &self.mapped {
+                    Some(mmap) => VcfSource::Mapped(Cursor::new(MappedFile(Arc::clone(mmap)))),
+                    None => VcfSource::File(File::open(&self.path)?),
+                };
+                // Queries seek through the genomic index, so the header needn't be read first
+                vcf::io::Reader::new(CachedBgzfReader::new(source, Arc::clone(&self.block_cache)))
+            }
+        };
+        Ok(PooledReader {
+            pool: self,
+            reader: Some(reader),
+        })
     }
+}
 
-    None
+// Compressed VCF bytes behind a pooled reader: read from a file handle, or copied out of a
+// shared mapping without a system call per block
+enum VcfSource {
+    File(File),
+    Mapped(Cursor<MappedFile>),
 }
 
-// Helper function to extract reference genome from VCF header
-fn extract_reference_genome(header: &vcf::Header) -> ReferenceGenomeInfo {
-    use vcf::header::record::value::Collection;
+struct MappedFile(Arc<Mmap>);
 
-    // Try to get ##reference line from header
-    // Collection can be Unstructured (Vec<String>) or Structured (IndexMap)
-    // ##reference is typically unstructured with a single string value
-    if let Some(Collection::Unstructured(values)) = header.get("reference") {
-        if let Some(reference_value) = values.first() {
-            return ReferenceGenomeInfo {
-                build: reference_value.clone(),
-                source: ReferenceGenomeSource::HeaderLine,
-            };
-        }
+impl AsRef<[u8]> for MappedFile {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
     }
+}
 
-    // Fall back to inferring from contig lengths
-    if let Some(inferred_build) = infer_genome_build_from_contigs(header) {
-        return ReferenceGenomeInfo {
-            build: inferred_build,
-            source: ReferenceGenomeSource::InferredFromContigLengths,
-        };
+impl Read for VcfSource {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self {
+            Self::File(file) => file.read(buf),
+            Self::Mapped(cursor) => cursor.read(buf),
+        }
     }
+}
 
-    // Unknown
-    ReferenceGenomeInfo {
-        build: "Unknown".to_string(),
-        source: ReferenceGenomeSource::Unknown,
+impl Seek for VcfSource {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        match self {
+            Self::File(file) => file.seek(pos),
+            Self::Mapped(cursor) => cursor.seek(pos),
+        }
     }
 }
 
-// Helper function to extract metadata from VCF header
-fn extract_metadata(header: &vcf::Header) -> VcfMetadata {
-    // Extract file format version
-    let file_format = format!("{:?}", header.file_format());
-
-    // Extract reference genome information
-    let reference_genome = extract_reference_genome(header);
-
-    // Extract contig information
-    let contigs: Vec<ContigInfo> = header
-        .contigs()
-        .keys()
-        .map(|id| ContigInfo { id: id.to_string() })
-        .collect();
+// A reader borrowed from the pool, returned to it on drop
+struct PooledReader<'a> {
+    pool: &'a ReaderPool,
+    reader: Option<VcfReader>,
+}
 
-    // Extract sample names
-    let samples: Vec<String> = header
-        .sample_names()
-        .iter()
-        .map(|s| s.to_string())
-        .collect();
+impl std::ops::Deref for PooledReader<'_> {
+    type Target = VcfReader;
 
-    VcfMetadata {
-        file_format,
-        reference_genome,
-        contigs,
-        samples,
+    fn deref(&self) -> &VcfReader {
+        self.reader.as_ref().expect("reader is present until drop")
     }
 }
 
-// Helper function to convert debug-formatted info values to JSON
-// Converts: Integer(123) -> 123, Float(1.23) -> 1.23, String("foo") -> "foo", etc.
-fn convert_info_value(debug_str: &str) -> serde_json::Value {
-    let s = debug_str;
-
-    // Handle common patterns from noodles VCF library:
-    // Integer(123) -> JSON number
-    // Float(1.23) -> JSON number
-    // String("foo") -> JSON string
-    // Array([Ok(Some(1)), Ok(Some(2))]) -> JSON array
-    // Flag -> JSON true
-
-    if s == "Flag" {
-        return serde_json::Value::Bool(true);
+impl std::ops::DerefMut for PooledReader<'_> {
+    fn deref_mut(&mut self) -> &mut VcfReader {
+        self.reader.as_mut().expect("reader is present until drop")
     }
+}
 
-    // Match Integer(value)
-    if let Some(inner) = s.strip_prefix("Integer(").and_then(|s| s.strip_suffix(')')) {
-        if let Ok(num) = inner.parse::<i64>() {
-            return serde_json::Value::Number(num.into());
-        }
+impl PooledReader<'_> {
+    // Close the reader instead of returning it to the pool
+    fn discard(mut self) {
+        self.reader = None;
     }
+}
 
-    // Match Float(value)
-    if let Some(inner) = s.strip_prefix("Float(").and_then(|s| s.strip_suffix(')')) {
-        if let Ok(num) = inner.parse::<f64>() {
-            if let Some(json_num) = serde_json::Number::from_f64(num) {
-                return serde_json::Value::Number(json_num);
+impl Drop for PooledReader<'_> {
+    fn drop(&mut self) {
+        if let Some(reader) = self.reader.take() {
+            let mut idle = self.pool.idle.lock().unwrap();
+            if idle.len() < self.pool.max_idle {
+                idle.push(reader);
             }
         }
     }
+}
 
-    // Match Character(value)
-    if let Some(inner) = s
-        .strip_prefix("Character(")
-        .and_then(|s| s.strip_suffix(')'))
-    {
-        return serde_json::Value::String(inner.trim_matches('\'').to_string());
-    }
+// Progress of an ID index build running in the background
+#[derive(Debug, Default)]
+struct BuildProgress {
+    bytes_read: AtomicU64,
+    total_bytes: AtomicU64,
+    variants_scanned: AtomicU64,
+    error: Mutex<Option<String>>,
+    // Set at shutdown: the scan gives up at the next record
+    stop: AtomicBool,
+    // The build thread, until it is waited for
+    thread: Mutex<Option<std::thread::JoinHandle<()>>>,
+}
 
-    // Match String("value")
-    if let Some(inner) = s
-        .strip_prefix("String(\"")
-        .and_then(|s| s.strip_suffix("\")"))
-    {
-        return serde_json::Value::String(inner.to_string());
-    }
+// Availability of the ID index (and the statistics computed after it)
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum IdIndexStatus {
+    Ready,
+    Building {
+        percent_complete: f64,
+        variants_scanned: u64,
+    },
+    Failed {
+        error: String,
+    },
+}
 
-    // Match Array([...])
-    if let Some(inner) = s.strip_prefix("Array([").and_then(|s| s.strip_suffix("])")) {
-        // Extract Ok(Some(value)) patterns
-        let values: Vec<serde_json::Value> = inner
-            .split("), ")
-            .filter_map(|part| {
-                let part = part.trim_end_matches(')');
-                if let Some(val_str) = part.strip_prefix("Ok(Some(") {
-                    let val_str = val_str.trim_matches('"');
-                    // Try to parse as number first, otherwise string
-                    if let Ok(num) = val_str.parse::<i64>() {
-                        return Some(serde_json::Value::Number(num.into()));
-                    }
-                    if let Ok(num) = val_str.parse::<f64>() {
-                        if let Some(json_num) = serde_json::Number::from_f64(num) {
-                            return Some(serde_json::Value::Number(json_num));
+impl VcfIndex {
+    // Helper to get alternate chromosome name
+    fn get_chromosome_variants(chromosome: &str) -> Vec<String> {
+        let mut variants = vec![chromosome.to_string()];
+        if let Some(stripped) = chromosome.strip_prefix("chr") {
+            variants.push(stripped.to_string());
+        } else {
+            variants.push(format!("chr{}", chromosome));
+        }
+        variants
+    }
+
+    // Get list of chromosomes present in the VCF file
+    pub fn get_available_chromosomes(&self) -> Vec<String> {
+        // Try to get chromosomes from VCF header contigs first
+        let from_header: Vec<String> = self
+            .header
+            .contigs()
+            .keys()
+            .map(|k| k.to_string())
+            .collect();
+
+        if !from_header.is_empty() {
+            return from_header;
+        }
+
+        // Fall back to index if header has no contigs
+        if let Some(header) = self.index.header() {
+            header
+                .reference_sequence_names()
+                .iter()
+                .map(|s| s.to_string())
+                .collect()
+        } else {
+            Vec::new()
+        }
+    }
+
+    // Check if a chromosome (or its variant) exists in the header
+    fn find_matching_chromosome(&self, chromosome: &str) -> Option<String> {
+        let variants = Self::get_chromosome_variants(chromosome);
+        let available = self.get_available_chromosomes();
+
+        variants
+            .into_iter()
+            .find(|variant| available.contains(variant))
+    }
+
+    // A reader from the pool, or None (after logging why) if the file can't be reopened
+    fn reader(&self) -> Option<PooledReader<'_>> {
+        match self.readers.get() {
+            Ok(reader) => Some(reader),
+            Err(e) => {
+                warn!("Failed to open VCF reader: {}", e);
+                None
+            }
+        }
+    }
+
+    // True when the ID index shows no variant on the chromosome can overlap start..=end, so
+    // the query can be answered without touching the reader: from its variant start
+    // positions, or the first/last positions cached by indexes written without them
+    fn outside_variant_range(&self, chromosome: &str, start: u64, end: u64) -> bool {
+        let Some(id_index) = self.id_index.get() else {
+            return false;
+        };
+        if let Some(may_overlap) = id_index.may_overlap(chromosome, start, end) {
+            return !may_overlap;
+        }
+        if !id_index.has_position_ranges() {
+            return false;
+        }
+        match id_index.position_range(chromosome) {
+            Some((first, last)) => end < first || start > last,
+            // The chromosome has no variants at all
+            None => true,
+        }
+    }
+
+    pub fn query_by_position(
+        &self,
+        chromosome: &str,
+        position: u64,
+    ) -> (Vec<Variant>, Option<String>) {
+        // Try to find the matching chromosome format
+        if let Some(matching_chr) = self.find_matching_chromosome(chromosome) {
+            if self.outside_variant_range(&matching_chr, position, position) {
+                return (Vec::new(), Some(matching_chr));
+            }
+            let Some(mut reader) = self.reader() else {
+                return (Vec::new(), Some(matching_chr));
+            };
+            let results = match &self.index {
+                GenomicIndex::Tabix(idx) => query_indexed_region(
+                    &mut reader,
+                    idx,
+                    &self.header,
+                    &matching_chr,
+                    position,
+                    position,
+                    &[],
+                ),
+                GenomicIndex::Csi(idx) => query_indexed_region(
+                    &mut reader,
+                    idx,
+                    &self.header,
+                    &matching_chr,
+                    position,
+                    position,
+                    &[],
+                ),
+            };
+            let results = results.into_iter().map(|v| self.redact(v)).collect();
+            return (results, Some(matching_chr));
+        }
+        (Vec::new(), None)
+    }
+
+    // Annotations from this file for the ALT alleles `alternates` of a variant at `position`
+    // with REF `reference`: the INFO fields (all, or those in `fields`) of the records at the
+    // same position with the same REF and that ALT, compared ignoring case. Number=A and
+    // Number=R values are cut down to the matching allele's, so split and multi-allelic
+    // records give the same result; alleles must otherwise be written the same way.
+    pub fn allele_annotations(
+        &self,
+        chromosome: &str,
+        position: u64,
+        reference: &str,
+        alternates: &[String],
+        fields: &[String],
+    ) -> Result<Vec<AlleleAnnotation>, ReadFailure> {
+        let mut annotations: Vec<AlleleAnnotation> = Vec::new();
+        self.try_scan_region(chromosome, position, position, &[], &mut |record| {
+            if record.position != position || !record.reference.eq_ignore_ascii_case(reference) {
+                return true;
+            }
+            for (allele, alternate) in record.alternate.iter().enumerate() {
+                let wanted = alternates.iter().any(|a| a.eq_ignore_ascii_case(alternate));
+                let seen = annotations
+                    .iter()
+                    .any(|annotation| annotation.allele.eq_ignore_ascii_case(alternate));
+                if !wanted || seen {
+                    continue;
+                }
+                let info = record
+                    .info
+                    .iter()
+                    .filter(|(key, _)| fields.is_empty() || fields.contains(key))
+                    .map(|(key, value)| (key.clone(), self.allele_value(key, value, allele)))
+                    .collect();
+                annotations.push(AlleleAnnotation {
+                    allele: alternate.clone(),
+                    id: (record.id != ".").then(|| record.id.clone()),
+                    info,
+                    clinvar: record.clinvar.clone(),
+                });
+            }
+            true
+        })?;
+        annotations.sort_by_key(|annotation| {
+            alternates
+                .iter()
+                .position(|a| a.eq_ignore_ascii_case(&annotation.allele))
+        });
+        Ok(annotations)
+    }
+
+    // The part of INFO `key`'s value that belongs to ALT allele `allele` (0-based), going by
+    // the header's Number for the key
+    fn allele_value(
+        &self,
+        key: &str,
+        value: &serde_json::Value,
+        allele: usize,
+    ) -> serde_json::Value {
+        let element = match self.header.infos().get(key).map(|info| info.number()) {
+            Some(InfoNumber::AlternateBases) => allele,
+            Some(InfoNumber::ReferenceAlternateBases) => allele + 1,
+            _ => return value.clone(),
+        };
+        match value {
+            serde_json::Value::Array(values) => values
+                .get(element)
+                .cloned()
+                .unwrap_or(serde_json::Value::Null),
+            other => other.clone(),
+        }
+    }
+
+    // Start positions of the variants starting within `window` bp of `position` (at most
+    // `limit`), with the matched chromosome name. Answered from the ID index's variant
+    // positions without reading the VCF when it has them, else by scanning the window.
+    pub fn variant_starts_near(
+        &self,
+        chromosome: &str,
+        position: u64,
+        window: u64,
+        limit: usize,
+    ) -> Result<(NearbyVariants, Option<String>), ReadFailure> {
+        let start = position.saturating_sub(window);
+        let end = position.saturating_add(window);
+        let Some(matching_chr) = self.find_matching_chromosome(chromosome) else {
+            return Ok((NearbyVariants::default(), None));
+        };
+        // One more than the limit tells whether there were more
+        let (mut positions, from_index) =
+            self.starts_within(&matching_chr, start, end, limit.saturating_add(1))?;
+        let truncated = positions.len() > limit;
+        positions.truncate(limit);
+        Ok((
+            NearbyVariants {
+                positions,
+                truncated,
+                from_index,
+            },
+            Some(matching_chr),
+        ))
+    }
+
+    // The variants starting closest to `position` on each side, at most `max_distance` bp
+    // away, with the matched chromosome name. The search widens outward from one tabix bin
+    // (16 kb), doubling until both sides have a variant or it reaches max_distance, so
+    // nearby variants are found without reading the whole range.
+    pub fn nearest_variants(
+        &self,
+        chromosome: &str,
+        position: u64,
+        max_distance: u64,
+    ) -> Result<(NearestVariants, Option<String>), ReadFailure> {
+        const FIRST_WINDOW: u64 = 1 << 14;
+        let Some(matching_chr) = self.find_matching_chromosome(chromosome) else {
+            return Ok((NearestVariants::default(), None));
+        };
+        let prefilter = RecordPrefilter::default();
+        let mut nearest = NearestVariants::default();
+        self.scan_with_retry(
+            &matching_chr,
+            position,
+            position,
+            &prefilter,
+            &mut |variant| {
+                if variant.position == position {
+                    nearest.at_position.push(variant);
+                }
+                true
+            },
+        )?;
+
+        let mut window = FIRST_WINDOW.min(max_distance);
+        while window > nearest.searched_distance {
+            // Upstream: the last start in the newly widened stretch below the position
+            let upstream_end = position.saturating_sub(nearest.searched_distance + 1);
+            if nearest.upstream.is_none() && upstream_end >= 1 {
+                let upstream_start = position.saturating_sub(window).max(1);
+                let mut closest: Vec<Variant> = Vec::new();
+                self.scan_with_retry(
+                    &matching_chr,
+                    upstream_start,
+                    upstream_end,
+                    &prefilter,
+                    &mut |variant| {
+                        // Records overlapping the stretch from the left start outside it
+                        if variant.position < upstream_start || variant.position > upstream_end {
+                            return true;
                         }
-                    }
-                    return Some(serde_json::Value::String(val_str.to_string()));
+                        if closest
+                            .first()
+                            .is_some_and(|first| first.position < variant.position)
+                        {
+                            closest.clear();
+                        }
+                        closest.push(variant);
+                        true
+                    },
+                )?;
+                if let Some(first) = closest.first() {
+                    nearest.upstream = Some(NearestVariant {
+                        distance: position - first.position,
+                        variants: closest,
+                    });
                 }
-                None
+            }
+            // Downstream: the first start in the newly widened stretch above the position
+            if nearest.downstream.is_none() {
+                let downstream_start = position.saturating_add(nearest.searched_distance + 1);
+                let mut closest: Vec<Variant> = Vec::new();
+                self.scan_with_retry(
+                    &matching_chr,
+                    downstream_start,
+                    position.saturating_add(window),
+                    &prefilter,
+                    &mut |variant| {
+                        if variant.position < downstream_start {
+                            return true;
+                        }
+                        if closest
+                            .first()
+                            .is_some_and(|first| first.position < variant.position)
+                        {
+                            return false;
+                        }
+                        closest.push(variant);
+                        true
+                    },
+                )?;
+                if let Some(first) = closest.first() {
+                    nearest.downstream = Some(NearestVariant {
+                        distance: first.position - position,
+                        variants: closest,
+                    });
+                }
+            }
+            nearest.searched_distance = window;
+            if nearest.upstream.is_some() && nearest.downstream.is_some() {
+                break;
+            }
+            window = window.saturating_mul(2).min(max_distance);
+        }
+        Ok((nearest, Some(matching_chr)))
+    }
+
+    // Up to `limit` distinct start positions within start..=end of a chromosome named as in
+    // the file, and whether they came from the ID index rather than a scan of the VCF
+    fn starts_within(
+        &self,
+        chromosome: &str,
+        start: u64,
+        end: u64,
+        limit: usize,
+    ) -> Result<(Vec<u64>, bool), ReadFailure> {
+        if let Some(positions) = self
+            .id_index
+            .get()
+            .and_then(|id_index| id_index.variant_starts(chromosome, start, end, limit))
+        {
+            return Ok((positions, true));
+        }
+        let mut positions: Vec<u64> = Vec::new();
+        if limit == 0 {
+            return Ok((positions, false));
+        }
+        self.scan_with_retry(
+            chromosome,
+            start,
+            end,
+            &RecordPrefilter::default(),
+            &mut |variant| {
+                // Records overlapping the window from the left start outside it
+                if variant.position >= start && positions.last() != Some(&variant.position) {
+                    positions.push(variant.position);
+                }
+                positions.len() < limit
+            },
+        )?;
+        Ok((positions, false))
+    }
+
+    // The file's name for a chromosome and where it ends: its declared length, else its
+    // last indexed variant. None for a chromosome not in the file.
+    pub fn chromosome_extent(&self, chromosome: &str) -> Option<(String, Option<u64>)> {
+        let matching_chr = self.find_matching_chromosome(chromosome)?;
+        let end = self
+            .header
+            .contigs()
+            .get(matching_chr.as_str())
+            .and_then(|contig| contig.length())
+            .map(|length| length as u64)
+            .or_else(|| {
+                let (_, last) = self.id_index.get()?.position_range(&matching_chr)?;
+                Some(last)
+            });
+        Some((matching_chr, end))
+    }
+
+    // Variant start positions per `window_size` bp window of start..=end on a chromosome
+    // named as in the file (see chromosome_extent). Records sharing a position count once.
+    pub fn variant_density(
+        &self,
+        chromosome: &str,
+        start: u64,
+        end: u64,
+        window_size: u64,
+    ) -> Result<VariantDensity, ReadFailure> {
+        let window_size = window_size.max(1);
+        let windows = (end.saturating_sub(start) / window_size + 1) as usize;
+        let mut counts = vec![0u64; windows];
+        let (positions, from_index) = self.starts_within(chromosome, start, end, usize::MAX)?;
+        for position in positions {
+            counts[((position - start) / window_size) as usize] += 1;
+        }
+        Ok(VariantDensity {
+            start,
+            end,
+            window_size,
+            counts,
+            from_index,
+        })
+    }
+
+    // The variants of a region, within the server's QueryLimits like query_region: a query
+    // the limits refuse or stop is logged and returns the variants read before that
+    pub fn query_by_region(
+        &self,
+        chromosome: &str,
+        start: u64,
+        end: u64,
+    ) -> (Vec<Variant>, Option<String>) {
+        self.query_by_region_with_info_flags(chromosome, start, end, &[])
+    }
+
+    // Like query_by_region, but only returns variants carrying every INFO flag in `info_flags`
+    pub fn query_by_region_with_info_flags(
+        &self,
+        chromosome: &str,
+        start: u64,
+        end: u64,
+        info_flags: &[String],
+    ) -> (Vec<Variant>, Option<String>) {
+        let options = QueryOptions {
+            info_flags: info_flags.to_vec(),
+            ..Default::default()
+        };
+        let page = self.query_region(chromosome, start, end, &options);
+        if let Some(exceeded) = &page.limit_exceeded {
+            warn!(
+                "Region query {}:{}-{} stopped: {:?}",
+                chromosome, start, end, exceeded
+            );
+        }
+        if let Some(failure) = &page.read_failure {
+            warn!("Failed to read VCF: {}", failure.message);
+        }
+        (page.variants, page.matched_chromosome)
+    }
+
+    // Visit the variants of a region carrying every flag in `info_flags` in file order,
+    // without collecting them; `visit` returns false to stop the scan early. Returns the
+    // matched chromosome name. A failed read is logged and ends the scan.
+    pub fn scan_region(
+        &self,
+        chromosome: &str,
+        start: u64,
+        end: u64,
+        info_flags: &[String],
+        visit: &mut dyn FnMut(Variant) -> bool,
+    ) -> Option<String> {
+        self.try_scan_region(chromosome, start, end, info_flags, visit)
+            .unwrap_or_else(|failure| {
+                warn!("Failed to read VCF: {}", failure.message);
+                self.find_matching_chromosome(chromosome)
             })
-            .collect();
-        return serde_json::Value::Array(values);
     }
 
-    // Fall back to string if no pattern matched
-    serde_json::Value::String(s.to_string())
+    // try_scan_region over every chromosome in natural order, until `visit` returns false.
+    // Chromosomes are read up to their declared length, else up to their last indexed variant.
+    pub fn try_scan_file(&self, visit: &mut dyn FnMut(Variant) -> bool) -> Result<(), ReadFailure> {
+        for contig in self.contig_summaries() {
+            let end = contig
+                .length
+                .map(|length| length as u64)
+                .or_else(|| {
+                    let (_, last) = self.id_index.get()?.position_range(&contig.name)?;
+                    Some(last)
+                })
+                .unwrap_or(MAX_TABIX_POSITION);
+            let mut stopped = false;
+            self.try_scan_region(&contig.name, 1, end, &[], &mut |variant| {
+                stopped = !visit(variant);
+                !stopped
+            })?;
+            if stopped {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    // Like scan_region, but reports a read that still failed after the retries allowed by
+    // LoadOptions::retry, so callers can tell it apart from a region without variants
+    pub fn try_scan_region(
+        &self,
+        chromosome: &str,
+        start: u64,
+        end: u64,
+        info_flags: &[String],
+        visit: &mut dyn FnMut(Variant) -> bool,
+    ) -> Result<Option<String>, ReadFailure> {
+        self.scan_carriers_of(chromosome, start, end, info_flags, None, visit)
+    }
+
+    // try_scan_region, also skipping records at positions where the ID index's sample
+    // presence bitmaps show no ALT allele for sample column `carrier` (when it has them)
+    fn scan_carriers_of(
+        &self,
+        chromosome: &str,
+        start: u64,
+        end: u64,
+        info_flags: &[String],
+        carrier: Option<usize>,
+        visit: &mut dyn FnMut(Variant) -> bool,
+    ) -> Result<Option<String>, ReadFailure> {
+        // Try to find the matching chromosome format
+        let Some(matching_chr) = self.find_matching_chromosome(chromosome) else {
+            return Ok(None);
+        };
+        let prefilter = RecordPrefilter {
+            info_flags,
+            carriers: carrier
+                .and_then(|sample| self.id_index.get()?.sample_carriers(&matching_chr, sample)),
+        };
+        if !self.outside_variant_range(&matching_chr, start, end) {
+            self.scan_with_retry(&matching_chr, start, end, &prefilter, visit)?;
+        }
+        Ok(Some(matching_chr))
+    }
+
+    // Scan a region of a chromosome named as in the file, retrying transient read errors on
+    // a fresh reader after a growing delay. A retry resumes after the last record visited, so
+    // `visit` sees every record once. Regions covering more than a quarter of the block
+    // cache are read through it without adding their blocks (see CachedBgzfReader).
+    fn scan_with_retry(
+        &self,
+        chromosome: &str,
+        start: u64,
+        end: u64,
+        prefilter: &RecordPrefilter,
+        visit: &mut dyn FnMut(Variant) -> bool,
+    ) -> Result<(), ReadFailure> {
+        let mut resume = start;
+        // Position of the last record visited, and how many records there were visited
+        let mut last: Option<(u64, usize)> = None;
+        let mut delay = self.retry.initial_delay;
+        let mut attempts = 0;
+        let cache_capacity = self.readers.block_cache.capacity() as u64;
+        let bulk = cache_capacity > 0
+            && self.region_compressed_bytes(chromosome, start, end) > cache_capacity / 4;
+
+        loop {
+            attempts += 1;
+            let retrying = attempts > 1;
+            let mut skip = last
+                .filter(|&(position, _)| retrying && position == resume)
+                .map_or(0, |(_, visited)| visited);
+            let mut visit_once = |variant: Variant| {
+                // Records overlapping the resume position from the left were visited already
+                if retrying && variant.position < resume {
+                    return true;
+                }
+                if skip > 0 && variant.position == resume {
+                    skip -= 1;
+                    return true;
+                }
+                last = Some(match last {
+                    Some((position, visited)) if position == variant.position => {
+                        (position, visited + 1)
+                    }
+                    _ => (variant.position, 1),
+                });
+                visit(self.redact(variant))
+            };
+
+            let result = self.readers.get().and_then(|mut reader| {
+                reader.get_mut().set_fill_cache(!bulk);
+                let result = match &self.index {
+                    GenomicIndex::Tabix(idx) => scan_indexed_region(
+                        &mut reader,
+                        idx,
+                        &self.header,
+                        chromosome,
+                        resume,
+                        end,
+                        prefilter,
+                        &mut visit_once,
+                    ),
+                    GenomicIndex::Csi(idx) => scan_indexed_region(
+                        &mut reader,
+                        idx,
+                        &self.header,
+                        chromosome,
+                        resume,
+                        end,
+                        prefilter,
+                        &mut visit_once,
+                    ),
+                };
+                if result.is_err() {
+                    // The reader may be left mid-block; don't hand it to another query
+                    reader.discard();
+                }
+                result
+            });
+
+            match result {
+                Ok(()) => return Ok(()),
+                Err(e) if is_transient(&e) && attempts <= self.retry.retries => {
+                    warn!(
+                        "Transient error reading VCF (attempt {}): {}; retrying in {} ms",
+                        attempts,
+                        e,
+                        delay.as_millis()
+                    );
+                    std::thread::sleep(delay);
+                    delay = delay.saturating_mul(2);
+                    if let Some((position, _)) = last {
+                        resume = position;
+                    }
+                }
+                Err(e) => {
+                    return Err(ReadFailure {
+                        message: e.to_string(),
+                        transient: is_transient(&e),
+                        attempts,
+                    })
+                }
+            }
+        }
+    }
+
+    // Query a region with per-call options (filtering, projection, paging, sorting, sample
+    // subset and coordinate system)
+    pub fn query_region(
+        &self,
+        chromosome: &str,
+        start: u64,
+        end: u64,
+        options: &QueryOptions,
+    ) -> RegionPage {
+        self.query_region_with_progress(chromosome, start, end, options, &mut |_| {
+            ControlFlow::Continue(())
+        })
+    }
+
+    // Like query_region, calling `progress` after every record read; the scan stops early
+    // when it returns Break. A full page also stops the scan, so paged queries of whole
+    // chromosomes return quickly.
+    pub fn query_region_with_progress(
+        &self,
+        chromosome: &str,
+        start: u64,
+        end: u64,
+        options: &QueryOptions,
+        progress: &mut dyn FnMut(&ScanProgress) -> ControlFlow<()>,
+    ) -> RegionPage {
+        let (vcf_start, vcf_end) = options.coordinates.to_vcf_region(start, end);
+        if let Err(exceeded) = self.check_region_span(vcf_start, vcf_end) {
+            return RegionPage {
+                variants: Vec::new(),
+                matched_chromosome: None,
+                next_cursor: None,
+                cancelled: false,
+                limit_exceeded: Some(exceeded),
+                read_failure: None,
+                excluded: Vec::new(),
+            };
+        }
+        self.query_region_page(chromosome, start, end, options, progress)
+    }
+
+    // Like query_region, but regions of any span are read: for streaming sessions
+    // (start_region_query, get_next_variant), which walk a region a small page per call, so
+    // each call stays within the result and timeout limits however wide the region is
+    pub fn stream_region(
+        &self,
+        chromosome: &str,
+        start: u64,
+        end: u64,
+        options: &QueryOptions,
+    ) -> RegionPage {
+        self.query_region_page(chromosome, start, end, options, &mut |_| {
+            ControlFlow::Continue(())
+        })
+    }
+
+    // query_region_with_progress after the region span check
+    fn query_region_page(
+        &self,
+        chromosome: &str,
+        start: u64,
+        end: u64,
+        options: &QueryOptions,
+        progress: &mut dyn FnMut(&ScanProgress) -> ControlFlow<()>,
+    ) -> RegionPage {
+        let (start, end) = options.coordinates.to_vcf_region(start, end);
+        // Pages never exceed max_results; an unpaged query that would is refused instead
+        let max_results = self.limits.max_results;
+        let limit = match (options.limit, max_results) {
+            (Some(limit), Some(max)) => Some(limit.min(max)),
+            (limit, None) => limit,
+            (None, Some(_)) => None,
+        };
+        let started = Instant::now();
+        let resume = options.cursor;
+        let mut variants: Vec<Variant> = Vec::new();
+        let mut excluded: Vec<ExcludedVariant> = Vec::new();
+        let mut records_read = 0u64;
+        let mut skip = resume.map_or(0, |resume| resume.skip);
+        let mut more = false;
+        let mut cancelled = false;
+        let mut limit_exceeded = None;
+        // An unknown sample carries nothing
+        let carrier = options
+            .present_in_sample
+            .as_deref()
+            .map(|name| self.sample_index(name).unwrap_or(usize::MAX));
+
+        let scan = self.scan_carriers_of(
+            chromosome,
+            resume.map_or(start, |resume| resume.position),
+            end,
+            &options.info_flags,
+            carrier,
+            &mut |variant| {
+                records_read += 1;
+                let flow = progress(&ScanProgress {
+                    records_read,
+                    matching: variants.len(),
+                    position: variant.position,
+                });
+                if flow.is_break() {
+                    cancelled = true;
+                    return false;
+                }
+                if let Some(timeout) = self.limits.timeout {
+                    if started.elapsed() > timeout {
+                        limit_exceeded = Some(LimitExceeded::Timeout {
+                            timeout_ms: timeout.as_millis() as u64,
+                            position: variant.position,
+                            records_read,
+                        });
+                        return false;
+                    }
+                }
+
+                if let Some(resume) = resume {
+                    // Records starting before the cursor were on an earlier page
+                    if variant.position < resume.position {
+                        return true;
+                    }
+                }
+                let matches = options.record_filter.admits(&variant)
+                    && carrier.is_none_or(|sample| carries_alternate(&variant.raw_row, sample))
+                    && (options.filter.trim().is_empty()
+                        || self
+                            .filter_engine
+                            .evaluate(&options.filter, &variant.raw_row)
+                            .unwrap_or(false)); // Treat filter errors as non-match
+                if !matches {
+                    if excluded.len() < options.explain_excluded {
+                        excluded.push(self.explain_exclusion(&variant, options));
+                    }
+                    return true;
+                }
+                if resume.is_some_and(|resume| variant.position == resume.position) && skip > 0 {
+                    skip -= 1;
+                    return true;
+                }
+                if limit.is_some_and(|limit| variants.len() >= limit) {
+                    more = true;
+                    return false;
+                }
+                if let Some(max_results) = max_results {
+                    if limit.is_none() && variants.len() >= max_results {
+                        limit_exceeded = Some(LimitExceeded::Results {
+                            max_results,
+                            position: variant.position,
+                        });
+                        return false;
+                    }
+                }
+                variants.push(variant);
+                true
+            },
+        );
+
+        let next_cursor = variants.last().filter(|_| more).map(|last| {
+            let at_last = variants
+                .iter()
+                .filter(|v| v.position == last.position)
+                .count();
+            let earlier = resume
+                .filter(|resume| resume.position == last.position)
+                .map_or(0, |resume| resume.skip);
+            RegionCursor {
+                position: last.position,
+                skip: earlier + at_last,
+            }
+        });
+
+        if options.sort == SortOrder::Quality {
+            variants.sort_by(|a, b| match (a.quality, b.quality) {
+                (Some(a), Some(b)) => b.total_cmp(&a),
+                (Some(_), None) => std::cmp::Ordering::Less,
+                (None, Some(_)) => std::cmp::Ordering::Greater,
+                (None, None) => std::cmp::Ordering::Equal,
+            });
+        }
+        let samples: Vec<(&str, usize)> = options
+            .samples
+            .iter()
+            .filter_map(|name| Some((name.as_str(), self.sample_index(name)?)))
+            .collect();
+        for variant in &mut variants {
+            shape_variant(variant, options, &samples);
+        }
+
+        let (matched_chromosome, read_failure) = match scan {
+            Ok(matched_chromosome) => (matched_chromosome, None),
+            Err(failure) => (self.find_matching_chromosome(chromosome), Some(failure)),
+        };
+
+        RegionPage {
+            variants,
+            matched_chromosome,
+            next_cursor,
+            cancelled,
+            limit_exceeded,
+            read_failure,
+            excluded,
+        }
+    }
+
+    // Split `filter` into clauses (see filter_clauses) and evaluate each on its own against
+    // `variant`: returns the clauses it matched and those it failed. A clause the engine
+    // can't evaluate counts as failed, as a whole filter would.
+    pub fn evaluate_clauses(&self, filter: &str, variant: &Variant) -> (Vec<String>, Vec<String>) {
+        filter_clauses(filter).into_iter().partition(|clause| {
+            self.filter_engine
+                .evaluate(clause, &variant.raw_row)
+                .unwrap_or(false)
+        })
+    }
+
+    fn explain_exclusion(&self, variant: &Variant, options: &QueryOptions) -> ExcludedVariant {
+        let mut failed = options.record_filter.failures(variant);
+        if let Some(name) = &options.present_in_sample {
+            let carried = self
+                .sample_index(name)
+                .is_some_and(|sample| carries_alternate(&variant.raw_row, sample));
+            if !carried {
+                failed.push(format!("present_in_sample: no ALT allele in {}", name));
+            }
+        }
+        failed.extend(self.evaluate_clauses(&options.filter, variant).1);
+        ExcludedVariant {
+            chromosome: variant.chromosome.clone(),
+            position: options.coordinates.from_vcf_position(variant.position),
+            id: variant.id.clone(),
+            reference: variant.reference.clone(),
+            alternate: variant.alternate.clone(),
+            failed,
+        }
+    }
+
+    // The limits set when the file was loaded (see LoadOptions::limits)
+    pub fn limits(&self) -> QueryLimits {
+        self.limits
+    }
+
+    // Refuse a region (1-based, inclusive) wider than --max-region-bp
+    pub fn check_region_span(&self, start: u64, end: u64) -> Result<(), LimitExceeded> {
+        let region_bp = (end + 1).saturating_sub(start);
+        match self.limits.max_region_bp {
+            Some(max_region_bp) if region_bp > max_region_bp => Err(LimitExceeded::RegionSpan {
+                region_bp,
+                max_region_bp,
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    // Estimate how much of the file a query of start..=end would read, with the matched
+    // chromosome name; None when the chromosome is not in the file
+    pub fn estimate_region(
+        &self,
+        chromosome: &str,
+        start: u64,
+        end: u64,
+    ) -> Option<(RegionEstimate, String)> {
+        let matching_chr = self.find_matching_chromosome(chromosome)?;
+        let compressed_bytes = self.region_compressed_bytes(&matching_chr, start, end);
+        let estimated_variants = self.statistics.get().and_then(|stats| {
+            let file_size = std::fs::metadata(&self.path).ok()?.len();
+            (file_size > 0).then(|| {
+                (u128::from(compressed_bytes) * u128::from(stats.total_variants)
+                    / u128::from(file_size)) as u64
+            })
+        });
+
+        Some((
+            RegionEstimate {
+                compressed_bytes,
+                estimated_variants,
+            },
+            matching_chr,
+        ))
+    }
+
+    // Compressed bytes the genomic index says cover start..=end of a chromosome named as in
+    // the file
+    fn region_compressed_bytes(&self, chromosome: &str, start: u64, end: u64) -> u64 {
+        let (Ok(start), Ok(end)) = (
+            Position::try_from(start.max(1) as usize),
+            Position::try_from(end.max(1) as usize),
+        ) else {
+            return 0;
+        };
+        let chunks = match &self.index {
+            GenomicIndex::Tabix(idx) => region_chunks(idx, &self.header, chromosome, start, end),
+            GenomicIndex::Csi(idx) => region_chunks(idx, &self.header, chromosome, start, end),
+        }
+        .unwrap_or_default();
+        chunks
+            .iter()
+            .map(|chunk| {
+                chunk
+                    .end()
+                    .compressed()
+                    .saturating_sub(chunk.start().compressed())
+            })
+            .sum()
+    }
+
+    // Draw a uniform random sample of up to `count` variants from a region, optionally
+    // restricted to variants passing `filter` and `record_filter`. The same seed always
+    // yields the same sample.
+    // Returns the sample in position order, the number of variants it was drawn from and
+    // the matched chromosome name.
+    #[allow(clippy::too_many_arguments)]
+    pub fn sample_region(
+        &self,
+        chromosome: &str,
+        start: u64,
+        end: u64,
+        count: usize,
+        seed: u64,
+        filter: &str,
+        record_filter: &RecordFilter,
+    ) -> (Vec<Variant>, usize, Option<String>) {
+        let sample = self.sample_region_with_progress(
+            chromosome,
+            start,
+            end,
+            count,
+            seed,
+            filter,
+            record_filter,
+            &mut |_| ControlFlow::Continue(()),
+        );
+        if let Some(failure) = &sample.read_failure {
+            warn!("Failed to read VCF: {}", failure.message);
+        }
+        (
+            sample.variants,
+            sample.population,
+            sample.matched_chromosome,
+        )
+    }
+
+    // Like sample_region, calling `progress` after every record read and stopping early when
+    // it returns Break. The region is streamed through a reservoir (Algorithm R), so memory
+    // stays proportional to `count` however many variants the region holds. The region span
+    // and timeout limits apply as to query_region.
+    #[allow(clippy::too_many_arguments)]
+    pub fn sample_region_with_progress(
+        &self,
+        chromosome: &str,
+        start: u64,
+        end: u64,
+        count: usize,
+        seed: u64,
+        filter: &str,
+        record_filter: &RecordFilter,
+        progress: &mut dyn FnMut(&ScanProgress) -> ControlFlow<()>,
+    ) -> RegionSample {
+        let mut sample = RegionSample::default();
+        if let Err(exceeded) = self.check_region_span(start, end) {
+            sample.limit_exceeded = Some(exceeded);
+            return sample;
+        }
+
+        let started = Instant::now();
+        let mut rng = SplitMix64::new(seed);
+        // Sampled variants with their rank among the matching ones, to restore file order
+        let mut reservoir: Vec<(usize, Variant)> = Vec::with_capacity(count.min(1024));
+        let mut records_read = 0u64;
+        let scan = self.try_scan_region(chromosome, start, end, &[], &mut |variant| {
+            records_read += 1;
+            let flow = progress(&ScanProgress {
+                records_read,
+                matching: sample.population,
+                position: variant.position,
+            });
+            if flow.is_break() {
+                sample.cancelled = true;
+                return false;
+            }
+            if let Some(timeout) = self.limits.timeout {
+                if started.elapsed() > timeout {
+                    sample.limit_exceeded = Some(LimitExceeded::Timeout {
+                        timeout_ms: timeout.as_millis() as u64,
+                        position: variant.position,
+                        records_read,
+                    });
+                    return false;
+                }
+            }
+
+            let matches = record_filter.admits(&variant)
+                && (filter.trim().is_empty()
+                    || self
+                        .filter_engine
+                        .evaluate(filter, &variant.raw_row)
+                        .unwrap_or(false));
+            if !matches {
+                return true;
+            }
+            // The n-th match (0-based) replaces a random slot with probability count / (n + 1)
+            let rank = sample.population;
+            sample.population += 1;
+            if reservoir.len() < count {
+                reservoir.push((rank, variant));
+            } else {
+                let slot = (rng.next_u64() % sample.population as u64) as usize;
+                if slot < count {
+                    reservoir[slot] = (rank, variant);
+                }
+            }
+            true
+        });
+
+        reservoir.sort_by_key(|(rank, _)| *rank);
+        sample.variants = reservoir.into_iter().map(|(_, variant)| variant).collect();
+        match scan {
+            Ok(matched_chromosome) => sample.matched_chromosome = matched_chromosome,
+            Err(failure) => {
+                sample.matched_chromosome = self.find_matching_chromosome(chromosome);
+                sample.read_failure = Some(failure);
+            }
+        }
+        sample
+    }
+
+    // Whether ID lookups can be served yet; IDs are reported as absent until then
+    pub fn id_index_status(&self) -> IdIndexStatus {
+        if self.id_index.get().is_some() {
+            return IdIndexStatus::Ready;
+        }
+        if let Some(error) = self.id_index_progress.error.lock().unwrap().clone() {
+            return IdIndexStatus::Failed { error };
+        }
+
+        let total = self.id_index_progress.total_bytes.load(Ordering::Relaxed);
+        let read = self.id_index_progress.bytes_read.load(Ordering::Relaxed);
+        let percent_complete = if total == 0 {
+            0.0
+        } else {
+            // Rounded to one decimal place
+            (read.min(total) as f64 * 1000.0 / total as f64).round() / 10.0
+        };
+        IdIndexStatus::Building {
+            percent_complete,
+            variants_scanned: self
+                .id_index_progress
+                .variants_scanned
+                .load(Ordering::Relaxed),
+        }
+    }
+
+    // Stop a background ID index build before exiting: the scan is abandoned and its temp file
+    // removed, while index and statistics files already being written are finished. Returns
+    // false if the build thread is still running after `timeout`.
+    pub fn stop_background_indexing(&self, timeout: std::time::Duration) -> bool {
+        let Some(thread) = self.id_index_progress.thread.lock().unwrap().take() else {
+            return true;
+        };
+        self.id_index_progress.stop.store(true, Ordering::Relaxed);
+        let deadline = std::time::Instant::now() + timeout;
+        while !thread.is_finished() {
+            if std::time::Instant::now() >= deadline {
+                return false;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(20));
+        }
+        let _ = thread.join();
+        true
+    }
+
+    #[allow(dead_code)] // Library entry point; the server binary calls lookup_id
+    pub fn query_by_id(&self, id: &str) -> Vec<Variant> {
+        self.lookup_id(id).unwrap_or_else(|failure| {
+            warn!("Failed to read VCF: {}", failure.message);
+            Vec::new()
+        })
+    }
+
+    // Like query_by_id, but reports a read that still failed after retries instead of
+    // returning no variants
+    pub fn lookup_id(&self, id: &str) -> Result<Vec<Variant>, ReadFailure> {
+        let Some(id_index) = self.id_index.get() else {
+            return Ok(Vec::new());
+        };
+        // Use the ID index for O(key length) lookup
+        let mut locations = id_index.get(id);
+        // An ID listed twice on one record is indexed twice at the same location
+        locations.sort();
+        locations.dedup();
+
+        let mut results = Vec::new();
+        for (chromosome, position) in &locations {
+            let prefilter = RecordPrefilter::default();
+            self.scan_with_retry(
+                chromosome,
+                *position,
+                *position,
+                &prefilter,
+                &mut |variant| {
+                    // Other records may share the position; keep only those carrying this ID
+                    if variant.ids.iter().any(|record_id| record_id == id) {
+                        results.push(variant);
+                    }
+                    true
+                },
+            )?;
+        }
+        Ok(results)
+    }
+
+    // Bloom filter hit/miss counters for the ID index (None while it is being built)
+    pub fn id_bloom_stats(&self) -> Option<BloomStats> {
+        self.id_index.get().map(IdIndex::bloom_stats)
+    }
+
+    // Find IDs matching a pattern. '*' matches any run of characters and '?' a single
+    // character; a pattern without wildcards is treated as a prefix. Returns at most
+    // `limit` matches in sorted order plus whether more matches were available.
+    pub fn search_ids(&self, pattern: &str, limit: usize) -> (Vec<IdMatch>, bool) {
+        let Some(id_index) = self.id_index.get() else {
+            return (Vec::new(), false);
+        };
+        let pattern = if pattern.contains(['*', '?']) {
+            pattern.to_string()
+        } else {
+            format!("{}*", pattern)
+        };
+
+        // Only IDs sharing the literal prefix before the first wildcard can match
+        let literal_prefix = &pattern[..pattern.find(['*', '?']).unwrap_or(pattern.len())];
+
+        let mut matches = Vec::new();
+        let mut truncated = false;
+        id_index.for_each_with_prefix(literal_prefix, |id, locations| {
+            if !glob_match(&pattern, id) {
+                return true;
+            }
+            if matches.len() == limit {
+                truncated = true;
+                return false;
+            }
+            matches.push(IdMatch {
+                id: id.to_string(),
+                locations: locations
+                    .into_iter()
+                    .map(|(chromosome, position)| IdLocation {
+                        chromosome,
+                        position,
+                    })
+                    .collect(),
+            });
+            true
+        });
+
+        (matches, truncated)
+    }
+
+    // Page `page` (0-based) of every ID in sorted order, `page_size` IDs per page; None while
+    // the ID index is not ready
+    pub fn id_page(&self, page: usize, page_size: usize) -> Option<IdPage> {
+        let id_index = self.id_index.get()?;
+        let total_ids = id_index.id_count();
+        let total_pages = total_ids.div_ceil(page_size);
+        let ids = id_index
+            .entries(page.saturating_mul(page_size), page_size)
+            .into_iter()
+            .flat_map(|(id, locations)| {
+                locations
+                    .into_iter()
+                    .map(move |(chromosome, position)| IdRow {
+                        id: id.clone(),
+                        chromosome,
+                        position,
+                    })
+            })
+            .collect();
+        Some(IdPage {
+            page,
+            page_size,
+            total_ids,
+            total_pages,
+            next_page: (page + 1 < total_pages).then_some(page + 1),
+            ids,
+        })
+    }
+
+    pub fn get_metadata(&self) -> VcfMetadata {
+        let mut metadata = extract_metadata(self.served_header());
+        metadata.variant_ranges =
+            self.id_index
+                .get()
+                .and_then(IdIndex::position_ranges)
+                .map(|ranges| {
+                    ranges
+                        .into_iter()
+                        .map(
+                            |(chromosome, first_position, last_position)| ChromosomeRange {
+                                chromosome,
+                                first_position,
+                                last_position,
+                            },
+                        )
+                        .collect()
+                });
+        metadata
+    }
+
+    pub fn get_reference_genome(&self) -> String {
+        let metadata = self.get_metadata();
+        format!(
+            "{} ({})",
+            metadata.reference_genome.build,
+            match metadata.reference_genome.source {
+                ReferenceGenomeSource::HeaderLine => "from header",
+                ReferenceGenomeSource::InferredFromContigLengths => "inferred from contigs",
+                ReferenceGenomeSource::Unknown => "unknown source",
+            }
+        )
+    }
+
+    pub fn get_header_string(&self, search: Option<&str>) -> String {
+        let mut buffer = Vec::new();
+        let mut writer = vcf::io::Writer::new(&mut buffer);
+        if writer.write_header(self.served_header()).is_ok() {
+            let full_header = String::from_utf8_lossy(&buffer).to_string();
+
+            // Apply search filter if provided, otherwise exclude ##contig lines by default
+            if let Some(search_str) = search {
+                full_header
+                    .lines()
+                    .filter(|line| line.contains(search_str))
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            } else {
+                // Exclude ##contig lines by default (noodles doesn't include them anyway)
+                // This prevents clutter while keeping useful metadata
+                full_header
+                    .lines()
+                    .filter(|line| !line.starts_with("##contig"))
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            }
+        } else {
+            "Error formatting header".to_string()
+        }
+    }
+
+    // `variants` as a VCF file: a minimal header (##fileformat, the FILTER, INFO, FORMAT and
+    // ALT definitions, a ##contig line for each chromosome present, the #CHROM line with every
+    // sample) followed by each record as the file holds it
+    pub fn render_vcf(&self, variants: &[Variant]) -> String {
+        let mut buffer = Vec::new();
+        let mut writer = vcf::io::Writer::new(&mut buffer);
+        if writer.write_header(self.served_header()).is_err() {
+            return "Error formatting header".to_string();
+        }
+        let full_header = String::from_utf8_lossy(&buffer).to_string();
+
+        let mut chromosomes: Vec<&str> = Vec::new();
+        for variant in variants {
+            if !chromosomes.contains(&variant.chromosome.as_str()) {
+                chromosomes.push(&variant.chromosome);
+            }
+        }
+        let contig_id = |line: &str| {
+            line.strip_prefix("##contig=<ID=")
+                .and_then(|rest| rest.split([',', '>']).next())
+                .map(str::to_string)
+        };
+
+        let mut lines: Vec<String> = Vec::new();
+        let mut column_line = None;
+        for line in full_header.lines() {
+            if line.starts_with("#CHROM") {
+                column_line = Some(line.to_string());
+            } else if let Some(id) = contig_id(line) {
+                if chromosomes.contains(&id.as_str()) {
+                    lines.push(line.to_string());
+                }
+            } else if [
+                "##fileformat=",
+                "##FILTER=",
+                "##INFO=",
+                "##FORMAT=",
+                "##ALT=",
+            ]
+            .iter()
+            .any(|prefix| line.starts_with(prefix))
+            {
+                lines.push(line.to_string());
+            }
+        }
+        for chromosome in chromosomes {
+            if !lines
+                .iter()
+                .any(|line| contig_id(line).as_deref() == Some(chromosome))
+            {
+                lines.push(format!("##contig=<ID={}>", chromosome));
+            }
+        }
+        lines.extend(column_line);
+        lines.extend(variants.iter().map(|variant| variant.raw_row.clone()));
+
+        let mut vcf = lines.join("\n");
+        vcf.push('\n');
+        vcf
+    }
+
+    // Write `variants`, in file order, to `path` as a bgzipped VCF with the file's full header,
+    // and index it with tabix at `path` + ".tbi"
+    pub fn write_bgzipped_vcf(&self, path: &Path, variants: &[Variant]) -> std::io::Result<()> {
+        use std::io::Write;
+
+        let mut writer = vcf::io::Writer::new(bgzf::io::Writer::new(File::create(path)?));
+        writer.write_header(self.served_header())?;
+        let mut bgzf_writer = writer.into_inner();
+        for variant in variants {
+            writeln!(bgzf_writer, "{}", variant.raw_row)?;
+        }
+        bgzf_writer.finish()?;
+
+        let index = vcf::fs::index(path)?;
+        let tbi_path = PathBuf::from(format!("{}.tbi", path.display()));
+        let mut writer = tabix::io::Writer::new(std::io::BufWriter::new(File::create(&tbi_path)?));
+        writer.write_index(&index)
+    }
+
+    // Names in `flags` that the header does not declare as INFO fields of type Flag
+    pub fn undeclared_info_flags<'a>(&self, flags: &'a [String]) -> Vec<&'a str> {
+        flags
+            .iter()
+            .filter(|flag| {
+                !self
+                    .header
+                    .infos()
+                    .get(flag.as_str())
+                    .is_some_and(|info| info.ty() == InfoType::Flag)
+            })
+            .map(String::as_str)
+            .collect()
+    }
+
+    // The keys in `keys` that the header doesn't declare with an ##INFO line
+    pub fn undeclared_info_keys<'a>(&self, keys: &'a [String]) -> Vec<&'a str> {
+        keys.iter()
+            .filter(|key| !self.header.infos().contains_key(key.as_str()))
+            .map(String::as_str)
+            .collect()
+    }
+
+    // Explain each FILTER in `value` (several may be given separated by ';' as in a VCF
+    // record): its ##FILTER description and how many records carry it. Names are matched
+    // case-insensitively when there is no exact match.
+    pub fn explain_filter(&self, value: &str) -> Vec<FilterExplanation> {
+        let statistics = self.statistics.get();
+        value
+            .split([';', ','])
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .map(|name| {
+                let filters = self.header.filters();
+                let declared = filters
+                    .get_key_value(name)
+                    .or_else(|| filters.iter().find(|(id, _)| id.eq_ignore_ascii_case(name)));
+                let (filter, description) = match declared {
+                    Some((id, map)) => (id.to_string(), Some(map.description().to_string())),
+                    // PASS is reserved by the VCF specification and need not be declared
+                    None if name.eq_ignore_ascii_case("PASS") => {
+                        ("PASS".to_string(), Some("All filters passed".to_string()))
+                    }
+                    None => (name.to_string(), None),
+                };
+                let count =
+                    statistics.map(|stats| stats.filter_counts.get(&filter).copied().unwrap_or(0));
+                let fraction = statistics
+                    .zip(count)
+                    .filter(|(stats, _)| stats.total_variants > 0)
+                    .map(|(stats, count)| count as f64 / stats.total_variants as f64);
+                FilterExplanation {
+                    declared: description.is_some(),
+                    filter,
+                    description,
+                    count,
+                    fraction,
+                }
+            })
+            .collect()
+    }
+
+    // FILTER names declared by ##FILTER header lines
+    pub fn declared_filters(&self) -> Vec<String> {
+        self.header.filters().keys().cloned().collect()
+    }
+
+    // The header shown to clients, which lacks FORMAT definitions and samples when genotypes
+    // are hidden
+    fn served_header(&self) -> &vcf::Header {
+        self.redacted_header.as_ref().unwrap_or(&self.header)
+    }
+
+    // Whether FORMAT and sample columns are withheld (LoadOptions::hide_genotypes)
+    pub fn genotypes_hidden(&self) -> bool {
+        self.redacted_header.is_some()
+    }
+
+    // Drop the FORMAT and sample columns of a variant read from the file when they are hidden
+    fn redact(&self, mut variant: Variant) -> Variant {
+        if self.genotypes_hidden() {
+            variant.raw_row = site_columns(&variant.raw_row).to_string();
+        }
+        variant
+    }
+
+    // Column index of a sample among the header's samples
+    pub fn sample_index(&self, name: &str) -> Option<usize> {
+        self.served_header().sample_names().get_index_of(name)
+    }
+
+    // Number of sample columns in the file
+    pub fn sample_count(&self) -> usize {
+        self.served_header().sample_names().len()
+    }
+
+    // Up to `limit` sample names in column order, after skipping the first `offset`
+    pub fn sample_names(&self, offset: usize, limit: usize) -> Vec<String> {
+        self.served_header()
+            .sample_names()
+            .iter()
+            .skip(offset)
+            .take(limit)
+            .cloned()
+            .collect()
+    }
+
+    // Samples whose GT calls ALT allele `allele` (1 for the first ALT, as in GT), listing at
+    // most `limit` names per zygosity. A sample is homozygous when every called allele is
+    // this one ("1/1", haploid "1", "./1") and heterozygous when it also has another.
+    pub fn allele_carriers(
+        &self,
+        variant: &Variant,
+        allele: usize,
+        limit: usize,
+    ) -> AlleleCarriers {
+        let mut carriers = AlleleCarriers::default();
+        let mut columns = variant.raw_row.split('\t');
+        let Some(slot) = columns
+            .nth(8)
+            .and_then(|format| format.split(':').position(|field| field == "GT"))
+        else {
+            carriers.no_call_count = self.sample_count();
+            return carriers;
+        };
+
+        for (name, values) in self.served_header().sample_names().iter().zip(columns) {
+            let called: Vec<usize> = values
+                .split(':')
+                .nth(slot)
+                .unwrap_or(".")
+                .split(['/', '|'])
+                .filter_map(|allele| allele.parse().ok())
+                .collect();
+            if called.is_empty() {
+                carriers.no_call_count += 1;
+                continue;
+            }
+            if !called.contains(&allele) {
+                continue;
+            }
+            let (count, names) = if called.iter().all(|&called| called == allele) {
+                (&mut carriers.homozygous_count, &mut carriers.homozygous)
+            } else {
+                (&mut carriers.heterozygous_count, &mut carriers.heterozygous)
+            };
+            *count += 1;
+            if names.len() < limit {
+                names.push(name.clone());
+            } else {
+                carriers.truncated = true;
+            }
+        }
+        carriers
+    }
+
+    // Contigs of the header (or the tabix index when the header declares none) plus any
+    // chromosome seen while computing statistics, in natural order (1..22, X, Y, MT)
+    pub fn contig_summaries(&self) -> Vec<ContigSummary> {
+        let counts = self
+            .statistics
+            .get()
+            .map(|stats| &stats.variants_per_chromosome);
+        let mut names = self.get_available_chromosomes();
+        if let Some(counts) = counts {
+            for name in counts.keys() {
+                if !names.contains(name) {
+                    names.push(name.clone());
+                }
+            }
+        }
+        names.sort_by(|a, b| natural_contig_key(a).cmp(&natural_contig_key(b)));
+
+        names
+            .into_iter()
+            .map(|name| ContigSummary {
+                length: self
+                    .header
+                    .contigs()
+                    .get(name.as_str())
+                    .and_then(|contig| contig.length()),
+                variant_count: counts.map(|counts| counts.get(&name).copied().unwrap_or(0)),
+                name,
+            })
+            .collect()
+    }
+
+    // True for a header-only VCF without any variant records
+    pub fn is_empty_file(&self) -> bool {
+        self.empty
+    }
+
+    // Hits, misses and size of the decompressed block cache
+    pub fn block_cache_stats(&self) -> BlockCacheStats {
+        self.readers.block_cache.stats()
+    }
+
+    // Get reference to the filter engine for evaluating filters
+    pub fn filter_engine(&self) -> Arc<FilterEngine> {
+        Arc::clone(&self.filter_engine)
+    }
+
+    // Gene name locations in the annotation INFO fields the header declares
+    pub fn gene_annotations(&self) -> GeneAnnotations {
+        GeneAnnotations::from_header(&header_definitions(&self.get_header_string(None)).info)
+    }
+
+    // Whether statistics were loaded or computed, so per-chromosome counts are known
+    pub fn statistics_ready(&self) -> bool {
+        self.statistics.get().is_some()
+    }
+
+    // Compute comprehensive statistics about the VCF file
+    pub fn compute_statistics(&self) -> std::io::Result<VcfStatistics> {
+        // Return cached statistics (computed at load time, or after a background ID index build)
+        self.statistics.get().cloned().ok_or_else(|| {
+            let status = match self.id_index_status() {
+                IdIndexStatus::Failed { error } => format!("indexing failed: {}", error),
+                IdIndexStatus::Building {
+                    percent_complete, ..
+                } => format!("ID index {}% built", percent_complete),
+                IdIndexStatus::Ready => "ID index built".to_string(),
+            };
+            std::io::Error::new(
+                std::io::ErrorKind::WouldBlock,
+                format!("Statistics are still being computed ({})", status),
+            )
+        })
+    }
+}
+
+// Helper function to query indexed VCF by region (generic over BinningIndex trait)
+fn query_indexed_region<I: BinningIndex>(
+    reader: &mut VcfReader,
+    index: &I,
+    header: &vcf::Header,
+    chromosome: &str,
+    start: u64,
+    end: u64,
+    info_flags: &[String],
+) -> Vec<Variant> {
+    let mut results = Vec::new();
+    let scan = scan_indexed_region(
+        reader,
+        index,
+        header,
+        chromosome,
+        start,
+        end,
+        &RecordPrefilter {
+            info_flags,
+            carriers: None,
+        },
+        &mut |variant| {
+            results.push(variant);
+            true
+        },
+    );
+    if let Err(e) = scan {
+        warn!("Failed to read VCF: {}", e);
+    }
+    results
+}
+
+// Pass the records of a region to `visit` in file order until it returns false
+#[allow(clippy::too_many_arguments)]
+fn scan_indexed_region<I: BinningIndex>(
+    reader: &mut VcfReader,
+    index: &I,
+    header: &vcf::Header,
+    chromosome: &str,
+    start: u64,
+    end: u64,
+    prefilter: &RecordPrefilter,
+    visit: &mut dyn FnMut(Variant) -> bool,
+) -> std::io::Result<()> {
+    // Create region with Position types
+    let Ok(start_pos) = Position::try_from(start as usize) else {
+        return Ok(());
+    };
+    let Ok(end_pos) = Position::try_from(end as usize) else {
+        return Ok(());
+    };
+    let region = Region::new(chromosome, start_pos..=end_pos);
+
+    let query_result = match reader.query(header, index, &region) {
+        Ok(q) => q,
+        // The chromosome has no records in the index
+        Err(e) if e.kind() == std::io::ErrorKind::InvalidInput => return Ok(()),
+        Err(e) => return Err(e),
+    };
+
+    for record in query_result.records() {
+        let record = match record {
+            Ok(record) => record,
+            // Malformed records are skipped; any other error is a failed read
+            Err(e) if e.kind() == std::io::ErrorKind::InvalidData => continue,
+            Err(e) => return Err(e),
+        };
+        if !prefilter.admits(&record) {
+            continue;
+        }
+        if let Ok(variant) = parse_variant_record(&record, header) {
+            if !visit(variant) {
+                return Ok(());
+            }
+        }
+    }
+    Ok(())
+}
+
+// Distinct start positions of the variants near a position (VcfIndex::variant_starts_near)
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize)]
+pub struct NearbyVariants {
+    pub positions: Vec<u64>,
+    // More variants start within the window than were listed
+    pub truncated: bool,
+    // Answered from the ID index rather than by reading the VCF
+    pub from_index: bool,
+}
+
+// The variants starting closest to a position on either side (VcfIndex::nearest_variants).
+// Upstream and downstream are by coordinate, whatever the strand of nearby genes.
+#[derive(Debug, Clone, Default, serde::Serialize)]
+pub struct NearestVariants {
+    // Records starting at the position itself
+    pub at_position: Vec<Variant>,
+    // The records sharing the closest start below the position
+    pub upstream: Option<NearestVariant>,
+    // The records sharing the closest start above the position
+    pub downstream: Option<NearestVariant>,
+    // Bases searched either side; a missing side has no variant starting this close
+    pub searched_distance: u64,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct NearestVariant {
+    pub distance: u64,
+    pub variants: Vec<Variant>,
+}
+
+// Variant start positions per window (VcfIndex::variant_density); window i covers
+// start + i * window_size onwards, the last one ending at `end`
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct VariantDensity {
+    pub start: u64,
+    pub end: u64,
+    pub window_size: u64,
+    pub counts: Vec<u64>,
+    // Answered from the ID index rather than by reading the VCF
+    pub from_index: bool,
+}
+
+// Checks made on a raw record before it is parsed into a Variant
+#[derive(Default)]
+struct RecordPrefilter<'a> {
+    info_flags: &'a [String],
+    // Sample presence bitmaps of the scanned chromosome for a present_in_sample query
+    carriers: Option<SampleCarriers<'a>>,
+}
+
+impl RecordPrefilter<'_> {
+    fn admits(&self, record: &vcf::Record) -> bool {
+        let carried = self.carriers.is_none_or(|carriers| {
+            match record.variant_start() {
+                Some(Ok(position)) => carriers.carries(usize::from(position) as u64),
+                // Left for parsing to reject
+                _ => true,
+            }
+        });
+        carried && has_info_flags(record, self.info_flags)
+    }
+}
+
+// Conditions on a record that the filter expression can't state exactly: FILTER may list
+// several values, the variant type depends on all ALT alleles, and an INFO key may be
+// present without a value worth comparing. Empty fields don't restrict anything.
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize)]
+pub struct RecordFilter {
+    // Drop variants whose FILTER lists any of these values (compared ignoring case, as in
+    // explain_filter)
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub exclude_filters: Vec<String>,
+    // Only variants of one of these types
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub variant_types: Vec<VariantType>,
+    // Only variants carrying every one of these INFO keys, whatever their values
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub require_info: Vec<String>,
+    // Only variants ClinVar classifies as pathogenic or likely pathogenic (ClinVar::is_pathogenic)
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub pathogenic_only: bool,
+}
+
+impl RecordFilter {
+    pub fn admits(&self, variant: &Variant) -> bool {
+        !has_excluded_filter(variant, &self.exclude_filters)
+            && (self.variant_types.is_empty()
+                || variant
+                    .variant_type
+                    .is_some_and(|kind| self.variant_types.contains(&kind)))
+            && self
+                .require_info
+                .iter()
+                .all(|key| variant.info.contains_key(key))
+            && (!self.pathogenic_only
+                || variant.clinvar.as_ref().is_some_and(ClinVar::is_pathogenic))
+    }
+
+    // INFO keys the header must declare for the filter to make sense
+    pub fn required_info_keys(&self) -> Vec<String> {
+        let mut keys = self.require_info.clone();
+        if self.pathogenic_only && !keys.iter().any(|key| key == "CLNSIG") {
+            keys.push("CLNSIG".to_string());
+        }
+        keys
+    }
+
+    // The checks `variant` fails, naming the parameter and the offending values
+    pub fn failures(&self, variant: &Variant) -> Vec<String> {
+        let mut failures = Vec::new();
+        let excluded: Vec<&str> = variant
+            .filter
+            .iter()
+            .filter(|filter| {
+                self.exclude_filters
+                    .iter()
+                    .any(|excluded| excluded.eq_ignore_ascii_case(filter))
+            })
+            .map(String::as_str)
+            .collect();
+        if !excluded.is_empty() {
+            failures.push(format!("exclude_filters: {}", excluded.join(", ")));
+        }
+        if !self.variant_types.is_empty()
+            && !variant
+                .variant_type
+                .is_some_and(|kind| self.variant_types.contains(&kind))
+        {
+            failures.push(format!(
+                "variant_types: {}",
+                variant.variant_type.map_or("none", VariantType::as_str)
+            ));
+        }
+        let missing: Vec<&str> = self
+            .require_info
+            .iter()
+            .filter(|key| !variant.info.contains_key(*key))
+            .map(String::as_str)
+            .collect();
+        if !missing.is_empty() {
+            failures.push(format!("require_info: {} missing", missing.join(", ")));
+        }
+        if self.pathogenic_only && !variant.clinvar.as_ref().is_some_and(ClinVar::is_pathogenic) {
+            let significance = variant
+                .clinvar
+                .as_ref()
+                .map(|clinvar| clinvar.significance.join("/"))
+                .filter(|significance| !significance.is_empty());
+            failures.push(format!(
+                "pathogenic_only: CLNSIG {}",
+                significance.as_deref().unwrap_or("missing")
+            ));
+        }
+        failures
+    }
+}
+
+fn has_excluded_filter(variant: &Variant, exclude_filters: &[String]) -> bool {
+    variant.filter.iter().any(|filter| {
+        exclude_filters
+            .iter()
+            .any(|excluded| excluded.eq_ignore_ascii_case(filter))
+    })
+}
+
+// Read errors worth retrying: the same read may well succeed a moment later
+fn is_transient(error: &std::io::Error) -> bool {
+    use std::io::ErrorKind;
+    matches!(
+        error.kind(),
+        ErrorKind::Interrupted
+            | ErrorKind::TimedOut
+            | ErrorKind::WouldBlock
+            | ErrorKind::ConnectionReset
+            | ErrorKind::ConnectionAborted
+            | ErrorKind::NotConnected
+            | ErrorKind::BrokenPipe
+    )
+}
+
+// Index chunks covering start..=end of a chromosome, resolved the way queries resolve it:
+// through the index's own sequence names, or the header contigs when it has none
+fn region_chunks<I: BinningIndex>(
+    index: &I,
+    header: &vcf::Header,
+    chromosome: &str,
+    start: Position,
+    end: Position,
+) -> Option<Vec<csi::binning_index::index::reference_sequence::bin::Chunk>> {
+    let reference_sequence_id = match index.header() {
+        Some(index_header) => index_header
+            .reference_sequence_names()
+            .get_index_of(chromosome.as_bytes())?,
+        None => header.contigs().get_index_of(chromosome)?,
+    };
+    index
+        .query(reference_sequence_id, Interval::from(start..=end))
+        .ok()
+}
+
+// True when the record's INFO column contains every flag in `flags`. Checked on the raw
+// column so non-matching records are skipped before being parsed into a Variant.
+fn has_info_flags(record: &vcf::Record, flags: &[String]) -> bool {
+    if flags.is_empty() {
+        return true;
+    }
+    let info: &str = record.info().as_ref();
+    flags
+        .iter()
+        .all(|flag| info.split(';').any(|field| field == flag))
+}
+
+// Small seedable PRNG for reproducible sampling (output is stable across platforms and releases)
+struct SplitMix64 {
+    state: u64,
+}
+
+impl SplitMix64 {
+    fn new(seed: u64) -> Self {
+        SplitMix64 { state: seed }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+}
+
+// Glob matching supporting '*' (any run, including empty) and '?' (exactly one character)
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    // Position of the last '*' seen and the text position it is currently matched up to
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, t));
+            p += 1;
+        } else if let Some((star, matched)) = backtrack {
+            // Let the last '*' absorb one more character and retry
+            p = star + 1;
+            t = matched + 1;
+            backtrack = Some((star, matched + 1));
+        } else {
+            return false;
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+// Sort key putting numbered chromosomes first (by number), then X, Y and the mitochondrial
+// genome, then everything else (scaffolds, decoys, ...) by name; "chr" prefixes are ignored
+fn natural_contig_key(name: &str) -> (u8, u64, &str) {
+    let bare = name.strip_prefix("chr").unwrap_or(name);
+    if let Ok(number) = bare.parse::<u64>() {
+        return (0, number, name);
+    }
+    let rank = match bare {
+        "X" => 1,
+        "Y" => 2,
+        "M" | "MT" => 3,
+        _ => 4,
+    };
+    (rank, 0, name)
+}
+
+// Helper function to infer genome build from contig lengths
+// GRCh37/hg19: chr1 = 249,250,621 bp
+// GRCh38/hg38: chr1 = 248,956,422 bp
+fn infer_genome_build_from_contigs(header: &vcf::Header) -> Option<String> {
+    const CHR1_GRCH37_LENGTH: usize = 249_250_621;
+    const CHR1_GRCH38_LENGTH: usize = 248_956_422;
+    const TOLERANCE: usize = 1000; // Allow small differences
+
+    // Try both "chr1" and "1" naming conventions
+    for chr_name in ["chr1", "1"] {
+        if let Some(contig) = header.contigs().get(chr_name) {
+            if let Some(length) = contig.length() {
+                let diff_grch37 =
+                    (length as i64 - CHR1_GRCH37_LENGTH as i64).unsigned_abs() as usize;
+                let diff_grch38 =
+                    (length as i64 - CHR1_GRCH38_LENGTH as i64).unsigned_abs() as usize;
+
+                if diff_grch37 < TOLERANCE {
+                    return Some("GRCh37".to_string());
+                } else if diff_grch38 < TOLERANCE {
+                    return Some("GRCh38".to_string());
+                }
+            }
+        }
+    }
+
+    None
+}
+
+// Helper function to extract reference genome from VCF header
+fn extract_reference_genome(header: &vcf::Header) -> ReferenceGenomeInfo {
+    use vcf::header::record::value::Collection;
+
+    // Try to get ##reference line from header
+    // Collection can be Unstructured (Vec<String>) or Structured (IndexMap)
+    // ##reference is typically unstructured with a single string value
+    if let Some(Collection::Unstructured(values)) = header.get("reference") {
+        if let Some(reference_value) = values.first() {
+            return ReferenceGenomeInfo {
+                build: reference_value.clone(),
+                source: ReferenceGenomeSource::HeaderLine,
+            };
+        }
+    }
+
+    // Fall back to inferring from contig lengths
+    if let Some(inferred_build) = infer_genome_build_from_contigs(header) {
+        return ReferenceGenomeInfo {
+            build: inferred_build,
+            source: ReferenceGenomeSource::InferredFromContigLengths,
+        };
+    }
+
+    // Unknown
+    ReferenceGenomeInfo {
+        build: "Unknown".to_string(),
+        source: ReferenceGenomeSource::Unknown,
+    }
+}
+
+// Helper function to extract metadata from VCF header
+fn extract_metadata(header: &vcf::Header) -> VcfMetadata {
+    // Extract file format version
+    let file_format = format!("{:?}", header.file_format());
+
+    // Extract reference genome information
+    let reference_genome = extract_reference_genome(header);
+
+    // Extract contig information
+    let contigs: Vec<ContigInfo> = header
+        .contigs()
+        .keys()
+        .map(|id| ContigInfo { id: id.to_string() })
+        .collect();
+
+    // Extract sample names
+    let samples: Vec<String> = header
+        .sample_names()
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+
+    VcfMetadata {
+        file_format,
+        reference_genome,
+        contigs,
+        samples,
+        variant_ranges: None,
+    }
+}
+
+// Top-level clauses of a filter expression, for explaining which parts a variant matched:
+// the alternatives of a top-level `||`, otherwise the terms of `&&`. A parenthesized term
+// that is itself a conjunction is split in turn, so the shortcut-combined
+// `(DP > 10 && AF < 0.1) && QUAL >= 30` yields all three comparisons.
+pub fn filter_clauses(filter: &str) -> Vec<String> {
+    let filter = strip_enclosing_parens(filter);
+    if filter.is_empty() {
+        return Vec::new();
+    }
+    let alternatives = split_top_level(filter, "||");
+    if alternatives.len() > 1 {
+        return alternatives
+            .into_iter()
+            .map(|alternative| strip_enclosing_parens(alternative).to_string())
+            .collect();
+    }
+    let terms = split_top_level(filter, "&&");
+    if terms.len() == 1 {
+        return vec![filter.to_string()];
+    }
+    terms
+        .into_iter()
+        .flat_map(|term| {
+            let inner = strip_enclosing_parens(term);
+            if split_top_level(inner, "||").len() > 1 {
+                vec![term.to_string()]
+            } else {
+                filter_clauses(inner)
+            }
+        })
+        .collect()
+}
+
+// Split at `operator` outside parentheses and quoted strings, trimming each part
+fn split_top_level<'a>(text: &'a str, operator: &str) -> Vec<&'a str> {
+    let mut parts = Vec::new();
+    let mut depth = 0usize;
+    let mut quote: Option<char> = None;
+    let mut escaped = false;
+    let mut part_start = 0;
+    for (i, c) in text.char_indices() {
+        if let Some(q) = quote {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                _ if c == q => quote = None,
+                _ => {}
+            }
+            continue;
+        }
+        match c {
+            '"' | '\'' => quote = Some(c),
+            '(' => depth += 1,
+            ')' => depth = depth.saturating_sub(1),
+            _ if depth == 0 && text[i..].starts_with(operator) => {
+                parts.push(text[part_start..i].trim());
+                part_start = i + operator.len();
+            }
+            _ => {}
+        }
+    }
+    parts.push(text[part_start..].trim());
+    parts
+}
+
+// Remove parentheses enclosing the whole text, e.g. "((A && B))" -> "A && B"
+fn strip_enclosing_parens(text: &str) -> &str {
+    let mut text = text.trim();
+    while text.starts_with('(') && text.ends_with(')') {
+        let mut depth = 0usize;
+        let mut quote: Option<char> = None;
+        let mut escaped = false;
+        let mut closes_at_end = false;
+        for (i, c) in text.char_indices() {
+            if let Some(q) = quote {
+                match c {
+                    _ if escaped => escaped = false,
+                    '\\' => escaped = true,
+                    _ if c == q => quote = None,
+                    _ => {}
+                }
+                continue;
+            }
+            match c {
+                '"' | '\'' => quote = Some(c),
+                '(' => depth += 1,
+                ')' => {
+                    depth = depth.saturating_sub(1);
+                    if depth == 0 {
+                        closes_at_end = i == text.len() - 1;
+                        break;
+                    }
+                }
+                _ => {}
+            }
+        }
+        if !closes_at_end {
+            break;
+        }
+        text = text[1..text.len() - 1].trim();
+    }
+    text
+}
+
+// Definitions in the structured lines of a header text (e.g. from get_header_string), in
+// file order; other lines are skipped
+pub fn header_definitions(header_text: &str) -> HeaderDefinitions {
+    let mut definitions = HeaderDefinitions::default();
+    for line in header_text.lines() {
+        let Some((key, value)) = line.strip_prefix("##").and_then(|l| l.split_once('=')) else {
+            continue;
+        };
+        let target = match key {
+            "INFO" => &mut definitions.info,
+            "FORMAT" => &mut definitions.format,
+            "FILTER" => &mut definitions.filter,
+            "contig" => &mut definitions.contig,
+            _ => continue,
+        };
+        let Some(fields) = value.strip_prefix('<').and_then(|v| v.strip_suffix('>')) else {
+            continue;
+        };
+        if let Some(mut definition) = parse_header_definition(fields) {
+            if key == "INFO" {
+                definition.subfields = definition
+                    .description
+                    .as_deref()
+                    .and_then(annotation_subfields);
+            }
+            target.push(definition);
+        }
+    }
+    definitions
+}
+
+// Subfield names from an annotation field's description: VEP writes "... Format:
+// Allele|Consequence|IMPACT", SnpEff "Functional annotations: 'Allele | Annotation | ...'"
+fn annotation_subfields(description: &str) -> Option<Vec<String>> {
+    let (_, format) = description.rsplit_once(':')?;
+    let names: Vec<String> = format
+        .trim()
+        .trim_matches(|c| c == '\'' || c == '"')
+        .split('|')
+        .map(|name| name.trim().to_string())
+        .collect();
+    (names.len() > 1 && names.iter().all(|name| !name.is_empty())).then_some(names)
+}
+
+// Parse `ID=DP,Number=1,Type=Integer,Description="Depth, total"`; quoted values may contain
+// commas and backslash-escaped quotes
+fn parse_header_definition(fields: &str) -> Option<HeaderDefinition> {
+    let mut definition = HeaderDefinition::default();
+    let mut chars = fields.chars().peekable();
+    while chars.peek().is_some() {
+        let key: String = chars.by_ref().take_while(|&c| c != '=').collect();
+        let value: String = if chars.peek() == Some(&'"') {
+            chars.next();
+            let mut value = String::new();
+            while let Some(c) = chars.next() {
+                match c {
+                    '\\' => value.extend(chars.next()),
+                    '"' => break,
+                    c => value.push(c),
+                }
+            }
+            // Skip to the separating comma
+            chars.by_ref().find(|&c| c == ',');
+            value
+        } else {
+            chars.by_ref().take_while(|&c| c != ',').collect()
+        };
+        match key.trim() {
+            "" => {}
+            "ID" => definition.id = value,
+            "Number" => definition.number = Some(value),
+            "Type" => definition.ty = Some(value),
+            "Description" => definition.description = Some(value),
+            "length" => definition.length = value.parse().ok(),
+            other => {
+                definition.other.insert(other.to_string(), value);
+            }
+        }
+    }
+    (!definition.id.is_empty()).then_some(definition)
+}
+
+// Helper function to convert debug-formatted info values to JSON
+// Converts: Integer(123) -> 123, Float(1.23) -> 1.23, String("foo") -> "foo", etc.
+fn convert_info_value(debug_str: &str) -> serde_json::Value {
+    let s = debug_str;
+
+    // Handle common patterns from noodles VCF library:
+    // Integer(123) -> JSON number
+    // Float(1.23) -> JSON number
+    // String("foo") -> JSON string
+    // Array([Ok(Some(1)), Ok(Some(2))]) -> JSON array
+    // Flag -> JSON true
+
+    if s == "Flag" {
+        return serde_json::Value::Bool(true);
+    }
+
+    // Match Integer(value)
+    if let Some(inner) = s.strip_prefix("Integer(").and_then(|s| s.strip_suffix(')')) {
+        if let Ok(num) = inner.parse::<i64>() {
+            return serde_json::Value::Number(num.into());
+        }
+    }
+
+    // Match Float(value)
+    if let Some(inner) = s.strip_prefix("Float(").and_then(|s| s.strip_suffix(')')) {
+        if let Ok(num) = inner.parse::<f64>() {
+            if let Some(json_num) = serde_json::Number::from_f64(num) {
+                return serde_json::Value::Number(json_num);
+            }
+        }
+    }
+
+    // Match Character(value)
+    if let Some(inner) = s
+        .strip_prefix("Character(")
+        .and_then(|s| s.strip_suffix(')'))
+    {
+        return serde_json::Value::String(inner.trim_matches('\'').to_string());
+    }
+
+    // Match String("value")
+    if let Some(inner) = s
+        .strip_prefix("String(\"")
+        .and_then(|s| s.strip_suffix("\")"))
+    {
+        return serde_json::Value::String(inner.to_string());
+    }
+
+    // Match Array([...])
+    if let Some(inner) = s.strip_prefix("Array([").and_then(|s| s.strip_suffix("])")) {
+        // Extract Ok(Some(value)) patterns
+        let values: Vec<serde_json::Value> = inner
+            .split("), ")
+            .filter_map(|part| {
+                let part = part.trim_end_matches(')');
+                if let Some(val_str) = part.strip_prefix("Ok(Some(") {
+                    let val_str = val_str.trim_matches('"');
+                    // Try to parse as number first, otherwise string
+                    if let Ok(num) = val_str.parse::<i64>() {
+                        return Some(serde_json::Value::Number(num.into()));
+                    }
+                    if let Ok(num) = val_str.parse::<f64>() {
+                        if let Some(json_num) = serde_json::Number::from_f64(num) {
+                            return Some(serde_json::Value::Number(json_num));
+                        }
+                    }
+                    return Some(serde_json::Value::String(val_str.to_string()));
+                }
+                None
+            })
+            .collect();
+        return serde_json::Value::Array(values);
+    }
+
+    // Fall back to string if no pattern matched
+    serde_json::Value::String(s.to_string())
+}
+
+// Helper function to parse a VCF record into a Variant
+fn parse_variant_record(record: &vcf::Record, header: &vcf::Header) -> std::io::Result<Variant> {
+    // Serialize record to VCF row string for filtering
+    let mut raw_row = Vec::new();
+    {
+        let mut writer = vcf::io::Writer::new(&mut raw_row);
+        writer
+            .write_record(header, record)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+    }
+    let raw_row_string = String::from_utf8(raw_row)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?
+        .trim_end()
+        .to_string();
+
+    let alternate: Vec<String> = record
+        .alternate_bases()
+        .iter()
+        .map(|alt| {
+            alt.map(|a| a.to_string())
+                .unwrap_or_else(|_| ".".to_string())
+        })
+        .collect();
+    let alternate_alleles: Vec<AlternateAllele> =
+        alternate.iter().map(|a| classify_allele(a)).collect();
+    let reference = record.reference_bases().to_string();
+    let variant_type = classify_variant(&reference, &alternate_alleles);
+
+    // The ID column may hold several semicolon-separated IDs
+    let ids: Vec<String> = record
+        .ids()
+        .iter()
+        .filter(|id| *id != ".")
+        .map(|id| id.to_string())
+        .collect();
+
+    Ok(Variant {
+        chromosome: record.reference_sequence_name().to_string(),
+        position: usize::from(
+            record
+                .variant_start()
+                .transpose()
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?
+                .ok_or_else(|| {
+                    std::io::Error::new(std::io::ErrorKind::InvalidData, "Missing position")
+                })?,
+        ) as u64,
+        id: ids.first().map(String::as_str).unwrap_or(".").to_string(),
+        ids,
+        reference,
+        alternate,
+        alternate_alleles,
+        variant_type,
+        quality: record
+            .quality_score()
+            .transpose()
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?,
+        filter: record
+            .filters()
+            .iter(header)
+            .filter_map(|f| f.ok())
+            .map(|filter| filter.to_string())
+            .collect(),
+        info: record
+            .info()
+            .iter(header)
+            .map(|item| {
+                item.map(|(key, value)| {
+                    if let Some(val) = value {
+                        let debug_str = format!("{:?}", val);
+                        let json_value = convert_info_value(&debug_str);
+                        (key.to_string(), json_value)
+                    } else {
+                        // Flag with no value - just the key is present
+                        (key.to_string(), serde_json::Value::Bool(true))
+                    }
+                })
+            })
+            .filter_map(|item| item.ok())
+            .collect(),
+        samples: None,
+        normalized: None,
+        clinvar: decode_clinvar(&raw_row_string),
+        annotation: None,
+        vrs: None,
+        spdi: None,
+        browser_links: None,
+        raw_row: raw_row_string,
+    })
+}
+
+// Helper function to save ID index to disk
+// Helper function to atomically save statistics to disk
+fn save_statistics_to_disk(
+    statistics: &VcfStatistics,
+    stats_path: &PathBuf,
+    debug: bool,
+) -> std::io::Result<()> {
+    use std::fs;
+    use std::io::Write;
+
+    // Create temporary file with .tmp extension
+    let tmp_path = PathBuf::from(format!("{}.tmp", stats_path.display()));
+
+    if debug {
+        debug!(
+            "Writing statistics to temporary file: {}",
+            tmp_path.display()
+        );
+    }
+
+    // Serialize and write to temp file
+    {
+        let encoded = bincode::serialize(statistics)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+        let mut tmp_file = fs::File::create(&tmp_path)?;
+        tmp_file.write_all(&encoded)?;
+        tmp_file.flush()?;
+        tmp_file.sync_all()?; // Force OS to write to disk
+    }
+
+    // Check if .stats file was created by another process (race condition)
+    if stats_path.exists() {
+        if debug {
+            debug!("Statistics file appeared during write, removing temporary file");
+        }
+        fs::remove_file(&tmp_path)?;
+        return Ok(());
+    }
+
+    // Atomically rename temp file to final .stats file
+    fs::rename(&tmp_path, stats_path)?;
+
+    Ok(())
+}
+
+// Helper function to load statistics from disk
+pub(crate) fn load_statistics_from_disk(
+    stats_path: &PathBuf,
+    debug: bool,
+) -> std::io::Result<VcfStatistics> {
+    use std::fs;
+
+    if debug {
+        debug!("Loading statistics from: {}", stats_path.display());
+    }
+
+    let mut file = fs::File::open(stats_path)?;
+    let mut buffer = Vec::new();
+    file.read_to_end(&mut buffer)?;
+
+    let statistics: VcfStatistics = bincode::deserialize(&buffer)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+
+    Ok(statistics)
+}
+
+// Helper function to compute statistics by scanning all variants
+fn compute_statistics_from_vcf(
+    path: &PathBuf,
+    header: &vcf::Header,
+    id_index: &IdIndex,
+    debug: bool,
+) -> std::io::Result<VcfStatistics> {
+    if debug {
+        debug!("Computing VCF statistics...");
+    }
+
+    // Extract metadata using existing helper function
+    let metadata = extract_metadata(header);
+
+    // Get chromosomes from header contigs (fallback to index if empty)
+    let mut chromosomes: Vec<String> = header.contigs().keys().map(|k| k.to_string()).collect();
+
+    if chromosomes.is_empty() {
+        // Fall back to variants_per_chromosome keys collected during scan
+        // We'll populate this after the scan
+    }
+
+    // Unique IDs from existing id_index (no scan needed)
+    let unique_ids = id_index.id_count() as u64;
+
+    // Counters for single-pass scan
+    let mut total_variants = 0u64;
+    let mut variants_per_chromosome: HashMap<String, u64> = HashMap::new();
+    let mut missing_ids = 0u64;
+    let mut filter_counts: HashMap<String, u64> = HashMap::new();
+    let mut filter_variant_types: HashMap<String, VariantTypeStats> = HashMap::new();
+
+    // Quality statistics (running calculations)
+    let mut qual_min = f32::INFINITY;
+    let mut qual_max = f32::NEG_INFINITY;
+    let mut qual_sum = 0.0;
+    let mut qual_count = 0u64;
+
+    // Variant type counters
+    let mut variant_types = VariantTypeStats::default();
+
+    // Genotype missingness and QC ratios
+    let mut call_rates = CallRateStats::new(header.sample_names().len());
+    let mut qc_metrics = QcMetrics::default();
+
+    // Single-pass scan through all variants
+    let file = File::open(path)?;
+    let mut reader = vcf::io::Reader::new(bgzf::io::Reader::new(file));
+    let _ = reader.read_header()?; // Skip header
+
+    for record in reader.records().flatten() {
+        if let Ok(variant) = parse_variant_record(&record, header) {
+            total_variants += 1;
+
+            // Count per chromosome
+            *variants_per_chromosome
+                .entry(variant.chromosome.clone())
+                .or_insert(0) += 1;
+
+            // Count missing IDs
+            if variant.id == "." {
+                missing_ids += 1;
+            }
+
+            // Track quality stats
+            if let Some(qual) = variant.quality {
+                qual_min = qual_min.min(qual);
+                qual_max = qual_max.max(qual);
+                qual_sum += qual as f64;
+                qual_count += 1;
+            }
+
+            // Count filter categories
+            for filter in &variant.filter {
+                *filter_counts.entry(filter.clone()).or_insert(0) += 1;
+            }
+            count_filter_types(&mut filter_variant_types, &variant);
+
+            call_rates.add(&variant.raw_row);
+            qc_metrics.add(&variant);
+
+            // Classify variant type
+            variant_types.add(&variant);
+        }
+    }
+
+    // Compute quality statistics
+    let quality_stats = if qual_count > 0 {
+        Some(QualityStats {
+            min: qual_min,
+            max: qual_max,
+            mean: (qual_sum / qual_count as f64) as f32,
+        })
+    } else {
+        None
+    };
+
+    // If header had no contigs, use chromosomes from actual variants
+    if chromosomes.is_empty() {
+        chromosomes = variants_per_chromosome.keys().cloned().collect();
+        chromosomes.sort(); // Sort for consistent ordering
+    }
+
+    // Get reference genome using existing helper
+    let reference_genome_info = extract_reference_genome(header);
+    let reference_genome = format!(
+        "{} ({})",
+        reference_genome_info.build,
+        match reference_genome_info.source {
+            ReferenceGenomeSource::HeaderLine => "from header",
+            ReferenceGenomeSource::InferredFromContigLengths => "inferred from contigs",
+            ReferenceGenomeSource::Unknown => "unknown source",
+        }
+    );
+
+    if debug {
+        debug!(
+            "Statistics computed: {} total variants, {} chromosomes",
+            total_variants,
+            chromosomes.len()
+        );
+    } else {
+        info!("Statistics computed ({} total variants)", total_variants);
+    }
+
+    Ok(VcfStatistics {
+        file_format: metadata.file_format,
+        reference_genome,
+        chromosome_count: chromosomes.len(),
+        sample_count: metadata.samples.len(),
+        chromosomes,
+        total_variants,
+        variants_per_chromosome,
+        unique_ids,
+        missing_ids,
+        quality_stats,
+        filter_counts,
+        filter_variant_types,
+        variant_types,
+        call_rates,
+        qc_metrics,
+    })
+}
+
+fn save_id_index_to_disk(image: &[u8], idx_path: &PathBuf, debug: bool) -> std::io::Result<()> {
+    use std::fs;
+    use std::io::Write;
+
+    // Create temporary file with .tmp extension
+    let tmp_path = PathBuf::from(format!("{}.tmp", idx_path.display()));
+
+    if debug {
+        debug!("Writing ID index to temporary file: {}", tmp_path.display());
+    }
+
+    // Write the serialized index to temp file
+    {
+        let mut tmp_file = fs::File::create(&tmp_path)?;
+        tmp_file.write_all(image)?;
+        tmp_file.flush()?;
+        tmp_file.sync_all()?; // Force OS to write to disk
+    }
+
+    commit_id_index_file(&tmp_path, idx_path, debug)
+}
+
+// Move a fully written temp file into place as the .idx file
+fn commit_id_index_file(
+    tmp_path: &PathBuf,
+    idx_path: &PathBuf,
+    debug: bool,
+) -> std::io::Result<()> {
+    use std::fs;
+
+    // Check if .idx file was created by another process (race condition)
+    if idx_path.exists() {
+        if debug {
+            debug!("ID index file appeared during write, removing temporary file");
+        }
+        fs::remove_file(tmp_path)?;
+        return Ok(());
+    }
+
+    // Atomically rename temp file to final .idx file
+    fs::rename(tmp_path, idx_path)?;
+
+    Ok(())
+}
+
+// Helper function to load (memory-map) ID index from disk
+fn load_id_index_from_disk(idx_path: &PathBuf, debug: bool) -> std::io::Result<IdIndex> {
+    if debug {
+        debug!("Loading ID index from: {}", idx_path.display());
+    }
+
+    IdIndex::open(idx_path)
+}
+
+// Last reference position covered by a variant: INFO END for symbolic alleles, else REF length
+fn variant_end(variant: &Variant) -> u64 {
+    let ref_end = variant.position + (variant.reference.len() as u64).saturating_sub(1);
+    variant
+        .info
+        .get("END")
+        .and_then(|end| end.as_u64())
+        .map_or(ref_end, |end| end.max(ref_end))
+}
+
+// Scan all variants for the ID index, passing each one to `visit`; returns the number scanned
+fn scan_id_index_variants(
+    path: &PathBuf,
+    header: &vcf::Header,
+    progress: Option<&BuildProgress>,
+    mut visit: impl FnMut(&Variant) -> std::io::Result<()>,
+) -> std::io::Result<u64> {
+    let file = File::open(path)?;
+    if let Some(progress) = progress {
+        progress
+            .total_bytes
+            .store(file.metadata()?.len(), Ordering::Relaxed);
+    }
+    let mut reader = vcf::io::Reader::new(bgzf::io::Reader::new(file));
+    let _ = reader.read_header()?; // Skip header
+
+    let mut count = 0;
+    let mut record = vcf::Record::default();
+    loop {
+        match reader.read_record(&mut record) {
+            Ok(0) => break,
+            Ok(_) => {}
+            Err(_) => continue,
+        }
+        if let Ok(variant) = parse_variant_record(&record, header) {
+            visit(&variant)?;
+            count += 1;
+        }
+        if let Some(progress) = progress {
+            // Compressed offset of the current BGZF block, against the file size
+            let offset = reader.get_ref().virtual_position().compressed();
+            progress.bytes_read.store(offset, Ordering::Relaxed);
+            progress.variants_scanned.store(count, Ordering::Relaxed);
+            if progress.stop.load(Ordering::Relaxed) {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::Interrupted,
+                    "ID index build stopped at shutdown",
+                ));
+            }
+        }
+    }
+    Ok(count)
+}
+
+fn log_id_index_built(variants: u64, unique_ids: usize, debug: bool) {
+    if debug {
+        debug!(
+            "ID index built: {} variants scanned, {} unique IDs indexed",
+            variants, unique_ids
+        );
+    } else {
+        info!("ID index built ({} unique IDs)", unique_ids);
+    }
+}
+
+// Number of samples to record presence bitmaps for (LoadOptions::sample_presence), or None
+fn presence_sample_count(header: &vcf::Header, sample_presence: bool) -> Option<usize> {
+    let sample_count = header.sample_names().len();
+    (sample_presence && sample_count > 0).then_some(sample_count)
+}
+
+// Helper function to build ID index by scanning all variants; returns the serialized image
+fn build_id_index(
+    path: &PathBuf,
+    header: &vcf::Header,
+    progress: Option<&BuildProgress>,
+    sample_presence: bool,
+    debug: bool,
+) -> std::io::Result<Vec<u8>> {
+    let mut builder = IdIndexBuilder::new();
+    builder.set_source(SourceFingerprint::of_file(path)?);
+    let sample_count = presence_sample_count(header, sample_presence);
+    if let Some(sample_count) = sample_count {
+        builder.enable_sample_presence(sample_count);
+    }
+
+    if debug {
+        debug!("Building ID index...");
+    }
+
+    let count = scan_id_index_variants(path, header, progress, |variant| {
+        builder.add_span(&variant.chromosome, variant.position, variant_end(variant));
+        // Index every ID on the record ("." parses to an empty list)
+        for id in &variant.ids {
+            builder.add(id, &variant.chromosome, variant.position);
+        }
+        if let Some(sample_count) = sample_count {
+            let carriers = sample_presence_bits(&variant.raw_row, sample_count);
+            builder.add_sample_presence(&variant.chromosome, variant.position, &carriers);
+        }
+        Ok(())
+    })?;
+    log_id_index_built(count, builder.id_count(), debug);
+
+    builder.finish()
+}
+
+// Like build_id_index, but sorts the IDs externally (spilling to the temp directory) and
+// writes the index straight to `out_path`, so memory use stays bounded (--low-memory)
+fn build_id_index_file(
+    path: &PathBuf,
+    header: &vcf::Header,
+    out_path: &PathBuf,
+    progress: Option<&BuildProgress>,
+    sample_presence: bool,
+    debug: bool,
+) -> std::io::Result<()> {
+    let spill_dir = std::env::temp_dir();
+    let mut builder = ExternalIdIndexBuilder::new(&spill_dir, DEFAULT_RUN_ENTRIES);
+    builder.set_source(SourceFingerprint::of_file(path)?);
+    let sample_count = presence_sample_count(header, sample_presence);
+    if let Some(sample_count) = sample_count {
+        builder.enable_sample_presence(sample_count)?;
+    }
+
+    if debug {
+        debug!(
+            "Building ID index in low-memory mode (sorted runs in {})...",
+            spill_dir.display()
+        );
+    }
+
+    let count = scan_id_index_variants(path, header, progress, |variant| {
+        builder.add_span(&variant.chromosome, variant.position, variant_end(variant));
+        for id in &variant.ids {
+            builder.add(id, &variant.chromosome, variant.position)?;
+        }
+        if let Some(sample_count) = sample_count {
+            let carriers = sample_presence_bits(&variant.raw_row, sample_count);
+            builder.add_sample_presence(&variant.chromosome, variant.position, &carriers)?;
+        }
+        Ok(())
+    })?;
+    let unique_ids = builder.finish_to_file(out_path)?;
+    log_id_index_built(count, unique_ids, debug);
+    Ok(())
+}
+
+// build_id_index_file to a temp file that is removed again if the build fails or is stopped
+fn build_id_index_temp_file(
+    path: &PathBuf,
+    header: &vcf::Header,
+    tmp_path: &PathBuf,
+    progress: Option<&BuildProgress>,
+    sample_presence: bool,
+    debug: bool,
+) -> std::io::Result<()> {
+    let result = build_id_index_file(path, header, tmp_path, progress, sample_presence, debug);
+    if result.is_err() {
+        let _ = std::fs::remove_file(tmp_path);
+    }
+    result
+}
+
+// Build the ID index and, if allowed, persist it and serve it memory-mapped from disk.
+// `replace_existing` removes an unreadable or stale index file first so the rebuild can take its place.
+fn build_and_store_id_index(
+    path: &PathBuf,
+    header: &vcf::Header,
+    idx_path: &PathBuf,
+    replace_existing: bool,
+    progress: Option<&BuildProgress>,
+    options: &LoadOptions,
+) -> std::io::Result<IdIndex> {
+    if options.low_memory {
+        return build_and_map_id_index_file(
+            path,
+            header,
+            idx_path,
+            replace_existing,
+            progress,
+            options,
+        );
+    }
+    let debug = options.debug;
+    let image = build_id_index(path, header, progress, options.sample_presence, debug)?;
+
+    if !options.save_index {
+        if debug {
+            debug!("Skipping ID index save (--never-save-index flag set)");
+        }
+        return IdIndex::from_image(image);
+    }
+
+    if replace_existing {
+        if let Err(e) = std::fs::remove_file(idx_path) {
+            warn!("Failed to remove existing ID index: {}", e);
+        }
+    }
+
+    match save_id_index_to_disk(&image, idx_path, debug) {
+        Ok(()) => {
+            info!("ID index saved to {}", idx_path.display());
+            match IdIndex::open(idx_path) {
+                Ok(index) => return Ok(index),
+                Err(e) => warn!("Failed to map saved ID index: {}", e),
+            }
+        }
+        Err(e) => {
+            warn!("Failed to save ID index to disk: {}", e);
+            info!("Continuing with in-memory index...");
+        }
+    }
+
+    IdIndex::from_image(image)
+}
+
+// --low-memory counterpart of build_and_store_id_index: the index is only ever on disk. Without
+// --never-save-index it goes through a temp file next to the target; otherwise it is written to
+// the temp directory and unlinked once mapped.
+fn build_and_map_id_index_file(
+    path: &PathBuf,
+    header: &vcf::Header,
+    idx_path: &PathBuf,
+    replace_existing: bool,
+    progress: Option<&BuildProgress>,
+    options: &LoadOptions,
+) -> std::io::Result<IdIndex> {
+    let debug = options.debug;
+
+    if !options.save_index {
+        if debug {
+            debug!("Skipping ID index save (--never-save-index flag set)");
+        }
+        let file_name = idx_path.file_name().unwrap_or_default().to_string_lossy();
+        let tmp_path =
+            std::env::temp_dir().join(format!("{}.{}.tmp", file_name, std::process::id()));
+        build_id_index_temp_file(
+            path,
+            header,
+            &tmp_path,
+            progress,
+            options.sample_presence,
+            debug,
+        )?;
+        let index = IdIndex::open(&tmp_path);
+        let _ = std::fs::remove_file(&tmp_path);
+        return index;
+    }
+
+    let tmp_path = PathBuf::from(format!("{}.tmp", idx_path.display()));
+    if debug {
+        debug!("Writing ID index to temporary file: {}", tmp_path.display());
+    }
+    build_id_index_temp_file(
+        path,
+        header,
+        &tmp_path,
+        progress,
+        options.sample_presence,
+        debug,
+    )?;
+
+    if replace_existing {
+        if let Err(e) = std::fs::remove_file(idx_path) {
+            warn!("Failed to remove existing ID index: {}", e);
+        }
+    }
+
+    match commit_id_index_file(&tmp_path, idx_path, debug) {
+        Ok(()) => {
+            info!("ID index saved to {}", idx_path.display());
+            IdIndex::open(idx_path)
+        }
+        Err(e) => {
+            warn!("Failed to save ID index to disk: {}", e);
+            info!("Continuing with the temporary index file...");
+            IdIndex::open(&tmp_path)
+        }
+    }
+}
+
+// Outcome of looking for a usable ID index on disk
+enum IdIndexSource {
+    Loaded(IdIndex),
+    Build {
+        save_index: bool,
+        replace_existing: bool,
+    },
 }
 
-// Helper function to parse a VCF record into a Variant
-fn parse_variant_record(record: &vcf::Record, header: &vcf::Header) -> std::io::Result<Variant> {
-    // Serialize record to VCF row string for filtering
-    let mut raw_row = Vec::new();
-    {
-        let mut writer = vcf::io::Writer::new(&mut raw_row);
-        writer
-            .write_record(header, record)
-            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+// Open the ID index next to the VCF, or decide how it has to be rebuilt
+// `sample_presence` rebuilds an index written without the sample presence bitmaps.
+fn open_id_index(
+    path: &PathBuf,
+    idx_path: &PathBuf,
+    save_index: bool,
+    sample_presence: bool,
+    debug: bool,
+) -> std::io::Result<IdIndexSource> {
+    if !idx_path.exists() {
+        // Build ID index from scratch
+        return Ok(IdIndexSource::Build {
+            save_index,
+            replace_existing: false,
+        });
     }
-    let raw_row_string = String::from_utf8(raw_row)
-        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?
-        .trim_end()
-        .to_string();
 
-    Ok(Variant {
-        chromosome: record.reference_sequence_name().to_string(),
-        position: usize::from(
-            record
-                .variant_start()
-                .transpose()
-                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?
-                .ok_or_else(|| {
-                    std::io::Error::new(std::io::ErrorKind::InvalidData, "Missing position")
-                })?,
-        ) as u64,
-        id: record.ids().iter().next().unwrap_or(".").to_string(),
-        reference: record.reference_bases().to_string(),
-        alternate: record
-            .alternate_bases()
-            .iter()
-            .map(|alt| {
-                alt.map(|a| a.to_string())
-                    .unwrap_or_else(|_| ".".to_string())
-            })
-            .collect(),
-        quality: record
-            .quality_score()
-            .transpose()
-            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?,
-        filter: record
-            .filters()
-            .iter(header)
-            .filter_map(|f| f.ok())
-            .map(|filter| filter.to_string())
-            .collect(),
-        info: record
-            .info()
-            .iter(header)
-            .map(|item| {
-                item.map(|(key, value)| {
-                    if let Some(val) = value {
-                        let debug_str = format!("{:?}", val);
-                        let json_value = convert_info_value(&debug_str);
-                        (key.to_string(), json_value)
+    // Load existing ID index
+    if debug {
+        debug!("Found ID index: {}", idx_path.display());
+    }
+    info!("Loading VCF file with existing ID index...");
+    let source = match load_id_index_from_disk(idx_path, debug) {
+        Ok(index) if index.source() != SourceFingerprint::of_file(path)? => {
+            // The VCF was regenerated or modified after the index was built
+            if debug {
+                debug!(
+                    "ID index fingerprint {:?} does not match VCF",
+                    index.source()
+                );
+            }
+            warn!("ID index is stale (VCF changed since it was built)");
+            info!("Rebuilding ID index...");
+            // Unmap the old file before it is replaced
+            drop(index);
+            IdIndexSource::Build {
+                save_index,
+                replace_existing: true,
+            }
+        }
+        Ok(index) if sample_presence && !index.has_sample_presence() => {
+            info!("ID index has no sample presence bitmaps; rebuilding it with them...");
+            drop(index);
+            IdIndexSource::Build {
+                save_index,
+                replace_existing: true,
+            }
+        }
+        Ok(index) => {
+            info!("ID index loaded ({} unique IDs)", index.id_count());
+            IdIndexSource::Loaded(index)
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::Unsupported => {
+            // Written by a newer release: keep it for that release and index in memory
+            warn!("{} ({})", e, idx_path.display());
+            info!("Building an in-memory ID index without replacing it...");
+            IdIndexSource::Build {
+                save_index: false,
+                replace_existing: false,
+            }
+        }
+        Err(e) => {
+            // Also covers outdated formats, e.g. the bincode HashMap used by older releases
+            warn!("Failed to load ID index: {}", e);
+            info!("Rebuilding ID index...");
+            IdIndexSource::Build {
+                save_index,
+                replace_existing: true,
+            }
+        }
+    };
+    Ok(source)
+}
+
+// Load statistics cached next to the VCF; None if absent or unreadable
+fn load_cached_statistics(stats_path: &PathBuf, debug: bool) -> Option<VcfStatistics> {
+    if !stats_path.exists() {
+        return None;
+    }
+
+    if debug {
+        debug!("Found statistics file: {}", stats_path.display());
+    }
+    info!("Loading VCF statistics from cache...");
+    match load_statistics_from_disk(stats_path, debug) {
+        Ok(stats) => {
+            info!(
+                "Statistics loaded ({} total variants)",
+                stats.total_variants
+            );
+            Some(stats)
+        }
+        Err(e) => {
+            warn!("Failed to load statistics: {}", e);
+            info!("Recomputing statistics...");
+            None
+        }
+    }
+}
+
+// Compute statistics and, if allowed, cache them next to the VCF
+fn compute_and_store_statistics(
+    path: &PathBuf,
+    header: &vcf::Header,
+    id_index: &IdIndex,
+    save_index: bool,
+    debug: bool,
+) -> std::io::Result<VcfStatistics> {
+    let stats = compute_statistics_from_vcf(path, header, id_index, debug)?;
+    let stats_path = PathBuf::from(format!("{}.stats", path.display()));
+
+    // Try to save statistics to disk if requested
+    if save_index {
+        match save_statistics_to_disk(&stats, &stats_path, debug) {
+            Ok(()) => info!("Statistics saved to {}", stats_path.display()),
+            Err(e) => {
+                warn!("Failed to save statistics to disk: {}", e);
+                info!("Continuing with in-memory statistics...");
+            }
+        }
+    } else if debug {
+        debug!("Skipping statistics save (--never-save-index flag set)");
+    }
+
+    Ok(stats)
+}
+
+// Indexing left to do once the VCF is open: the ID index (unless loaded) and then statistics,
+// which need its ID count
+struct IndexJob {
+    path: PathBuf,
+    header: vcf::Header,
+    source: IdIndexSource,
+    options: LoadOptions,
+    id_index: Arc<OnceLock<IdIndex>>,
+    statistics: Arc<OnceLock<VcfStatistics>>,
+    progress: Arc<BuildProgress>,
+}
+
+impl IndexJob {
+    // Whether finishing requires a scan of the VCF
+    fn needs_scan(&self) -> bool {
+        matches!(self.source, IdIndexSource::Build { .. }) || self.statistics.get().is_none()
+    }
+
+    fn run(self) -> std::io::Result<()> {
+        let index = match self.source {
+            IdIndexSource::Loaded(index) => index,
+            IdIndexSource::Build {
+                save_index,
+                replace_existing,
+            } => {
+                let idx_path = PathBuf::from(format!("{}.idx", self.path.display()));
+                build_and_store_id_index(
+                    &self.path,
+                    &self.header,
+                    &idx_path,
+                    replace_existing,
+                    Some(&self.progress),
+                    &LoadOptions {
+                        save_index,
+                        ..self.options
+                    },
+                )?
+            }
+        };
+        let id_index = self.id_index.get_or_init(|| index);
+
+        // Stopped at shutdown: statistics are computed on the next load
+        if self.progress.stop.load(Ordering::Relaxed) {
+            return Ok(());
+        }
+        if self.statistics.get().is_none() {
+            let stats = compute_and_store_statistics(
+                &self.path,
+                &self.header,
+                id_index,
+                self.options.save_index,
+                self.options.debug,
+            )?;
+            let _ = self.statistics.set(stats);
+        }
+        Ok(())
+    }
+}
+
+// How load_vcf_with_options indexes a VCF file
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LoadOptions {
+    pub debug: bool,
+    // Save built indexes and statistics next to the VCF
+    pub save_index: bool,
+    // Build a missing ID index (and statistics) on a background thread while position and
+    // region queries are served; `VcfIndex::id_index_status` reports its progress
+    pub background: bool,
+    // Build the ID index by external sort with bounded memory, for dbSNP-sized files
+    pub low_memory: bool,
+    // Record which samples carry each variant in the ID index, so present_in_sample queries
+    // skip the records a sample doesn't carry without parsing them
+    pub sample_presence: bool,
+    // Bytes of decompressed BGZF blocks cached for repeated queries (0 disables the cache)
+    pub block_cache_bytes: usize,
+    // Memory-map the VCF for queries instead of reading it through file handles
+    pub mmap: bool,
+    // Limits enforced on every region query
+    pub limits: QueryLimits,
+    // Retries of reads that fail with a transient I/O error
+    pub retry: RetryPolicy,
+    // Serve no FORMAT or sample columns: variants lose them as they are read, and the header,
+    // metadata and sample lookups show a file without samples
+    pub hide_genotypes: bool,
+}
+
+// Load and index VCF file
+#[allow(dead_code)] // Library entry point; the server binary calls load_vcf_with_options
+pub fn load_vcf(path: &PathBuf, debug: bool, save_index: bool) -> std::io::Result<VcfIndex> {
+    load_vcf_with_options(
+        path,
+        &LoadOptions {
+            debug,
+            save_index,
+            ..Default::default()
+        },
+    )
+}
+
+pub fn load_vcf_with_options(path: &PathBuf, options: &LoadOptions) -> std::io::Result<VcfIndex> {
+    let LoadOptions {
+        debug,
+        save_index,
+        background,
+        block_cache_bytes,
+        mmap,
+        limits,
+        retry,
+        hide_genotypes,
+        ..
+    } = *options;
+
+    // Check for existing indices: TBI first (for compatibility), then CSI
+    let csi_path = PathBuf::from(format!("{}.csi", path.display()));
+    let tbi_path = PathBuf::from(format!("{}.tbi", path.display()));
+
+    let genomic_index = if tbi_path.exists() {
+        // Use existing tabix index (prefer TBI if it exists for compatibility)
+        if debug {
+            debug!("Found tabix index: {}", tbi_path.display());
+        }
+        info!("Loading VCF file with existing tabix index...");
+        GenomicIndex::Tabix(tabix::fs::read(&tbi_path)?)
+    } else if csi_path.exists() {
+        // Use existing CSI index
+        if debug {
+            debug!("Found CSI index: {}", csi_path.display());
+        }
+        info!("Loading VCF file with existing CSI index...");
+        GenomicIndex::Csi(csi::fs::read(&csi_path)?)
+    } else {
+        // Build tabix index on the fly (fallback - CSI requires external bcftools)
+        info!("No index found. Building tabix index...");
+        let index = vcf::fs::index(path)?;
+        info!("Tabix index built successfully");
+
+        // Try to save index to disk if requested
+        if save_index {
+            match save_tabix_index_to_disk(&index, &tbi_path, debug) {
+                Ok(()) => info!("Tabix index saved to {}", tbi_path.display()),
+                Err(e) => {
+                    warn!("Failed to save tabix index to disk: {}", e);
+                    info!("Continuing with in-memory index...");
+                }
+            }
+        } else if debug {
+            debug!("Skipping index save (--never-save-index flag set)");
+        }
+
+        GenomicIndex::Tabix(index)
+    };
+
+    // Read the header and check for a first record; query readers are opened on demand by
+    // the reader pool
+    let file = File::open(path)?;
+    let mut reader = vcf::io::Reader::new(bgzf::io::Reader::new(file));
+    let header = reader.read_header()?;
+    let empty = reader.read_record(&mut vcf::Record::default())? == 0;
+    if empty {
+        info!("VCF file has no variant records; serving header metadata only");
+    }
+
+    // Initialize filter engine with VCF header
+    let header_string = {
+        let mut buffer = Vec::new();
+        let mut writer = vcf::io::Writer::new(&mut buffer);
+        if writer.write_header(&header).is_ok() {
+            String::from_utf8_lossy(&buffer).to_string()
+        } else {
+            String::new() // Empty header if write fails
+        }
+    };
+
+    let filter_engine = Arc::new(FilterEngine::new(&header_string).map_err(|e| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("Failed to create filter engine: {}", e),
+        )
+    })?);
+
+    let idx_path = PathBuf::from(format!("{}.idx", path.display()));
+    let stats_path = PathBuf::from(format!("{}.stats", path.display()));
+
+    let id_index = Arc::new(OnceLock::new());
+    let statistics = Arc::new(OnceLock::new());
+    let id_index_progress = Arc::new(BuildProgress::default());
+    if let Some(stats) = load_cached_statistics(&stats_path, debug) {
+        let _ = statistics.set(stats);
+    }
+
+    let job = IndexJob {
+        path: path.clone(),
+        header: header.clone(),
+        source: open_id_index(
+            path,
+            &idx_path,
+            save_index,
+            presence_sample_count(&header, options.sample_presence).is_some(),
+            debug,
+        )?,
+        options: *options,
+        id_index: Arc::clone(&id_index),
+        statistics: Arc::clone(&statistics),
+        progress: Arc::clone(&id_index_progress),
+    };
+
+    if background && job.needs_scan() {
+        info!("Building ID index in the background; ID queries are unavailable until it is ready");
+        let progress = Arc::clone(&id_index_progress);
+        let thread = std::thread::Builder::new()
+            .name("id-index-build".to_string())
+            .spawn(move || {
+                if let Err(e) = job.run() {
+                    if e.kind() == std::io::ErrorKind::Interrupted {
+                        info!("Background ID index build stopped; it restarts on the next load");
                     } else {
-                        // Flag with no value - just the key is present
-                        (key.to_string(), serde_json::Value::Bool(true))
+                        error!("Background ID index build failed: {}", e);
                     }
-                })
-            })
-            .filter_map(|item| item.ok())
-            .collect(),
-        raw_row: raw_row_string,
+                    *progress.error.lock().unwrap() = Some(e.to_string());
+                }
+            })?;
+        *id_index_progress.thread.lock().unwrap() = Some(thread);
+        info!("VCF loaded (indexed mode, ID index pending)");
+    } else {
+        job.run()?;
+        info!("VCF loaded (indexed mode)");
+    }
+
+    let redacted_header = hide_genotypes.then(|| {
+        let mut redacted = header.clone();
+        redacted.formats_mut().clear();
+        redacted.sample_names_mut().clear();
+        redacted
+    });
+
+    Ok(VcfIndex {
+        path: path.clone(),
+        index: genomic_index,
+        header,
+        readers: ReaderPool::new(path, block_cache_bytes, mmap)?,
+        empty,
+        limits,
+        retry,
+        id_index,
+        id_index_progress,
+        filter_engine,
+        statistics,
+        redacted_header,
     })
 }
 
-// Helper function to save ID index to disk
-// Helper function to atomically save statistics to disk
-fn save_statistics_to_disk(
-    statistics: &VcfStatistics,
-    stats_path: &PathBuf,
+// Helper function to atomically save tabix index to disk
+fn save_tabix_index_to_disk(
+    index: &tabix::Index,
+    tbi_path: &PathBuf,
     debug: bool,
 ) -> std::io::Result<()> {
     use std::fs;
-    use std::io::Write;
+    use std::io::BufWriter;
 
     // Create temporary file with .tmp extension
-    let tmp_path = PathBuf::from(format!("{}.tmp", stats_path.display()));
+    let tmp_path = PathBuf::from(format!("{}.tmp", tbi_path.display()));
 
     if debug {
-        eprintln!(
-            "Writing statistics to temporary file: {}",
+        debug!(
+            "Writing tabix index to temporary file: {}",
             tmp_path.display()
         );
     }
 
-    // Serialize and write to temp file
+    // Write index to temporary file
     {
-        let encoded = bincode::serialize(statistics)
-            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
-        let mut tmp_file = fs::File::create(&tmp_path)?;
-        tmp_file.write_all(&encoded)?;
-        tmp_file.flush()?;
-        tmp_file.sync_all()?; // Force OS to write to disk
+        let tmp_file = fs::File::create(&tmp_path)?;
+        let mut writer = tabix::io::Writer::new(BufWriter::new(tmp_file));
+        writer.write_index(index)?;
+    }
+
+    // Check again if .tbi file was created by another process (race condition)
+    if tbi_path.exists() {
+        if debug {
+            debug!("Index file appeared during write, removing temporary file");
+        }
+        fs::remove_file(&tmp_path)?;
+        return Ok(());
+    }
+
+    // Atomically rename temp file to final .tbi file
+    fs::rename(&tmp_path, tbi_path)?;
+
+    Ok(())
+}
+
+// Helper function to atomically save CSI index to disk
+// Save CSI index to disk with atomic write (currently unused but provided for future functionality)
+#[allow(dead_code)]
+fn save_csi_index_to_disk(
+    index: &csi::Index,
+    csi_path: &PathBuf,
+    debug: bool,
+) -> std::io::Result<()> {
+    use std::fs;
+    use std::io::BufWriter;
+
+    // Create temporary file with .tmp extension
+    let tmp_path = PathBuf::from(format!("{}.tmp", csi_path.display()));
+
+    if debug {
+        debug!(
+            "Writing CSI index to temporary file: {}",
+            tmp_path.display()
+        );
     }
 
-    // Check if .stats file was created by another process (race condition)
-    if stats_path.exists() {
+    // Write index to temporary file
+    {
+        let tmp_file = fs::File::create(&tmp_path)?;
+        let mut writer = csi::io::Writer::new(BufWriter::new(tmp_file));
+        writer.write_index(index)?;
+    }
+
+    // Check again if .csi file was created by another process (race condition)
+    if csi_path.exists() {
         if debug {
-            eprintln!("Statistics file appeared during write, removing temporary file");
+            debug!("Index file appeared during write, removing temporary file");
         }
         fs::remove_file(&tmp_path)?;
         return Ok(());
     }
 
-    // Atomically rename temp file to final .stats file
-    fs::rename(&tmp_path, stats_path)?;
+    // Atomically rename temp file to final .csi file
+    fs::rename(&tmp_path, csi_path)?;
 
     Ok(())
 }
 
-// Helper function to load statistics from disk
-fn load_statistics_from_disk(stats_path: &PathBuf, debug: bool) -> std::io::Result<VcfStatistics> {
-    use std::fs;
-    use std::io::Read;
+// BED interval: 0-based start, exclusive end
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BedInterval {
+    pub chromosome: String,
+    pub start: u64,
+    pub end: u64,
+    // Variant ID for per-variant intervals; None for merged intervals (written as BED3)
+    pub name: Option<String>,
+}
 
-    if debug {
-        eprintln!("Loading statistics from: {}", stats_path.display());
+impl BedInterval {
+    pub fn to_bed_line(&self) -> String {
+        match &self.name {
+            Some(name) => format!(
+                "{}\t{}\t{}\t{}",
+                self.chromosome, self.start, self.end, name
+            ),
+            None => format!("{}\t{}\t{}", self.chromosome, self.start, self.end),
+        }
     }
+}
 
-    let mut file = fs::File::open(stats_path)?;
-    let mut buffer = Vec::new();
-    file.read_to_end(&mut buffer)?;
+// BED intervals covering each variant's reference span widened by `padding` on both sides.
+// With `merge`, overlapping or touching intervals are combined, as `bedtools merge` does.
+pub fn bed_intervals(variants: &[Variant], padding: u64, merge: bool) -> Vec<BedInterval> {
+    let mut intervals: Vec<BedInterval> = variants
+        .iter()
+        .map(|variant| BedInterval {
+            chromosome: variant.chromosome.clone(),
+            start: variant.position.saturating_sub(1 + padding),
+            end: variant_end(variant).saturating_add(padding),
+            name: Some(variant.id.clone()),
+        })
+        .collect();
+    if !merge {
+        return intervals;
+    }
 
-    let statistics: VcfStatistics = bincode::deserialize(&buffer)
-        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+    intervals.sort_by(|a, b| (&a.chromosome, a.start).cmp(&(&b.chromosome, b.start)));
+    let mut merged: Vec<BedInterval> = Vec::new();
+    for interval in intervals {
+        match merged.last_mut() {
+            Some(last) if last.chromosome == interval.chromosome && interval.start <= last.end => {
+                last.end = last.end.max(interval.end);
+            }
+            _ => merged.push(BedInterval {
+                name: None,
+                ..interval
+            }),
+        }
+    }
+    merged
+}
 
-    Ok(statistics)
+// Run of nearby variants on one chromosome; positions are 1-based and inclusive
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct VariantCluster {
+    pub chromosome: String,
+    pub start: u64,
+    pub end: u64,
+    pub span: u64,
+    pub variant_count: usize,
+    // IDs of the clustered variants; missing (".") IDs are left out
+    pub ids: Vec<String>,
 }
 
-// Helper function to compute statistics by scanning all variants
-fn compute_statistics_from_vcf(
-    path: &PathBuf,
-    header: &vcf::Header,
-    id_index: &HashMap<String, Vec<(String, u64)>>,
-    debug: bool,
-) -> std::io::Result<VcfStatistics> {
-    if debug {
-        eprintln!("Computing VCF statistics...");
+// Group variants whose reference spans are at most `max_gap` bases apart (overlapping
+// variants are always grouped) and keep clusters of at least `min_size` variants
+pub fn cluster_variants(
+    variants: &[Variant],
+    max_gap: u64,
+    min_size: usize,
+) -> Vec<VariantCluster> {
+    let mut sorted: Vec<&Variant> = variants.iter().collect();
+    sorted.sort_by(|a, b| (&a.chromosome, a.position).cmp(&(&b.chromosome, b.position)));
+
+    let mut clusters: Vec<VariantCluster> = Vec::new();
+    for variant in sorted {
+        let end = variant_end(variant);
+        match clusters.last_mut() {
+            Some(last)
+                if last.chromosome == variant.chromosome
+                    && variant.position.saturating_sub(last.end + 1) <= max_gap =>
+            {
+                last.end = last.end.max(end);
+                last.variant_count += 1;
+                last.ids.extend(variant.ids.iter().cloned());
+            }
+            _ => clusters.push(VariantCluster {
+                chromosome: variant.chromosome.clone(),
+                start: variant.position,
+                end,
+                span: 0,
+                variant_count: 1,
+                ids: variant.ids.clone(),
+            }),
+        }
     }
 
-    // Extract metadata using existing helper function
-    let metadata = extract_metadata(header);
+    clusters.retain(|cluster| cluster.variant_count >= min_size.max(1));
+    for cluster in &mut clusters {
+        cluster.span = cluster.end - cluster.start + 1;
+    }
+    clusters
+}
 
-    // Get chromosomes from header contigs (fallback to index if empty)
-    let mut chromosomes: Vec<String> = header.contigs().keys().map(|k| k.to_string()).collect();
+// Where the gene name sits in the file's annotation INFO fields: (field, subfield slot) for
+// each field whose header description lists a Gene_Name (SnpEff ANN), SYMBOL (VEP CSQ) or
+// Gene subfield
+#[derive(Debug, Clone, Default)]
+pub struct GeneAnnotations {
+    // INFO field, position of the gene subfield and its name
+    slots: Vec<(String, usize, String)>,
+}
 
-    if chromosomes.is_empty() {
-        // Fall back to variants_per_chromosome keys collected during scan
-        // We'll populate this after the scan
+impl GeneAnnotations {
+    fn from_header(definitions: &[HeaderDefinition]) -> Self {
+        let slots = definitions
+            .iter()
+            .filter_map(|definition| {
+                let subfields = definition.subfields.as_ref()?;
+                let slot = ["Gene_Name", "SYMBOL", "Gene"].iter().find_map(|name| {
+                    subfields
+                        .iter()
+                        .position(|subfield| subfield.eq_ignore_ascii_case(name))
+                })?;
+                Some((definition.id.clone(), slot, subfields[slot].clone()))
+            })
+            .collect();
+        GeneAnnotations { slots }
     }
 
-    // Unique IDs from existing id_index (no scan needed)
-    let unique_ids = id_index.len() as u64;
+    pub fn is_empty(&self) -> bool {
+        self.slots.is_empty()
+    }
 
-    // Counters for single-pass scan
-    let mut total_variants = 0u64;
-    let mut variants_per_chromosome: HashMap<String, u64> = HashMap::new();
-    let mut missing_ids = 0u64;
-    let mut filter_counts: HashMap<String, u64> = HashMap::new();
+    // Distinct gene names a record's annotations mention, in order of appearance
+    pub fn genes(&self, variant: &Variant) -> Vec<String> {
+        let mut genes: Vec<String> = Vec::new();
+        let Some(info) = variant.raw_row.split('\t').nth(7) else {
+            return genes;
+        };
+        for field in info.split(';') {
+            let Some((key, value)) = field.split_once('=') else {
+                continue;
+            };
+            let Some(&(_, slot, _)) = self.slots.iter().find(|(id, _, _)| id == key) else {
+                continue;
+            };
+            for entry in value.split(',') {
+                match entry.split('|').nth(slot) {
+                    Some(gene) if !gene.is_empty() && !genes.iter().any(|g| g == gene) => {
+                        genes.push(gene.to_string())
+                    }
+                    _ => {}
+                }
+            }
+        }
+        genes
+    }
 
-    // Quality statistics (running calculations)
-    let mut qual_min = f32::INFINITY;
-    let mut qual_max = f32::NEG_INFINITY;
-    let mut qual_sum = 0.0;
-    let mut qual_count = 0u64;
+    // A filter expression keeping records annotated with `gene`, e.g. ANN[*].Gene_Name == "BRCA2"
+    pub fn filter(&self, gene: &str) -> Option<String> {
+        let clauses: Vec<String> = self
+            .slots
+            .iter()
+            .map(|(id, _, subfield)| format!("{}[*].{} == {:?}", id, subfield, gene))
+            .collect();
+        (!clauses.is_empty()).then(|| clauses.join(" || "))
+    }
+}
 
-    // Variant type counters
-    let mut snps = 0u64;
-    let mut insertions = 0u64;
-    let mut deletions = 0u64;
-    let mut mnps = 0u64;
-    let mut complex = 0u64;
+// A record where one sample is heterozygous (two different alleles, at least one ALT)
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct HeterozygousSite {
+    pub position: u64,
+    pub id: String,
+    pub reference: String,
+    pub alternate: Vec<String>,
+    pub genotype: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub phase_set: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub genes: Vec<String>,
+}
 
-    // Single-pass scan through all variants
-    let file = File::open(path)?;
-    let mut reader = vcf::io::Reader::new(bgzf::io::Reader::new(file));
-    let _ = reader.read_header()?; // Skip header
+// What a pair's phasing says about the two ALT alleles
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PairPhase {
+    // On different haplotypes: both copies of the gene are hit
+    Trans,
+    // On the same haplotype: one copy is left intact, so not a compound heterozygote
+    Cis,
+    // Either genotype is unphased, or they are in different phase sets
+    Unknown,
+}
 
-    for record in reader.records().flatten() {
-        if let Ok(variant) = parse_variant_record(&record, header) {
-            total_variants += 1;
+// Two heterozygous sites of one sample (indexes into the site list) that may knock out
+// both copies of a gene
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct CompoundHetPair {
+    pub sites: [usize; 2],
+    pub phase: PairPhase,
+}
 
-            // Count per chromosome
-            *variants_per_chromosome
-                .entry(variant.chromosome.clone())
-                .or_insert(0) += 1;
+// The sample's call at a record, if heterozygous
+pub fn heterozygous_site(
+    variant: &Variant,
+    sample: usize,
+    annotations: &GeneAnnotations,
+) -> Option<HeterozygousSite> {
+    let genotype = sample_field(&variant.raw_row, sample, "GT")?;
+    let alleles: Vec<usize> = genotype
+        .split(['/', '|'])
+        .map(|allele| allele.parse().ok())
+        .collect::<Option<_>>()?;
+    let [first, second] = alleles[..] else {
+        return None;
+    };
+    if first == second {
+        return None;
+    }
+    Some(HeterozygousSite {
+        position: variant.position,
+        id: variant.id.clone(),
+        reference: variant.reference.clone(),
+        alternate: variant.alternate.clone(),
+        genotype: genotype.to_string(),
+        phase_set: sample_field(&variant.raw_row, sample, "PS")
+            .filter(|ps| *ps != ".")
+            .map(str::to_string),
+        genes: annotations.genes(variant),
+    })
+}
 
-            // Count missing IDs
-            if variant.id == "." {
-                missing_ids += 1;
-            }
+// Haplotype slots carrying an ALT allele, when the genotype is phased
+fn alternate_haplotypes(site: &HeterozygousSite) -> Option<Vec<usize>> {
+    let alleles = phased_alleles(&site.genotype)?;
+    Some(
+        (0..alleles.len())
+            .filter(|&slot| alleles[slot] > 0)
+            .collect(),
+    )
+}
 
-            // Track quality stats
-            if let Some(qual) = variant.quality {
-                qual_min = qual_min.min(qual);
-                qual_max = qual_max.max(qual);
-                qual_sum += qual as f64;
-                qual_count += 1;
+// Phase of two heterozygous sites: phased genotypes in the same phase set (or without one,
+// as for MNVs) are cis when their ALT alleles share their only haplotype, trans otherwise
+pub fn pair_phase(first: &HeterozygousSite, second: &HeterozygousSite) -> PairPhase {
+    let same_phase_set = first.phase_set.is_none()
+        || second.phase_set.is_none()
+        || first.phase_set == second.phase_set;
+    match (alternate_haplotypes(first), alternate_haplotypes(second)) {
+        (Some(a), Some(b)) if same_phase_set => {
+            if a.len() == 1 && a == b {
+                PairPhase::Cis
+            } else {
+                PairPhase::Trans
             }
+        }
+        _ => PairPhase::Unknown,
+    }
+}
 
-            // Count filter categories
-            for filter in &variant.filter {
-                *filter_counts.entry(filter.clone()).or_insert(0) += 1;
+// Candidate compound heterozygous pairs among a sample's heterozygous sites, trans pairs
+// first, with the number of cis pairs left out. With `same_gene`, only sites sharing an
+// annotated gene are paired.
+pub fn compound_het_pairs(
+    sites: &[HeterozygousSite],
+    same_gene: bool,
+) -> (Vec<CompoundHetPair>, usize) {
+    let mut pairs = Vec::new();
+    let mut cis_pairs = 0;
+    for (i, first) in sites.iter().enumerate() {
+        for (j, second) in sites.iter().enumerate().skip(i + 1) {
+            if same_gene && !first.genes.iter().any(|gene| second.genes.contains(gene)) {
+                continue;
             }
-
-            // Classify variant type
-            let ref_len = variant.reference.len();
-            if variant.alternate.len() == 1 {
-                let alt_len = variant.alternate[0].len();
-                if ref_len == 1 && alt_len == 1 {
-                    snps += 1;
-                } else if ref_len < alt_len {
-                    insertions += 1;
-                } else if ref_len > alt_len {
-                    deletions += 1;
-                } else if ref_len == alt_len && ref_len > 1 {
-                    mnps += 1;
-                } else {
-                    complex += 1;
-                }
-            } else {
-                // Multiple alternates or complex
-                complex += 1;
+            match pair_phase(first, second) {
+                PairPhase::Cis => cis_pairs += 1,
+                phase => pairs.push(CompoundHetPair {
+                    sites: [i, j],
+                    phase,
+                }),
             }
         }
     }
+    pairs.sort_by_key(|pair| pair.phase != PairPhase::Trans);
+    (pairs, cis_pairs)
+}
 
-    // Compute quality statistics
-    let quality_stats = if qual_count > 0 {
-        Some(QualityStats {
-            min: qual_min,
-            max: qual_max,
-            mean: (qual_sum / qual_count as f64) as f32,
-        })
-    } else {
-        None
-    };
+// Adjacent SNVs phased onto the same haplotype of one sample, reported as a single MNV
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct MergedMnv {
+    pub chromosome: String,
+    pub position: u64,
+    pub reference: String,
+    pub alternate: String,
+    // GT allele slots (0-based) carrying every component, e.g. [0, 1] for a homozygous MNV
+    pub haplotypes: Vec<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub phase_set: Option<String>,
+    pub component_positions: Vec<u64>,
+    pub component_ids: Vec<String>,
+    // Codons hit by more than one component according to SnpEff ANN annotations; the
+    // per-SNV consequences listed there do not describe the combined change
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub shared_codons: Vec<SharedCodon>,
+}
 
-    // If header had no contigs, use chromosomes from actual variants
-    if chromosomes.is_empty() {
-        chromosomes = variants_per_chromosome.keys().cloned().collect();
-        chromosomes.sort(); // Sort for consistent ordering
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct SharedCodon {
+    pub transcript: String,
+    pub amino_acid_position: u64,
+    // HGVS.p of each component on this transcript, in component order
+    pub component_consequences: Vec<String>,
+}
+
+// The eight site columns of a VCF row (CHROM to INFO), without FORMAT and the samples
+fn site_columns(raw_row: &str) -> &str {
+    match raw_row.match_indices('\t').nth(7) {
+        Some((end, _)) => &raw_row[..end],
+        None => raw_row,
     }
+}
 
-    // Get reference genome using existing helper
-    let reference_genome_info = extract_reference_genome(header);
-    let reference_genome = format!(
-        "{} ({})",
-        reference_genome_info.build,
-        match reference_genome_info.source {
-            ReferenceGenomeSource::HeaderLine => "from header",
-            ReferenceGenomeSource::InferredFromContigLengths => "inferred from contigs",
-            ReferenceGenomeSource::Unknown => "unknown source",
-        }
-    );
+// Value of FORMAT field `key` for the sample in column `sample`, read from the raw row
+fn sample_field<'a>(raw_row: &'a str, sample: usize, key: &str) -> Option<&'a str> {
+    let mut columns = raw_row.split('\t');
+    let format = columns.nth(8)?;
+    let values = columns.nth(sample)?;
+    let slot = format.split(':').position(|field| field == key)?;
+    values.split(':').nth(slot)
+}
 
-    if debug {
-        eprintln!(
-            "Statistics computed: {} total variants, {} chromosomes",
-            total_variants,
-            chromosomes.len()
-        );
-    } else {
-        eprintln!("Statistics computed ({} total variants)", total_variants);
+// Whether a GT value calls an ALT allele on any haplotype ("0/1", "1|2", "./1", ...)
+fn genotype_has_alternate(genotype: &str) -> bool {
+    genotype
+        .split(['/', '|'])
+        .any(|allele| allele.parse::<usize>().is_ok_and(|allele| allele > 0))
+}
+
+// Whether the sample in column `sample` has an ALT allele in its GT
+fn carries_alternate(raw_row: &str, sample: usize) -> bool {
+    sample_field(raw_row, sample, "GT").is_some_and(genotype_has_alternate)
+}
+
+// Bitmap of the first `sample_count` samples with an ALT allele in their GT, bit i of byte
+// i / 8 for sample i, as stored in the ID index's sample presence section
+fn sample_presence_bits(raw_row: &str, sample_count: usize) -> Vec<u8> {
+    let mut bits = vec![0u8; sample_count.div_ceil(8)];
+    let mut columns = raw_row.split('\t');
+    let Some(slot) = columns
+        .nth(8)
+        .and_then(|format| format.split(':').position(|field| field == "GT"))
+    else {
+        return bits;
+    };
+    for (sample, values) in columns.take(sample_count).enumerate() {
+        if values
+            .split(':')
+            .nth(slot)
+            .is_some_and(genotype_has_alternate)
+        {
+            bits[sample / 8] |= 1 << (sample % 8);
+        }
     }
+    bits
+}
 
-    Ok(VcfStatistics {
-        file_format: metadata.file_format,
-        reference_genome,
-        chromosome_count: chromosomes.len(),
-        sample_count: metadata.samples.len(),
-        chromosomes,
-        total_variants,
-        variants_per_chromosome,
-        unique_ids,
-        missing_ids,
-        quality_stats,
-        filter_counts,
-        variant_types: VariantTypeStats {
-            snps,
-            insertions,
-            deletions,
-            mnps,
-            complex,
-        },
-    })
+// Allele index on each haplotype of a phased (or haploid) genotype; None when unphased
+// or any allele is missing
+fn phased_alleles(genotype: &str) -> Option<Vec<usize>> {
+    if genotype.contains('/') {
+        return None;
+    }
+    genotype
+        .split('|')
+        .map(|allele| allele.parse().ok())
+        .collect()
 }
 
-fn save_id_index_to_disk(
-    id_index: &HashMap<String, Vec<(String, u64)>>,
-    idx_path: &PathBuf,
-    debug: bool,
-) -> std::io::Result<()> {
-    use std::fs;
-    use std::io::Write;
+// Phased SNV alternate base of one haplotype, if it carries one
+fn snv_on_haplotype(variant: &Variant, alleles: &[usize], haplotype: usize) -> Option<char> {
+    let allele = *alleles.get(haplotype)?;
+    if allele == 0 || variant.reference.len() != 1 {
+        return None;
+    }
+    let alternate = variant.alternate_alleles.get(allele - 1)?;
+    let mut bases = alternate.allele.chars();
+    match (alternate.allele_type, bases.next(), bases.next()) {
+        (AlleleType::Sequence, Some(base), None) => Some(base),
+        _ => None,
+    }
+}
 
-    // Create temporary file with .tmp extension
-    let tmp_path = PathBuf::from(format!("{}.tmp", idx_path.display()));
+// Merge runs of SNVs at consecutive positions that `sample` carries on the same haplotype
+// (phased GT, and equal PS when both records have one) into MNVs
+pub fn merge_phased_mnvs(variants: &[Variant], sample: usize) -> Vec<MergedMnv> {
+    let mut sorted: Vec<&Variant> = variants.iter().collect();
+    sorted.sort_by(|a, b| (&a.chromosome, a.position).cmp(&(&b.chromosome, b.position)));
 
-    if debug {
-        eprintln!("Writing ID index to temporary file: {}", tmp_path.display());
+    let genotypes: Vec<Option<Vec<usize>>> = sorted
+        .iter()
+        .map(|variant| sample_field(&variant.raw_row, sample, "GT").and_then(phased_alleles))
+        .collect();
+    let ploidy = genotypes.iter().flatten().map(Vec::len).max().unwrap_or(0);
+
+    let mut mnvs: Vec<MergedMnv> = Vec::new();
+    for haplotype in 0..ploidy {
+        let mut run: Vec<(&Variant, char)> = Vec::new();
+        let mut run_phase_set: Option<&str> = None;
+        for (&variant, alleles) in sorted.iter().zip(&genotypes) {
+            // A record with the REF allele on this haplotype (e.g. the other half of a split
+            // multiallelic site) leaves its sequence unchanged, so it doesn't end the run
+            if alleles
+                .as_deref()
+                .is_some_and(|alleles| alleles.get(haplotype).is_none_or(|&allele| allele == 0))
+            {
+                continue;
+            }
+            let base = alleles
+                .as_deref()
+                .and_then(|alleles| snv_on_haplotype(variant, alleles, haplotype));
+            let phase_set = sample_field(&variant.raw_row, sample, "PS").filter(|ps| *ps != ".");
+            let extends = run.last().is_some_and(|(last, _)| {
+                last.chromosome == variant.chromosome
+                    && variant.position == last.position + 1
+                    && (run_phase_set.is_none()
+                        || phase_set.is_none()
+                        || run_phase_set == phase_set)
+            });
+            if !extends || base.is_none() {
+                push_mnv(&mut mnvs, &run, run_phase_set, haplotype);
+                run.clear();
+                run_phase_set = None;
+            }
+            if let Some(base) = base {
+                run.push((variant, base));
+                run_phase_set = run_phase_set.or(phase_set);
+            }
+        }
+        push_mnv(&mut mnvs, &run, run_phase_set, haplotype);
     }
 
-    // Serialize and write to temp file
-    {
-        let encoded = bincode::serialize(id_index)
-            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
-        let mut tmp_file = fs::File::create(&tmp_path)?;
-        tmp_file.write_all(&encoded)?;
-        tmp_file.flush()?;
-        tmp_file.sync_all()?; // Force OS to write to disk
+    mnvs.sort_by(|a, b| (&a.chromosome, a.position).cmp(&(&b.chromosome, b.position)));
+    mnvs
+}
+
+// Record a finished run as an MNV, or add the haplotype to an identical one already found
+fn push_mnv(
+    mnvs: &mut Vec<MergedMnv>,
+    run: &[(&Variant, char)],
+    phase_set: Option<&str>,
+    haplotype: usize,
+) {
+    if run.len() < 2 {
+        return;
+    }
+    let (first, _) = run[0];
+    let alternate: String = run.iter().map(|(_, base)| base).collect();
+    if let Some(existing) = mnvs.iter_mut().find(|mnv| {
+        mnv.chromosome == first.chromosome
+            && mnv.position == first.position
+            && mnv.alternate == alternate
+    }) {
+        existing.haplotypes.push(haplotype);
+        return;
     }
 
-    // Check if .idx file was created by another process (race condition)
-    if idx_path.exists() {
-        if debug {
-            eprintln!("ID index file appeared during write, removing temporary file");
+    mnvs.push(MergedMnv {
+        chromosome: first.chromosome.clone(),
+        position: first.position,
+        reference: run
+            .iter()
+            .map(|(variant, _)| variant.reference.as_str())
+            .collect(),
+        alternate,
+        haplotypes: vec![haplotype],
+        phase_set: phase_set.map(str::to_string),
+        component_positions: run.iter().map(|(variant, _)| variant.position).collect(),
+        component_ids: run.iter().map(|(variant, _)| variant.id.clone()).collect(),
+        shared_codons: shared_codons(run),
+    });
+}
+
+// Codons that several components change, from SnpEff ANN entries for the carried allele:
+// Allele|Annotation|Impact|Gene|GeneID|FeatureType|FeatureID|BioType|Rank|HGVS.c|HGVS.p|cDNA|CDS|AA
+fn shared_codons(run: &[(&Variant, char)]) -> Vec<SharedCodon> {
+    let mut codons: Vec<(String, u64, Vec<(usize, String)>)> = Vec::new();
+    for (component, (variant, base)) in run.iter().enumerate() {
+        let Some(info) = variant.raw_row.split('\t').nth(7) else {
+            continue;
+        };
+        let Some(ann) = info.split(';').find_map(|field| field.strip_prefix("ANN=")) else {
+            continue;
+        };
+        for entry in ann.split(',') {
+            let fields: Vec<&str> = entry.split('|').collect();
+            if fields.len() < 14 || fields[0] != base.to_string() || fields[6].is_empty() {
+                continue;
+            }
+            let Some(position) = fields[13]
+                .split('/')
+                .next()
+                .and_then(|aa| aa.parse::<u64>().ok())
+            else {
+                continue;
+            };
+            let consequence = (component, fields[10].to_string());
+            match codons
+                .iter_mut()
+                .find(|(transcript, aa, _)| transcript == fields[6] && *aa == position)
+            {
+                Some((_, _, hits)) => hits.push(consequence),
+                None => codons.push((fields[6].to_string(), position, vec![consequence])),
+            }
         }
-        fs::remove_file(&tmp_path)?;
-        return Ok(());
     }
 
-    // Atomically rename temp file to final .idx file
-    fs::rename(&tmp_path, idx_path)?;
-
-    Ok(())
+    codons
+        .into_iter()
+        .filter(|(_, _, hits)| hits.iter().any(|(component, _)| *component != hits[0].0))
+        .map(|(transcript, amino_acid_position, hits)| SharedCodon {
+            transcript,
+            amino_acid_position,
+            component_consequences: hits.into_iter().map(|(_, hgvs_p)| hgvs_p).collect(),
+        })
+        .collect()
 }
 
-// Helper function to load ID index from disk
-fn load_id_index_from_disk(
-    idx_path: &PathBuf,
-    debug: bool,
-) -> std::io::Result<HashMap<String, Vec<(String, u64)>>> {
-    use std::fs;
-    use std::io::Read;
+// Format variant for MCP response (no-op now that types are unified)
+// Apply the output options of a query to one variant: projection, detail level, sample
+// subset and coordinate system. The raw row is left intact for later filtering.
+fn shape_variant(variant: &mut Variant, options: &QueryOptions, samples: &[(&str, usize)]) {
+    if let Some(fields) = &options.info_fields {
+        variant.info.retain(|key, _| fields.contains(key));
+    }
+    if options.detail == DetailLevel::Summary {
+        variant.info.clear();
+        variant.alternate_alleles.clear();
+    }
+    select_samples(variant, samples);
+    variant.position = options.coordinates.from_vcf_position(variant.position);
+}
 
-    if debug {
-        eprintln!("Loading ID index from: {}", idx_path.display());
+// Report the FORMAT values of the given samples (name and column, see
+// VcfIndex::sample_index) in `samples`; no samples leaves the variant without any
+pub fn select_samples<S: AsRef<str>>(variant: &mut Variant, samples: &[(S, usize)]) {
+    if samples.is_empty() {
+        return;
     }
+    variant.samples = Some(
+        samples
+            .iter()
+            .map(|(name, sample)| {
+                (
+                    name.as_ref().to_string(),
+                    sample_values(&variant.raw_row, *sample),
+                )
+            })
+            .collect(),
+    );
+}
 
-    let mut file = fs::File::open(idx_path)?;
-    let mut buffer = Vec::new();
-    file.read_to_end(&mut buffer)?;
+// GT value of the sample in column `sample` (see VcfIndex::sample_index)
+pub fn sample_genotype(variant: &Variant, sample: usize) -> Option<&str> {
+    sample_value(variant, sample, "GT")
+}
+
+// Value of FORMAT field `key` (e.g. "GQ", "DP") for the sample in column `sample`
+pub fn sample_value<'a>(variant: &'a Variant, sample: usize, key: &str) -> Option<&'a str> {
+    sample_field(&variant.raw_row, sample, key)
+}
 
-    let id_index: HashMap<String, Vec<(String, u64)>> = bincode::deserialize(&buffer)
-        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+// Allele indexes of a GT regardless of phasing and order ("1|0" and "0/1" both give [0, 1]);
+// None when any allele is missing
+pub fn unordered_alleles(genotype: &str) -> Option<Vec<usize>> {
+    let mut alleles: Vec<usize> = genotype
+        .split(['/', '|'])
+        .map(|allele| allele.parse().ok())
+        .collect::<Option<_>>()?;
+    alleles.sort_unstable();
+    Some(alleles)
+}
 
-    Ok(id_index)
+// All FORMAT values of one sample column, keyed by FORMAT field
+fn sample_values(raw_row: &str, sample: usize) -> HashMap<String, String> {
+    let mut columns = raw_row.split('\t');
+    let (Some(format), Some(values)) = (columns.nth(8), columns.nth(sample)) else {
+        return HashMap::new();
+    };
+    format
+        .split(':')
+        .zip(values.split(':'))
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect()
 }
 
-// Helper function to build ID index by scanning all variants
-fn build_id_index(
-    path: &PathBuf,
-    header: &vcf::Header,
-    debug: bool,
-) -> std::io::Result<HashMap<String, Vec<(String, u64)>>> {
-    let mut id_index: HashMap<String, Vec<(String, u64)>> = HashMap::new();
+pub fn format_variant(variant: Variant) -> Variant {
+    variant
+}
 
-    if debug {
-        eprintln!("Building ID index...");
+// One line of text per variant for callers short on context, e.g.
+// "20:14370 G>A rs6054257 QUAL=29 PASS AF=0.5 DB het(NA00002)": position, alleles, ID,
+// QUAL and FILTER when present, INFO fields by key (flags by name alone) and the zygosity
+// of each requested sample
+pub fn render_compact(variant: &Variant) -> String {
+    let mut parts = vec![
+        format!("{}:{}", variant.chromosome, variant.position),
+        format!("{}>{}", variant.reference, variant.alternate.join(",")),
+    ];
+    if !variant.id.is_empty() && variant.id != "." {
+        parts.push(variant.id.clone());
+    }
+    if let Some(quality) = variant.quality {
+        parts.push(format!("QUAL={}", quality));
+    }
+    if !variant.filter.is_empty() {
+        parts.push(variant.filter.join(";"));
     }
 
-    let file = File::open(path)?;
-    let mut reader = vcf::io::Reader::new(bgzf::io::Reader::new(file));
-    let _ = reader.read_header()?; // Skip header
+    let mut keys: Vec<&String> = variant.info.keys().collect();
+    keys.sort();
+    for key in keys {
+        parts.push(match &variant.info[key] {
+            serde_json::Value::Bool(true) => key.clone(),
+            value => format!("{}={}", key, compact_info_value(value)),
+        });
+    }
 
-    let mut count = 0;
-    for record in reader.records().flatten() {
-        if let Ok(variant) = parse_variant_record(&record, header) {
-            // Skip "." (missing ID)
-            if variant.id != "." {
-                id_index
-                    .entry(variant.id.clone())
-                    .or_default()
-                    .push((variant.chromosome.clone(), variant.position));
-            }
-            count += 1;
+    if let Some(samples) = &variant.samples {
+        let mut names: Vec<&String> = samples.keys().collect();
+        names.sort();
+        for name in names {
+            let genotype = samples[name].get("GT").map_or(".", String::as_str);
+            parts.push(format!("{}({})", zygosity(genotype), name));
         }
     }
+    parts.join(" ")
+}
 
-    if debug {
-        eprintln!(
-            "ID index built: {} variants scanned, {} unique IDs indexed",
-            count,
-            id_index.len()
-        );
-    } else {
-        eprintln!("ID index built ({} unique IDs)", id_index.len());
+fn compact_info_value(value: &serde_json::Value) -> String {
+    match value {
+        serde_json::Value::String(text) => text.clone(),
+        serde_json::Value::Array(values) => values
+            .iter()
+            .map(compact_info_value)
+            .collect::<Vec<_>>()
+            .join(","),
+        serde_json::Value::Null => ".".to_string(),
+        other => other.to_string(),
     }
-
-    Ok(id_index)
 }
 
-// Load and index VCF file
-pub fn load_vcf(path: &PathBuf, debug: bool, save_index: bool) -> std::io::Result<VcfIndex> {
-    // Check for existing indices: TBI first (for compatibility), then CSI
-    let csi_path = PathBuf::from(format!("{}.csi", path.display()));
-    let tbi_path = PathBuf::from(format!("{}.tbi", path.display()));
+// A column of the tabular output formats
+#[derive(Debug, Clone, PartialEq)]
+pub enum TableColumn {
+    Chromosome,
+    Position,
+    Id,
+    Reference,
+    Alternate,
+    Quality,
+    Filter,
+    VariantType,
+    // An INFO field by key
+    Info(String),
+    // The GT of a sample: its name and column (see VcfIndex::sample_index)
+    Genotype(String, usize),
+}
 
-    let genomic_index = if tbi_path.exists() {
-        // Use existing tabix index (prefer TBI if it exists for compatibility)
-        if debug {
-            eprintln!("Found tabix index: {}", tbi_path.display());
-        }
-        eprintln!("Loading VCF file with existing tabix index...");
-        GenomicIndex::Tabix(tabix::fs::read(&tbi_path)?)
-    } else if csi_path.exists() {
-        // Use existing CSI index
-        if debug {
-            eprintln!("Found CSI index: {}", csi_path.display());
+impl TableColumn {
+    // The column's name in the header row
+    pub fn name(&self) -> &str {
+        match self {
+            TableColumn::Chromosome => "CHROM",
+            TableColumn::Position => "POS",
+            TableColumn::Id => "ID",
+            TableColumn::Reference => "REF",
+            TableColumn::Alternate => "ALT",
+            TableColumn::Quality => "QUAL",
+            TableColumn::Filter => "FILTER",
+            TableColumn::VariantType => "TYPE",
+            TableColumn::Info(key) => key,
+            TableColumn::Genotype(name, _) => name,
         }
-        eprintln!("Loading VCF file with existing CSI index...");
-        GenomicIndex::Csi(csi::fs::read(&csi_path)?)
-    } else {
-        // Build tabix index on the fly (fallback - CSI requires external bcftools)
-        eprintln!("No index found. Building tabix index...");
-        let index = vcf::fs::index(path)?;
-        eprintln!("Tabix index built successfully");
+    }
 
-        // Try to save index to disk if requested
-        if save_index {
-            match save_tabix_index_to_disk(&index, &tbi_path, debug) {
-                Ok(()) => eprintln!("Tabix index saved to {}", tbi_path.display()),
-                Err(e) => {
-                    eprintln!("Warning: Failed to save tabix index to disk: {}", e);
-                    eprintln!("Continuing with in-memory index...");
-                }
-            }
-        } else if debug {
-            eprintln!("Skipping index save (--never-save-index flag set)");
+    // The variant's value in this column, "." when missing
+    fn value(&self, variant: &Variant) -> String {
+        let value = match self {
+            TableColumn::Chromosome => variant.chromosome.clone(),
+            TableColumn::Position => variant.position.to_string(),
+            TableColumn::Id => variant.id.clone(),
+            TableColumn::Reference => variant.reference.clone(),
+            TableColumn::Alternate => variant.alternate.join(","),
+            TableColumn::Quality => variant
+                .quality
+                .map(|quality| quality.to_string())
+                .unwrap_or_default(),
+            TableColumn::Filter => variant.filter.join(";"),
+            TableColumn::VariantType => variant
+                .variant_type
+                .map(|variant_type| variant_type.as_str().to_string())
+                .unwrap_or_default(),
+            TableColumn::Info(key) => variant
+                .info
+                .get(key)
+                .map(compact_info_value)
+                .unwrap_or_default(),
+            TableColumn::Genotype(_, sample) => sample_genotype(variant, *sample)
+                .unwrap_or_default()
+                .to_string(),
+        };
+        if value.is_empty() {
+            ".".to_string()
+        } else {
+            value
         }
+    }
+}
 
-        GenomicIndex::Tabix(index)
+// A header row and one row per variant, separated by `delimiter` (tab or comma); with commas,
+// values holding a comma, quote or line break are quoted as in RFC 4180
+pub fn render_table(variants: &[Variant], columns: &[TableColumn], delimiter: char) -> String {
+    let cell = |value: &str| {
+        if delimiter == ',' && value.contains([',', '"', '\n', '\r']) {
+            format!("\"{}\"", value.replace('"', "\"\""))
+        } else if delimiter == '\t' {
+            value.replace(['\t', '\n', '\r'], " ")
+        } else {
+            value.to_string()
+        }
     };
+    let row = |values: Vec<String>| {
+        values
+            .iter()
+            .map(|value| cell(value))
+            .collect::<Vec<_>>()
+            .join(&delimiter.to_string())
+    };
+    let mut lines = vec![row(columns
+        .iter()
+        .map(|column| column.name().to_string())
+        .collect())];
+    lines.extend(
+        variants
+            .iter()
+            .map(|variant| row(columns.iter().map(|column| column.value(variant)).collect())),
+    );
+    let mut table = lines.join("\n");
+    table.push('\n');
+    table
+}
 
-    // Create reader for queries
-    let file = File::open(path)?;
-    let mut reader = vcf::io::Reader::new(bgzf::io::Reader::new(file));
-    let header = reader.read_header()?;
+// "ref", "het", "hom" (every called allele the same ALT) or "nocall" for a GT value;
+// missing alleles next to called ones are ignored ("./1" is hom)
+fn zygosity(genotype: &str) -> &'static str {
+    let alleles: Vec<usize> = genotype
+        .split(['/', '|'])
+        .filter_map(|allele| allele.parse().ok())
+        .collect();
+    match alleles.split_first() {
+        None => "nocall",
+        Some((first, rest)) if rest.iter().any(|allele| allele != first) => "het",
+        Some((0, _)) => "ref",
+        Some(_) => "hom",
+    }
+}
 
-    // Check if ID index file exists
-    let idx_path = PathBuf::from(format!("{}.idx", path.display()));
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    let id_index = if idx_path.exists() {
-        // Load existing ID index
-        if debug {
-            eprintln!("Found ID index: {}", idx_path.display());
-        }
-        eprintln!("Loading VCF file with existing ID index...");
-        match load_id_index_from_disk(&idx_path, debug) {
-            Ok(index) => {
-                eprintln!("ID index loaded ({} unique IDs)", index.len());
-                index
-            }
-            Err(e) => {
-                eprintln!("Warning: Failed to load ID index: {}", e);
-                eprintln!("Rebuilding ID index...");
-                let index = build_id_index(path, &header, debug)?;
-
-                // Try to save the rebuilt index
-                if save_index {
-                    match save_id_index_to_disk(&index, &idx_path, debug) {
-                        Ok(()) => eprintln!("ID index saved to {}", idx_path.display()),
-                        Err(e) => {
-                            eprintln!("Warning: Failed to save ID index: {}", e);
-                            eprintln!("Continuing with in-memory index...");
-                        }
-                    }
-                }
+    #[test]
+    fn test_filter_clauses() {
+        assert_eq!(
+            filter_clauses("(DP > 10 && AF < 0.1) && QUAL >= 30"),
+            vec!["DP > 10", "AF < 0.1", "QUAL >= 30"]
+        );
+        // && binds tighter than ||, so this is a disjunction of two alternatives
+        assert_eq!(
+            filter_clauses("QUAL > 30 && DP > 5 || FILTER == \"PASS\""),
+            vec!["QUAL > 30 && DP > 5", "FILTER == \"PASS\""]
+        );
+        // A group of alternatives stays one clause; operators in strings are not split
+        assert_eq!(
+            filter_clauses("((A == 1 || B == 2)) && GENE == \"X && Y\""),
+            vec!["(A == 1 || B == 2)", "GENE == \"X && Y\""]
+        );
+        assert_eq!(filter_clauses("!(A && B)"), vec!["!(A && B)"]);
+        assert_eq!(filter_clauses("(A) && (B)"), vec!["A", "B"]);
+        assert!(filter_clauses("  ").is_empty());
+    }
 
-                index
-            }
-        }
-    } else {
-        // Build ID index from scratch
-        let index = build_id_index(path, &header, debug)?;
+    #[test]
+    fn test_record_filter_failures() {
+        let mut variant = snv(14370, "rs1", "G", "A", "0|1:14370");
+        variant.filter = vec!["q10".to_string(), "s50".to_string()];
+        let record_filter = RecordFilter {
+            exclude_filters: vec!["Q10".to_string()],
+            variant_types: vec![VariantType::Deletion],
+            require_info: vec!["DP".to_string(), "AF".to_string()],
+            pathogenic_only: true,
+        };
+        assert_eq!(
+            record_filter.failures(&variant),
+            vec![
+                "exclude_filters: q10",
+                "variant_types: snp",
+                "require_info: DP, AF missing",
+                "pathogenic_only: CLNSIG missing"
+            ]
+        );
+        assert!(RecordFilter::default().failures(&variant).is_empty());
+    }
 
-        // Try to save index to disk if requested
-        if save_index {
-            match save_id_index_to_disk(&index, &idx_path, debug) {
-                Ok(()) => eprintln!("ID index saved to {}", idx_path.display()),
-                Err(e) => {
-                    eprintln!("Warning: Failed to save ID index to disk: {}", e);
-                    eprintln!("Continuing with in-memory index...");
-                }
-            }
-        } else if debug {
-            eprintln!("Skipping ID index save (--never-save-index flag set)");
-        }
+    #[test]
+    fn test_annotation_subfields() {
+        let vep = "Consequence annotations from Ensembl VEP. Format: Allele|Consequence|IMPACT";
+        assert_eq!(
+            annotation_subfields(vep).unwrap(),
+            vec!["Allele", "Consequence", "IMPACT"]
+        );
+        let snpeff = "Functional annotations: 'Allele | Annotation | Annotation_Impact ' ";
+        assert_eq!(
+            annotation_subfields(snpeff).unwrap(),
+            vec!["Allele", "Annotation", "Annotation_Impact"]
+        );
+        assert_eq!(annotation_subfields("Total depth: all samples"), None);
+        assert_eq!(annotation_subfields("Allele frequency"), None);
+    }
 
-        index
-    };
+    #[test]
+    fn test_header_definitions() {
+        let header = concat!(
+            "##fileformat=VCFv4.3\n",
+            "##INFO=<ID=DP,Number=1,Type=Integer,Description=\"Total depth, all samples\">\n",
+            "##INFO=<ID=DB,Number=0,Type=Flag,Description=\"dbSNP \\\"membership\\\"\",Source=dbsnp>\n",
+            "##FORMAT=<ID=GT,Number=1,Type=String,Description=\"Genotype\">\n",
+            "##FILTER=<ID=q10,Description=\"Quality below 10\">\n",
+            "##contig=<ID=20,length=62435964,assembly=B36>\n",
+            "##source=myImputationProgramV3.1\n",
+            "#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO",
+        );
+        let definitions = header_definitions(header);
+
+        assert_eq!(definitions.info.len(), 2);
+        let dp = &definitions.info[0];
+        assert_eq!(dp.id, "DP");
+        assert_eq!(dp.number.as_deref(), Some("1"));
+        assert_eq!(dp.ty.as_deref(), Some("Integer"));
+        assert_eq!(dp.description.as_deref(), Some("Total depth, all samples"));
+        let db = &definitions.info[1];
+        assert_eq!(db.description.as_deref(), Some("dbSNP \"membership\""));
+        assert_eq!(db.other.get("Source").map(String::as_str), Some("dbsnp"));
+
+        assert_eq!(dp.subfields, None);
+        assert_eq!(definitions.format[0].id, "GT");
+        assert_eq!(definitions.filter[0].id, "q10");
+        assert_eq!(definitions.filter[0].number, None);
+        let contig = &definitions.contig[0];
+        assert_eq!((contig.id.as_str(), contig.length), ("20", Some(62435964)));
+        assert_eq!(
+            contig.other.get("assembly").map(String::as_str),
+            Some("B36")
+        );
+    }
 
-    eprintln!("VCF loaded (indexed mode)");
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("rs6054*", "rs6054257"));
+        assert!(glob_match("rs*257", "rs6054257"));
+        assert!(glob_match("rs?054257", "rs6054257"));
+        assert!(glob_match("*", ""));
+        assert!(glob_match("rs6054257", "rs6054257"));
+        assert!(!glob_match("rs6054257", "rs60542570"));
+        assert!(!glob_match("rs?", "rs"));
+        assert!(!glob_match("rs*9", "rs6054257"));
+        assert!(glob_match("*a*b*", "xxaxxbxx"));
+    }
 
-    // Initialize filter engine with VCF header
-    let header_string = {
-        let mut buffer = Vec::new();
-        let mut writer = vcf::io::Writer::new(&mut buffer);
-        if writer.write_header(&header).is_ok() {
-            String::from_utf8_lossy(&buffer).to_string()
-        } else {
-            String::new() // Empty header if write fails
+    #[test]
+    fn test_natural_contig_order() {
+        let mut names = vec![
+            "chrUn_gl000220",
+            "chrMT",
+            "chr10",
+            "chrY",
+            "chr2",
+            "chrX",
+            "chr1",
+            "GL000192.1",
+        ];
+        names.sort_by_key(|name| natural_contig_key(name));
+        assert_eq!(
+            names,
+            vec![
+                "chr1",
+                "chr2",
+                "chr10",
+                "chrX",
+                "chrY",
+                "chrMT",
+                "GL000192.1",
+                "chrUn_gl000220"
+            ]
+        );
+    }
+
+    // SNV record with one sample column for the MNV tests
+    fn snv(position: u64, id: &str, reference: &str, alternate: &str, sample: &str) -> Variant {
+        let info = "DP=10";
+        Variant {
+            chromosome: "20".to_string(),
+            position,
+            id: id.to_string(),
+            ids: vec![id.to_string()],
+            reference: reference.to_string(),
+            alternate: vec![alternate.to_string()],
+            alternate_alleles: vec![classify_allele(alternate)],
+            variant_type: Some(VariantType::Snp),
+            quality: None,
+            filter: vec!["PASS".to_string()],
+            info: HashMap::new(),
+            samples: None,
+            normalized: None,
+            clinvar: None,
+            annotation: None,
+            vrs: None,
+            spdi: None,
+            browser_links: None,
+            raw_row: format!(
+                "20\t{}\t{}\t{}\t{}\t.\tPASS\t{}\tGT:PS\t{}",
+                position, id, reference, alternate, info, sample
+            ),
         }
-    };
+    }
 
-    let filter_engine = Arc::new(FilterEngine::new(&header_string).map_err(|e| {
-        std::io::Error::new(
-            std::io::ErrorKind::InvalidData,
-            format!("Failed to create filter engine: {}", e),
-        )
-    })?);
+    #[test]
+    fn test_shape_variant_applies_output_options() {
+        let mut variant = snv(14370, "rs1", "G", "A", "0|1:14370");
+        variant.info.insert("DP".to_string(), serde_json::json!(10));
+        variant
+            .info
+            .insert("AF".to_string(), serde_json::json!(0.5));
+        let options = QueryOptions {
+            info_fields: Some(vec!["AF".to_string()]),
+            coordinates: CoordinateSystem::ZeroBased,
+            ..Default::default()
+        };
 
-    // Load or compute statistics
-    let stats_path = PathBuf::from(format!("{}.stats", path.display()));
+        shape_variant(&mut variant, &options, &[("S1", 0)]);
+        assert_eq!(variant.position, 14369);
+        assert_eq!(variant.info.keys().collect::<Vec<_>>(), vec!["AF"]);
+        let samples = variant.samples.as_ref().unwrap();
+        assert_eq!(samples["S1"]["GT"], "0|1");
+        assert_eq!(samples["S1"]["PS"], "14370");
 
-    let statistics = if stats_path.exists() {
-        // Load existing statistics
-        if debug {
-            eprintln!("Found statistics file: {}", stats_path.display());
-        }
-        eprintln!("Loading VCF statistics from cache...");
-        match load_statistics_from_disk(&stats_path, debug) {
-            Ok(stats) => {
-                eprintln!(
-                    "Statistics loaded ({} total variants)",
-                    stats.total_variants
-                );
-                stats
-            }
-            Err(e) => {
-                eprintln!("Warning: Failed to load statistics: {}", e);
-                eprintln!("Recomputing statistics...");
-                let stats = compute_statistics_from_vcf(path, &header, &id_index, debug)?;
-
-                // Try to save the recomputed statistics
-                if save_index {
-                    match save_statistics_to_disk(&stats, &stats_path, debug) {
-                        Ok(()) => eprintln!("Statistics saved to {}", stats_path.display()),
-                        Err(e) => {
-                            eprintln!("Warning: Failed to save statistics: {}", e);
-                            eprintln!("Continuing with in-memory statistics...");
-                        }
-                    }
-                }
+        let summary = QueryOptions {
+            detail: DetailLevel::Summary,
+            ..Default::default()
+        };
+        shape_variant(&mut variant, &summary, &[]);
+        assert!(variant.info.is_empty() && variant.alternate_alleles.is_empty());
+    }
 
-                stats
-            }
-        }
-    } else {
-        // Compute statistics from scratch
-        let stats = compute_statistics_from_vcf(path, &header, &id_index, debug)?;
+    #[test]
+    fn test_merge_phased_mnvs() {
+        let variants = vec![
+            snv(101, "b", "C", "T", "1|0:100"),
+            snv(100, "a", "A", "G", "1|0:100"),
+            snv(102, "c", "G", "A", "0|1:100"),
+            snv(200, "d", "T", "C", "1|1:200"),
+            snv(201, "e", "T", "G", "1|1:200"),
+            snv(300, "f", "A", "C", "1/0:."),
+            snv(301, "g", "A", "T", "1/0:."),
+        ];
+        let mnvs = merge_phased_mnvs(&variants, 0);
+        assert_eq!(mnvs.len(), 2);
+
+        // 102 is on the other haplotype, so only 100-101 merge
+        assert_eq!(mnvs[0].position, 100);
+        assert_eq!(
+            (mnvs[0].reference.as_str(), mnvs[0].alternate.as_str()),
+            ("AC", "GT")
+        );
+        assert_eq!(mnvs[0].haplotypes, vec![0]);
+        assert_eq!(mnvs[0].component_ids, vec!["a", "b"]);
+        assert_eq!(mnvs[0].phase_set.as_deref(), Some("100"));
 
-        // Try to save statistics to disk if requested
-        if save_index {
-            match save_statistics_to_disk(&stats, &stats_path, debug) {
-                Ok(()) => eprintln!("Statistics saved to {}", stats_path.display()),
-                Err(e) => {
-                    eprintln!("Warning: Failed to save statistics to disk: {}", e);
-                    eprintln!("Continuing with in-memory statistics...");
-                }
-            }
-        } else if debug {
-            eprintln!("Skipping statistics save (--never-save-index flag set)");
-        }
+        // Homozygous MNVs are reported once; unphased genotypes never merge
+        assert_eq!(mnvs[1].position, 200);
+        assert_eq!(mnvs[1].haplotypes, vec![0, 1]);
+    }
 
-        stats
-    };
+    #[test]
+    fn test_merge_phased_mnvs_across_split_multiallelic_sites() {
+        // 100 A>G is carried on haplotype 0, the split-off 100 A>T is not
+        let variants = vec![
+            snv(100, "a", "A", "G", "1|0:100"),
+            snv(100, "b", "A", "T", "0|1:100"),
+            snv(101, "c", "C", "T", "1|0:100"),
+        ];
+        let mnvs = merge_phased_mnvs(&variants, 0);
+        assert_eq!(mnvs.len(), 1);
+        assert_eq!(
+            (mnvs[0].reference.as_str(), mnvs[0].alternate.as_str()),
+            ("AC", "GT")
+        );
+        assert_eq!(mnvs[0].component_ids, vec!["a", "c"]);
+        assert_eq!(mnvs[0].haplotypes, vec![0]);
+
+        // The same with the uncarried record first
+        let variants = vec![
+            snv(99, "z", "G", "C", "1|0:100"),
+            snv(100, "b", "A", "T", "0|1:100"),
+            snv(100, "a", "A", "G", "1|0:100"),
+            snv(101, "c", "C", "T", "1|0:100"),
+        ];
+        let mnvs = merge_phased_mnvs(&variants, 0);
+        assert_eq!(mnvs.len(), 1);
+        assert_eq!(mnvs[0].component_ids, vec!["z", "a", "c"]);
+    }
 
-    Ok(VcfIndex {
-        path: path.clone(),
-        index: genomic_index,
-        header,
-        reader: Mutex::new(reader),
-        id_index,
-        filter_engine,
-        statistics,
-    })
-}
+    #[test]
+    fn test_merge_phased_mnvs_respects_phase_sets() {
+        let variants = vec![
+            snv(100, "a", "A", "G", "1|0:100"),
+            snv(101, "b", "C", "T", "1|0:101"),
+        ];
+        assert!(merge_phased_mnvs(&variants, 0).is_empty());
+    }
 
-// Helper function to atomically save tabix index to disk
-fn save_tabix_index_to_disk(
-    index: &tabix::Index,
-    tbi_path: &PathBuf,
-    debug: bool,
-) -> std::io::Result<()> {
-    use std::fs;
-    use std::io::BufWriter;
+    #[test]
+    fn test_merge_phased_mnvs_reports_shared_codons() {
+        let mut first = snv(100, "a", "A", "G", "1|0:100");
+        let mut second = snv(101, "b", "C", "T", "1|0:100");
+        first.raw_row = first.raw_row.replace(
+            "DP=10",
+            "ANN=G|missense_variant|MODERATE|GENE|GENE|transcript|TX1|protein_coding|1/1|c.10A>G|p.Thr4Ala|10/90|10/90|4/30",
+        );
+        second.raw_row = second.raw_row.replace(
+            "DP=10",
+            "ANN=T|missense_variant|MODERATE|GENE|GENE|transcript|TX1|protein_coding|1/1|c.11C>T|p.Thr4Ile|11/90|11/90|4/30",
+        );
 
-    // Create temporary file with .tmp extension
-    let tmp_path = PathBuf::from(format!("{}.tmp", tbi_path.display()));
+        let mnvs = merge_phased_mnvs(&[first, second], 0);
+        assert_eq!(mnvs.len(), 1);
+        assert_eq!(
+            mnvs[0].shared_codons,
+            vec![SharedCodon {
+                transcript: "TX1".to_string(),
+                amino_acid_position: 4,
+                component_consequences: vec!["p.Thr4Ala".to_string(), "p.Thr4Ile".to_string()],
+            }]
+        );
+    }
 
-    if debug {
-        eprintln!(
-            "Writing tabix index to temporary file: {}",
-            tmp_path.display()
+    #[test]
+    fn test_unordered_alleles() {
+        assert_eq!(unordered_alleles("1|0"), Some(vec![0, 1]));
+        assert_eq!(unordered_alleles("0/1"), unordered_alleles("1|0"));
+        assert_eq!(unordered_alleles("2|1"), Some(vec![1, 2]));
+        assert_eq!(unordered_alleles("1"), Some(vec![1]));
+        assert_eq!(unordered_alleles("./1"), None);
+        assert_eq!(unordered_alleles("."), None);
+    }
+
+    #[test]
+    fn test_call_rate_stats() {
+        let mut stats = CallRateStats::new(4);
+        let row = |samples: &str| format!("20\t100\t.\tA\tG\t.\tPASS\t.\tGT:DP\t{}", samples);
+        assert_eq!(stats.add(&row("0/1:5\t./.:0\t1|1:7\t./1:2")), Some(0.5));
+        // A truncated row leaves the last sample without a GT
+        assert_eq!(stats.add(&row("0/0:5\t0/0:6\t0/1:7")), Some(0.75));
+        assert_eq!(stats.add(&row("0/0\t0/0\t0/0\t0/0")), Some(1.0));
+        assert_eq!(
+            stats.add("20\t100\t.\tA\tG\t.\tPASS\t.\tDP\t5\t5\t5\t5"),
+            None
         );
+        assert_eq!(stats.sites, 3);
+        assert_eq!(stats.missing_per_sample, vec![0, 1, 0, 2]);
+        assert_eq!(stats.site_call_rate_bins[5], 1);
+        assert_eq!(stats.site_call_rate_bins[7], 1);
+        assert_eq!(stats.site_call_rate_bins[9], 1);
     }
 
-    // Write index to temporary file
-    {
-        let tmp_file = fs::File::create(&tmp_path)?;
-        let mut writer = tabix::io::Writer::new(BufWriter::new(tmp_file));
-        writer.write_index(index)?;
+    #[test]
+    fn test_decode_clinvar() {
+        let row = |info: &str| format!("13\t32338103\t.\tA\tG\t.\t.\t{}", info);
+        let clinvar = decode_clinvar(&row(
+            "ALLELEID=1;CLNDN=Hereditary_breast_ovarian_cancer_syndrome|not_provided;\
+             CLNREVSTAT=criteria_provided,_multiple_submitters,_no_conflicts;\
+             CLNSIG=Pathogenic/Likely_pathogenic|risk_factor",
+        ))
+        .unwrap();
+        assert_eq!(
+            clinvar.significance,
+            vec!["Pathogenic", "Likely pathogenic", "risk factor"]
+        );
+        assert_eq!(
+            clinvar.review_status.as_deref(),
+            Some("criteria provided, multiple submitters, no conflicts")
+        );
+        assert_eq!(clinvar.review_stars, Some(2));
+        assert_eq!(
+            clinvar.conditions,
+            vec!["Hereditary breast ovarian cancer syndrome", "not provided"]
+        );
+        assert!(clinvar.is_pathogenic());
+
+        let conflicting =
+            decode_clinvar(&row("CLNSIG=Conflicting_interpretations_of_pathogenicity")).unwrap();
+        assert!(!conflicting.is_pathogenic());
+        let low_penetrance = decode_clinvar(&row("CLNSIG=Pathogenic,_low_penetrance")).unwrap();
+        assert_eq!(
+            low_penetrance.significance,
+            vec!["Pathogenic", "low penetrance"]
+        );
+        assert!(low_penetrance.is_pathogenic());
+        // CLNSIGCONF is not CLNSIG
+        assert_eq!(decode_clinvar(&row("CLNSIGCONF=Benign(1);DP=4")), None);
     }
 
-    // Check again if .tbi file was created by another process (race condition)
-    if tbi_path.exists() {
-        if debug {
-            eprintln!("Index file appeared during write, removing temporary file");
-        }
-        fs::remove_file(&tmp_path)?;
-        return Ok(());
+    #[test]
+    fn test_count_filter_types() {
+        let mut counts = HashMap::new();
+        let mut failing = snv(100, ".", "A", "G", "0/1");
+        failing.filter = vec!["q10".to_string(), "s50".to_string()];
+        count_filter_types(&mut counts, &failing);
+        let mut insertion = snv(200, ".", "A", "AT", "0/1");
+        insertion.filter = Vec::new();
+        count_filter_types(&mut counts, &insertion);
+        count_filter_types(&mut counts, &snv(300, ".", "C", "T", "0/1"));
+
+        assert_eq!(counts["q10"].snps, 1);
+        assert_eq!(counts["s50"].total(), 1);
+        assert_eq!(counts["."].insertions, 1);
+        assert_eq!(counts["PASS"].snps, 1);
     }
 
-    // Atomically rename temp file to final .tbi file
-    fs::rename(&tmp_path, tbi_path)?;
+    #[test]
+    fn test_qc_metrics() {
+        let mut metrics = QcMetrics::default();
+        // A>G transition; samples het, hom-alt, hom-ref and missing
+        let mut variant = snv(100, ".", "A", "G", "0/1");
+        variant.raw_row = variant.raw_row.replace("0/1", "0/1\t1|1\t0/0\t./.");
+        metrics.add(&variant);
+        // c>a transversion, lower case
+        metrics.add(&snv(200, ".", "c", "a", "1|0"));
+        // An insertion, not counted among SNVs; haploid calls are not counted
+        let mut insertion = snv(300, ".", "A", "AT", "1");
+        insertion.alternate_alleles = vec![classify_allele("AT")];
+        metrics.add(&insertion);
+        // A symbolic allele is neither
+        let mut deletion = snv(400, ".", "A", "<DEL>", "0/1");
+        deletion.alternate_alleles = vec![classify_allele("<DEL>")];
+        metrics.add(&deletion);
+        assert_eq!(
+            metrics,
+            QcMetrics {
+                transitions: 1,
+                transversions: 1,
+                indels: 1,
+                heterozygous: 3,
+                homozygous_alt: 1,
+            }
+        );
+        assert_eq!(metrics.snvs(), 2);
+    }
 
-    Ok(())
-}
+    #[test]
+    fn test_frequency_spectrum() {
+        let mut with_af = snv(100, ".", "A", "G", "0/0");
+        with_af
+            .info
+            .insert("AF".to_string(), serde_json::json!([0.05]));
+        // No INFO/AF: 1 ALT allele among 4 called ones
+        let mut computed = snv(200, ".", "A", "G", "0/1");
+        computed.raw_row = computed.raw_row.replace("0/1", "0/1\t0|0\t./.");
+        let mut spectrum = FrequencySpectrum::new(4, false);
+        spectrum.add(&with_af);
+        spectrum.add(&computed);
+        let counts: Vec<u64> = spectrum.bins.iter().map(|bin| bin.count).collect();
+        assert_eq!(counts, vec![1, 1, 0, 0]);
+        assert_eq!(spectrum.bins[1].start, 0.25);
+        assert_eq!(
+            (spectrum.alleles_from_info, spectrum.alleles_from_genotypes),
+            (1, 1)
+        );
 
-// Helper function to atomically save CSI index to disk
-// Save CSI index to disk with atomic write (currently unused but provided for future functionality)
-#[allow(dead_code)]
-fn save_csi_index_to_disk(
-    index: &csi::Index,
-    csi_path: &PathBuf,
-    debug: bool,
-) -> std::io::Result<()> {
-    use std::fs;
-    use std::io::BufWriter;
+        // Folded, an AF of 0.95 is a minor allele frequency of 0.05
+        let mut common = snv(300, ".", "A", "G", "1/1");
+        common
+            .info
+            .insert("AF".to_string(), serde_json::json!(0.95));
+        let mut folded = FrequencySpectrum::new(5, true);
+        folded.add(&common);
+        assert_eq!(folded.bins[0].count, 1);
+        assert_eq!(folded.bins[4].end, 0.5);
+
+        // A record without ALT allele or genotypes is skipped
+        let mut no_alt = snv(400, ".", "A", ".", "0/0");
+        no_alt.alternate_alleles = vec![classify_allele(".")];
+        folded.add(&no_alt);
+        assert_eq!(folded.records_skipped, 1);
+    }
 
-    // Create temporary file with .tmp extension
-    let tmp_path = PathBuf::from(format!("{}.tmp", csi_path.display()));
+    #[test]
+    fn test_histogram() {
+        let mut values: Vec<f64> = (1..=100).rev().map(f64::from).collect();
+        let histogram = Histogram::of(&mut values, 4).unwrap();
+        assert_eq!(
+            (histogram.min, histogram.max, histogram.mean),
+            (1.0, 100.0, 50.5)
+        );
+        assert_eq!(histogram.percentiles.p5, 5.0);
+        assert_eq!(histogram.percentiles.p50, 50.0);
+        assert_eq!(histogram.percentiles.p95, 95.0);
+        let counts: Vec<u64> = histogram.bins.iter().map(|bin| bin.count).collect();
+        assert_eq!(counts, vec![25, 25, 25, 25]);
+        assert_eq!(histogram.bins[3].end, 100.0);
+
+        // Equal values fall in one bin; no values, no histogram
+        let single = Histogram::of(&mut [30.0, 30.0], 10).unwrap();
+        assert_eq!(single.bins.len(), 1);
+        assert_eq!(single.bins[0].count, 2);
+        assert_eq!(Histogram::of(&mut [], 10), None);
+    }
 
-    if debug {
-        eprintln!(
-            "Writing CSI index to temporary file: {}",
-            tmp_path.display()
+    #[test]
+    fn test_render_compact() {
+        let mut variant = snv(14370, "rs6054257", "G", "A", "0|1:14370");
+        variant.quality = Some(29.0);
+        variant.info = HashMap::from([
+            ("AF".to_string(), serde_json::json!([0.5, 0.25])),
+            ("DB".to_string(), serde_json::json!(true)),
+            ("DP".to_string(), serde_json::json!(14)),
+        ]);
+        let genotype = |gt: &str| HashMap::from([("GT".to_string(), gt.to_string())]);
+        variant.samples = Some(HashMap::from([
+            ("NA00002".to_string(), genotype("1|0")),
+            ("NA00001".to_string(), genotype("0|0")),
+            ("NA00003".to_string(), genotype("1/1")),
+            ("NA00004".to_string(), genotype("./.")),
+        ]));
+        assert_eq!(
+            render_compact(&variant),
+            "20:14370 G>A rs6054257 QUAL=29 PASS AF=0.5,0.25 DB DP=14 \
+             ref(NA00001) het(NA00002) hom(NA00003) nocall(NA00004)"
         );
+
+        // Missing ID, QUAL and FILTER are left out rather than printed as '.'
+        let mut variant = snv(17330, ".", "T", "A", "0|0:17330");
+        variant.filter.clear();
+        variant.alternate.push("C".to_string());
+        assert_eq!(render_compact(&variant), "20:17330 T>A,C");
+        assert_eq!(zygosity("1|2"), "het");
+        assert_eq!(zygosity("./1"), "hom");
     }
 
-    // Write index to temporary file
-    {
-        let tmp_file = fs::File::create(&tmp_path)?;
-        let mut writer = csi::io::Writer::new(BufWriter::new(tmp_file));
-        writer.write_index(index)?;
+    #[test]
+    fn test_render_table() {
+        let mut variant = snv(14370, "rs6054257", "G", "A", "0|1:14370");
+        variant.quality = Some(29.0);
+        variant.info = HashMap::from([("AF".to_string(), serde_json::json!([0.5, 0.25]))]);
+        let mut other = snv(17330, ".", "T", "A", "./.:17330");
+        other.filter.clear();
+        let columns = [
+            TableColumn::Chromosome,
+            TableColumn::Position,
+            TableColumn::Id,
+            TableColumn::Quality,
+            TableColumn::Filter,
+            TableColumn::VariantType,
+            TableColumn::Info("AF".to_string()),
+            TableColumn::Genotype("NA00001".to_string(), 0),
+        ];
+        assert_eq!(
+            render_table(&[variant.clone(), other], &columns, '\t'),
+            "CHROM\tPOS\tID\tQUAL\tFILTER\tTYPE\tAF\tNA00001\n\
+             20\t14370\trs6054257\t29\tPASS\tsnp\t0.5,0.25\t0|1\n\
+             20\t17330\t.\t.\t.\tsnp\t.\t./.\n"
+        );
+        assert_eq!(
+            render_table(&[variant], &columns[5..7], ','),
+            "TYPE,AF\nsnp,\"0.5,0.25\"\n"
+        );
     }
 
-    // Check again if .csi file was created by another process (race condition)
-    if csi_path.exists() {
-        if debug {
-            eprintln!("Index file appeared during write, removing temporary file");
-        }
-        fs::remove_file(&tmp_path)?;
-        return Ok(());
+    #[test]
+    fn test_compound_het_pairs() {
+        let annotations = GeneAnnotations {
+            slots: vec![("ANN".to_string(), 3, "Gene_Name".to_string())],
+        };
+        assert_eq!(
+            annotations.filter("BRCA2").as_deref(),
+            Some("ANN[*].Gene_Name == \"BRCA2\"")
+        );
+        assert_eq!(GeneAnnotations::default().filter("BRCA2"), None);
+        let annotated = |position: u64, sample: &str, gene: &str| {
+            let mut variant = snv(position, ".", "A", "G", sample);
+            variant.raw_row = variant.raw_row.replace(
+                "DP=10",
+                &format!("ANN=G|missense_variant|MODERATE|{}|ID|transcript", gene),
+            );
+            variant
+        };
+        let sites: Vec<HeterozygousSite> = [
+            annotated(100, "0|1:100", "GENE1"),
+            annotated(200, "0|1:100", "GENE1"),
+            annotated(300, "1|0:100", "GENE1"),
+            annotated(400, "0/1:.", "GENE1"),
+            annotated(500, "1|0:100", "GENE2"),
+            annotated(600, "1|1:100", "GENE1"),
+        ]
+        .iter()
+        .filter_map(|variant| heterozygous_site(variant, 0, &annotations))
+        .collect();
+        // The hom-alt call is not a heterozygous site
+        assert_eq!(sites.len(), 5);
+        assert_eq!(sites[0].genes, vec!["GENE1"]);
+
+        let (pairs, cis_pairs) = compound_het_pairs(&sites, true);
+        // 100 and 200 are on the same haplotype; GENE2 pairs with nothing
+        assert_eq!(cis_pairs, 1);
+        let phases: Vec<([usize; 2], PairPhase)> =
+            pairs.iter().map(|pair| (pair.sites, pair.phase)).collect();
+        assert_eq!(
+            phases,
+            vec![
+                ([0, 2], PairPhase::Trans),
+                ([1, 2], PairPhase::Trans),
+                ([0, 3], PairPhase::Unknown),
+                ([1, 3], PairPhase::Unknown),
+                ([2, 3], PairPhase::Unknown),
+            ]
+        );
+        // Across genes, 300 and 500 are also on the same haplotype
+        let (pairs, cis_pairs) = compound_het_pairs(&sites, false);
+        assert_eq!((pairs.len(), cis_pairs), (8, 2));
     }
 
-    // Atomically rename temp file to final .csi file
-    fs::rename(&tmp_path, csi_path)?;
+    #[test]
+    fn test_splitmix64_is_stable() {
+        // Reference output of SplitMix64 for seed 0; sampled results depend on this sequence
+        let mut rng = SplitMix64::new(0);
+        assert_eq!(rng.next_u64(), 0xe220_a839_7b1d_cdaf);
+        assert_eq!(rng.next_u64(), 0x6e78_9e6a_a1b9_65f4);
+    }
 
-    Ok(())
-}
+    #[test]
+    fn test_classify_variant() {
+        let classify = |reference: &str, alts: &[&str]| {
+            let alleles: Vec<AlternateAllele> = alts.iter().map(|a| classify_allele(a)).collect();
+            classify_variant(reference, &alleles)
+        };
+        assert_eq!(classify("A", &["G"]), Some(VariantType::Snp));
+        assert_eq!(classify("A", &["G", "T"]), Some(VariantType::Snp));
+        assert_eq!(classify("A", &["AT"]), Some(VariantType::Insertion));
+        assert_eq!(classify("AT", &["A"]), Some(VariantType::Deletion));
+        assert_eq!(classify("AC", &["GT"]), Some(VariantType::Mnp));
+        assert_eq!(classify("A", &["G", "AT"]), Some(VariantType::Complex));
+        assert_eq!(classify("A", &["<DEL>"]), Some(VariantType::Sv));
+        assert_eq!(classify("G", &["G]17:198982]", "A"]), Some(VariantType::Sv));
+        // '*' and <NON_REF> don't count towards the type
+        assert_eq!(classify("A", &["G", "*"]), Some(VariantType::Snp));
+        assert_eq!(classify("A", &["<NON_REF>"]), None);
+        assert_eq!(classify("A", &["."]), None);
+    }
 
-// Format variant for MCP response (no-op now that types are unified)
-pub fn format_variant(variant: Variant) -> Variant {
-    variant
+    #[test]
+    fn test_only_transient_read_errors_are_retried() {
+        use std::io::{Error, ErrorKind};
+        assert!(is_transient(&Error::from(ErrorKind::TimedOut)));
+        assert!(is_transient(&Error::from(ErrorKind::ConnectionReset)));
+        assert!(is_transient(&Error::from(ErrorKind::Interrupted)));
+        assert!(!is_transient(&Error::from(ErrorKind::UnexpectedEof)));
+        assert!(!is_transient(&Error::from(ErrorKind::PermissionDenied)));
+        assert!(!is_transient(&Error::from(ErrorKind::InvalidData)));
+    }
 }
 
 //
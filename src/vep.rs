@@ -0,0 +1,228 @@
+// Ensembl VEP REST annotation for annotate_via_vep, available only when the server runs with
+// --allow-network.
+//
+// Variants are posted to POST /vep/:species/region as VCF-style strings ("20 14370 rs6054257
+// G A . . ."), the input form the endpoint accepts for SNVs, indels and multi-allelic records
+// alike, in batches of at most 200 (the endpoint's limit). Ensembl names chromosomes without
+// a "chr" prefix, so one is removed. The public servers serve GRCh38 (rest.ensembl.org) and
+// GRCh37 (grch37.rest.ensembl.org); the server follows the VCF's build unless --vep-server
+// names another.
+
+use crate::vcf::Variant;
+use std::time::Duration;
+
+pub const GRCH38_SERVER: &str = "https://rest.ensembl.org";
+pub const GRCH37_SERVER: &str = "https://grch37.rest.ensembl.org";
+// Most variants one POST /vep/:species/region request may carry
+pub const MAX_BATCH: usize = 200;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+
+pub struct VepClient {
+    http: reqwest::Client,
+    server: String,
+}
+
+// One transcript consequence as VEP reports it, keeping the fields useful for interpretation
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct TranscriptConsequence {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gene_symbol: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gene_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transcript_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub variant_allele: Option<String>,
+    #[serde(default)]
+    pub consequence_terms: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub impact: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub biotype: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub amino_acids: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub codons: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub protein_start: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sift_prediction: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub polyphen_prediction: Option<String>,
+    // 1 for the gene's canonical transcript
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub canonical: Option<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Deserialize)]
+pub struct ColocatedVariant {
+    #[serde(default)]
+    pub id: Option<String>,
+}
+
+// VEP's result for one input variant
+#[derive(Debug, Clone, PartialEq, serde::Deserialize)]
+pub struct VepResult {
+    // The input string the result belongs to
+    pub input: String,
+    #[serde(default)]
+    pub most_severe_consequence: Option<String>,
+    #[serde(default)]
+    pub transcript_consequences: Vec<TranscriptConsequence>,
+    #[serde(default)]
+    pub colocated_variants: Vec<ColocatedVariant>,
+}
+
+// A failed VEP request, worded for the caller
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VepError {
+    pub message: String,
+    // Worth repeating later (rate limiting, server or network trouble)
+    pub transient: bool,
+}
+
+// The VCF-style input string VEP is sent for a variant
+pub fn vep_input(variant: &Variant) -> String {
+    let chromosome = variant
+        .chromosome
+        .strip_prefix("chr")
+        .unwrap_or(&variant.chromosome);
+    let id = if variant.id.is_empty() {
+        "."
+    } else {
+        &variant.id
+    };
+    format!(
+        "{} {} {} {} {} . . .",
+        chromosome,
+        variant.position,
+        id,
+        variant.reference,
+        variant.alternate.join(",")
+    )
+}
+
+// The VEP server for a build name such as "GRCh37" or "GRCh38"
+pub fn server_for_build(build: &str) -> &'static str {
+    let build = build.to_ascii_lowercase();
+    if build.contains("37") || build.contains("hg19") {
+        GRCH37_SERVER
+    } else {
+        GRCH38_SERVER
+    }
+}
+
+impl VepClient {
+    pub fn new(server: &str) -> Result<Self, reqwest::Error> {
+        let http = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .user_agent(concat!("vcf_mcp_server/", env!("CARGO_PKG_VERSION")))
+            .build()?;
+        Ok(VepClient {
+            http,
+            server: server.trim_end_matches('/').to_string(),
+        })
+    }
+
+    pub fn server(&self) -> &str {
+        &self.server
+    }
+
+    // Annotate `inputs` (from vep_input, at most MAX_BATCH) with one request; `pick` asks
+    // VEP for a single consequence per variant
+    pub async fn annotate(
+        &self,
+        species: &str,
+        inputs: &[String],
+        pick: bool,
+    ) -> Result<Vec<VepResult>, VepError> {
+        let url = format!("{}/vep/{}/region", self.server, species);
+        let mut body = serde_json::json!({ "variants": inputs, "canonical": 1 });
+        if pick {
+            body["pick"] = serde_json::json!(1);
+        }
+        let response = self
+            .http
+            .post(&url)
+            .header(reqwest::header::ACCEPT, "application/json")
+            .json(&body)
+            .send()
+            .await
+            .map_err(|e| VepError {
+                message: format!("Ensembl VEP request to {} failed: {}", self.server, e),
+                transient: true,
+            })?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let retry_after = response
+                .headers()
+                .get(reqwest::header::RETRY_AFTER)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string);
+            let detail = response.text().await.unwrap_or_default();
+            let mut message = format!("Ensembl VEP returned {}", status);
+            if let Some(error) = serde_json::from_str::<serde_json::Value>(&detail)
+                .ok()
+                .and_then(|value| value.get("error")?.as_str().map(str::to_string))
+            {
+                message.push_str(&format!(": {}", error));
+            }
+            if let Some(seconds) = retry_after {
+                message.push_str(&format!(" (retry after {} s)", seconds));
+            }
+            return Err(VepError {
+                message,
+                transient: status.as_u16() == 429 || status.is_server_error(),
+            });
+        }
+        response.json().await.map_err(|e| VepError {
+            message: format!("Unexpected response from Ensembl VEP: {}", e),
+            transient: false,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_server_for_build() {
+        assert_eq!(server_for_build("GRCh37"), GRCH37_SERVER);
+        assert_eq!(server_for_build("hg19"), GRCH37_SERVER);
+        assert_eq!(server_for_build("GRCh38"), GRCH38_SERVER);
+        assert_eq!(server_for_build("Unknown"), GRCH38_SERVER);
+    }
+
+    #[test]
+    fn test_vep_result_from_json() {
+        let results: Vec<VepResult> = serde_json::from_value(serde_json::json!([{
+            "input": "20 14370 rs6054257 G A . . .",
+            "most_severe_consequence": "missense_variant",
+            "transcript_consequences": [{
+                "gene_symbol": "GENE1",
+                "transcript_id": "ENST00000000001",
+                "consequence_terms": ["missense_variant"],
+                "impact": "MODERATE",
+                "protein_start": 42,
+                "canonical": 1,
+                "distance": 10
+            }],
+            "colocated_variants": [{"id": "rs6054257"}]
+        }]))
+        .unwrap();
+        assert_eq!(
+            results[0].most_severe_consequence.as_deref(),
+            Some("missense_variant")
+        );
+        let consequence = &results[0].transcript_consequences[0];
+        assert_eq!(consequence.protein_start, Some(42));
+        assert_eq!(consequence.canonical, Some(1));
+        assert_eq!(
+            results[0].colocated_variants[0].id.as_deref(),
+            Some("rs6054257")
+        );
+    }
+}
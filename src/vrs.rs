@@ -0,0 +1,431 @@
+// GA4GH VRS 2.0 Alleles with computed identifiers, for the query tools' vrs option (needs
+// --reference).
+//
+// VRS locates an allele on a sequence named by its refget digest ("SQ." and the sha512t24u of
+// the uppercased bases: SHA-512, truncated to 24 bytes, base64url-encoded) in 0-based
+// interbase coordinates. Digests are computed from the --reference FASTA the first time a
+// chromosome is asked for and kept for the life of the server; hashing a large chromosome
+// takes about a second.
+//
+// Alleles are normalized as VRS requires before their identifiers are computed: the bases
+// shared by REF and ALT are trimmed (prefix first, then suffix), a substitution becomes a
+// LiteralSequenceExpression, and an insertion or deletion is rolled left and right over the
+// reference to the full span it could be placed at. An insertion that can't move stays a
+// LiteralSequenceExpression; anything else becomes a ReferenceLengthExpression with the
+// length of the span's ALT bases and the shortest repeating unit of the inserted or deleted
+// bases. The identifier of an object is its type prefix ("ga4gh:VA.", "ga4gh:SL.") and the
+// sha512t24u of its canonical JSON: the fields VRS digests, sorted, without whitespace, with
+// nested identifiable objects replaced by their digest.
+
+use crate::reference::ReferenceFasta;
+use sha2::{Digest, Sha512};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct VrsAllele {
+    pub id: String,
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+    pub location: SequenceLocation,
+    pub state: AlleleState,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SequenceLocation {
+    pub id: String,
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+    pub sequence_reference: SequenceReference,
+    // 0-based interbase
+    pub start: u64,
+    pub end: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SequenceReference {
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+    // "SQ." and the sequence's digest
+    pub refget_accession: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+#[serde(tag = "type", rename_all_fields = "camelCase")]
+pub enum AlleleState {
+    LiteralSequenceExpression {
+        sequence: String,
+    },
+    // An insertion or deletion in a repeat: the location's reference bases become `length`
+    // bases continuing the repeat of `repeat_subunit_length` bases
+    ReferenceLengthExpression {
+        length: u64,
+        repeat_subunit_length: u64,
+        sequence: String,
+    },
+}
+
+impl AlleleState {
+    // Canonical JSON of the fields VRS digests (ReferenceLengthExpression's sequence is
+    // derived from the reference, so it is left out)
+    fn digest_json(&self) -> String {
+        match self {
+            AlleleState::LiteralSequenceExpression { sequence } => format!(
+                r#"{{"sequence":"{}","type":"LiteralSequenceExpression"}}"#,
+                sequence
+            ),
+            AlleleState::ReferenceLengthExpression {
+                length,
+                repeat_subunit_length,
+                ..
+            } => format!(
+                r#"{{"length":{},"repeatSubunitLength":{},"type":"ReferenceLengthExpression"}}"#,
+                length, repeat_subunit_length
+            ),
+        }
+    }
+}
+
+// sha512t24u: SHA-512 truncated to 24 bytes, base64url-encoded (32 characters, no padding)
+pub fn sha512t24u(data: &[u8]) -> String {
+    base64url(&Sha512::digest(data)[..24])
+}
+
+fn base64url(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let bits = chunk.iter().enumerate().fold(0u32, |bits, (i, &byte)| {
+            bits | ((byte as u32) << (16 - 8 * i))
+        });
+        for i in 0..=chunk.len() {
+            encoded.push(ALPHABET[((bits >> (18 - 6 * i)) & 0x3f) as usize] as char);
+        }
+    }
+    encoded
+}
+
+// The Allele at interbase `start`-`end` of the sequence with `accession` ("SQ.…"), with the
+// identifiers of it and its location
+pub fn allele(accession: &str, start: u64, end: u64, state: AlleleState) -> VrsAllele {
+    let location_digest = sha512t24u(
+        format!(
+            r#"{{"end":{},"sequenceReference":{{"refgetAccession":"{}","type":"SequenceReference"}},"start":{},"type":"SequenceLocation"}}"#,
+            end, accession, start
+        )
+        .as_bytes(),
+    );
+    let allele_digest = sha512t24u(
+        format!(
+            r#"{{"location":"{}","state":{},"type":"Allele"}}"#,
+            location_digest,
+            state.digest_json()
+        )
+        .as_bytes(),
+    );
+    VrsAllele {
+        id: format!("ga4gh:VA.{}", allele_digest),
+        kind: "Allele",
+        location: SequenceLocation {
+            id: format!("ga4gh:SL.{}", location_digest),
+            kind: "SequenceLocation",
+            sequence_reference: SequenceReference {
+                kind: "SequenceReference",
+                refget_accession: accession.to_string(),
+            },
+            start,
+            end,
+        },
+        state,
+    }
+}
+
+// Normalize an ALT allele of a record whose (uppercase) REF covers interbase `start`-`end`,
+// reading reference bases by 0-based offset from `base_at`. Returns the allele's interbase
+// location and state; None when ALT equals REF.
+pub fn normalize_allele(
+    start: u64,
+    reference: &[u8],
+    alternate: &[u8],
+    base_at: impl Fn(u64) -> Option<u8>,
+) -> Option<(u64, u64, AlleleState)> {
+    let prefix = reference
+        .iter()
+        .zip(alternate)
+        .take_while(|(a, b)| a == b)
+        .count();
+    let (reference, alternate) = (&reference[prefix..], &alternate[prefix..]);
+    let suffix = reference
+        .iter()
+        .rev()
+        .zip(alternate.iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let reference = &reference[..reference.len() - suffix];
+    let alternate = &alternate[..alternate.len() - suffix];
+    let start = start + prefix as u64;
+    let end = start + reference.len() as u64;
+    let text = |bases: &[u8]| String::from_utf8_lossy(bases).into_owned();
+
+    match (reference.is_empty(), alternate.is_empty()) {
+        (true, true) => return None,
+        (false, false) => {
+            return Some((
+                start,
+                end,
+                AlleleState::LiteralSequenceExpression {
+                    sequence: text(alternate),
+                },
+            ))
+        }
+        _ => {}
+    }
+
+    // Roll the inserted or deleted bases as far left and right as the reference allows
+    let indel = if reference.is_empty() {
+        alternate
+    } else {
+        reference
+    };
+    let mut left_bases = indel.to_vec();
+    let mut left = start;
+    while left > 0 && base_at(left - 1) == left_bases.last().copied() {
+        left_bases.rotate_right(1);
+        left -= 1;
+    }
+    let mut right_bases = indel.to_vec();
+    let mut right = end;
+    while base_at(right) == right_bases.first().copied() {
+        right_bases.rotate_left(1);
+        right += 1;
+    }
+
+    let span: Vec<u8> = (left..right).filter_map(&base_at).collect();
+    if span.is_empty() {
+        return Some((
+            start,
+            end,
+            AlleleState::LiteralSequenceExpression {
+                sequence: text(indel),
+            },
+        ));
+    }
+    let sequence = if reference.is_empty() {
+        [left_bases.as_slice(), &span].concat()
+    } else {
+        span[..span.len() - indel.len()].to_vec()
+    };
+    Some((
+        left,
+        right,
+        AlleleState::ReferenceLengthExpression {
+            length: sequence.len() as u64,
+            repeat_subunit_length: repeat_unit(indel) as u64,
+            sequence: text(&sequence),
+        },
+    ))
+}
+
+// Length of the shortest unit `bases` is a whole number of repeats of
+fn repeat_unit(bases: &[u8]) -> usize {
+    (1..=bases.len())
+        .find(|&length| {
+            bases.len() % length == 0 && bases.chunks(length).all(|chunk| chunk == &bases[..length])
+        })
+        .unwrap_or(bases.len())
+}
+
+// VRS Alleles of records, against the server's reference FASTA
+pub struct VrsSource {
+    reference: Arc<ReferenceFasta>,
+    // Refget accessions by FASTA sequence name, computed on first use
+    accessions: Mutex<HashMap<String, String>>,
+}
+
+impl VrsSource {
+    pub fn new(reference: Arc<ReferenceFasta>) -> Self {
+        VrsSource {
+            reference,
+            accessions: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // "SQ." and the digest of a FASTA sequence
+    fn accession(&self, sequence: &str) -> Option<String> {
+        if let Some(accession) = self.accessions.lock().ok()?.get(sequence) {
+            return Some(accession.clone());
+        }
+        // Hashed without the lock, so other chromosomes aren't held up meanwhile
+        let mut hasher = Sha512::new();
+        for line in self.reference.sequence_lines(sequence)? {
+            hasher.update(line.to_ascii_uppercase());
+        }
+        let accession = format!("SQ.{}", base64url(&hasher.finalize()[..24]));
+        self.accessions
+            .lock()
+            .ok()?
+            .insert(sequence.to_string(), accession.clone());
+        Some(accession)
+    }
+
+    // One Allele per ALT allele of a record at 1-based `position`, None for alleles VRS
+    // can't express here (symbolic, breakend, '*', missing). None for the record when its
+    // chromosome is not in the FASTA or REF doesn't match it (usually a FASTA for a
+    // different build).
+    pub fn alleles(
+        &self,
+        chromosome: &str,
+        position: u64,
+        reference: &str,
+        alternates: &[String],
+    ) -> Option<Vec<Option<VrsAllele>>> {
+        let sequence = self.reference.sequence_name(chromosome)?;
+        let reference = reference.to_ascii_uppercase().into_bytes();
+        let matches_reference = reference
+            .iter()
+            .enumerate()
+            .all(|(i, &base)| self.reference.base(&sequence, position + i as u64) == Some(base));
+        if position == 0 || !matches_reference {
+            return None;
+        }
+        let accession = self.accession(&sequence)?;
+        let base_at = |offset: u64| self.reference.base(&sequence, offset + 1);
+        let is_bases = |allele: &str| {
+            !allele.is_empty()
+                && allele
+                    .bytes()
+                    .all(|base| b"ACGTN".contains(&base.to_ascii_uppercase()))
+        };
+        Some(
+            alternates
+                .iter()
+                .map(|alternate| {
+                    if !is_bases(alternate) {
+                        return None;
+                    }
+                    let (start, end, state) = normalize_allele(
+                        position - 1,
+                        &reference,
+                        &alternate.to_ascii_uppercase().into_bytes(),
+                        base_at,
+                    )?;
+                    Some(allele(&accession, start, end, state))
+                })
+                .collect(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Offset 0 is the first base
+    const SEQUENCE: &[u8] = b"GCACACACATTTTGA";
+
+    fn normalize(start: u64, reference: &str, alternate: &str) -> (u64, u64, AlleleState) {
+        normalize_allele(
+            start,
+            reference.as_bytes(),
+            alternate.as_bytes(),
+            |offset| SEQUENCE.get(offset as usize).copied(),
+        )
+        .expect("a change")
+    }
+
+    #[test]
+    fn test_sha512t24u() {
+        // The refget specification's example
+        assert_eq!(sha512t24u(b"ACGT"), "aKF498dAxcJAqme6QYQ7EZ07-fiw8Kw2");
+        assert_eq!(base64url(&[0xfb, 0xff]), "-_8");
+    }
+
+    #[test]
+    fn test_normalize_allele() {
+        // Substitution, with a shared base trimmed
+        assert_eq!(
+            normalize(8, "AT", "GT"),
+            (
+                8,
+                9,
+                AlleleState::LiteralSequenceExpression {
+                    sequence: "G".to_string()
+                }
+            )
+        );
+        // Deleting one "CA" of the repeat spans the whole repeat
+        assert_eq!(
+            normalize(6, "ACA", "A"),
+            (
+                1,
+                9,
+                AlleleState::ReferenceLengthExpression {
+                    length: 6,
+                    repeat_subunit_length: 2,
+                    sequence: "CACACA".to_string()
+                }
+            )
+        );
+        // Inserting a T in the run of Ts
+        assert_eq!(
+            normalize(9, "T", "TT"),
+            (
+                9,
+                13,
+                AlleleState::ReferenceLengthExpression {
+                    length: 5,
+                    repeat_subunit_length: 1,
+                    sequence: "TTTTT".to_string()
+                }
+            )
+        );
+        // An insertion that can't move
+        assert_eq!(
+            normalize(13, "G", "GC"),
+            (
+                14,
+                14,
+                AlleleState::LiteralSequenceExpression {
+                    sequence: "C".to_string()
+                }
+            )
+        );
+        assert_eq!(repeat_unit(b"CACA"), 2);
+        assert_eq!(repeat_unit(b"CAT"), 3);
+        assert!(normalize_allele(0, b"G", b"G", |_| None).is_none());
+    }
+
+    #[test]
+    fn test_allele_identifiers() {
+        let accession = format!("SQ.{}", sha512t24u(SEQUENCE));
+        let snv = allele(
+            &accession,
+            8,
+            9,
+            AlleleState::LiteralSequenceExpression {
+                sequence: "G".to_string(),
+            },
+        );
+        assert_eq!(snv.id, "ga4gh:VA.-mQtoZUVYlif24hfU92cTT9CoPDufIlB");
+        assert_eq!(snv.location.id, "ga4gh:SL.RrlWc4EgbSm1NKSUKTqqIHUM3_yfxu30");
+
+        // The sequence of a ReferenceLengthExpression is not part of the identifier
+        let state = |sequence: &str| AlleleState::ReferenceLengthExpression {
+            length: 6,
+            repeat_subunit_length: 2,
+            sequence: sequence.to_string(),
+        };
+        assert_eq!(
+            allele(&accession, 1, 9, state("CACACA")).id,
+            allele(&accession, 1, 9, state("")).id
+        );
+        let json = serde_json::to_value(&snv).unwrap();
+        assert_eq!(
+            json["location"]["sequenceReference"]["refgetAccession"],
+            accession
+        );
+        assert_eq!(json["state"]["type"], "LiteralSequenceExpression");
+    }
+}
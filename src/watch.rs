@@ -0,0 +1,182 @@
+// --watch: reload the VCF when a pipeline rewrites it or its index, and tell clients that
+// subscribed to resources.
+//
+// The VCF and its .tbi or .csi index are polled every WATCH_INTERVAL for their size and
+// modification time, which works on every platform and network filesystem. A change is acted
+// on once the files have looked the same for one more poll, so a file still being written (or
+// a VCF whose index is rewritten after it) isn't loaded halfway. Resource subscriptions are
+// kept per client session; after a reload every subscribed URI gets notifications/resources/
+// updated, and sessions whose notification fails are dropped.
+
+use rmcp::{model::ResourceUpdatedNotificationParam, service::Peer, RoleServer};
+use std::collections::{BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+pub const WATCH_INTERVAL: Duration = Duration::from_secs(2);
+
+// The VCF and the index files next to it that a reload would read
+pub fn watched_paths(vcf: &Path) -> Vec<PathBuf> {
+    let mut paths = vec![vcf.to_path_buf()];
+    for extension in ["tbi", "csi"] {
+        let mut index = vcf.as_os_str().to_owned();
+        index.push(".");
+        index.push(extension);
+        paths.push(PathBuf::from(index));
+    }
+    paths
+}
+
+// Size and modification time of each watched file (None while it is missing)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileState(Vec<Option<(u64, SystemTime)>>);
+
+impl FileState {
+    pub fn read(paths: &[PathBuf]) -> FileState {
+        FileState(
+            paths
+                .iter()
+                .map(|path| {
+                    let metadata = std::fs::metadata(path).ok()?;
+                    Some((metadata.len(), metadata.modified().ok()?))
+                })
+                .collect(),
+        )
+    }
+}
+
+// Decides when the watched files changed and have settled
+pub struct ChangeDetector {
+    loaded: FileState,
+    // The state seen by the previous poll, when it differed from the loaded one
+    pending: Option<FileState>,
+}
+
+impl ChangeDetector {
+    pub fn new(loaded: FileState) -> Self {
+        ChangeDetector {
+            loaded,
+            pending: None,
+        }
+    }
+
+    // True when `current` differs from the loaded state and matches the previous poll
+    pub fn poll(&mut self, current: FileState) -> bool {
+        if current == self.loaded {
+            self.pending = None;
+            return false;
+        }
+        if self.pending.as_ref() == Some(&current) {
+            return true;
+        }
+        self.pending = Some(current);
+        false
+    }
+
+    // The files were reloaded in `state`
+    pub fn loaded(&mut self, state: FileState) {
+        self.loaded = state;
+        self.pending = None;
+    }
+}
+
+// Resource URIs each client session subscribed to
+#[derive(Default)]
+pub struct Subscriptions {
+    sessions: Mutex<HashMap<String, (Peer<RoleServer>, BTreeSet<String>)>>,
+}
+
+impl Subscriptions {
+    pub fn subscribe(&self, session: String, peer: Peer<RoleServer>, uri: String) {
+        let mut sessions = self.sessions.lock().unwrap_or_else(|e| e.into_inner());
+        sessions
+            .entry(session)
+            .or_insert_with(|| (peer, BTreeSet::new()))
+            .1
+            .insert(uri);
+    }
+
+    pub fn unsubscribe(&self, session: &str, uri: &str) {
+        let mut sessions = self.sessions.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some((_, uris)) = sessions.get_mut(session) {
+            uris.remove(uri);
+            if uris.is_empty() {
+                sessions.remove(session);
+            }
+        }
+    }
+
+    // Send notifications/resources/updated for every subscribed URI; returns how many were sent
+    pub async fn notify_updated(&self) -> usize {
+        let sessions: Vec<(String, Peer<RoleServer>, Vec<String>)> = {
+            let sessions = self.sessions.lock().unwrap_or_else(|e| e.into_inner());
+            sessions
+                .iter()
+                .map(|(session, (peer, uris))| {
+                    (
+                        session.clone(),
+                        peer.clone(),
+                        uris.iter().cloned().collect(),
+                    )
+                })
+                .collect()
+        };
+        let mut sent = 0;
+        for (session, peer, uris) in sessions {
+            for uri in uris {
+                if peer
+                    .notify_resource_updated(ResourceUpdatedNotificationParam { uri })
+                    .await
+                    .is_err()
+                {
+                    // The client is gone
+                    self.sessions
+                        .lock()
+                        .unwrap_or_else(|e| e.into_inner())
+                        .remove(&session);
+                    break;
+                }
+                sent += 1;
+            }
+        }
+        sent
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_watched_paths() {
+        let paths = watched_paths(Path::new("/data/calls.vcf.gz"));
+        assert_eq!(
+            paths,
+            vec![
+                PathBuf::from("/data/calls.vcf.gz"),
+                PathBuf::from("/data/calls.vcf.gz.tbi"),
+                PathBuf::from("/data/calls.vcf.gz.csi"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_change_detector_waits_for_files_to_settle() {
+        let time = SystemTime::UNIX_EPOCH;
+        let state = |size: u64| FileState(vec![Some((size, time)), None]);
+        let mut detector = ChangeDetector::new(state(100));
+        assert!(!detector.poll(state(100)));
+        // Still being written
+        assert!(!detector.poll(state(150)));
+        assert!(!detector.poll(state(200)));
+        assert!(detector.poll(state(200)));
+        detector.loaded(state(200));
+        assert!(!detector.poll(state(200)));
+
+        // Changed back before settling
+        assert!(!detector.poll(state(300)));
+        assert!(!detector.poll(state(200)));
+        assert!(!detector.poll(state(200)));
+    }
+}
@@ -1,5 +1,7 @@
 use std::path::PathBuf;
-use vcf_mcp_server::vcf::{format_variant, load_vcf, ReferenceGenomeSource};
+use vcf_mcp_server::vcf::{
+    classify_allele, format_variant, load_vcf, AlleleType, ReferenceGenomeSource,
+};
 
 #[test]
 fn test_load_compressed_vcf() {
@@ -74,6 +76,72 @@ fn test_query_by_id_with_real_data() {
         2,
         "rs6040355 should have 2 alternate alleles"
     );
+    assert_eq!(results[0].ids, vec!["rs6040355".to_string()]);
+}
+
+#[test]
+fn test_query_by_id_only_returns_records_carrying_id() {
+    let vcf_path = PathBuf::from("sample_data/sample.compressed.vcf.gz");
+
+    if !vcf_path.exists() {
+        eprintln!("Warning: Sample VCF file not found, skipping test");
+        return;
+    }
+
+    let index = load_vcf(&vcf_path, false, false).expect("Failed to load VCF file");
+
+    // Every returned record must list the queried ID among its IDs
+    let results = index.query_by_id("rs6054257");
+    assert!(!results.is_empty());
+    for variant in &results {
+        assert!(variant.ids.contains(&"rs6054257".to_string()));
+        assert_eq!(variant.id, variant.ids[0]);
+    }
+
+    // Records without an ID have an empty ID list and "." as the primary ID
+    let (variants, _) = index.query_by_position("20", 17330);
+    assert_eq!(variants.len(), 1);
+    assert!(variants[0].ids.is_empty());
+    assert_eq!(variants[0].id, ".");
+}
+
+#[test]
+fn test_query_by_id_finds_every_id_of_multi_id_record() {
+    use noodles::bgzf;
+    use std::io::Write;
+    use tempfile::TempDir;
+
+    let temp_dir = TempDir::new().expect("Failed to create temp dir");
+    let vcf_path = temp_dir.path().join("multi_id.vcf.gz");
+    let mut writer = bgzf::io::Writer::new(std::fs::File::create(&vcf_path).unwrap());
+    writer
+        .write_all(
+            b"##fileformat=VCFv4.3\n\
+              ##contig=<ID=20>\n\
+              #CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\n\
+              20\t100\trsA;rsB\tA\tG\t.\tPASS\t.\n\
+              20\t200\trsB2\tC\tT\t.\tPASS\t.\n\
+              20\t300\trsC\tG\tA\t.\tPASS\t.\n",
+        )
+        .unwrap();
+    writer.finish().unwrap();
+
+    let index = load_vcf(&vcf_path, false, false).expect("Failed to load VCF");
+    let expected_ids = vec!["rsA".to_string(), "rsB".to_string()];
+
+    // The second ID finds the record just like the first, with every ID listed
+    let results = index.query_by_id("rsB");
+    assert_eq!(results.len(), 1, "rsB should not match rsB2");
+    assert_eq!(results[0].position, 100);
+    assert_eq!(results[0].ids, expected_ids);
+    assert_eq!(results[0].id, "rsA");
+
+    let results = index.query_by_id("rsA");
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].ids, expected_ids);
+
+    // The joined ID column is not an ID of its own
+    assert!(index.query_by_id("rsA;rsB").is_empty());
 }
 
 #[test]
@@ -507,6 +575,297 @@ async fn test_streaming_multiallelic_variant() {
     );
     assert_eq!(results[0].alternate[0], "G");
     assert_eq!(results[0].alternate[1], "T");
+    assert!(results[0]
+        .alternate_alleles
+        .iter()
+        .all(|a| a.allele_type == AlleleType::Sequence));
+}
+
+#[test]
+fn test_search_ids_prefix_and_wildcards() {
+    let vcf_path = PathBuf::from("sample_data/sample.compressed.vcf.gz");
+    if !vcf_path.exists() {
+        eprintln!("Warning: Sample VCF file not found, skipping test");
+        return;
+    }
+
+    let index = load_vcf(&vcf_path, false, false).expect("Failed to load VCF file");
+
+    // Plain pattern is a prefix search; results are sorted by ID
+    let (matches, truncated) = index.search_ids("rs60", 100);
+    let ids: Vec<&str> = matches.iter().map(|m| m.id.as_str()).collect();
+    assert_eq!(ids, vec!["rs6040355", "rs6054257"]);
+    assert!(!truncated);
+    assert_eq!(matches[1].locations[0].chromosome, "20");
+    assert_eq!(matches[1].locations[0].position, 14370);
+
+    // Wildcards anywhere in the pattern
+    let (matches, _) = index.search_ids("rs*257", 100);
+    assert_eq!(matches.len(), 1);
+    assert_eq!(matches[0].id, "rs6054257");
+    let (matches, _) = index.search_ids("rs60?0355", 100);
+    assert_eq!(matches.len(), 1);
+
+    // Limit caps the result and reports truncation
+    let (matches, truncated) = index.search_ids("rs", 1);
+    assert_eq!(matches.len(), 1);
+    assert!(truncated);
+
+    let (matches, _) = index.search_ids("nonexistent*", 100);
+    assert!(matches.is_empty());
+}
+
+#[test]
+fn test_sample_region_is_reproducible_for_a_seed() {
+    let vcf_path = PathBuf::from("sample_data/sample.compressed.vcf.gz");
+    if !vcf_path.exists() {
+        eprintln!("Warning: Sample VCF file not found, skipping test");
+        return;
+    }
+
+    let index = load_vcf(&vcf_path, false, false).expect("Failed to load VCF file");
+
+    let (sample, population, matched) =
+        index.sample_region("20", 1, 2_000_000, 2, 42, "", &Default::default());
+    assert_eq!(matched.as_deref(), Some("20"));
+    assert!(population >= 4);
+    assert_eq!(sample.len(), 2);
+    assert!(sample[0].position < sample[1].position);
+
+    // Same seed, same sample
+    for _ in 0..3 {
+        let (again, _, _) = index.sample_region("20", 1, 2_000_000, 2, 42, "", &Default::default());
+        let positions: Vec<u64> = again.iter().map(|v| v.position).collect();
+        assert_eq!(positions, vec![sample[0].position, sample[1].position]);
+    }
+
+    // Asking for more than the region holds returns everything once
+    let (all, population, _) =
+        index.sample_region("20", 1, 2_000_000, 1000, 7, "", &Default::default());
+    assert_eq!(all.len(), population);
+
+    let (none, population, matched) =
+        index.sample_region("nonexistent", 1, 1000, 5, 1, "", &Default::default());
+    assert!(none.is_empty());
+    assert_eq!(population, 0);
+    assert!(matched.is_none());
+}
+
+#[test]
+fn test_sample_region_streams_a_uniform_reservoir() {
+    use noodles::bgzf;
+    use std::io::Write;
+    use std::ops::ControlFlow;
+    use tempfile::TempDir;
+    use vcf_mcp_server::vcf::{load_vcf_with_options, LimitExceeded, LoadOptions, QueryLimits};
+
+    let temp_dir = TempDir::new().expect("Failed to create temp dir");
+    let vcf_path = temp_dir.path().join("sample.vcf.gz");
+    let mut writer = bgzf::io::Writer::new(std::fs::File::create(&vcf_path).unwrap());
+    writer
+        .write_all(
+            b"##fileformat=VCFv4.3\n\
+              ##contig=<ID=1,length=100000>\n\
+              #CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\n",
+        )
+        .unwrap();
+    // 200 records; every other one has QUAL 50
+    for i in 1..=200u64 {
+        let quality = if i % 2 == 0 { 50 } else { 10 };
+        writeln!(writer, "1\t{}\t.\tA\tG\t{}\tPASS\t.", i * 10, quality).unwrap();
+    }
+    writer.finish().unwrap();
+
+    let index = load_vcf(&vcf_path, false, false).expect("Failed to load VCF");
+    let (sample, population, _) =
+        index.sample_region("1", 1, 100_000, 5, 3, "QUAL > 30", &Default::default());
+    assert_eq!(population, 100);
+    assert_eq!(sample.len(), 5);
+    assert!(sample.windows(2).all(|w| w[0].position < w[1].position));
+    assert!(sample.iter().all(|v| v.quality == Some(50.0)));
+
+    // Every matching variant is equally likely to be drawn, including the first few
+    let mut drawn = [0u32; 100];
+    for seed in 0..2000 {
+        let (sample, _, _) =
+            index.sample_region("1", 1, 100_000, 5, seed, "QUAL > 30", &Default::default());
+        for variant in sample {
+            drawn[(variant.position / 20 - 1) as usize] += 1;
+        }
+    }
+    // 2000 draws of 5 from 100 pick each variant 100 times on average
+    assert!(
+        drawn.iter().all(|&n| (40..=180).contains(&n)),
+        "{:?}",
+        drawn
+    );
+
+    // A cancelled scan is reported as such
+    let mut reads = 0;
+    let cancelled = index.sample_region_with_progress(
+        "1",
+        1,
+        100_000,
+        5,
+        1,
+        "",
+        &Default::default(),
+        &mut |_| {
+            reads += 1;
+            if reads > 10 {
+                ControlFlow::Break(())
+            } else {
+                ControlFlow::Continue(())
+            }
+        },
+    );
+    assert!(cancelled.cancelled);
+    assert_eq!(cancelled.population, 10);
+
+    // The region span limit applies as to region queries
+    let options = LoadOptions {
+        limits: QueryLimits {
+            max_region_bp: Some(1_000),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let limited = load_vcf_with_options(&vcf_path, &options).expect("Failed to load VCF");
+    let refused = limited.sample_region_with_progress(
+        "1",
+        1,
+        100_000,
+        5,
+        1,
+        "",
+        &Default::default(),
+        &mut |_| ControlFlow::Continue(()),
+    );
+    assert!(matches!(
+        refused.limit_exceeded,
+        Some(LimitExceeded::RegionSpan { .. })
+    ));
+    assert!(refused.variants.is_empty());
+}
+
+#[test]
+fn test_region_limits_apply_to_query_by_region_but_not_streaming() {
+    use noodles::bgzf;
+    use std::io::Write;
+    use tempfile::TempDir;
+    use vcf_mcp_server::vcf::{load_vcf_with_options, LoadOptions, QueryLimits, QueryOptions};
+
+    let temp_dir = TempDir::new().expect("Failed to create temp dir");
+    let vcf_path = temp_dir.path().join("limits.vcf.gz");
+    let mut writer = bgzf::io::Writer::new(std::fs::File::create(&vcf_path).unwrap());
+    writer
+        .write_all(
+            b"##fileformat=VCFv4.3\n\
+              ##contig=<ID=1,length=100000>\n\
+              #CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\n",
+        )
+        .unwrap();
+    for i in 1..=20u64 {
+        writeln!(writer, "1\t{}\t.\tA\tG\t50\tPASS\t.", i * 1000).unwrap();
+    }
+    writer.finish().unwrap();
+
+    let options = LoadOptions {
+        limits: QueryLimits {
+            max_region_bp: Some(5_000),
+            max_results: Some(3),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let index = load_vcf_with_options(&vcf_path, &options).expect("Failed to load VCF");
+
+    // The library's region query stops at the limits
+    let (variants, matched) = index.query_by_region("1", 1, 5_000);
+    assert_eq!(matched.as_deref(), Some("1"));
+    assert_eq!(variants.len(), 3);
+    let (variants, _) = index.query_by_region("1", 1, 100_000);
+    assert!(variants.is_empty());
+
+    // Streaming walks the whole chromosome a page at a time
+    let mut options = QueryOptions {
+        limit: Some(1),
+        ..Default::default()
+    };
+    let mut positions = Vec::new();
+    loop {
+        let page = index.stream_region("1", 1, 100_000, &options);
+        assert!(page.limit_exceeded.is_none());
+        positions.extend(page.variants.iter().map(|v| v.position));
+        match page.next_cursor {
+            Some(cursor) => options.cursor = Some(cursor),
+            None => break,
+        }
+    }
+    assert_eq!(positions, (1..=20).map(|i| i * 1000).collect::<Vec<u64>>());
+}
+
+#[test]
+fn test_variant_ranges_in_metadata_and_queries_outside_them() {
+    let vcf_path = PathBuf::from("sample_data/sample.compressed.vcf.gz");
+    if !vcf_path.exists() {
+        eprintln!("Warning: Sample VCF file not found, skipping test");
+        return;
+    }
+
+    let index = load_vcf(&vcf_path, false, false).expect("Failed to load VCF file");
+
+    let ranges = index
+        .get_metadata()
+        .variant_ranges
+        .expect("ID index should carry position ranges");
+    let chr20 = ranges
+        .iter()
+        .find(|r| r.chromosome == "20")
+        .expect("chromosome 20 range");
+    assert_eq!(chr20.first_position, 14370);
+    assert!(chr20.last_position >= 1234567);
+    let chr_x = ranges
+        .iter()
+        .find(|r| r.chromosome == "X")
+        .expect("X range");
+    assert_eq!(chr_x.first_position, 10);
+
+    // Queries beyond the cached range are answered empty but still match the chromosome
+    let (variants, matched) = index.query_by_region("20", 1, 14369);
+    assert!(variants.is_empty());
+    assert_eq!(matched.as_deref(), Some("20"));
+    let (variants, matched) = index.query_by_position("20", chr20.last_position + 1);
+    assert!(variants.is_empty());
+    assert_eq!(matched.as_deref(), Some("20"));
+
+    // Boundaries are inclusive
+    let (variants, _) = index.query_by_position("20", 14370);
+    assert_eq!(variants.len(), 1);
+}
+
+#[test]
+fn test_classify_symbolic_alleles() {
+    assert_eq!(classify_allele("A").allele_type, AlleleType::Sequence);
+    assert_eq!(
+        classify_allele("*").allele_type,
+        AlleleType::SpanningDeletion
+    );
+    assert_eq!(classify_allele(".").allele_type, AlleleType::Missing);
+
+    let non_ref = classify_allele("<NON_REF>");
+    assert_eq!(non_ref.allele_type, AlleleType::NonRef);
+    assert_eq!(classify_allele("<*>").allele_type, AlleleType::NonRef);
+
+    let del = classify_allele("<DEL>");
+    assert_eq!(del.allele_type, AlleleType::Symbolic);
+    assert_eq!(del.symbol, Some("DEL".to_string()));
+
+    assert_eq!(
+        classify_allele("G]17:198982]").allele_type,
+        AlleleType::Breakend
+    );
+    assert_eq!(classify_allele(".A").allele_type, AlleleType::Breakend);
 }
 
 // ============================================================================
@@ -725,6 +1084,303 @@ fn test_index_files_created() {
     assert!(idx_path.exists(), "Should create ID index (.idx)");
 }
 
+#[test]
+fn test_id_index_reloaded_from_disk_and_rebuilt_when_unreadable() {
+    use std::fs;
+    use tempfile::TempDir;
+
+    let vcf_path = PathBuf::from("sample_data/sample.compressed.vcf.gz");
+    if !vcf_path.exists() {
+        eprintln!("Warning: Sample VCF file not found, skipping test");
+        return;
+    }
+
+    let temp_dir = TempDir::new().expect("Failed to create temp dir");
+    let temp_vcf_path = temp_dir.path().join("test.vcf.gz");
+    fs::copy(&vcf_path, &temp_vcf_path).expect("Failed to copy VCF file");
+    let idx_path = temp_dir.path().join("test.vcf.gz.idx");
+
+    // An index in an unknown format (e.g. the old bincode HashMap) is replaced
+    fs::write(&idx_path, b"stale index contents").expect("Failed to write stale index");
+    let index = load_vcf(&temp_vcf_path, false, true).expect("Failed to load VCF file");
+    assert_eq!(index.query_by_id("rs6054257").len(), 1);
+    let rebuilt = fs::read(&idx_path).expect("Failed to read rebuilt index");
+    assert_ne!(rebuilt, b"stale index contents".to_vec());
+    drop(index);
+
+    // Second load maps the saved index from disk
+    let index = load_vcf(&temp_vcf_path, false, true).expect("Failed to load VCF file");
+    let results = index.query_by_id("microsat1");
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].position, 1234567);
+    assert!(index.query_by_id("rs0").is_empty());
+}
+
+#[test]
+fn test_stale_id_index_rebuilt_when_vcf_changes() {
+    use std::fs;
+    use std::time::{Duration, SystemTime};
+    use tempfile::TempDir;
+
+    let vcf_path = PathBuf::from("sample_data/sample.compressed.vcf.gz");
+    if !vcf_path.exists() {
+        eprintln!("Warning: Sample VCF file not found, skipping test");
+        return;
+    }
+
+    let temp_dir = TempDir::new().expect("Failed to create temp dir");
+    let temp_vcf_path = temp_dir.path().join("test.vcf.gz");
+    fs::copy(&vcf_path, &temp_vcf_path).expect("Failed to copy VCF file");
+    let idx_path = temp_dir.path().join("test.vcf.gz.idx");
+
+    drop(load_vcf(&temp_vcf_path, false, true).expect("Failed to load VCF file"));
+    let original = fs::read(&idx_path).expect("Failed to read ID index");
+
+    // Unchanged VCF: the saved index is reused as-is
+    drop(load_vcf(&temp_vcf_path, false, true).expect("Failed to load VCF file"));
+    assert_eq!(
+        fs::read(&idx_path).expect("Failed to read ID index"),
+        original
+    );
+
+    // Touching the VCF (as regenerating it would) invalidates the index
+    let file = fs::File::options()
+        .write(true)
+        .open(&temp_vcf_path)
+        .expect("Failed to open VCF file");
+    file.set_modified(SystemTime::now() + Duration::from_secs(60))
+        .expect("Failed to set mtime");
+    drop(file);
+
+    let index = load_vcf(&temp_vcf_path, false, true).expect("Failed to load VCF file");
+    assert_ne!(
+        fs::read(&idx_path).expect("Failed to read ID index"),
+        original
+    );
+    assert_eq!(index.query_by_id("rs6054257").len(), 1);
+}
+
+#[test]
+fn test_id_index_from_newer_version_is_left_in_place() {
+    use std::fs;
+    use tempfile::TempDir;
+
+    let vcf_path = PathBuf::from("sample_data/sample.compressed.vcf.gz");
+    if !vcf_path.exists() {
+        eprintln!("Warning: Sample VCF file not found, skipping test");
+        return;
+    }
+
+    let temp_dir = TempDir::new().expect("Failed to create temp dir");
+    let temp_vcf_path = temp_dir.path().join("test.vcf.gz");
+    fs::copy(&vcf_path, &temp_vcf_path).expect("Failed to copy VCF file");
+    let idx_path = temp_dir.path().join("test.vcf.gz.idx");
+
+    drop(load_vcf(&temp_vcf_path, false, true).expect("Failed to load VCF file"));
+
+    // Pretend a future release wrote the index (version field follows the 8-byte magic)
+    let mut newer = fs::read(&idx_path).expect("Failed to read ID index");
+    newer[8..12].copy_from_slice(&(vcf_mcp_server::id_index::FORMAT_VERSION + 1).to_le_bytes());
+    fs::write(&idx_path, &newer).expect("Failed to write ID index");
+
+    let index = load_vcf(&temp_vcf_path, false, true).expect("Failed to load VCF file");
+    assert_eq!(index.query_by_id("rs6054257").len(), 1);
+    assert_eq!(fs::read(&idx_path).expect("Failed to read ID index"), newer);
+}
+
+#[test]
+fn test_background_id_index_build() {
+    use std::fs;
+    use std::time::{Duration, Instant};
+    use tempfile::TempDir;
+    use vcf_mcp_server::vcf::{load_vcf_with_options, IdIndexStatus, LoadOptions};
+
+    let vcf_path = PathBuf::from("sample_data/sample.compressed.vcf.gz");
+    if !vcf_path.exists() {
+        eprintln!("Warning: Sample VCF file not found, skipping test");
+        return;
+    }
+
+    let temp_dir = TempDir::new().expect("Failed to create temp dir");
+    let temp_vcf_path = temp_dir.path().join("test.vcf.gz");
+    fs::copy(&vcf_path, &temp_vcf_path).expect("Failed to copy VCF file");
+
+    let options = LoadOptions {
+        save_index: true,
+        background: true,
+        ..Default::default()
+    };
+    let index = load_vcf_with_options(&temp_vcf_path, &options).expect("Failed to load VCF");
+
+    // Position queries do not depend on the ID index
+    let (variants, _) = index.query_by_position("20", 14370);
+    assert_eq!(variants.len(), 1);
+
+    // Statistics are computed after the ID index, so wait for both
+    let deadline = Instant::now() + Duration::from_secs(30);
+    while index.compute_statistics().is_err() {
+        match index.id_index_status() {
+            IdIndexStatus::Ready => {}
+            IdIndexStatus::Building {
+                percent_complete, ..
+            } => assert!((0.0..=100.0).contains(&percent_complete)),
+            IdIndexStatus::Failed { error } => panic!("ID index build failed: {}", error),
+        }
+        assert!(Instant::now() < deadline, "ID index build timed out");
+        std::thread::sleep(Duration::from_millis(10));
+    }
+
+    assert!(matches!(index.id_index_status(), IdIndexStatus::Ready));
+    assert_eq!(index.query_by_id("rs6054257").len(), 1);
+    assert!(temp_dir.path().join("test.vcf.gz.idx").exists());
+}
+
+#[test]
+fn test_stop_background_id_index_build() {
+    use std::fs;
+    use std::time::Duration;
+    use tempfile::TempDir;
+    use vcf_mcp_server::vcf::{load_vcf_with_options, LoadOptions};
+
+    let vcf_path = PathBuf::from("sample_data/sample.compressed.vcf.gz");
+    if !vcf_path.exists() {
+        eprintln!("Warning: Sample VCF file not found, skipping test");
+        return;
+    }
+
+    let temp_dir = TempDir::new().expect("Failed to create temp dir");
+    let temp_vcf_path = temp_dir.path().join("test.vcf.gz");
+    fs::copy(&vcf_path, &temp_vcf_path).expect("Failed to copy VCF file");
+
+    let options = LoadOptions {
+        save_index: true,
+        background: true,
+        low_memory: true,
+        ..Default::default()
+    };
+    let index = load_vcf_with_options(&temp_vcf_path, &options).expect("Failed to load VCF");
+    assert!(index.stop_background_indexing(Duration::from_secs(30)));
+    // Already stopped
+    assert!(index.stop_background_indexing(Duration::ZERO));
+
+    // Whether or not the build got to finish, no partial index is left behind
+    assert!(!temp_dir.path().join("test.vcf.gz.idx.tmp").exists());
+    let reloaded = load_vcf_with_options(
+        &temp_vcf_path,
+        &LoadOptions {
+            save_index: true,
+            ..Default::default()
+        },
+    )
+    .expect("Failed to reload VCF");
+    assert_eq!(reloaded.query_by_id("rs6054257").len(), 1);
+}
+
+#[test]
+fn test_low_memory_id_index_matches_default_build() {
+    use std::fs;
+    use tempfile::TempDir;
+    use vcf_mcp_server::vcf::{load_vcf_with_options, LoadOptions};
+
+    let vcf_path = PathBuf::from("sample_data/sample.compressed.vcf.gz");
+    if !vcf_path.exists() {
+        eprintln!("Warning: Sample VCF file not found, skipping test");
+        return;
+    }
+
+    let temp_dir = TempDir::new().expect("Failed to create temp dir");
+    let temp_vcf_path = temp_dir.path().join("test.vcf.gz");
+    fs::copy(&vcf_path, &temp_vcf_path).expect("Failed to copy VCF file");
+    let idx_path = temp_dir.path().join("test.vcf.gz.idx");
+
+    let low_memory = LoadOptions {
+        save_index: true,
+        low_memory: true,
+        ..Default::default()
+    };
+    let index = load_vcf_with_options(&temp_vcf_path, &low_memory).expect("Failed to load VCF");
+    assert_eq!(index.query_by_id("rs6054257").len(), 1);
+    drop(index);
+    let external = fs::read(&idx_path).expect("Failed to read ID index");
+
+    fs::remove_file(&idx_path).expect("Failed to remove ID index");
+    drop(load_vcf(&temp_vcf_path, false, true).expect("Failed to load VCF file"));
+    assert_eq!(
+        fs::read(&idx_path).expect("Failed to read ID index"),
+        external
+    );
+
+    // Without saving, the index is served from an unlinked temp file
+    fs::remove_file(&idx_path).expect("Failed to remove ID index");
+    let unsaved = LoadOptions {
+        low_memory: true,
+        ..Default::default()
+    };
+    let index = load_vcf_with_options(&temp_vcf_path, &unsaved).expect("Failed to load VCF");
+    assert_eq!(index.query_by_id("microsat1").len(), 1);
+    assert!(!idx_path.exists());
+}
+
+#[test]
+fn test_present_in_sample_with_and_without_presence_bitmaps() {
+    use std::fs;
+    use tempfile::TempDir;
+    use vcf_mcp_server::vcf::{load_vcf_with_options, LoadOptions, QueryOptions};
+
+    let vcf_path = PathBuf::from("sample_data/sample.compressed.vcf.gz");
+    if !vcf_path.exists() {
+        eprintln!("Warning: Sample VCF file not found, skipping test");
+        return;
+    }
+
+    let temp_dir = TempDir::new().expect("Failed to create temp dir");
+    let temp_vcf_path = temp_dir.path().join("test.vcf.gz");
+    fs::copy(&vcf_path, &temp_vcf_path).expect("Failed to copy VCF file");
+    let idx_path = temp_dir.path().join("test.vcf.gz.idx");
+
+    let carried_by = |index: &vcf_mcp_server::vcf::VcfIndex, sample: &str| -> Vec<u64> {
+        let options = QueryOptions {
+            present_in_sample: Some(sample.to_string()),
+            ..Default::default()
+        };
+        index
+            .query_region("20", 1, 2_000_000, &options)
+            .variants
+            .iter()
+            .map(|v| v.position)
+            .collect()
+    };
+
+    // Without bitmaps every record is parsed and its GT checked
+    let index = load_vcf(&temp_vcf_path, false, true).expect("Failed to load VCF file");
+    assert_eq!(carried_by(&index, "NA00001"), vec![1110696, 1234567]);
+    let plain_size = fs::metadata(&idx_path).expect("ID index").len();
+    drop(index);
+
+    // An index without bitmaps is rebuilt with them, and answers the same
+    for low_memory in [false, true] {
+        let options = LoadOptions {
+            save_index: true,
+            sample_presence: true,
+            low_memory,
+            ..Default::default()
+        };
+        let index =
+            load_vcf_with_options(&temp_vcf_path, &options).expect("Failed to load VCF file");
+        assert!(fs::metadata(&idx_path).expect("ID index").len() > plain_size);
+        assert_eq!(carried_by(&index, "NA00001"), vec![1110696, 1234567]);
+        assert_eq!(
+            carried_by(&index, "NA00002"),
+            vec![14370, 17330, 1110696, 1234567]
+        );
+        assert_eq!(carried_by(&index, "NA00003"), vec![14370, 1110696, 1234567]);
+        assert!(carried_by(&index, "NA99999").is_empty());
+        assert_eq!(index.query_by_id("rs6054257").len(), 1);
+        drop(index);
+        fs::remove_file(&idx_path).expect("Failed to remove ID index");
+    }
+}
+
 #[test]
 fn test_never_save_index_flag() {
     use std::fs;
@@ -1001,3 +1657,663 @@ fn test_vcf_statistics_computation() {
     eprintln!("  MNPs: {}", stats.variant_types.mnps);
     eprintln!("  Complex: {}", stats.variant_types.complex);
 }
+
+#[test]
+fn test_query_by_region_with_info_flags() {
+    let vcf_path = PathBuf::from("sample_data/sample.compressed.vcf.gz");
+    if !vcf_path.exists() {
+        eprintln!("Warning: Sample VCF file not found, skipping test");
+        return;
+    }
+
+    let index = load_vcf(&vcf_path, false, false).expect("Failed to load VCF file");
+    let flags = |names: &[&str]| names.iter().map(|n| n.to_string()).collect::<Vec<_>>();
+
+    // rs6054257 and rs6040355 are dbSNP members; only rs6054257 is also in HapMap2
+    let (variants, matched) =
+        index.query_by_region_with_info_flags("20", 1, 2_000_000, &flags(&["DB"]));
+    assert_eq!(matched.as_deref(), Some("20"));
+    let positions: Vec<u64> = variants.iter().map(|v| v.position).collect();
+    assert_eq!(positions, vec![14370, 1110696]);
+
+    let (variants, _) =
+        index.query_by_region_with_info_flags("20", 1, 2_000_000, &flags(&["DB", "H2"]));
+    assert_eq!(variants.len(), 1);
+    assert_eq!(variants[0].id, "rs6054257");
+
+    // No flags means no restriction
+    let (all, _) = index.query_by_region_with_info_flags("20", 1, 2_000_000, &[]);
+    assert_eq!(all.len(), index.query_by_region("20", 1, 2_000_000).0.len());
+
+    let requested = flags(&["DB", "NOT_A_FLAG", "DP"]);
+    assert_eq!(
+        index.undeclared_info_flags(&requested),
+        vec!["NOT_A_FLAG", "DP"]
+    );
+}
+
+#[test]
+fn test_bed_intervals_from_region() {
+    use vcf_mcp_server::vcf::bed_intervals;
+
+    let vcf_path = PathBuf::from("sample_data/sample.compressed.vcf.gz");
+    if !vcf_path.exists() {
+        eprintln!("Warning: Sample VCF file not found, skipping test");
+        return;
+    }
+
+    let index = load_vcf(&vcf_path, false, false).expect("Failed to load VCF file");
+    let (variants, _) = index.query_by_region("20", 1, 2_000_000);
+    let lines = |padding, merge| -> Vec<String> {
+        bed_intervals(&variants, padding, merge)
+            .iter()
+            .map(|interval| interval.to_bed_line())
+            .collect()
+    };
+
+    // One named interval per variant; microsat1 spans its 4-base REF
+    let bed = lines(0, false);
+    assert_eq!(bed.len(), variants.len());
+    assert_eq!(bed[0], "20\t14369\t14370\trs6054257");
+    assert_eq!(bed[1], "20\t17329\t17330\t.");
+    assert_eq!(bed.last().unwrap(), "20\t1234566\t1234570\tmicrosat1");
+
+    // Padding only merges the two variants 3 kb apart
+    let merged = lines(2000, true);
+    assert_eq!(merged.len(), variants.len() - 1);
+    assert_eq!(merged[0], "20\t12369\t19330");
+
+    // Without padding nothing overlaps, but merged output drops the names
+    assert_eq!(lines(0, true)[0], "20\t14369\t14370");
+}
+
+#[test]
+fn test_cluster_variants_by_gap() {
+    use vcf_mcp_server::vcf::cluster_variants;
+
+    let vcf_path = PathBuf::from("sample_data/sample.compressed.vcf.gz");
+    if !vcf_path.exists() {
+        eprintln!("Warning: Sample VCF file not found, skipping test");
+        return;
+    }
+
+    let index = load_vcf(&vcf_path, false, false).expect("Failed to load VCF file");
+    let (variants, _) = index.query_by_region("20", 1, 2_000_000);
+
+    // Only the two variants 2,959 bases apart are within a 3 kb gap
+    let clusters = cluster_variants(&variants, 3000, 2);
+    assert_eq!(clusters.len(), 1);
+    assert_eq!((clusters[0].start, clusters[0].end), (14370, 17330));
+    assert_eq!(clusters[0].span, 2961);
+    assert_eq!(clusters[0].variant_count, 2);
+    assert_eq!(clusters[0].ids, vec!["rs6054257".to_string()]);
+
+    // A wider gap also joins 1230237 with microsat1, whose REF runs to 1234570
+    let clusters = cluster_variants(&variants, 5000, 2);
+    assert_eq!(clusters.len(), 2);
+    assert_eq!((clusters[1].start, clusters[1].end), (1230237, 1234570));
+
+    // Singletons are reported when the minimum size allows them
+    let all = cluster_variants(&variants, 0, 1);
+    assert_eq!(all.len(), variants.len());
+}
+
+#[test]
+fn test_block_cache_serves_repeated_queries() {
+    use vcf_mcp_server::vcf::{load_vcf_with_options, LoadOptions, VcfIndex};
+
+    let vcf_path = PathBuf::from("sample_data/sample.compressed.vcf.gz");
+    if !vcf_path.exists() {
+        eprintln!("Warning: Sample VCF file not found, skipping test");
+        return;
+    }
+
+    let uncached = load_vcf(&vcf_path, false, false).expect("Failed to load VCF file");
+    let options = LoadOptions {
+        block_cache_bytes: 1 << 20,
+        ..Default::default()
+    };
+    let cached = load_vcf_with_options(&vcf_path, &options).expect("Failed to load VCF file");
+
+    let ids = |index: &VcfIndex| -> Vec<u64> {
+        let (variants, _) = index.query_by_region("20", 1, 2_000_000);
+        variants.iter().map(|v| v.position).collect()
+    };
+    let expected = ids(&uncached);
+    assert_eq!(ids(&cached), expected);
+
+    // The repeat reads only cached blocks
+    let misses = cached.block_cache_stats().misses;
+    assert_eq!(ids(&cached), expected);
+    let stats = cached.block_cache_stats();
+    assert_eq!(stats.misses, misses);
+    assert!(stats.hits > 0);
+    assert_eq!(uncached.block_cache_stats().cached_blocks, 0);
+}
+
+#[test]
+fn test_mmap_reader_matches_file_reader() {
+    use vcf_mcp_server::vcf::{load_vcf_with_options, LoadOptions};
+
+    let vcf_path = PathBuf::from("sample_data/sample.compressed.vcf.gz");
+    if !vcf_path.exists() {
+        eprintln!("Warning: Sample VCF file not found, skipping test");
+        return;
+    }
+
+    let buffered = load_vcf(&vcf_path, false, false).expect("Failed to load VCF file");
+    let options = LoadOptions {
+        mmap: true,
+        ..Default::default()
+    };
+    let mapped = load_vcf_with_options(&vcf_path, &options).expect("Failed to load VCF file");
+
+    for (start, end) in [(1, 2_000_000), (14_370, 17_330), (1_234_567, 1_234_567)] {
+        let (expected, _) = buffered.query_by_region("20", start, end);
+        let (actual, _) = mapped.query_by_region("20", start, end);
+        assert_eq!(
+            actual.iter().map(|v| &v.raw_row).collect::<Vec<_>>(),
+            expected.iter().map(|v| &v.raw_row).collect::<Vec<_>>()
+        );
+    }
+}
+
+#[test]
+fn test_id_pages_enumerate_every_id() {
+    let vcf_path = PathBuf::from("sample_data/sample.compressed.vcf.gz");
+    if !vcf_path.exists() {
+        eprintln!("Warning: Sample VCF file not found, skipping test");
+        return;
+    }
+
+    let index = load_vcf(&vcf_path, false, false).expect("Failed to load VCF file");
+    let first = index.id_page(0, 2).expect("ID index is ready");
+    assert_eq!(first.page_size, 2);
+    assert_eq!(first.total_pages, first.total_ids.div_ceil(2));
+    assert_eq!(first.next_page, Some(1));
+
+    // Walking every page yields each ID once, in sorted order
+    let mut ids = Vec::new();
+    let mut page = Some(0);
+    while let Some(n) = page {
+        let id_page = index.id_page(n, 2).expect("ID index is ready");
+        ids.extend(id_page.ids.into_iter().map(|row| row.id));
+        page = id_page.next_page;
+    }
+    let mut sorted = ids.clone();
+    sorted.sort();
+    sorted.dedup();
+    assert_eq!(ids, sorted);
+    assert_eq!(ids.len(), first.total_ids);
+    assert!(ids.contains(&"rs6054257".to_string()));
+
+    let past_end = index.id_page(first.total_pages, 2).unwrap();
+    assert!(past_end.ids.is_empty());
+    assert_eq!(past_end.next_page, None);
+}
+
+#[test]
+fn test_header_only_vcf() {
+    use noodles::bgzf;
+    use std::io::Write;
+    use tempfile::TempDir;
+
+    let temp_dir = TempDir::new().expect("Failed to create temp dir");
+    let vcf_path = temp_dir.path().join("empty.vcf.gz");
+    let mut writer = bgzf::io::Writer::new(std::fs::File::create(&vcf_path).unwrap());
+    writer
+        .write_all(
+            b"##fileformat=VCFv4.3\n##contig=<ID=20,length=64444167>\n#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\n",
+        )
+        .unwrap();
+    writer.finish().unwrap();
+
+    let index = load_vcf(&vcf_path, false, false).expect("Header-only VCF should load");
+    assert!(index.is_empty_file());
+
+    // Metadata is served as usual; queries and statistics simply find nothing
+    assert_eq!(index.get_available_chromosomes(), vec!["20".to_string()]);
+    let (variants, _) = index.query_by_region("20", 1, 1_000_000);
+    assert!(variants.is_empty());
+    assert!(index.query_by_id("rs6054257").is_empty());
+    let stats = index.compute_statistics().expect("statistics");
+    assert_eq!(stats.total_variants, 0);
+}
+
+#[test]
+fn test_contig_summaries_are_naturally_sorted_with_counts() {
+    use noodles::bgzf;
+    use std::io::Write;
+    use tempfile::TempDir;
+    use vcf_mcp_server::vcf::ContigSummary;
+
+    let temp_dir = TempDir::new().expect("Failed to create temp dir");
+    let vcf_path = temp_dir.path().join("contigs.vcf.gz");
+    let mut writer = bgzf::io::Writer::new(std::fs::File::create(&vcf_path).unwrap());
+    writer
+        .write_all(
+            b"##fileformat=VCFv4.3\n\
+              ##contig=<ID=chr2,length=242193529>\n\
+              ##contig=<ID=chrX,length=156040895>\n\
+              ##contig=<ID=chr10,length=133797422>\n\
+              ##contig=<ID=chr1>\n\
+              #CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\n\
+              chr2\t100\t.\tA\tG\t.\tPASS\t.\n\
+              chr10\t200\t.\tC\tT\t.\tPASS\t.\n\
+              chr10\t300\t.\tG\tA\t.\tPASS\t.\n",
+        )
+        .unwrap();
+    writer.finish().unwrap();
+
+    let index = load_vcf(&vcf_path, false, false).expect("Failed to load VCF");
+    let summary = |name: &str, length: Option<usize>, count: u64| ContigSummary {
+        name: name.to_string(),
+        length,
+        variant_count: Some(count),
+    };
+    assert_eq!(
+        index.contig_summaries(),
+        vec![
+            summary("chr1", None, 0),
+            summary("chr2", Some(242193529), 1),
+            summary("chr10", Some(133797422), 2),
+            summary("chrX", Some(156040895), 0),
+        ]
+    );
+}
+
+#[test]
+fn test_estimate_region_scales_with_region() {
+    let vcf_path = PathBuf::from("sample_data/sample.compressed.vcf.gz");
+    if !vcf_path.exists() {
+        eprintln!("Warning: Sample VCF file not found, skipping test");
+        return;
+    }
+
+    let index = load_vcf(&vcf_path, false, false).expect("Failed to load VCF file");
+    let (whole, matched) = index
+        .estimate_region("chr20", 1, 64_000_000)
+        .expect("chromosome 20 is in the file");
+    assert_eq!(matched, "20");
+    let (point, _) = index.estimate_region("20", 14_370, 14_370).unwrap();
+    assert!(whole.compressed_bytes >= point.compressed_bytes);
+    let total = index.compute_statistics().unwrap().total_variants;
+    assert!(whole.estimated_variants.unwrap() <= total);
+
+    assert!(index.estimate_region("99", 1, 1_000).is_none());
+}
+
+#[test]
+fn test_query_region_options() {
+    use vcf_mcp_server::vcf::{
+        CoordinateSystem, DetailLevel, QueryOptions, RecordFilter, SortOrder, VariantType,
+    };
+
+    let vcf_path = PathBuf::from("sample_data/sample.compressed.vcf.gz");
+    if !vcf_path.exists() {
+        eprintln!("Warning: Sample VCF file not found, skipping test");
+        return;
+    }
+
+    let index = load_vcf(&vcf_path, false, false).expect("Failed to load VCF file");
+    let (all, _) = index.query_by_region("20", 1, 2_000_000);
+    let positions: Vec<u64> = all.iter().map(|v| v.position).collect();
+
+    // Paging with a cursor visits every variant exactly once
+    let mut options = QueryOptions {
+        limit: Some(2),
+        ..Default::default()
+    };
+    let mut paged = Vec::new();
+    loop {
+        let page = index.query_region("20", 1, 2_000_000, &options);
+        assert!(page.variants.len() <= 2);
+        paged.extend(page.variants.iter().map(|v| v.position));
+        match page.next_cursor {
+            Some(cursor) => options.cursor = Some(cursor),
+            None => break,
+        }
+    }
+    assert_eq!(paged, positions);
+
+    // Zero-based half-open coordinates select the same variant and report it 0-based
+    let zero_based = QueryOptions {
+        coordinates: CoordinateSystem::ZeroBased,
+        ..Default::default()
+    };
+    let page = index.query_region("20", 14_369, 14_370, &zero_based);
+    assert_eq!(page.variants.len(), 1);
+    assert_eq!(page.variants[0].position, 14_369);
+    assert_eq!(page.variants[0].id, "rs6054257");
+
+    // Projection, summary detail and quality sorting
+    let projected = QueryOptions {
+        info_fields: Some(vec!["DP".to_string()]),
+        sort: SortOrder::Quality,
+        ..Default::default()
+    };
+    let page = index.query_region("20", 1, 2_000_000, &projected);
+    assert!(page
+        .variants
+        .iter()
+        .all(|v| v.info.keys().all(|k| k == "DP")));
+    let qualities: Vec<f32> = page.variants.iter().filter_map(|v| v.quality).collect();
+    assert!(qualities.windows(2).all(|pair| pair[0] >= pair[1]));
+
+    let summary = QueryOptions {
+        detail: DetailLevel::Summary,
+        filter: "QUAL > 30".to_string(),
+        ..Default::default()
+    };
+    let page = index.query_region("20", 1, 2_000_000, &summary);
+    assert!(!page.variants.is_empty());
+    assert!(page
+        .variants
+        .iter()
+        .all(|v| v.info.is_empty() && v.quality.is_some_and(|q| q > 30.0)));
+
+    // Excluded FILTER values are matched per value, ignoring case
+    let excluded = QueryOptions {
+        record_filter: RecordFilter {
+            exclude_filters: vec!["Q10".to_string()],
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let page = index.query_region("20", 1, 2_000_000, &excluded);
+    assert!(!page.variants.is_empty());
+    assert!(page
+        .variants
+        .iter()
+        .all(|v| !v.filter.iter().any(|f| f == "q10")));
+    assert!(page.variants.len() < all.len());
+
+    // Every record is classified, and variant_types keeps only the requested kinds
+    assert!(all.iter().all(|v| v.variant_type.is_some()));
+    let snps = QueryOptions {
+        record_filter: RecordFilter {
+            variant_types: vec![VariantType::Snp],
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let page = index.query_region("20", 1, 2_000_000, &snps);
+    let expected = all
+        .iter()
+        .filter(|v| v.variant_type == Some(VariantType::Snp))
+        .count();
+    assert_eq!(page.variants.len(), expected);
+}
+
+#[test]
+fn test_query_region_stops_when_cancelled() {
+    use std::ops::ControlFlow;
+    use vcf_mcp_server::vcf::QueryOptions;
+
+    let vcf_path = PathBuf::from("sample_data/sample.compressed.vcf.gz");
+    if !vcf_path.exists() {
+        eprintln!("Warning: Sample VCF file not found, skipping test");
+        return;
+    }
+
+    let index = load_vcf(&vcf_path, false, false).expect("Failed to load VCF file");
+    let options = QueryOptions::default();
+
+    let mut calls = 0;
+    let page = index.query_region_with_progress("20", 1, 2_000_000, &options, &mut |_| {
+        calls += 1;
+        if calls > 2 {
+            ControlFlow::Break(())
+        } else {
+            ControlFlow::Continue(())
+        }
+    });
+    assert!(page.cancelled);
+    assert_eq!(page.variants.len(), 2);
+    assert!(page.next_cursor.is_none());
+
+    let page = index.query_region_with_progress("20", 1, 2_000_000, &options, &mut |_| {
+        ControlFlow::Continue(())
+    });
+    assert!(!page.cancelled);
+    assert_eq!(page.variants.len(), 5);
+}
+
+#[test]
+fn test_query_limits() {
+    use std::time::Duration;
+    use vcf_mcp_server::vcf::{
+        load_vcf_with_options, LimitExceeded, LoadOptions, QueryLimits, QueryOptions,
+    };
+
+    let vcf_path = PathBuf::from("sample_data/sample.compressed.vcf.gz");
+    if !vcf_path.exists() {
+        eprintln!("Warning: Sample VCF file not found, skipping test");
+        return;
+    }
+
+    let load = |limits: QueryLimits| {
+        let options = LoadOptions {
+            limits,
+            ..Default::default()
+        };
+        load_vcf_with_options(&vcf_path, &options).expect("Failed to load VCF file")
+    };
+    let unpaged = QueryOptions::default();
+
+    // Region span is checked before reading anything
+    let index = load(QueryLimits {
+        max_region_bp: Some(1_000),
+        ..Default::default()
+    });
+    let page = index.query_region("20", 14_000, 15_000, &unpaged);
+    assert_eq!(
+        page.limit_exceeded,
+        Some(LimitExceeded::RegionSpan {
+            region_bp: 1_001,
+            max_region_bp: 1_000
+        })
+    );
+    assert!(page.variants.is_empty());
+    let page = index.query_region("20", 14_000, 14_999, &unpaged);
+    assert!(page.limit_exceeded.is_none());
+    assert_eq!(page.variants.len(), 1);
+
+    // Unpaged queries fail past max_results; paged queries get pages no larger than it
+    let index = load(QueryLimits {
+        max_results: Some(2),
+        ..Default::default()
+    });
+    let page = index.query_region("20", 1, 2_000_000, &unpaged);
+    assert_eq!(
+        page.limit_exceeded,
+        Some(LimitExceeded::Results {
+            max_results: 2,
+            position: 1_110_696
+        })
+    );
+    let paged = QueryOptions {
+        limit: Some(100),
+        ..Default::default()
+    };
+    let page = index.query_region("20", 1, 2_000_000, &paged);
+    assert!(page.limit_exceeded.is_none());
+    assert_eq!(page.variants.len(), 2);
+    assert!(page.next_cursor.is_some());
+
+    // A zero timeout stops the scan at the first record
+    let index = load(QueryLimits {
+        timeout: Some(Duration::ZERO),
+        ..Default::default()
+    });
+    let page = index.query_region("20", 1, 2_000_000, &unpaged);
+    assert!(matches!(
+        page.limit_exceeded,
+        Some(LimitExceeded::Timeout {
+            position: 14_370,
+            records_read: 1,
+            ..
+        })
+    ));
+}
+
+#[test]
+fn test_explain_filter() {
+    let vcf_path = PathBuf::from("sample_data/sample.compressed.vcf.gz");
+    if !vcf_path.exists() {
+        eprintln!("Warning: Sample VCF file not found, skipping test");
+        return;
+    }
+
+    let index = load_vcf(&vcf_path, false, false).expect("Failed to load VCF file");
+    let stats = index
+        .compute_statistics()
+        .expect("Statistics computed at load");
+
+    let explained = index.explain_filter("pass; NoSuchFilter");
+    assert_eq!(explained.len(), 2);
+    assert_eq!(explained[0].filter, "PASS");
+    assert!(explained[0].declared);
+    assert!(explained[0].description.is_some());
+    assert_eq!(explained[0].count, stats.filter_counts.get("PASS").copied());
+    assert_eq!(explained[1].filter, "NoSuchFilter");
+    assert!(!explained[1].declared);
+    assert!(explained[1].description.is_none());
+    assert_eq!(explained[1].count, Some(0));
+
+    // Every header FILTER line explains itself
+    for name in index.declared_filters() {
+        let explained = index.explain_filter(&name);
+        assert_eq!(explained[0].filter, name);
+        assert!(explained[0].description.is_some());
+    }
+}
+
+#[test]
+fn test_allele_annotations_match_position_and_alleles() {
+    let vcf_path = PathBuf::from("sample_data/sample.compressed.vcf.gz");
+    if !vcf_path.exists() {
+        eprintln!("Warning: Sample VCF file not found, skipping test");
+        return;
+    }
+
+    // The file annotates itself; only the T allele of rs6040355 (A>G,T) is asked for
+    let index = load_vcf(&vcf_path, false, false).expect("Failed to load VCF file");
+    let annotations = index
+        .allele_annotations(
+            "chr20",
+            1_110_696,
+            "A",
+            &["T".to_string()],
+            &["AF".to_string()],
+        )
+        .expect("Annotation lookup");
+    assert_eq!(annotations.len(), 1);
+    assert_eq!(annotations[0].allele, "T");
+    assert_eq!(annotations[0].id.as_deref(), Some("rs6040355"));
+    // AF is Number=A, so only the T allele's frequency is kept
+    assert_eq!(annotations[0].info.len(), 1);
+    assert_eq!(
+        annotations[0].info.get("AF"),
+        Some(&serde_json::json!(0.667))
+    );
+
+    // Every INFO field when none are selected
+    let all = index
+        .allele_annotations("20", 14_370, "G", &["A".to_string()], &[])
+        .expect("Annotation lookup");
+    assert_eq!(all.len(), 1);
+    assert!(all[0].info.contains_key("DP") && all[0].info.contains_key("DB"));
+
+    // A different REF or ALT is not a match
+    for (reference, alternate) in [("C", "T"), ("A", "C")] {
+        let none = index
+            .allele_annotations("20", 1_110_696, reference, &[alternate.to_string()], &[])
+            .expect("Annotation lookup");
+        assert!(none.is_empty());
+    }
+}
+
+#[test]
+fn test_nearest_variants_search_outward() {
+    let vcf_path = PathBuf::from("sample_data/sample.compressed.vcf.gz");
+    if !vcf_path.exists() {
+        eprintln!("Warning: Sample VCF file not found, skipping test");
+        return;
+    }
+
+    let index = load_vcf(&vcf_path, false, false).expect("Failed to load VCF file");
+
+    let (nearest, matched) = index.nearest_variants("chr20", 15000, 100_000).unwrap();
+    assert_eq!(matched.as_deref(), Some("20"));
+    assert!(nearest.at_position.is_empty());
+    let upstream = nearest.upstream.expect("upstream variant");
+    assert_eq!(upstream.distance, 630);
+    assert_eq!(upstream.variants[0].id, "rs6054257");
+    assert_eq!(
+        nearest.downstream.expect("downstream variant").distance,
+        2330
+    );
+
+    // The first variant of the chromosome has nothing upstream
+    let (nearest, _) = index.nearest_variants("20", 14370, 100_000).unwrap();
+    assert_eq!(nearest.at_position.len(), 1);
+    assert!(nearest.upstream.is_none());
+    assert_eq!(nearest.searched_distance, 100_000);
+    assert_eq!(
+        nearest.downstream.expect("downstream variant").distance,
+        2960
+    );
+
+    // Nothing downstream within max_distance
+    let (nearest, _) = index.nearest_variants("20", 15000, 1000).unwrap();
+    assert!(nearest.upstream.is_some());
+    assert!(nearest.downstream.is_none());
+    assert_eq!(nearest.searched_distance, 1000);
+
+    let (_, matched) = index.nearest_variants("7", 15000, 1000).unwrap();
+    assert!(matched.is_none());
+}
+
+#[test]
+fn test_hidden_genotypes_strip_sample_columns() {
+    use vcf_mcp_server::vcf::{load_vcf_with_options, LoadOptions};
+
+    let vcf_path = PathBuf::from("sample_data/sample.compressed.vcf.gz");
+    if !vcf_path.exists() {
+        eprintln!("Warning: Sample VCF file not found, skipping test");
+        return;
+    }
+
+    let options = LoadOptions {
+        hide_genotypes: true,
+        ..Default::default()
+    };
+    let index = load_vcf_with_options(&vcf_path, &options).expect("Failed to load VCF file");
+    assert!(index.genotypes_hidden());
+
+    let (variants, _) = index.query_by_region("20", 1, 2_000_000);
+    assert!(!variants.is_empty());
+    for variant in &variants {
+        assert_eq!(
+            variant.raw_row.split('\t').count(),
+            8,
+            "{}",
+            variant.raw_row
+        );
+    }
+    let (variants, _) = index.query_by_position("20", 14370);
+    assert_eq!(variants[0].raw_row.split('\t').count(), 8);
+
+    assert_eq!(index.sample_count(), 0);
+    assert!(index.sample_index("NA00001").is_none());
+    assert!(index.get_metadata().samples.is_empty());
+    let header = index.get_header_string(None);
+    assert!(!header.contains("##FORMAT"));
+    assert!(header
+        .lines()
+        .any(|line| line == "#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO"));
+    let vcf = index.render_vcf(&variants);
+    assert!(!vcf.contains("NA00001"));
+    assert!(!vcf.contains("GT:"));
+}